@@ -0,0 +1,283 @@
+//! Alarms
+//!
+//! Starts the built-in player at set local times, once or on chosen days of
+//! the week. An alarm plays a media item (a playlist, say) or resumes the
+//! queue, optionally at a set volume, and can fade in from near silence
+//! over a few minutes. The fade is a software gain on the main player (see
+//! [`set_main_player_ramp_db`]), so it works whatever the volume and
+//! doesn't touch the volume the server knows about.
+//!
+//! Alarms are kept in the settings, so they survive restarts. An alarm
+//! whose time passed while the app wasn't running, or the computer was
+//! asleep, is skipped rather than played late.
+
+use crate::now_playing;
+use crate::sendspin::dsp::set_main_player_ramp_db;
+use crate::sendspin::{PlaybackCommand, SendspinManager};
+use crate::settings::{self, Alarm};
+use chrono::{Datelike, Duration as DateDuration, Local, NaiveDateTime, NaiveTime};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often alarms are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// How late an alarm may still go off, e.g. after the check thread was held
+/// up; beyond this it is skipped
+const MAX_LATENESS: DateDuration = DateDuration::minutes(2);
+/// How long to wait for the built-in player to connect, e.g. right after the
+/// computer wakes up
+const PLAYER_WAIT: Duration = Duration::from_secs(90);
+const PLAYER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Gain a fade-in starts from
+const RAMP_START_DB: f64 = -30.0;
+/// How often the fade-in's gain is raised
+const RAMP_STEP: Duration = Duration::from_secs(1);
+/// Time playback gets to start before a fade-in gives up on it
+const RAMP_GRACE: Duration = Duration::from_secs(15);
+/// Longest fade-in
+const MAX_RAMP_MINUTES: u32 = 60;
+
+/// Bumped by every alarm that goes off; a fade-in whose generation is no
+/// longer current gives up
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Start checking alarms in the background
+pub fn init(sendspin: SendspinManager) {
+    std::thread::spawn(move || {
+        let mut last_check = Local::now().naive_local();
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            let now = Local::now().naive_local();
+            for alarm in settings::get_settings().alarms {
+                if alarm.enabled && is_due(&alarm, last_check, now) {
+                    go_off(&sendspin, alarm);
+                }
+            }
+            last_check = now;
+        }
+    });
+}
+
+/// Check an alarm before it is saved
+pub fn validate(alarm: &Alarm) -> Result<(), String> {
+    parse_time(&alarm.time)?;
+    if alarm.days.iter().any(|&day| day > 6) {
+        return Err("Alarm days must be 0 (Monday) to 6 (Sunday)".to_string());
+    }
+    if alarm
+        .media_uri
+        .as_ref()
+        .is_some_and(|uri| !uri.contains("://"))
+    {
+        return Err("Alarm media must be a URI like library://playlist/12".to_string());
+    }
+    if alarm.volume.is_some_and(|volume| volume > 100) {
+        return Err("Alarm volume must be between 0 and 100".to_string());
+    }
+    if alarm.ramp_minutes > MAX_RAMP_MINUTES {
+        return Err(format!(
+            "Alarm fade-in must be at most {} minutes",
+            MAX_RAMP_MINUTES
+        ));
+    }
+    Ok(())
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("Alarm time must be HH:MM, not {:?}", time))
+}
+
+/// First time after `after` that `alarm` goes off
+fn next_occurrence(alarm: &Alarm, after: NaiveDateTime) -> Option<NaiveDateTime> {
+    let time = parse_time(&alarm.time).ok()?;
+    (0..=7)
+        .map(|days| after.date() + DateDuration::days(days))
+        .filter(|date| {
+            alarm.days.is_empty()
+                || alarm
+                    .days
+                    .contains(&(date.weekday().num_days_from_monday() as u8))
+        })
+        .map(|date| date.and_time(time))
+        .find(|&at| at > after)
+}
+
+/// Whether `alarm` goes off between the checks at `last_check` and `now`
+fn is_due(alarm: &Alarm, last_check: NaiveDateTime, now: NaiveDateTime) -> bool {
+    next_occurrence(alarm, last_check).is_some_and(|at| at <= now && now - at <= MAX_LATENESS)
+}
+
+fn go_off(sendspin: &SendspinManager, alarm: Alarm) {
+    log::info!("[Alarms] Alarm {} at {}", alarm.id, alarm.time);
+    if alarm.days.is_empty() {
+        // A one-off alarm is done once it goes off
+        let done = Alarm {
+            enabled: false,
+            ..alarm.clone()
+        };
+        if let Err(e) = settings::update_alarm(done) {
+            log::warn!("[Alarms] Failed to disable alarm {}: {}", alarm.id, e);
+        }
+    }
+    let sendspin = sendspin.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = start(&sendspin, &alarm) {
+            log::warn!("[Alarms] Alarm {} failed: {}", alarm.id, e);
+        }
+    });
+}
+
+fn start(sendspin: &SendspinManager, alarm: &Alarm) -> Result<(), String> {
+    if !settings::get_settings().sendspin_enabled {
+        return Err("The built-in player is disabled".to_string());
+    }
+    let player_id = wait_for_player(sendspin, alarm.media_uri.is_some())?;
+
+    // Also ends the fade-in of an earlier alarm
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    set_main_player_ramp_db(if alarm.ramp_minutes > 0 {
+        RAMP_START_DB
+    } else {
+        0.0
+    });
+    if let Some(volume) = alarm.volume {
+        if let Err(e) = sendspin.set_volume_percent(volume) {
+            log::warn!("[Alarms] Failed to set the volume: {}", e);
+        }
+    }
+    let played = match &alarm.media_uri {
+        Some(uri) => crate::ma_api::play_media(&player_id, uri),
+        None => sendspin
+            .send_command(PlaybackCommand::Play)
+            .map_err(String::from),
+    };
+    if let Err(e) = played {
+        set_main_player_ramp_db(0.0);
+        return Err(e);
+    }
+
+    if alarm.ramp_minutes > 0 {
+        ramp_up(
+            generation,
+            Duration::from_secs(u64::from(alarm.ramp_minutes) * 60),
+        );
+    }
+    Ok(())
+}
+
+/// Wait for the built-in player to connect, and for a server session if
+/// media is to be picked
+fn wait_for_player(sendspin: &SendspinManager, needs_session: bool) -> Result<String, String> {
+    let deadline = Instant::now() + PLAYER_WAIT;
+    loop {
+        if let Some(player_id) = sendspin.get_player_id() {
+            if !needs_session || crate::ma_api::current_session().is_some() {
+                return Ok(player_id);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err("The built-in player isn't connected".to_string());
+        }
+        std::thread::sleep(PLAYER_POLL_INTERVAL);
+    }
+}
+
+/// Gain of a fade-in `progress` (0 to 1) of the way through
+fn ramp_db(progress: f64) -> f64 {
+    RAMP_START_DB * (1.0 - progress.clamp(0.0, 1.0))
+}
+
+/// Raise the main player's gain to unity over `length`. Stops early, back at
+/// unity, if playback is paused or another alarm goes off.
+fn ramp_up(generation: u64, length: Duration) {
+    let started = Instant::now();
+    loop {
+        if GENERATION.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let elapsed = started.elapsed();
+        let stopped = elapsed > RAMP_GRACE && !now_playing::get_now_playing().is_playing;
+        if elapsed >= length || stopped {
+            set_main_player_ramp_db(0.0);
+            return;
+        }
+        set_main_player_ramp_db(ramp_db(elapsed.as_secs_f64() / length.as_secs_f64()));
+        std::thread::sleep(RAMP_STEP);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn alarm(time: &str, days: &[u8]) -> Alarm {
+        Alarm {
+            id: "alarm".to_string(),
+            enabled: true,
+            time: time.to_string(),
+            days: days.to_vec(),
+            media_uri: None,
+            volume: None,
+            ramp_minutes: 0,
+        }
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-01-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn occurrences_follow_the_chosen_days() {
+        let once = alarm("07:30", &[]);
+        assert_eq!(next_occurrence(&once, at(1, 7, 0)), Some(at(1, 7, 30)));
+        assert_eq!(next_occurrence(&once, at(1, 7, 30)), Some(at(2, 7, 30)));
+
+        // Weekdays, asked on a Friday evening
+        let weekdays = alarm("07:30", &[0, 1, 2, 3, 4]);
+        assert_eq!(next_occurrence(&weekdays, at(5, 20, 0)), Some(at(8, 7, 30)));
+        // Sundays only, asked on a Sunday after the alarm
+        let sundays = alarm("09:00", &[6]);
+        assert_eq!(next_occurrence(&sundays, at(7, 10, 0)), Some(at(14, 9, 0)));
+
+        assert_eq!(next_occurrence(&alarm("7.30", &[]), at(1, 7, 0)), None);
+    }
+
+    #[test]
+    fn alarms_are_due_once_and_not_late() {
+        let once = alarm("07:30", &[]);
+        assert!(is_due(&once, at(1, 7, 29), at(1, 7, 30)));
+        assert!(
+            !is_due(&once, at(1, 7, 30), at(1, 7, 31)),
+            "already gone off"
+        );
+        assert!(!is_due(&once, at(1, 7, 28), at(1, 7, 29)), "not yet");
+        // Woken from sleep long after the alarm
+        assert!(!is_due(&once, at(1, 7, 0), at(1, 9, 0)));
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate(&alarm("23:59", &[0, 6])).is_ok());
+        assert!(validate(&alarm("24:00", &[])).is_err());
+        assert!(validate(&alarm("07:30", &[7])).is_err());
+        let mut bad_uri = alarm("07:30", &[]);
+        bad_uri.media_uri = Some("my playlist".to_string());
+        assert!(validate(&bad_uri).is_err());
+        let mut long_ramp = alarm("07:30", &[]);
+        long_ramp.ramp_minutes = MAX_RAMP_MINUTES + 1;
+        assert!(validate(&long_ramp).is_err());
+    }
+
+    #[test]
+    fn fade_in_rises_to_unity() {
+        assert!((ramp_db(0.0) - RAMP_START_DB).abs() < 1e-9);
+        assert!((ramp_db(0.5) - RAMP_START_DB / 2.0).abs() < 1e-9);
+        assert!(ramp_db(1.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,564 @@
+//! Disk-backed artwork cache.
+//!
+//! Cover art URLs from the server metadata point at the Music Assistant image
+//! proxy, which resizes on request via its `size` query parameter. Instead of
+//! every consumer (webview, MPRIS, `MPNowPlayingInfoCenter`, ...) re-fetching the
+//! full-size image on each track change, artwork is fetched once per size
+//! variant, stored under the user cache directory and served from disk.
+//!
+//! Only the configured server is ever contacted. Artwork hosted elsewhere is
+//! fetched through the server's image proxy, which resizes it like its own,
+//! so a URL handed in by the webview can't make the app request arbitrary
+//! hosts on the local network.
+//!
+//! The cache is bounded by total size and evicts the least recently used
+//! files first; a file's modification time doubles as its last-use stamp.
+//!
+//! Artwork the server pushes over the Sendspin connection is stored here too,
+//! under a [`pushed_key`] for its track that stands in for the URL. It comes
+//! in one size, which is served for every variant.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use tauri::Url;
+
+/// Upper bound for the on-disk cache; evicted least recently used first.
+const CACHE_LIMIT_BYTES: u64 = 64 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Artwork larger than this is almost certainly not artwork; refuse to cache it.
+const MAX_ARTWORK_BYTES: u64 = 8 * 1024 * 1024;
+/// Marks partially written files so eviction and lookups ignore them.
+const TEMP_SUFFIX: &str = ".part";
+/// Scheme of the keys pushed artwork is stored under
+const PUSHED_SCHEME: &str = "sendspin-artwork:";
+
+/// Size variants requested by the native integrations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArtworkSize {
+    /// Small thumbnail for tray / menubar surfaces
+    Tray,
+    /// Notification and OSD artwork
+    Notification,
+    /// OS media controls (SMTC, MPRIS, Now Playing)
+    MediaControls,
+    /// Whatever the server returns for the unmodified URL
+    Original,
+}
+
+impl ArtworkSize {
+    /// Edge length in pixels passed to the image proxy, `None` for the original.
+    pub(crate) const fn pixels(self) -> Option<u32> {
+        match self {
+            Self::Tray => Some(64),
+            Self::Notification => Some(256),
+            Self::MediaControls => Some(512),
+            Self::Original => None,
+        }
+    }
+
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Tray => "tray",
+            Self::Notification => "notification",
+            Self::MediaControls => "media",
+            Self::Original => "original",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tray" => Some(Self::Tray),
+            "notification" => Some(Self::Notification),
+            "media" => Some(Self::MediaControls),
+            "original" => Some(Self::Original),
+            _ => None,
+        }
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        dirs::cache_dir().map(|dir| dir.join("music-assistant-companion").join("artwork"))
+    })
+    .clone()
+}
+
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::Agent::config_builder()
+            .timeout_global(Some(FETCH_TIMEOUT))
+            .build()
+            .into()
+    })
+}
+
+/// Return the cached file for `url` at `size` if it is already on disk.
+///
+/// Never touches the network, so it is safe to call from latency-sensitive
+/// paths; pair it with [`prefetch`] to populate the cache in the background.
+pub(crate) fn cached_path(url: &str, size: ArtworkSize) -> Option<PathBuf> {
+    let size = if is_pushed(url) {
+        ArtworkSize::Original
+    } else {
+        size
+    };
+    let path = cache_dir()?.join(cache_file_name(url, size));
+    if !path.is_file() {
+        return None;
+    }
+    touch(&path);
+    Some(path)
+}
+
+/// Return the cached file for `url` at `size`, downloading it first if needed.
+///
+/// Blocking; call from a worker thread.
+pub(crate) fn fetch(url: &str, size: ArtworkSize) -> Result<PathBuf, String> {
+    if let Some(path) = cached_path(url, size) {
+        return Ok(path);
+    }
+    if is_pushed(url) {
+        return Err("pushed artwork is no longer cached".to_string());
+    }
+
+    let server = server_base_url()
+        .ok_or_else(|| "no Music Assistant server to fetch artwork from".to_string())?;
+    let source = source_url(url, size, &server)
+        .ok_or_else(|| "artwork URL is not an http(s) URL".to_string())?;
+    let bytes = download(&source)?;
+    if sniff_content_type(&bytes).is_none() {
+        return Err("response is not a supported image".to_string());
+    }
+    write_entry(url, size, &bytes)
+}
+
+/// Key to store artwork the server pushed for a track under: the player and
+/// a hash of the track's `identity`
+pub(crate) fn pushed_key(player_id: &str, identity: &str) -> String {
+    format!("{PUSHED_SCHEME}//{player_id}/{:016x}", fnv1a(identity))
+}
+
+/// Whether `url` is a [`pushed_key`] rather than something to download
+pub(crate) fn is_pushed(url: &str) -> bool {
+    url.starts_with(PUSHED_SCHEME)
+}
+
+/// Store artwork the server pushed under `key` (see [`pushed_key`]).
+///
+/// Blocking; call from a worker thread.
+pub(crate) fn store_pushed(key: &str, bytes: &[u8]) -> Result<PathBuf, String> {
+    if sniff_content_type(bytes).is_none() {
+        return Err("pushed artwork is not a supported image".to_string());
+    }
+    if bytes.len() as u64 > MAX_ARTWORK_BYTES {
+        return Err(format!(
+            "pushed artwork is too large ({} bytes)",
+            bytes.len()
+        ));
+    }
+    write_entry(key, ArtworkSize::Original, bytes)
+}
+
+fn write_entry(url: &str, size: ArtworkSize, bytes: &[u8]) -> Result<PathBuf, String> {
+    let dir = cache_dir().ok_or_else(|| "no cache directory available".to_string())?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create artwork cache dir: {e}"))?;
+
+    let path = dir.join(cache_file_name(url, size));
+    let temp_path = path.with_extension(format!("img{TEMP_SUFFIX}"));
+    let write_result = fs::File::create(&temp_path)
+        .and_then(|mut file| file.write_all(bytes))
+        .and_then(|()| fs::rename(&temp_path, &path));
+    if let Err(e) = write_result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Failed to write artwork cache entry: {e}"));
+    }
+    log::debug!(
+        "[ArtworkCache] Cached {} variant ({} bytes) for {url}",
+        size.as_str(),
+        bytes.len()
+    );
+
+    evict(&dir, CACHE_LIMIT_BYTES);
+    Ok(path)
+}
+
+/// Read the cached bytes for `url` at `size`, downloading them if needed.
+pub(crate) fn fetch_bytes(url: &str, size: ArtworkSize) -> Result<Vec<u8>, String> {
+    let path = fetch(url, size)?;
+    fs::read(&path).map_err(|e| format!("Failed to read cached artwork: {e}"))
+}
+
+/// Populate the cache for `url` at `size` on a background thread.
+pub(crate) fn prefetch(url: &str, size: ArtworkSize) {
+    if cached_path(url, size).is_some() {
+        return;
+    }
+    let url = url.to_string();
+    std::thread::spawn(move || {
+        if let Err(e) = fetch(&url, size) {
+            log::debug!("[ArtworkCache] Prefetch failed for {url}: {e}");
+        }
+    });
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let mut request = agent().get(url);
+    // The image proxy sits behind the same auth as the API when it is not
+    // publicly reachable; pass the session token to same-server URLs only.
+    if let Some(session) = crate::ma_api::current_session() {
+        if same_server(url, &session.server_base_url) {
+            request = request.header("Authorization", format!("Bearer {}", session.auth_token));
+        }
+    }
+    let mut response = request.call().map_err(|e| e.to_string())?;
+    response
+        .body_mut()
+        .with_config()
+        .limit(MAX_ARTWORK_BYTES)
+        .read_to_vec()
+        .map_err(|e| e.to_string())
+}
+
+/// Base URL of the server in use, or of the last one connected to
+fn server_base_url() -> Option<String> {
+    crate::ma_api::current_session()
+        .map(|session| session.server_base_url)
+        .or_else(|| crate::settings::read(|s| s.last_server_url.clone()))
+}
+
+/// Where to download `url` at `size` from: the server's own image proxy URLs
+/// directly, anything else through that proxy. `None` for URLs that aren't
+/// http(s).
+fn source_url(url: &str, size: ArtworkSize, server_base_url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return None;
+    }
+    if same_server(url, server_base_url) && parsed.path().ends_with("/imageproxy") {
+        return Some(variant_url(url, size));
+    }
+    let mut proxy = Url::parse(&format!(
+        "{}/imageproxy",
+        server_base_url.trim_end_matches('/')
+    ))
+    .ok()?;
+    proxy.query_pairs_mut().append_pair("path", url);
+    if let Some(pixels) = size.pixels() {
+        proxy
+            .query_pairs_mut()
+            .append_pair("size", &pixels.to_string());
+    }
+    Some(proxy.into())
+}
+
+/// Whether `url` is served by the server at `server_base_url`: same scheme,
+/// host and port. A prefix match would also let through look-alikes such as
+/// `http://ma.local:8095.example.com`.
+fn same_server(url: &str, server_base_url: &str) -> bool {
+    let (Ok(url), Ok(server)) = (Url::parse(url), Url::parse(server_base_url)) else {
+        return false;
+    };
+    url.scheme() == server.scheme()
+        && url.host().is_some()
+        && url.host() == server.host()
+        && url.port_or_known_default() == server.port_or_known_default()
+}
+
+/// Rewrite an image proxy URL to request the given size.
+///
+/// Only `/imageproxy` URLs understand the `size` parameter; anything else is
+/// left as it is (see [`source_url`]).
+fn variant_url(url: &str, size: ArtworkSize) -> String {
+    let Some(pixels) = size.pixels() else {
+        return url.to_string();
+    };
+    let (base, fragment) = url
+        .split_once('#')
+        .map_or((url, None), |(b, f)| (b, Some(f)));
+    let (path, query) = base
+        .split_once('?')
+        .map_or((base, None), |(p, q)| (p, Some(q)));
+    if !path.contains("/imageproxy") {
+        return url.to_string();
+    }
+
+    let mut params: Vec<String> = query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("size="))
+        .map(ToString::to_string)
+        .collect();
+    params.push(format!("size={pixels}"));
+
+    let mut rewritten = format!("{path}?{}", params.join("&"));
+    if let Some(fragment) = fragment {
+        rewritten.push('#');
+        rewritten.push_str(fragment);
+    }
+    rewritten
+}
+
+/// Stable file name for a URL/size pair (FNV-1a, so it survives toolchain
+/// upgrades unlike `DefaultHasher`).
+fn cache_file_name(url: &str, size: ArtworkSize) -> String {
+    format!("{:016x}-{}.img", fnv1a(url), size.as_str())
+}
+
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.as_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// MIME type of a supported image, detected from its magic bytes.
+pub(crate) fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// Mark a cache entry as recently used.
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+fn evict(dir: &Path, limit: u64) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    let entries: Vec<CacheEntry> = read_dir
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_name().to_string_lossy().ends_with(TEMP_SUFFIX))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| CacheEntry {
+                path: entry.path(),
+                size: metadata.len(),
+                last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            })
+        })
+        .collect();
+
+    for path in plan_eviction(entries, limit) {
+        if let Err(e) = fs::remove_file(&path) {
+            log::debug!("[ArtworkCache] Failed to evict {}: {e}", path.display());
+        }
+    }
+}
+
+/// Pick the least recently used entries to delete so the rest fit in `limit`.
+fn plan_eviction(mut entries: Vec<CacheEntry>, limit: u64) -> Vec<PathBuf> {
+    let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+    if total <= limit {
+        return Vec::new();
+    }
+    entries.sort_by_key(|entry| entry.last_used);
+    let mut evicted = Vec::new();
+    for entry in entries {
+        if total <= limit {
+            break;
+        }
+        total = total.saturating_sub(entry.size);
+        evicted.push(entry.path);
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, age_secs: u64) -> CacheEntry {
+        CacheEntry {
+            path: PathBuf::from(name),
+            size,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000 - age_secs),
+        }
+    }
+
+    #[test]
+    fn test_same_server_compares_scheme_host_and_port() {
+        let server = "http://ma.local:8095/";
+        assert!(same_server(
+            "http://ma.local:8095/imageproxy?path=x",
+            server
+        ));
+        assert!(same_server(
+            "http://MA.local:8095/a",
+            "http://ma.local:8095"
+        ));
+        assert!(same_server(
+            "https://ma.example/a",
+            "https://ma.example:443"
+        ));
+
+        assert!(!same_server("http://ma.local:8095.example.com/a", server));
+        assert!(!same_server("http://ma.local:80950/a", server));
+        assert!(!same_server("http://ma.local.example.com:8095/a", server));
+        assert!(!same_server("https://ma.local:8095/a", server));
+        assert!(!same_server("http://ma.local/a", server));
+        assert!(!same_server("not a url", server));
+    }
+
+    #[test]
+    fn test_source_url_only_contacts_the_server() {
+        let server = "http://ma.local:8095";
+        assert_eq!(
+            source_url(
+                "http://ma.local:8095/imageproxy?path=abc&size=1000",
+                ArtworkSize::Tray,
+                server
+            )
+            .as_deref(),
+            Some("http://ma.local:8095/imageproxy?path=abc&size=64")
+        );
+        // Hosted elsewhere: through the proxy, which resizes it
+        assert_eq!(
+            source_url("https://cdn.example/cover.jpg?x=1&y=2", ArtworkSize::Tray, server)
+                .as_deref(),
+            Some("http://ma.local:8095/imageproxy?path=https%3A%2F%2Fcdn.example%2Fcover.jpg%3Fx%3D1%26y%3D2&size=64")
+        );
+        assert_eq!(
+            source_url("http://192.168.1.1/admin", ArtworkSize::Original, server).as_deref(),
+            Some("http://ma.local:8095/imageproxy?path=http%3A%2F%2F192.168.1.1%2Fadmin")
+        );
+        assert!(source_url("file:///etc/passwd", ArtworkSize::Tray, server).is_none());
+        assert!(source_url("not a url", ArtworkSize::Tray, server).is_none());
+    }
+
+    #[test]
+    fn test_plan_eviction_keeps_everything_under_limit() {
+        let entries = vec![entry("a", 10, 5), entry("b", 10, 1)];
+        assert!(plan_eviction(entries, 20).is_empty());
+    }
+
+    #[test]
+    fn test_plan_eviction_drops_least_recently_used_first() {
+        let entries = vec![
+            entry("recent", 40, 1),
+            entry("oldest", 40, 100),
+            entry("middle", 40, 50),
+        ];
+        assert_eq!(plan_eviction(entries, 80), vec![PathBuf::from("oldest")]);
+
+        let entries = vec![
+            entry("recent", 40, 1),
+            entry("oldest", 40, 100),
+            entry("middle", 40, 50),
+        ];
+        assert_eq!(
+            plan_eviction(entries, 50),
+            vec![PathBuf::from("oldest"), PathBuf::from("middle")]
+        );
+    }
+
+    #[test]
+    fn test_variant_url_rewrites_imageproxy_size() {
+        assert_eq!(
+            variant_url(
+                "http://ma:8095/imageproxy?path=abc&provider=x&size=1000",
+                ArtworkSize::Tray
+            ),
+            "http://ma:8095/imageproxy?path=abc&provider=x&size=64"
+        );
+        assert_eq!(
+            variant_url(
+                "http://ma:8095/imageproxy?path=abc",
+                ArtworkSize::MediaControls
+            ),
+            "http://ma:8095/imageproxy?path=abc&size=512"
+        );
+        // Original keeps the URL untouched
+        assert_eq!(
+            variant_url(
+                "http://ma:8095/imageproxy?path=abc&size=1000",
+                ArtworkSize::Original
+            ),
+            "http://ma:8095/imageproxy?path=abc&size=1000"
+        );
+        // Non-proxy URLs cannot be resized server-side
+        assert_eq!(
+            variant_url("https://cdn.example/cover.jpg", ArtworkSize::Tray),
+            "https://cdn.example/cover.jpg"
+        );
+    }
+
+    #[test]
+    fn test_cache_file_name_is_stable_and_size_specific() {
+        let url = "http://ma:8095/imageproxy?path=abc";
+        assert_eq!(
+            cache_file_name(url, ArtworkSize::Tray),
+            cache_file_name(url, ArtworkSize::Tray)
+        );
+        assert_ne!(
+            cache_file_name(url, ArtworkSize::Tray),
+            cache_file_name(url, ArtworkSize::Notification)
+        );
+        assert_ne!(
+            cache_file_name(url, ArtworkSize::Tray),
+            cache_file_name("http://ma:8095/imageproxy?path=def", ArtworkSize::Tray)
+        );
+    }
+
+    #[test]
+    fn test_pushed_keys_are_per_player_and_track() {
+        let key = pushed_key("player-1", "Song\nArtist\nAlbum");
+        assert!(is_pushed(&key));
+        assert_eq!(key, pushed_key("player-1", "Song\nArtist\nAlbum"));
+        assert_ne!(key, pushed_key("player-2", "Song\nArtist\nAlbum"));
+        assert_ne!(key, pushed_key("player-1", "Other\nArtist\nAlbum"));
+        assert!(!is_pushed("http://ma:8095/imageproxy?path=abc"));
+    }
+
+    #[test]
+    fn test_sniff_content_type() {
+        assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n"), Some("image/png"));
+        assert_eq!(
+            sniff_content_type(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some("image/jpeg")
+        );
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_content_type(b"<html>"), None);
+    }
+
+    #[test]
+    fn test_artwork_size_round_trips() {
+        for size in [
+            ArtworkSize::Tray,
+            ArtworkSize::Notification,
+            ArtworkSize::MediaControls,
+            ArtworkSize::Original,
+        ] {
+            assert_eq!(ArtworkSize::parse(size.as_str()), Some(size));
+        }
+        assert_eq!(ArtworkSize::parse("huge"), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! Launch at login
+//!
+//! Registers the app as a login item: a Launch Agent driven through
+//! `AppleScript` on macOS, a `Run` registry value on Windows and an XDG
+//! autostart entry on Linux (written directly inside Flatpak, where the
+//! plugin can't reach the host's autostart directory). Login launches carry
+//! [`LOGIN_FLAG`] so the app can tell them apart and start in the tray.
+
+use crate::settings;
+use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
+
+/// Argument the login item launches the app with
+pub const LOGIN_FLAG: &str = "--autostart";
+
+/// The autostart plugin, set up to pass [`LOGIN_FLAG`]
+pub fn plugin<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<R> {
+    tauri_plugin_autostart::init(MacosLauncher::AppleScript, Some(vec![LOGIN_FLAG]))
+}
+
+/// Whether this process was started by the login item
+pub fn launched_at_login() -> bool {
+    std::env::args().skip(1).any(|arg| arg == LOGIN_FLAG)
+}
+
+fn in_flatpak() -> bool {
+    cfg!(target_os = "linux") && std::env::var_os("FLATPAK_ID").is_some()
+}
+
+/// Register or unregister the login item
+pub fn set_enabled(app: &tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    if in_flatpak() {
+        return set_flatpak_autostart(enabled).map_err(|error| {
+            let message = format!("Failed to update Flatpak autostart: {error}");
+            log::warn!("[Autostart] {message}");
+            message
+        });
+    }
+
+    let autostart_manager = app.autolaunch();
+
+    let result = if enabled {
+        autostart_manager.enable()
+    } else {
+        autostart_manager.disable()
+    };
+
+    result.map_err(|error| {
+        let message = format!("Failed to update autostart: {error}");
+        log::warn!("[Autostart] {message}");
+        message
+    })
+}
+
+/// Bring the `autostart` setting and the OS registration back in line at
+/// startup. A login item removed in the system settings turns the setting
+/// off; one that is still there is re-registered, so it picks up the current
+/// executable path and launch arguments.
+pub fn sync(app: &tauri::AppHandle) {
+    if !settings::get_settings().autostart {
+        return;
+    }
+    // The Flatpak entry can't be checked through the plugin; just rewrite it
+    if in_flatpak() {
+        let _ = set_enabled(app, true);
+        return;
+    }
+    match app.autolaunch().is_enabled() {
+        Ok(true) => {
+            if let Err(error) = app.autolaunch().enable() {
+                log::warn!("[Autostart] Failed to refresh the login item: {error}");
+            }
+        }
+        Ok(false) => {
+            log::info!("[Autostart] Login item was removed outside the app; turning autostart off");
+            if let Err(error) = settings::set_setting(app.clone(), "autostart", false) {
+                log::warn!("[Autostart] Failed to save the autostart setting: {error}");
+            }
+        }
+        Err(error) => log::warn!("[Autostart] Failed to check the login item: {error}"),
+    }
+}
+
+#[cfg(all(desktop, target_os = "linux"))]
+fn set_flatpak_autostart(enabled: bool) -> std::io::Result<()> {
+    const DESKTOP_FILE_NAME: &str = "io.music_assistant.Companion.desktop";
+    const AUTOSTART_DESKTOP_ENTRY: &str = include_str!("../templates/flatpak-autostart.desktop");
+
+    // In a Flatpak sandbox, XDG_CONFIG_HOME points at the app-private config
+    // dir. The manifest grants `xdg-config/autostart:create`, so write through
+    // $HOME/.config/autostart to reach the host XDG autostart directory.
+    let autostart_dir = dirs::home_dir()
+        .ok_or_else(|| std::io::Error::other("Could not determine home directory"))?
+        .join(".config")
+        .join("autostart");
+    let autostart_file = autostart_dir.join(DESKTOP_FILE_NAME);
+
+    if !enabled {
+        match std::fs::remove_file(&autostart_file) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(&autostart_dir)?;
+
+    let temp_file = autostart_file.with_extension("desktop.tmp");
+    std::fs::write(&temp_file, AUTOSTART_DESKTOP_ENTRY)?;
+    std::fs::rename(temp_file, autostart_file)?;
+
+    Ok(())
+}
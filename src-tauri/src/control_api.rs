@@ -0,0 +1,503 @@
+//! Local control API
+//!
+//! An optional HTTP and WebSocket API on localhost, so tools like
+//! `AutoHotkey`, Stream Deck plugins and scripts can drive the built-in player
+//! directly instead of through the MA server. It answers the same requests
+//! as the command line (see [`crate::ipc`]), plus the now-playing state:
+//!
+//! - `GET /api/status`, `GET /api/now_playing`, `GET /api/volume`
+//! - `POST /api/play`, `/api/pause`, `/api/stop`, `/api/next`, `/api/previous`
+//! - `POST /api/volume` with `{"volume": 0-100}`
+//! - `GET /api/ws`, a WebSocket taking the same commands as JSON messages
+//!   (`{"id": 1, "command": "volume", "volume": 40}`) and pushing
+//!   now-playing changes as `{"event": "now_playing", "data": {...}}`
+//! - `GET /metrics`, playback and sync statistics for Prometheus (see
+//!   [`crate::metrics`])
+//!
+//! Replies are JSON, with `{"error": "..."}` for failures. Every request
+//! carries the token from the settings, as `Authorization: Bearer <token>`
+//! or, for WebSocket clients that can't set headers, a `token` query
+//! parameter.
+
+use crate::ipc;
+use crate::metrics;
+use crate::now_playing::{self, NowPlaying};
+use crate::sendspin::http_stream::header;
+use crate::sendspin::SendspinManager;
+use crate::settings;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Message as WsMessage, Role};
+use tokio_tungstenite::WebSocketStream;
+
+/// Port the API listens on unless the settings say otherwise
+pub const DEFAULT_PORT: u16 = 8771;
+/// Most bytes of request head and body accepted
+const MAX_REQUEST: usize = 16 * 1024;
+/// Now-playing changes held for a slow WebSocket client before it skips some
+const UPDATE_BACKLOG: usize = 16;
+/// Time a client gets to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause after a failed accept, which fails again straight away while the
+/// process is out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Commands WebSocket messages can carry
+const COMMANDS: &[&str] = &[
+    "status",
+    "now_playing",
+    "volume",
+    "play",
+    "pause",
+    "stop",
+    "next",
+    "previous",
+];
+
+/// The running server
+struct Server {
+    sendspin: SendspinManager,
+    task: tauri::async_runtime::JoinHandle<()>,
+    /// Dropped to close the WebSocket connections
+    _shutdown: watch::Sender<()>,
+}
+
+static SERVER: Mutex<Option<Server>> = Mutex::new(None);
+
+/// Now-playing changes, for WebSocket clients to subscribe to
+static UPDATES: OnceLock<broadcast::Sender<NowPlaying>> = OnceLock::new();
+
+/// What a request asks for
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// Command-line style arguments to answer with [`call`]
+    Call(Vec<String>),
+    WebSocket,
+    Metrics,
+}
+
+fn updates() -> &'static broadcast::Sender<NowPlaying> {
+    UPDATES.get_or_init(|| {
+        let (tx, _) = broadcast::channel(UPDATE_BACKLOG);
+        let sender = tx.clone();
+        now_playing::on_now_playing_change(Arc::new(move |now_playing| {
+            let _ = sender.send(now_playing.clone());
+        }));
+        tx
+    })
+}
+
+/// A new random API token
+pub fn new_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Compare without stopping at the first difference, so response times
+/// don't give the token away
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The token a request carries
+fn request_token<'a>(head: &'a str, query: &'a str) -> Option<&'a str> {
+    header(head, "authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+        })
+}
+
+fn authorized(head: &str, query: &str) -> bool {
+    let token = settings::get_settings().control_api_token;
+    match (request_token(head, query), token) {
+        (Some(given), Some(token)) => !token.is_empty() && same_token(given, &token),
+        _ => false,
+    }
+}
+
+/// What `method` on `path` asks for, or the status and error to reply with
+fn route(method: &str, path: &str, body: &[u8]) -> Result<Route, (&'static str, String)> {
+    if path == "/metrics" {
+        return match method {
+            "GET" => Ok(Route::Metrics),
+            _ => Err((
+                "405 Method Not Allowed",
+                format!("{} isn't allowed on {}", method, path),
+            )),
+        };
+    }
+    let name = path
+        .strip_prefix("/api/")
+        .ok_or_else(|| ("404 Not Found", format!("No such endpoint: {}", path)))?;
+    match (method, name) {
+        ("GET", "ws") => Ok(Route::WebSocket),
+        ("GET", "status" | "now_playing" | "volume")
+        | ("POST", "play" | "pause" | "stop" | "next" | "previous") => {
+            Ok(Route::Call(vec![name.to_string()]))
+        }
+        ("POST", "volume") => {
+            let volume = serde_json::from_slice::<Value>(body)
+                .ok()
+                .and_then(|body| body.get("volume").and_then(Value::as_u64))
+                .ok_or_else(|| {
+                    (
+                        "400 Bad Request",
+                        "Expected {\"volume\": 0-100}".to_string(),
+                    )
+                })?;
+            Ok(Route::Call(vec![name.to_string(), volume.to_string()]))
+        }
+        (
+            _,
+            "ws" | "status" | "now_playing" | "volume" | "play" | "pause" | "stop" | "next"
+            | "previous",
+        ) => Err((
+            "405 Method Not Allowed",
+            format!("{} isn't allowed on {}", method, path),
+        )),
+        _ => Err(("404 Not Found", format!("No such endpoint: {}", path))),
+    }
+}
+
+/// Command-line style arguments for a WebSocket message
+fn message_args(message: &Value) -> Result<Vec<String>, String> {
+    let command = message
+        .get("command")
+        .and_then(Value::as_str)
+        .filter(|command| COMMANDS.contains(command))
+        .ok_or_else(|| format!("Expected a command: {}", COMMANDS.join(", ")))?;
+    let mut args = vec![command.to_string()];
+    if let Some(volume) = message.get("volume").filter(|_| command == "volume") {
+        args.push(volume.to_string());
+    }
+    Ok(args)
+}
+
+fn call(sendspin: &SendspinManager, args: &[String]) -> Result<Value, String> {
+    if args == ["now_playing"] {
+        return serde_json::to_value(now_playing::get_now_playing())
+            .map_err(|e| format!("Failed to serialize now playing: {}", e));
+    }
+    ipc::handle(sendspin, args)
+}
+
+/// Reply to a WebSocket message, echoing its `id`
+fn handle_message(sendspin: &SendspinManager, text: &str) -> Value {
+    let message = match serde_json::from_str::<Value>(text) {
+        Ok(message) => message,
+        Err(e) => return json!({ "error": format!("Malformed message: {}", e) }),
+    };
+    let mut reply = match message_args(&message).and_then(|args| call(sendspin, &args)) {
+        Ok(result) => json!({ "result": result }),
+        Err(e) => json!({ "error": e }),
+    };
+    if let Some(id) = message.get("id") {
+        reply["id"] = id.clone();
+    }
+    reply
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<(String, Vec<u8>), String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_REQUEST {
+            return Err("Request too large".to_string());
+        }
+        let read = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("Connection closed mid-request".to_string());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let content_length = header(&head, "content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0)
+        .min(MAX_REQUEST);
+    while buffer.len() < head_end + content_length {
+        let read = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    Ok((head, buffer.split_off(head_end)))
+}
+
+async fn handle_connection(
+    sendspin: SendspinManager,
+    mut stream: TcpStream,
+    shutdown: watch::Receiver<()>,
+) -> Result<(), String> {
+    let (head, body) = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| "Timed out reading the request".to_string())??;
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let routed = if authorized(&head, query) {
+        route(method, path, &body)
+    } else {
+        Err(("401 Unauthorized", "Missing or wrong token".to_string()))
+    };
+    let (status, reply) = match routed {
+        Ok(Route::WebSocket) => match header(&head, "sec-websocket-key") {
+            Some(key) => {
+                let key = key.to_string();
+                return serve_websocket(sendspin, stream, &key, shutdown).await;
+            }
+            None => (
+                "400 Bad Request",
+                json!({ "error": "Expected a WebSocket upgrade" }),
+            ),
+        },
+        Ok(Route::Metrics) => {
+            let reply = metrics::render(&sendspin.player_stats());
+            return write_response(&mut stream, "200 OK", metrics::CONTENT_TYPE, &reply).await;
+        }
+        Ok(Route::Call(args)) => {
+            log::debug!("[Control API] {} {}", method, path);
+            match call(&sendspin, &args) {
+                Ok(result) => ("200 OK", result),
+                Err(e) => ("500 Internal Server Error", json!({ "error": e })),
+            }
+        }
+        Err((status, e)) => (status, json!({ "error": e })),
+    };
+    write_response(&mut stream, status, "application/json", &reply.to_string()).await
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    reply: &str,
+) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        content_type,
+        reply.len(),
+        reply
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Complete the upgrade on `stream`, then answer commands and push
+/// now-playing changes until the client goes or the API stops.
+async fn serve_websocket(
+    sendspin: SendspinManager,
+    mut stream: TcpStream,
+    key: &str,
+    mut shutdown: watch::Receiver<()>,
+) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut socket = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+    let mut updates = updates().subscribe();
+    log::debug!("[Control API] WebSocket client connected");
+
+    let status = json!({
+        "event": "status",
+        "data": call(&sendspin, &["status".to_string()])?,
+    });
+    socket
+        .send(WsMessage::Text(status.to_string().into()))
+        .await
+        .map_err(|e| e.to_string())?;
+    loop {
+        let outgoing = tokio::select! {
+            _ = shutdown.changed() => {
+                let _ = socket.close(None).await;
+                return Ok(());
+            }
+            update = updates.recv() => match update {
+                Ok(now_playing) => json!({ "event": "now_playing", "data": now_playing }),
+                // The next change brings the client up to date
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            message = socket.next() => match message {
+                Some(Ok(WsMessage::Text(text))) => handle_message(&sendspin, text.as_ref()),
+                Some(Ok(WsMessage::Close(_))) | None => return Ok(()),
+                // Pings are answered by the WebSocket layer
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.to_string()),
+            },
+        };
+        socket
+            .send(WsMessage::Text(outgoing.to_string().into()))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+}
+
+async fn serve(sendspin: SendspinManager, listener: TcpListener, shutdown: watch::Receiver<()>) {
+    loop {
+        let (stream, from) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::warn!("[Control API] Failed to accept a connection: {}", e);
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let sendspin = sendspin.clone();
+        let shutdown = shutdown.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = handle_connection(sendspin, stream, shutdown).await {
+                log::debug!("[Control API] Request from {} failed: {}", from, e);
+            }
+        });
+    }
+}
+
+/// Listen on the port in the settings, replacing a server already running.
+pub fn start(sendspin: SendspinManager) -> Result<(), String> {
+    stop();
+    let port = settings::get_settings().control_api_port;
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        .map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let serving = sendspin.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        match TcpListener::from_std(listener) {
+            Ok(listener) => serve(serving, listener, shutdown_rx).await,
+            Err(e) => log::warn!("[Control API] Failed to listen: {}", e),
+        }
+    });
+    log::info!("[Control API] Listening on 127.0.0.1:{}", port);
+    if let Ok(mut server) = SERVER.lock() {
+        *server = Some(Server {
+            sendspin,
+            task,
+            _shutdown: shutdown_tx,
+        });
+    }
+    Ok(())
+}
+
+/// Stop listening and close the WebSocket connections
+pub fn stop() {
+    let server = SERVER.lock().ok().and_then(|mut server| server.take());
+    if let Some(server) = server {
+        server.task.abort();
+        log::info!("[Control API] Stopped");
+    }
+}
+
+/// Start again on the port in the settings, if running, dropping clients
+/// of the old token
+pub fn restart() -> Result<(), String> {
+    let sendspin = SERVER
+        .lock()
+        .ok()
+        .and_then(|server| server.as_ref().map(|server| server.sendspin.clone()));
+    match sendspin {
+        Some(sendspin) => start(sendspin),
+        None => Ok(()),
+    }
+}
+
+/// Start the API if it's enabled in the settings
+pub fn init(sendspin: SendspinManager) {
+    if !settings::get_settings().control_api_enabled {
+        return;
+    }
+    if let Err(e) = start(sendspin) {
+        log::warn!("[Control API] {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Route {
+        Route::Call(list.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn routes_requests_to_commands() {
+        assert_eq!(route("GET", "/api/status", b""), Ok(args(&["status"])));
+        assert_eq!(route("POST", "/api/next", b""), Ok(args(&["next"])));
+        assert_eq!(
+            route("POST", "/api/volume", br#"{"volume": 40}"#),
+            Ok(args(&["volume", "40"]))
+        );
+        assert_eq!(route("GET", "/api/ws", b""), Ok(Route::WebSocket));
+        assert_eq!(route("GET", "/metrics", b""), Ok(Route::Metrics));
+        assert_eq!(
+            route("POST", "/api/volume", b"40").map_err(|(status, _)| status),
+            Err("400 Bad Request")
+        );
+        assert_eq!(
+            route("GET", "/api/play", b"").map_err(|(status, _)| status),
+            Err("405 Method Not Allowed")
+        );
+        assert_eq!(
+            route("GET", "/index.html", b"").map_err(|(status, _)| status),
+            Err("404 Not Found")
+        );
+    }
+
+    #[test]
+    fn takes_the_token_from_the_header_or_query() {
+        let head = "GET /api/status HTTP/1.1\r\nAuthorization: Bearer abc\r\n\r\n";
+        assert_eq!(request_token(head, ""), Some("abc"));
+        let head = "GET /api/ws HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(request_token(head, "x=1&token=def"), Some("def"));
+        assert_eq!(request_token(head, ""), None);
+
+        assert!(same_token("abc", "abc"));
+        assert!(!same_token("abd", "abc"));
+        assert!(!same_token("ab", "abc"));
+    }
+
+    #[test]
+    fn reads_commands_from_websocket_messages() {
+        assert_eq!(
+            message_args(&json!({ "command": "volume", "volume": 40 })),
+            Ok(vec!["volume".to_string(), "40".to_string()])
+        );
+        // Only the volume command takes a level
+        assert_eq!(
+            message_args(&json!({ "command": "play", "volume": 40 })),
+            Ok(vec!["play".to_string()])
+        );
+        assert!(message_args(&json!({ "command": "rewind" })).is_err());
+        assert!(message_args(&json!({ "volume": 40 })).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! `music-assistant://` deep links
+//!
+//! Links open the app, or are handed to the running instance, and act on it:
+//!
+//! - `music-assistant://open` brings the window to the front
+//! - `music-assistant://play/<media_type>/<item_id>[?provider=<provider>]`
+//!   plays a library item (or an item of a streaming provider) on the
+//!   built-in player
+//! - `music-assistant://control/<play|pause|stop|next|previous>`
+//! - `music-assistant://settings[/<section>]` opens the settings window,
+//!   optionally at `integrations`, `behavior`, `shortcuts`, `player`,
+//!   `players` or `troubleshooting`
+
+use crate::sendspin::{PlaybackCommand, SendspinManager};
+use std::time::{Duration, Instant};
+use tauri::{Manager, Url};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+/// URL scheme registered with the OS
+const SCHEME: &str = "music-assistant";
+/// Media types a `play` link may name
+const MEDIA_TYPES: &[&str] = &[
+    "track",
+    "album",
+    "artist",
+    "playlist",
+    "radio",
+    "podcast",
+    "audiobook",
+];
+/// How long a `play` link that launched the app waits for the built-in
+/// player to connect
+const PLAYER_WAIT: Duration = Duration::from_secs(30);
+const PLAYER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq)]
+enum DeepLink {
+    Open,
+    /// Play the Music Assistant media URI
+    Play(String),
+    Control(PlaybackCommand),
+    /// Open settings, at the heading element with this id if given
+    Settings(Option<&'static str>),
+}
+
+/// Whether a command-line argument is a deep link
+pub fn is_link(arg: &str) -> bool {
+    arg.get(..SCHEME.len() + 1)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&format!("{}:", SCHEME)))
+}
+
+/// Id of the settings page heading for a link's section name
+fn settings_heading(section: &str) -> Option<&'static str> {
+    match section {
+        "integrations" => Some("heading-integrations"),
+        "behavior" => Some("heading-behavior"),
+        "shortcuts" => Some("heading-hotkeys"),
+        "player" | "audio" => Some("heading-audio-output"),
+        "players" => Some("heading-additional-players"),
+        "troubleshooting" => Some("heading-troubleshooting"),
+        _ => None,
+    }
+}
+
+fn parse(link: &str) -> Result<DeepLink, String> {
+    let url = Url::parse(link).map_err(|e| format!("Invalid link: {}", e))?;
+    if url.scheme() != SCHEME {
+        return Err(format!("Not a {} link", SCHEME));
+    }
+    let action = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    match (action.as_str(), segments.as_slice()) {
+        ("" | "open", []) => Ok(DeepLink::Open),
+        ("play", [media_type, item_id]) => {
+            if !MEDIA_TYPES.contains(media_type) {
+                return Err(format!("Unknown media type: {}", media_type));
+            }
+            let provider = url
+                .query_pairs()
+                .find(|(key, _)| key == "provider")
+                .map_or_else(|| "library".to_string(), |(_, value)| value.into_owned());
+            Ok(DeepLink::Play(format!(
+                "{}://{}/{}",
+                provider, media_type, item_id
+            )))
+        }
+        ("control", [command]) => match *command {
+            "play" => Ok(DeepLink::Control(PlaybackCommand::Play)),
+            "pause" => Ok(DeepLink::Control(PlaybackCommand::Pause)),
+            "stop" => Ok(DeepLink::Control(PlaybackCommand::Stop)),
+            "next" => Ok(DeepLink::Control(PlaybackCommand::Next)),
+            "previous" | "prev" => Ok(DeepLink::Control(PlaybackCommand::Previous)),
+            other => Err(format!("Unknown command: {}", other)),
+        },
+        ("settings", []) => Ok(DeepLink::Settings(None)),
+        ("settings", [section]) => settings_heading(section)
+            .map(|heading| DeepLink::Settings(Some(heading)))
+            .ok_or_else(|| format!("Unknown settings section: {}", section)),
+        _ => Err("Unsupported link".to_string()),
+    }
+}
+
+/// Play `uri` on the built-in player once it is connected. Runs on its own
+/// thread since the API call blocks.
+fn play_when_connected(sendspin: SendspinManager, uri: String) {
+    std::thread::spawn(move || {
+        let deadline = Instant::now() + PLAYER_WAIT;
+        let player_id = loop {
+            if let Some(player_id) = sendspin.get_player_id() {
+                if crate::ma_api::current_session().is_some() {
+                    break player_id;
+                }
+            }
+            if Instant::now() >= deadline {
+                log::warn!(
+                    "[DeepLink] Not playing {}: the built-in player isn't connected",
+                    uri
+                );
+                return;
+            }
+            std::thread::sleep(PLAYER_POLL_INTERVAL);
+        };
+        match crate::ma_api::play_media(&player_id, &uri) {
+            Ok(()) => log::info!("[DeepLink] Playing {}", uri),
+            Err(e) => log::warn!("[DeepLink] Failed to play {}: {}", uri, e),
+        }
+    });
+}
+
+/// Act on an opened `music-assistant://` link
+pub fn handle(app: &tauri::AppHandle, link: &str) {
+    log::info!("[DeepLink] Opened {}", link);
+    let deep_link = match parse(link) {
+        Ok(deep_link) => deep_link,
+        Err(e) => {
+            log::warn!("[DeepLink] Ignoring {}: {}", link, e);
+            return;
+        }
+    };
+    match deep_link {
+        DeepLink::Open => crate::show_main_window(app),
+        DeepLink::Settings(section) => crate::open_settings_window(app, section),
+        DeepLink::Control(command) => {
+            if let Err(e) = app.state::<SendspinManager>().send_command(command) {
+                log::warn!("[DeepLink] {:?} failed: {}", command, e);
+            }
+        }
+        DeepLink::Play(uri) => {
+            play_when_connected(app.state::<SendspinManager>().inner().clone(), uri);
+        }
+    }
+}
+
+/// Route links opened while the app runs, and the one it was launched
+/// with, if any.
+pub fn init(app: &tauri::AppHandle) {
+    // Installers register the scheme; AppImages and development builds
+    // have to do it themselves
+    #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+    if let Err(e) = app.deep_link().register_all() {
+        log::warn!("[DeepLink] Failed to register the {} scheme: {}", SCHEME, e);
+    }
+
+    let app_handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            handle(&app_handle, url.as_str());
+        }
+    });
+    if let Ok(Some(urls)) = app.deep_link().get_current() {
+        for url in urls {
+            handle(app, url.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_links_become_media_uris() {
+        assert_eq!(
+            parse("music-assistant://play/album/12"),
+            Ok(DeepLink::Play("library://album/12".to_string()))
+        );
+        assert_eq!(
+            parse("music-assistant://play/track/abc?provider=spotify"),
+            Ok(DeepLink::Play("spotify://track/abc".to_string()))
+        );
+        assert!(parse("music-assistant://play/genre/1").is_err());
+        assert!(parse("music-assistant://play/album").is_err());
+    }
+
+    #[test]
+    fn control_and_settings_links() {
+        assert_eq!(
+            parse("music-assistant://control/next"),
+            Ok(DeepLink::Control(PlaybackCommand::Next))
+        );
+        assert_eq!(
+            parse("music-assistant://settings"),
+            Ok(DeepLink::Settings(None))
+        );
+        assert_eq!(
+            parse("music-assistant://settings/player/"),
+            Ok(DeepLink::Settings(Some("heading-audio-output")))
+        );
+        assert!(parse("music-assistant://settings/nope").is_err());
+        assert_eq!(parse("music-assistant://open"), Ok(DeepLink::Open));
+    }
+
+    #[test]
+    fn recognizes_links_among_arguments() {
+        assert!(is_link("music-assistant://open"));
+        assert!(is_link("Music-Assistant://open"));
+        assert!(!is_link("--headless"));
+        assert!(!is_link("music"));
+    }
+
+    #[test]
+    fn other_schemes_are_rejected() {
+        assert!(parse("https://play/album/12").is_err());
+        assert!(parse("not a link").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Diagnostics bundle for bug reports
+//!
+//! Gathers the logs, the current settings, the audio device list, the
+//! built-in players' connection, clock-sync and buffer statistics and the
+//! latest protocol trace into a single zip the user can attach to a GitHub
+//! issue.
+
+use crate::logging;
+use crate::sendspin::{self, SendspinManager};
+use crate::settings;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use tauri::Manager;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Replaces the value of every redacted key in the bundle
+const REDACTED: &str = "<redacted>";
+
+/// Default file name offered in the save dialog
+pub fn default_file_name() -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    format!("music-assistant-diagnostics-{timestamp}.zip")
+}
+
+/// Whether a settings key may hold a credential. Webhook URLs often carry
+/// one (Home Assistant's webhook IDs, for one).
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["token", "password", "secret", "webhook"]
+        .iter()
+        .any(|s| key.contains(s))
+}
+
+/// Blank out credentials anywhere in `value`
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if is_secret_key(key) && !v.is_null() {
+                    *v = Value::String(REDACTED.to_string());
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[derive(Serialize)]
+struct SystemInfo {
+    app_version: String,
+    os: &'static str,
+    arch: &'static str,
+    locale: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PlayersReport {
+    players: Vec<sendspin::PlayerInfo>,
+    stats: Vec<sendspin::stats::PlayerStats>,
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| format!("Failed to serialize: {e}"))
+}
+
+/// Write the diagnostics bundle to `path`
+pub fn write_bundle(
+    app: &tauri::AppHandle,
+    sendspin: &SendspinManager,
+    path: &Path,
+) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut add = |name: &str, data: &[u8]| -> Result<(), String> {
+        zip.start_file(name, options)
+            .and_then(|()| zip.write_all(data).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {name} to the bundle: {e}"))
+    };
+
+    add(
+        "system.json",
+        &to_json(&SystemInfo {
+            app_version: app.package_info().version.to_string(),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            locale: sys_locale::get_locale(),
+        })?,
+    )?;
+
+    let mut config = serde_json::to_value(settings::get_settings())
+        .map_err(|e| format!("Failed to serialize settings: {e}"))?;
+    redact(&mut config);
+    add("settings.json", &to_json(&config)?)?;
+
+    // A device enumeration failure is itself useful to report
+    let devices = match sendspin::devices::list_devices() {
+        Ok(devices) => serde_json::to_value(devices).unwrap_or(Value::Null),
+        Err(e) => serde_json::json!({ "error": e }),
+    };
+    add("devices.json", &to_json(&devices)?)?;
+
+    add(
+        "players.json",
+        &to_json(&PlayersReport {
+            players: sendspin.players(),
+            stats: sendspin.player_stats(),
+        })?,
+    )?;
+
+    // The current log plus its rotated predecessors
+    match app.path().app_log_dir() {
+        Ok(log_dir) => {
+            let mut logs: Vec<_> = std::fs::read_dir(&log_dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|e| e.path())
+                        .filter(|p| {
+                            p.is_file()
+                                && p.file_name()
+                                    .and_then(|n| n.to_str())
+                                    .is_some_and(|n| n.starts_with(logging::LOG_FILE_STEM))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            logs.sort();
+            for log_path in logs {
+                let Some(name) = log_path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                match std::fs::read(&log_path) {
+                    Ok(data) => add(&format!("logs/{name}"), &data)?,
+                    Err(e) => log::warn!("[Diagnostics] Failed to read {}: {}", name, e),
+                }
+            }
+        }
+        Err(e) => log::warn!("[Diagnostics] Failed to resolve the log directory: {}", e),
+    }
+
+    if let Some(trace_path) = sendspin::trace::latest() {
+        let name = trace_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("sendspin.jsonl");
+        match std::fs::read(&trace_path) {
+            Ok(data) => add(&format!("traces/{name}"), &data)?,
+            Err(e) => log::warn!("[Diagnostics] Failed to read {}: {}", name, e),
+        }
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finish the bundle: {e}"))?;
+    log::info!(
+        "[Diagnostics] Wrote diagnostics bundle to {}",
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_blanks_nested_credentials() {
+        let mut value = serde_json::json!({
+            "server_url": "http://ma.local:8095",
+            "auth_token": "abc",
+            "players": [{ "name": "Kitchen", "api_password": "hunter2" }],
+            "refresh_token": null,
+            "webhooks": [{ "url": "http://ha.local:8123/api/webhook/abc" }],
+        });
+        redact(&mut value);
+        assert_eq!(value["server_url"], "http://ma.local:8095");
+        assert_eq!(value["auth_token"], REDACTED);
+        assert_eq!(value["players"][0]["name"], "Kitchen");
+        assert_eq!(value["players"][0]["api_password"], REDACTED);
+        assert!(value["refresh_token"].is_null());
+        assert_eq!(value["webhooks"], REDACTED);
+    }
+}
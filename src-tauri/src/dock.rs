@@ -0,0 +1,206 @@
+//! macOS Dock icon: transport controls in its menu and a playing/paused badge
+//!
+//! Tauri has no Dock menu, and the app delegate that `AppKit` asks for one
+//! (`applicationDockMenu:`) belongs to tao. [`init`] adds that method, and
+//! the menu items' actions, to the delegate's class; the menu is built anew
+//! from the now-playing state each time it opens. The badge follows
+//! now-playing changes.
+#![allow(unsafe_code)] // objc2 framework methods and the runtime are all `unsafe`; lift the workspace deny.
+
+use crate::i18n;
+use crate::now_playing::{self, NowPlaying};
+use objc2::rc::Retained;
+use objc2::runtime::{AnyClass, AnyObject, Imp, Sel};
+use objc2::{sel, MainThreadMarker};
+use objc2_app_kit::{NSApplication, NSMenu, NSMenuItem};
+use objc2_foundation::{ns_string, NSString};
+use parking_lot::Mutex;
+use std::ffi::CStr;
+use std::sync::{Arc, OnceLock};
+use tauri::AppHandle;
+
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+/// Badge last shown, so unchanged states don't go to the main thread
+static BADGE: Mutex<Option<&'static str>> = Mutex::new(None);
+
+/// Add the Dock menu and keep the badge up to date. Call on the main thread.
+pub fn init(app: &AppHandle) {
+    let Some(mtm) = MainThreadMarker::new() else {
+        log::warn!("[Dock] The Dock menu needs setting up on the main thread");
+        return;
+    };
+    if APP_HANDLE.set(app.clone()).is_err() {
+        return;
+    }
+    if let Err(e) = unsafe { add_delegate_methods(mtm) } {
+        log::warn!("[Dock] Failed to add the Dock menu: {}", e);
+    }
+
+    now_playing::on_now_playing_change(Arc::new(|now_playing| {
+        let label = badge(now_playing);
+        if std::mem::replace(&mut *BADGE.lock(), label) == label {
+            return;
+        }
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.run_on_main_thread(move || {
+                if let Some(mtm) = MainThreadMarker::new() {
+                    unsafe { set_badge(mtm, label) };
+                }
+            });
+        }
+    }));
+}
+
+unsafe fn set_badge(mtm: MainThreadMarker, label: Option<&str>) {
+    let label = label.map(NSString::from_str);
+    NSApplication::sharedApplication(mtm)
+        .dockTile()
+        .setBadgeLabel(label.as_deref());
+}
+
+/// Badge for `now_playing`: none without a track
+fn badge(now_playing: &NowPlaying) -> Option<&'static str> {
+    now_playing
+        .track
+        .as_ref()
+        .map(|_| if now_playing.is_playing { "▶" } else { "⏸" })
+}
+
+type DockMenu = unsafe extern "C-unwind" fn(&AnyObject, Sel, *mut AnyObject) -> *mut NSMenu;
+type Action = unsafe extern "C-unwind" fn(&AnyObject, Sel, *mut AnyObject);
+
+unsafe fn add_delegate_methods(mtm: MainThreadMarker) -> Result<(), String> {
+    let delegate = NSApplication::sharedApplication(mtm)
+        .delegate()
+        .ok_or("no app delegate")?;
+    let class: &AnyClass = AsRef::<AnyObject>::as_ref(&*delegate).class();
+    let class = std::ptr::from_ref(class).cast_mut();
+    // With their Objective-C type encodings
+    let methods: [(Sel, Imp, &CStr); 4] = [
+        (
+            sel!(applicationDockMenu:),
+            std::mem::transmute::<DockMenu, Imp>(dock_menu),
+            c"@@:@",
+        ),
+        (
+            sel!(dockPlayPause:),
+            std::mem::transmute::<Action, Imp>(play_pause),
+            c"v@:@",
+        ),
+        (
+            sel!(dockNext:),
+            std::mem::transmute::<Action, Imp>(next),
+            c"v@:@",
+        ),
+        (
+            sel!(dockPrevious:),
+            std::mem::transmute::<Action, Imp>(previous),
+            c"v@:@",
+        ),
+    ];
+    for (name, imp, types) in methods {
+        // Never replaces a method the class has already
+        if !objc2::ffi::class_addMethod(class, name, imp, types.as_ptr()).as_bool() {
+            return Err(format!("the app delegate already has {name}"));
+        }
+    }
+    Ok(())
+}
+
+unsafe extern "C-unwind" fn dock_menu(
+    this: &AnyObject,
+    _cmd: Sel,
+    _sender: *mut AnyObject,
+) -> *mut NSMenu {
+    let Some(mtm) = MainThreadMarker::new() else {
+        return std::ptr::null_mut();
+    };
+    Retained::autorelease_return(menu(mtm, this))
+}
+
+/// The Dock menu for what is playing now; its actions go to `target`
+unsafe fn menu(mtm: MainThreadMarker, target: &AnyObject) -> Retained<NSMenu> {
+    let now_playing = now_playing::get_now_playing();
+    let has_player = now_playing.player_id.is_some();
+    let menu = NSMenu::new(mtm);
+    menu.setAutoenablesItems(false);
+
+    if now_playing.track.is_some() {
+        let title = NSString::from_str(&now_playing::format_now_playing(&now_playing));
+        let item = NSMenuItem::initWithTitle_action_keyEquivalent(
+            mtm.alloc(),
+            &title,
+            None,
+            ns_string!(""),
+        );
+        item.setEnabled(false);
+        menu.addItem(&item);
+        menu.addItem(&NSMenuItem::separatorItem(mtm));
+    }
+
+    let play_pause = if now_playing.is_playing {
+        ("desktop.dock.pause", now_playing.can_pause)
+    } else {
+        ("desktop.dock.play", now_playing.can_play)
+    };
+    let items = [
+        (play_pause.0, sel!(dockPlayPause:), play_pause.1),
+        ("desktop.dock.next", sel!(dockNext:), now_playing.can_next),
+        (
+            "desktop.dock.previous",
+            sel!(dockPrevious:),
+            now_playing.can_previous,
+        ),
+    ];
+    for (key, action, enabled) in items {
+        let item = NSMenuItem::initWithTitle_action_keyEquivalent(
+            mtm.alloc(),
+            &NSString::from_str(&i18n::tr(key)),
+            Some(action),
+            ns_string!(""),
+        );
+        item.setTarget(Some(target));
+        item.setEnabled(has_player && enabled);
+        menu.addItem(&item);
+    }
+    menu
+}
+
+unsafe extern "C-unwind" fn play_pause(_this: &AnyObject, _cmd: Sel, _sender: *mut AnyObject) {
+    let command = if now_playing::get_now_playing().is_playing {
+        "pause"
+    } else {
+        "play"
+    };
+    send(command);
+}
+
+unsafe extern "C-unwind" fn next(_this: &AnyObject, _cmd: Sel, _sender: *mut AnyObject) {
+    send("next");
+}
+
+unsafe extern "C-unwind" fn previous(_this: &AnyObject, _cmd: Sel, _sender: *mut AnyObject) {
+    send("previous");
+}
+
+fn send(command: &str) {
+    log::debug!("[Dock] {}", command);
+    if let Some(app) = APP_HANDLE.get() {
+        crate::send_player_command(app, command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_shows_whether_a_track_plays() {
+        let mut now_playing = NowPlaying::default();
+        assert_eq!(badge(&now_playing), None);
+        now_playing.track = Some("Track".to_string());
+        assert_eq!(badge(&now_playing), Some("⏸"));
+        now_playing.is_playing = true;
+        assert_eq!(badge(&now_playing), Some("▶"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Headless mode: run the Sendspin player without a window
+//!
+//! `--headless` starts only the backend — the native player, its volume
+//! control and the sleep inhibitor — with no webview or tray, so the app can
+//! run on a media PC or as a systemd service and still show up as a player
+//! in Music Assistant.
+//!
+//! The server is the one the app last connected to (or `--server <url>`),
+//! and the auth token comes from the credential store or the
+//! `MUSIC_ASSISTANT_TOKEN` environment variable. The process runs until it
+//! receives SIGINT or SIGTERM, and takes the same command-line control
+//! requests as the windowed app (see [`crate::ipc`]) and, if enabled, local
+//! API requests (see [`crate::control_api`]). Webhooks, the now-playing
+//! file, the listening history and alarms work as they do with a window
+//! (see [`crate::webhooks`], [`crate::now_playing_file`],
+//! [`crate::history`] and [`crate::alarms`]).
+
+use crate::sendspin::SendspinManager;
+use crate::{
+    alarms, control_api, history, ipc, logging, now_playing_file, power, secrets, settings,
+    webhooks,
+};
+
+/// Command-line flag selecting headless mode
+pub const FLAG: &str = "--headless";
+/// Command-line option naming the server to connect to
+const SERVER_OPTION: &str = "--server";
+/// Environment variable that can supply the auth token
+const TOKEN_ENV: &str = "MUSIC_ASSISTANT_TOKEN";
+
+/// Value of `--server <url>` or `--server=<url>` in `args`
+fn server_arg(args: &[String]) -> Option<String> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == SERVER_OPTION {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(SERVER_OPTION)
+                .and_then(|rest| rest.strip_prefix('='))
+                .map(str::to_string)
+        }
+    })
+}
+
+/// Run until asked to stop.
+pub fn run() -> Result<(), String> {
+    let loaded_settings = settings::load_settings();
+    logging::init_stderr(logging::verbosity_from_settings(
+        loaded_settings.debug_logging,
+        loaded_settings.trace_logging,
+    ));
+    if let Err(e) = logging::set_module_levels(&loaded_settings.log_levels) {
+        log::warn!("[Headless] Ignoring saved log levels: {}", e);
+    }
+
+    // A second player registration under the same ID would fight the first
+    if ipc::running_instance().is_some() {
+        return Err(
+            "Music Assistant Companion is already running; quit it before starting headless"
+                .to_string(),
+        );
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let server_url = server_arg(&args)
+        .or(loaded_settings.last_server_url)
+        .ok_or_else(|| {
+            format!(
+                "No server configured; connect once from the app or pass {} <url>",
+                SERVER_OPTION
+            )
+        })?;
+    let auth_token = match std::env::var(TOKEN_ENV) {
+        Ok(token) if !token.is_empty() => token,
+        _ => secrets::get_token(&server_url)?.ok_or_else(|| {
+            format!(
+                "No auth token stored for {}; sign in once from the app or set {}",
+                server_url, TOKEN_ENV
+            )
+        })?,
+    };
+    let config = crate::sendspin_config_for_session(
+        &server_url,
+        auth_token,
+        env!("CARGO_PKG_VERSION").to_string(),
+    )
+    .ok_or("The native player is disabled in settings")?;
+
+    log::info!(
+        "[Headless] Music Assistant Companion v{} starting headless for {}",
+        env!("CARGO_PKG_VERSION"),
+        server_url
+    );
+    power::inhibit::init();
+
+    tauri::async_runtime::block_on(async move {
+        let sendspin = SendspinManager::new();
+        sendspin.start(config).await?;
+        ipc::serve(sendspin.clone(), true);
+        control_api::init(sendspin.clone());
+        webhooks::init();
+        now_playing_file::init();
+        history::init();
+        alarms::init(sendspin.clone());
+        shutdown_signal().await;
+        log::info!("[Headless] Shutting down");
+        sendspin.stop().await;
+        Ok(())
+    })
+}
+
+/// Wait for SIGINT, or SIGTERM from a service manager
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => log::warn!("[Headless] Failed to listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::warn!("[Headless] Failed to listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn server_option_accepts_both_forms() {
+        assert_eq!(
+            server_arg(&args(&["--headless", "--server", "http://ma:8095"])),
+            Some("http://ma:8095".to_string())
+        );
+        assert_eq!(
+            server_arg(&args(&["--server=http://ma:8095", "--headless"])),
+            Some("http://ma:8095".to_string())
+        );
+        assert_eq!(server_arg(&args(&["--headless"])), None);
+        assert_eq!(server_arg(&args(&["--headless", "--server"])), None);
+    }
+}
@@ -0,0 +1,444 @@
+//! Listening history
+//!
+//! Records what plays, from the now-playing updates, in a local database,
+//! and answers the queries of a stats page: recent tracks, play counts and
+//! listening time per day. Nothing leaves the computer.
+//!
+//! A track counts as played once it has been listened to for 30 seconds, or
+//! half its length if that is shorter; paused time doesn't count. Its row is
+//! written then and its listening time kept up to date while it plays, so
+//! quitting mid-track loses at most [`SAVE_INTERVAL`] of it.
+
+use crate::now_playing::{self, NowPlaying};
+use crate::settings;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Listening time after which a track counts as played
+const MIN_LISTEN: Duration = Duration::from_secs(30);
+/// How often the listening time of the playing track is saved
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Version of the schema [`migrate`] creates, kept in `user_version`
+const SCHEMA_VERSION: i32 = 1;
+
+/// The open database, opened on first use
+static DB: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// Queue of the recording thread, started on first use
+static QUEUE: Mutex<Option<Sender<NowPlaying>>> = Mutex::new(None);
+
+/// A play of a track
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Play {
+    /// When it started, in seconds since the Unix epoch
+    pub played_at: i64,
+    pub player_id: Option<String>,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Length of the track in seconds, if known
+    pub duration: Option<f64>,
+    /// Seconds actually listened to
+    pub listened_secs: f64,
+}
+
+/// How often a track was played
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayCount {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub plays: u32,
+    pub listened_secs: f64,
+    /// Last time it was played, in seconds since the Unix epoch
+    pub last_played_at: i64,
+}
+
+/// Listening time of one day
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyListening {
+    /// Local date as `YYYY-MM-DD`
+    pub date: String,
+    pub listened_secs: f64,
+    pub plays: u32,
+}
+
+/// Record plays on every now-playing change from here on
+pub fn init() {
+    now_playing::on_now_playing_change(Arc::new(|now_playing| {
+        if let Ok(mut queue) = QUEUE.lock() {
+            let queue = queue.get_or_insert_with(|| {
+                let (tx, rx) = mpsc::channel();
+                std::thread::spawn(move || run(&rx));
+                tx
+            });
+            let _ = queue.send(now_playing.clone());
+        }
+    }));
+}
+
+/// The last `limit` plays, newest first
+pub fn recent(limit: u32) -> Result<Vec<Play>, String> {
+    with_db(|db| query_recent(db, limit))
+}
+
+/// The `limit` most played tracks of the last `days` days, or of all time
+pub fn play_counts(limit: u32, days: Option<u32>) -> Result<Vec<PlayCount>, String> {
+    let since = days.map_or(0, |days| now_secs() - i64::from(days) * 86_400);
+    with_db(|db| query_play_counts(db, limit, since))
+}
+
+/// Listening time per day over the last `days` days, oldest first. Days
+/// without any listening are left out.
+pub fn daily_listening(days: u32) -> Result<Vec<DailyListening>, String> {
+    let since = now_secs() - i64::from(days) * 86_400;
+    with_db(|db| query_daily_listening(db, since))
+}
+
+/// Delete the whole history
+pub fn clear() -> Result<(), String> {
+    with_db(|db| db.execute("DELETE FROM plays", []).map(|_| ()))?;
+    log::info!("[History] Cleared");
+    Ok(())
+}
+
+fn db_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("music-assistant-companion").join("history.db"))
+}
+
+/// Run `f` on the database, opening it first if needed
+fn with_db<T>(f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+    let mut db = DB
+        .lock()
+        .map_err(|_| "History database is unavailable".to_string())?;
+    if db.is_none() {
+        *db = Some(open()?);
+    }
+    let db = db.as_ref().ok_or("History database is unavailable")?;
+    f(db).map_err(|e| format!("History database error: {}", e))
+}
+
+fn open() -> Result<Connection, String> {
+    let path = db_path().ok_or("No data directory for the history database")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let db =
+        Connection::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    migrate(&db).map_err(|e| format!("Failed to set up the history database: {}", e))?;
+    Ok(db)
+}
+
+/// Bring the schema up to [`SCHEMA_VERSION`]
+fn migrate(db: &Connection) -> rusqlite::Result<()> {
+    let version: i32 = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < 1 {
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS plays (
+                id INTEGER PRIMARY KEY,
+                played_at INTEGER NOT NULL,
+                player_id TEXT,
+                title TEXT NOT NULL,
+                artist TEXT,
+                album TEXT,
+                duration REAL,
+                listened_secs REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS plays_played_at ON plays (played_at);",
+        )?;
+    }
+    db.pragma_update(None, "user_version", SCHEMA_VERSION)
+}
+
+fn query_recent(db: &Connection, limit: u32) -> rusqlite::Result<Vec<Play>> {
+    let mut statement = db.prepare(
+        "SELECT played_at, player_id, title, artist, album, duration, listened_secs
+         FROM plays ORDER BY played_at DESC, id DESC LIMIT ?1",
+    )?;
+    let rows = statement.query_map([limit], |row| {
+        Ok(Play {
+            played_at: row.get(0)?,
+            player_id: row.get(1)?,
+            title: row.get(2)?,
+            artist: row.get(3)?,
+            album: row.get(4)?,
+            duration: row.get(5)?,
+            listened_secs: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn query_play_counts(db: &Connection, limit: u32, since: i64) -> rusqlite::Result<Vec<PlayCount>> {
+    let mut statement = db.prepare(
+        "SELECT title, artist, album, COUNT(*), SUM(listened_secs), MAX(played_at)
+         FROM plays WHERE played_at >= ?1
+         GROUP BY title, artist, album
+         ORDER BY COUNT(*) DESC, MAX(played_at) DESC LIMIT ?2",
+    )?;
+    let rows = statement.query_map(params![since, limit], |row| {
+        Ok(PlayCount {
+            title: row.get(0)?,
+            artist: row.get(1)?,
+            album: row.get(2)?,
+            plays: row.get(3)?,
+            listened_secs: row.get(4)?,
+            last_played_at: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn query_daily_listening(db: &Connection, since: i64) -> rusqlite::Result<Vec<DailyListening>> {
+    let mut statement = db.prepare(
+        "SELECT date(played_at, 'unixepoch', 'localtime') AS day, SUM(listened_secs), COUNT(*)
+         FROM plays WHERE played_at >= ?1
+         GROUP BY day ORDER BY day",
+    )?;
+    let rows = statement.query_map([since], |row| {
+        Ok(DailyListening {
+            date: row.get(0)?,
+            listened_secs: row.get(1)?,
+            plays: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Insert `play`, or update its listening time if it has a row already.
+/// Returns its row ID.
+fn save(db: &Connection, row_id: Option<i64>, play: &Play) -> rusqlite::Result<i64> {
+    if let Some(row_id) = row_id {
+        let updated = db.execute(
+            "UPDATE plays SET listened_secs = ?1 WHERE id = ?2",
+            params![play.listened_secs, row_id],
+        )?;
+        // Still there, unless the history was cleared meanwhile
+        if updated > 0 {
+            return Ok(row_id);
+        }
+    }
+    db.execute(
+        "INSERT INTO plays (played_at, player_id, title, artist, album, duration, listened_secs)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            play.played_at,
+            play.player_id,
+            play.title,
+            play.artist,
+            play.album,
+            play.duration,
+            play.listened_secs
+        ],
+    )?;
+    Ok(db.last_insert_rowid())
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// The track playing now, as it is being listened to
+struct Current {
+    play: Play,
+    /// Listening time before `playing_since`
+    listened: Duration,
+    /// When playback last started or resumed, while playing
+    playing_since: Option<Instant>,
+    /// Its row, once it counts as played
+    row_id: Option<i64>,
+}
+
+impl Current {
+    fn start(now_playing: &NowPlaying, title: &str) -> Self {
+        Self {
+            play: Play {
+                played_at: now_secs(),
+                player_id: now_playing.player_id.clone(),
+                title: title.to_string(),
+                artist: now_playing.artist.clone(),
+                album: now_playing.album.clone(),
+                duration: now_playing.duration,
+                listened_secs: 0.0,
+            },
+            listened: Duration::ZERO,
+            playing_since: None,
+            row_id: None,
+        }
+    }
+
+    fn is_track(&self, now_playing: &NowPlaying) -> bool {
+        now_playing.track.as_deref() == Some(self.play.title.as_str())
+            && now_playing.artist == self.play.artist
+            && now_playing.album == self.play.album
+            && now_playing.player_id == self.play.player_id
+    }
+
+    fn set_playing(&mut self, playing: bool, now: Instant) {
+        match (self.playing_since, playing) {
+            (None, true) => self.playing_since = Some(now),
+            (Some(since), false) => {
+                self.listened += now.saturating_duration_since(since);
+                self.playing_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn listened(&self, now: Instant) -> Duration {
+        self.listened
+            + self
+                .playing_since
+                .map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+    }
+
+    /// Whether it has been listened to long enough to count as played
+    fn counts(&self, now: Instant) -> bool {
+        let half = self
+            .play
+            .duration
+            .filter(|duration| *duration > 0.0)
+            .map_or(MIN_LISTEN, |duration| {
+                Duration::from_secs_f64(duration / 2.0)
+            });
+        self.listened(now) >= MIN_LISTEN.min(half)
+    }
+}
+
+fn run(rx: &Receiver<NowPlaying>) {
+    let mut current: Option<Current> = None;
+    loop {
+        match rx.recv_timeout(SAVE_INTERVAL) {
+            Ok(now_playing) => {
+                let now = Instant::now();
+                if current
+                    .as_ref()
+                    .is_some_and(|current| !current.is_track(&now_playing))
+                {
+                    if let Some(mut finished) = current.take() {
+                        finished.set_playing(false, now);
+                        record(&mut finished, now);
+                    }
+                }
+                if current.is_none() {
+                    current = now_playing
+                        .track
+                        .as_deref()
+                        .map(|title| Current::start(&now_playing, title));
+                }
+                if let Some(current) = current.as_mut() {
+                    current.set_playing(now_playing.is_playing, now);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(current) = current.as_mut() {
+                    if current.playing_since.is_some() {
+                        record(current, Instant::now());
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Save the play of `current` if it counts and history is on
+fn record(current: &mut Current, now: Instant) {
+    if !current.counts(now) || !settings::get_settings().listening_history_enabled {
+        return;
+    }
+    current.play.listened_secs = current.listened(now).as_secs_f64();
+    let row_id = current.row_id;
+    match with_db(|db| save(db, row_id, &current.play)) {
+        Ok(row_id) => current.row_id = Some(row_id),
+        Err(e) => log::warn!("[History] {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        migrate(&db).unwrap();
+        db
+    }
+
+    fn play(title: &str, played_at: i64, listened_secs: f64) -> Play {
+        Play {
+            played_at,
+            player_id: Some("player".to_string()),
+            title: title.to_string(),
+            artist: Some("Artist".to_string()),
+            album: None,
+            duration: Some(200.0),
+            listened_secs,
+        }
+    }
+
+    #[test]
+    fn saving_again_updates_the_listening_time() {
+        let db = db();
+        let row_id = save(&db, None, &play("A", 100, 30.0)).unwrap();
+        assert_eq!(
+            save(&db, Some(row_id), &play("A", 100, 90.0)).unwrap(),
+            row_id
+        );
+        assert_eq!(query_recent(&db, 10).unwrap(), vec![play("A", 100, 90.0)]);
+        // Migrating again keeps the data
+        migrate(&db).unwrap();
+        assert_eq!(query_recent(&db, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn counts_plays_per_track_and_time_per_day() {
+        let db = db();
+        let noon = 1_700_000_000 - 1_700_000_000 % 86_400 + 43_200;
+        save(&db, None, &play("A", noon, 100.0)).unwrap();
+        save(&db, None, &play("B", noon + 600, 50.0)).unwrap();
+        save(&db, None, &play("A", noon + 86_400, 200.0)).unwrap();
+
+        let counts = query_play_counts(&db, 10, 0).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!((counts[0].title.as_str(), counts[0].plays), ("A", 2));
+        assert!((counts[0].listened_secs - 300.0).abs() < f64::EPSILON);
+        assert_eq!(query_play_counts(&db, 10, noon + 601).unwrap().len(), 1);
+
+        let days = query_daily_listening(&db, 0).unwrap();
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].plays, 2);
+        assert!((days[0].listened_secs - 150.0).abs() < f64::EPSILON);
+        assert_eq!(query_recent(&db, 1).unwrap()[0].played_at, noon + 86_400);
+    }
+
+    #[test]
+    fn only_listening_time_counts() {
+        let now_playing = NowPlaying {
+            track: Some("Short".to_string()),
+            duration: Some(40.0),
+            ..NowPlaying::default()
+        };
+        let start = Instant::now();
+        let mut current = Current::start(&now_playing, "Short");
+        current.set_playing(true, start);
+        current.set_playing(false, start + Duration::from_secs(15));
+        // Paused time doesn't count
+        assert!(!current.counts(start + Duration::from_secs(500)));
+        current.set_playing(true, start + Duration::from_secs(500));
+        // Half of a 40-second track is enough
+        assert!(current.counts(start + Duration::from_secs(505)));
+        assert_eq!(
+            current.listened(start + Duration::from_secs(505)),
+            Duration::from_secs(20)
+        );
+        assert!(current.is_track(&now_playing));
+    }
+}
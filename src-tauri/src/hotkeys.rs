@@ -0,0 +1,291 @@
+//! Global keyboard shortcuts
+//!
+//! User-defined system-wide shortcuts, stored in settings as action name →
+//! accelerator (e.g. `"play_pause": "Ctrl+Alt+KeyP"`) and registered with the
+//! OS whenever they change. Transport actions go to the player selected in
+//! the app, like the tray's; volume and mute act on this computer's built-in
+//! player, favorite adds the track playing to the library's favorites, the
+//! seek shortcuts skip [`SEEK_STEP_MS`] forward or back in the track on the
+//! selected player, and one shortcut can toggle the mini player. Push-to-talk
+//! announces from the default input on the selected player for as long as
+//! it's held. The sleep timer shortcut starts a timer of
+//! [`SLEEP_TIMER_MINUTES`], or cancels the running one.
+
+use crate::now_playing::get_now_playing;
+use crate::sendspin::{PlaybackCommand, SendspinManager, SEEK_STEP_MS};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tauri::Manager;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+/// Length of the sleep timer the shortcut starts
+const SLEEP_TIMER_MINUTES: u32 = 30;
+
+/// Something a shortcut can do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    MiniPlayer,
+    PushToTalk,
+    SleepTimer,
+    Favorite,
+    SeekForward,
+    SeekBackward,
+}
+
+impl Action {
+    const ALL: [Action; 12] = [
+        Action::PlayPause,
+        Action::Next,
+        Action::Previous,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::Mute,
+        Action::MiniPlayer,
+        Action::PushToTalk,
+        Action::SleepTimer,
+        Action::Favorite,
+        Action::SeekForward,
+        Action::SeekBackward,
+    ];
+
+    /// Name used as the settings key
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PlayPause => "play_pause",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::VolumeUp => "volume_up",
+            Action::VolumeDown => "volume_down",
+            Action::Mute => "mute",
+            Action::MiniPlayer => "mini_player",
+            Action::PushToTalk => "push_to_talk",
+            Action::SleepTimer => "sleep_timer",
+            Action::Favorite => "favorite",
+            Action::SeekForward => "seek_forward",
+            Action::SeekBackward => "seek_backward",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// Registered shortcut IDs and their actions
+static BINDINGS: Mutex<Vec<(u32, Action)>> = Mutex::new(Vec::new());
+
+fn parse_shortcut(accelerator: &str) -> Result<Shortcut, String> {
+    Shortcut::from_str(accelerator)
+        .map_err(|e| format!("Invalid shortcut '{}': {}", accelerator, e))
+}
+
+/// Check a new binding of `accelerator` to the action `action` against the
+/// existing `bindings`.
+pub fn validate(
+    action: &str,
+    accelerator: &str,
+    bindings: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    Action::from_name(action).ok_or_else(|| format!("Unknown shortcut action: {}", action))?;
+    let shortcut = parse_shortcut(accelerator)?;
+    let taken_by = bindings.iter().find(|(other, other_accelerator)| {
+        other.as_str() != action
+            && parse_shortcut(other_accelerator).is_ok_and(|other| other.id() == shortcut.id())
+    });
+    match taken_by {
+        Some((other, _)) => Err(format!("{} is already used for {}", accelerator, other)),
+        None => Ok(()),
+    }
+}
+
+/// The shortcuts in `bindings`, skipping (and logging) entries that no
+/// longer parse
+fn parse_bindings(bindings: &BTreeMap<String, String>) -> Vec<(Shortcut, Action)> {
+    bindings
+        .iter()
+        .filter_map(|(name, accelerator)| {
+            let parsed = Action::from_name(name)
+                .ok_or_else(|| format!("Unknown shortcut action: {}", name))
+                .and_then(|action| Ok((parse_shortcut(accelerator)?, action)));
+            parsed
+                .map_err(|e| log::warn!("[Hotkeys] Ignoring saved shortcut: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Replace the registered shortcuts with `bindings`. Shortcuts the OS
+/// refuses (usually because another app holds them) are reported together.
+pub fn apply(app: &tauri::AppHandle, bindings: &BTreeMap<String, String>) -> Result<(), String> {
+    let shortcuts = app.global_shortcut();
+    shortcuts
+        .unregister_all()
+        .map_err(|e| format!("Failed to release shortcuts: {}", e))?;
+
+    let mut registered = Vec::new();
+    let mut errors = Vec::new();
+    for (shortcut, action) in parse_bindings(bindings) {
+        let id = shortcut.id();
+        match shortcuts.register(shortcut) {
+            Ok(()) => registered.push((id, action)),
+            Err(e) => errors.push(format!("{}: {}", action.name(), e)),
+        }
+    }
+    log::debug!("[Hotkeys] {} shortcut(s) registered", registered.len());
+    if let Ok(mut guard) = BINDINGS.lock() {
+        *guard = registered;
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Couldn't register {}", errors.join(", ")))
+    }
+}
+
+fn run(app: &tauri::AppHandle, action: Action) {
+    log::debug!("[Hotkeys] {}", action.name());
+    let sendspin = app.state::<SendspinManager>();
+    let result = match action {
+        Action::PlayPause => {
+            let command = if get_now_playing().is_playing {
+                "pause"
+            } else {
+                "play"
+            };
+            crate::send_player_command(app, command);
+            Ok(())
+        }
+        Action::Next => {
+            crate::send_player_command(app, "next");
+            Ok(())
+        }
+        Action::Previous => {
+            crate::send_player_command(app, "previous");
+            Ok(())
+        }
+        Action::VolumeUp => crate::step_volume(&sendspin, 1)
+            .map(|_| ())
+            .map_err(String::from),
+        Action::VolumeDown => crate::step_volume(&sendspin, -1)
+            .map(|_| ())
+            .map_err(String::from),
+        Action::Mute => crate::toggle_mute(&sendspin)
+            .map(|_| ())
+            .map_err(String::from),
+        Action::MiniPlayer => {
+            crate::mini_player::toggle(app);
+            Ok(())
+        }
+        Action::PushToTalk => {
+            crate::sendspin::capture::push_to_talk(true, get_now_playing().player_id);
+            Ok(())
+        }
+        Action::SleepTimer => {
+            if crate::sleep_timer::status().active {
+                crate::sleep_timer::cancel();
+                Ok(())
+            } else {
+                crate::sleep_timer::start(
+                    &sendspin,
+                    crate::sleep_timer::SleepTimerMode::Minutes(SLEEP_TIMER_MINUTES),
+                )
+            }
+        }
+        Action::Favorite => sendspin
+            .send_command_to(
+                get_now_playing().player_id.as_deref(),
+                PlaybackCommand::Favorite,
+            )
+            .map_err(String::from),
+        Action::SeekForward => {
+            crate::seek_now_playing_by(&sendspin, SEEK_STEP_MS);
+            Ok(())
+        }
+        Action::SeekBackward => {
+            crate::seek_now_playing_by(&sendspin, -SEEK_STEP_MS);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        log::warn!("[Hotkeys] {} failed: {}", action.name(), e);
+    }
+}
+
+/// The global shortcut plugin, dispatching presses to their actions, and
+/// releases to push-to-talk
+pub fn plugin() -> tauri::plugin::TauriPlugin<tauri::Wry> {
+    tauri_plugin_global_shortcut::Builder::new()
+        .with_handler(|app, shortcut, event| {
+            let pressed = event.state() == ShortcutState::Pressed;
+            let action = BINDINGS.lock().ok().and_then(|bindings| {
+                bindings
+                    .iter()
+                    .find(|(id, _)| *id == shortcut.id())
+                    .map(|(_, action)| *action)
+            });
+            match action {
+                Some(Action::PushToTalk) if !pressed => {
+                    crate::sendspin::capture::push_to_talk(false, None);
+                }
+                Some(action) if pressed => run(app, action),
+                _ => {}
+            }
+        })
+        .build()
+}
+
+/// Register the shortcuts saved in settings
+pub fn init(app: &tauri::AppHandle) {
+    if let Err(e) = apply(app, &crate::settings::get_settings().hotkeys) {
+        log::warn!("[Hotkeys] {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_names_round_trip() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+        assert_eq!(Action::from_name("rewind"), None);
+    }
+
+    #[test]
+    fn validate_rejects_unknown_actions_and_bad_accelerators() {
+        let bindings = BTreeMap::new();
+        assert!(validate("play_pause", "Ctrl+Alt+KeyP", &bindings).is_ok());
+        assert!(validate("rewind", "Ctrl+Alt+KeyP", &bindings).is_err());
+        assert!(validate("play_pause", "Ctrl+Nope", &bindings).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_a_shortcut_bound_to_another_action() {
+        let bindings = BTreeMap::from([("next".to_string(), "Ctrl+Alt+KeyN".to_string())]);
+        assert!(validate("previous", "Ctrl+Alt+KeyN", &bindings).is_err());
+        // Rebinding the same action to its own shortcut is fine
+        assert!(validate("next", "Ctrl+Alt+KeyN", &bindings).is_ok());
+    }
+
+    #[test]
+    fn unparsable_saved_bindings_are_skipped() {
+        let bindings = BTreeMap::from([
+            ("next".to_string(), "Ctrl+Alt+KeyN".to_string()),
+            ("mute".to_string(), "Nope".to_string()),
+            ("rewind".to_string(), "Ctrl+KeyR".to_string()),
+        ]);
+        let parsed = parse_bindings(&bindings);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].1, Action::Next);
+    }
+}
@@ -0,0 +1,320 @@
+//! Command-line control of the running instance
+//!
+//! `music-assistant-companion play|pause|stop|next|prev|volume [<n>]|status`
+//! doesn't start a second app; it connects to the instance that is already
+//! running (windowed or headless) over a local socket, forwards the command
+//! and prints the reply. That makes the built-in player scriptable and
+//! usable from window-manager keybindings.
+//!
+//! The socket is a Unix domain socket in the user's runtime directory, or a
+//! named pipe on Windows. Each connection carries one request, the
+//! subcommand's arguments as a JSON array on one line, answered by one JSON
+//! line holding a `Result`.
+
+use crate::now_playing::{self, NowPlaying};
+use crate::sendspin::{ConnectionStatus, PlaybackCommand, SendspinManager};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+
+/// First arguments that make the process act as a control client
+pub const SUBCOMMANDS: &[&str] = &[
+    "play", "pause", "stop", "next", "prev", "previous", "volume", "status",
+];
+
+/// Name of the socket (Unix) or pipe (Windows)
+const SOCKET_NAME: &str = "music-assistant-companion.sock";
+
+/// Whether this instance runs headless, reported in `status`
+static HEADLESS: AtomicBool = AtomicBool::new(false);
+
+/// A parsed control request
+#[derive(Debug, PartialEq)]
+enum Request {
+    Command(PlaybackCommand),
+    GetVolume,
+    Status,
+}
+
+/// Reply to `status`
+#[derive(Serialize)]
+struct Status {
+    headless: bool,
+    connection: ConnectionStatus,
+    player_id: Option<String>,
+    volume: Option<u8>,
+    now_playing: NowPlaying,
+}
+
+fn usage() -> String {
+    format!(
+        "Usage: music-assistant-companion <{}> [volume 0-100]",
+        SUBCOMMANDS.join("|")
+    )
+}
+
+fn parse(args: &[String]) -> Result<Request, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["play"] => Ok(Request::Command(PlaybackCommand::Play)),
+        ["pause"] => Ok(Request::Command(PlaybackCommand::Pause)),
+        ["stop"] => Ok(Request::Command(PlaybackCommand::Stop)),
+        ["next"] => Ok(Request::Command(PlaybackCommand::Next)),
+        ["prev" | "previous"] => Ok(Request::Command(PlaybackCommand::Previous)),
+        ["volume"] => Ok(Request::GetVolume),
+        ["volume", level] => level
+            .parse::<u8>()
+            .ok()
+            .filter(|v| *v <= 100)
+            .map(|v| Request::Command(PlaybackCommand::SetVolume(v)))
+            .ok_or_else(|| format!("Invalid volume '{}', expected 0-100", level)),
+        ["status"] => Ok(Request::Status),
+        _ => Err(usage()),
+    }
+}
+
+/// Answer the request in `args`
+pub(crate) fn handle(sendspin: &SendspinManager, args: &[String]) -> Result<Value, String> {
+    match parse(args)? {
+        Request::Command(command) => sendspin
+            .send_command(command)
+            .map(|()| Value::Null)
+            .map_err(String::from),
+        Request::GetVolume => sendspin
+            .get_volume_percent()
+            .map(Value::from)
+            .map_err(String::from),
+        Request::Status => serde_json::to_value(Status {
+            headless: HEADLESS.load(Ordering::Relaxed),
+            connection: sendspin.get_status(),
+            player_id: sendspin.get_player_id(),
+            volume: sendspin.get_volume_percent().ok(),
+            now_playing: now_playing::get_now_playing(),
+        })
+        .map_err(|e| format!("Failed to serialize status: {}", e)),
+    }
+}
+
+async fn serve_connection<S>(sendspin: SendspinManager, stream: S) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = tokio::io::BufReader::new(stream);
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    let reply = serde_json::from_str::<Vec<String>>(&line)
+        .map_err(|e| format!("Malformed request: {}", e))
+        .and_then(|args| {
+            log::debug!("[IPC] {}", args.join(" "));
+            handle(&sendspin, &args)
+        });
+    let mut reply = serde_json::to_string(&reply).unwrap_or_default();
+    reply.push('\n');
+    stream.write_all(reply.as_bytes()).await?;
+    stream.flush().await
+}
+
+#[cfg(unix)]
+fn socket_path() -> std::path::PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(SOCKET_NAME)
+}
+
+#[cfg(windows)]
+fn pipe_name() -> String {
+    format!(r"\\.\pipe\{}", SOCKET_NAME)
+}
+
+/// Accept control requests for `sendspin` in the background. `headless`
+/// tells later launches what kind of instance they found.
+pub fn serve(sendspin: SendspinManager, headless: bool) {
+    HEADLESS.store(headless, Ordering::Relaxed);
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = listen(sendspin).await {
+            log::warn!("[IPC] Command-line control unavailable: {}", e);
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn listen(sendspin: SendspinManager) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = socket_path();
+    // A socket nobody answers on is left over from an instance that didn't
+    // shut down cleanly
+    if path.exists() && std::os::unix::net::UnixStream::connect(&path).is_err() {
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = tokio::net::UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    log::info!("[IPC] Listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let sendspin = sendspin.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = serve_connection(sendspin, stream).await {
+                log::debug!("[IPC] Connection failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+async fn listen(sendspin: SendspinManager) -> std::io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let name = pipe_name();
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .reject_remote_clients(true)
+        .create(&name)?;
+    log::info!("[IPC] Listening on {}", name);
+
+    loop {
+        server.connect().await?;
+        // Create the next instance before handing this one off so a client
+        // never finds the pipe missing
+        let connected = std::mem::replace(&mut server, ServerOptions::new().create(&name)?);
+        let sendspin = sendspin.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = serve_connection(sendspin, connected).await {
+                log::debug!("[IPC] Connection failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn listen(_sendspin: SendspinManager) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(unix)]
+fn connect() -> std::io::Result<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(socket_path())
+}
+
+#[cfg(windows)]
+fn connect() -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pipe_name())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn connect() -> std::io::Result<std::fs::File> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Send `args` to the running instance and return its reply
+fn request(args: &[String]) -> Result<Value, String> {
+    let mut stream = connect().map_err(|e| {
+        format!(
+            "Music Assistant Companion doesn't seem to be running ({})",
+            e
+        )
+    })?;
+    let mut line = serde_json::to_string(args).map_err(|e| e.to_string())?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to send the command: {}", e))?;
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|e| format!("Failed to read the reply: {}", e))?;
+    serde_json::from_str::<Result<Value, String>>(&reply)
+        .map_err(|e| format!("Malformed reply: {}", e))?
+}
+
+/// Whether another instance is running: `Some(true)` if it is headless,
+/// `Some(false)` if it has a window, `None` if nothing answers.
+pub fn running_instance() -> Option<bool> {
+    request(&["status".to_string()])
+        .ok()
+        .map(|status| status["headless"].as_bool().unwrap_or(false))
+}
+
+/// Run as a control client for the subcommand in `args` and return the
+/// process exit code.
+pub fn run_client(args: &[String]) -> i32 {
+    // Release builds are GUI-subsystem binaries without a console of their
+    // own; print to the terminal the command was run from
+    #[cfg(windows)]
+    #[allow(unsafe_code)]
+    unsafe {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+
+    // Catch usage errors before bothering the running instance
+    if let Err(e) = parse(args) {
+        eprintln!("{}", e);
+        return 2;
+    }
+    match request(args) {
+        Ok(Value::Null) => 0,
+        Ok(value) => {
+            match value {
+                Value::Object(_) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&value).unwrap_or_default()
+                ),
+                other => println!("{}", other),
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(list: &[&str]) -> Result<Request, String> {
+        parse(&list.iter().map(ToString::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_transport_subcommands() {
+        assert_eq!(
+            parse_args(&["play"]),
+            Ok(Request::Command(PlaybackCommand::Play))
+        );
+        assert_eq!(
+            parse_args(&["prev"]),
+            Ok(Request::Command(PlaybackCommand::Previous))
+        );
+        assert_eq!(parse_args(&["status"]), Ok(Request::Status));
+        assert!(parse_args(&["play", "now"]).is_err());
+        assert!(parse_args(&[]).is_err());
+    }
+
+    #[test]
+    fn volume_takes_an_optional_level() {
+        assert_eq!(parse_args(&["volume"]), Ok(Request::GetVolume));
+        assert_eq!(
+            parse_args(&["volume", "40"]),
+            Ok(Request::Command(PlaybackCommand::SetVolume(40)))
+        );
+        assert!(parse_args(&["volume", "101"]).is_err());
+        assert!(parse_args(&["volume", "loud"]).is_err());
+    }
+
+    #[test]
+    fn every_subcommand_parses() {
+        for subcommand in SUBCOMMANDS {
+            assert!(parse_args(&[subcommand]).is_ok(), "{}", subcommand);
+        }
+    }
+}
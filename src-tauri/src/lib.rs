@@ -1,31 +1,57 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, Once};
 use std::thread;
 use std::time::Duration;
-use tauri::menu::{CheckMenuItemBuilder, MenuBuilder, MenuItemBuilder, PredefinedMenuItem};
+use tauri::menu::{
+    CheckMenuItemBuilder, MenuBuilder, MenuItemBuilder, PredefinedMenuItem, SubmenuBuilder,
+};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
-use tauri::Manager;
+use tauri::{Manager, State};
 use tauri_plugin_dialog::{
     DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
 };
 use tauri_plugin_opener::OpenerExt;
 use tauri_plugin_updater::UpdaterExt;
 
+mod alarms;
+mod artwork_cache;
+mod autostart;
+mod control_api;
+mod deep_link;
+mod diagnostics;
 mod discord_rpc;
+#[cfg(target_os = "macos")]
+mod dock;
+mod headless;
+mod history;
+mod hotkeys;
 mod i18n;
+mod ipc;
+#[cfg(target_os = "linux")]
+mod linux_dbus;
 #[cfg(target_os = "linux")]
 mod linux_theme;
 mod logging;
 mod ma_api;
 mod mdns_discovery;
 mod media_controls;
+mod metrics;
+mod mini_player;
 mod now_playing;
+mod now_playing_file;
+mod osd;
+mod player_identity;
+mod power;
+mod secrets;
 mod sendspin;
 mod settings;
+mod sleep_timer;
+mod tray_scroll;
+mod webhooks;
 
 use mdns_discovery::DiscoveredServer;
-use now_playing::NowPlaying;
-use tauri_plugin_autostart::MacosLauncher;
+use now_playing::{NowPlaying, RepeatMode};
+use sendspin::SendspinManager;
 
 static SERVICES_STARTER: Once = Once::new();
 
@@ -42,11 +68,63 @@ static NOW_PLAYING_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> =
 static PLAY_PAUSE_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> = Mutex::new(None);
 static PREV_TRACK_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> = Mutex::new(None);
 static NEXT_TRACK_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> = Mutex::new(None);
+static FAVORITE_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> = Mutex::new(None);
+
+// Global menu item references for the queue's shuffle and repeat modes
+static SHUFFLE_MENU_ITEM: Mutex<Option<tauri::menu::CheckMenuItem<tauri::Wry>>> = Mutex::new(None);
+static REPEAT_MENU_ITEMS: Mutex<Vec<(RepeatMode, tauri::menu::CheckMenuItem<tauri::Wry>)>> =
+    Mutex::new(Vec::new());
+
+/// Repeat modes offered in the tray menu, with their menu item IDs
+const TRAY_REPEAT_MODES: [(RepeatMode, &str); 3] = [
+    (RepeatMode::Off, "repeat_off"),
+    (RepeatMode::One, "repeat_one"),
+    (RepeatMode::All, "repeat_all"),
+];
+
+// Global references for the tray's group submenu: the submenu itself, the
+// group volume controls and a checkbox for each player that can join
+static GROUP_MENU: Mutex<Option<tauri::menu::Submenu<tauri::Wry>>> = Mutex::new(None);
+static GROUP_VOLUME_UP_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> =
+    Mutex::new(None);
+static GROUP_VOLUME_DOWN_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> =
+    Mutex::new(None);
+static GROUP_MUTE_MENU_ITEM: Mutex<Option<tauri::menu::CheckMenuItem<tauri::Wry>>> =
+    Mutex::new(None);
+static GROUP_MEMBER_MENU_ITEMS: Mutex<Vec<tauri::menu::CheckMenuItem<tauri::Wry>>> =
+    Mutex::new(Vec::new());
+// Global references for the tray's submenu of players to send playback to
+static TRANSFER_MENU: Mutex<Option<tauri::menu::Submenu<tauri::Wry>>> = Mutex::new(None);
+static TRANSFER_MENU_ITEMS: Mutex<Vec<tauri::menu::MenuItem<tauri::Wry>>> = Mutex::new(Vec::new());
+// Player and group the member checkboxes and transfer targets were last
+// listed for
+static GROUP_MEMBERS_LISTED_FOR: Mutex<Option<(String, Option<String>)>> = Mutex::new(None);
+
+/// Group volume change per tray click, in percent
+const TRAY_GROUP_VOLUME_STEP: u8 = 5;
+/// Prefix of the menu item IDs of the group member checkboxes
+const GROUP_MEMBER_ID_PREFIX: &str = "group_member:";
+/// Prefix of the menu item IDs of the players to send playback to
+const TRANSFER_ID_PREFIX: &str = "transfer_to:";
 
 // Global menu item reference for the Discord Rich Presence checkbox
 static DISCORD_RPC_MENU_ITEM: Mutex<Option<tauri::menu::CheckMenuItem<tauri::Wry>>> =
     Mutex::new(None);
 
+// Global menu item reference for cancelling the sleep timer, enabled while
+// one runs
+static SLEEP_TIMER_CANCEL_MENU_ITEM: Mutex<Option<tauri::menu::MenuItem<tauri::Wry>>> =
+    Mutex::new(None);
+
+/// Sleep timer lengths offered in the tray menu, in minutes
+const TRAY_SLEEP_TIMER_MINUTES: [u32; 4] = [15, 30, 45, 60];
+
+/// How long a volume change or mute shows on the tray
+const TRAY_STATUS_FLASH: Duration = Duration::from_millis(1500);
+// Counts the tray status flashes, so only the latest one restores the
+// tray's now-playing text
+static TRAY_STATUS_FLASHES: AtomicU64 = AtomicU64::new(0);
+
 // Discord RPC enabled state
 pub static DISCORD_RPC_ENABLED: AtomicBool = AtomicBool::new(true);
 
@@ -419,7 +497,7 @@ async fn navigate_to_launcher(app: tauri::AppHandle) -> Result<(), String> {
     // Stop Sendspin if running and clear any frontend-reported playback state.
     // This also releases Windows sleep prevention when logging out before the
     // remote frontend has had a chance to publish its stopped state.
-    sendspin::stop().await;
+    app.state::<SendspinManager>().stop().await;
     now_playing::update_now_playing(NowPlaying::default());
 
     // Find the current window (could be "main" or "launcher" depending on how we got here)
@@ -463,10 +541,28 @@ fn get_now_playing() -> NowPlaying {
     now_playing::get_now_playing()
 }
 
+/// Up to `limit` (default 20) tracks queued after the current one on the
+/// player `player_id`, the Sendspin player if `None`
+#[tauri::command]
+async fn get_queue(
+    sendspin: State<'_, SendspinManager>,
+    player_id: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<now_playing::QueueItem>, String> {
+    let player_id = player_id
+        .or_else(|| sendspin.get_player_id())
+        .ok_or("No player to get the queue of")?;
+    tauri::async_runtime::spawn_blocking(move || {
+        ma_api::upcoming_items(&player_id, limit.unwrap_or(20).min(100))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 /// Update now-playing information (called from frontend when track changes)
 #[tauri::command]
-fn update_now_playing(now_playing: NowPlaying) {
-    let sendspin_player_id = sendspin::get_player_id();
+fn update_now_playing(sendspin: State<'_, SendspinManager>, now_playing: NowPlaying) {
+    let sendspin_player_id = sendspin.get_player_id();
     let current_now_playing = now_playing::get_now_playing();
 
     // Filter out frontend updates when Sendspin is active
@@ -500,6 +596,8 @@ fn start_rpc(app: tauri::AppHandle, _websocket: String) {
 
 /// Start all background services (tray tooltip updates, Discord RPC, media controls)
 fn start_services(app_handle: tauri::AppHandle) {
+    let sendspin = app_handle.state::<SendspinManager>().inner().clone();
+
     // Store app handle for media controls callback
     {
         let mut handle = APP_HANDLE.lock().unwrap();
@@ -513,7 +611,7 @@ fn start_services(app_handle: tauri::AppHandle) {
             update_tray_now_playing(np);
             media_controls::update(np);
         }));
-        now_playing::init_power_management();
+        power::inhibit::init();
 
         // Get HWND for Windows media controls
         #[cfg(target_os = "windows")]
@@ -558,7 +656,15 @@ fn start_services(app_handle: tauri::AppHandle) {
             // Route media control events (from OS Now Playing / media keys) to frontend
             log::debug!("[MediaControls] OS media command: {command}");
             if let Some(ref app) = *APP_HANDLE.lock().unwrap() {
-                if let Some(window) = app.get_webview_window("main")
+                let step = match command {
+                    "seek_forward" => Some(sendspin::SEEK_STEP_MS),
+                    "seek_backward" => Some(-sendspin::SEEK_STEP_MS),
+                    _ => None,
+                };
+                if let Some(offset_ms) = step {
+                    // The frontend has no relative seek; this works for any player
+                    seek_now_playing_by(&app.state::<SendspinManager>(), offset_ms);
+                } else if let Some(window) = app.get_webview_window("main")
                     .or_else(|| app.get_webview_window("launcher")) {
                     let cmd = if command == "toggle" {
                         // For toggle, check current state
@@ -572,7 +678,7 @@ fn start_services(app_handle: tauri::AppHandle) {
                     ));
                 }
             }
-        }), hwnd, dispatch);
+        }), hwnd, dispatch, sendspin);
 
         // Start Discord RPC in a separate thread
         thread::spawn(|| {
@@ -600,6 +706,47 @@ fn take_hidden_window_position() -> Option<tauri::PhysicalPosition<i32>> {
         .and_then(|mut guard| guard.take())
 }
 
+/// Bring the main (or launcher) window to the front.
+fn show_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app
+        .get_webview_window("main")
+        .or_else(|| app.get_webview_window("launcher"))
+    {
+        // Undo both hide states: an app-level hide (unmapped) and a
+        // window-manager minimize.
+        let _ = window.unminimize();
+        let _ = window.show();
+        // Restore prior position; the WM may still re-place on map.
+        if let Some(pos) = take_hidden_window_position() {
+            let _ = window.set_position(pos);
+        }
+        let _ = window.set_focus();
+    }
+}
+
+/// Whether a hidden window can be brought back without relaunching: from
+/// the tray icon, or on macOS the Dock.
+fn can_restore_hidden_window(settings: &settings::Settings) -> bool {
+    settings.show_tray_icon || cfg!(target_os = "macos")
+}
+
+/// Act on the command line of a second launch. `args[0]` is its executable.
+fn handle_forwarded_args(app: &tauri::AppHandle, args: &[String]) {
+    let args = args.get(1..).unwrap_or_default();
+    // Deep links are routed by the deep-link plugin and decide themselves
+    // whether the window comes up
+    if !args.iter().any(|arg| deep_link::is_link(arg)) {
+        show_main_window(app);
+    }
+    if args.is_empty() {
+        return;
+    }
+    log::info!("[App] Second launch forwarded: {}", args.join(" "));
+    if args.iter().any(|arg| arg == headless::FLAG) {
+        log::warn!("[App] Not starting headless: the app is already running with a window");
+    }
+}
+
 pub fn set_tray_visible(visible: bool) {
     if let Ok(tray_guard) = TRAY_ICON.try_lock() {
         if let Some(ref tray) = *tray_guard {
@@ -627,6 +774,87 @@ fn load_tray_icon() -> tauri::image::Image<'static> {
     decode_png_icon(png_bytes)
 }
 
+/// Ask the frontend to send `command` (`play`, `pause`, `next`, ...) to the
+/// active player.
+pub(crate) fn send_player_command(app: &tauri::AppHandle, command: &str) {
+    if let Some(window) = app
+        .get_webview_window("main")
+        .or_else(|| app.get_webview_window("launcher"))
+    {
+        let _ = window.eval(format!(
+            "window.__COMPANION_PLAYER_COMMAND__ && window.__COMPANION_PLAYER_COMMAND__('{command}');",
+        ));
+    }
+}
+
+/// Seek the track on the active player by `offset_ms`, back if negative.
+/// A built-in player seeks through its client loop; any other player
+/// through the MA API.
+pub(crate) fn seek_now_playing_by(sendspin: &SendspinManager, offset_ms: i64) {
+    let Some(player_id) = now_playing::get_now_playing().player_id else {
+        return;
+    };
+    let command = sendspin::PlaybackCommand::SeekBy(offset_ms);
+    match sendspin.send_command_to(Some(&player_id), command) {
+        Ok(()) => {}
+        Err(sendspin::SendspinError::UnknownPlayer(_)) => {
+            thread::spawn(move || {
+                if let Err(e) = ma_api::skip_active_queue(&player_id, offset_ms) {
+                    log::warn!("Failed to seek {} by {}ms: {}", player_id, offset_ms, e);
+                }
+            });
+        }
+        Err(e) => log::warn!("Failed to seek by {}ms: {}", offset_ms, e),
+    }
+}
+
+/// Change the main player's volume by `steps` volume steps (see
+/// `Settings::volume_step`), down if negative. Returns the new volume.
+pub(crate) fn step_volume(
+    sendspin: &SendspinManager,
+    steps: i32,
+) -> Result<u8, sendspin::SendspinError> {
+    let settings = settings::get_settings();
+    let volume =
+        i32::from(sendspin.get_volume_percent()?) + steps * i32::from(settings.volume_step);
+    let volume = volume.clamp(0, i32::from(settings.max_volume)) as u8;
+    sendspin.set_volume_percent(volume)?;
+    osd::volume_changed(volume, settings.muted);
+    Ok(volume)
+}
+
+/// Mute the main player, or unmute it. Returns whether it's now muted.
+pub(crate) fn toggle_mute(sendspin: &SendspinManager) -> Result<bool, sendspin::SendspinError> {
+    let muted = !settings::get_settings().muted;
+    sendspin.send_command(sendspin::PlaybackCommand::SetMute(muted))?;
+    osd::volume_changed(sendspin.get_volume_percent().unwrap_or(0), muted);
+    Ok(muted)
+}
+
+/// Briefly show `text` in place of the tray's now-playing tooltip, and its
+/// title on macOS, where it shows next to the icon
+fn flash_tray_status(text: String) {
+    let flash = TRAY_STATUS_FLASHES.fetch_add(1, Ordering::Relaxed) + 1;
+    thread::spawn(move || {
+        if let Ok(tray_guard) = TRAY_ICON.lock() {
+            if let Some(ref tray) = *tray_guard {
+                #[cfg(target_os = "macos")]
+                let _ = tray.set_title(Some(&text));
+                let _ = tray.set_tooltip(Some(&text));
+            }
+        }
+        thread::sleep(TRAY_STATUS_FLASH);
+        if TRAY_STATUS_FLASHES.load(Ordering::Relaxed) == flash {
+            refresh_tray_now_playing();
+        }
+    });
+}
+
+/// Flash the main player's `volume` on the tray
+fn flash_tray_volume(volume: u8) {
+    flash_tray_status(i18n::tr("desktop.tray.volume_level").replace("{0}", &volume.to_string()));
+}
+
 pub(crate) fn refresh_tray_now_playing() {
     update_tray_now_playing(&now_playing::get_now_playing());
 }
@@ -724,7 +952,139 @@ fn update_tray_now_playing(np: &NowPlaying) {
                 let _ = item.set_enabled(has_player && np.can_next);
             }
         }
+
+        // Favorite - needs a track to favorite
+        if let Ok(item_guard) = FAVORITE_MENU_ITEM.try_lock() {
+            if let Some(ref item) = *item_guard {
+                let _ = item.set_enabled(has_player && np.track.is_some());
+            }
+        }
+
+        // Shuffle and repeat - enabled once the player reported them
+        if let Ok(item_guard) = SHUFFLE_MENU_ITEM.try_lock() {
+            if let Some(ref item) = *item_guard {
+                let _ = item.set_enabled(has_player && np.shuffle.is_some());
+                let _ = item.set_checked(np.shuffle == Some(true));
+            }
+        }
+        if let Ok(items) = REPEAT_MENU_ITEMS.try_lock() {
+            for (mode, item) in items.iter() {
+                let _ = item.set_enabled(has_player && np.repeat.is_some());
+                let _ = item.set_checked(np.repeat == Some(*mode));
+            }
+        }
+
+        // Group - volume controls once the server reported the group's volume
+        for item_lock in [&GROUP_VOLUME_UP_MENU_ITEM, &GROUP_VOLUME_DOWN_MENU_ITEM] {
+            if let Ok(item_guard) = item_lock.try_lock() {
+                if let Some(ref item) = *item_guard {
+                    let _ = item.set_enabled(has_player && np.group_volume.is_some());
+                }
+            }
+        }
+        if let Ok(item_guard) = GROUP_MUTE_MENU_ITEM.try_lock() {
+            if let Some(ref item) = *item_guard {
+                let _ = item.set_enabled(has_player && np.group_muted.is_some());
+                let _ = item.set_checked(np.group_muted == Some(true));
+            }
+        }
+        // The players that can join, or take over, are listed again when the
+        // group changes
+        if let Some(player_id) = np.player_id.as_deref() {
+            let listed_for = Some((player_id.to_string(), np.group_name.clone()));
+            let changed = GROUP_MEMBERS_LISTED_FOR.lock().is_ok_and(|mut last| {
+                let changed = *last != listed_for;
+                *last = listed_for;
+                changed
+            });
+            if changed {
+                refresh_tray_group_members(player_id);
+                refresh_tray_transfer_targets(player_id);
+            }
+        }
+    });
+}
+
+/// List the players that can join the group `player_id` plays in as
+/// checkboxes in the tray's group submenu. Blocks on the MA API, so it runs
+/// off the main thread.
+fn refresh_tray_group_members(player_id: &str) {
+    let members = ma_api::group_members(player_id).unwrap_or_else(|e| {
+        log::debug!("[Tray] Failed to list group members: {}", e);
+        Vec::new()
+    });
+    let Some(app) = APP_HANDLE.lock().ok().and_then(|guard| guard.clone()) else {
+        return;
+    };
+    let (Ok(menu_guard), Ok(mut items)) = (GROUP_MENU.lock(), GROUP_MEMBER_MENU_ITEMS.lock())
+    else {
+        return;
+    };
+    let Some(ref menu) = *menu_guard else {
+        return;
+    };
+    for item in items.drain(..) {
+        let _ = menu.remove(&item);
+    }
+    for member in members {
+        let item = CheckMenuItemBuilder::with_id(
+            format!("{GROUP_MEMBER_ID_PREFIX}{}", member.player_id),
+            &member.name,
+        )
+        .checked(member.joined)
+        .build(&app);
+        match item {
+            Ok(item) => {
+                let _ = menu.append(&item);
+                items.push(item);
+            }
+            Err(e) => log::debug!(
+                "[Tray] Failed to add {} to the group menu: {}",
+                member.name,
+                e
+            ),
+        }
+    }
+}
+
+/// List the players `player_id` can send its playback to in the tray's
+/// transfer submenu. Blocks on the MA API, so it runs off the main thread.
+fn refresh_tray_transfer_targets(player_id: &str) {
+    let targets = ma_api::transfer_targets(player_id).unwrap_or_else(|e| {
+        log::debug!("[Tray] Failed to list players to send playback to: {}", e);
+        Vec::new()
     });
+    let Some(app) = APP_HANDLE.lock().ok().and_then(|guard| guard.clone()) else {
+        return;
+    };
+    let (Ok(menu_guard), Ok(mut items)) = (TRANSFER_MENU.lock(), TRANSFER_MENU_ITEMS.lock()) else {
+        return;
+    };
+    let Some(ref menu) = *menu_guard else {
+        return;
+    };
+    for item in items.drain(..) {
+        let _ = menu.remove(&item);
+    }
+    let _ = menu.set_enabled(!targets.is_empty());
+    for target in targets {
+        let item = MenuItemBuilder::with_id(
+            format!("{TRANSFER_ID_PREFIX}{}", target.player_id),
+            &target.name,
+        )
+        .build(&app);
+        match item {
+            Ok(item) => {
+                let _ = menu.append(&item);
+                items.push(item);
+            }
+            Err(e) => log::debug!(
+                "[Tray] Failed to add {} to the transfer menu: {}",
+                target.name,
+                e
+            ),
+        }
+    }
 }
 
 /// Discover Music Assistant servers on the local network via mDNS
@@ -737,13 +1097,71 @@ async fn discover_servers(timeout_secs: Option<u64>) -> Result<Vec<DiscoveredSer
         .map_err(|e| format!("Discovery task failed: {e}"))?
 }
 
+/// Resolve artwork to a locally cached, resized file and return its path.
+/// `size` is one of `tray`, `notification`, `media` or `original` (default).
+#[tauri::command]
+async fn get_cached_artwork(url: String, size: Option<String>) -> Result<String, String> {
+    let size = match size.as_deref() {
+        None => artwork_cache::ArtworkSize::Original,
+        Some(value) => artwork_cache::ArtworkSize::parse(value)
+            .ok_or_else(|| format!("Unknown artwork size: {value}"))?,
+    };
+    tauri::async_runtime::spawn_blocking(move || artwork_cache::fetch(&url, size))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Serve `ma-artwork://localhost/<size>?url=<artwork url>` from the artwork cache.
+fn artwork_protocol_response(uri: &str) -> tauri::http::Response<Vec<u8>> {
+    let error = |status: u16, message: String| {
+        log::debug!("[ArtworkCache] Protocol request {uri} failed: {message}");
+        tauri::http::Response::builder()
+            .status(status)
+            .body(message.into_bytes())
+            .unwrap_or_default()
+    };
+
+    let Ok(parsed) = tauri::Url::parse(uri) else {
+        return error(400, "invalid artwork URL".to_string());
+    };
+    let size_name = parsed.path().trim_matches('/');
+    let size = if size_name.is_empty() {
+        artwork_cache::ArtworkSize::Original
+    } else {
+        match artwork_cache::ArtworkSize::parse(size_name) {
+            Some(size) => size,
+            None => return error(400, format!("unknown artwork size: {size_name}")),
+        }
+    };
+    let Some(url) = parsed
+        .query_pairs()
+        .find_map(|(key, value)| (key == "url").then(|| value.into_owned()))
+    else {
+        return error(400, "missing url parameter".to_string());
+    };
+
+    match artwork_cache::fetch_bytes(&url, size) {
+        Ok(bytes) => tauri::http::Response::builder()
+            .status(200)
+            .header(
+                "Content-Type",
+                artwork_cache::sniff_content_type(&bytes).unwrap_or("application/octet-stream"),
+            )
+            .header("Cache-Control", "max-age=86400")
+            .body(bytes)
+            .unwrap_or_default(),
+        Err(e) => error(502, e),
+    }
+}
+
 /// Get all settings (with actual runtime state for some fields)
 #[tauri::command]
-fn get_settings() -> settings::Settings {
+fn get_settings(sendspin: State<'_, SendspinManager>) -> settings::Settings {
     let mut s = settings::get_settings();
     // Override with actual runtime state
     s.discord_rpc_enabled = DISCORD_RPC_ENABLED.load(std::sync::atomic::Ordering::SeqCst);
-    s.sendspin_enabled = sendspin::is_enabled();
+    s.sendspin_enabled = sendspin.is_enabled();
     s
 }
 
@@ -786,178 +1204,987 @@ async fn reconfigure_sendspin_from_current_session(app: tauri::AppHandle) -> Res
 
 /// Set a string setting
 #[tauri::command]
-fn set_string_setting(key: String, value: Option<String>) -> Result<(), String> {
-    settings::set_string_setting(&key, value)
+fn set_string_setting(
+    sendspin: State<'_, SendspinManager>,
+    key: String,
+    value: Option<String>,
+) -> Result<(), String> {
+    if settings::set_string_setting(&key, value)? {
+        let sendspin = sendspin.inner().clone();
+        tauri::async_runtime::spawn(async move {
+            sendspin.restart().await;
+        });
+    } else if key == "sendspin_player_name" {
+        sendspin.rename(&settings::get_settings().sendspin_player_name)?;
+    } else if sendspin::dsp::SETTINGS.contains(&key.as_str()) {
+        sendspin.reload_dsp()?;
+    } else if key.starts_with("osd_") {
+        osd::preview();
+    }
+    Ok(())
 }
 
-/// Set an integer setting
+/// Play the built-in player on another output device, or on the system
+/// default with `None`. A playing stream moves over without stopping.
 #[tauri::command]
-fn set_int_setting(key: String, value: i32) -> Result<(), String> {
-    settings::set_int_setting(&key, value)
+fn set_output_device(
+    sendspin: State<'_, SendspinManager>,
+    audio_device_id: Option<String>,
+) -> Result<(), String> {
+    if settings::set_string_setting("audio_device_id", audio_device_id.clone())? {
+        sendspin.switch_output_device(audio_device_id)?;
+        // The new device may bring back the sync delay last used on it
+        sendspin.set_static_delay(settings::get_settings().sync_delay_ms)?;
+    }
+    Ok(())
 }
 
-// ============ Sendspin Commands ============
+/// Set the output devices the built-in player falls back to, in order, when
+/// its device isn't connected; applies from the next connection
+#[tauri::command]
+fn set_fallback_devices(device_ids: Vec<String>) -> Result<(), String> {
+    settings::set_fallback_devices(device_ids)
+}
 
-/// List available audio output devices
+/// Set an integer setting
 #[tauri::command]
-fn list_audio_devices() -> Result<Vec<sendspin::devices::AudioDevice>, String> {
-    sendspin::devices::list_devices()
+fn set_int_setting(
+    sendspin: State<'_, SendspinManager>,
+    key: String,
+    value: i32,
+) -> Result<(), String> {
+    settings::set_int_setting(&key, value)?;
+
+    if key == "sync_delay_ms" && settings::get_settings().sendspin_enabled {
+        sendspin.set_static_delay(value)?;
+    }
+    if key == "mirror_delay_ms" && settings::get_settings().sendspin_enabled {
+        sendspin.set_mirror_delay(value)?;
+    }
+    if key == "max_volume" {
+        sendspin.apply_max_volume()?;
+    }
+    if sendspin::dsp::SETTINGS.contains(&key.as_str()) {
+        sendspin.reload_dsp()?;
+    }
+    Ok(())
 }
 
-/// Stop the Sendspin client
+/// Get the per-module log level overrides
 #[tauri::command]
-async fn stop_sendspin() {
-    sendspin::stop().await;
+fn get_log_levels() -> std::collections::BTreeMap<String, String> {
+    settings::get_settings().log_levels
 }
 
-/// Restart the Sendspin client
+/// Set the log level for one module (e.g. `sendspin::devices`), or clear
+/// its override with `level: null`
 #[tauri::command]
-async fn restart_sendspin() -> Result<(), String> {
-    sendspin::restart().await;
-    Ok(())
+fn set_log_level(module: String, level: Option<String>) -> Result<(), String> {
+    settings::set_log_level(&module, level)
 }
 
-/// Get Sendspin connection status
+/// Open the mini player, or close it if it is open
 #[tauri::command]
-fn get_sendspin_status() -> sendspin::ConnectionStatus {
-    sendspin::get_status()
+fn toggle_mini_player(app: tauri::AppHandle) {
+    mini_player::toggle(&app);
 }
 
-/// Send a playback command to Sendspin
+/// What the mini player shows
 #[tauri::command]
-fn sendspin_command(command: String) -> Result<(), String> {
-    sendspin::send_command(&command)
+fn get_mini_player_state(sendspin: State<'_, SendspinManager>) -> mini_player::MiniPlayerState {
+    mini_player::state(&sendspin)
 }
 
-/// Get the Sendspin player ID (for frontend "this device" badge)
+/// Transport control from the mini player; goes to the active player like
+/// the tray's
 #[tauri::command]
-fn get_sendspin_player_id() -> Option<String> {
-    sendspin::get_player_id()
+fn mini_player_command(app: tauri::AppHandle, command: String) -> Result<(), String> {
+    match command.as_str() {
+        "play" | "pause" | "next" | "previous" => {
+            send_player_command(&app, &command);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command: {}", command)),
+    }
 }
 
-/// Configure and optionally start the Sendspin client with server URL from frontend.
-/// This is called by the frontend when it connects to the MA server.
+/// Set the built-in player's volume from the mini player
 #[tauri::command]
-async fn configure_sendspin(
-    app: tauri::AppHandle,
-    server_base_url: String,
-    auth_token: String,
-) -> Result<Option<String>, String> {
-    remember_current_ma_session(server_base_url.clone(), auth_token.clone());
-    configure_sendspin_for_session(app, server_base_url, auth_token).await
+fn mini_player_set_volume(
+    sendspin: State<'_, SendspinManager>,
+    volume: u8,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.set_volume_percent(volume)
 }
 
-fn remember_current_ma_session(server_base_url: String, auth_token: String) {
-    ma_api::remember_session(server_base_url, auth_token);
+/// Set the volume of the group of the player shown in the mini player
+#[tauri::command]
+fn mini_player_set_group_volume(
+    sendspin: State<'_, SendspinManager>,
+    volume: u8,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(
+        now_playing::get_now_playing().player_id.as_deref(),
+        sendspin::PlaybackCommand::SetGroupVolume(volume),
+    )
 }
 
-async fn configure_sendspin_for_session(
+/// Bind a global shortcut (e.g. `Ctrl+Alt+KeyP`) to an action such as
+/// `play_pause`, or clear it with `accelerator: null`
+#[tauri::command]
+fn set_hotkey(
     app: tauri::AppHandle,
-    server_base_url: String,
-    auth_token: String,
-) -> Result<Option<String>, String> {
-    let loaded_settings = settings::get_settings();
+    action: String,
+    accelerator: Option<String>,
+) -> Result<(), String> {
+    settings::set_hotkey(&app, &action, accelerator)
+}
 
-    let sendspin_url = build_sendspin_ws_url(&server_base_url);
+/// Replace the local control API's token, returning the new one
+#[tauri::command]
+fn regenerate_control_api_token() -> Result<String, String> {
+    settings::regenerate_control_api_token()
+}
 
-    // Save the URL to settings
-    let _ = settings::set_string_setting("sendspin_server_url", Some(sendspin_url.clone()));
+/// Give the main player a new random ID and reconnect with it, returning
+/// the new ID
+#[tauri::command]
+fn regenerate_player_id(sendspin: State<'_, SendspinManager>) -> Result<String, String> {
+    let id = player_identity::regenerate()?;
+    let sendspin = sendspin.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        sendspin.restart().await;
+    });
+    Ok(id)
+}
 
-    log::info!(
-        "[Sendspin] Configuring client (enabled={}, url={})",
-        loaded_settings.sendspin_enabled,
-        sendspin_url
-    );
+/// Add a webhook fired on the given playback events
+#[tauri::command]
+fn add_webhook(
+    url: String,
+    events: Vec<settings::WebhookEvent>,
+    template: Option<String>,
+) -> Result<settings::Webhook, String> {
+    settings::add_webhook(url, events, template)
+}
 
-    // If sendspin is enabled, start the client
-    if loaded_settings.sendspin_enabled {
-        // Use hostname as fallback if player name is empty
-        let player_name = if loaded_settings.sendspin_player_name.is_empty() {
-            hostname::get()
-                .ok()
-                .and_then(|h| h.into_string().ok())
-                .map_or_else(
-                    || i18n::tr("desktop.app.companion_name"),
-                    |name| strip_hostname_suffix(&name),
-                )
-        } else {
-            loaded_settings.sendspin_player_name.clone()
-        };
+/// Update a webhook's URL, events or template
+#[tauri::command]
+fn update_webhook(webhook: settings::Webhook) -> Result<(), String> {
+    settings::update_webhook(webhook)
+}
 
-        // Get or generate a persistent player ID
-        let player_id = if let Some(id) = loaded_settings.sendspin_player_id.clone() {
-            id
-        } else {
-            let new_id = format!("ma_companion_{}", uuid::Uuid::new_v4());
-            // Save the generated ID so it persists across restarts
-            let _ = settings::set_string_setting("sendspin_player_id", Some(new_id.clone()));
-            new_id
-        };
+/// Remove a webhook
+#[tauri::command]
+fn remove_webhook(id: String) -> Result<(), String> {
+    settings::remove_webhook(&id)
+}
 
-        let config = sendspin::SendspinConfig {
-            player_id,
-            player_name,
-            server_url: sendspin_url,
-            audio_device_id: loaded_settings.audio_device_id.clone(),
-            sync_delay_ms: loaded_settings.sync_delay_ms,
-            auth_token,
-            app_version: app.package_info().version.to_string(),
-        };
+/// Add an alarm that starts the built-in player at `time` (`HH:MM`), on
+/// `days` of the week (0 being Monday) or once if none
+#[tauri::command]
+fn add_alarm(
+    time: String,
+    days: Vec<u8>,
+    media_uri: Option<String>,
+    volume: Option<u8>,
+    ramp_minutes: u32,
+) -> Result<settings::Alarm, String> {
+    settings::add_alarm(time, days, media_uri, volume, ramp_minutes)
+}
 
-        return sendspin::start(config).await.map(Some);
-    }
+/// Update an alarm, e.g. to switch it on or off
+#[tauri::command]
+fn update_alarm(alarm: settings::Alarm) -> Result<(), String> {
+    settings::update_alarm(alarm)
+}
 
-    Ok(None)
+/// Remove an alarm
+#[tauri::command]
+fn remove_alarm(id: String) -> Result<(), String> {
+    settings::remove_alarm(&id)
 }
 
-/// Build a WebSocket URL for Sendspin from an HTTP(S) server base URL
-fn build_sendspin_ws_url(server_base_url: &str) -> String {
-    let trimmed_url = server_base_url.trim_end_matches('/');
-    let lower_url = trimmed_url.to_ascii_lowercase();
+/// Send a webhook one request for `event` with what is playing now, so the
+/// user can check the endpoint accepts it
+#[tauri::command]
+async fn test_webhook(
+    webhook: settings::Webhook,
+    event: settings::WebhookEvent,
+) -> Result<(), String> {
+    webhooks::validate(&webhook)?;
+    tauri::async_runtime::spawn_blocking(move || webhooks::test(&webhook, event))
+        .await
+        .map_err(|e| format!("Webhook test failed: {e}"))?
+}
 
-    let (ws_scheme, url_without_scheme) = if lower_url.starts_with("https://") {
-        ("wss", &trimmed_url["https://".len()..])
-    } else if lower_url.starts_with("http://") {
-        ("ws", &trimmed_url["http://".len()..])
-    } else {
-        ("ws", trimmed_url)
+/// Ask where to save a diagnostics bundle and write it there. Returns the
+/// saved path, or `None` if the user cancelled.
+#[tauri::command]
+async fn export_diagnostics(
+    app: tauri::AppHandle,
+    sendspin: State<'_, SendspinManager>,
+) -> Result<Option<String>, String> {
+    let Some(path) = app
+        .dialog()
+        .file()
+        .set_file_name(diagnostics::default_file_name())
+        .add_filter("Zip", &["zip"])
+        .blocking_save_file()
+    else {
+        return Ok(None);
     };
+    let path = path.into_path().map_err(|e| e.to_string())?;
+    diagnostics::write_bundle(&app, &sendspin, &path)?;
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
 
-    format!("{}://{}/sendspin", ws_scheme, url_without_scheme)
+/// Ask where to write the now-playing file for streaming overlays and start
+/// writing it there. Returns the chosen path, or `None` if the user cancelled.
+#[tauri::command]
+async fn choose_now_playing_file(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    let (file_name, filter, extension) = match settings::get_settings().now_playing_file_format {
+        settings::NowPlayingFileFormat::Text => ("now-playing.txt", "Text", "txt"),
+        settings::NowPlayingFileFormat::Json => ("now-playing.json", "JSON", "json"),
+    };
+    let Some(path) = app
+        .dialog()
+        .file()
+        .set_file_name(file_name)
+        .add_filter(filter, &[extension])
+        .blocking_save_file()
+    else {
+        return Ok(None);
+    };
+    let path = path
+        .into_path()
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .into_owned();
+    settings::set_string_setting("now_playing_file_path", Some(path.clone()))?;
+    Ok(Some(path))
 }
 
-/// Strip common local network suffixes from a hostname
-fn strip_hostname_suffix(name: &str) -> String {
-    name.trim_end_matches(".local")
-        .trim_end_matches(".lan")
-        .trim_end_matches(".home")
-        .trim_end_matches(".localdomain")
-        .to_string()
+/// Start or stop recording what the main player plays to FLAC files
+#[tauri::command]
+fn set_recording_enabled(enabled: bool) {
+    sendspin::recording::set_enabled(enabled);
 }
 
-/// Open or focus the companion app's settings window.
-fn open_settings_window(app: &tauri::AppHandle) {
-    if let Some(window) = app.get_webview_window("settings") {
-        // The window may have been hidden (close-to-tray) while settings
-        // changed elsewhere (e.g. the tray menu); re-read them before showing
-        refresh_settings_window(app);
-        let _ = window.show();
-        let _ = window.set_focus();
-    } else {
-        let _ = tauri::WebviewWindowBuilder::new(
-            app,
-            "settings",
-            tauri::WebviewUrl::App("settings.html".into()),
-        )
-        .title(i18n::tr("desktop.app.settings_title"))
-        .inner_size(600.0, 700.0)
-        .resizable(true)
-        .build();
-    }
+#[tauri::command]
+fn get_recording_status() -> sendspin::recording::RecordingStatus {
+    sendspin::recording::status()
 }
 
-/// Apply the shared configuration every MA-frontend content window must carry.
-/// Callers add per-window specifics (size, min size, zoom hotkeys) after this.
+/// Start or stop tracing the Sendspin protocol to a file
+#[tauri::command]
+fn set_protocol_trace(enabled: bool) -> Result<sendspin::trace::TraceStatus, String> {
+    sendspin::trace::set_enabled(enabled)
+}
+
+#[tauri::command]
+fn get_protocol_trace_status() -> sendspin::trace::TraceStatus {
+    sendspin::trace::status()
+}
+
+/// Ask which folder to record to. Returns the chosen folder, or `None` if
+/// the user cancelled.
+#[tauri::command]
+async fn choose_recording_dir(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    let Some(dir) = app.dialog().file().blocking_pick_folder() else {
+        return Ok(None);
+    };
+    let dir = dir
+        .into_path()
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .into_owned();
+    settings::set_string_setting("recording_dir", Some(dir.clone()))?;
+    Ok(Some(dir))
+}
+
+/// The last `limit` tracks played, newest first
+#[tauri::command]
+fn get_recent_tracks(limit: u32) -> Result<Vec<history::Play>, String> {
+    history::recent(limit)
+}
+
+/// The `limit` most played tracks of the last `days` days, or of all time
+#[tauri::command]
+fn get_play_counts(limit: u32, days: Option<u32>) -> Result<Vec<history::PlayCount>, String> {
+    history::play_counts(limit, days)
+}
+
+/// Listening time per day over the last `days` days
+#[tauri::command]
+fn get_daily_listening(days: u32) -> Result<Vec<history::DailyListening>, String> {
+    history::daily_listening(days)
+}
+
+#[tauri::command]
+fn clear_listening_history() -> Result<(), String> {
+    history::clear()
+}
+
+/// Pause the built-in player after a number of minutes or at the end of the
+/// current track
+#[tauri::command]
+fn start_sleep_timer(
+    sendspin: State<'_, SendspinManager>,
+    mode: sleep_timer::SleepTimerMode,
+) -> Result<(), String> {
+    sleep_timer::start(&sendspin, mode)
+}
+
+#[tauri::command]
+fn cancel_sleep_timer() {
+    sleep_timer::cancel();
+}
+
+#[tauri::command]
+fn get_sleep_timer() -> sleep_timer::SleepTimerStatus {
+    sleep_timer::status()
+}
+
+// ============ Sendspin Commands ============
+
+/// List available audio output devices
+#[tauri::command]
+fn list_audio_devices() -> Result<Vec<sendspin::devices::AudioDevice>, sendspin::SendspinError> {
+    sendspin::devices::list_devices()
+}
+
+/// Stop the Sendspin client
+#[tauri::command]
+async fn stop_sendspin(sendspin: State<'_, SendspinManager>) -> Result<(), String> {
+    sendspin.stop().await;
+    Ok(())
+}
+
+/// Restart the Sendspin client
+#[tauri::command]
+async fn restart_sendspin(sendspin: State<'_, SendspinManager>) -> Result<(), String> {
+    sendspin.restart().await;
+    Ok(())
+}
+
+/// Get Sendspin connection status
+#[tauri::command]
+fn get_sendspin_status(sendspin: State<'_, SendspinManager>) -> sendspin::ConnectionStatus {
+    sendspin.get_status()
+}
+
+/// Send a playback command to Sendspin. Goes to the main player unless
+/// `player_id` names an additional one.
+#[tauri::command]
+fn sendspin_command(
+    sendspin: State<'_, SendspinManager>,
+    command: sendspin::PlaybackCommand,
+    player_id: Option<String>,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(player_id.as_deref(), command)
+}
+
+/// Seek the current track to `position_ms`. Goes to the main player unless
+/// `player_id` names an additional one.
+#[tauri::command]
+fn sendspin_seek(
+    sendspin: State<'_, SendspinManager>,
+    position_ms: u64,
+    player_id: Option<String>,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(
+        player_id.as_deref(),
+        sendspin::PlaybackCommand::Seek(position_ms),
+    )
+}
+
+/// Shuffle the queue, or play it in order again. Goes to the main player
+/// unless `player_id` names an additional one.
+#[tauri::command]
+fn sendspin_set_shuffle(
+    sendspin: State<'_, SendspinManager>,
+    enabled: bool,
+    player_id: Option<String>,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(
+        player_id.as_deref(),
+        sendspin::PlaybackCommand::SetShuffle(enabled),
+    )
+}
+
+/// Set what the queue repeats: `off`, `one` or `all`. Goes to the main
+/// player unless `player_id` names an additional one.
+#[tauri::command]
+fn sendspin_set_repeat(
+    sendspin: State<'_, SendspinManager>,
+    mode: RepeatMode,
+    player_id: Option<String>,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(
+        player_id.as_deref(),
+        sendspin::PlaybackCommand::SetRepeat(mode),
+    )
+}
+
+/// Set the volume of the player's whole group. Goes to the main player
+/// unless `player_id` names an additional one.
+#[tauri::command]
+fn sendspin_set_group_volume(
+    sendspin: State<'_, SendspinManager>,
+    volume: u8,
+    player_id: Option<String>,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(
+        player_id.as_deref(),
+        sendspin::PlaybackCommand::SetGroupVolume(volume),
+    )
+}
+
+/// Mute or unmute the player's whole group. Goes to the main player unless
+/// `player_id` names an additional one.
+#[tauri::command]
+fn sendspin_set_group_mute(
+    sendspin: State<'_, SendspinManager>,
+    muted: bool,
+    player_id: Option<String>,
+) -> Result<(), sendspin::SendspinError> {
+    sendspin.send_command_to(
+        player_id.as_deref(),
+        sendspin::PlaybackCommand::SetGroupMute(muted),
+    )
+}
+
+/// List the players that can join the group the main player (or
+/// `player_id`) plays in, and whether they have
+#[tauri::command]
+async fn get_group_members(
+    sendspin: State<'_, SendspinManager>,
+    player_id: Option<String>,
+) -> Result<Vec<ma_api::GroupMember>, String> {
+    let player_id = player_id
+        .or_else(|| sendspin.get_player_id())
+        .ok_or("No player to get the group of")?;
+    tauri::async_runtime::spawn_blocking(move || ma_api::group_members(&player_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Add `member_id` to the group the main player (or `player_id`) plays in,
+/// or take it out
+#[tauri::command]
+async fn set_group_member(
+    sendspin: State<'_, SendspinManager>,
+    member_id: String,
+    joined: bool,
+    player_id: Option<String>,
+) -> Result<(), String> {
+    let player_id = player_id
+        .or_else(|| sendspin.get_player_id())
+        .ok_or("No player to group with")?;
+    tauri::async_runtime::spawn_blocking(move || {
+        ma_api::set_group_member(&player_id, &member_id, joined)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Move the queue playing on `from_player_id` (or, without it, whichever is
+/// playing elsewhere) onto the main player, or `player_id`
+#[tauri::command]
+async fn move_playback_here(
+    sendspin: State<'_, SendspinManager>,
+    from_player_id: Option<String>,
+    player_id: Option<String>,
+) -> Result<(), String> {
+    let player_id = player_id
+        .or_else(|| sendspin.get_player_id())
+        .ok_or("No player to move playback to")?;
+    tauri::async_runtime::spawn_blocking(move || {
+        ma_api::move_playback_here(&player_id, from_player_id.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Move what the main player (or `player_id`) plays onto `target_player_id`
+#[tauri::command]
+async fn send_playback_to(
+    sendspin: State<'_, SendspinManager>,
+    target_player_id: String,
+    player_id: Option<String>,
+) -> Result<(), String> {
+    let player_id = player_id
+        .or_else(|| sendspin.get_player_id())
+        .ok_or("No player to send playback from")?;
+    tauri::async_runtime::spawn_blocking(move || {
+        ma_api::send_playback_to(&player_id, &target_player_id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// List the players the main player (or `player_id`) can send playback to
+#[tauri::command]
+async fn get_transfer_targets(
+    sendspin: State<'_, SendspinManager>,
+    player_id: Option<String>,
+) -> Result<Vec<ma_api::TransferTarget>, String> {
+    let player_id = player_id
+        .or_else(|| sendspin.get_player_id())
+        .ok_or("No player to send playback from")?;
+    tauri::async_runtime::spawn_blocking(move || ma_api::transfer_targets(&player_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Send a command from the tray menu to the player now playing
+fn send_tray_command(app: &tauri::AppHandle, command: sendspin::PlaybackCommand) {
+    let np = now_playing::get_now_playing();
+    let sendspin = app.state::<SendspinManager>();
+    if let Err(e) = sendspin.send_command_to(np.player_id.as_deref(), command) {
+        log::warn!("[Tray] Failed to send {:?}: {}", command, e);
+    }
+}
+
+/// List the running built-in players
+#[tauri::command]
+fn get_sendspin_players(sendspin: State<'_, SendspinManager>) -> Vec<sendspin::PlayerInfo> {
+    sendspin.players()
+}
+
+/// Get live playback and clock-sync statistics of the running players, or
+/// only of `player_id`
+#[tauri::command]
+fn get_player_stats(
+    sendspin: State<'_, SendspinManager>,
+    player_id: Option<String>,
+) -> Vec<sendspin::stats::PlayerStats> {
+    let mut stats = sendspin.player_stats();
+    if let Some(player_id) = player_id {
+        stats.retain(|s| s.player_id == player_id);
+    }
+    stats
+}
+
+/// Add an additional built-in player and start it if Sendspin is running
+#[tauri::command]
+async fn add_sendspin_player(
+    sendspin: State<'_, SendspinManager>,
+    player_name: String,
+    audio_device_id: Option<String>,
+) -> Result<settings::AdditionalPlayer, String> {
+    let player = settings::add_additional_player(player_name, audio_device_id)?;
+    sendspin.sync_additional_players().await;
+    Ok(player)
+}
+
+/// Update an additional player's name, output device or sync delay
+#[tauri::command]
+async fn update_sendspin_player(
+    sendspin: State<'_, SendspinManager>,
+    player: settings::AdditionalPlayer,
+) -> Result<(), String> {
+    if player.player_name.trim().is_empty() {
+        return Err("Player name must not be empty".to_string());
+    }
+    settings::update_additional_player(&player.player_id, |p| {
+        p.player_name = player.player_name.trim().to_string();
+        p.audio_device_id = player.audio_device_id.clone();
+        p.sync_delay_ms = player.sync_delay_ms;
+    })?;
+    sendspin.sync_additional_players().await;
+    Ok(())
+}
+
+/// Remove an additional built-in player, disconnecting it
+#[tauri::command]
+async fn remove_sendspin_player(
+    sendspin: State<'_, SendspinManager>,
+    player_id: String,
+) -> Result<(), String> {
+    settings::remove_additional_player(&player_id)?;
+    sendspin.sync_additional_players().await;
+    Ok(())
+}
+
+/// Play a short sweep on the left, then the right channel of an output
+/// device (the default output if `None`), to check it's the right one
+#[tauri::command]
+async fn test_audio_device(device_id: Option<String>) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || sendspin::test_tone::play(device_id.as_deref()))
+        .await
+        .map_err(|e| format!("Test tone failed: {e}"))?
+}
+
+/// List input devices that can serve as a loopback for calibration
+#[tauri::command]
+fn list_calibration_inputs() -> Result<Vec<String>, String> {
+    sendspin::calibration::input_devices()
+}
+
+/// Measure the output latency of a player's device with a test signal and
+/// suggest a sync delay for it, listening on `input_device_id` as a loopback
+/// if given. With `apply`, the suggestion is saved as the player's sync
+/// delay. Goes to the main player unless `player_id` names an additional one.
+#[tauri::command]
+async fn calibrate_sync_delay(
+    sendspin: State<'_, SendspinManager>,
+    player_id: Option<String>,
+    input_device_id: Option<String>,
+    apply: bool,
+) -> Result<sendspin::calibration::CalibrationResult, String> {
+    let settings = settings::get_settings();
+    let additional = player_id.as_deref().and_then(|id| {
+        settings
+            .additional_players
+            .iter()
+            .find(|p| p.player_id == id)
+            .cloned()
+    });
+    let audio_device_id = match additional {
+        Some(ref p) => p.audio_device_id.clone(),
+        None => settings.audio_device_id.clone(),
+    };
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        sendspin::calibration::run(audio_device_id.as_deref(), input_device_id.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Calibration failed: {e}"))??;
+
+    if apply {
+        let delay = result.suggested_sync_delay_ms;
+        if let Some(p) = additional {
+            settings::update_additional_player(&p.player_id, |p| p.sync_delay_ms = delay)?;
+            sendspin.sync_additional_players().await;
+        } else {
+            settings::set_int_setting("sync_delay_ms", delay)?;
+            if settings.sendspin_enabled {
+                sendspin.set_static_delay(delay)?;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Capture what this computer is playing and play it on the MA player
+/// `player_id`, replacing any capture already running
+#[tauri::command]
+async fn start_system_capture(
+    player_id: String,
+) -> Result<sendspin::capture::CaptureStatus, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        sendspin::capture::start(&player_id, sendspin::capture::Source::SystemAudio)
+    })
+    .await
+    .map_err(|e| format!("Capture failed: {e}"))?
+}
+
+/// Announce from a microphone or line-in (the default input if none is
+/// given) on an MA player, until the capture is stopped
+#[tauri::command]
+async fn start_announcement(
+    player_id: String,
+    input_device_id: Option<String>,
+) -> Result<sendspin::capture::CaptureStatus, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        sendspin::capture::start(
+            &player_id,
+            sendspin::capture::Source::Input(input_device_id),
+        )
+    })
+    .await
+    .map_err(|e| format!("Capture failed: {e}"))?
+}
+
+/// Stop capturing; a system audio stream also stops the player it was
+/// playing on
+#[tauri::command]
+async fn stop_system_capture() -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(sendspin::capture::stop)
+        .await
+        .map_err(|e| format!("Stopping capture failed: {e}"))?
+}
+
+/// Get the capture that's running, if any
+#[tauri::command]
+fn get_system_capture_status() -> Option<sendspin::capture::CaptureStatus> {
+    sendspin::capture::status()
+}
+
+/// Get whether the output device is currently held in exclusive mode
+#[tauri::command]
+fn get_exclusive_mode_status() -> sendspin::exclusive::ExclusiveStatus {
+    sendspin::exclusive::status()
+}
+
+/// Start or stop the `sendspin://visualizer` spectrum frames of the main
+/// player; pages showing a spectrum turn them on while visible
+#[tauri::command]
+fn set_visualizer_enabled(enabled: bool) {
+    sendspin::visualizer::set_enabled(enabled);
+}
+
+/// Start or stop `sendspin://output-levels` meter events for the calling
+/// window; closing the window stops them too
+#[tauri::command]
+fn set_output_levels_enabled(window: tauri::Window, enabled: bool) {
+    sendspin::levels::subscribe(window.label(), enabled);
+}
+
+/// Get the output profile (EQ and other processing) of an output device;
+/// `None` is the system default output
+#[tauri::command]
+fn get_output_profile(audio_device_id: Option<String>) -> settings::OutputProfile {
+    settings::output_profile(&settings::get_settings(), audio_device_id.as_deref())
+}
+
+/// Set or clear the output profile of an output device; players on it
+/// pick up the change without reconnecting
+#[tauri::command]
+fn set_output_profile(
+    sendspin: State<'_, SendspinManager>,
+    audio_device_id: Option<String>,
+    profile: Option<settings::OutputProfile>,
+) -> Result<(), String> {
+    settings::set_output_profile(audio_device_id.as_deref(), profile)?;
+    sendspin.reload_dsp().map_err(String::from)
+}
+
+/// Get the balance and mono downmix of the main player, or of the
+/// additional player `player_id`
+#[tauri::command]
+fn get_channel_mix(player_id: Option<String>) -> settings::ChannelMix {
+    settings::channel_mix(&settings::get_settings(), player_id.as_deref())
+}
+
+/// Set the balance and mono downmix of the main player, or of the
+/// additional player `player_id`; it's heard without reconnecting
+#[tauri::command]
+fn set_channel_mix(
+    sendspin: State<'_, SendspinManager>,
+    player_id: Option<String>,
+    mix: settings::ChannelMix,
+) -> Result<(), String> {
+    settings::set_channel_mix(player_id.as_deref(), mix)?;
+    sendspin.reload_dsp().map_err(String::from)
+}
+
+/// List the built-in EQ presets
+#[tauri::command]
+fn get_eq_presets() -> Vec<sendspin::dsp::EqPreset> {
+    sendspin::dsp::PRESETS.to_vec()
+}
+
+/// Get the Sendspin player ID (for frontend "this device" badge)
+#[tauri::command]
+fn get_sendspin_player_id(sendspin: State<'_, SendspinManager>) -> Option<String> {
+    sendspin.get_player_id()
+}
+
+/// Get the custom TLS trust settings for a server, if any
+#[tauri::command]
+fn get_server_tls(server_url: String) -> Option<settings::ServerTls> {
+    settings::server_tls(&server_url)
+}
+
+/// Set or clear the custom TLS trust settings for a server and reconnect
+/// so they take effect
+#[tauri::command]
+async fn set_server_tls(
+    sendspin: State<'_, SendspinManager>,
+    server_url: String,
+    tls: Option<settings::ServerTls>,
+) -> Result<(), String> {
+    settings::set_server_tls(&server_url, tls)?;
+    sendspin.restart().await;
+    Ok(())
+}
+
+/// Store the auth token for a server in the OS credential store
+#[tauri::command]
+async fn store_auth_token(server_url: String, token: String) -> Result<(), String> {
+    // The Secret Service may show an unlock prompt; keep it off the async runtime
+    tokio::task::spawn_blocking(move || secrets::store_token(&server_url, &token))
+        .await
+        .map_err(|e| format!("Token storage task failed: {e}"))?
+}
+
+/// Get the stored auth token for a server, if any
+#[tauri::command]
+async fn get_auth_token(server_url: String) -> Result<Option<String>, String> {
+    tokio::task::spawn_blocking(move || secrets::get_token(&server_url))
+        .await
+        .map_err(|e| format!("Token storage task failed: {e}"))?
+}
+
+/// Forget the stored auth token for a server
+#[tauri::command]
+async fn delete_auth_token(server_url: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || secrets::delete_token(&server_url))
+        .await
+        .map_err(|e| format!("Token storage task failed: {e}"))?
+}
+
+/// Configure and optionally start the Sendspin client with server URL from frontend.
+/// This is called by the frontend when it connects to the MA server.
+#[tauri::command]
+async fn configure_sendspin(
+    app: tauri::AppHandle,
+    server_base_url: String,
+    auth_token: String,
+) -> Result<Option<String>, sendspin::SendspinError> {
+    remember_current_ma_session(server_base_url.clone(), auth_token.clone());
+    configure_sendspin_for_session(app, server_base_url, auth_token).await
+}
+
+/// Swap in a new auth token after `sendspin://auth-required`, reconnecting
+/// the players with the rest of their configuration unchanged.
+#[tauri::command]
+async fn reauthenticate_sendspin(
+    sendspin: State<'_, SendspinManager>,
+    auth_token: String,
+) -> Result<(), sendspin::SendspinError> {
+    if let Some(session) = ma_api::current_session() {
+        remember_current_ma_session(session.server_base_url, auth_token.clone());
+    }
+    sendspin.reauthenticate(auth_token).await
+}
+
+fn remember_current_ma_session(server_base_url: String, auth_token: String) {
+    // Headless mode reads the token back from here; the Secret Service may
+    // show an unlock prompt, so keep it off the async runtime
+    let (url, token) = (server_base_url.clone(), auth_token.clone());
+    tauri::async_runtime::spawn_blocking(move || secrets::adopt_token(&url, &token));
+    ma_api::remember_session(server_base_url, auth_token);
+}
+
+async fn configure_sendspin_for_session(
+    app: tauri::AppHandle,
+    server_base_url: String,
+    auth_token: String,
+) -> Result<Option<String>, sendspin::SendspinError> {
+    let app_version = app.package_info().version.to_string();
+    match sendspin_config_for_session(&server_base_url, auth_token, app_version) {
+        Some(config) => app.state::<SendspinManager>().start(config).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Build the main player's config for a session with the server at
+/// `server_base_url`, or `None` if the native player is disabled. Also
+/// remembers the Sendspin URL and, on first use, a generated player ID.
+pub(crate) fn sendspin_config_for_session(
+    server_base_url: &str,
+    auth_token: String,
+    app_version: String,
+) -> Option<sendspin::SendspinConfig> {
+    let loaded_settings = settings::get_settings();
+
+    let sendspin_url = build_sendspin_ws_url(server_base_url);
+
+    // Save the URL to settings
+    let _ = settings::set_string_setting("sendspin_server_url", Some(sendspin_url.clone()));
+
+    log::info!(
+        "[Sendspin] Configuring client (enabled={}, url={})",
+        loaded_settings.sendspin_enabled,
+        sendspin_url
+    );
+
+    if !loaded_settings.sendspin_enabled {
+        return None;
+    }
+
+    // Use hostname as fallback if player name is empty
+    let player_name = if loaded_settings.sendspin_player_name.is_empty() {
+        hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .map_or_else(
+                || i18n::tr("desktop.app.companion_name"),
+                |name| strip_hostname_suffix(&name),
+            )
+    } else {
+        loaded_settings.sendspin_player_name.clone()
+    };
+
+    let player_id = player_identity::main_player_id();
+
+    Some(sendspin::SendspinConfig {
+        player_id,
+        player_name,
+        server_url: sendspin_url,
+        audio_device_id: loaded_settings.audio_device_id.clone(),
+        sync_delay_ms: loaded_settings.sync_delay_ms,
+        mirror_device_id: loaded_settings.mirror_device_id.clone(),
+        mirror_delay_ms: loaded_settings.mirror_delay_ms,
+        auth_token,
+        app_version,
+    })
+}
+
+/// Build a WebSocket URL for Sendspin from an HTTP(S) server base URL
+fn build_sendspin_ws_url(server_base_url: &str) -> String {
+    let trimmed_url = server_base_url.trim_end_matches('/');
+    let lower_url = trimmed_url.to_ascii_lowercase();
+
+    let (ws_scheme, url_without_scheme) = if lower_url.starts_with("https://") {
+        ("wss", &trimmed_url["https://".len()..])
+    } else if lower_url.starts_with("http://") {
+        ("ws", &trimmed_url["http://".len()..])
+    } else {
+        ("ws", trimmed_url)
+    };
+
+    format!("{}://{}/sendspin", ws_scheme, url_without_scheme)
+}
+
+/// Strip common local network suffixes from a hostname
+fn strip_hostname_suffix(name: &str) -> String {
+    name.trim_end_matches(".local")
+        .trim_end_matches(".lan")
+        .trim_end_matches(".home")
+        .trim_end_matches(".localdomain")
+        .to_string()
+}
+
+/// Open or focus the companion app's settings window, scrolled to the
+/// section headed by the element with id `section` if given.
+fn open_settings_window(app: &tauri::AppHandle, section: Option<&str>) {
+    let scroll_script = section.map(|id| {
+        format!("document.getElementById('{id}')?.scrollIntoView({{ block: 'start' }});")
+    });
+    if let Some(window) = app.get_webview_window("settings") {
+        // The window may have been hidden (close-to-tray) while settings
+        // changed elsewhere (e.g. the tray menu); re-read them before showing
+        refresh_settings_window(app);
+        let _ = window.show();
+        let _ = window.set_focus();
+        if let Some(script) = scroll_script {
+            let _ = window.eval(script);
+        }
+    } else {
+        let mut builder = tauri::WebviewWindowBuilder::new(
+            app,
+            "settings",
+            tauri::WebviewUrl::App("settings.html".into()),
+        )
+        .title(i18n::tr("desktop.app.settings_title"))
+        .inner_size(600.0, 700.0)
+        .resizable(true);
+        if let Some(script) = scroll_script {
+            builder = builder.initialization_script(format!(
+                "document.addEventListener('DOMContentLoaded', () => {{ {script} }});"
+            ));
+        }
+        let _ = builder.build();
+    }
+}
+
+/// Apply the shared configuration every MA-frontend content window must carry.
+/// Callers add per-window specifics (size, min size, zoom hotkeys) after this.
 fn apply_window_defaults<R: tauri::Runtime, M: tauri::Manager<R>>(
     mut builder: tauri::WebviewWindowBuilder<'_, R, M>,
 ) -> tauri::WebviewWindowBuilder<'_, R, M> {
@@ -1122,35 +2349,65 @@ fn apply_linux_webview_workarounds() -> LinuxWebviewWorkaroundReport {
 }
 
 pub fn run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == sendspin::audio_process::FLAG) {
+        std::process::exit(sendspin::audio_process::run_child());
+    }
+    if args
+        .first()
+        .is_some_and(|arg| ipc::SUBCOMMANDS.contains(&arg.as_str()))
+    {
+        std::process::exit(ipc::run_client(&args));
+    }
+    if args.iter().any(|arg| arg == headless::FLAG) {
+        std::process::exit(match headless::run() {
+            Ok(()) => 0,
+            Err(e) => {
+                log::error!("[Headless] {}", e);
+                1
+            }
+        });
+    }
+
+    // The single-instance plugin only knows about windowed instances; a
+    // headless one is found through its control socket
+    if ipc::running_instance() == Some(true) {
+        eprintln!(
+            "Music Assistant Companion is already running headless; stop it first \
+             or control it with `music-assistant-companion status|play|pause|...`"
+        );
+        std::process::exit(1);
+    }
+
     #[cfg(target_os = "linux")]
     let linux_webview_workarounds = apply_linux_webview_workarounds();
 
     let context = tauri::generate_context!();
     let mut builder = tauri::Builder::default();
 
-    builder = builder.plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
-        if let Some(window) = app
-            .get_webview_window("main")
-            .or_else(|| app.get_webview_window("launcher"))
-        {
-            let _ = window.unminimize();
-            let _ = window.show();
-            if let Some(pos) = take_hidden_window_position() {
-                let _ = window.set_position(pos);
-            }
-            let _ = window.set_focus();
-        }
+    // A second windowed launch hands its command line over to this instance
+    // and exits instead of registering another player
+    builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+        handle_forwarded_args(app, &args);
     }));
 
-    builder = builder.plugin(tauri_plugin_window_state::Builder::new().build());
+    // Must come after single-instance, which forwards links that start a
+    // second instance to this one
+    builder = builder.plugin(tauri_plugin_deep_link::init());
+    // The OSD is placed afresh each time it shows
+    builder = builder.plugin(
+        tauri_plugin_window_state::Builder::new()
+            .with_denylist(&[osd::LABEL])
+            .build(),
+    );
 
     builder
+        .manage(SendspinManager::new())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_autostart::init(
-            MacosLauncher::AppleScript,
-            None,))
+        .plugin(autostart::plugin())
         .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(hotkeys::plugin())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .invoke_handler(tauri::generate_handler![
             is_companion_app,
@@ -1163,27 +2420,122 @@ pub fn run() {
             companion_ready,
             navigate_to_launcher,
             get_now_playing,
+            get_queue,
             update_now_playing,
             start_desktop_services,
             start_discord_rpc,
             start_rpc,
             discover_servers,
+            get_cached_artwork,
             get_settings,
             set_setting,
             set_string_setting,
+            set_output_device,
+            set_fallback_devices,
             set_int_setting,
+            get_log_levels,
+            set_log_level,
+            set_hotkey,
+            regenerate_control_api_token,
+            regenerate_player_id,
+            add_webhook,
+            update_webhook,
+            remove_webhook,
+            test_webhook,
+            add_alarm,
+            update_alarm,
+            remove_alarm,
+            toggle_mini_player,
+            get_mini_player_state,
+            mini_player_command,
+            mini_player_set_volume,
+            mini_player_set_group_volume,
+            export_diagnostics,
+            choose_now_playing_file,
+            set_recording_enabled,
+            get_recording_status,
+            set_protocol_trace,
+            get_protocol_trace_status,
+            choose_recording_dir,
+            get_recent_tracks,
+            get_play_counts,
+            get_daily_listening,
+            clear_listening_history,
+            start_sleep_timer,
+            cancel_sleep_timer,
+            get_sleep_timer,
+            get_server_tls,
+            set_server_tls,
+            store_auth_token,
+            get_auth_token,
+            delete_auth_token,
+            reauthenticate_sendspin,
             // Sendspin commands
             list_audio_devices,
             stop_sendspin,
             restart_sendspin,
             get_sendspin_status,
+            get_exclusive_mode_status,
+            set_visualizer_enabled,
+            set_output_levels_enabled,
+            get_output_profile,
+            set_output_profile,
+            get_eq_presets,
+            get_channel_mix,
+            set_channel_mix,
+            test_audio_device,
+            list_calibration_inputs,
+            calibrate_sync_delay,
+            start_system_capture,
+            start_announcement,
+            stop_system_capture,
+            get_system_capture_status,
             sendspin_command,
+            sendspin_seek,
+            sendspin_set_shuffle,
+            sendspin_set_repeat,
+            sendspin_set_group_volume,
+            sendspin_set_group_mute,
+            get_group_members,
+            set_group_member,
+            move_playback_here,
+            send_playback_to,
+            get_transfer_targets,
             get_sendspin_player_id,
+            get_sendspin_players,
+            get_player_stats,
+            add_sendspin_player,
+            update_sendspin_player,
+            remove_sendspin_player,
             configure_sendspin
         ])
+        .register_asynchronous_uri_scheme_protocol("ma-artwork", |_ctx, request, responder| {
+            let uri = request.uri().to_string();
+            thread::spawn(move || responder.respond(artwork_protocol_response(&uri)));
+        })
         .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                sendspin::levels::subscribe(window.label(), false);
+            }
             if let tauri::WindowEvent::CloseRequested { api, .. } = event {
-                if settings::get_settings().close_to_tray {
+                // The mini player and the OSD really close; the tray, a
+                // shortcut or the next change opens a fresh one
+                if window.label() == mini_player::LABEL || window.label() == osd::LABEL {
+                    return;
+                }
+                let settings = settings::get_settings();
+                // Quitting mid-playback would also stop everyone listening
+                // along in a group with this player; keep it running in the
+                // tray instead
+                let keep_playing = !settings.close_to_tray
+                    && settings.keep_playing_on_close
+                    && window.label() != "settings"
+                    && can_restore_hidden_window(&settings)
+                    && window.state::<SendspinManager>().is_playing();
+                if keep_playing {
+                    log::info!("[App] Window closed during playback; keeping the player running");
+                }
+                if settings.close_to_tray || keep_playing {
                     if let Ok(pos) = window.outer_position() {
                         stash_window_position(pos);
                     }
@@ -1208,6 +2560,9 @@ pub fn run() {
             // Installing the plugin resets the global max level, so re-apply the
             // persisted verbosity now.
             logging::apply_after_install(log_verbosity);
+            if let Err(e) = logging::set_module_levels(&loaded_settings.log_levels) {
+                log::warn!("[App] Ignoring saved log levels: {}", e);
+            }
             i18n::init(app.handle());
 
             // Replace Tauri's default (hardcoded-English) menu bar with one
@@ -1234,6 +2589,11 @@ pub fn run() {
             #[cfg(target_os = "linux")]
             linux_theme::init(app.handle().clone());
 
+            // Player on/off, output device and sync stats for scripts, which
+            // MPRIS can't express
+            #[cfg(target_os = "linux")]
+            linux_dbus::init(app.handle().clone());
+
             // Create main window (companion bridge + clipboard polyfill applied
             // via apply_window_defaults; runs on every page load, including the
             // remote MA frontend loaded via window.location.href).
@@ -1248,10 +2608,40 @@ pub fn run() {
 
             // Update runtime state flags from settings
             DISCORD_RPC_ENABLED.store(loaded_settings.discord_rpc_enabled, Ordering::SeqCst);
-            sendspin::set_enabled(loaded_settings.sendspin_enabled);
+            app.state::<SendspinManager>()
+                .set_enabled(loaded_settings.sendspin_enabled);
+            sendspin::events::init(app.handle().clone());
+            // Reconnect after sleep instead of leaving a stale connection behind,
+            // and pause while the session is locked if enabled
+            power::init(app.handle().clone());
+            // Accept `music-assistant-companion play|pause|...` from the command line
+            ipc::serve(app.state::<SendspinManager>().inner().clone(), false);
+            // The same, over HTTP and WebSocket, if enabled
+            control_api::init(app.state::<SendspinManager>().inner().clone());
+            // Tell configured webhooks about playback events
+            webhooks::init();
+            // Keep the now-playing file for streaming overlays up to date
+            now_playing_file::init();
+            // Show volume changes and new tracks on screen, if enabled
+            osd::init(app.handle());
+            // Transport controls in the Dock icon's menu, and a playing badge
+            #[cfg(target_os = "macos")]
+            dock::init(app.handle());
+            // Keep the listening history
+            history::init();
+            // Start the player at the times set for alarms
+            alarms::init(app.state::<SendspinManager>().inner().clone());
+            // Act on music-assistant:// links
+            deep_link::init(app.handle());
+            hotkeys::init(app.handle());
+
+            autostart::sync(app.handle());
 
             // "Start minimized": launch to the tray; Show / single-instance restore it.
-            if loaded_settings.start_minimized {
+            // The window still loads, so the built-in player connects as usual.
+            let start_hidden = loaded_settings.start_minimized
+                || (loaded_settings.minimize_at_login && autostart::launched_at_login());
+            if start_hidden && can_restore_hidden_window(&loaded_settings) {
                 if let Some(main_window) = app.get_webview_window("main") {
                     let _ = main_window.hide();
                 }
@@ -1274,9 +2664,89 @@ pub fn run() {
             let next_track = MenuItemBuilder::with_id("next_track", i18n::tr("desktop.tray.next"))
                 .enabled(false)
                 .build(app)?;
+            let favorite = MenuItemBuilder::with_id("favorite", i18n::tr("desktop.tray.favorite"))
+                .enabled(false)
+                .build(app)?;
+            // Shuffle and repeat - start disabled until the player reports them
+            let shuffle = CheckMenuItemBuilder::with_id("shuffle", i18n::tr("desktop.tray.shuffle"))
+                .enabled(false)
+                .build(app)?;
+            let mut repeat_menu = SubmenuBuilder::new(app, i18n::tr("desktop.tray.repeat"));
+            let mut repeat_items = Vec::new();
+            for (mode, id) in TRAY_REPEAT_MODES {
+                let item = CheckMenuItemBuilder::with_id(id, i18n::tr(&format!("desktop.tray.{id}")))
+                    .enabled(false)
+                    .build(app)?;
+                repeat_menu = repeat_menu.item(&item);
+                repeat_items.push((mode, item));
+            }
+            let repeat_menu = repeat_menu.build()?;
+            // Group - players that can join are appended once known
+            let group_volume_up = MenuItemBuilder::with_id(
+                "group_volume_up",
+                i18n::tr("desktop.tray.group_volume_up"),
+            )
+            .enabled(false)
+            .build(app)?;
+            let group_volume_down = MenuItemBuilder::with_id(
+                "group_volume_down",
+                i18n::tr("desktop.tray.group_volume_down"),
+            )
+            .enabled(false)
+            .build(app)?;
+            let group_mute = CheckMenuItemBuilder::with_id("group_mute", i18n::tr("desktop.tray.group_mute"))
+                .enabled(false)
+                .build(app)?;
+            let group_menu = SubmenuBuilder::new(app, i18n::tr("desktop.tray.group"))
+                .item(&group_volume_up)
+                .item(&group_volume_down)
+                .item(&group_mute)
+                .separator()
+                .build()?;
+            // Transfer - players to send playback to are appended once known
+            let move_playback_here = MenuItemBuilder::with_id(
+                "move_playback_here",
+                i18n::tr("desktop.tray.move_playback_here"),
+            )
+            .build(app)?;
+            let transfer_menu = SubmenuBuilder::new(app, i18n::tr("desktop.tray.send_playback_to"))
+                .enabled(false)
+                .build()?;
+            let mut sleep_timer_menu =
+                SubmenuBuilder::new(app, i18n::tr("desktop.tray.sleep_timer"));
+            for minutes in TRAY_SLEEP_TIMER_MINUTES {
+                sleep_timer_menu = sleep_timer_menu.item(
+                    &MenuItemBuilder::with_id(
+                        format!("sleep_timer_{minutes}"),
+                        i18n::tr("desktop.tray.sleep_timer_minutes")
+                            .replace("{0}", &minutes.to_string()),
+                    )
+                    .build(app)?,
+                );
+            }
+            let sleep_timer_cancel = MenuItemBuilder::with_id(
+                "sleep_timer_cancel",
+                i18n::tr("desktop.tray.sleep_timer_cancel"),
+            )
+            .enabled(false)
+            .build(app)?;
+            let sleep_timer_menu = sleep_timer_menu
+                .item(
+                    &MenuItemBuilder::with_id(
+                        "sleep_timer_end_of_track",
+                        i18n::tr("desktop.tray.sleep_timer_end_of_track"),
+                    )
+                    .build(app)?,
+                )
+                .separator()
+                .item(&sleep_timer_cancel)
+                .build()?;
             let separator_playback = PredefinedMenuItem::separator(app)?;
             let show = MenuItemBuilder::with_id("show", i18n::tr("common.actions.show")).build(app)?;
             let hide = MenuItemBuilder::with_id("hide", i18n::tr("common.actions.hide")).build(app)?;
+            let mini_player_item =
+                MenuItemBuilder::with_id("mini_player", i18n::tr("desktop.tray.mini_player"))
+                    .build(app)?;
             let switch_server =
                 MenuItemBuilder::with_id("switch_server", i18n::tr("desktop.tray.switch_server"))
                     .build(app)?;
@@ -1318,9 +2788,43 @@ pub fn run() {
             if let Ok(mut item_guard) = NEXT_TRACK_MENU_ITEM.lock() {
                 *item_guard = Some(next_track.clone());
             }
+            if let Ok(mut item_guard) = FAVORITE_MENU_ITEM.lock() {
+                *item_guard = Some(favorite.clone());
+            }
+            if let Ok(mut item_guard) = SHUFFLE_MENU_ITEM.lock() {
+                *item_guard = Some(shuffle.clone());
+            }
+            if let Ok(mut items) = REPEAT_MENU_ITEMS.lock() {
+                *items = repeat_items;
+            }
+            if let Ok(mut item_guard) = GROUP_MENU.lock() {
+                *item_guard = Some(group_menu.clone());
+            }
+            if let Ok(mut item_guard) = TRANSFER_MENU.lock() {
+                *item_guard = Some(transfer_menu.clone());
+            }
+            if let Ok(mut item_guard) = GROUP_VOLUME_UP_MENU_ITEM.lock() {
+                *item_guard = Some(group_volume_up.clone());
+            }
+            if let Ok(mut item_guard) = GROUP_VOLUME_DOWN_MENU_ITEM.lock() {
+                *item_guard = Some(group_volume_down.clone());
+            }
+            if let Ok(mut item_guard) = GROUP_MUTE_MENU_ITEM.lock() {
+                *item_guard = Some(group_mute.clone());
+            }
             if let Ok(mut item_guard) = DISCORD_RPC_MENU_ITEM.lock() {
                 *item_guard = Some(discord_rpc_item.clone());
             }
+            if let Ok(mut item_guard) = SLEEP_TIMER_CANCEL_MENU_ITEM.lock() {
+                *item_guard = Some(sleep_timer_cancel.clone());
+            }
+            sleep_timer::on_change(Arc::new(|status| {
+                if let Ok(item_guard) = SLEEP_TIMER_CANCEL_MENU_ITEM.lock() {
+                    if let Some(ref item) = *item_guard {
+                        let _ = item.set_enabled(status.active);
+                    }
+                }
+            }));
 
             let menu = MenuBuilder::new(app)
                 .items(&[
@@ -1329,9 +2833,17 @@ pub fn run() {
                     &play_pause,
                     &prev_track,
                     &next_track,
+                    &favorite,
+                    &shuffle,
+                    &repeat_menu,
+                    &group_menu,
+                    &move_playback_here,
+                    &transfer_menu,
+                    &sleep_timer_menu,
                     &separator_playback,
                     &show,
                     &hide,
+                    &mini_player_item,
                     &switch_server,
                     &separator2,
                     &discord_rpc_item,
@@ -1358,6 +2870,7 @@ pub fn run() {
                     "quit" => {
                         app.exit(0);
                     }
+                    "mini_player" => mini_player::toggle(app),
                     "hide" => {
                         if let Some(window) = app
                             .get_webview_window("main")
@@ -1369,22 +2882,7 @@ pub fn run() {
                             let _ = window.hide();
                         }
                     }
-                    "show" => {
-                        if let Some(window) = app
-                            .get_webview_window("main")
-                            .or_else(|| app.get_webview_window("launcher"))
-                        {
-                            // Undo both hide states: an app-level hide
-                            // (unmapped) and a window-manager minimize.
-                            let _ = window.unminimize();
-                            let _ = window.show();
-                            // Restore prior position; the WM may still re-place on map.
-                            if let Some(pos) = take_hidden_window_position() {
-                                let _ = window.set_position(pos);
-                            }
-                            let _ = window.set_focus();
-                        }
-                    }
+                    "show" => show_main_window(app),
                     "switch_server" => {
                         ma_api::clear_current_session();
 
@@ -1393,8 +2891,9 @@ pub fn run() {
                         let _ = settings::set_string_setting("last_server_name", None);
 
                         // Stop Sendspin client
-                        tauri::async_runtime::spawn(async {
-                            sendspin::stop().await;
+                        let sendspin = app.state::<SendspinManager>().inner().clone();
+                        tauri::async_runtime::spawn(async move {
+                            sendspin.stop().await;
                         });
 
                         // Find the current window (could be "main" or "launcher")
@@ -1453,6 +2952,72 @@ pub fn run() {
                             );
                         }
                     }
+                    "favorite" => send_tray_command(app, sendspin::PlaybackCommand::Favorite),
+                    "shuffle" => {
+                        let enabled = now_playing::get_now_playing().shuffle != Some(true);
+                        send_tray_command(app, sendspin::PlaybackCommand::SetShuffle(enabled));
+                    }
+                    id if id.starts_with("repeat_") => {
+                        if let Some((mode, _)) =
+                            TRAY_REPEAT_MODES.into_iter().find(|(_, mode_id)| *mode_id == id)
+                        {
+                            send_tray_command(app, sendspin::PlaybackCommand::SetRepeat(mode));
+                        }
+                    }
+                    "group_volume_up" | "group_volume_down" => {
+                        if let Some(volume) = now_playing::get_now_playing().group_volume {
+                            let volume = if event.id().as_ref() == "group_volume_up" {
+                                volume.saturating_add(TRAY_GROUP_VOLUME_STEP).min(100)
+                            } else {
+                                volume.saturating_sub(TRAY_GROUP_VOLUME_STEP)
+                            };
+                            send_tray_command(app, sendspin::PlaybackCommand::SetGroupVolume(volume));
+                        }
+                    }
+                    "group_mute" => {
+                        let muted = now_playing::get_now_playing().group_muted != Some(true);
+                        send_tray_command(app, sendspin::PlaybackCommand::SetGroupMute(muted));
+                    }
+                    id if id.starts_with(GROUP_MEMBER_ID_PREFIX) => {
+                        let member_id = id.trim_start_matches(GROUP_MEMBER_ID_PREFIX).to_string();
+                        // The checkbox has already toggled itself
+                        let joined = GROUP_MEMBER_MENU_ITEMS.lock().ok().and_then(|items| {
+                            items
+                                .iter()
+                                .find(|item| item.id().as_ref() == id)
+                                .and_then(|item| item.is_checked().ok())
+                        });
+                        if let (Some(joined), Some(player_id)) = (joined, now_playing::get_now_playing().player_id) {
+                            thread::spawn(move || {
+                                if let Err(e) = ma_api::set_group_member(&player_id, &member_id, joined) {
+                                    log::warn!("[Tray] Failed to change the group: {}", e);
+                                }
+                                refresh_tray_group_members(&player_id);
+                            });
+                        }
+                    }
+                    "move_playback_here" => {
+                        if let Some(player_id) = app.state::<SendspinManager>().get_player_id() {
+                            thread::spawn(move || {
+                                if let Err(e) = ma_api::move_playback_here(&player_id, None) {
+                                    log::warn!("[Tray] Failed to move playback here: {}", e);
+                                }
+                            });
+                        }
+                    }
+                    id if id.starts_with(TRANSFER_ID_PREFIX) => {
+                        let target_id = id.trim_start_matches(TRANSFER_ID_PREFIX).to_string();
+                        let player_id = now_playing::get_now_playing()
+                            .player_id
+                            .or_else(|| app.state::<SendspinManager>().get_player_id());
+                        if let Some(player_id) = player_id {
+                            thread::spawn(move || {
+                                if let Err(e) = ma_api::send_playback_to(&player_id, &target_id) {
+                                    log::warn!("[Tray] Failed to send playback to {}: {}", target_id, e);
+                                }
+                            });
+                        }
+                    }
                     "discord_rpc" => {
                         // Toggle Discord RPC; persist through the settings so
                         // the change survives restarts, the activity is
@@ -1468,7 +3033,7 @@ pub fn run() {
                         refresh_settings_window(app);
                     }
                     "settings" => {
-                        open_settings_window(app);
+                        open_settings_window(app, None);
                     }
                     "relaunch" => {
                         tauri::process::restart(&app.env());
@@ -1489,6 +3054,21 @@ pub fn run() {
                         let handle = app.app_handle().clone();
                         tauri::async_runtime::spawn(check_for_updates(handle));
                     }
+                    "sleep_timer_cancel" => sleep_timer::cancel(),
+                    "sleep_timer_end_of_track" => {
+                        let sendspin = app.state::<SendspinManager>();
+                        if let Err(e) = sleep_timer::start(&sendspin, sleep_timer::SleepTimerMode::EndOfTrack) {
+                            log::warn!("[Tray] Sleep timer: {}", e);
+                        }
+                    }
+                    id if id.starts_with("sleep_timer_") => {
+                        if let Ok(minutes) = id.trim_start_matches("sleep_timer_").parse() {
+                            let sendspin = app.state::<SendspinManager>();
+                            if let Err(e) = sleep_timer::start(&sendspin, sleep_timer::SleepTimerMode::Minutes(minutes)) {
+                                log::warn!("[Tray] Sleep timer: {}", e);
+                            }
+                        }
+                    }
                     "now_playing" => {
                         // Click on now-playing opens the app
                         if let Some(window) = app
@@ -1502,13 +3082,12 @@ pub fn run() {
                     _ => (),
                   }
                 })
-                .on_tray_icon_event(|tray, event| {
-                    if let TrayIconEvent::Click {
+                .on_tray_icon_event(|tray, event| match event {
+                    TrayIconEvent::Click {
                         button: MouseButton::Left,
                         button_state: MouseButtonState::Up,
                         ..
-                    } = event
-                    {
+                    } => {
                         let app = tray.app_handle();
                         if let Some(window) = app
                             .get_webview_window("main")
@@ -1518,6 +3097,27 @@ pub fn run() {
                             let _ = window.set_focus();
                         }
                     }
+                    TrayIconEvent::Click {
+                        button: MouseButton::Middle,
+                        button_state: MouseButtonState::Up,
+                        ..
+                    } => {
+                        let sendspin = tray.app_handle().state::<SendspinManager>();
+                        match toggle_mute(&sendspin) {
+                            Ok(true) => flash_tray_status(i18n::tr("desktop.tray.muted")),
+                            Ok(false) => {
+                                if let Ok(volume) = sendspin.get_volume_percent() {
+                                    flash_tray_volume(volume);
+                                }
+                            }
+                            Err(e) => log::warn!("[Tray] Failed to toggle mute: {}", e),
+                        }
+                    }
+                    TrayIconEvent::Enter { .. } | TrayIconEvent::Move { .. } => {
+                        tray_scroll::set_pointer_over(true);
+                    }
+                    TrayIconEvent::Leave { .. } => tray_scroll::set_pointer_over(false),
+                    _ => {}
                 })
                 .build(app)?;
 
@@ -1526,6 +3126,16 @@ pub fn run() {
                 *tray_guard = Some(tray);
             }
 
+            // Scrolling over the icon changes the main player's volume
+            let scroll_app = app.handle().clone();
+            tray_scroll::init(Box::new(move |steps| {
+                let sendspin = scroll_app.state::<SendspinManager>();
+                match step_volume(&sendspin, steps) {
+                    Ok(volume) => flash_tray_volume(volume),
+                    Err(e) => log::debug!("[Tray] Failed to change the volume: {}", e),
+                }
+            }));
+
             // Apply initial tray visibility from settings
             if !loaded_settings.show_tray_icon {
                 set_tray_visible(false);
@@ -1557,7 +3167,7 @@ pub fn run() {
             }
             app.on_menu_event(move |app, event| {
                 match event.id().as_ref() {
-                    "app_preferences" => open_settings_window(app),
+                    "app_preferences" => open_settings_window(app, None),
                     "help_docs" => {
                         if let Err(error) = app.opener().open_url(DOCS_URL, None::<&str>) {
                             log::warn!("[App] Failed to open documentation: {error}");
@@ -1572,6 +3182,12 @@ pub fn run() {
         .build(context)
         .expect("Error while building Music Assistant companion")
         .run(|app, event| {
+            // Disconnect cleanly so the server drops the players right away
+            // instead of waiting for the connection to time out
+            if matches!(event, tauri::RunEvent::Exit) {
+                let sendspin = app.state::<SendspinManager>().inner().clone();
+                tauri::async_runtime::block_on(sendspin.stop());
+            }
             #[cfg(target_os = "macos")]
             if let tauri::RunEvent::Reopen { has_visible_windows, .. } = event {
                 if !has_visible_windows {
@@ -1584,10 +3200,6 @@ pub fn run() {
                     }
                 }
             }
-            #[cfg(not(target_os = "macos"))]
-            {
-                let _ = (app, event);
-            }
         });
 }
 
@@ -0,0 +1,166 @@
+//! App control over D-Bus on Linux.
+//!
+//! MPRIS (see `media_controls`) covers transport and volume; this service
+//! covers what it can't express, for scripts and desktop integrations:
+//! turning the built-in player on and off, switching its output device and
+//! reading its clock-sync and buffer statistics.
+//!
+//! Registers `io.musicassistant.Companion` on the session bus, with the
+//! `io.musicassistant.Companion1` interface at `/io/musicassistant/Companion`.
+//! Its properties don't emit change signals; read them again instead.
+
+use crate::sendspin::{devices, SendspinManager};
+use crate::settings;
+use std::collections::HashMap;
+use tauri::{AppHandle, Manager};
+use zbus::fdo;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{connection, interface};
+
+const BUS_NAME: &str = "io.musicassistant.Companion";
+const OBJECT_PATH: &str = "/io/musicassistant/Companion";
+
+/// Serve the interface on a background thread.
+///
+/// Best-effort: without a session bus, or with the name taken by another
+/// instance, the thread logs once and exits.
+pub fn init(app: AppHandle) {
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::error!("[DBus] Failed to create D-Bus service runtime: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = runtime.block_on(run(app)) {
+            log::info!("[DBus] Control service unavailable: {e}");
+        }
+    });
+}
+
+async fn run(app: AppHandle) -> zbus::Result<()> {
+    // Held for as long as the service runs
+    let _connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Companion { app })?
+        .build()
+        .await?;
+    log::info!("[DBus] Control service registered as {BUS_NAME}");
+    std::future::pending::<()>().await;
+    Ok(())
+}
+
+fn owned_value<'a, T>(value: T) -> OwnedValue
+where
+    T: Into<Value<'a>>,
+{
+    OwnedValue::try_from(value.into()).expect("D-Bus value should be ownable")
+}
+
+/// Statistics of one player as a D-Bus dictionary. Values that aren't known
+/// yet (before the clock has synced) are left out.
+fn stats_entry(stats: &crate::sendspin::stats::PlayerStats) -> HashMap<String, OwnedValue> {
+    let mut entry = HashMap::new();
+    let clock = stats.clock.as_ref();
+    entry.insert(
+        "synchronized".to_string(),
+        owned_value(clock.is_some_and(|clock| clock.synchronized)),
+    );
+    if let Some(rtt_us) = clock.and_then(|clock| clock.rtt_us) {
+        entry.insert("rtt_us".to_string(), owned_value(rtt_us));
+    }
+    if let Some(offset_us) = clock.and_then(|clock| clock.offset_us) {
+        entry.insert("offset_us".to_string(), owned_value(offset_us));
+    }
+    if let Some(drift_ppm) = clock.and_then(|clock| clock.drift_ppm) {
+        entry.insert("drift_ppm".to_string(), owned_value(drift_ppm));
+    }
+    let buffer = &stats.buffer;
+    for (key, value) in [
+        ("buffered_ms", buffer.buffered_ms),
+        ("target_buffer_ms", buffer.target_buffer_ms),
+        ("underruns", buffer.underruns),
+        ("underrun_fades", buffer.underrun_fades),
+        ("dropped_chunks", buffer.dropped_chunks),
+        ("chunks_received", buffer.chunks_received),
+    ] {
+        entry.insert(key.to_string(), owned_value(value));
+    }
+    entry.insert("fill_level".to_string(), owned_value(buffer.fill_level));
+    entry
+}
+
+struct Companion {
+    app: AppHandle,
+}
+
+// The `#[interface]` macro needs a `&self` receiver even on methods that
+// only read global state
+#[allow(clippy::unused_self)]
+#[interface(name = "io.musicassistant.Companion1")]
+impl Companion {
+    /// Turn the built-in player on or off, as the settings toggle does
+    async fn set_player_enabled(&self, enabled: bool) -> fdo::Result<()> {
+        crate::set_setting(self.app.clone(), "sendspin_enabled".to_string(), enabled)
+            .await
+            .map_err(fdo::Error::Failed)?;
+        crate::refresh_settings_window(&self.app);
+        Ok(())
+    }
+
+    /// Output devices as (ID, name, whether it's the system default)
+    fn list_output_devices(&self) -> fdo::Result<Vec<(String, String, bool)>> {
+        let devices = devices::list_devices().map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        Ok(devices
+            .into_iter()
+            .map(|device| (device.id, device.name, device.is_default))
+            .collect())
+    }
+
+    /// Play the built-in player on the device `device_id`, or on the system
+    /// default with an empty ID
+    fn set_output_device(&self, device_id: &str) -> fdo::Result<()> {
+        if !device_id.is_empty()
+            && !devices::list_devices()
+                .map_err(|e| fdo::Error::Failed(e.to_string()))?
+                .iter()
+                .any(|device| device.id == device_id)
+        {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "No output device {device_id}"
+            )));
+        }
+        let value = (!device_id.is_empty()).then(|| device_id.to_string());
+        crate::set_output_device(self.app.state::<SendspinManager>(), value)
+            .map_err(fdo::Error::Failed)?;
+        crate::refresh_settings_window(&self.app);
+        Ok(())
+    }
+
+    /// Clock-sync and buffer statistics of each running built-in player, by
+    /// player ID
+    fn get_sync_stats(&self) -> HashMap<String, HashMap<String, OwnedValue>> {
+        self.app
+            .state::<SendspinManager>()
+            .player_stats()
+            .iter()
+            .map(|stats| (stats.player_id.clone(), stats_entry(stats)))
+            .collect()
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn player_enabled(&self) -> bool {
+        settings::get_settings().sendspin_enabled
+    }
+
+    /// ID of the output device in use; empty for the system default
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn output_device(&self) -> String {
+        settings::get_settings().audio_device_id.unwrap_or_default()
+    }
+}
@@ -4,7 +4,9 @@
 //! `log::debug!`, `log::info!`, `log::warn!`, `log::error!`). Output lands in
 //! the rotating log file that the "Open log file" tray item reveals, so a user
 //! can attach it to a GitHub issue. Raw `println!`/`eprintln!` bypass that file
-//! and must not be used for diagnostics.
+//! and must not be used for diagnostics. In headless mode, which has no app
+//! to install the log plugin into, records go to stderr instead (see
+//! [`init_stderr`]).
 //!
 //! Verbosity is controlled by persisted settings:
 //!
@@ -15,10 +17,18 @@
 //! Toggling settings takes effect immediately (live toggle) via
 //! [`set_verbosity`], and persisted values are applied at startup so verbose
 //! logging can capture early connection/startup errors.
+//!
+//! Per-module level overrides (e.g. `sendspin::devices = trace`) take
+//! precedence over the verbosity toggles for the modules they name, so one
+//! subsystem can be traced without drowning the file in everything else.
+//! They are also live and persisted; see [`set_module_levels`]. The caps on
+//! noisy dependencies below still apply.
 
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU8, Ordering};
 
 use log::{Level, LevelFilter, Metadata};
+use parking_lot::RwLock;
 use tauri_plugin_log::{Builder, RotationStrategy, Target, TargetKind};
 
 /// Base name of the release log file (the log plugin appends ".log"). Shared by
@@ -64,6 +74,13 @@ impl LogVerbosity {
 
 static VERBOSITY: AtomicU8 = AtomicU8::new(0);
 
+/// Per-module level overrides, most specific (longest) module first.
+static MODULE_LEVELS: RwLock<Vec<(String, LevelFilter)>> = RwLock::new(Vec::new());
+
+/// Log targets of this crate's modules start with the crate name; overrides
+/// may name them relative to it.
+const CRATE_NAME: &str = env!("CARGO_CRATE_NAME");
+
 /// Resolve persisted settings into one effective logging verbosity.
 pub fn verbosity_from_settings(debug_logging: bool, trace_logging: bool) -> LogVerbosity {
     if !debug_logging {
@@ -81,7 +98,57 @@ pub fn verbosity_from_settings(debug_logging: bool, trace_logging: bool) -> LogV
 /// short-circuit before formatting their arguments.
 pub fn set_verbosity(verbosity: LogVerbosity) {
     VERBOSITY.store(verbosity.as_u8(), Ordering::SeqCst);
-    log::set_max_level(verbosity.level_filter());
+    update_max_level();
+}
+
+/// Raise the global max level to whatever the verbosity or any module
+/// override needs.
+fn update_max_level() {
+    let overrides = MODULE_LEVELS.read().iter().map(|(_, level)| *level).max();
+    let verbosity = verbosity().level_filter();
+    log::set_max_level(overrides.map_or(verbosity, |level| level.max(verbosity)));
+}
+
+/// Parse a level name (`off`, `error`, `warn`, `info`, `debug`, `trace`).
+pub fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .parse::<LevelFilter>()
+        .map_err(|_| format!("Invalid log level: {}", level))
+}
+
+/// Replace the per-module level overrides (module path to level name).
+///
+/// Modules are named by path relative to this crate (`sendspin`,
+/// `sendspin::volume_control`) or by a dependency's crate name.
+pub fn set_module_levels(levels: &BTreeMap<String, String>) -> Result<(), String> {
+    let mut parsed = levels
+        .iter()
+        .map(|(module, level)| Ok((module.clone(), parse_level(level)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    parsed.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+    *MODULE_LEVELS.write() = parsed;
+    update_max_level();
+    Ok(())
+}
+
+/// Whether a log target falls under `module` (the module itself or a child).
+fn module_matches(target: &str, module: &str) -> bool {
+    let relative = target
+        .strip_prefix(CRATE_NAME)
+        .and_then(|t| t.strip_prefix("::"));
+    [Some(target), relative].into_iter().flatten().any(|t| {
+        t.strip_prefix(module)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    })
+}
+
+/// The override for a log target, if one of its modules has one.
+fn module_level(target: &str) -> Option<LevelFilter> {
+    MODULE_LEVELS
+        .read()
+        .iter()
+        .find(|(module, _)| module_matches(target, module))
+        .map(|(_, level)| *level)
 }
 
 /// Current effective logging verbosity.
@@ -92,6 +159,9 @@ pub fn verbosity() -> LogVerbosity {
 /// Decide whether a record should be written, honoring the live verbosity toggle.
 fn should_log(metadata: &Metadata<'_>) -> bool {
     let level = metadata.level();
+    if let Some(filter) = module_level(metadata.target()) {
+        return level <= filter;
+    }
     // Info/Warn/Error are always recorded.
     if level <= Level::Info {
         return true;
@@ -132,7 +202,66 @@ pub fn build_plugin<R: tauri::Runtime>(verbosity: LogVerbosity) -> tauri::plugin
     builder.build()
 }
 
+/// Logger for headless mode, where there is no app to install the plugin
+/// into. Writes to stderr, which a service manager such as systemd collects.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        should_log(metadata) && !is_capped_dependency(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{} {} {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Same caps as `build_plugin` puts on noisy dependencies
+fn is_capped_dependency(metadata: &Metadata<'_>) -> bool {
+    metadata.level() > Level::Info
+        && ["tokio_tungstenite", "tungstenite", "ureq", "ureq_proto"]
+            .iter()
+            .any(|module| module_matches(metadata.target(), module))
+}
+
+/// Install the stderr logger used in headless mode.
+pub fn init_stderr(verbosity: LogVerbosity) {
+    if log::set_logger(&StderrLogger).is_ok() {
+        set_verbosity(verbosity);
+    }
+}
+
 /// Re-apply the persisted verbosity after the plugin has been installed.
 pub fn apply_after_install(verbosity: LogVerbosity) {
     set_verbosity(verbosity);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_matches_crate_relative_and_absolute_paths() {
+        let target = format!("{}::sendspin::devices", CRATE_NAME);
+        assert!(module_matches(&target, "sendspin"));
+        assert!(module_matches(&target, "sendspin::devices"));
+        assert!(module_matches(&target, &target));
+        assert!(!module_matches(&target, "sendspin::dev"));
+        assert!(!module_matches(&target, "settings"));
+        assert!(module_matches("tungstenite::protocol", "tungstenite"));
+    }
+
+    #[test]
+    fn invalid_levels_are_rejected() {
+        assert_eq!(parse_level("TRACE"), Ok(LevelFilter::Trace));
+        assert_eq!(parse_level("off"), Ok(LevelFilter::Off));
+        assert!(parse_level("loud").is_err());
+
+        let levels = BTreeMap::from([("sendspin".to_string(), "loud".to_string())]);
+        assert!(set_module_levels(&levels).is_err());
+    }
+}
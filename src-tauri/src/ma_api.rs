@@ -1,9 +1,13 @@
+use crate::now_playing::{QueueItem, RepeatMode};
+use serde::Serialize;
 use serde_json::{json, Value};
 use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 
 /// Keep synchronous native MA API calls short so app service threads do not stall long.
 const API_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Announcements are only answered once they have played.
+const ANNOUNCEMENT_TIMEOUT: Duration = Duration::from_secs(600);
 /// The native callers only need small metadata responses; reject unexpectedly large bodies.
 const MAX_API_RESPONSE_BYTES: u64 = 256 * 1024;
 
@@ -47,6 +51,499 @@ pub(crate) fn get_active_queue(player_id: &str) -> Result<String, String> {
     )
 }
 
+/// ID of the active queue of `player_id`
+fn active_queue_id(player_id: &str) -> Result<String, String> {
+    let queue: Value =
+        serde_json::from_str(&get_active_queue(player_id)?).map_err(|err| err.to_string())?;
+    queue
+        .get("queue_id")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("no active queue for player {}", player_id))
+}
+
+/// Seek the active queue of `player_id` to `position_ms`.
+pub(crate) fn seek_active_queue(player_id: &str, position_ms: u64) -> Result<(), String> {
+    let queue_id = active_queue_id(player_id)?;
+    post_command_raw(
+        "sendspin-seek",
+        "player_queues/seek",
+        json!({ "queue_id": queue_id, "position": position_ms / 1000 }),
+    )
+    .map(|_| ())
+}
+
+/// Seek the active queue of `player_id` by `offset_ms`, back if negative.
+pub(crate) fn skip_active_queue(player_id: &str, offset_ms: i64) -> Result<(), String> {
+    let queue_id = active_queue_id(player_id)?;
+    post_command_raw(
+        "queue-skip",
+        "player_queues/skip",
+        json!({ "queue_id": queue_id, "seconds": offset_ms / 1000 }),
+    )
+    .map(|_| ())
+}
+
+/// Shuffle the active queue of `player_id`, or play it in order again.
+pub(crate) fn set_shuffle(player_id: &str, enabled: bool) -> Result<(), String> {
+    let queue_id = active_queue_id(player_id)?;
+    post_command_raw(
+        "queue-shuffle",
+        "player_queues/shuffle",
+        json!({ "queue_id": queue_id, "shuffle_enabled": enabled }),
+    )
+    .map(|_| ())
+}
+
+/// Set what the active queue of `player_id` repeats.
+pub(crate) fn set_repeat(player_id: &str, mode: RepeatMode) -> Result<(), String> {
+    let queue_id = active_queue_id(player_id)?;
+    post_command_raw(
+        "queue-repeat",
+        "player_queues/repeat",
+        json!({ "queue_id": queue_id, "repeat_mode": mode.as_str() }),
+    )
+    .map(|_| ())
+}
+
+/// Send a transport command (`play`, `pause`, `stop`, `next`, `previous`)
+/// to `player_id`, for players whose protocol has no controls of its own.
+pub(crate) fn player_command(player_id: &str, command: &str) -> Result<(), String> {
+    post_command_raw(
+        "player-command",
+        &format!("players/cmd/{}", command),
+        json!({ "player_id": player_id }),
+    )
+    .map(|_| ())
+}
+
+/// Set the volume (0-100) of `player_id`.
+pub(crate) fn set_player_volume(player_id: &str, volume: u8) -> Result<(), String> {
+    post_command_raw(
+        "player-volume",
+        "players/cmd/volume_set",
+        json!({ "player_id": player_id, "volume_level": volume }),
+    )
+    .map(|_| ())
+}
+
+/// Mute or unmute `player_id`.
+pub(crate) fn set_player_mute(player_id: &str, muted: bool) -> Result<(), String> {
+    post_command_raw(
+        "player-mute",
+        "players/cmd/volume_mute",
+        json!({ "player_id": player_id, "muted": muted }),
+    )
+    .map(|_| ())
+}
+
+/// A player that can play in the group of another
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct GroupMember {
+    pub(crate) player_id: String,
+    pub(crate) name: String,
+    /// Whether it plays in the group now
+    pub(crate) joined: bool,
+}
+
+fn all_players() -> Result<Value, String> {
+    serde_json::from_str(&post_command_raw("players", "players/all", json!({}))?)
+        .map_err(|err| err.to_string())
+}
+
+/// The players that can join the group `player_id` plays in, other than its
+/// leader
+pub(crate) fn group_members(player_id: &str) -> Result<Vec<GroupMember>, String> {
+    Ok(parse_group_members(&all_players()?, player_id))
+}
+
+/// Add `member_id` to the group `player_id` plays in, or take it out.
+pub(crate) fn set_group_member(
+    player_id: &str,
+    member_id: &str,
+    joined: bool,
+) -> Result<(), String> {
+    if joined {
+        let leader = group_leader(&all_players()?, player_id);
+        post_command_raw(
+            "group-join",
+            "players/cmd/group",
+            json!({ "player_id": member_id, "target_player": leader }),
+        )
+    } else {
+        post_command_raw(
+            "group-leave",
+            "players/cmd/ungroup",
+            json!({ "player_id": member_id }),
+        )
+    }
+    .map(|_| ())
+}
+
+/// Set the volume (0-100) of the group `player_id` plays in.
+pub(crate) fn set_group_volume(player_id: &str, volume: u8) -> Result<(), String> {
+    let leader = group_leader(&all_players()?, player_id);
+    post_command_raw(
+        "group-volume",
+        "players/cmd/group_volume",
+        json!({ "player_id": leader, "volume_level": volume }),
+    )
+    .map(|_| ())
+}
+
+/// Mute or unmute the group `player_id` plays in.
+pub(crate) fn set_group_mute(player_id: &str, muted: bool) -> Result<(), String> {
+    let leader = group_leader(&all_players()?, player_id);
+    post_command_raw(
+        "group-mute",
+        "players/cmd/group_volume_mute",
+        json!({ "player_id": leader, "muted": muted }),
+    )
+    .map(|_| ())
+}
+
+fn find_player<'a>(players: &'a Value, player_id: &str) -> &'a Value {
+    players
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|player| player["player_id"].as_str() == Some(player_id))
+        .unwrap_or(&Value::Null)
+}
+
+fn strings(value: &Value) -> Vec<&str> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+/// Whether `player` is an available single player rather than a group
+fn is_playable(player: &Value) -> bool {
+    player["available"].as_bool() != Some(false)
+        && player["type"].as_str().is_none_or(|kind| kind == "player")
+}
+
+fn player_name(player: &Value, player_id: &str) -> String {
+    player["display_name"]
+        .as_str()
+        .or_else(|| player["name"].as_str())
+        .unwrap_or(player_id)
+        .to_string()
+}
+
+/// A player playback can be sent to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct TransferTarget {
+    pub(crate) player_id: String,
+    pub(crate) name: String,
+}
+
+/// The players `player_id` can send its playback to
+pub(crate) fn transfer_targets(player_id: &str) -> Result<Vec<TransferTarget>, String> {
+    Ok(parse_transfer_targets(&all_players()?, player_id))
+}
+
+fn parse_transfer_targets(players: &Value, player_id: &str) -> Vec<TransferTarget> {
+    players
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|player| is_playable(player))
+        .filter_map(|player| {
+            let id = player["player_id"].as_str()?;
+            (id != player_id).then(|| TransferTarget {
+                player_id: id.to_string(),
+                name: player_name(player, id),
+            })
+        })
+        .collect()
+}
+
+/// Move the queue playing on `from` (or, without it, whichever queue is
+/// playing on another player) onto `player_id` and play it there.
+pub(crate) fn move_playback_here(player_id: &str, from: Option<&str>) -> Result<(), String> {
+    let source = if let Some(from) = from {
+        active_queue_id(from)?
+    } else {
+        let queues: Value =
+            serde_json::from_str(&post_command_raw("queues", "player_queues/all", json!({}))?)
+                .map_err(|err| err.to_string())?;
+        playing_queue(&queues, player_id)
+            .ok_or("Nothing is playing on another player")?
+            .to_string()
+    };
+    transfer_queue(&source, player_id)
+}
+
+/// Move what `player_id` plays onto `target_id` and play it there.
+pub(crate) fn send_playback_to(player_id: &str, target_id: &str) -> Result<(), String> {
+    transfer_queue(&active_queue_id(player_id)?, target_id)
+}
+
+/// Move a queue to another player. The Sendspin controller role can only
+/// switch a player between groups, not move what a queue plays.
+fn transfer_queue(source_queue_id: &str, target_id: &str) -> Result<(), String> {
+    post_command_raw(
+        "queue-transfer",
+        "player_queues/transfer",
+        json!({
+            "source_queue_id": source_queue_id,
+            "target_queue_id": target_id,
+            "auto_play": true,
+        }),
+    )
+    .map(|_| ())
+}
+
+/// The ID of a queue playing on a player other than `player_id`
+fn playing_queue<'a>(queues: &'a Value, player_id: &str) -> Option<&'a str> {
+    queues
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|queue| queue["state"].as_str() == Some("playing"))
+        .filter_map(|queue| queue["queue_id"].as_str())
+        .find(|queue_id| *queue_id != player_id)
+}
+
+/// The player leading the group `player_id` plays in: the one it is synced
+/// to, or itself
+fn group_leader(players: &Value, player_id: &str) -> String {
+    find_player(players, player_id)["synced_to"]
+        .as_str()
+        .unwrap_or(player_id)
+        .to_string()
+}
+
+fn parse_group_members(players: &Value, player_id: &str) -> Vec<GroupMember> {
+    let leader_id = group_leader(players, player_id);
+    let leader = find_player(players, &leader_id);
+    // Older servers call the members `group_childs`
+    let joined = if leader["group_members"].is_array() {
+        strings(&leader["group_members"])
+    } else {
+        strings(&leader["group_childs"])
+    };
+    // Player IDs and provider instances the leader can group with; empty if
+    // the server doesn't say
+    let can_group_with = strings(&leader["can_group_with"]);
+    players
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|player| is_playable(player))
+        .filter_map(|player| {
+            let id = player["player_id"].as_str()?;
+            let groupable = can_group_with.is_empty()
+                || can_group_with.contains(&id)
+                || player["provider"]
+                    .as_str()
+                    .is_some_and(|provider| can_group_with.contains(&provider));
+            (id != leader_id && groupable).then(|| GroupMember {
+                player_id: id.to_string(),
+                name: player_name(player, id),
+                joined: joined.contains(&id)
+                    || player["synced_to"].as_str() == Some(leader_id.as_str()),
+            })
+        })
+        .collect()
+}
+
+/// Rename `player_id` in the server's player settings.
+pub(crate) fn rename_player(player_id: &str, name: &str) -> Result<(), String> {
+    post_command_raw(
+        "player-rename",
+        "config/players/save",
+        json!({ "player_id": player_id, "values": { "name": name } }),
+    )
+    .map(|_| ())
+}
+
+/// Play the media item `uri` (e.g. `library://album/12`) on the active queue
+/// of `player_id`, replacing what is queued.
+pub(crate) fn play_media(player_id: &str, uri: &str) -> Result<(), String> {
+    let queue_id = active_queue_id(player_id)?;
+    post_command_raw(
+        "deep-link-play",
+        "player_queues/play_media",
+        json!({ "queue_id": queue_id, "media": uri, "option": "replace" }),
+    )
+    .map(|_| ())
+}
+
+/// Play the audio at `url` on `player_id` as an announcement, over whatever
+/// it's playing, without the chime before it. Blocks until the announcement
+/// has played.
+pub(crate) fn play_announcement(player_id: &str, url: &str) -> Result<(), String> {
+    post_command(
+        announcement_agent(),
+        "announcement",
+        "players/cmd/play_announcement",
+        json!({ "player_id": player_id, "url": url, "pre_announce": false }),
+    )
+    .map(|_| ())
+}
+
+/// Add the track playing on `player_id` to the library's favorites. Given
+/// the `title` and `artist` the player's metadata reports, the active queue's
+/// current item is only taken if it is that track; otherwise the library is
+/// searched for it.
+pub(crate) fn favorite_playing_track(
+    player_id: &str,
+    title: Option<&str>,
+    artist: Option<&str>,
+) -> Result<(), String> {
+    let queue: Value =
+        serde_json::from_str(&get_active_queue(player_id)?).map_err(|err| err.to_string())?;
+    let current = &queue["current_item"]["media_item"];
+    let track = match title {
+        Some(title) if !is_track(current, title, artist) => {
+            let query =
+                artist.map_or_else(|| title.to_string(), |artist| format!("{artist} {title}"));
+            let results: Value = serde_json::from_str(&post_command_raw(
+                "search-track",
+                "music/search",
+                json!({ "search_query": query, "media_types": ["track"], "limit": 10 }),
+            )?)
+            .map_err(|err| err.to_string())?;
+            results["tracks"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|track| is_track(track, title, artist))
+                .cloned()
+                .ok_or_else(|| format!("{} not found in the library", title))?
+        }
+        _ => current.clone(),
+    };
+    let uri = track["uri"]
+        .as_str()
+        .ok_or_else(|| format!("no track playing on player {}", player_id))?;
+    post_command_raw(
+        "favorite-add",
+        "music/favorites/add_item",
+        json!({ "item": uri }),
+    )
+    .map(|_| ())
+}
+
+/// Whether the media item `media` is the track named `title`, by `artist` if
+/// given. Metadata joins several artists into one string, so any of the
+/// item's artists appearing in it counts.
+fn is_track(media: &Value, title: &str, artist: Option<&str>) -> bool {
+    let title_matches = media["name"]
+        .as_str()
+        .is_some_and(|name| name.trim().to_lowercase() == title.trim().to_lowercase());
+    let artist_matches = artist.is_none_or(|artist| {
+        let artist = artist.to_lowercase();
+        media["artists"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| item["name"].as_str())
+            .any(|name| artist.contains(&name.to_lowercase()))
+    });
+    title_matches && artist_matches
+}
+
+/// Integrated loudness Music Assistant measured for a track and its album,
+/// in LUFS
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Loudness {
+    pub(crate) track_lufs: Option<f64>,
+    pub(crate) album_lufs: Option<f64>,
+}
+
+/// Loudness of the item playing on the active queue of `player_id`.
+/// Unmeasured tracks come back with neither value.
+pub(crate) fn current_loudness(player_id: &str) -> Result<Loudness, String> {
+    let queue: Value =
+        serde_json::from_str(&get_active_queue(player_id)?).map_err(|err| err.to_string())?;
+    Ok(parse_loudness(&queue))
+}
+
+fn parse_loudness(queue: &Value) -> Loudness {
+    let details = &queue["current_item"]["streamdetails"];
+    Loudness {
+        track_lufs: details["loudness"].as_f64(),
+        album_lufs: details["loudness_album"].as_f64(),
+    }
+}
+
+/// The track after the one playing on the active queue of `player_id`
+pub(crate) fn next_item(player_id: &str) -> Result<Option<QueueItem>, String> {
+    let queue: Value =
+        serde_json::from_str(&get_active_queue(player_id)?).map_err(|err| err.to_string())?;
+    let base_url = current_session().map(|session| session.server_base_url);
+    Ok(parse_queue_item(&queue["next_item"], base_url.as_deref()))
+}
+
+/// Up to `limit` tracks queued after the one playing on the active queue of
+/// `player_id`, in play order
+pub(crate) fn upcoming_items(player_id: &str, limit: u32) -> Result<Vec<QueueItem>, String> {
+    let queue: Value =
+        serde_json::from_str(&get_active_queue(player_id)?).map_err(|err| err.to_string())?;
+    let queue_id = queue
+        .get("queue_id")
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("no active queue for player {}", player_id))?;
+    let offset = queue["current_index"].as_u64().map_or(0, |index| index + 1);
+    let items: Value = serde_json::from_str(&post_command_raw(
+        "queue-items",
+        "player_queues/items",
+        json!({ "queue_id": queue_id, "limit": limit, "offset": offset }),
+    )?)
+    .map_err(|err| err.to_string())?;
+    let base_url = current_session().map(|session| session.server_base_url);
+    Ok(items
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| parse_queue_item(item, base_url.as_deref()))
+        .collect())
+}
+
+/// A queue item as the app shows it. Artwork that isn't reachable from
+/// outside the server is fetched through its image proxy at `base_url`.
+fn parse_queue_item(item: &Value, base_url: Option<&str>) -> Option<QueueItem> {
+    if !item.is_object() {
+        return None;
+    }
+    let text = |value: &Value| value.as_str().map(str::to_string);
+    let media = &item["media_item"];
+    let artists: Vec<&str> = media["artists"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|artist| artist["name"].as_str())
+        .collect();
+    let image = if item["image"].is_object() {
+        &item["image"]
+    } else {
+        &media["image"]
+    };
+    let image_url = image["path"].as_str().and_then(|path| {
+        if image["remotely_accessible"].as_bool() == Some(true) {
+            return Some(path.to_string());
+        }
+        let proxy = format!("{}/imageproxy", base_url?.trim_end_matches('/'));
+        let provider = image["provider"].as_str().unwrap_or_default();
+        tauri::Url::parse_with_params(&proxy, [("path", path), ("provider", provider)])
+            .ok()
+            .map(String::from)
+    });
+    Some(QueueItem {
+        track: text(&media["name"]).or_else(|| text(&item["name"])),
+        artist: (!artists.is_empty()).then(|| artists.join(", ")),
+        album: text(&media["album"]["name"]),
+        image_url,
+        duration: item["duration"].as_f64().filter(|duration| *duration > 0.0),
+    })
+}
+
 fn api_agent() -> &'static ureq::Agent {
     static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
     AGENT.get_or_init(|| {
@@ -57,7 +554,26 @@ fn api_agent() -> &'static ureq::Agent {
     })
 }
 
+fn announcement_agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::Agent::config_builder()
+            .timeout_global(Some(ANNOUNCEMENT_TIMEOUT))
+            .build()
+            .into()
+    })
+}
+
 fn post_command_raw(message_id: &str, command: &str, args: Value) -> Result<String, String> {
+    post_command(api_agent(), message_id, command, args)
+}
+
+fn post_command(
+    agent: &ureq::Agent,
+    message_id: &str,
+    command: &str,
+    args: Value,
+) -> Result<String, String> {
     let session =
         current_session().ok_or_else(|| "no active Music Assistant session".to_string())?;
     let api_url = format!("{}/api", session.server_base_url.trim_end_matches('/'));
@@ -69,7 +585,7 @@ fn post_command_raw(message_id: &str, command: &str, args: Value) -> Result<Stri
     .to_string();
 
     let auth_header = format!("Bearer {}", session.auth_token);
-    let mut response = api_agent()
+    let mut response = agent
         .post(api_url)
         .header("Authorization", auth_header)
         .header("Content-Type", "application/json")
@@ -83,3 +599,150 @@ fn post_command_raw(message_id: &str, command: &str, args: Value) -> Result<Stri
         .read_to_string()
         .map_err(|err| err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loudness_comes_from_the_current_items_stream_details() {
+        let queue = json!({
+            "queue_id": "q1",
+            "current_item": {
+                "streamdetails": { "loudness": -9.5, "loudness_album": -11.25 }
+            }
+        });
+        assert_eq!(
+            parse_loudness(&queue),
+            Loudness {
+                track_lufs: Some(-9.5),
+                album_lufs: Some(-11.25),
+            }
+        );
+    }
+
+    #[test]
+    fn queue_items_take_their_details_from_the_media_item() {
+        let item = json!({
+            "name": "Artist One - Song",
+            "duration": 215,
+            "media_item": {
+                "name": "Song",
+                "artists": [{ "name": "Artist One" }, { "name": "Artist Two" }],
+                "album": { "name": "Album" }
+            },
+            "image": {
+                "path": "/library/cover.jpg",
+                "provider": "filesystem",
+                "remotely_accessible": false
+            }
+        });
+        let parsed = parse_queue_item(&item, Some("http://ma.local:8095/")).unwrap();
+        assert_eq!(parsed.track.as_deref(), Some("Song"));
+        assert_eq!(parsed.artist.as_deref(), Some("Artist One, Artist Two"));
+        assert_eq!(parsed.album.as_deref(), Some("Album"));
+        assert_eq!(parsed.duration, Some(215.0));
+        assert_eq!(
+            parsed.image_url.as_deref(),
+            Some("http://ma.local:8095/imageproxy?path=%2Flibrary%2Fcover.jpg&provider=filesystem")
+        );
+    }
+
+    #[test]
+    fn queue_items_keep_public_artwork_and_need_an_object() {
+        let item = json!({
+            "name": "Radio",
+            "image": { "path": "https://cdn.example/radio.png", "remotely_accessible": true }
+        });
+        let parsed = parse_queue_item(&item, None).unwrap();
+        assert_eq!(parsed.track.as_deref(), Some("Radio"));
+        assert_eq!(parsed.artist, None);
+        assert_eq!(
+            parsed.image_url.as_deref(),
+            Some("https://cdn.example/radio.png")
+        );
+        assert_eq!(parse_queue_item(&Value::Null, None), None);
+    }
+
+    #[test]
+    fn tracks_match_by_title_and_any_artist() {
+        let track = json!({
+            "name": "Song ",
+            "uri": "library://track/12",
+            "artists": [{ "name": "Artist One" }, { "name": "Artist Two" }]
+        });
+        assert!(is_track(&track, "song", Some("Artist Two")));
+        assert!(is_track(&track, "Song", Some("Artist One, Artist Two")));
+        assert!(is_track(&track, "Song", None));
+        assert!(!is_track(&track, "Song", Some("Someone Else")));
+        assert!(!is_track(&track, "Another Song", None));
+        assert!(!is_track(&Value::Null, "Song", None));
+    }
+
+    #[test]
+    fn group_members_are_the_players_that_can_join_the_leader() {
+        let players = json!([
+            {
+                "player_id": "kitchen",
+                "name": "Kitchen",
+                "provider": "sendspin",
+                "group_members": ["kitchen", "desktop"],
+                "can_group_with": ["sendspin", "garden"]
+            },
+            { "player_id": "desktop", "name": "Desktop", "provider": "sendspin", "synced_to": "kitchen" },
+            { "player_id": "garden", "display_name": "Garden", "provider": "airplay" },
+            { "player_id": "hall", "name": "Hall", "provider": "chromecast" },
+            { "player_id": "attic", "name": "Attic", "provider": "sendspin", "available": false },
+            { "player_id": "upstairs", "name": "Upstairs", "type": "group" }
+        ]);
+        assert_eq!(group_leader(&players, "desktop"), "kitchen");
+        assert_eq!(group_leader(&players, "kitchen"), "kitchen");
+        let member = |player_id: &str, name: &str, joined| GroupMember {
+            player_id: player_id.to_string(),
+            name: name.to_string(),
+            joined,
+        };
+        assert_eq!(
+            parse_group_members(&players, "desktop"),
+            vec![
+                member("desktop", "Desktop", true),
+                member("garden", "Garden", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn playback_moves_from_a_queue_playing_elsewhere() {
+        let queues = json!([
+            { "queue_id": "desktop", "state": "playing" },
+            { "queue_id": "kitchen", "state": "paused" },
+            { "queue_id": "garden", "state": "playing" }
+        ]);
+        assert_eq!(playing_queue(&queues, "desktop"), Some("garden"));
+        assert_eq!(playing_queue(&queues, "garden"), Some("desktop"));
+        assert_eq!(playing_queue(&json!([]), "desktop"), None);
+
+        let players = json!([
+            { "player_id": "desktop", "name": "Desktop" },
+            { "player_id": "garden", "display_name": "Garden" },
+            { "player_id": "upstairs", "name": "Upstairs", "type": "group" }
+        ]);
+        assert_eq!(
+            parse_transfer_targets(&players, "desktop"),
+            vec![TransferTarget {
+                player_id: "garden".to_string(),
+                name: "Garden".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn unmeasured_or_missing_items_have_no_loudness() {
+        let unmeasured = json!({ "current_item": { "streamdetails": { "loudness": null } } });
+        assert_eq!(parse_loudness(&unmeasured), Loudness::default());
+        assert_eq!(
+            parse_loudness(&json!({ "current_item": null })),
+            Loudness::default()
+        );
+    }
+}
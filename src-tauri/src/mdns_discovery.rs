@@ -16,6 +16,15 @@ fn select_advertised_url(internal_url: Option<&str>, base_url: Option<&str>) ->
         .map(ToString::to_string)
 }
 
+/// Select the server version advertised in mDNS TXT records.
+fn select_version(server_version: Option<&str>, version: Option<&str>) -> Option<String> {
+    server_version
+        .or(version)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(ToString::to_string)
+}
+
 /// Select preferred IP address from mDNS address data.
 /// Prioritizes IPv4 over IPv6 and returns None if no IP is available.
 fn select_preferred_ip(addresses: &[std::net::IpAddr]) -> Option<std::net::IpAddr> {
@@ -50,6 +59,8 @@ pub struct DiscoveredServer {
     pub url: String,
     /// Whether HTTPS is available
     pub https: bool,
+    /// Server version (from TXT record)
+    pub version: Option<String>,
 }
 
 /// Discover Music Assistant servers on the local network
@@ -89,6 +100,11 @@ pub fn discover_servers(timeout_secs: u64) -> Result<Vec<DiscoveredServer>, Stri
                     properties.get("base_url").map(|v| v.val_str()),
                 );
 
+                let version = select_version(
+                    properties.get("server_version").map(|v| v.val_str()),
+                    properties.get("version").map(|v| v.val_str()),
+                );
+
                 let port = info.get_port();
 
                 // Use the advertised MA URL for connecting, falling back to mDNS
@@ -112,6 +128,7 @@ pub fn discover_servers(timeout_secs: u64) -> Result<Vec<DiscoveredServer>, Stri
                     address,
                     url: url.clone(),
                     https,
+                    version,
                 };
 
                 // Use server_id as key if available, otherwise fullname. This helps
@@ -161,6 +178,20 @@ mod tests {
         assert_eq!(select_advertised_url(Some(""), Some("")), None);
     }
 
+    #[test]
+    fn test_select_version() {
+        assert_eq!(
+            select_version(Some("2.5.0"), Some("1.0")),
+            Some("2.5.0".to_string())
+        );
+        assert_eq!(
+            select_version(None, Some(" 2.4.1 ")),
+            Some("2.4.1".to_string())
+        );
+        assert_eq!(select_version(Some(""), None), None);
+        assert_eq!(select_version(None, None), None);
+    }
+
     #[test]
     fn test_format_ip_host() {
         assert_eq!(
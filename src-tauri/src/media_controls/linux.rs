@@ -5,8 +5,9 @@
 //! in [`super::plan`] with the macOS backend.
 
 use super::{plan, MediaControlCallback, PlaybackState};
+use crate::artwork_cache::{self, ArtworkSize};
 use crate::now_playing::NowPlaying;
-use crate::sendspin;
+use crate::sendspin::{self, SendspinManager};
 use parking_lot::Mutex;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
@@ -34,7 +35,11 @@ enum ServiceCommand {
     VolumeChanged(u8),
 }
 
-pub fn init(callback: MediaControlCallback, _hwnd_param: Option<*mut std::ffi::c_void>) {
+pub fn init(
+    callback: MediaControlCallback,
+    _hwnd_param: Option<*mut std::ffi::c_void>,
+    sendspin: SendspinManager,
+) {
     let mut tx_guard = SERVICE_TX.lock();
     if tx_guard.is_some() {
         return;
@@ -65,7 +70,7 @@ pub fn init(callback: MediaControlCallback, _hwnd_param: Option<*mut std::ffi::c
             }
         };
 
-        if let Err(e) = runtime.block_on(run_service(bus_name, callback, rx)) {
+        if let Err(e) = runtime.block_on(run_service(bus_name, callback, sendspin, rx)) {
             log::error!("[MediaControls] Linux MPRIS service stopped: {e}");
         }
     });
@@ -91,6 +96,7 @@ fn send_command(command: ServiceCommand) {
 async fn run_service(
     bus_name: String,
     callback: MediaControlCallback,
+    sendspin: SendspinManager,
     mut rx: mpsc::UnboundedReceiver<ServiceCommand>,
 ) -> zbus::Result<()> {
     let shared = SharedState::default();
@@ -102,6 +108,7 @@ async fn run_service(
             MediaPlayer2Player {
                 callback,
                 state: shared.clone(),
+                sendspin: sendspin.clone(),
             },
         )?
         .build()
@@ -118,11 +125,11 @@ async fn run_service(
         match command {
             ServiceCommand::Update(np) => {
                 shared.update(np);
-                emit_player_properties(&emitter, &player_iface, &shared).await;
+                emit_player_properties(&emitter, &player_iface, &shared, &sendspin).await;
             }
             ServiceCommand::Clear => {
                 shared.clear();
-                emit_player_properties(&emitter, &player_iface, &shared).await;
+                emit_player_properties(&emitter, &player_iface, &shared, &sendspin).await;
             }
             ServiceCommand::VolumeChanged(volume) => {
                 let changed =
@@ -139,6 +146,7 @@ async fn emit_player_properties(
     emitter: &SignalEmitter<'static>,
     interface: &InterfaceName<'static>,
     state: &SharedState,
+    sendspin: &SendspinManager,
 ) {
     let snapshot = state.snapshot();
     let mut changed: HashMap<&str, Value<'_>> = HashMap::new();
@@ -148,10 +156,11 @@ async fn emit_player_properties(
     changed.insert("CanPause", Value::from(snapshot.can_pause));
     changed.insert("CanGoNext", Value::from(snapshot.can_next));
     changed.insert("CanGoPrevious", Value::from(snapshot.can_previous));
-    changed.insert("Volume", Value::from(current_mpris_volume()));
+    changed.insert("CanSeek", Value::from(snapshot.can_seek()));
+    changed.insert("Volume", Value::from(current_mpris_volume(sendspin)));
 
     // `Position` is omitted on purpose: the spec says clients should track it
-    // via the Seeked signal, and CanSeek is false here.
+    // via the Seeked signal.
     emit_properties_changed(emitter, interface, changed).await;
 }
 
@@ -181,7 +190,9 @@ impl SharedState {
     }
 
     fn update(&self, np: NowPlaying) {
-        *self.0.lock() = MprisState::from_now_playing(&np);
+        let mut state = MprisState::from_now_playing(&np);
+        state.art_url = state.art_url.map(local_art_url);
+        *self.0.lock() = state;
     }
 
     fn clear(&self) {
@@ -278,6 +289,22 @@ impl MprisState {
     fn position_us(&self) -> i64 {
         self.position_us
     }
+
+    /// Seeking needs a track of known length
+    fn can_seek(&self) -> bool {
+        self.length_us.is_some()
+    }
+}
+
+/// Prefer the locally cached artwork so shells reading `mpris:artUrl` don't
+/// hit the server on every metadata change; until the cache has the image,
+/// keep the remote URL and fetch it in the background.
+fn local_art_url(remote: String) -> String {
+    if let Some(path) = artwork_cache::cached_path(&remote, ArtworkSize::MediaControls) {
+        return format!("file://{}", path.display());
+    }
+    artwork_cache::prefetch(&remote, ArtworkSize::MediaControls);
+    remote
 }
 
 fn track_id_path(track: Option<&str>, artist: Option<&str>, album: Option<&str>) -> String {
@@ -317,8 +344,10 @@ fn percent_to_mpris_volume(volume: u8) -> f64 {
 
 /// Current player volume as an MPRIS `Volume` value. Falls back to full
 /// volume when the sendspin client is not connected or hasn't reported yet.
-fn current_mpris_volume() -> f64 {
-    sendspin::get_volume_percent().map_or(1.0, percent_to_mpris_volume)
+fn current_mpris_volume(sendspin: &SendspinManager) -> f64 {
+    sendspin
+        .get_volume_percent()
+        .map_or(1.0, percent_to_mpris_volume)
 }
 
 fn owned_value<'a, T>(value: T) -> OwnedValue
@@ -395,6 +424,7 @@ impl MediaPlayer2Root {
 struct MediaPlayer2Player {
     callback: MediaControlCallback,
     state: SharedState,
+    sendspin: SendspinManager,
 }
 
 // See the note on `MediaPlayer2Root`: the macro dictates these signatures, so
@@ -426,7 +456,10 @@ impl MediaPlayer2Player {
         self.command("play");
     }
 
-    fn seek(&self, offset: i64) {}
+    fn seek(&self, offset: i64) {
+        // `offset` is in microseconds
+        crate::seek_now_playing_by(&self.sendspin, offset / 1000);
+    }
 
     fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {}
 
@@ -468,14 +501,17 @@ impl MediaPlayer2Player {
 
     #[zbus(property)]
     fn volume(&self) -> f64 {
-        current_mpris_volume()
+        current_mpris_volume(&self.sendspin)
     }
 
     #[zbus(property)]
     fn set_volume(&self, volume: f64) {
         // Fire-and-forget: the applied value flows back through the sendspin
         // volume listener, which re-emits the `Volume` property.
-        if let Err(e) = sendspin::set_volume_percent(mpris_volume_to_percent(volume)) {
+        if let Err(e) = self
+            .sendspin
+            .set_volume_percent(mpris_volume_to_percent(volume))
+        {
             log::warn!("[MediaControls] Failed to set Linux MPRIS volume: {e}");
         }
     }
@@ -517,7 +553,7 @@ impl MediaPlayer2Player {
 
     #[zbus(property)]
     fn can_seek(&self) -> bool {
-        false
+        self.state.snapshot().can_seek()
     }
 
     #[zbus(property)]
@@ -541,6 +577,7 @@ mod tests {
         let state = MprisState::from_now_playing(&NowPlaying::default());
         assert_eq!(state.playback_status(), "Stopped");
         assert_eq!(state.position_us(), 0);
+        assert!(!state.can_seek());
         // With no current track, MPRIS Metadata must be empty (no trackid).
         assert!(state.metadata().is_empty());
     }
@@ -574,6 +611,7 @@ mod tests {
         assert!(metadata.contains_key("mpris:length"));
         assert!(state.can_next);
         assert!(state.can_previous);
+        assert!(state.can_seek());
     }
 
     #[test]
@@ -10,6 +10,7 @@
 #![allow(unsafe_code)] // objc2 framework methods are all `unsafe`; lift the workspace deny.
 
 use super::{MainThreadDispatch, MediaControlCallback, NowPlayingPlan, PlaybackState};
+use crate::artwork_cache::{self, ArtworkSize};
 use crate::now_playing::NowPlaying;
 use block2::RcBlock;
 use objc2::rc::Retained;
@@ -29,11 +30,8 @@ use parking_lot::Mutex;
 use std::ptr::NonNull;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
 
 const ARTWORK_SIZE: f64 = 512.0;
-const COVER_TIMEOUT: Duration = Duration::from_secs(10);
-const MAX_COVER_BYTES: u64 = 8 * 1024 * 1024;
 
 static CALLBACK: Mutex<Option<MediaControlCallback>> = Mutex::new(None);
 static DISPATCH: Mutex<Option<MainThreadDispatch>> = Mutex::new(None);
@@ -121,18 +119,8 @@ fn refresh_cover_if_changed(plan: &NowPlayingPlan) {
     });
 }
 
-fn download_image(url: &str) -> Result<Vec<u8>, ureq::Error> {
-    let config = ureq::Agent::config_builder()
-        .timeout_global(Some(COVER_TIMEOUT))
-        .build();
-    let agent = ureq::Agent::new_with_config(config);
-    let mut response = agent.get(url).call()?;
-    let bytes = response
-        .body_mut()
-        .with_config()
-        .limit(MAX_COVER_BYTES)
-        .read_to_vec()?;
-    Ok(bytes)
+fn download_image(url: &str) -> Result<Vec<u8>, String> {
+    artwork_cache::fetch_bytes(url, ArtworkSize::MediaControls)
 }
 
 fn dispatch_render() {
@@ -10,6 +10,7 @@
 //! trait object is needed (only one backend is ever compiled in).
 
 use crate::now_playing::NowPlaying;
+use crate::sendspin::SendspinManager;
 use std::sync::Arc;
 
 #[cfg(target_os = "linux")]
@@ -20,7 +21,7 @@ mod macos;
 mod windows;
 
 /// Callback type for media control events (`"play"`, `"pause"`, `"toggle"`,
-/// `"next"`, `"previous"`, `"stop"`).
+/// `"next"`, `"previous"`, `"stop"`, `"seek_forward"`, `"seek_backward"`).
 pub type MediaControlCallback = Arc<dyn Fn(&str) + Send + Sync>;
 
 /// Runs a closure on the platform UI / main thread.
@@ -30,15 +31,17 @@ pub type MediaControlCallback = Arc<dyn Fn(&str) + Send + Sync>;
 /// to keep System Media Transport Controls calls on the Tauri window thread.
 pub type MainThreadDispatch = Arc<dyn Fn(Box<dyn FnOnce() + Send + 'static>) + Send + Sync>;
 
-/// `hwnd` is used only on Windows; `dispatch` is used by macOS and Windows.
+/// `hwnd` is used only on Windows; `dispatch` is used by macOS and Windows;
+/// `sendspin` backs the volume control exposed over MPRIS on Linux.
 #[allow(unused_variables)]
 pub fn init(
     callback: MediaControlCallback,
     hwnd: Option<*mut std::ffi::c_void>,
     dispatch: MainThreadDispatch,
+    sendspin: SendspinManager,
 ) {
     #[cfg(target_os = "linux")]
-    linux::init(callback, hwnd);
+    linux::init(callback, hwnd, sendspin);
     #[cfg(target_os = "macos")]
     macos::init(callback, dispatch);
     #[cfg(target_os = "windows")]
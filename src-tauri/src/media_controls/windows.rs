@@ -2,6 +2,7 @@
 #![allow(unsafe_code)] // `GetForWindow` is a WinRT interop call.
 
 use super::{MainThreadDispatch, MediaControlCallback, NowPlayingPlan, PlaybackState};
+use crate::artwork_cache::{self, ArtworkSize};
 use crate::now_playing::NowPlaying;
 use parking_lot::Mutex;
 use std::ffi::c_void;
@@ -15,7 +16,9 @@ use windows::Media::{
     SystemMediaTransportControlsButton, SystemMediaTransportControlsButtonPressedEventArgs,
     SystemMediaTransportControlsDisplayUpdater, SystemMediaTransportControlsTimelineProperties,
 };
-use windows::Storage::Streams::RandomAccessStreamReference;
+use windows::Storage::Streams::{
+    DataWriter, InMemoryRandomAccessStream, RandomAccessStreamReference,
+};
 use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, RPC_E_CHANGED_MODE, WPARAM};
 use windows::Win32::Graphics::Gdi::{
     CreateBitmap, DeleteObject, GetSysColor, COLOR_BTNTEXT, HGDIOBJ,
@@ -209,6 +212,10 @@ impl WindowsMediaControls {
             .SetIsPauseEnabled(np.can_pause || np.is_playing)?;
         self.controls.SetIsNextEnabled(np.can_next)?;
         self.controls.SetIsPreviousEnabled(np.can_previous)?;
+        // Skipping within the track needs one with a known length
+        self.controls
+            .SetIsFastForwardEnabled(np.duration.is_some())?;
+        self.controls.SetIsRewindEnabled(np.duration.is_some())?;
         if let Some(thumbbar) = &mut self.thumbbar {
             if let Err(e) = thumbbar.update(np) {
                 log::warn!(
@@ -267,9 +274,19 @@ impl WindowsMediaControls {
             return;
         };
 
-        let result = Uri::CreateUri(&HSTRING::from(url))
-            .and_then(|uri| RandomAccessStreamReference::CreateFromUri(&uri))
-            .and_then(|stream| self.display_updater.SetThumbnail(&stream));
+        let result = if artwork_cache::is_pushed(url) {
+            // Pushed artwork only exists in the cache; hand SMTC the bytes
+            let Some(bytes) = artwork_cache::cached_path(url, ArtworkSize::MediaControls)
+                .and_then(|path| std::fs::read(path).ok())
+            else {
+                return;
+            };
+            stream_reference(&bytes)
+        } else {
+            Uri::CreateUri(&HSTRING::from(url))
+                .and_then(|uri| RandomAccessStreamReference::CreateFromUri(&uri))
+        };
+        let result = result.and_then(|stream| self.display_updater.SetThumbnail(&stream));
         if let Err(e) = result {
             log::warn!("[MediaControls] Failed to set Windows SMTC thumbnail for {url}: {e:?}");
         }
@@ -293,6 +310,16 @@ impl WindowsMediaControls {
     }
 }
 
+fn stream_reference(bytes: &[u8]) -> windows::core::Result<RandomAccessStreamReference> {
+    let stream = InMemoryRandomAccessStream::new()?;
+    let writer = DataWriter::CreateDataWriter(&stream)?;
+    writer.WriteBytes(bytes)?;
+    writer.StoreAsync()?.get()?;
+    writer.DetachStream()?;
+    stream.Seek(0)?;
+    RandomAccessStreamReference::CreateFromStream(&stream)
+}
+
 impl Drop for WindowsMediaControls {
     fn drop(&mut self) {
         let _ = self.controls.RemoveButtonPressed(self.button_token);
@@ -585,6 +612,10 @@ fn command_for_button(button: SystemMediaTransportControlsButton) -> Option<&'st
         Some("next")
     } else if button == SystemMediaTransportControlsButton::Previous {
         Some("previous")
+    } else if button == SystemMediaTransportControlsButton::FastForward {
+        Some("seek_forward")
+    } else if button == SystemMediaTransportControlsButton::Rewind {
+        Some("seek_backward")
     } else {
         None
     }
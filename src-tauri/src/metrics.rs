@@ -0,0 +1,211 @@
+//! Prometheus metrics
+//!
+//! Renders the statistics of the built-in players (see
+//! [`crate::sendspin::stats`]) in the Prometheus text exposition format, for
+//! the control API's `GET /metrics`. Every series carries a `player_id`
+//! label; durations are in seconds, as Prometheus expects.
+
+use crate::sendspin::stats::PlayerStats;
+use std::fmt::Write;
+
+/// Prefix of every metric name
+const PREFIX: &str = "music_assistant_companion";
+
+/// Content type of [`render`]'s output
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+struct Metric {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    /// The player's value, if it has one yet
+    value: fn(&PlayerStats) -> Option<f64>,
+}
+
+/// Clock stats in microseconds as seconds
+fn micros(us: i64) -> f64 {
+    us as f64 / 1_000_000.0
+}
+
+const METRICS: &[Metric] = &[
+    Metric {
+        name: "underruns_total",
+        kind: "counter",
+        help: "Times the buffer ran dry mid-stream",
+        value: |s| Some(s.buffer.underruns as f64),
+    },
+    Metric {
+        name: "underrun_fades_total",
+        kind: "counter",
+        help: "Times playback faded out because the buffer was about to run dry",
+        value: |s| Some(s.buffer.underrun_fades as f64),
+    },
+    Metric {
+        name: "reconnects_total",
+        kind: "counter",
+        help: "Times the connection to the server was lost and retried",
+        value: |s| Some(s.reconnects as f64),
+    },
+    Metric {
+        name: "dropped_chunks_total",
+        kind: "counter",
+        help: "Audio chunks discarded instead of played",
+        value: |s| Some(s.buffer.dropped_chunks as f64),
+    },
+    Metric {
+        name: "chunks_received_total",
+        kind: "counter",
+        help: "Audio chunks received from the server",
+        value: |s| Some(s.buffer.chunks_received as f64),
+    },
+    Metric {
+        name: "received_bytes_total",
+        kind: "counter",
+        help: "Audio bytes received from the server",
+        value: |s| Some(s.buffer.bytes_received as f64),
+    },
+    Metric {
+        name: "streams_started_total",
+        kind: "counter",
+        help: "Streams the server started",
+        value: |s| Some(s.buffer.streams_started as f64),
+    },
+    Metric {
+        name: "decode_seconds_avg",
+        kind: "gauge",
+        help: "Average time to decode and resample a chunk",
+        value: |s| Some(s.decode.avg_us as f64 / 1_000_000.0),
+    },
+    Metric {
+        name: "decode_seconds_max",
+        kind: "gauge",
+        help: "Longest time to decode and resample a chunk",
+        value: |s| Some(s.decode.max_us as f64 / 1_000_000.0),
+    },
+    Metric {
+        name: "buffer_seconds",
+        kind: "gauge",
+        help: "Audio buffered and waiting to play",
+        value: |s| Some(s.buffer.buffered_ms as f64 / 1000.0),
+    },
+    Metric {
+        name: "target_buffer_seconds",
+        kind: "gauge",
+        help: "Audio the server is asked to keep buffered",
+        value: |s| Some(s.buffer.target_buffer_ms as f64 / 1000.0),
+    },
+    Metric {
+        name: "buffer_fill_ratio",
+        kind: "gauge",
+        help: "Buffered audio as a fraction of the buffer capacity",
+        value: |s| Some(s.buffer.fill_level),
+    },
+    Metric {
+        name: "clock_synchronized",
+        kind: "gauge",
+        help: "Whether the clock is synchronized with the server (1) or not (0)",
+        value: |s| Some(f64::from(u8::from(s.clock.as_ref()?.synchronized))),
+    },
+    Metric {
+        name: "clock_offset_seconds",
+        kind: "gauge",
+        help: "Estimated offset of the server clock from the local clock",
+        value: |s| s.clock.as_ref()?.offset_us.map(micros),
+    },
+    Metric {
+        name: "clock_rtt_seconds",
+        kind: "gauge",
+        help: "Round-trip time of the last clock sync exchange",
+        value: |s| s.clock.as_ref()?.rtt_us.map(micros),
+    },
+    Metric {
+        name: "clock_drift_ppm",
+        kind: "gauge",
+        help: "Estimated drift of the server clock from the local clock",
+        value: |s| s.clock.as_ref()?.drift_ppm,
+    },
+];
+
+/// Escape a label value: backslashes, quotes and newlines
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The metrics of `players` in the text exposition format
+pub fn render(players: &[PlayerStats]) -> String {
+    let mut out = String::new();
+    for metric in METRICS {
+        let _ = writeln!(out, "# HELP {}_{} {}", PREFIX, metric.name, metric.help);
+        let _ = writeln!(out, "# TYPE {}_{} {}", PREFIX, metric.name, metric.kind);
+        for player in players {
+            if let Some(value) = (metric.value)(player).filter(|v| v.is_finite()) {
+                let _ = writeln!(
+                    out,
+                    "{}_{}{{player_id=\"{}\"}} {}",
+                    PREFIX,
+                    metric.name,
+                    escape(&player.player_id),
+                    value
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sendspin::stats::{BufferStats, ClockStats, DecodeStats};
+
+    fn stats(player_id: &str, clock: Option<ClockStats>) -> PlayerStats {
+        PlayerStats {
+            player_id: player_id.to_string(),
+            reconnects: 2,
+            clock,
+            buffer: BufferStats {
+                buffered_ms: 1500,
+                underruns: 3,
+                ..BufferStats::default()
+            },
+            decode: DecodeStats {
+                avg_us: 250,
+                max_us: 1200,
+            },
+        }
+    }
+
+    #[test]
+    fn renders_one_series_per_player() {
+        let clock = ClockStats {
+            synchronized: true,
+            rtt_us: Some(4000),
+            offset_us: Some(-2500),
+            drift_ppm: None,
+        };
+        let out = render(&[stats("main", Some(clock)), stats("kitchen", None)]);
+        assert!(out.contains("# TYPE music_assistant_companion_underruns_total counter\n"));
+        assert!(out.contains("music_assistant_companion_underruns_total{player_id=\"main\"} 3\n"));
+        assert!(
+            out.contains("music_assistant_companion_reconnects_total{player_id=\"kitchen\"} 2\n")
+        );
+        assert!(out.contains("music_assistant_companion_buffer_seconds{player_id=\"main\"} 1.5\n"));
+        assert!(out.contains(
+            "music_assistant_companion_decode_seconds_avg{player_id=\"main\"} 0.00025\n"
+        ));
+        assert!(out.contains(
+            "music_assistant_companion_clock_offset_seconds{player_id=\"main\"} -0.0025\n"
+        ));
+        // Without a clock sync, or a value, there's no sample
+        assert!(!out.contains("clock_offset_seconds{player_id=\"kitchen\"}"));
+        assert!(!out.contains("clock_drift_ppm{"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
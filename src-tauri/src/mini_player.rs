@@ -0,0 +1,57 @@
+//! Mini player
+//!
+//! A compact always-on-top window with the current track's artwork and
+//! title, transport buttons, this computer's player volume (and its group's,
+//! while it plays in one) and its output level meters. It is opened and closed from the tray or a global shortcut;
+//! its page polls [`state`], acts through the `mini_player_*` commands and
+//! subscribes to the output level events.
+
+use crate::i18n;
+use crate::now_playing::{self, NowPlaying};
+use crate::sendspin::SendspinManager;
+use serde::Serialize;
+use tauri::Manager;
+
+/// Window label
+pub const LABEL: &str = "mini-player";
+
+/// What the mini player shows
+#[derive(Debug, Clone, Serialize)]
+pub struct MiniPlayerState {
+    pub now_playing: NowPlaying,
+    /// Volume of the built-in player; `None` while it isn't connected
+    pub volume: Option<u8>,
+    pub muted: bool,
+}
+
+pub fn state(sendspin: &SendspinManager) -> MiniPlayerState {
+    MiniPlayerState {
+        now_playing: now_playing::get_now_playing(),
+        volume: sendspin.get_volume_percent().ok(),
+        muted: crate::settings::get_settings().muted,
+    }
+}
+
+/// Open the mini player, or close it if it is already open
+pub fn toggle(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_webview_window(LABEL) {
+        let _ = window.close();
+        return;
+    }
+    let result = tauri::WebviewWindowBuilder::new(
+        app,
+        LABEL,
+        tauri::WebviewUrl::App("mini-player.html".into()),
+    )
+    .title(i18n::tr("desktop.mini_player.title"))
+    .inner_size(360.0, 112.0)
+    .resizable(false)
+    .maximizable(false)
+    .minimizable(false)
+    .always_on_top(true)
+    .skip_taskbar(true)
+    .build();
+    if let Err(e) = result {
+        log::warn!("[MiniPlayer] Failed to open: {}", e);
+    }
+}
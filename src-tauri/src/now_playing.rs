@@ -1,91 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex, RwLock};
 
-/// Windows power-management integration for active playback.
-///
-/// `SetThreadExecutionState` is thread-scoped, so playback state changes are
-/// forwarded to a dedicated worker that owns the execution-state assertion.
-#[cfg(target_os = "windows")]
-#[allow(unsafe_code)]
-mod power_management {
-    use super::{get_now_playing, on_now_playing_change};
-    use std::sync::{mpsc, Arc, Once};
-    use std::thread;
-    use windows::Win32::System::Power::{
-        SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED, EXECUTION_STATE,
-    };
-
-    static START: Once = Once::new();
-
-    const ACTIVE_STATE: EXECUTION_STATE = EXECUTION_STATE(ES_CONTINUOUS.0 | ES_SYSTEM_REQUIRED.0);
-    const INACTIVE_STATE: EXECUTION_STATE = EXECUTION_STATE(ES_CONTINUOUS.0);
-
-    pub(super) fn init() {
-        START.call_once(|| {
-            let (tx, rx) = mpsc::channel::<()>();
-            thread::spawn(move || run_worker(rx));
-
-            let callback_tx = tx.clone();
-            on_now_playing_change(Arc::new(move |_now_playing| {
-                let _ = callback_tx.send(());
-            }));
-
-            // Playback may have started before desktop services were initialized.
-            let _ = tx.send(());
-        });
-    }
-
-    fn run_worker(rx: mpsc::Receiver<()>) {
-        let mut active = false;
-
-        while rx.recv().is_ok() {
-            // Coalesce metadata/progress updates; only the latest playback state
-            // matters to the power assertion.
-            while rx.try_recv().is_ok() {}
-            let should_prevent_sleep = get_now_playing().is_playing;
-
-            if should_prevent_sleep == active {
-                continue;
-            }
-
-            let state = if should_prevent_sleep {
-                ACTIVE_STATE
-            } else {
-                INACTIVE_STATE
-            };
-            let previous_state = unsafe { SetThreadExecutionState(state) };
-            if previous_state == EXECUTION_STATE(0) {
-                log::warn!(
-                    "[PowerManagement] Failed to {} Windows sleep prevention",
-                    if should_prevent_sleep {
-                        "enable"
-                    } else {
-                        "disable"
-                    }
-                );
-                continue;
-            }
-
-            active = should_prevent_sleep;
-            log::debug!(
-                "[PowerManagement] Windows sleep prevention {}",
-                if active { "enabled" } else { "disabled" }
-            );
-        }
-
-        // Release the assertion if the worker is ever shut down cleanly.
-        if active {
-            let _ = unsafe { SetThreadExecutionState(INACTIVE_STATE) };
-        }
-    }
-}
-
-/// Start the platform-specific playback power-management integration.
-pub fn init_power_management() {
-    #[cfg(target_os = "windows")]
-    power_management::init();
-}
-
 /// Current now-playing information
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NowPlaying {
@@ -107,6 +22,24 @@ pub struct NowPlaying {
     pub duration: Option<f64>,
     /// Elapsed time in seconds
     pub elapsed: Option<f64>,
+    /// Track queued to play next, if known
+    #[serde(default)]
+    pub next: Option<QueueItem>,
+    /// Whether the queue is shuffled, if known
+    #[serde(default)]
+    pub shuffle: Option<bool>,
+    /// Repeat mode of the queue, if known
+    #[serde(default)]
+    pub repeat: Option<RepeatMode>,
+    /// Name of the group the player plays in, if known
+    #[serde(default)]
+    pub group_name: Option<String>,
+    /// Volume of the whole group (0-100), if known
+    #[serde(default)]
+    pub group_volume: Option<u8>,
+    /// Whether the whole group is muted, if known
+    #[serde(default)]
+    pub group_muted: Option<bool>,
     /// Whether play action is available
     #[serde(default)]
     pub can_play: bool,
@@ -121,6 +54,52 @@ pub struct NowPlaying {
     pub can_previous: bool,
 }
 
+/// What the player's queue repeats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    Off,
+    /// The current track
+    One,
+    /// The whole queue
+    All,
+}
+
+impl RepeatMode {
+    /// Name as Music Assistant and the Sendspin protocol use it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::One => "one",
+            Self::All => "all",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "off" => Some(Self::Off),
+            "one" => Some(Self::One),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+/// A track on the player's queue
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueueItem {
+    /// Track name
+    pub track: Option<String>,
+    /// Artist name
+    pub artist: Option<String>,
+    /// Album name
+    pub album: Option<String>,
+    /// Image URL
+    pub image_url: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+}
+
 /// Callback type for now-playing updates
 pub type NowPlayingCallback = Arc<dyn Fn(&NowPlaying) + Send + Sync>;
 
@@ -135,18 +114,40 @@ static NOW_PLAYING: RwLock<NowPlaying> = RwLock::new(NowPlaying {
     player_id: None,
     duration: None,
     elapsed: None,
+    next: None,
+    shuffle: None,
+    repeat: None,
+    group_name: None,
+    group_volume: None,
+    group_muted: None,
     can_play: false,
     can_pause: false,
     can_next: false,
     can_previous: false,
 });
 
+/// Reports where playback of a published state is now, so it can be read
+/// between updates without the elapsed time standing still
+pub type PositionSource = Arc<dyn Fn() -> Option<f64> + Send + Sync>;
+
+/// Position source of the state in `NOW_PLAYING`, if it came with one;
+/// written under its lock
+static POSITION: RwLock<Option<PositionSource>> = RwLock::new(None);
+
 /// Callbacks to notify when now-playing changes
 static CALLBACKS: Mutex<Vec<NowPlayingCallback>> = Mutex::new(Vec::new());
 
-/// Get the current now-playing state
+/// Get the current now-playing state. A state published with a position
+/// source reports its elapsed time as of now.
 pub fn get_now_playing() -> NowPlaying {
-    NOW_PLAYING.read().unwrap().clone()
+    let (mut state, position) = {
+        let state = NOW_PLAYING.read().unwrap();
+        (state.clone(), POSITION.read().unwrap().clone())
+    };
+    if let Some(position) = position {
+        state.elapsed = valid_seconds(position());
+    }
+    state
 }
 
 /// Register a callback to be notified when now-playing changes
@@ -168,6 +169,16 @@ fn sanitize_now_playing(mut now_playing: NowPlaying) -> NowPlaying {
 
 /// Update the now-playing state (called from frontend via Tauri command)
 pub fn update_now_playing(now_playing: NowPlaying) {
+    publish(now_playing, None);
+}
+
+/// Update the now-playing state, reading its elapsed time from `position`
+/// until the next update
+pub fn update_now_playing_with_position(now_playing: NowPlaying, position: PositionSource) {
+    publish(now_playing, Some(position));
+}
+
+fn publish(now_playing: NowPlaying, position: Option<PositionSource>) {
     let now_playing = sanitize_now_playing(now_playing);
 
     // Skip updates where playback is active but track info is missing (race condition)
@@ -179,6 +190,7 @@ pub fn update_now_playing(now_playing: NowPlaying) {
     // Update global state
     if let Ok(mut state) = NOW_PLAYING.write() {
         *state = now_playing.clone();
+        *POSITION.write().unwrap() = position;
     }
 
     // Notify all callbacks (tray tooltip, Discord RPC, etc.)
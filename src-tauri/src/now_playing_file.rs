@@ -0,0 +1,187 @@
+//! Now-playing file export
+//!
+//! Keeps a file the user picked up to date with the current track, for OBS
+//! text sources and streaming overlays that read from disk. The file holds
+//! one line of text ("Artist - Track", empty while nothing plays) or JSON
+//! with the track's details and `artwork_path`, the cover art's file in the
+//! artwork cache, for an image source.
+//!
+//! The file is only rewritten when its contents change, and replaced in one
+//! step (written next to it, then renamed over it) so a reader never sees
+//! half of it.
+
+use crate::artwork_cache::{self, ArtworkSize};
+use crate::now_playing::{self, NowPlaying};
+use crate::settings::{self, NowPlayingFileFormat};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Queue of the writer thread, started on first use
+static QUEUE: Mutex<Option<Sender<NowPlaying>>> = Mutex::new(None);
+
+/// Write the file on every now-playing change from here on
+pub fn init() {
+    now_playing::on_now_playing_change(Arc::new(|now_playing| {
+        send(now_playing.clone());
+    }));
+    refresh();
+}
+
+/// Write the file again, for when its settings changed
+pub fn refresh() {
+    send(now_playing::get_now_playing());
+}
+
+fn send(now_playing: NowPlaying) {
+    if let Ok(mut queue) = QUEUE.lock() {
+        let queue = queue.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || run(&rx));
+            tx
+        });
+        let _ = queue.send(now_playing);
+    }
+}
+
+fn run(rx: &Receiver<NowPlaying>) {
+    // What was last written where, to skip rewriting the same contents
+    let mut written: Option<(PathBuf, String)> = None;
+    // Cached artwork of the last image URL, so a failed download isn't
+    // retried on every update of the same track
+    let mut artwork: Option<(String, Option<PathBuf>)> = None;
+
+    while let Ok(mut now_playing) = rx.recv() {
+        // Only the latest state matters
+        while let Ok(newer) = rx.try_recv() {
+            now_playing = newer;
+        }
+        let settings = settings::get_settings();
+        let Some(path) = settings.now_playing_file_path.map(PathBuf::from) else {
+            written = None;
+            continue;
+        };
+
+        let contents = match settings.now_playing_file_format {
+            NowPlayingFileFormat::Text => text(&now_playing),
+            NowPlayingFileFormat::Json => {
+                let artwork_path = now_playing.image_url.as_deref().and_then(|url| {
+                    if artwork.as_ref().is_none_or(|(cached, _)| cached != url) {
+                        let path = artwork_cache::fetch(url, ArtworkSize::MediaControls)
+                            .map_err(|e| log::debug!("[NowPlayingFile] No artwork: {}", e))
+                            .ok();
+                        artwork = Some((url.to_string(), path));
+                    }
+                    artwork.as_ref().and_then(|(_, path)| path.clone())
+                });
+                json_contents(&now_playing, artwork_path.as_deref())
+            }
+        };
+        if written
+            .as_ref()
+            .is_some_and(|(last_path, last)| *last_path == path && *last == contents)
+        {
+            continue;
+        }
+        match write_file(&path, &contents) {
+            Ok(()) => written = Some((path, contents)),
+            Err(e) => {
+                log::warn!("[NowPlayingFile] {}", e);
+                written = None;
+            }
+        }
+    }
+}
+
+/// "Artist - Track", just the track without an artist, or nothing while
+/// nothing plays
+fn text(now_playing: &NowPlaying) -> String {
+    if !now_playing.is_playing {
+        return String::new();
+    }
+    match (&now_playing.artist, &now_playing.track) {
+        (Some(artist), Some(track)) => format!("{} - {}", artist, track),
+        (None, Some(track)) => track.clone(),
+        _ => String::new(),
+    }
+}
+
+/// The track's details as JSON. Elapsed time is left out: it's stale as
+/// soon as it's written, and would have the file rewritten constantly.
+fn json_contents(now_playing: &NowPlaying, artwork_path: Option<&Path>) -> String {
+    let value = json!({
+        "is_playing": now_playing.is_playing,
+        "track": now_playing.track,
+        "artist": now_playing.artist,
+        "album": now_playing.album,
+        "duration": now_playing.duration,
+        "player_name": now_playing.player_name,
+        "image_url": now_playing.image_url,
+        "artwork_path": artwork_path.map(|path| path.to_string_lossy()),
+    });
+    serde_json::to_string_pretty(&value).unwrap_or_default()
+}
+
+/// Replace `path` with `contents` in one step
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let result = fs::write(&temp_path, contents).and_then(|()| fs::rename(&temp_path, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(artist: Option<&str>) -> NowPlaying {
+        NowPlaying {
+            is_playing: true,
+            track: Some("Track".to_string()),
+            artist: artist.map(ToString::to_string),
+            ..NowPlaying::default()
+        }
+    }
+
+    #[test]
+    fn text_is_artist_and_track_while_playing() {
+        assert_eq!(text(&playing(Some("Artist"))), "Artist - Track");
+        assert_eq!(text(&playing(None)), "Track");
+        let paused = NowPlaying {
+            is_playing: false,
+            ..playing(Some("Artist"))
+        };
+        assert_eq!(text(&paused), "");
+    }
+
+    #[test]
+    fn json_has_the_artwork_path() {
+        let contents = json_contents(
+            &playing(Some("Artist")),
+            Some(Path::new("/cache/artwork.img")),
+        );
+        let value: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(value["artist"], "Artist");
+        assert_eq!(value["artwork_path"], "/cache/artwork.img");
+        assert!(value.get("elapsed").is_none());
+    }
+
+    #[test]
+    fn write_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("now-playing-file-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("now-playing.txt");
+        write_file(&path, "first").unwrap();
+        write_file(&path, "second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,272 @@
+//! On-screen display
+//!
+//! A small frameless window that briefly shows volume changes made from the
+//! tray or the keyboard shortcuts, and each new track, whether or not the
+//! main window is open. It never takes focus or clicks. Everything it shows
+//! is decided here and handed to its page (`osd.html`), which only lays it
+//! out; where it shows and its colors are settings.
+
+use crate::i18n;
+use crate::now_playing::{self, NowPlaying};
+use crate::settings::{self, OsdPosition, OsdTheme};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use tauri::webview::PageLoadEvent;
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, WebviewWindow};
+
+/// Window label
+pub const LABEL: &str = "osd";
+
+/// Size of the window, in logical pixels
+const WIDTH: f64 = 320.0;
+const HEIGHT: f64 = 80.0;
+/// Distance kept from the screen's edges, in logical pixels
+const MARGIN: f64 = 48.0;
+/// How long it shows after the last change
+const SHOW_FOR: Duration = Duration::from_millis(2000);
+
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+/// What shows now, for a page that is still loading
+static CURRENT: Mutex<Option<Osd>> = Mutex::new(None);
+/// Counts the times it was shown, so only the latest one hides it
+static SHOWN: AtomicU64 = AtomicU64::new(0);
+/// Track of the last now-playing change, to tell when a new one starts
+static LAST_TRACK: Mutex<Option<TrackKey>> = Mutex::new(None);
+
+/// What the display shows
+#[derive(Debug, Clone, Serialize)]
+struct Osd {
+    title: String,
+    subtitle: Option<String>,
+    /// Fill of the level bar (0-100), shown for volume changes
+    level: Option<u8>,
+    image_url: Option<String>,
+    theme: OsdTheme,
+}
+
+/// Player, track and artist, which change together with the track
+type TrackKey = (Option<String>, Option<String>, Option<String>);
+
+/// Show new tracks from here on
+pub fn init(app: &AppHandle) {
+    let _ = APP_HANDLE.set(app.clone());
+    now_playing::on_now_playing_change(Arc::new(|now_playing| {
+        let key = (
+            now_playing.player_id.clone(),
+            now_playing.track.clone(),
+            now_playing.artist.clone(),
+        );
+        let changed = LAST_TRACK
+            .lock()
+            .is_ok_and(|mut last| last.replace(key.clone()) != Some(key));
+        if changed && now_playing.is_playing && settings::get_settings().osd_enabled {
+            show(&track(now_playing));
+        }
+    }));
+}
+
+/// Show the main player's new `volume`
+pub fn volume_changed(volume: u8, muted: bool) {
+    let settings = settings::get_settings();
+    if !settings.osd_enabled {
+        return;
+    }
+    let title = if muted {
+        i18n::tr("desktop.osd.muted")
+    } else {
+        i18n::tr("desktop.osd.volume").replace("{0}", &volume.to_string())
+    };
+    show(&Osd {
+        title,
+        subtitle: None,
+        level: Some(if muted { 0 } else { volume }),
+        image_url: None,
+        theme: settings.osd_theme,
+    });
+}
+
+/// Show what is playing, or a sample, to try out the position and theme.
+/// Shows even while the display is off.
+pub fn preview() {
+    let now_playing = now_playing::get_now_playing();
+    if now_playing.track.is_some() {
+        show(&track(&now_playing));
+    } else {
+        show(&Osd {
+            title: i18n::tr("desktop.osd.preview"),
+            subtitle: None,
+            level: None,
+            image_url: None,
+            theme: settings::get_settings().osd_theme,
+        });
+    }
+}
+
+/// Hide the display right away
+pub fn hide() {
+    SHOWN.fetch_add(1, Ordering::Relaxed);
+    if let Some(window) = APP_HANDLE
+        .get()
+        .and_then(|app| app.get_webview_window(LABEL))
+    {
+        let _ = window.eval("hideOsd()");
+        let _ = window.hide();
+    }
+}
+
+fn track(now_playing: &NowPlaying) -> Osd {
+    Osd {
+        title: now_playing.track.clone().unwrap_or_default(),
+        subtitle: now_playing.artist.clone(),
+        level: None,
+        image_url: now_playing.image_url.clone(),
+        theme: settings::get_settings().osd_theme,
+    }
+}
+
+fn show(osd: &Osd) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+    let shown = SHOWN.fetch_add(1, Ordering::Relaxed) + 1;
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(osd.clone());
+    }
+    let window = match app.get_webview_window(LABEL) {
+        Some(window) => {
+            render(&window, osd);
+            window
+        }
+        // Rendered once its page has loaded
+        None => match open(app) {
+            Ok(window) => window,
+            Err(e) => {
+                log::warn!("[OSD] Failed to open: {}", e);
+                return;
+            }
+        },
+    };
+    if let Err(e) = move_into_place(&window) {
+        log::debug!("[OSD] Failed to position: {}", e);
+    }
+    let _ = window.show();
+
+    thread::spawn(move || {
+        thread::sleep(SHOW_FOR);
+        if SHOWN.load(Ordering::Relaxed) == shown {
+            hide();
+        }
+    });
+}
+
+fn open(app: &AppHandle) -> tauri::Result<WebviewWindow> {
+    let window =
+        tauri::WebviewWindowBuilder::new(app, LABEL, tauri::WebviewUrl::App("osd.html".into()))
+            .title("Music Assistant")
+            .inner_size(WIDTH, HEIGHT)
+            .decorations(false)
+            .resizable(false)
+            .maximizable(false)
+            .minimizable(false)
+            .always_on_top(true)
+            .visible_on_all_workspaces(true)
+            .skip_taskbar(true)
+            .focused(false)
+            .focusable(false)
+            .visible(false)
+            .on_page_load(|window, payload| {
+                if payload.event() == PageLoadEvent::Finished {
+                    if let Some(osd) = CURRENT.lock().ok().and_then(|current| current.clone()) {
+                        render(&window, &osd);
+                    }
+                }
+            })
+            .build()?;
+    // Clicks go to whatever is underneath
+    window.set_ignore_cursor_events(true)?;
+    Ok(window)
+}
+
+fn render(window: &WebviewWindow, osd: &Osd) {
+    match serde_json::to_string(osd) {
+        Ok(json) => {
+            let _ = window.eval(&format!("showOsd({json})"));
+        }
+        Err(e) => log::warn!("[OSD] Failed to render: {}", e),
+    }
+}
+
+/// Move the window to its place on the primary screen
+fn move_into_place(window: &WebviewWindow) -> tauri::Result<()> {
+    let Some(monitor) = window.primary_monitor()? else {
+        return Ok(());
+    };
+    let scale = monitor.scale_factor();
+    let size = PhysicalSize::new((WIDTH * scale) as u32, (HEIGHT * scale) as u32);
+    let area = monitor.work_area();
+    window.set_position(place(
+        settings::get_settings().osd_position,
+        area.position,
+        area.size,
+        size,
+        (MARGIN * scale) as i32,
+    ))
+}
+
+/// Where a window of `size` goes to sit at `position` in a screen area of
+/// `area` at `origin`, `margin` away from its edges; all in physical pixels
+fn place(
+    position: OsdPosition,
+    origin: PhysicalPosition<i32>,
+    area: PhysicalSize<u32>,
+    size: PhysicalSize<u32>,
+    margin: i32,
+) -> PhysicalPosition<i32> {
+    let free_width = area.width.saturating_sub(size.width) as i32;
+    let free_height = area.height.saturating_sub(size.height) as i32;
+    let left = margin.min(free_width / 2);
+    let top = margin.min(free_height / 2);
+    let (x, y) = match position {
+        OsdPosition::TopLeft => (left, top),
+        OsdPosition::TopCenter => (free_width / 2, top),
+        OsdPosition::TopRight => (free_width - left, top),
+        OsdPosition::Center => (free_width / 2, free_height / 2),
+        OsdPosition::BottomLeft => (left, free_height - top),
+        OsdPosition::BottomCenter => (free_width / 2, free_height - top),
+        OsdPosition::BottomRight => (free_width - left, free_height - top),
+    };
+    PhysicalPosition::new(origin.x + x, origin.y + y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placed(position: OsdPosition, area: (u32, u32)) -> (i32, i32) {
+        let position = place(
+            position,
+            PhysicalPosition::new(100, 20),
+            PhysicalSize::new(area.0, area.1),
+            PhysicalSize::new(300, 80),
+            40,
+        );
+        (position.x, position.y)
+    }
+
+    #[test]
+    fn places_the_window_within_the_screen_area() {
+        assert_eq!(placed(OsdPosition::TopLeft, (1900, 1000)), (140, 60));
+        assert_eq!(placed(OsdPosition::Center, (1900, 1000)), (900, 480));
+        assert_eq!(placed(OsdPosition::BottomCenter, (1900, 1000)), (900, 900));
+        assert_eq!(placed(OsdPosition::BottomRight, (1900, 1000)), (1660, 900));
+    }
+
+    #[test]
+    fn gives_up_the_margin_on_small_screens() {
+        assert_eq!(placed(OsdPosition::TopRight, (320, 100)), (110, 30));
+        assert_eq!(placed(OsdPosition::BottomLeft, (200, 60)), (100, 20));
+    }
+}
@@ -0,0 +1,168 @@
+//! Stable identity of the main player
+//!
+//! Music Assistant knows players by ID, so a new ID shows up as another
+//! player and leaves the old one behind as a stale duplicate. The ID is
+//! kept in the settings and copied to a file in the app's data folder, so
+//! losing one of them (a settings file reset after it couldn't be read, an
+//! uninstaller that clears the config folder) doesn't lose the ID. With
+//! both gone, the ID is derived from the computer's own ID (`machine-id` on
+//! Linux, `MachineGuid` on Windows, the hardware UUID on macOS), so setting
+//! the app up again on the same computer brings back the same player.
+//!
+//! [`regenerate`] replaces the ID with a random one, e.g. for a cloned
+//! system that shows up as the same player as the original.
+
+use crate::settings;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Prefix of every player ID the app generates
+const PREFIX: &str = "ma_companion_";
+
+fn backup_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|p| p.join("music-assistant-companion").join("player-id"))
+}
+
+fn read_backup() -> Option<String> {
+    let id = fs::read_to_string(backup_path()?).ok()?;
+    let id = id.trim();
+    id.starts_with(PREFIX).then(|| id.to_string())
+}
+
+/// The main player's ID, generated on first use and stored
+pub fn main_player_id() -> String {
+    let stored = settings::get_settings()
+        .sendspin_player_id
+        .filter(|id| !id.is_empty());
+    let id = stored
+        .clone()
+        .or_else(|| {
+            let id = read_backup()?;
+            log::info!("[PlayerIdentity] Restored the player ID from its backup");
+            Some(id)
+        })
+        .or_else(|| {
+            let id = derived_id()?;
+            log::info!("[PlayerIdentity] Derived the player ID from the machine ID");
+            Some(id)
+        })
+        .unwrap_or_else(random_id);
+    if let Err(e) = store(&id, stored.as_deref()) {
+        log::warn!("[PlayerIdentity] Failed to store the player ID: {}", e);
+    }
+    id
+}
+
+/// Replace the main player's ID with a random one. Music Assistant sees a
+/// new player from the next connection; the old one stays until removed
+/// there.
+pub fn regenerate() -> Result<String, String> {
+    let previous = settings::get_settings().sendspin_player_id;
+    let id = random_id();
+    store(&id, previous.as_deref())?;
+    log::info!("[PlayerIdentity] New player ID {}", id);
+    Ok(id)
+}
+
+/// Save `id` to the settings (unless already there as `stored`) and to the
+/// backup file
+fn store(id: &str, stored: Option<&str>) -> Result<(), String> {
+    if stored != Some(id) {
+        settings::set_string_setting("sendspin_player_id", Some(id.to_string()))?;
+    }
+    let path = backup_path().ok_or("Could not determine the data folder")?;
+    if read_backup().as_deref() == Some(id) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, id).map_err(|e| e.to_string())
+}
+
+fn random_id() -> String {
+    format!("{}{}", PREFIX, uuid::Uuid::new_v4())
+}
+
+fn derived_id() -> Option<String> {
+    machine_id().map(|machine_id| id_for_machine(&machine_id))
+}
+
+/// Player ID for a machine ID. Hashed, since the machine ID itself is meant
+/// to stay private to the computer.
+fn id_for_machine(machine_id: &str) -> String {
+    let digest = Sha256::digest(format!("music-assistant-companion:{}", machine_id.trim()));
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    format!("{}{}", PREFIX, uuid::Uuid::from_bytes(bytes))
+}
+
+#[cfg(target_os = "linux")]
+fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+}
+
+#[cfg(target_os = "macos")]
+fn machine_id() -> Option<String> {
+    let output = std::process::Command::new("ioreg")
+        .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.contains("\"IOPlatformUUID\""))
+        .and_then(|line| line.rsplit('"').nth(1))
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "windows")]
+#[allow(unsafe_code)] // `RegGetValueW` is a plain Win32 call
+fn machine_id() -> Option<String> {
+    use windows::core::w;
+    use windows::Win32::System::Registry::{
+        RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ, RRF_SUBKEY_WOW6464KEY,
+    };
+
+    let mut buffer = [0u16; 64];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Cryptography"),
+            w!("MachineGuid"),
+            RRF_RT_REG_SZ | RRF_SUBKEY_WOW6464KEY,
+            None,
+            Some(buffer.as_mut_ptr().cast()),
+            Some(&raw mut size),
+        )
+    };
+    if result.is_err() {
+        return None;
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(String::from_utf16_lossy(&buffer[..len])).filter(|id| !id.is_empty())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn machine_id() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine_ids_map_to_stable_distinct_player_ids() {
+        let id = id_for_machine("4c4c4544-0042-3510-8052-b4c04f4e3132");
+        assert!(id.starts_with(PREFIX));
+        assert_eq!(id.len(), PREFIX.len() + 36);
+        assert_eq!(id, id_for_machine("4c4c4544-0042-3510-8052-b4c04f4e3132\n"));
+        assert_ne!(id, id_for_machine("4c4c4544-0042-3510-8052-b4c04f4e3133"));
+    }
+}
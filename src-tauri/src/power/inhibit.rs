@@ -0,0 +1,281 @@
+//! Keep the machine awake while music is playing
+//!
+//! Each platform's sleep assertion is held by a dedicated worker thread
+//! (`SetThreadExecutionState` is thread-scoped on Windows) that follows
+//! `NowPlaying::is_playing`. By default only system sleep is blocked and the
+//! display may still turn off; the `keep_display_awake` setting blocks that
+//! too.
+
+use crate::now_playing::{get_now_playing, on_now_playing_change};
+use crate::settings;
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+
+/// What the worker keeps awake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    System,
+    SystemAndDisplay,
+}
+
+/// Wakes the worker to re-evaluate the playback state and settings
+static WAKE: OnceLock<mpsc::Sender<()>> = OnceLock::new();
+
+fn wanted_level(is_playing: bool, keep_display_awake: bool) -> Option<Level> {
+    match (is_playing, keep_display_awake) {
+        (false, _) => None,
+        (true, false) => Some(Level::System),
+        (true, true) => Some(Level::SystemAndDisplay),
+    }
+}
+
+/// Start following the playback state.
+pub fn init() {
+    let (tx, rx) = mpsc::channel::<()>();
+    if WAKE.set(tx.clone()).is_err() {
+        return;
+    }
+    thread::spawn(move || run_worker(rx));
+
+    on_now_playing_change(Arc::new(move |_now_playing| {
+        let _ = tx.send(());
+    }));
+
+    // Playback may have started before desktop services were initialized.
+    refresh();
+}
+
+/// Re-apply the inhibitor, e.g. after `keep_display_awake` changed.
+pub fn refresh() {
+    if let Some(tx) = WAKE.get() {
+        let _ = tx.send(());
+    }
+}
+
+fn run_worker(rx: mpsc::Receiver<()>) {
+    let mut inhibitor = platform::Inhibitor::default();
+    let mut active = None;
+
+    while rx.recv().is_ok() {
+        // Coalesce metadata/progress updates; only the latest playback state
+        // matters to the assertion.
+        while rx.try_recv().is_ok() {}
+        let wanted = wanted_level(
+            get_now_playing().is_playing,
+            settings::get_settings().keep_display_awake,
+        );
+        if wanted == active {
+            continue;
+        }
+
+        if let Err(e) = inhibitor.set(wanted) {
+            log::warn!("[PowerManagement] Failed to update sleep prevention: {}", e);
+            continue;
+        }
+        active = wanted;
+        log::debug!("[PowerManagement] Sleep prevention: {:?}", active);
+    }
+
+    // Release the assertion if the worker is ever shut down cleanly.
+    let _ = inhibitor.set(None);
+}
+
+#[cfg(target_os = "windows")]
+#[allow(unsafe_code)]
+mod platform {
+    use super::Level;
+    use windows::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED,
+        EXECUTION_STATE,
+    };
+
+    #[derive(Default)]
+    pub(super) struct Inhibitor;
+
+    impl Inhibitor {
+        pub(super) fn set(&mut self, level: Option<Level>) -> Result<(), String> {
+            let flags = match level {
+                None => ES_CONTINUOUS.0,
+                Some(Level::System) => ES_CONTINUOUS.0 | ES_SYSTEM_REQUIRED.0,
+                Some(Level::SystemAndDisplay) => {
+                    ES_CONTINUOUS.0 | ES_SYSTEM_REQUIRED.0 | ES_DISPLAY_REQUIRED.0
+                }
+            };
+            let previous_state = unsafe { SetThreadExecutionState(EXECUTION_STATE(flags)) };
+            if previous_state == EXECUTION_STATE(0) {
+                return Err("SetThreadExecutionState failed".to_string());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[allow(unsafe_code)]
+mod platform {
+    use super::Level;
+    use objc2_core_foundation::CFString;
+
+    /// `kIOPMAssertionLevelOn`
+    const ASSERTION_LEVEL_ON: u32 = 255;
+    const ASSERTION_NAME: &str = "Music Assistant is playing audio";
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: &CFString,
+            level: u32,
+            name: &CFString,
+            assertion_id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(assertion_id: u32) -> i32;
+    }
+
+    /// `IOKit` power assertions currently held
+    #[derive(Default)]
+    pub(super) struct Inhibitor {
+        assertions: Vec<u32>,
+    }
+
+    fn create_assertion(kind: &str) -> Result<u32, String> {
+        let mut id = 0;
+        let status = unsafe {
+            IOPMAssertionCreateWithName(
+                &CFString::from_str(kind),
+                ASSERTION_LEVEL_ON,
+                &CFString::from_str(ASSERTION_NAME),
+                &raw mut id,
+            )
+        };
+        if status == 0 {
+            Ok(id)
+        } else {
+            Err(format!(
+                "IOPMAssertionCreateWithName({kind}) failed: {status}"
+            ))
+        }
+    }
+
+    impl Inhibitor {
+        pub(super) fn set(&mut self, level: Option<Level>) -> Result<(), String> {
+            for id in self.assertions.drain(..) {
+                unsafe { IOPMAssertionRelease(id) };
+            }
+            let kinds: &[&str] = match level {
+                None => &[],
+                Some(Level::System) => &["PreventUserIdleSystemSleep"],
+                Some(Level::SystemAndDisplay) => {
+                    &["PreventUserIdleSystemSleep", "PreventUserIdleDisplaySleep"]
+                }
+            };
+            for kind in kinds {
+                self.assertions.push(create_assertion(kind)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Level;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedFd;
+
+    const APP_NAME: &str = "Music Assistant";
+    const REASON: &str = "Playing audio";
+
+    /// A logind sleep inhibitor (released by closing its fd) and, when the
+    /// display is kept awake too, a screensaver inhibit cookie. The
+    /// screensaver drops inhibits when their D-Bus connection closes, so the
+    /// connection is kept with the cookie.
+    #[derive(Default)]
+    pub(super) struct Inhibitor {
+        sleep_lock: Option<OwnedFd>,
+        screensaver: Option<(Connection, u32)>,
+    }
+
+    fn screensaver(connection: &Connection) -> zbus::Result<Proxy<'_>> {
+        Proxy::new(
+            connection,
+            "org.freedesktop.ScreenSaver",
+            "/org/freedesktop/ScreenSaver",
+            "org.freedesktop.ScreenSaver",
+        )
+    }
+
+    fn inhibit_sleep() -> Result<OwnedFd, String> {
+        let connection = Connection::system()
+            .map_err(|e| format!("Failed to connect to the system bus: {e}"))?;
+        let logind = Proxy::new(
+            &connection,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )
+        .map_err(|e| format!("logind unavailable: {e}"))?;
+        logind
+            .call("Inhibit", &("sleep:idle", APP_NAME, REASON, "block"))
+            .map_err(|e| format!("logind Inhibit failed: {e}"))
+    }
+
+    fn inhibit_screensaver() -> Result<(Connection, u32), String> {
+        let connection = Connection::session()
+            .map_err(|e| format!("Failed to connect to the session bus: {e}"))?;
+        let cookie: u32 = screensaver(&connection)
+            .and_then(|proxy| proxy.call("Inhibit", &(APP_NAME, REASON)))
+            .map_err(|e| format!("Screensaver Inhibit failed: {e}"))?;
+        Ok((connection, cookie))
+    }
+
+    impl Inhibitor {
+        pub(super) fn set(&mut self, level: Option<Level>) -> Result<(), String> {
+            if level.is_none() {
+                self.sleep_lock = None;
+            } else if self.sleep_lock.is_none() {
+                self.sleep_lock = Some(inhibit_sleep()?);
+            }
+
+            let keep_display = level == Some(Level::SystemAndDisplay);
+            if keep_display && self.screensaver.is_none() {
+                self.screensaver = Some(inhibit_screensaver()?);
+            } else if !keep_display {
+                if let Some((connection, cookie)) = self.screensaver.take() {
+                    // Closing the connection releases it as well; this is
+                    // just the polite way
+                    let _ = screensaver(&connection)
+                        .and_then(|proxy| proxy.call::<_, _, ()>("UnInhibit", &(cookie,)));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    use super::Level;
+
+    #[derive(Default)]
+    pub(super) struct Inhibitor;
+
+    impl Inhibitor {
+        #[allow(clippy::unused_self)]
+        pub(super) fn set(&mut self, _level: Option<Level>) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_playback_keeps_the_machine_awake() {
+        assert_eq!(wanted_level(false, false), None);
+        assert_eq!(wanted_level(false, true), None);
+        assert_eq!(wanted_level(true, false), Some(Level::System));
+        assert_eq!(wanted_level(true, true), Some(Level::SystemAndDisplay));
+    }
+}
@@ -0,0 +1,76 @@
+//! Resume and lock detection through logind: the manager's
+//! `PrepareForSleep` signal and the session's `LockedHint` property
+
+use futures_util::StreamExt;
+use tauri::AppHandle;
+
+const LOGIND_BUS: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_IFACE: &str = "org.freedesktop.login1.Manager";
+/// logind resolves this to the session the app runs in
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_IFACE: &str = "org.freedesktop.login1.Session";
+
+/// Watch for resume and session lock on a background thread.
+///
+/// Best-effort: without logind (non-systemd distros, containers) the thread
+/// logs once and exits.
+pub fn init(app: AppHandle) {
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::error!("[Sendspin] Failed to create power event runtime: {e}");
+                return;
+            }
+        };
+
+        runtime.block_on(async {
+            let connection = match zbus::Connection::system().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::info!("[Sendspin] Power events unavailable: {e}");
+                    return;
+                }
+            };
+            let (sleep, lock) = tokio::join!(
+                watch_sleep(&connection, &app),
+                watch_lock(&connection, &app)
+            );
+            if let Err(e) = sleep {
+                log::info!("[Sendspin] Resume detection unavailable: {e}");
+            }
+            if let Err(e) = lock {
+                log::info!("[Sendspin] Session lock detection unavailable: {e}");
+            }
+        });
+    });
+}
+
+async fn watch_sleep(connection: &zbus::Connection, app: &AppHandle) -> zbus::Result<()> {
+    let proxy = zbus::Proxy::new(connection, LOGIND_BUS, LOGIND_PATH, MANAGER_IFACE).await?;
+    let mut signals = proxy.receive_signal("PrepareForSleep").await?;
+
+    while let Some(message) = signals.next().await {
+        // `true` right before suspending, `false` once resumed
+        if let Ok(false) = message.body().deserialize::<bool>() {
+            super::on_resume(app);
+        }
+    }
+    Ok(())
+}
+
+async fn watch_lock(connection: &zbus::Connection, app: &AppHandle) -> zbus::Result<()> {
+    let proxy = zbus::Proxy::new(connection, LOGIND_BUS, SESSION_PATH, SESSION_IFACE).await?;
+    let mut changes = proxy.receive_property_changed::<bool>("LockedHint").await;
+
+    while let Some(change) = changes.next().await {
+        if let Ok(locked) = change.get().await {
+            super::lock::on_lock_changed(app, locked);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Pause playback while the session is locked
+//!
+//! With `pause_on_lock` enabled, locking the screen pauses whatever is
+//! playing and unlocking resumes it, so music doesn't keep playing from an
+//! unattended office PC. Playback that was already paused stays paused.
+
+use crate::now_playing::get_now_playing;
+use crate::settings;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::AppHandle;
+
+/// Whether playback was paused by the last lock and should resume on unlock
+static PAUSED_BY_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Player command to send when the lock state changes, if any
+fn lock_command(
+    locked: bool,
+    pause_on_lock: bool,
+    is_playing: bool,
+    paused_by_lock: bool,
+) -> Option<&'static str> {
+    match (locked, paused_by_lock) {
+        (true, _) if pause_on_lock && is_playing => Some("pause"),
+        (false, true) => Some("play"),
+        _ => None,
+    }
+}
+
+pub(super) fn on_lock_changed(app: &AppHandle, locked: bool) {
+    log::debug!(
+        "[Sendspin] Session {}",
+        if locked { "locked" } else { "unlocked" }
+    );
+    let command = lock_command(
+        locked,
+        settings::get_settings().pause_on_lock,
+        get_now_playing().is_playing,
+        PAUSED_BY_LOCK.load(Ordering::SeqCst),
+    );
+    PAUSED_BY_LOCK.store(command == Some("pause"), Ordering::SeqCst);
+    if let Some(command) = command {
+        log::info!("[Sendspin] Sending {} for session lock change", command);
+        crate::send_player_command(app, command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_pauses_only_when_enabled_and_playing() {
+        assert_eq!(lock_command(true, true, true, false), Some("pause"));
+        assert_eq!(lock_command(true, false, true, false), None);
+        assert_eq!(lock_command(true, true, false, false), None);
+    }
+
+    #[test]
+    fn unlock_resumes_only_what_the_lock_paused() {
+        assert_eq!(lock_command(false, true, false, true), Some("play"));
+        assert_eq!(lock_command(false, true, false, false), None);
+    }
+}
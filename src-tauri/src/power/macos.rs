@@ -0,0 +1,50 @@
+//! Resume detection through `NSWorkspaceDidWakeNotification` and lock
+//! detection through the `com.apple.screenIsLocked` / `screenIsUnlocked`
+//! distributed notifications
+#![allow(unsafe_code)] // objc2 framework methods are all `unsafe`; lift the workspace deny.
+
+use block2::RcBlock;
+use objc2_app_kit::{NSWorkspace, NSWorkspaceDidWakeNotification};
+use objc2_foundation::{
+    NSDistributedNotificationCenter, NSNotification, NSNotificationCenter, NSString,
+};
+use std::ptr::NonNull;
+use tauri::AppHandle;
+
+/// Register the observers. Notifications are delivered on the main thread.
+pub fn init(app: AppHandle) {
+    let handle = app.clone();
+    let result = app.run_on_main_thread(move || unsafe {
+        let workspace_center = NSWorkspace::sharedWorkspace().notificationCenter();
+        let wake_app = handle.clone();
+        observe(
+            &workspace_center,
+            NSWorkspaceDidWakeNotification,
+            move || {
+                super::on_resume(&wake_app);
+            },
+        );
+
+        let distributed_center = NSDistributedNotificationCenter::defaultCenter();
+        for (name, locked) in [
+            ("com.apple.screenIsLocked", true),
+            ("com.apple.screenIsUnlocked", false),
+        ] {
+            let lock_app = handle.clone();
+            observe(&distributed_center, &NSString::from_str(name), move || {
+                super::lock::on_lock_changed(&lock_app, locked);
+            });
+        }
+    });
+    if let Err(e) = result {
+        log::warn!("[Sendspin] Failed to register for power notifications: {e}");
+    }
+}
+
+unsafe fn observe(center: &NSNotificationCenter, name: &NSString, handler: impl Fn() + 'static) {
+    let block = RcBlock::new(move |_notification: NonNull<NSNotification>| handler());
+    let observer =
+        center.addObserverForName_object_queue_usingBlock(Some(name), None, None, &block);
+    // Observes for the app's lifetime
+    std::mem::forget(observer);
+}
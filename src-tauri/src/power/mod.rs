@@ -0,0 +1,107 @@
+//! System suspend/resume and session lock handling
+//!
+//! While the machine sleeps the server drops the Sendspin connection, but
+//! nothing on this side notices: the socket and the audio stream come back
+//! looking alive and the player sits in a zombie `Connected` state. On
+//! resume every running player is torn down and reconnected.
+//!
+//! Linux listens to logind, macOS to workspace and distributed
+//! notifications, and Windows to a suspend/resume callback and session
+//! changes sent to the app's window. Elsewhere a watcher thread notices the
+//! wall clock jumping past a sleep.
+//!
+//! [`inhibit`] keeps the machine from sleeping in the first place while
+//! music is playing, and [`lock`] pauses playback while the session is
+//! locked.
+
+pub mod inhibit;
+#[cfg(target_os = "linux")]
+mod linux;
+mod lock;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "windows")]
+mod windows;
+
+use crate::sendspin::SendspinManager;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Time given to the network to come back before reconnecting
+const RESUME_SETTLE: Duration = Duration::from_secs(2);
+
+/// Start watching for the system resuming from sleep and the session
+/// locking.
+pub fn init(app: AppHandle) {
+    #[cfg(target_os = "linux")]
+    linux::init(app);
+    #[cfg(target_os = "macos")]
+    macos::init(app);
+    #[cfg(target_os = "windows")]
+    windows::init(app);
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    watch_clock_jumps(app);
+}
+
+/// Reconnect every running player after the system woke up.
+fn on_resume(app: &AppHandle) {
+    log::info!("[Sendspin] System resumed from sleep; reconnecting");
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(RESUME_SETTLE).await;
+        app.state::<SendspinManager>().restart().await;
+    });
+}
+
+/// Interval of the clock-jump watcher
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether `elapsed` wall-clock time over a `WATCH_INTERVAL` sleep means the
+/// machine was suspended in between. Generous slack keeps a busy system or
+/// an NTP correction from counting.
+#[cfg(any(
+    test,
+    not(any(target_os = "linux", target_os = "macos", target_os = "windows"))
+))]
+fn is_resume_gap(interval: Duration, elapsed: Duration) -> bool {
+    elapsed > interval * 2 + Duration::from_secs(10)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn watch_clock_jumps(app: AppHandle) {
+    use std::time::SystemTime;
+
+    std::thread::spawn(move || {
+        let mut last = SystemTime::now();
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            let now = SystemTime::now();
+            // A clock stepped backwards is not a resume
+            if let Ok(elapsed) = now.duration_since(last) {
+                if is_resume_gap(WATCH_INTERVAL, elapsed) {
+                    log::debug!(
+                        "[Sendspin] Wall clock jumped {}s; assuming the system slept",
+                        elapsed.as_secs()
+                    );
+                    on_resume(&app);
+                }
+            }
+            last = now;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_gap_needs_a_real_jump() {
+        let interval = Duration::from_secs(5);
+        assert!(!is_resume_gap(interval, Duration::from_secs(5)));
+        // A loaded system oversleeping a little is not a suspend
+        assert!(!is_resume_gap(interval, Duration::from_secs(12)));
+        assert!(is_resume_gap(interval, Duration::from_secs(60 * 30)));
+    }
+}
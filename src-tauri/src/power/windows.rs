@@ -0,0 +1,137 @@
+//! Resume detection through `PowerRegisterSuspendResumeNotification`, which
+//! calls back on a system thread rather than needing a window, and lock
+//! detection through `WM_WTSSESSION_CHANGE` on the app's window
+#![allow(unsafe_code)] // Win32 registration is `unsafe`; lift the workspace deny.
+
+use std::ffi::c_void;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+use windows::Win32::Foundation::{ERROR_SUCCESS, HANDLE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::Power::{
+    PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS, HPOWERNOTIFY,
+};
+use windows::Win32::System::RemoteDesktop::{
+    WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+use windows::Win32::UI::Shell::{DefSubclassProc, RemoveWindowSubclass, SetWindowSubclass};
+use windows::Win32::UI::WindowsAndMessaging::{
+    DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, WM_NCDESTROY, WM_WTSSESSION_CHANGE,
+    WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+};
+
+/// App the power and session callbacks act on
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+const SESSION_SUBCLASS_ID: usize = 0x4d41_4c4b;
+
+/// Register for suspend/resume and session notifications, for the app's
+/// lifetime.
+pub fn init(app: AppHandle) {
+    if APP.set(app.clone()).is_err() {
+        return;
+    }
+    // Handed to the system, which calls back through it until unregistered
+    let parameters = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+        Callback: Some(on_power_event),
+        Context: std::ptr::null_mut(),
+    }));
+    let mut registration = HPOWERNOTIFY::default();
+    let result = unsafe {
+        PowerRegisterSuspendResumeNotification(
+            DEVICE_NOTIFY_CALLBACK,
+            HANDLE(std::ptr::from_mut(parameters).cast()),
+            &raw mut registration,
+        )
+    };
+    if let Err(e) = result.ok() {
+        log::warn!("[Sendspin] Failed to register for power notifications: {e}");
+    }
+
+    // Windows are subclassed from the thread that owns them
+    let handle = app.clone();
+    if let Err(e) = app.run_on_main_thread(move || watch_session(&handle, None)) {
+        log::warn!("[Sendspin] Failed to register for session notifications: {e}");
+    }
+}
+
+/// Sent `PBT_APMRESUMEAUTOMATIC` on every resume, whether or not a user is
+/// there to also cause `PBT_APMRESUMESUSPEND`
+unsafe extern "system" fn on_power_event(
+    _context: *const c_void,
+    event: u32,
+    _setting: *const c_void,
+) -> u32 {
+    if event == PBT_APMRESUMEAUTOMATIC {
+        if let Some(app) = APP.get() {
+            super::on_resume(app);
+        }
+    }
+    ERROR_SUCCESS.0
+}
+
+/// Have the app's window, other than the one at `closed`, told about the
+/// session locking and unlocking. Session changes are only sent to windows.
+fn watch_session(app: &AppHandle, closed: Option<usize>) {
+    let Some(hwnd) = app_window(app).filter(|hwnd| Some(hwnd.0 as usize) != closed) else {
+        log::debug!("[Sendspin] No window left to watch the session lock from");
+        return;
+    };
+    unsafe {
+        if !SetWindowSubclass(hwnd, Some(session_subclass_proc), SESSION_SUBCLASS_ID, 0).as_bool() {
+            log::warn!("[Sendspin] Failed to watch the window for session changes");
+            return;
+        }
+        if let Err(e) = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) {
+            log::warn!("[Sendspin] Failed to register for session notifications: {e}");
+            let _ = RemoveWindowSubclass(hwnd, Some(session_subclass_proc), SESSION_SUBCLASS_ID);
+        }
+    }
+}
+
+/// The main window, or the launcher while signed out
+fn app_window(app: &AppHandle) -> Option<HWND> {
+    use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+    let window = app
+        .get_webview_window("main")
+        .or_else(|| app.get_webview_window("launcher"))?;
+    let handle = window.window_handle().ok()?;
+    match handle.as_ref() {
+        RawWindowHandle::Win32(win32) => Some(HWND(win32.hwnd.get() as *mut c_void)),
+        _ => None,
+    }
+}
+
+unsafe extern "system" fn session_subclass_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _subclass_id: usize,
+    _ref_data: usize,
+) -> LRESULT {
+    match msg {
+        WM_WTSSESSION_CHANGE => {
+            let locked = match wparam.0 as u32 {
+                WTS_SESSION_LOCK => Some(true),
+                WTS_SESSION_UNLOCK => Some(false),
+                _ => None,
+            };
+            if let (Some(locked), Some(app)) = (locked, APP.get()) {
+                super::lock::on_lock_changed(app, locked);
+            }
+        }
+        WM_NCDESTROY => {
+            let _ = WTSUnRegisterSessionNotification(hwnd);
+            let _ = RemoveWindowSubclass(hwnd, Some(session_subclass_proc), SESSION_SUBCLASS_ID);
+            // Signing in or out replaces the window; carry on from the new one
+            if let Some(app) = APP.get() {
+                let handle = app.clone();
+                let closed = hwnd.0 as usize;
+                let _ = app.run_on_main_thread(move || watch_session(&handle, Some(closed)));
+            }
+        }
+        _ => {}
+    }
+    DefSubclassProc(hwnd, msg, wparam, lparam)
+}
@@ -0,0 +1,172 @@
+//! Auth tokens in the OS credential store
+//!
+//! Tokens are kept in the Secret Service on Linux, the Keychain on macOS and
+//! the Credential Manager on Windows, one entry per server, instead of in
+//! the plain-text settings file.
+
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Service name the entries are filed under
+const SERVICE: &str = "music-assistant-companion";
+
+/// Top-level settings keys older builds stored the token under
+const LEGACY_TOKEN_KEYS: [&str; 2] = ["auth_token", "ma_auth_token"];
+
+/// Tokens read or written so far, by account, so the credential store
+/// (which may prompt, and is slow on macOS) is asked once per server
+static CACHE: Mutex<BTreeMap<String, Option<String>>> = Mutex::new(BTreeMap::new());
+
+/// Entries are keyed by server URL; normalize so `http://ma:8095/` and
+/// `http://ma:8095` share one.
+fn account(server_url: &str) -> String {
+    server_url.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+fn entry(server_url: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(SERVICE, &account(server_url))
+        .map_err(|e| format!("Failed to open credential store entry: {e}"))
+}
+
+/// Store the auth token for `server_url`, replacing any previous one.
+pub fn store_token(server_url: &str, token: &str) -> Result<(), String> {
+    entry(server_url)?
+        .set_password(token)
+        .map_err(|e| format!("Failed to store token: {e}"))?;
+    CACHE
+        .lock()
+        .insert(account(server_url), Some(token.to_string()));
+    Ok(())
+}
+
+/// The stored auth token for `server_url`, if any.
+pub fn get_token(server_url: &str) -> Result<Option<String>, String> {
+    if let Some(token) = CACHE.lock().get(&account(server_url)) {
+        return Ok(token.clone());
+    }
+    let token = match entry(server_url)?.get_password() {
+        Ok(token) => Some(token),
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => return Err(format!("Failed to read token: {e}")),
+    };
+    CACHE.lock().insert(account(server_url), token.clone());
+    Ok(token)
+}
+
+/// Forget the auth token for `server_url`. Succeeds if there was none.
+pub fn delete_token(server_url: &str) -> Result<(), String> {
+    match entry(server_url)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => {
+            CACHE.lock().insert(account(server_url), None);
+            Ok(())
+        }
+        Err(e) => Err(format!("Failed to delete token: {e}")),
+    }
+}
+
+/// Remove plain-text tokens from raw settings JSON, returning each with the
+/// server it belongs to.
+fn take_plaintext_tokens(raw: &mut Value) -> Vec<(Option<String>, String)> {
+    let Some(map) = raw.as_object_mut() else {
+        return Vec::new();
+    };
+    let server_url = map
+        .get("last_server_url")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    LEGACY_TOKEN_KEYS
+        .iter()
+        .filter_map(|key| map.remove(*key))
+        .filter_map(|value| value.as_str().filter(|t| !t.is_empty()).map(str::to_string))
+        .map(|token| (server_url.clone(), token))
+        .collect()
+}
+
+/// Move tokens older builds left in the settings file into the credential
+/// store. Tokens that can't be moved are dropped rather than left in plain
+/// text; the frontend then asks to sign in again.
+pub fn migrate_plaintext_tokens(raw: &mut Value) {
+    for (server_url, token) in take_plaintext_tokens(raw) {
+        let Some(server_url) = server_url else {
+            log::warn!("[Settings] Dropping a stored token with no server to file it under");
+            continue;
+        };
+        match store_token(&server_url, &token) {
+            Ok(()) => log::info!(
+                "[Settings] Moved the token for {} to the credential store",
+                server_url
+            ),
+            Err(e) => log::warn!(
+                "[Settings] Dropping the stored token for {}: {}",
+                server_url,
+                e
+            ),
+        }
+    }
+}
+
+/// Keep the token the Music Assistant frontend hands over when it connects.
+///
+/// Before tokens moved here the only persistent copy lived in the frontend's
+/// own storage inside the webview, which nothing outside that page can read;
+/// this is where it reaches the companion, so a session signed in before the
+/// move lands in the credential store on its first connect. Skips the write
+/// when the stored token already matches; after the first read that's
+/// answered from the cache, so the Keychain isn't touched on every
+/// reconnect.
+pub fn adopt_token(server_url: &str, token: &str) {
+    if token.is_empty() {
+        return;
+    }
+    if get_token(server_url).ok().flatten().as_deref() == Some(token) {
+        return;
+    }
+    match store_token(server_url, token) {
+        Ok(()) => log::info!(
+            "[Secrets] Stored the token for {} in the credential store",
+            server_url
+        ),
+        Err(e) => log::warn!(
+            "[Secrets] Couldn't keep the token for {}: {}",
+            server_url,
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_ignore_trailing_slash_and_case() {
+        assert_eq!(account("http://MA.local:8095/"), "http://ma.local:8095");
+        assert_eq!(account(" http://ma.local:8095 "), "http://ma.local:8095");
+    }
+
+    #[test]
+    fn plaintext_tokens_are_taken_out_of_the_settings() {
+        let mut raw = serde_json::json!({
+            "last_server_url": "http://ma.local:8095",
+            "auth_token": "abc",
+            "ma_auth_token": "",
+            "autostart": true,
+        });
+        let tokens = take_plaintext_tokens(&mut raw);
+        assert_eq!(
+            tokens,
+            [(Some("http://ma.local:8095".to_string()), "abc".to_string())]
+        );
+        assert!(raw.get("auth_token").is_none());
+        assert!(raw.get("ma_auth_token").is_none());
+        assert_eq!(raw["autostart"], true);
+    }
+
+    #[test]
+    fn settings_without_tokens_are_untouched() {
+        let mut raw = serde_json::json!({ "autostart": true });
+        assert!(take_plaintext_tokens(&mut raw).is_empty());
+        assert_eq!(raw, serde_json::json!({ "autostart": true }));
+    }
+}
@@ -0,0 +1,170 @@
+//! Adaptive playback buffer target
+//!
+//! The buffer the player asks the server to keep ahead (`min_buffer_ms`)
+//! starts at the connection's base value. Gaps in chunk arrival and buffer
+//! underruns grow it so brief Wi-Fi hiccups are ridden out instead of heard;
+//! after a stable stretch it shrinks back toward the base to keep latency
+//! for pause and skip low.
+
+use std::time::{Duration, Instant};
+
+/// Upper bound for the target
+const MAX_TARGET_MS: u32 = 5_000;
+/// A pause between chunks longer than this counts as a network hiccup
+const GAP_THRESHOLD: Duration = Duration::from_millis(250);
+/// Time without hiccups before the target shrinks a step
+const STABLE_PERIOD: Duration = Duration::from_secs(60);
+/// Targets are rounded up to this granularity so small fluctuations don't
+/// each produce a state update
+const STEP_MS: u32 = 100;
+
+#[derive(Debug)]
+pub(crate) struct AdaptiveBuffer {
+    base_ms: u32,
+    target_ms: u32,
+    last_chunk_at: Option<Instant>,
+    /// Last hiccup or shrink step
+    stable_since: Instant,
+}
+
+fn round_up(ms: u32) -> u32 {
+    ms.div_ceil(STEP_MS) * STEP_MS
+}
+
+impl AdaptiveBuffer {
+    pub(crate) fn new(base_ms: u32, now: Instant) -> Self {
+        Self {
+            base_ms,
+            target_ms: base_ms,
+            last_chunk_at: None,
+            stable_since: now,
+        }
+    }
+
+    pub(crate) fn target_ms(&self) -> u32 {
+        self.target_ms
+    }
+
+    /// A stream started or was cleared; the gap before its first chunk is
+    /// not a network hiccup.
+    pub(crate) fn reset_arrivals(&mut self) {
+        self.last_chunk_at = None;
+    }
+
+    /// Record a chunk arriving at `now`; `ran_dry` whether the buffer had
+    /// run out before it. Returns the new target if it changed.
+    pub(crate) fn on_chunk(&mut self, now: Instant, ran_dry: bool) -> Option<u32> {
+        let gap = self
+            .last_chunk_at
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last_chunk_at = Some(now);
+
+        let previous = self.target_ms;
+        if ran_dry || gap > GAP_THRESHOLD {
+            // Grow by half, and at least enough to cover twice the gap
+            let gap_ms = u32::try_from(gap.as_millis()).unwrap_or(u32::MAX);
+            let grown = (self.target_ms + self.target_ms / 2).max(gap_ms.saturating_mul(2));
+            self.target_ms = round_up(grown).min(MAX_TARGET_MS);
+            self.stable_since = now;
+        } else if self.target_ms > self.base_ms
+            && now.saturating_duration_since(self.stable_since) >= STABLE_PERIOD
+        {
+            // Shrink a quarter of the way back toward the base
+            let excess = self.target_ms - self.base_ms;
+            self.target_ms = round_up(self.base_ms + excess * 3 / 4).max(self.base_ms);
+            if self.target_ms == previous {
+                self.target_ms = self.base_ms;
+            }
+            self.stable_since = now;
+        }
+
+        (self.target_ms != previous).then_some(self.target_ms)
+    }
+
+    /// The player's buffer is nearly full (see `buffer_fill`): go back to
+    /// the base target so the server isn't asked to keep even more ahead.
+    /// Returns the new target if it changed.
+    pub(crate) fn back_off(&mut self, now: Instant) -> Option<u32> {
+        self.stable_since = now;
+        let previous = self.target_ms;
+        self.target_ms = self.base_ms;
+        (self.target_ms != previous).then_some(self.target_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn steady_arrivals_keep_the_base_target() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        for i in 0..100 {
+            assert_eq!(buffer.on_chunk(t0 + CHUNK * i, false), None);
+        }
+        assert_eq!(buffer.target_ms(), 500);
+    }
+
+    #[test]
+    fn gaps_grow_the_target() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        buffer.on_chunk(t0, false);
+        // A 1 s stall needs at least 2 s of buffer to ride out next time
+        assert_eq!(
+            buffer.on_chunk(t0 + Duration::from_secs(1), false),
+            Some(2000)
+        );
+    }
+
+    #[test]
+    fn underruns_grow_the_target() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        assert_eq!(buffer.on_chunk(t0, true), Some(800));
+    }
+
+    #[test]
+    fn target_is_capped() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        buffer.on_chunk(t0, false);
+        buffer.on_chunk(t0 + Duration::from_secs(10), false);
+        assert_eq!(buffer.target_ms(), MAX_TARGET_MS);
+    }
+
+    #[test]
+    fn stable_network_shrinks_back_to_base() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        buffer.on_chunk(t0, true);
+        assert_eq!(buffer.target_ms(), 800);
+
+        // A minute of steady chunks
+        for i in 1..=3_000 {
+            buffer.on_chunk(t0 + CHUNK * i, false);
+        }
+        assert_eq!(buffer.target_ms(), 500);
+    }
+
+    #[test]
+    fn backs_off_to_base_when_full() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        buffer.on_chunk(t0, true);
+        assert_eq!(buffer.back_off(t0), Some(500));
+        assert_eq!(buffer.back_off(t0), None);
+    }
+
+    #[test]
+    fn stream_start_gap_is_not_a_hiccup() {
+        let t0 = Instant::now();
+        let mut buffer = AdaptiveBuffer::new(500, t0);
+        buffer.on_chunk(t0, false);
+        buffer.reset_arrivals();
+        assert_eq!(buffer.on_chunk(t0 + Duration::from_secs(5), false), None);
+    }
+}
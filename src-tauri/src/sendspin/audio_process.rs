@@ -0,0 +1,599 @@
+//! Playback in a child process
+//!
+//! With `isolated_playback` on, the playback thread doesn't open the output
+//! device itself. It starts the app again with [`FLAG`], and that child
+//! process plays what is written to its stdin. An audio driver that crashes
+//! or a cpal panic then ends only the child: the playback thread notices on
+//! the next chunk, reports the device as failed like any other open
+//! failure, and the next stream starts a new child.
+//!
+//! The stream is still decoded in the app, where the clock is synchronized,
+//! and the child gets the samples with how long until each chunk is to be
+//! heard; the two processes share no clock to give it the time itself. The
+//! child plays them the way the Snapcast backend does, with
+//! [`timed_player`](super::timed_player). The mirror device and exclusive
+//! mode aren't used while playing this way.
+
+use super::timed_player::{Player, Schedule};
+use super::SendspinError;
+use parking_lot::{Mutex, MutexGuard};
+use sendspin::audio::{AudioBuffer, AudioFormat};
+use sendspin::sync::ClockSync;
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// Command-line flag that starts the app as the playback process
+pub const FLAG: &str = "--audio-process";
+
+/// Full scale of decoded samples, which are 24-bit
+const SAMPLE_SCALE: f32 = 8_388_608.0;
+
+const OPEN: u8 = 1;
+const AUDIO: u8 = 2;
+const CLEAR: u8 = 3;
+const VOLUME: u8 = 4;
+const STATIC_DELAY: u8 = 5;
+
+/// The output a stream is played on
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Opening {
+    device_id: Option<String>,
+    channels: u16,
+    sample_rate: u32,
+    volume: u8,
+    muted: bool,
+}
+
+/// What the playback thread sends the child
+#[derive(Debug, Clone, PartialEq)]
+enum Request {
+    /// Open the output device, closing any open one; answered with a line
+    /// on stdout (see [`reply`])
+    Open(Opening),
+    /// Interleaved samples to be heard `due_in_us` after they're read
+    Audio {
+        due_in_us: i64,
+        samples: Vec<f32>,
+    },
+    Clear,
+    Volume {
+        volume: u8,
+        muted: bool,
+    },
+    StaticDelay(u16),
+}
+
+impl Request {
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut frame = Vec::new();
+        match self {
+            Self::Open(opening) => {
+                frame.push(OPEN);
+                match &opening.device_id {
+                    Some(id) => {
+                        frame.push(1);
+                        frame.extend((id.len() as u32).to_le_bytes());
+                        frame.extend(id.as_bytes());
+                    }
+                    None => frame.push(0),
+                }
+                frame.extend(opening.channels.to_le_bytes());
+                frame.extend(opening.sample_rate.to_le_bytes());
+                frame.push(opening.volume);
+                frame.push(u8::from(opening.muted));
+            }
+            Self::Audio { due_in_us, samples } => {
+                frame.reserve(13 + samples.len() * 4);
+                frame.push(AUDIO);
+                frame.extend(due_in_us.to_le_bytes());
+                frame.extend((samples.len() as u32).to_le_bytes());
+                for sample in samples {
+                    frame.extend(sample.to_le_bytes());
+                }
+            }
+            Self::Clear => frame.push(CLEAR),
+            Self::Volume { volume, muted } => {
+                frame.extend([VOLUME, *volume, u8::from(*muted)]);
+            }
+            Self::StaticDelay(delay_ms) => {
+                frame.push(STATIC_DELAY);
+                frame.extend(delay_ms.to_le_bytes());
+            }
+        }
+        out.write_all(&frame)?;
+        out.flush()
+    }
+
+    /// The next request, or `None` once the app closed the pipe
+    fn read_from(input: &mut impl Read) -> io::Result<Option<Self>> {
+        let [tag] = match read_bytes::<1>(input) {
+            Ok(tag) => tag,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let request = match tag {
+            OPEN => {
+                let device_id = match read_bytes::<1>(input)? {
+                    [0] => None,
+                    _ => {
+                        let len = u32::from_le_bytes(read_bytes(input)?) as usize;
+                        let mut id = vec![0; len];
+                        input.read_exact(&mut id)?;
+                        Some(String::from_utf8(id).map_err(|e| {
+                            io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+                        })?)
+                    }
+                };
+                let channels = u16::from_le_bytes(read_bytes(input)?);
+                let sample_rate = u32::from_le_bytes(read_bytes(input)?);
+                let [volume, muted] = read_bytes(input)?;
+                Self::Open(Opening {
+                    device_id,
+                    channels,
+                    sample_rate,
+                    volume,
+                    muted: muted != 0,
+                })
+            }
+            AUDIO => {
+                let due_in_us = i64::from_le_bytes(read_bytes(input)?);
+                let count = u32::from_le_bytes(read_bytes(input)?) as usize;
+                let mut bytes = vec![0; count * 4];
+                input.read_exact(&mut bytes)?;
+                let samples = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                Self::Audio { due_in_us, samples }
+            }
+            CLEAR => Self::Clear,
+            VOLUME => {
+                let [volume, muted] = read_bytes(input)?;
+                Self::Volume {
+                    volume,
+                    muted: muted != 0,
+                }
+            }
+            STATIC_DELAY => Self::StaticDelay(u16::from_le_bytes(read_bytes(input)?)),
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown request {}", tag),
+                ))
+            }
+        };
+        Ok(Some(request))
+    }
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The line answering an `Open`: `ok`, or the error's kind and message
+fn reply(result: &Result<(), SendspinError>) -> String {
+    match result {
+        Ok(()) => "ok\n".to_string(),
+        Err(e) => format!("{}\t{}\n", e.kind(), e.to_string().replace('\n', " ")),
+    }
+}
+
+fn parse_reply(line: &str) -> Result<(), SendspinError> {
+    let line = line.trim_end();
+    if line == "ok" {
+        return Ok(());
+    }
+    let (kind, message) = line.split_once('\t').unwrap_or(("device", line));
+    let message = message.to_string();
+    Err(match kind {
+        "device_not_found" => SendspinError::DeviceNotFound(message),
+        "device_busy" => SendspinError::DeviceBusy(message),
+        _ => SendspinError::Device(message),
+    })
+}
+
+/// Microseconds from `from` to `to`, negative if `to` is earlier
+fn micros_between(from: Instant, to: Instant) -> i64 {
+    match to.checked_duration_since(from) {
+        Some(after) => after.as_micros() as i64,
+        None => -(from.duration_since(to).as_micros() as i64),
+    }
+}
+
+/// Run as the playback process: play what arrives on stdin until the app
+/// closes it. Returns the exit code.
+pub fn run_child() -> i32 {
+    let input = BufReader::new(io::stdin().lock());
+    match serve(
+        input,
+        io::stdout().lock(),
+        |opening, origin| {
+            Player::open(
+                opening.device_id.as_deref(),
+                opening.channels,
+                opening.sample_rate,
+                origin,
+            )
+        },
+        Player::schedule,
+    ) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("[Sendspin] Playback process: {}", e);
+            1
+        }
+    }
+}
+
+/// Take requests from `input` until it closes, playing on what `open`
+/// opens. Local times are counted in microseconds from when this started.
+fn serve<P>(
+    mut input: impl Read,
+    mut output: impl Write,
+    mut open: impl FnMut(&Opening, Instant) -> Result<P, SendspinError>,
+    schedule_of: impl Fn(&P) -> MutexGuard<'_, Schedule>,
+) -> io::Result<()> {
+    let origin = Instant::now();
+    let mut player = None;
+    while let Some(request) = Request::read_from(&mut input)? {
+        match request {
+            Request::Open(opening) => {
+                player = None;
+                let opened = open(&opening, origin).map(|opened| {
+                    schedule_of(&opened).set_volume(opening.volume, opening.muted);
+                    player = Some(opened);
+                });
+                output.write_all(reply(&opened).as_bytes())?;
+                output.flush()?;
+            }
+            Request::Audio { due_in_us, samples } => {
+                if let Some(player) = &player {
+                    let at = micros_between(origin, Instant::now()) + due_in_us;
+                    schedule_of(player).push(at, samples);
+                }
+            }
+            Request::Clear => {
+                if let Some(player) = &player {
+                    schedule_of(player).clear();
+                }
+            }
+            Request::Volume { volume, muted } => {
+                if let Some(player) = &player {
+                    schedule_of(player).set_volume(volume, muted);
+                }
+            }
+            Request::StaticDelay(delay_ms) => {
+                if let Some(player) = &player {
+                    schedule_of(player).set_static_delay(delay_ms);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A playback process, ended on drop
+struct PlaybackProcess {
+    child: Child,
+    /// To the thread writing to the child, so a child that stopped reading
+    /// can't hold up the playback thread
+    requests: std_mpsc::Sender<Request>,
+    exited: bool,
+}
+
+impl PlaybackProcess {
+    /// Start `command` as a playback process with `opening` open in it
+    fn start(mut command: Command, opening: Opening) -> Result<Self, SendspinError> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                SendspinError::Device(format!("Failed to start the playback process: {}", e))
+            })?;
+        let (Some(mut input), Some(output)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(SendspinError::Device(
+                "Failed to connect to the playback process".to_string(),
+            ));
+        };
+
+        let mut line = String::new();
+        let answered = Request::Open(opening)
+            .write_to(&mut input)
+            .and_then(|()| BufReader::new(output).read_line(&mut line));
+        let opened = match answered {
+            Ok(0) | Err(_) => Err(SendspinError::Device(
+                "The playback process exited".to_string(),
+            )),
+            Ok(_) => parse_reply(&line),
+        };
+        if let Err(e) = opened {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+
+        let (requests, queued) = std_mpsc::channel::<Request>();
+        let process = Self {
+            child,
+            requests,
+            exited: false,
+        };
+        thread::Builder::new()
+            .name("audio-process-writer".to_string())
+            .spawn(move || {
+                for request in queued {
+                    if let Err(e) = request.write_to(&mut input) {
+                        log::warn!("[Sendspin] Playback process stopped reading: {}", e);
+                        return;
+                    }
+                }
+            })
+            .map_err(|e| SendspinError::Device(format!("Failed to start the writer: {}", e)))?;
+        Ok(process)
+    }
+
+    fn send(&mut self, request: Request) {
+        if self.requests.send(request).is_err() {
+            self.exited = true;
+        }
+    }
+
+    /// Whether the process is gone, crashed or otherwise
+    fn exited(&mut self) -> bool {
+        if !self.exited {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                log::warn!("[Sendspin] Playback process exited: {}", status);
+                self.exited = true;
+            }
+        }
+        self.exited
+    }
+}
+
+impl Drop for PlaybackProcess {
+    fn drop(&mut self) {
+        // Also frees the writer thread if the child stopped reading
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Plays through a playback process of its own
+pub(crate) struct IsolatedPlayer {
+    process: RefCell<PlaybackProcess>,
+    clock_sync: Arc<Mutex<ClockSync>>,
+    /// Volume and mute, sent together
+    level: Cell<(u8, bool)>,
+}
+
+impl IsolatedPlayer {
+    /// Start a playback process and open `audio_device_id` (the default
+    /// output if `None`) in it for `format`.
+    pub(crate) fn open(
+        audio_device_id: Option<&str>,
+        format: &AudioFormat,
+        clock_sync: &Arc<Mutex<ClockSync>>,
+        (volume, muted): (u8, bool),
+    ) -> Result<Self, SendspinError> {
+        let exe = std::env::current_exe().map_err(|e| {
+            SendspinError::Device(format!("Failed to find the app executable: {}", e))
+        })?;
+        let mut command = Command::new(exe);
+        command.arg(FLAG);
+        let opening = Opening {
+            device_id: audio_device_id.map(str::to_string),
+            channels: format.channels,
+            sample_rate: format.sample_rate,
+            volume,
+            muted,
+        };
+        Ok(Self {
+            process: RefCell::new(PlaybackProcess::start(command, opening)?),
+            clock_sync: Arc::clone(clock_sync),
+            level: Cell::new((volume, muted)),
+        })
+    }
+
+    fn send(&self, request: Request) {
+        self.process.borrow_mut().send(request);
+    }
+
+    /// Queue `buffer` to play when its timestamp says. Dropped while the
+    /// clock isn't synchronized, as there is no saying when that is.
+    pub(crate) fn enqueue(&self, buffer: &AudioBuffer) {
+        let Some(plays_at) = self
+            .clock_sync
+            .lock()
+            .server_to_local_instant(buffer.timestamp)
+        else {
+            return;
+        };
+        let samples = buffer
+            .samples
+            .iter()
+            .map(|sample| sample.0 as f32 / SAMPLE_SCALE)
+            .collect();
+        self.send(Request::Audio {
+            due_in_us: micros_between(Instant::now(), plays_at),
+            samples,
+        });
+    }
+
+    pub(crate) fn clear(&self) {
+        self.send(Request::Clear);
+    }
+
+    pub(crate) fn set_volume(&self, volume: u8) {
+        let (_, muted) = self.level.get();
+        self.level.set((volume, muted));
+        self.send(Request::Volume { volume, muted });
+    }
+
+    pub(crate) fn set_mute(&self, muted: bool) {
+        let (volume, _) = self.level.get();
+        self.level.set((volume, muted));
+        self.send(Request::Volume { volume, muted });
+    }
+
+    pub(crate) fn set_static_delay(&self, delay_ms: u16) {
+        self.send(Request::StaticDelay(delay_ms));
+    }
+
+    /// Whether the playback process is gone, crashed or otherwise
+    pub(crate) fn exited(&self) -> bool {
+        self.process.borrow_mut().exited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opening() -> Opening {
+        Opening {
+            device_id: Some("USB DAC".to_string()),
+            channels: 1,
+            sample_rate: 1_000,
+            volume: 40,
+            muted: false,
+        }
+    }
+
+    fn lock(schedule: &Arc<Mutex<Schedule>>) -> MutexGuard<'_, Schedule> {
+        schedule.lock()
+    }
+
+    #[test]
+    fn requests_survive_the_pipe() {
+        let requests = [
+            Request::Open(opening()),
+            Request::Open(Opening {
+                device_id: None,
+                ..opening()
+            }),
+            Request::Audio {
+                due_in_us: -1_500,
+                samples: vec![0.25, -1.0, 0.0],
+            },
+            Request::Clear,
+            Request::Volume {
+                volume: 7,
+                muted: true,
+            },
+            Request::StaticDelay(120),
+        ];
+        let mut pipe = Vec::new();
+        for request in &requests {
+            request.write_to(&mut pipe).unwrap();
+        }
+
+        let mut input = pipe.as_slice();
+        for request in requests {
+            assert_eq!(Request::read_from(&mut input).unwrap(), Some(request));
+        }
+        assert_eq!(Request::read_from(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn the_child_plays_what_it_is_sent() {
+        let schedule = Arc::new(Mutex::new(Schedule::new(1, 1_000)));
+        let mut pipe = Vec::new();
+        for request in [
+            Request::Open(opening()),
+            Request::Audio {
+                due_in_us: 0,
+                samples: vec![0.5; 20],
+            },
+        ] {
+            request.write_to(&mut pipe).unwrap();
+        }
+        let mut replies = Vec::new();
+        let mut opened = Vec::new();
+
+        serve(
+            pipe.as_slice(),
+            &mut replies,
+            |opening, _| {
+                opened.push(opening.clone());
+                Ok(Arc::clone(&schedule))
+            },
+            lock,
+        )
+        .unwrap();
+
+        assert_eq!(opened, vec![opening()]);
+        assert_eq!(replies, b"ok\n");
+        assert_eq!(
+            schedule.lock().buffered(),
+            std::time::Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn open_failures_keep_their_kind() {
+        let mut pipe = Vec::new();
+        Request::Open(opening()).write_to(&mut pipe).unwrap();
+        let mut replies = Vec::new();
+
+        serve(
+            pipe.as_slice(),
+            &mut replies,
+            |_, _| {
+                Err(SendspinError::DeviceBusy(
+                    "Device or resource busy".to_string(),
+                ))
+            },
+            lock,
+        )
+        .unwrap();
+
+        let line = String::from_utf8(replies).unwrap();
+        assert_eq!(
+            parse_reply(&line).unwrap_err().kind(),
+            SendspinError::DeviceBusy(String::new()).kind()
+        );
+    }
+
+    #[cfg(unix)]
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_child_that_dies_before_opening_is_an_open_failure() {
+        let result = PlaybackProcess::start(shell("exit 3"), opening());
+        assert_eq!(result.err().map(|e| e.kind()), Some("device"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_child_that_crashes_while_playing_is_noticed() {
+        // Answers the open, then dies
+        let mut process = PlaybackProcess::start(
+            shell("dd bs=1 count=1 2>/dev/null; echo ok; kill -9 $$"),
+            opening(),
+        )
+        .unwrap();
+
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while !process.exited() {
+            assert!(Instant::now() < deadline, "the crash wasn't noticed");
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // Nothing to play to any more, and nothing blocks
+        process.send(Request::Clear);
+    }
+}
@@ -0,0 +1,54 @@
+//! Bluetooth output detection
+//!
+//! A2DP adds 100–300 ms of latency the OS mostly doesn't report, which puts
+//! a Bluetooth player audibly behind the rest of a group. Players on a
+//! Bluetooth output start with a larger sync delay and ask the server for
+//! more buffer.
+
+use super::transport::{self, Transport};
+
+/// Sync delay seeded for a Bluetooth device that has none stored yet
+pub const DEFAULT_SYNC_DELAY_MS: i32 = 200;
+
+/// Name fragments of Bluetooth outputs, for platforms where the transport
+/// can't be queried (see `transport`)
+const NAME_HINTS: &[&str] = &[
+    "bluetooth",
+    "bluez",
+    "bluealsa",
+    "a2dp",
+    "airpods",
+    "hands-free",
+    "handsfree",
+];
+
+/// Whether a device name looks like a Bluetooth output
+pub(super) fn name_suggests_bluetooth(name: &str) -> bool {
+    let name = name.to_lowercase();
+    NAME_HINTS.iter().any(|hint| name.contains(hint))
+}
+
+/// Whether `device` is a Bluetooth output
+pub fn is_bluetooth(device: &cpal::Device) -> bool {
+    transport::of_device(device) == Transport::Bluetooth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_bluetooth_names() {
+        assert!(name_suggests_bluetooth("Bluetooth Audio Renderer"));
+        assert!(name_suggests_bluetooth("AirPods Pro"));
+        assert!(name_suggests_bluetooth("bluez_output.00_1B_66.a2dp-sink"));
+        assert!(name_suggests_bluetooth("Headset (Hands-Free AG Audio)"));
+    }
+
+    #[test]
+    fn ignores_wired_outputs() {
+        assert!(!name_suggests_bluetooth("MacBook Pro Speakers"));
+        assert!(!name_suggests_bluetooth("Speakers (Realtek(R) Audio)"));
+        assert!(!name_suggests_bluetooth("hw:CARD=DAC,DEV=0"));
+    }
+}
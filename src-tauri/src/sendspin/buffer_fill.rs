@@ -0,0 +1,98 @@
+//! Keeping the player's buffer within the capacity told to the server
+//!
+//! `client/hello` advertises how many bytes of audio the player can hold
+//! (`buffer_capacity`), and the server shouldn't send further ahead than
+//! that. A server that does anyway would otherwise grow the player's queue
+//! without bound.
+//!
+//! The fill is how far ahead the queued audio reaches by its timestamps:
+//! from now to when the end of the last chunk queued is due. That is what
+//! the server decides. The output device isn't looked at, so a device that
+//! stops playing isn't caught here.
+//!
+//! Past [`HIGH_WATER`] of the capacity the buffer counts as nearly full: the
+//! client loop asks the server for its base buffer again in `client/state`
+//! (`client/state` has no field for the fill level itself), and the fill
+//! level shows in the player's stats. A chunk that doesn't fit at all is
+//! rejected rather than making room by dropping the oldest audio: that is
+//! already queued on the player, which can't drop part of its queue, and it
+//! is what plays next, so dropping it would be heard right away.
+
+/// Fraction of the capacity past which the buffer counts as nearly full
+const HIGH_WATER: f64 = 0.9;
+/// Fraction it has to drain below before it counts as having room again
+const LOW_WATER: f64 = 0.75;
+
+/// What to do with an arriving chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Admission {
+    /// Whether the chunk fits and should be queued
+    pub(crate) queue: bool,
+    /// Set when the buffer just became nearly full (`true`) or has room
+    /// again (`false`)
+    pub(crate) nearly_full: Option<bool>,
+}
+
+#[derive(Debug)]
+pub(crate) struct BufferFill {
+    capacity: u64,
+    nearly_full: bool,
+}
+
+impl BufferFill {
+    pub(crate) fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            nearly_full: false,
+        }
+    }
+
+    /// Check a chunk of `chunk_bytes` arriving while `buffered_bytes` are
+    /// still waiting to play
+    pub(crate) fn admit(&mut self, buffered_bytes: u64, chunk_bytes: u64) -> Admission {
+        let fill = buffered_bytes as f64 / self.capacity.max(1) as f64;
+        let was_nearly_full = self.nearly_full;
+        if fill >= HIGH_WATER {
+            self.nearly_full = true;
+        } else if fill < LOW_WATER {
+            self.nearly_full = false;
+        }
+        Admission {
+            queue: buffered_bytes + chunk_bytes <= self.capacity,
+            nearly_full: (self.nearly_full != was_nearly_full).then_some(self.nearly_full),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_crossing_the_watermarks_once() {
+        let mut fill = BufferFill::new(1000);
+        let queued = |nearly_full| Admission {
+            queue: true,
+            nearly_full,
+        };
+        assert_eq!(fill.admit(500, 50), queued(None));
+        assert_eq!(fill.admit(900, 50), queued(Some(true)));
+        assert_eq!(fill.admit(920, 50), queued(None));
+        // Still nearly full until it drains below the low watermark
+        assert_eq!(fill.admit(800, 50), queued(None));
+        assert_eq!(fill.admit(700, 50), queued(Some(false)));
+    }
+
+    #[test]
+    fn rejects_chunks_that_do_not_fit() {
+        let mut fill = BufferFill::new(1000);
+        assert_eq!(
+            fill.admit(980, 50),
+            Admission {
+                queue: false,
+                nearly_full: Some(true),
+            }
+        );
+        assert!(fill.admit(950, 50).queue);
+    }
+}
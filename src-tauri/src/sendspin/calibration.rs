@@ -0,0 +1,321 @@
+//! Sync delay calibration
+//!
+//! Plays a known test signal (short 1 kHz tone bursts) on an output device
+//! and measures how long it takes to actually come out. The device-reported
+//! output latency is always available; if a loopback input is given (a
+//! microphone near the speakers, or a cable from the output back into an
+//! input), the bursts are also detected there, which catches latency the
+//! device doesn't report — Bluetooth in particular.
+
+use super::devices;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Frequency of the test tone
+const TONE_HZ: f32 = 1000.0;
+/// Amplitude of the test tone (0-1)
+const TONE_AMPLITUDE: f32 = 0.5;
+/// Silence before the first burst, so the stream has settled
+const LEAD_IN: Duration = Duration::from_millis(300);
+/// Length of each tone burst
+const BURST_LENGTH: Duration = Duration::from_millis(30);
+/// Time from one burst's start to the next. Longer than any plausible
+/// output latency so each detection matches exactly one burst.
+const BURST_INTERVAL: Duration = Duration::from_millis(700);
+/// Number of bursts played
+const BURST_COUNT: u32 = 6;
+/// Input level a burst has to exceed to be detected
+const DETECT_THRESHOLD: f32 = 0.05;
+
+/// Outcome of a calibration run
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationResult {
+    pub audio_device_id: Option<String>,
+    /// Output latency reported by the device
+    pub reported_latency_ms: Option<f64>,
+    /// Latency measured through the loopback input, if one was used
+    pub measured_latency_ms: Option<f64>,
+    /// Bursts detected on the loopback input, out of `BURST_COUNT`
+    pub detected_bursts: u32,
+    /// Suggested `sync_delay_ms` for players on this device
+    pub suggested_sync_delay_ms: i32,
+}
+
+/// Whether output frame `frame` is the first frame of a tone burst
+fn is_burst_onset(frame: u64, sample_rate: u32) -> bool {
+    let rate = u64::from(sample_rate);
+    let lead_in = LEAD_IN.as_micros() as u64 * rate / 1_000_000;
+    let interval = BURST_INTERVAL.as_micros() as u64 * rate / 1_000_000;
+    frame >= lead_in
+        && (frame - lead_in) % interval == 0
+        && (frame - lead_in) / interval < u64::from(BURST_COUNT)
+}
+
+/// Sample of the test signal at output frame `frame`
+fn test_signal(frame: u64, sample_rate: u32) -> f32 {
+    let rate = u64::from(sample_rate);
+    let lead_in = LEAD_IN.as_micros() as u64 * rate / 1_000_000;
+    let interval = BURST_INTERVAL.as_micros() as u64 * rate / 1_000_000;
+    let burst = BURST_LENGTH.as_micros() as u64 * rate / 1_000_000;
+    if frame < lead_in || interval == 0 {
+        return 0.0;
+    }
+    let offset = frame - lead_in;
+    if offset / interval >= u64::from(BURST_COUNT) || offset % interval >= burst {
+        return 0.0;
+    }
+    let t = (offset % interval) as f32 / sample_rate as f32;
+    TONE_AMPLITUDE * (2.0 * std::f32::consts::PI * TONE_HZ * t).sin()
+}
+
+/// Total length of the test signal
+fn signal_length() -> Duration {
+    LEAD_IN + BURST_INTERVAL * BURST_COUNT
+}
+
+/// Pair each detected burst with the burst that was emitted before it and
+/// return the delays.
+fn match_latencies(emitted: &[Instant], detected: &[Instant]) -> Vec<Duration> {
+    detected
+        .iter()
+        .filter_map(|&heard| {
+            emitted
+                .iter()
+                .filter(|&&sent| sent <= heard)
+                .max()
+                .map(|&sent| heard - sent)
+                .filter(|latency| *latency < BURST_INTERVAL)
+        })
+        .collect()
+}
+
+fn median_ms(values: &mut [Duration]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    Some(values[values.len() / 2].as_secs_f64() * 1000.0)
+}
+
+/// Names of the input devices usable as a loopback
+pub fn input_devices() -> Result<Vec<String>, String> {
+    Ok(cpal::default_host()
+        .input_devices()
+        .map_err(|e| format!("Failed to enumerate input devices: {}", e))?
+        .filter_map(|d| d.description().ok().map(|desc| desc.name().to_string()))
+        .collect())
+}
+
+pub(super) fn find_input_device(device_id: &str) -> Result<cpal::Device, String> {
+    cpal::default_host()
+        .input_devices()
+        .map_err(|e| format!("Failed to enumerate input devices: {}", e))?
+        .find(|d| d.description().is_ok_and(|desc| desc.name() == device_id))
+        .ok_or_else(|| format!("Input device not found: {}", device_id))
+}
+
+#[derive(Default)]
+struct Timings {
+    /// When each burst's first sample was handed to the device
+    emitted: Vec<Instant>,
+    /// Output latency reported with each callback
+    reported: Vec<Duration>,
+    /// When each burst was captured on the loopback input
+    detected: Vec<Instant>,
+}
+
+/// Play the test signal on `audio_device_id` (the default output if `None`)
+/// and measure its latency, listening on `input_device_id` if given.
+///
+/// Blocks for the length of the test signal.
+pub fn run(
+    audio_device_id: Option<&str>,
+    input_device_id: Option<&str>,
+) -> Result<CalibrationResult, String> {
+    let device = devices::resolve_output_device(audio_device_id)
+        .ok_or_else(|| "No output device available".to_string())?;
+    let supported = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get output config: {}", e))?;
+    let config = supported.config();
+    let sample_rate = config.sample_rate;
+    let channels = usize::from(config.channels);
+    let timings = Arc::new(Mutex::new(Timings::default()));
+
+    log::info!(
+        "[Sendspin] Calibrating {} at {}Hz{}",
+        audio_device_id.unwrap_or("the default output"),
+        sample_rate,
+        input_device_id.map_or_else(String::new, |id| format!(", listening on {id}"))
+    );
+
+    let input_stream = input_device_id
+        .map(|id| {
+            let input = find_input_device(id)?;
+            let input_config = input
+                .default_input_config()
+                .map_err(|e| format!("Failed to get input config: {}", e))?
+                .config();
+            let input_rate = f64::from(input_config.sample_rate);
+            let input_channels = usize::from(input_config.channels);
+            let timings = Arc::clone(&timings);
+            let mut quiet_until: Option<Instant> = None;
+            input
+                .build_input_stream(
+                    &input_config,
+                    move |data: &[f32], info: &cpal::InputCallbackInfo| {
+                        let now = Instant::now();
+                        let ts = info.timestamp();
+                        let captured =
+                            now - ts.callback.duration_since(&ts.capture).unwrap_or_default();
+                        for (i, frame) in data.chunks(input_channels).enumerate() {
+                            let at = captured + Duration::from_secs_f64(i as f64 / input_rate);
+                            if quiet_until.is_some_and(|q| at < q) {
+                                continue;
+                            }
+                            if frame.iter().any(|s| s.abs() > DETECT_THRESHOLD) {
+                                timings.lock().detected.push(at);
+                                quiet_until = Some(at + BURST_INTERVAL / 2);
+                            }
+                        }
+                    },
+                    |e| log::warn!("[Sendspin] Calibration input stream error: {}", e),
+                    None,
+                )
+                .map_err(|e| format!("Failed to open input stream: {}", e))
+        })
+        .transpose()?;
+
+    let output_timings = Arc::clone(&timings);
+    let mut frames_written: u64 = 0;
+    let output_stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                let now = Instant::now();
+                let ts = info.timestamp();
+                let mut timings = output_timings.lock();
+                if let Some(latency) = ts.playback.duration_since(&ts.callback) {
+                    timings.reported.push(latency);
+                }
+                for (i, frame) in data.chunks_mut(channels).enumerate() {
+                    let n = frames_written + i as u64;
+                    if is_burst_onset(n, sample_rate) {
+                        timings
+                            .emitted
+                            .push(now + Duration::from_secs_f64(i as f64 / f64::from(sample_rate)));
+                    }
+                    frame.fill(test_signal(n, sample_rate));
+                }
+                frames_written += (data.len() / channels.max(1)) as u64;
+            },
+            |e| log::warn!("[Sendspin] Calibration output stream error: {}", e),
+            None,
+        )
+        .map_err(|e| format!("Failed to open output stream: {}", e))?;
+
+    if let Some(ref input) = input_stream {
+        input
+            .play()
+            .map_err(|e| format!("Failed to start input stream: {}", e))?;
+    }
+    output_stream
+        .play()
+        .map_err(|e| format!("Failed to start output stream: {}", e))?;
+
+    // Let the last burst play out and reach the input
+    std::thread::sleep(signal_length() + BURST_INTERVAL);
+    drop(output_stream);
+    drop(input_stream);
+
+    let mut timings = std::mem::take(&mut *timings.lock());
+    let reported_latency_ms = median_ms(&mut timings.reported);
+    let mut measured = match_latencies(&timings.emitted, &timings.detected);
+    let detected_bursts = measured.len() as u32;
+    let measured_latency_ms = median_ms(&mut measured);
+    let suggested_sync_delay_ms = measured_latency_ms
+        .or(reported_latency_ms)
+        .map_or(0, |ms| ms.round() as i32)
+        .clamp(0, 5_000);
+
+    log::info!(
+        "[Sendspin] Calibration: reported {:?}ms, measured {:?}ms ({}/{} bursts), suggesting {}ms",
+        reported_latency_ms,
+        measured_latency_ms,
+        detected_bursts,
+        BURST_COUNT,
+        suggested_sync_delay_ms
+    );
+
+    if input_device_id.is_some() && detected_bursts == 0 {
+        return Err(
+            "The test signal wasn't picked up on the input; check the volume and input level"
+                .to_string(),
+        );
+    }
+
+    Ok(CalibrationResult {
+        audio_device_id: audio_device_id.map(str::to_string),
+        reported_latency_ms,
+        measured_latency_ms,
+        detected_bursts,
+        suggested_sync_delay_ms,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)] // silence is exactly 0.0
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_is_silent_outside_bursts() {
+        let rate = 48_000;
+        assert_eq!(test_signal(0, rate), 0.0);
+        let first_burst = u64::from(rate) * 300 / 1000;
+        assert!(is_burst_onset(first_burst, rate));
+        assert!(!is_burst_onset(first_burst + 1, rate));
+        // A quarter period into the burst is the tone's peak
+        assert!((test_signal(first_burst + 12, rate) - TONE_AMPLITUDE).abs() < 1e-3);
+        // After the burst, silence until the next one
+        assert_eq!(test_signal(first_burst + 48 * 40, rate), 0.0);
+        assert!(is_burst_onset(first_burst + 48 * 700, rate));
+    }
+
+    #[test]
+    fn signal_stops_after_the_last_burst() {
+        let rate = 44_100;
+        let lead_in = u64::from(rate) * 300 / 1000;
+        let interval = u64::from(rate) * 700 / 1000;
+        let after = lead_in + interval * u64::from(BURST_COUNT);
+        assert!(!is_burst_onset(after, rate));
+        assert_eq!(test_signal(after + 10, rate), 0.0);
+    }
+
+    #[test]
+    fn latencies_pair_with_the_preceding_burst() {
+        let t0 = Instant::now();
+        let emitted = [t0, t0 + BURST_INTERVAL, t0 + BURST_INTERVAL * 2];
+        let detected = [
+            t0 + Duration::from_millis(120),
+            t0 + BURST_INTERVAL + Duration::from_millis(130),
+        ];
+        let mut latencies = match_latencies(&emitted, &detected);
+        assert_eq!(
+            latencies,
+            [Duration::from_millis(120), Duration::from_millis(130)]
+        );
+        assert_eq!(median_ms(&mut latencies), Some(130.0));
+    }
+
+    #[test]
+    fn detections_before_any_burst_are_ignored() {
+        let t0 = Instant::now();
+        let emitted = [t0 + Duration::from_millis(500)];
+        assert!(match_latencies(&emitted, &[t0]).is_empty());
+        assert_eq!(median_ms(&mut []), None);
+    }
+}
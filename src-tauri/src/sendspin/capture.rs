@@ -0,0 +1,488 @@
+//! Audio capture
+//!
+//! Captures audio on this computer and serves it over HTTP as a live WAVE
+//! stream, then has the MA server play that stream on one of its players:
+//! whatever the computer is playing, to send it to other speakers in the
+//! house, or a microphone or line-in, as an announcement over what a player
+//! is playing (an intercom, with push-to-talk from a global shortcut).
+//!
+//! The system output is captured as a loopback: WASAPI loopback of the
+//! default output device on Windows, a `CoreAudio` tap of it on macOS (14.6 or
+//! later, which asks for permission to record system audio the first time),
+//! and the monitor of the default sink through `PulseAudio` or `PipeWire` on
+//! Linux. If the built-in player plays on the same device, it is captured
+//! too. The stream is served on all interfaces under an unguessable path,
+//! for as long as the capture runs.
+
+use super::pcm::{self, PcmFormat};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Bit depth of the served stream
+const BIT_DEPTH: u16 = 16;
+/// Captured chunks a listener can fall behind by before it misses some
+const LISTENER_BACKLOG: usize = 64;
+/// How often the server checks whether the capture has stopped
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+/// Most bytes of request head read from a listener
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// Whether the push-to-talk shortcut is held
+static TALKING: AtomicBool = AtomicBool::new(false);
+
+/// What a capture records
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Whatever the computer is playing, played as a stream
+    SystemAudio,
+    /// A microphone or line-in (the default input if `None`), played as an
+    /// announcement
+    Input(Option<String>),
+}
+
+/// A running capture
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureStatus {
+    pub source: Source,
+    /// MA player the capture plays on
+    pub player_id: String,
+    /// Where the stream is served
+    pub url: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Where each listener's chunks go
+type Listeners = Arc<Mutex<Vec<std_mpsc::SyncSender<Arc<[u8]>>>>>;
+
+struct Capture {
+    status: CaptureStatus,
+    /// Ends the capture stream's thread when dropped
+    _stop_tx: std_mpsc::Sender<()>,
+    running: Arc<AtomicBool>,
+    listeners: Listeners,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        // Ends each listener's stream
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.clear();
+        }
+    }
+}
+
+static ACTIVE: Mutex<Option<Capture>> = Mutex::new(None);
+
+/// Address of this computer on the network the MA server at
+/// `server_base_url` is on
+fn local_address(server_base_url: &str) -> Result<IpAddr, String> {
+    let url =
+        tauri::Url::parse(server_base_url).map_err(|e| format!("Invalid server URL: {}", e))?;
+    let host = url
+        .host_str()
+        .ok_or("Server URL has no host")?
+        .trim_matches(['[', ']']);
+    let port = url.port_or_known_default().unwrap_or(80);
+    let server = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", host))?;
+    let unspecified = if server.is_ipv4() {
+        IpAddr::from(Ipv4Addr::UNSPECIFIED)
+    } else {
+        IpAddr::from(Ipv6Addr::UNSPECIFIED)
+    };
+    let socket =
+        UdpSocket::bind((unspecified, 0)).map_err(|e| format!("Failed to open a socket: {}", e))?;
+    // Connecting a UDP socket sends nothing; it only picks the route
+    socket
+        .connect(server)
+        .map_err(|e| format!("No route to {}: {}", host, e))?;
+    socket
+        .local_addr()
+        .map(|address| address.ip())
+        .map_err(|e| format!("Failed to find the local address: {}", e))
+}
+
+/// The device to capture the system output from, and its stream config
+#[cfg(not(target_os = "linux"))]
+fn loopback_device() -> Result<(cpal::Device, cpal::StreamConfig), String> {
+    // An input stream on an output device is its loopback
+    let device = super::devices::get_default_device()?;
+    let config = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get output config: {}", e))?
+        .config();
+    Ok((device, config))
+}
+
+/// The device to capture the system output from, and its stream config
+#[cfg(target_os = "linux")]
+fn loopback_device() -> Result<(cpal::Device, cpal::StreamConfig), String> {
+    let device = cpal::default_host()
+        .input_devices()
+        .map_err(|e| format!("Failed to enumerate input devices: {}", e))?
+        .find(|d| d.description().is_ok_and(|desc| desc.name() == "pulse"))
+        .ok_or("Capturing system audio needs PulseAudio or PipeWire")?;
+    let config = device
+        .default_input_config()
+        .map_err(|e| format!("Failed to get input config: {}", e))?
+        .config();
+    Ok((device, config))
+}
+
+/// The input device `device_id` (the default input if `None`), and its
+/// stream config
+fn input_device(device_id: Option<&str>) -> Result<(cpal::Device, cpal::StreamConfig), String> {
+    let device = match device_id {
+        Some(id) => super::calibration::find_input_device(id)?,
+        None => cpal::default_host()
+            .default_input_device()
+            .ok_or("No input device available")?,
+    };
+    let config = device
+        .default_input_config()
+        .map_err(|e| format!("Failed to get input config: {}", e))?
+        .config();
+    Ok((device, config))
+}
+
+/// Open a capture of `source`, handing each chunk to `listeners` as 16-bit
+/// PCM.
+fn open_source(source: &Source, listeners: Listeners) -> Result<(cpal::Stream, PcmFormat), String> {
+    let (device, config) = match source {
+        Source::SystemAudio => loopback_device()?,
+        Source::Input(device_id) => input_device(device_id.as_deref())?,
+    };
+    let format = PcmFormat {
+        sample_rate: config.sample_rate,
+        channels: config.channels,
+        bit_depth: BIT_DEPTH,
+    };
+    // The PulseAudio ALSA plugin records from the source this names when it
+    // opens, and PipeWire's PulseAudio server honours it too. Other inputs
+    // opened later shouldn't be redirected, so it only stays set meanwhile.
+    #[cfg(target_os = "linux")]
+    if *source == Source::SystemAudio {
+        std::env::set_var("PULSE_SOURCE", "@DEFAULT_MONITOR@");
+    }
+    let stream = device.build_input_stream(
+        &config,
+        move |data: &[f32], _: &cpal::InputCallbackInfo| {
+            let chunk: Arc<[u8]> = pcm::from_f32(data, BIT_DEPTH).into();
+            if let Ok(mut listeners) = listeners.lock() {
+                // A listener that has fallen behind misses this chunk
+                listeners.retain(|tx| {
+                    !matches!(
+                        tx.try_send(Arc::clone(&chunk)),
+                        Err(std_mpsc::TrySendError::Disconnected(_))
+                    )
+                });
+            }
+        },
+        |e| log::warn!("[Capture] Capture stream error: {}", e),
+        None,
+    );
+    #[cfg(target_os = "linux")]
+    std::env::remove_var("PULSE_SOURCE");
+    let stream = stream.map_err(|e| format!("Failed to open audio capture: {}", e))?;
+    stream
+        .play()
+        .map_err(|e| format!("Failed to start audio capture: {}", e))?;
+    Ok((stream, format))
+}
+
+/// Serve the stream at `path` until `running` clears.
+fn serve(
+    listener: &TcpListener,
+    path: &str,
+    format: PcmFormat,
+    listeners: &Listeners,
+    running: &AtomicBool,
+) {
+    while running.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, from)) => {
+                let path = path.to_string();
+                let listeners = Arc::clone(listeners);
+                thread::spawn(move || {
+                    if let Err(e) = serve_listener(stream, &path, format, &listeners) {
+                        log::debug!("[Capture] Stream to {} ended: {}", from, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+            Err(e) => {
+                log::warn!("[Capture] Failed to accept a listener: {}", e);
+                thread::sleep(ACCEPT_POLL);
+            }
+        }
+    }
+}
+
+/// Answer one request, streaming to it if it's for `path`.
+fn serve_listener(
+    mut stream: TcpStream,
+    path: &str,
+    format: PcmFormat,
+    listeners: &Listeners,
+) -> Result<(), String> {
+    stream
+        .set_nonblocking(false)
+        .map_err(|e| format!("Failed to set up connection: {}", e))?;
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST {
+            return Err("Request too large".to_string());
+        }
+        let read = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("Connection closed mid-request".to_string());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let requested = request_line.next().unwrap_or_default();
+    if requested != path || !matches!(method, "GET" | "HEAD") {
+        return stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .map_err(|e| e.to_string());
+    }
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nCache-Control: no-cache\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .map_err(|e| e.to_string())?;
+    if method == "HEAD" {
+        return Ok(());
+    }
+    stream
+        .write_all(&pcm::wave_header(format))
+        .map_err(|e| e.to_string())?;
+
+    let (tx, rx) = std_mpsc::sync_channel(LISTENER_BACKLOG);
+    listeners
+        .lock()
+        .map_err(|_| "Capture stopped".to_string())?
+        .push(tx);
+    // Until the capture stops or the listener goes away
+    for chunk in rx {
+        stream.write_all(&chunk).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Capture `source` and play it on the MA player `player_id`, replacing any
+/// capture already running.
+///
+/// Blocks while the MA server is asked to play the stream.
+pub fn start(player_id: &str, source: Source) -> Result<CaptureStatus, String> {
+    stop_capture();
+    let session = crate::ma_api::current_session()
+        .ok_or("Connect to a Music Assistant server to send audio to it")?;
+    let address = local_address(&session.server_base_url)?;
+
+    let listeners: Listeners = Arc::default();
+    let (stop_tx, stop_rx) = std_mpsc::channel::<()>();
+    let (ready_tx, ready_rx) = std_mpsc::channel::<Result<PcmFormat, String>>();
+    let capturing = Arc::clone(&listeners);
+    // cpal streams can't move between threads on every platform, so the
+    // stream lives on its own thread until the capture stops
+    let opening = source.clone();
+    thread::spawn(move || match open_source(&opening, capturing) {
+        Ok((stream, format)) => {
+            let _ = ready_tx.send(Ok(format));
+            let _ = stop_rx.recv();
+            drop(stream);
+        }
+        Err(e) => {
+            let _ = ready_tx.send(Err(e));
+        }
+    });
+    let format = ready_rx
+        .recv()
+        .map_err(|_| "Capture thread exited".to_string())??;
+
+    let listener = TcpListener::bind((address, 0))
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        .map_err(|e| format!("Failed to start the capture server: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let path = format!("/capture/{}.wav", uuid::Uuid::new_v4().simple());
+    let url = format!("http://{}{}", SocketAddr::new(address, port), path);
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let listeners = Arc::clone(&listeners);
+        let running = Arc::clone(&running);
+        thread::spawn(move || serve(&listener, &path, format, &listeners, &running));
+    }
+
+    let capture = Capture {
+        status: CaptureStatus {
+            source,
+            player_id: player_id.to_string(),
+            url,
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+        },
+        _stop_tx: stop_tx,
+        running,
+        listeners,
+    };
+    let status = capture.status.clone();
+    log::info!(
+        "[Capture] Capturing {:?} at {}Hz, {} channels, for {} from {}",
+        status.source,
+        format.sample_rate,
+        format.channels,
+        player_id,
+        status.url
+    );
+    match status.source {
+        Source::SystemAudio => crate::ma_api::play_media(player_id, &status.url)?,
+        Source::Input(_) => {
+            // Answered only once the announcement has played, which is when
+            // the capture stops
+            let (player_id, url) = (player_id.to_string(), status.url.clone());
+            thread::spawn(move || {
+                if let Err(e) = crate::ma_api::play_announcement(&player_id, &url) {
+                    log::warn!("[Capture] Announcement on {} failed: {}", player_id, e);
+                }
+            });
+        }
+    }
+    if let Ok(mut active) = ACTIVE.lock() {
+        *active = Some(capture);
+    }
+    Ok(status)
+}
+
+/// End the capture, if one is running, and return what it was.
+fn stop_capture() -> Option<CaptureStatus> {
+    let capture = ACTIVE.lock().ok()?.take()?;
+    log::info!("[Capture] Stopped capturing {:?}", capture.status.source);
+    Some(capture.status.clone())
+}
+
+/// Stop capturing. A stream of the system audio also stops the player it
+/// was playing on; an announcement just ends, and the player goes back to
+/// what it was playing.
+///
+/// Blocks while the MA server is asked to stop.
+pub fn stop() -> Result<(), String> {
+    match stop_capture() {
+        Some(status) if status.source == Source::SystemAudio => {
+            crate::ma_api::player_command(&status.player_id, "stop")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Start announcing from the default input on `player_id` when the
+/// push-to-talk shortcut is pressed, and stop when it's released.
+pub fn push_to_talk(pressed: bool, player_id: Option<String>) {
+    // Held keys repeat their presses
+    if TALKING.swap(pressed, Ordering::Relaxed) == pressed {
+        return;
+    }
+    thread::spawn(move || {
+        let result = if pressed {
+            match player_id {
+                Some(player_id) => start(&player_id, Source::Input(None)).map(|_| {
+                    // Released while the announcement was starting
+                    if !TALKING.load(Ordering::Relaxed) {
+                        let _ = stop();
+                    }
+                }),
+                None => Err("No player selected".to_string()),
+            }
+        } else {
+            stop()
+        };
+        if let Err(e) = result {
+            log::warn!("[Capture] Push-to-talk failed: {}", e);
+        }
+    });
+}
+
+/// The capture that's running, if any
+pub fn status() -> Option<CaptureStatus> {
+    ACTIVE
+        .lock()
+        .ok()?
+        .as_ref()
+        .map(|capture| capture.status.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(address: SocketAddr, request: &[u8]) -> TcpStream {
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(request).unwrap();
+        client
+    }
+
+    #[test]
+    fn streams_captured_audio_to_listeners_of_the_path() {
+        let server = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = server.local_addr().unwrap();
+        let format = PcmFormat {
+            sample_rate: 48_000,
+            channels: 2,
+            bit_depth: BIT_DEPTH,
+        };
+        let listeners: Listeners = Arc::default();
+
+        let mut client = request(address, b"GET /other.wav HTTP/1.1\r\n\r\n");
+        let (stream, _) = server.accept().unwrap();
+        serve_listener(stream, "/capture/a.wav", format, &listeners).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+        assert!(listeners.lock().unwrap().is_empty());
+
+        let mut client = request(address, b"GET /capture/a.wav HTTP/1.1\r\n\r\n");
+        let (stream, _) = server.accept().unwrap();
+        let serving = Arc::clone(&listeners);
+        let handle =
+            thread::spawn(move || serve_listener(stream, "/capture/a.wav", format, &serving));
+        while listeners.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let chunk: Arc<[u8]> = Arc::from(&[1u8, 2, 3, 4][..]);
+        listeners.lock().unwrap()[0].send(chunk).unwrap();
+        // Stopping the capture ends the stream
+        listeners.lock().unwrap().clear();
+        handle.join().unwrap().unwrap();
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        let body_start = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\n"));
+        let body = &response[body_start..];
+        assert_eq!(pcm::parse_wave_header(body), Ok((format, Some(44))));
+        assert_eq!(&body[44..], [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Output channel mapping.
+//!
+//! Applied to the PCM going to the player, after all other processing. It
+//! can swap left and right (speakers wired the wrong way round) and route a
+//! stereo stream to any pair of outputs of a multichannel interface, which
+//! is then opened with enough channels for that pair; the other outputs get
+//! silence.
+
+use crate::settings::OutputProfile;
+
+/// Where each channel of the stream goes on the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChannelMap {
+    /// Device channel of each stream channel.
+    targets: Vec<usize>,
+    output_channels: usize,
+}
+
+impl ChannelMap {
+    /// The mapping `profile` asks for on a `channels`-channel stream, or
+    /// `None` if it leaves every channel where it is. Routing applies to
+    /// stereo streams only; swapping exchanges the front pair of any layout.
+    pub(crate) fn for_profile(profile: &OutputProfile, channels: u16) -> Option<Self> {
+        let channels = usize::from(channels);
+        let mut targets: Vec<usize> = (0..channels).collect();
+        if channels == 2 {
+            if let Some([left, right]) = profile.stereo_channels {
+                targets = vec![usize::from(left.max(1)) - 1, usize::from(right.max(1)) - 1];
+            }
+        }
+        if profile.swap_channels && channels >= 2 {
+            targets.swap(0, 1);
+        }
+        let output_channels = targets
+            .iter()
+            .map(|&target| target + 1)
+            .max()
+            .unwrap_or(0)
+            .max(channels);
+        let map = Self {
+            targets,
+            output_channels,
+        };
+        (!map.is_identity()).then_some(map)
+    }
+
+    fn is_identity(&self) -> bool {
+        self.output_channels == self.targets.len()
+            && self
+                .targets
+                .iter()
+                .enumerate()
+                .all(|(index, &target)| index == target)
+    }
+
+    /// Channels of the stream going in.
+    pub(crate) fn input_channels(&self) -> u16 {
+        self.targets.len() as u16
+    }
+
+    /// Channels to open the device with.
+    pub(crate) fn output_channels(&self) -> u16 {
+        self.output_channels as u16
+    }
+
+    /// Open the device with `channels` channels instead of the fewest the
+    /// mapping needs, for devices that only offer wider configs. Ignored if
+    /// it's too few.
+    pub(crate) fn widen(&mut self, channels: u16) {
+        self.output_channels = self.output_channels.max(usize::from(channels));
+    }
+
+    /// Map a chunk of interleaved little-endian PCM with `bytes_per_sample`
+    /// bytes per sample into `out`, replacing its contents.
+    pub(crate) fn apply(&self, bytes: &[u8], bytes_per_sample: usize, out: &mut Vec<u8>) {
+        out.clear();
+        let input_frame = self.targets.len() * bytes_per_sample;
+        if input_frame == 0 {
+            return;
+        }
+        let output_frame = self.output_channels * bytes_per_sample;
+        let frames = bytes.len() / input_frame;
+        out.resize(frames * output_frame, 0);
+        for (input, output) in bytes
+            .chunks_exact(input_frame)
+            .zip(out.chunks_exact_mut(output_frame))
+        {
+            for (sample, &target) in input.chunks_exact(bytes_per_sample).zip(&self.targets) {
+                let start = target * bytes_per_sample;
+                output[start..start + bytes_per_sample].copy_from_slice(sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(swap_channels: bool, stereo_channels: Option<[u16; 2]>) -> OutputProfile {
+        OutputProfile {
+            swap_channels,
+            stereo_channels,
+            ..OutputProfile::default()
+        }
+    }
+
+    fn apply(map: &ChannelMap, bytes: &[u8], bytes_per_sample: usize) -> Vec<u8> {
+        // Left over from a previous chunk, to be replaced
+        let mut out = vec![9; 3];
+        map.apply(bytes, bytes_per_sample, &mut out);
+        out
+    }
+
+    #[test]
+    fn default_profile_maps_nothing() {
+        assert_eq!(ChannelMap::for_profile(&OutputProfile::default(), 2), None);
+        assert_eq!(
+            ChannelMap::for_profile(&profile(false, Some([1, 2])), 2),
+            None
+        );
+        assert_eq!(ChannelMap::for_profile(&profile(true, None), 1), None);
+    }
+
+    #[test]
+    fn swaps_left_and_right() {
+        let map = ChannelMap::for_profile(&profile(true, None), 2).unwrap();
+        // Two 16-bit frames
+        let bytes = [1, 0, 2, 0, 3, 0, 4, 0];
+        assert_eq!(apply(&map, &bytes, 2), [2, 0, 1, 0, 4, 0, 3, 0]);
+    }
+
+    #[test]
+    fn routes_stereo_to_other_outputs() {
+        let mut map = ChannelMap::for_profile(&profile(false, Some([3, 4])), 2).unwrap();
+        assert_eq!(map.output_channels(), 4);
+        map.widen(6);
+        assert_eq!(map.output_channels(), 6);
+        let bytes = [1, 0, 0, 2, 0, 0];
+        assert_eq!(
+            apply(&map, &bytes, 3),
+            [0, 0, 0, 0, 0, 0, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let swapped = ChannelMap::for_profile(&profile(true, Some([3, 4])), 2).unwrap();
+        assert_eq!(apply(&swapped, &[1, 0, 2, 0], 2), [0, 0, 0, 0, 2, 0, 1, 0]);
+    }
+}
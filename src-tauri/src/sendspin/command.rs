@@ -0,0 +1,90 @@
+//! Typed playback commands accepted from app surfaces.
+
+use crate::now_playing::RepeatMode;
+use serde::{Deserialize, Serialize};
+
+/// A playback command from the frontend, tray, or media keys.
+///
+/// Transport commands and group volume go to the server through the
+/// controller role; volume and mute apply to this player and are echoed
+/// back as player state; seeks, shuffle, repeat and favorite go through the
+/// Music Assistant API for the player's active queue.
+///
+/// Deserializes from the plain names the frontend has always sent
+/// (`"play"`, `"next"`, ...) and from single-key objects such as
+/// `{ "seek": 90000 }` for commands that carry a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackCommand {
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Seek the current track to a position in milliseconds.
+    Seek(u64),
+    /// Seek the current track by an offset in milliseconds, back if negative.
+    SeekBy(i64),
+    /// Set this player's volume (0-100). Applied up to the maximum volume
+    /// setting; the surface that asked for more is sent the volume set.
+    SetVolume(u8),
+    /// Mute or unmute this player.
+    SetMute(bool),
+    /// Shuffle the queue, or play it in order again.
+    SetShuffle(bool),
+    /// Set what the queue repeats.
+    SetRepeat(RepeatMode),
+    /// Add the playing track to the library's favorites.
+    Favorite,
+    /// Set the volume of this player's whole group (0-100).
+    SetGroupVolume(u8),
+    /// Mute or unmute this player's whole group.
+    SetGroupMute(bool),
+}
+
+/// How far the skip forward and back controls seek, in milliseconds
+pub const SEEK_STEP_MS: i64 = 10_000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_commands_deserialize_from_plain_names() {
+        for (name, command) in [
+            ("play", PlaybackCommand::Play),
+            ("pause", PlaybackCommand::Pause),
+            ("stop", PlaybackCommand::Stop),
+            ("next", PlaybackCommand::Next),
+            ("previous", PlaybackCommand::Previous),
+            ("favorite", PlaybackCommand::Favorite),
+        ] {
+            let parsed: PlaybackCommand = serde_json::from_str(&format!("\"{name}\"")).unwrap();
+            assert_eq!(parsed, command);
+        }
+    }
+
+    #[test]
+    fn valued_commands_deserialize_from_objects() {
+        let seek: PlaybackCommand = serde_json::from_str(r#"{"seek":90000}"#).unwrap();
+        assert_eq!(seek, PlaybackCommand::Seek(90_000));
+        let back: PlaybackCommand = serde_json::from_str(r#"{"seek_by":-10000}"#).unwrap();
+        assert_eq!(back, PlaybackCommand::SeekBy(-SEEK_STEP_MS));
+        let volume: PlaybackCommand = serde_json::from_str(r#"{"set_volume":40}"#).unwrap();
+        assert_eq!(volume, PlaybackCommand::SetVolume(40));
+        let mute: PlaybackCommand = serde_json::from_str(r#"{"set_mute":true}"#).unwrap();
+        assert_eq!(mute, PlaybackCommand::SetMute(true));
+        let shuffle: PlaybackCommand = serde_json::from_str(r#"{"set_shuffle":false}"#).unwrap();
+        assert_eq!(shuffle, PlaybackCommand::SetShuffle(false));
+        let repeat: PlaybackCommand = serde_json::from_str(r#"{"set_repeat":"one"}"#).unwrap();
+        assert_eq!(repeat, PlaybackCommand::SetRepeat(RepeatMode::One));
+        let group: PlaybackCommand = serde_json::from_str(r#"{"set_group_volume":60}"#).unwrap();
+        assert_eq!(group, PlaybackCommand::SetGroupVolume(60));
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected() {
+        assert!(serde_json::from_str::<PlaybackCommand>(r#""rewind""#).is_err());
+        assert!(serde_json::from_str::<PlaybackCommand>(r#"{"set_volume":400}"#).is_err());
+    }
+}
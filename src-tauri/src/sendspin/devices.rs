@@ -1,8 +1,15 @@
 //! Audio device enumeration and selection using cpal
 //!
 //! This module provides cross-platform audio device enumeration
-//! for selecting output devices in the Sendspin client.
-
+//! for selecting output devices in the Sendspin client. On Linux with
+//! `PipeWire` running, the devices listed are its sinks instead.
+
+#[cfg(all(target_os = "linux", feature = "jack"))]
+use super::jack_output;
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+use super::pipewire;
+use super::transport::{self, Transport};
+use super::{null_output, SendspinError};
 use cpal::traits::{DeviceTrait, HostTrait};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
@@ -16,7 +23,7 @@ pub struct SupportedPcmFormat {
 }
 
 /// Information about an audio output device
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AudioDevice {
     /// Unique identifier for the device
     pub id: String,
@@ -28,10 +35,26 @@ pub struct AudioDevice {
     pub sample_rates: Vec<u32>,
     /// Maximum number of output channels
     pub max_channels: u16,
+    /// Channel counts the device can be opened with
+    pub channel_counts: Vec<u16>,
+    /// PCM bit depths the device can play without dithering down
+    pub bit_depths: Vec<u16>,
+    /// How the device is attached
+    pub transport: Transport,
 }
 
+/// Rates listed for a device, where it takes them
+const LISTED_RATES: [u32; 7] = [44100, 48000, 88200, 96000, 176400, 192000, 384000];
+
 /// List all available audio output devices
-pub fn list_devices() -> Result<Vec<AudioDevice>, String> {
+pub fn list_devices() -> Result<Vec<AudioDevice>, SendspinError> {
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    match pipewire::list_sinks() {
+        Ok(sinks) if !sinks.is_empty() => return Ok(pipewire_devices(sinks)),
+        Ok(_) => log::debug!("[Sendspin] PipeWire has no sinks, listing ALSA devices"),
+        Err(e) => log::debug!("[Sendspin] Listing ALSA devices: {}", e),
+    }
+
     let host = cpal::default_host();
 
     let default_device_name = host
@@ -40,7 +63,7 @@ pub fn list_devices() -> Result<Vec<AudioDevice>, String> {
 
     let devices = host
         .output_devices()
-        .map_err(|e| format!("Failed to enumerate devices: {}", e))?;
+        .map_err(|e| SendspinError::Device(format!("Failed to enumerate devices: {}", e)))?;
 
     let mut result = Vec::new();
 
@@ -52,48 +75,41 @@ pub fn list_devices() -> Result<Vec<AudioDevice>, String> {
 
         let is_default = default_device_name.as_ref().is_some_and(|d| d == &name);
 
-        // Get supported configurations
-        let (sample_rates, max_channels) = match device.supported_output_configs() {
-            Ok(configs) => {
-                let mut rates = Vec::new();
-                let mut channels = 0u16;
-
-                for config in configs {
-                    // Collect common sample rates that are supported
-                    let min_rate = config.min_sample_rate();
-                    let max_rate = config.max_sample_rate();
-
-                    for &rate in &[44100, 48000, 88200, 96000, 176400, 192000, 384000] {
-                        if rate >= min_rate && rate <= max_rate && !rates.contains(&rate) {
-                            rates.push(rate);
-                        }
-                    }
-
-                    if config.channels() > channels {
-                        channels = config.channels();
-                    }
-                }
-
-                rates.sort_unstable();
-                (rates, channels)
-            }
-            Err(_) => (vec![44100, 48000], 2), // Fallback defaults
-        };
+        let caps = extract_capabilities(&device);
 
         // Use device name as ID (cpal doesn't provide stable IDs)
         let id = name.clone();
 
+        let channel_counts = caps.channel_counts();
         result.push(AudioDevice {
             id,
             name,
             is_default,
-            sample_rates,
-            max_channels,
+            sample_rates: caps.listed_rates(),
+            max_channels: channel_counts.last().copied().unwrap_or(2),
+            channel_counts,
+            bit_depths: caps.bit_depths(),
+            transport: transport::of_device(&device),
         });
     }
 
-    // Sort with default device first
-    result.sort_by(|a, b| {
+    sort_devices(&mut result);
+    push_other_outputs(&mut result);
+
+    Ok(result)
+}
+
+/// Add the outputs that aren't the host's devices: JACK's, when built with
+/// it, and the null output, always there, sound card or not
+fn push_other_outputs(devices: &mut Vec<AudioDevice>) {
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    devices.extend(jack_output::devices());
+    devices.push(null_output::device());
+}
+
+/// Sort with default device first, then by name
+fn sort_devices(devices: &mut [AudioDevice]) {
+    devices.sort_by(|a, b| {
         if a.is_default && !b.is_default {
             std::cmp::Ordering::Less
         } else if !a.is_default && b.is_default {
@@ -102,17 +118,54 @@ pub fn list_devices() -> Result<Vec<AudioDevice>, String> {
             a.name.cmp(&b.name)
         }
     });
+}
 
-    Ok(result)
+/// The device list when `PipeWire` is running: its sinks, which every rate
+/// can be played to as the graph resamples
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+fn pipewire_devices(sinks: Vec<pipewire::Sink>) -> Vec<AudioDevice> {
+    let mut result: Vec<AudioDevice> = sinks
+        .into_iter()
+        .map(|sink| {
+            let channels = sink.channels.unwrap_or(2);
+            AudioDevice {
+                id: format!("{}{}", pipewire::DEVICE_ID_PREFIX, sink.name),
+                transport: transport::from_name(&sink.name),
+                name: sink.description,
+                is_default: sink.is_default,
+                sample_rates: LISTED_RATES.to_vec(),
+                max_channels: channels,
+                channel_counts: vec![channels],
+                bit_depths: vec![16, 24],
+            }
+        })
+        .collect();
+    sort_devices(&mut result);
+    push_other_outputs(&mut result);
+    result
 }
 
-/// Get device by ID (name)
-pub fn get_device_by_id(device_id: &str) -> Result<cpal::Device, String> {
+/// Get device by ID (name). A `PipeWire` sink is played to through the
+/// `pipewire` ALSA device and JACK ports through cpal's JACK host, with
+/// streams routed to them afterwards; see [`stream_route`].
+pub fn get_device_by_id(device_id: &str) -> Result<cpal::Device, SendspinError> {
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    if pipewire::sink_name(device_id).is_some() {
+        return get_device_by_id(pipewire::ALSA_DEVICE);
+    }
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    if jack_output::ports(device_id).is_some() {
+        return cpal::host_from_id(cpal::HostId::Jack)
+            .map_err(|e| SendspinError::Device(format!("JACK host not available: {}", e)))?
+            .default_output_device()
+            .ok_or_else(|| SendspinError::DeviceNotFound("No JACK output available".to_string()));
+    }
+
     let host = cpal::default_host();
 
     let devices = host
         .output_devices()
-        .map_err(|e| format!("Failed to enumerate devices: {}", e))?;
+        .map_err(|e| SendspinError::Device(format!("Failed to enumerate devices: {}", e)))?;
 
     for device in devices {
         if let Ok(desc) = device.description() {
@@ -122,21 +175,136 @@ pub fn get_device_by_id(device_id: &str) -> Result<cpal::Device, String> {
         }
     }
 
-    Err(format!("Device not found: {}", device_id))
+    Err(SendspinError::DeviceNotFound(format!(
+        "Device not found: {}",
+        device_id
+    )))
+}
+
+/// Whether the output device `device_id` is currently connected; `None` if
+/// the devices couldn't be enumerated.
+pub fn is_output_device_present(device_id: &str) -> Option<bool> {
+    if device_id == null_output::DEVICE_ID {
+        return Some(true);
+    }
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    if let Some(name) = pipewire::sink_name(device_id) {
+        let sinks = pipewire::list_sinks().ok()?;
+        return Some(sinks.iter().any(|sink| sink.name == name));
+    }
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    if device_id.starts_with(jack_output::DEVICE_ID_PREFIX) {
+        return Some(jack_output::devices().iter().any(|d| d.id == device_id));
+    }
+    let devices = cpal::default_host().output_devices().ok()?;
+    Some(
+        devices
+            .filter_map(|device| device.description().ok())
+            .any(|desc| desc.name() == device_id),
+    )
+}
+
+/// The first of `fallbacks` to play on instead of `preferred` when that
+/// isn't connected, or `None` to play on `preferred` (the system default
+/// when unset). If no fallback is connected either, `preferred` is kept and
+/// opening it falls back to the system default as before.
+pub fn fallback_device(preferred: Option<&str>, fallbacks: &[String]) -> Option<String> {
+    first_connected(preferred, fallbacks, is_output_device_present)
+}
+
+/// [`fallback_device`] with `is_present` telling whether a device is
+/// connected. A device that can't be checked counts as connected.
+fn first_connected(
+    preferred: Option<&str>,
+    fallbacks: &[String],
+    is_present: impl Fn(&str) -> Option<bool>,
+) -> Option<String> {
+    let preferred = preferred?;
+    if is_present(preferred) != Some(false) {
+        return None;
+    }
+    fallbacks
+        .iter()
+        .filter(|id| id.as_str() != preferred)
+        .find(|id| is_present(id) == Some(true))
+        .cloned()
+}
+
+/// Connects a stream to its output once it's open, for outputs played to
+/// through a shared client rather than opened directly
+#[cfg_attr(
+    not(all(target_os = "linux", any(feature = "pipewire", feature = "jack"))),
+    allow(clippy::empty_enum)
+)]
+pub enum StreamRoute {
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    PipeWire(pipewire::Route),
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    Jack(jack_output::Route),
+}
+
+impl StreamRoute {
+    /// Route the stream opened since [`stream_route`], in the background as
+    /// it takes a moment to show up
+    pub fn apply_in_background(self) {
+        std::thread::spawn(move || {
+            if let Err(e) = self.apply() {
+                log::warn!("[Sendspin] Failed to route stream: {}", e);
+            }
+        });
+    }
+
+    fn apply(self) -> Result<(), String> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            Self::PipeWire(route) => route.apply(),
+            #[cfg(all(target_os = "linux", feature = "jack"))]
+            Self::Jack(route) => route.apply(),
+        }
+    }
+}
+
+/// How to route a stream about to be opened on `device_id`, if it needs
+/// routing
+#[cfg_attr(
+    not(all(target_os = "linux", any(feature = "pipewire", feature = "jack"))),
+    allow(unused_variables, clippy::unnecessary_wraps)
+)]
+pub fn stream_route(device_id: Option<&str>) -> Option<StreamRoute> {
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    if let Some(sink) = device_id.and_then(pipewire::sink_name) {
+        return pipewire::Route::new(sink)
+            .inspect_err(|e| log::warn!("[Sendspin] Can't route to PipeWire sink {}: {}", sink, e))
+            .ok()
+            .map(StreamRoute::PipeWire);
+    }
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    if let Some(ports) = device_id.and_then(jack_output::ports) {
+        return jack_output::Route::new(ports)
+            .inspect_err(|e| log::warn!("[Sendspin] Can't route to JACK ports: {}", e))
+            .ok()
+            .map(StreamRoute::Jack);
+    }
+    None
 }
 
 /// Get the default output device
 #[allow(dead_code)]
-pub fn get_default_device() -> Result<cpal::Device, String> {
+pub fn get_default_device() -> Result<cpal::Device, SendspinError> {
     let host = cpal::default_host();
 
-    host.default_output_device()
-        .ok_or_else(|| "No default output device available".to_string())
+    host.default_output_device().ok_or_else(|| {
+        SendspinError::DeviceNotFound("No default output device available".to_string())
+    })
 }
 
 /// Resolve output device based on optional device ID.
 /// Falls back to default output device if the requested device is not available.
+/// The null output has no cpal device, so resolves to `None`.
 pub fn resolve_output_device(device_id: Option<&str>) -> Option<cpal::Device> {
+    if device_id == Some(null_output::DEVICE_ID) {
+        return None;
+    }
     if let Some(id) = device_id {
         match get_device_by_id(id) {
             Ok(device) => {
@@ -173,6 +341,51 @@ pub fn resolve_output_device(device_id: Option<&str>) -> Option<cpal::Device> {
     }
 }
 
+/// The error for a stream cpal reported `DeviceNotAvailable` for. That's
+/// both a device that's gone and, on ALSA (`EBUSY`), one another
+/// application holds, so a device that's still connected is busy.
+pub fn unavailable_device_error(device: &cpal::Device, message: String) -> SendspinError {
+    let present = device
+        .description()
+        .ok()
+        .and_then(|desc| is_output_device_present(desc.name()));
+    if present == Some(true) {
+        SendspinError::DeviceBusy(message)
+    } else {
+        SendspinError::DeviceNotFound(message)
+    }
+}
+
+/// Why a player in this format didn't open on `device`, for players that
+/// report their errors only as text: a stream is opened on it the same way
+/// to get cpal's own error.
+pub fn open_failure(
+    device: Option<&cpal::Device>,
+    channels: u16,
+    sample_rate: u32,
+    message: String,
+) -> SendspinError {
+    let Some(device) = device else {
+        return SendspinError::DeviceNotFound(message);
+    };
+    let config = cpal::StreamConfig {
+        channels,
+        sample_rate,
+        buffer_size: cpal::BufferSize::Default,
+    };
+    match device.build_output_stream(
+        &config,
+        |_: &mut [f32], _: &cpal::OutputCallbackInfo| {},
+        |_| {},
+        None,
+    ) {
+        Err(cpal::BuildStreamError::DeviceNotAvailable) => {
+            unavailable_device_error(device, message)
+        }
+        _ => SendspinError::Device(message),
+    }
+}
+
 /// Build supported PCM stream formats for Sendspin negotiation.
 ///
 /// Strategy:
@@ -184,9 +397,13 @@ pub fn resolve_output_device(device_id: Option<&str>) -> Option<cpal::Device> {
 /// - Supplement with other common rates that `supported_output_configs()`
 ///   confirms the device can handle, for source material whose rate happens
 ///   to match one of them exactly.
-/// - Advertise stereo only, matching the current playback path. A device
-///   whose native config is mono is skipped for the native anchor (a stereo
-///   stream won't open on a mono device anyway).
+/// - Advertise stereo first. A device whose native config is mono is
+///   skipped for the native anchor (a stereo stream won't open on a mono
+///   device anyway).
+/// - Append 5.1/7.1 layouts after all stereo formats, only for devices that
+///   report a config with that exact channel count — or, with
+///   `downmix_to_stereo`, for any stereo device, since the client then
+///   folds surround streams down itself.
 /// - Prefer 24-bit at the native rate (higher quality through the matched-
 ///   rate, no-resample path), but prefer 16-bit at non-native rates for
 ///   broadest server/codec compatibility.
@@ -196,13 +413,98 @@ pub fn resolve_output_device(device_id: Option<&str>) -> Option<cpal::Device> {
 /// configured rate, so a DAC set to an uncommon rate (352800Hz, 384000Hz,
 /// etc.) would otherwise fall through to the 48/44.1kHz fallback and fail
 /// to open a stream.
-pub fn derive_supported_pcm_formats(device: Option<&cpal::Device>) -> Vec<SupportedPcmFormat> {
+pub fn derive_supported_pcm_formats(
+    device: Option<&cpal::Device>,
+    downmix_to_stereo: bool,
+) -> Vec<SupportedPcmFormat> {
     let Some(device) = device else {
         return vec![];
     };
 
     let caps = extract_capabilities(device);
-    build_formats(&caps)
+    let mut formats = build_formats(&caps);
+    formats.extend(build_multichannel_formats(&caps, downmix_to_stereo));
+    formats
+}
+
+/// Sample-rate ranges the device reports for output with `channels`
+/// channels, plus its native rate. Used to decide whether a stream needs
+/// resampling.
+///
+/// Stereo matches any config with two or more channels; surround layouts
+/// need a config with exactly that many channels.
+pub fn output_rate_capabilities(
+    device: &cpal::Device,
+    channels: u16,
+) -> (Vec<(u32, u32)>, Option<u32>) {
+    let caps = extract_capabilities(device);
+    let ranges = caps
+        .ranges
+        .iter()
+        .filter(|range| range_carries_channels(range, channels))
+        .map(|range| (range.min_sample_rate, range.max_sample_rate))
+        .collect();
+    (ranges, caps.native.map(|native| native.sample_rate))
+}
+
+/// Whether the device can open a stream with `channels` channels.
+pub fn supports_channel_count(device: &cpal::Device, channels: u16) -> bool {
+    let caps = extract_capabilities(device);
+    caps.native
+        .is_some_and(|native| native.channels == channels)
+        || caps
+            .ranges
+            .iter()
+            .any(|range| range_carries_channels(range, channels))
+}
+
+/// The fewest channels, at least `channels`, the device can be opened
+/// with; `None` if it has no config that wide.
+pub fn output_channels_at_least(device: &cpal::Device, channels: u16) -> Option<u16> {
+    caps_channels_at_least(&extract_capabilities(device), channels)
+}
+
+fn caps_channels_at_least(caps: &DeviceCapabilities, channels: u16) -> Option<u16> {
+    if caps.native.is_none() && caps.ranges.is_empty() {
+        return Some(channels);
+    }
+    caps.native
+        .map(|native| native.channels)
+        .into_iter()
+        .chain(caps.ranges.iter().map(|range| range.channels))
+        .filter(|&available| available >= channels)
+        .min()
+}
+
+/// Whether the device can play 24-bit samples with `channels` channels at
+/// `sample_rate`. When it can't, 24-bit streams are dithered down to 16 bits.
+pub fn supports_24bit(device: &cpal::Device, channels: u16, sample_rate: u32) -> bool {
+    caps_carry_24bit(&extract_capabilities(device), channels, sample_rate)
+}
+
+/// A device that reports no configs at all is given the benefit of the
+/// doubt; reducing the bit depth wouldn't help it open a stream anyway.
+fn caps_carry_24bit(caps: &DeviceCapabilities, channels: u16, sample_rate: u32) -> bool {
+    if caps.native.is_none() && caps.ranges.is_empty() {
+        return true;
+    }
+    caps.native.is_some_and(|native| {
+        native.supports_24bit
+            && native.sample_rate == sample_rate
+            && (native.channels == channels || (channels <= 2 && native.channels >= channels))
+    }) || caps.ranges.iter().any(|range| {
+        range.supports_24bit
+            && range_carries_channels(range, channels)
+            && (range.min_sample_rate..=range.max_sample_rate).contains(&sample_rate)
+    })
+}
+
+fn range_carries_channels(range: &ConfigRange, channels: u16) -> bool {
+    if channels <= 2 {
+        range.channels >= channels
+    } else {
+        range.channels == channels
+    }
 }
 
 /// Native (current default) output format of a device, as far as we care
@@ -234,6 +536,53 @@ struct DeviceCapabilities {
     ranges: Vec<ConfigRange>,
 }
 
+impl DeviceCapabilities {
+    /// The listed rates any config takes; 44.1 and 48 kHz if the device
+    /// reported nothing
+    fn listed_rates(&self) -> Vec<u32> {
+        if self.ranges.is_empty() {
+            return vec![44100, 48000];
+        }
+        LISTED_RATES
+            .into_iter()
+            .filter(|rate| {
+                self.ranges
+                    .iter()
+                    .any(|r| (r.min_sample_rate..=r.max_sample_rate).contains(rate))
+            })
+            .collect()
+    }
+
+    /// Channel counts of the configs, ascending; stereo if the device
+    /// reported nothing
+    fn channel_counts(&self) -> Vec<u16> {
+        let counts: BTreeSet<u16> = self
+            .ranges
+            .iter()
+            .map(|r| r.channels)
+            .chain(self.native.map(|n| n.channels))
+            .collect();
+        if counts.is_empty() {
+            vec![2]
+        } else {
+            counts.into_iter().collect()
+        }
+    }
+
+    /// 16-bit, and 24-bit where a config carries it. A device that reported
+    /// nothing is given the benefit of the doubt, as in `caps_carry_24bit`.
+    fn bit_depths(&self) -> Vec<u16> {
+        let carries_24bit = (self.native.is_none() && self.ranges.is_empty())
+            || self.native.is_some_and(|n| n.supports_24bit)
+            || self.ranges.iter().any(|r| r.supports_24bit);
+        if carries_24bit {
+            vec![16, 24]
+        } else {
+            vec![16]
+        }
+    }
+}
+
 /// Glue layer between cpal and `build_formats`. Also logs the detected
 /// native config so this detail is visible in the runtime logs without
 /// requiring `build_formats` to know about cpal types.
@@ -299,12 +648,11 @@ fn build_formats(caps: &DeviceCapabilities) -> Vec<SupportedPcmFormat> {
 
     // Supplement with other common rates the device reports as supported,
     // for source material whose rate happens to match one of them exactly.
-    let preferred_rates = [48_000u32, 44_100u32, 96_000u32, 192_000u32, 384_000u32];
     for range in &caps.ranges {
         if range.channels < 2 {
             continue;
         }
-        for rate in preferred_rates {
+        for rate in PREFERRED_RATES {
             if rate < range.min_sample_rate || rate > range.max_sample_rate {
                 continue;
             }
@@ -329,6 +677,72 @@ fn build_formats(caps: &DeviceCapabilities) -> Vec<SupportedPcmFormat> {
     result
 }
 
+/// Surround layouts advertised when possible: 5.1 and 7.1.
+const MULTICHANNEL_LAYOUTS: [u16; 2] = [6, 8];
+
+/// Common rates advertised beyond the native anchor, in preference order.
+const PREFERRED_RATES: [u32; 7] = [48_000, 44_100, 96_000, 88_200, 192_000, 176_400, 384_000];
+
+/// Produce the surround formats to append after the stereo list.
+///
+/// Without downmixing, a layout is only advertised at rates a config with
+/// exactly that channel count supports. With downmixing, every stereo rate
+/// is offered in every layout; playback folds it down to two channels.
+fn build_multichannel_formats(
+    caps: &DeviceCapabilities,
+    downmix_to_stereo: bool,
+) -> Vec<SupportedPcmFormat> {
+    let mut collected = BTreeSet::new();
+
+    for channels in MULTICHANNEL_LAYOUTS {
+        if downmix_to_stereo {
+            for stereo in build_formats(caps) {
+                collected.insert(SupportedPcmFormat { channels, ..stereo });
+            }
+            continue;
+        }
+
+        if let Some(native) = caps.native.filter(|n| n.channels == channels) {
+            collected.insert(SupportedPcmFormat {
+                channels,
+                sample_rate: native.sample_rate,
+                bit_depth: 16,
+            });
+            if native.supports_24bit {
+                collected.insert(SupportedPcmFormat {
+                    channels,
+                    sample_rate: native.sample_rate,
+                    bit_depth: 24,
+                });
+            }
+        }
+        for range in caps.ranges.iter().filter(|r| r.channels == channels) {
+            for rate in PREFERRED_RATES {
+                if rate < range.min_sample_rate || rate > range.max_sample_rate {
+                    continue;
+                }
+                collected.insert(SupportedPcmFormat {
+                    channels,
+                    sample_rate: rate,
+                    bit_depth: 16,
+                });
+                if range.supports_24bit {
+                    collected.insert(SupportedPcmFormat {
+                        channels,
+                        sample_rate: rate,
+                        bit_depth: 24,
+                    });
+                }
+            }
+        }
+    }
+
+    let native_rate = caps.native.map(|n| n.sample_rate);
+    let mut result: Vec<_> = collected.into_iter().collect();
+    result.sort_by_key(|f| (f.channels, sort_key(*f, native_rate)));
+    result
+}
+
 /// Sort key for advertised format preference.
 ///
 /// Rate ordering: the device's native rate ranks first (zero resampling),
@@ -343,14 +757,10 @@ fn sort_key(f: SupportedPcmFormat, native_rate: Option<u32>) -> (u32, u32, u32,
     let rate_rank = if Some(f.sample_rate) == native_rate {
         0
     } else {
-        match f.sample_rate {
-            48_000 => 1,
-            44_100 => 2,
-            96_000 => 3,
-            192_000 => 4,
-            384_000 => 5,
-            _ => 6,
-        }
+        PREFERRED_RATES
+            .iter()
+            .position(|&rate| rate == f.sample_rate)
+            .map_or(PREFERRED_RATES.len() as u32 + 1, |index| index as u32 + 1)
     };
     let depth_rank = if rate_rank == 0 {
         // Native rate: 24-bit first.
@@ -364,11 +774,23 @@ fn sort_key(f: SupportedPcmFormat, native_rate: Option<u32>) -> (u32, u32, u32,
 
 /// Whether a cpal sample format can carry 24-bit PCM content.
 ///
-/// Currently limited to the explicit 24-bit integer formats. Broader
-/// detection — F32/F64/I32/U32/I64/U64 all carry at least 24 bits of
-/// precision and so could safely advertise 24-bit — is a follow-up.
+/// Besides the explicit 24-bit integer formats, every wider format counts:
+/// F32 has a 24-bit mantissa and the 32/64-bit integer and F64 formats carry
+/// more than 24 bits, so 24-bit samples pass through them losslessly. This
+/// matters in practice because shared-mode WASAPI and `CoreAudio` report F32
+/// for nearly every device, which would otherwise never see 24-bit streams.
 fn sample_format_supports_24bit(fmt: cpal::SampleFormat) -> bool {
-    matches!(fmt, cpal::SampleFormat::I24 | cpal::SampleFormat::U24)
+    matches!(
+        fmt,
+        cpal::SampleFormat::I24
+            | cpal::SampleFormat::U24
+            | cpal::SampleFormat::I32
+            | cpal::SampleFormat::U32
+            | cpal::SampleFormat::I64
+            | cpal::SampleFormat::U64
+            | cpal::SampleFormat::F32
+            | cpal::SampleFormat::F64
+    )
 }
 
 #[cfg(test)]
@@ -382,6 +804,38 @@ mod tests {
         assert!(devices.is_ok());
     }
 
+    #[test]
+    fn falls_back_to_the_first_connected_device_in_order() {
+        let fallbacks = [
+            "usb-dac".to_string(),
+            "hdmi".to_string(),
+            "speakers".to_string(),
+        ];
+        let connected = |ids: &'static [&'static str]| move |id: &str| Some(ids.contains(&id));
+
+        // The chosen device is connected: no fallback
+        assert_eq!(
+            first_connected(Some("dock"), &fallbacks, connected(&["dock", "hdmi"])),
+            None
+        );
+        // Undocked: the first connected fallback, skipping missing ones
+        assert_eq!(
+            first_connected(Some("dock"), &fallbacks, connected(&["hdmi", "speakers"])),
+            Some("hdmi".to_string())
+        );
+        // Nothing connected, or the system default chosen: no fallback
+        assert_eq!(
+            first_connected(Some("dock"), &fallbacks, connected(&[])),
+            None
+        );
+        assert_eq!(
+            first_connected(None, &fallbacks, connected(&["hdmi"])),
+            None
+        );
+        // Devices that can't be checked are taken to be there
+        assert_eq!(first_connected(Some("dock"), &fallbacks, |_| None), None);
+    }
+
     #[test]
     fn test_device_sorting_default_first_and_alphabetical() {
         let mut devices = [
@@ -391,6 +845,7 @@ mod tests {
                 is_default: false,
                 sample_rates: vec![],
                 max_channels: 2,
+                ..Default::default()
             },
             AudioDevice {
                 id: "a".into(),
@@ -398,6 +853,7 @@ mod tests {
                 is_default: false,
                 sample_rates: vec![],
                 max_channels: 2,
+                ..Default::default()
             },
             AudioDevice {
                 id: "d".into(),
@@ -405,6 +861,7 @@ mod tests {
                 is_default: true,
                 sample_rates: vec![],
                 max_channels: 2,
+                ..Default::default()
             },
             AudioDevice {
                 id: "m".into(),
@@ -412,6 +869,7 @@ mod tests {
                 is_default: false,
                 sample_rates: vec![],
                 max_channels: 2,
+                ..Default::default()
             },
         ]
         .to_vec();
@@ -439,8 +897,10 @@ mod tests {
         let result = get_device_by_id("definitely_not_a_real_device_12345");
         match result {
             Err(err) => {
+                assert_eq!(err.kind(), "device_not_found");
                 assert!(
-                    err.contains("definitely_not_a_real_device_12345"),
+                    err.to_string()
+                        .contains("definitely_not_a_real_device_12345"),
                     "Error should contain the device ID, got: {}",
                     err
                 );
@@ -473,6 +933,8 @@ mod tests {
         assert!(sort_key(pcm(44_100, 16), None) < sort_key(pcm(96_000, 16), None));
         assert!(sort_key(pcm(96_000, 16), None) < sort_key(pcm(192_000, 16), None));
         assert!(sort_key(pcm(192_000, 16), None) < sort_key(pcm(384_000, 16), None));
+        assert!(sort_key(pcm(96_000, 16), None) < sort_key(pcm(88_200, 16), None));
+        assert!(sort_key(pcm(192_000, 16), None) < sort_key(pcm(176_400, 16), None));
     }
 
     #[test]
@@ -520,6 +982,16 @@ mod tests {
         assert!(sample_format_supports_24bit(cpal::SampleFormat::U24));
     }
 
+    #[test]
+    fn sample_format_supports_24bit_includes_wider_formats() {
+        assert!(sample_format_supports_24bit(cpal::SampleFormat::F32));
+        assert!(sample_format_supports_24bit(cpal::SampleFormat::F64));
+        assert!(sample_format_supports_24bit(cpal::SampleFormat::I32));
+        assert!(sample_format_supports_24bit(cpal::SampleFormat::U32));
+        assert!(sample_format_supports_24bit(cpal::SampleFormat::I64));
+        assert!(sample_format_supports_24bit(cpal::SampleFormat::U64));
+    }
+
     #[test]
     fn sample_format_supports_24bit_excludes_low_precision_formats() {
         assert!(!sample_format_supports_24bit(cpal::SampleFormat::I8));
@@ -533,7 +1005,7 @@ mod tests {
     #[test]
     fn derive_returns_empty_when_device_is_none() {
         assert_eq!(
-            derive_supported_pcm_formats(None),
+            derive_supported_pcm_formats(None, false),
             Vec::<SupportedPcmFormat>::new()
         );
     }
@@ -616,15 +1088,54 @@ mod tests {
             }],
         };
         let formats = build_formats(&caps);
-        // Preferred rates within [44100, 96000] are 48k, 44.1k, 96k — all
-        // at 16-bit since the range doesn't carry 24-bit. Order: the
+        // Preferred rates within [44100, 96000] are 48k, 44.1k, 96k, 88.2k
+        // — all at 16-bit since the range doesn't carry 24-bit. Order: the
         // non-native ladder, 48k first.
         assert_eq!(
             formats,
-            vec![pcm(48_000, 16), pcm(44_100, 16), pcm(96_000, 16)]
+            vec![
+                pcm(48_000, 16),
+                pcm(44_100, 16),
+                pcm(96_000, 16),
+                pcm(88_200, 16)
+            ]
         );
     }
 
+    #[test]
+    fn capabilities_are_summarized_for_the_device_list() {
+        let caps = DeviceCapabilities {
+            native: Some(NativeFormat {
+                channels: 2,
+                sample_rate: 48_000,
+                supports_24bit: false,
+            }),
+            ranges: vec![
+                ConfigRange {
+                    channels: 2,
+                    min_sample_rate: 44_100,
+                    max_sample_rate: 48_000,
+                    supports_24bit: false,
+                },
+                ConfigRange {
+                    channels: 6,
+                    min_sample_rate: 48_000,
+                    max_sample_rate: 96_000,
+                    supports_24bit: true,
+                },
+            ],
+        };
+        assert_eq!(caps.listed_rates(), [44_100, 48_000, 88_200, 96_000]);
+        assert_eq!(caps.channel_counts(), [2, 6]);
+        assert_eq!(caps.bit_depths(), [16, 24]);
+
+        // A device that reported nothing gets the conservative defaults
+        let unknown = DeviceCapabilities::default();
+        assert_eq!(unknown.listed_rates(), [44_100, 48_000]);
+        assert_eq!(unknown.channel_counts(), [2]);
+        assert_eq!(unknown.bit_depths(), [16, 24]);
+    }
+
     #[test]
     fn build_formats_combines_native_and_ranges_with_native_first() {
         // Device at 96kHz native, ranges cover a broader span including
@@ -646,7 +1157,7 @@ mod tests {
         let formats = build_formats(&caps);
         // 96k/16 is in the set from both the anchor and the range, dedup'd
         // by BTreeSet. Final order: native 96k/24, native 96k/16, then
-        // non-native ladder (48k, 44.1k — within the declared range,
+        // non-native ladder (48k, 44.1k, 88.2k — within the declared range,
         // 16-bit only because the range's sample format doesn't carry
         // 24-bit).
         assert_eq!(
@@ -656,6 +1167,7 @@ mod tests {
                 pcm(96_000, 16),
                 pcm(48_000, 16),
                 pcm(44_100, 16),
+                pcm(88_200, 16),
             ]
         );
     }
@@ -684,4 +1196,135 @@ mod tests {
         assert_eq!(formats[0], pcm(48_000, 16));
         assert!(formats.contains(&pcm(44_100, 16)));
     }
+
+    // ---- build_multichannel_formats ---------------------------------------
+
+    fn surround(channels: u16, rate: u32, depth: u16) -> SupportedPcmFormat {
+        SupportedPcmFormat {
+            channels,
+            sample_rate: rate,
+            bit_depth: depth,
+        }
+    }
+
+    #[test]
+    fn build_formats_includes_hires_rates_in_range() {
+        let caps = DeviceCapabilities {
+            native: None,
+            ranges: vec![ConfigRange {
+                channels: 2,
+                min_sample_rate: 176_400,
+                max_sample_rate: 192_000,
+                supports_24bit: true,
+            }],
+        };
+        let formats = build_formats(&caps);
+        assert!(formats.contains(&pcm(176_400, 24)));
+        assert!(formats.contains(&pcm(192_000, 24)));
+    }
+
+    #[test]
+    fn multichannel_not_advertised_for_stereo_device_without_downmix() {
+        let caps = DeviceCapabilities {
+            native: Some(NativeFormat {
+                channels: 2,
+                sample_rate: 48_000,
+                supports_24bit: false,
+            }),
+            ranges: vec![],
+        };
+        assert!(build_multichannel_formats(&caps, false).is_empty());
+    }
+
+    #[test]
+    fn multichannel_advertised_for_matching_device_config() {
+        let caps = DeviceCapabilities {
+            native: Some(NativeFormat {
+                channels: 8,
+                sample_rate: 48_000,
+                supports_24bit: true,
+            }),
+            ranges: vec![ConfigRange {
+                channels: 6,
+                min_sample_rate: 48_000,
+                max_sample_rate: 48_000,
+                supports_24bit: false,
+            }],
+        };
+        assert_eq!(
+            build_multichannel_formats(&caps, false),
+            vec![
+                surround(6, 48_000, 16),
+                surround(8, 48_000, 24),
+                surround(8, 48_000, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn downmix_advertises_surround_at_every_stereo_format() {
+        let caps = DeviceCapabilities {
+            native: Some(NativeFormat {
+                channels: 2,
+                sample_rate: 44_100,
+                supports_24bit: false,
+            }),
+            ranges: vec![],
+        };
+        assert_eq!(
+            build_multichannel_formats(&caps, true),
+            vec![surround(6, 44_100, 16), surround(8, 44_100, 16)]
+        );
+    }
+
+    #[test]
+    fn surround_layouts_need_exact_channel_count() {
+        let range = ConfigRange {
+            channels: 8,
+            min_sample_rate: 48_000,
+            max_sample_rate: 48_000,
+            supports_24bit: false,
+        };
+        assert!(range_carries_channels(&range, 2));
+        assert!(range_carries_channels(&range, 8));
+        assert!(!range_carries_channels(&range, 6));
+    }
+
+    #[test]
+    fn caps_carry_24bit_depends_on_rate_and_channels() {
+        let caps = DeviceCapabilities {
+            native: Some(NativeFormat {
+                channels: 2,
+                sample_rate: 48_000,
+                supports_24bit: false,
+            }),
+            ranges: vec![ConfigRange {
+                channels: 2,
+                min_sample_rate: 88_200,
+                max_sample_rate: 192_000,
+                supports_24bit: true,
+            }],
+        };
+        assert!(!caps_carry_24bit(&caps, 2, 48_000));
+        assert!(caps_carry_24bit(&caps, 2, 96_000));
+        assert!(!caps_carry_24bit(&caps, 6, 96_000));
+        assert!(caps_carry_24bit(&DeviceCapabilities::default(), 2, 48_000));
+    }
+
+    #[test]
+    fn channels_at_least_picks_the_narrowest_wide_enough_config() {
+        let range = |channels| ConfigRange {
+            channels,
+            min_sample_rate: 48_000,
+            max_sample_rate: 48_000,
+            supports_24bit: false,
+        };
+        let caps = DeviceCapabilities {
+            native: None,
+            ranges: vec![range(2), range(8), range(6)],
+        };
+        assert_eq!(caps_channels_at_least(&caps, 4), Some(6));
+        assert_eq!(caps_channels_at_least(&caps, 2), Some(2));
+        assert_eq!(caps_channels_at_least(&caps, 10), None);
+    }
 }
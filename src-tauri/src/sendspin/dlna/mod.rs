@@ -0,0 +1,887 @@
+//! DLNA renderer backend.
+//!
+//! When Sendspin isn't available, the built-in player can instead present
+//! itself on the network as a UPnP/DLNA `MediaRenderer`, for MA's DLNA
+//! provider or any other control point. It runs in place of the Sendspin
+//! connection inside the same reconnect loop, so it shares the output
+//! device, static delay, volume reporting and status handling.
+//!
+//! Control points give the renderer a URL to play; it fetches the stream
+//! itself and plays it, with the track set as the next one following on
+//! without a gap. Only PCM (WAVE and `audio/L16`/`L24`) streams are
+//! supported, so nothing else is advertised, and streams can't be seeked.
+//! Volume is applied to the samples. Transport and volume changes made here
+//! go through the MA API, which passes them back to the renderer.
+
+mod server;
+mod soap;
+mod ssdp;
+
+use super::http_stream::{self, PcmLayout, PcmStream, StreamEvent};
+use super::resampler::StreamResampler;
+use super::timed_player::Player;
+use super::{
+    backend_address, backend_now_playing, capped_volume, forward_to_server, initial_volume_state,
+    output_layout_for_stream, pcm, publish_volume, save_volume_state, AudioFormat, ClientCommand,
+    Codec, ConnectionStatus, PlaybackCommand, ResolvedVolumeMode, SendspinClient, SendspinConfig,
+};
+use crate::now_playing::NowPlaying;
+use server::{ActionResult, Control};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How often playback progress is checked
+const TICK: Duration = Duration::from_millis(100);
+/// How far ahead of playback audio is queued
+const READ_AHEAD: Duration = Duration::from_secs(10);
+/// Delay before the first audio of a stream plays, so the output doesn't
+/// run dry while the stream gets going
+const START_LEAD: Duration = Duration::from_millis(500);
+
+const INVALID_ACTION: (u16, &str) = (401, "Invalid Action");
+const INVALID_ARGS: (u16, &str) = (402, "Invalid Args");
+const TRANSITION_NOT_AVAILABLE: (u16, &str) = (701, "Transition not available");
+const SEEK_NOT_SUPPORTED: (u16, &str) = (710, "Seek mode not supported");
+const RESOURCE_NOT_FOUND: (u16, &str) = (716, "Resource not found");
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Unique device name to present for `player_id`
+fn device_udn(player_id: &str) -> String {
+    let hash = Sha256::digest(format!("dlna:{}", player_id).as_bytes());
+    let hex: String = hash[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "uuid:{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Address of the interface the MA server is reached through, which is the
+/// one to be found on
+async fn local_address(server_url: &str) -> Result<Ipv4Addr, String> {
+    let (host, _) = backend_address(None, server_url, 0)?;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| format!("Failed to open a socket: {}", e))?;
+    // Connecting a UDP socket sends nothing; it only picks the route
+    socket
+        .connect((host.as_str(), 9))
+        .await
+        .map_err(|e| format!("No route to {}: {}", host, e))?;
+    match socket.local_addr().map(|address| address.ip()) {
+        Ok(IpAddr::V4(address)) => Ok(address),
+        Ok(IpAddr::V6(_)) => Err("The DLNA renderer needs an IPv4 network".to_string()),
+        Err(e) => Err(format!("Failed to find the local address: {}", e)),
+    }
+}
+
+/// Duration in milliseconds from a track's DIDL-Lite metadata
+fn metadata_duration(metadata: &str) -> Option<u64> {
+    let start = metadata.find("duration=\"")? + "duration=\"".len();
+    let end = metadata[start..].find('"')?;
+    soap::parse_time(&metadata[start..start + end])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransportState {
+    NoMedia,
+    Stopped,
+    /// Waiting for the stream to start
+    Transitioning,
+    Playing,
+    Paused,
+}
+
+impl TransportState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NoMedia => "NO_MEDIA_PRESENT",
+            Self::Stopped => "STOPPED",
+            Self::Transitioning => "TRANSITIONING",
+            Self::Playing => "PLAYING",
+            Self::Paused => "PAUSED_PLAYBACK",
+        }
+    }
+
+    /// What `GetCurrentTransportActions` lists
+    fn actions(self) -> &'static str {
+        match self {
+            Self::NoMedia => "",
+            Self::Stopped => "Play",
+            Self::Transitioning | Self::Playing => "Pause,Stop",
+            Self::Paused => "Play,Stop",
+        }
+    }
+}
+
+/// A URL to play, with the metadata it came with
+#[derive(Debug, Clone, Default)]
+struct Media {
+    uri: String,
+    metadata: String,
+}
+
+/// A stream being fetched
+struct ActiveStream {
+    events: mpsc::Receiver<StreamEvent>,
+    task: JoinHandle<()>,
+    /// Set up once the response headers give the layout
+    pcm: Option<PcmStream>,
+    resampler: Option<StreamResampler>,
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A track from the moment its stream starts until it has played out
+struct Track {
+    media: Media,
+    /// Output position its first frame plays at, once queued
+    first_frame: Option<u64>,
+    started: bool,
+}
+
+/// The renderer's state, driven by control requests and the stream
+struct Renderer<'a> {
+    instance: &'a SendspinClient,
+    config: &'a SendspinConfig,
+    /// How MA knows the renderer
+    player_id: &'a str,
+    additional_player: Option<&'a str>,
+    origin: Instant,
+    state: TransportState,
+    /// The current track
+    media: Option<Media>,
+    /// The track to follow it
+    next: Option<Media>,
+    /// Tracks queued for output, the playing one first
+    tracks: VecDeque<Track>,
+    stream: Option<ActiveStream>,
+    /// The open output, with its channel count and rate
+    output: Option<(Player, u16, u32)>,
+    /// Output frames queued since it opened
+    queued_frames: u64,
+    volume: u8,
+    muted: bool,
+    static_delay_ms: u16,
+}
+
+impl Renderer<'_> {
+    /// Microseconds on the playback clock
+    fn now(&self) -> i64 {
+        self.origin.elapsed().as_micros() as i64
+    }
+
+    fn set_state(&mut self, state: TransportState) {
+        if self.state == state {
+            return;
+        }
+        log::debug!("[DLNA] {} -> {}", self.state.as_str(), state.as_str());
+        self.state = state;
+        let is_playing = state == TransportState::Playing;
+        self.instance.publish_now_playing(backend_now_playing(
+            self.config,
+            self.player_id,
+            is_playing,
+        ));
+    }
+
+    fn set_volume(&mut self, volume: u8, muted: bool) {
+        let volume = capped_volume(volume);
+        self.volume = volume;
+        self.muted = muted;
+        if let Some((ref player, _, _)) = self.output {
+            player.schedule().set_volume(volume, muted);
+        }
+        if self.additional_player.is_none() {
+            publish_volume(volume);
+        }
+        save_volume_state(
+            ResolvedVolumeMode::Software,
+            self.additional_player,
+            volume,
+            muted,
+        );
+    }
+
+    /// Start fetching `media`, to play after whatever is queued.
+    fn start_stream(&mut self, media: Media) -> Result<(), (u16, &'static str)> {
+        let (host, port, request) = http_stream::get_request(&media.uri).map_err(|e| {
+            log::warn!("[DLNA] {}", e);
+            RESOURCE_NOT_FOUND
+        })?;
+        log::info!("[DLNA] Starting stream {}", media.uri);
+        let (events_tx, events) = mpsc::channel(4);
+        let task = tokio::spawn(http_stream::fetch(host, port, request, events_tx));
+        self.stream = Some(ActiveStream {
+            events,
+            task,
+            pcm: None,
+            resampler: None,
+        });
+        self.tracks.push_back(Track {
+            media,
+            first_frame: None,
+            started: false,
+        });
+        Ok(())
+    }
+
+    /// Drop the stream and everything queued.
+    fn stop_playback(&mut self) {
+        self.stream = None;
+        self.tracks.clear();
+        if let Some((ref player, _, _)) = self.output {
+            player.schedule().clear();
+        }
+    }
+
+    fn play(&mut self) -> ActionResult {
+        match self.state {
+            TransportState::NoMedia => return Err(TRANSITION_NOT_AVAILABLE),
+            TransportState::Stopped => {
+                let media = self.media.clone().unwrap_or_default();
+                self.start_stream(media)?;
+                self.set_state(TransportState::Transitioning);
+            }
+            TransportState::Paused => {
+                let now = self.now();
+                if let Some((ref player, _, _)) = self.output {
+                    player.schedule().resume_at(now);
+                }
+                let started = self.tracks.front().is_some_and(|track| track.started);
+                self.set_state(if started {
+                    TransportState::Playing
+                } else {
+                    TransportState::Transitioning
+                });
+            }
+            TransportState::Transitioning | TransportState::Playing => {}
+        }
+        Ok(Vec::new())
+    }
+
+    fn stop(&mut self) {
+        self.stop_playback();
+        self.set_state(if self.media.is_some() {
+            TransportState::Stopped
+        } else {
+            TransportState::NoMedia
+        });
+    }
+
+    /// Output position of the current track in milliseconds
+    fn track_position_ms(&self) -> u64 {
+        let Some((ref player, _, rate)) = self.output else {
+            return 0;
+        };
+        let position = player.schedule().position();
+        self.tracks
+            .front()
+            .and_then(|track| track.first_frame)
+            .map_or(0, |first| {
+                position.saturating_sub(first) * 1_000 / u64::from(rate.max(1))
+            })
+    }
+
+    /// Answer a control request.
+    fn handle(&mut self, service: &str, action: &str, body: &str) -> ActionResult {
+        let media = self.media.clone().unwrap_or_default();
+        let next = self.next.clone().unwrap_or_default();
+        let duration = metadata_duration(&media.metadata)
+            .map_or_else(|| "0:00:00".to_string(), soap::format_time);
+        match (service, action) {
+            (soap::AV_TRANSPORT, "SetAVTransportURI") => {
+                let uri = soap::argument(body, "CurrentURI").ok_or(INVALID_ARGS)?;
+                let metadata = soap::argument(body, "CurrentURIMetaData").unwrap_or_default();
+                let was_playing = matches!(
+                    self.state,
+                    TransportState::Playing | TransportState::Transitioning
+                );
+                self.stop_playback();
+                self.next = None;
+                if uri.is_empty() {
+                    self.media = None;
+                    self.set_state(TransportState::NoMedia);
+                    return Ok(Vec::new());
+                }
+                self.media = Some(Media { uri, metadata });
+                // A new track while playing plays straight away
+                self.set_state(TransportState::Stopped);
+                if was_playing {
+                    self.play()?;
+                }
+                Ok(Vec::new())
+            }
+            (soap::AV_TRANSPORT, "SetNextAVTransportURI") => {
+                let uri = soap::argument(body, "NextURI").ok_or(INVALID_ARGS)?;
+                let metadata = soap::argument(body, "NextURIMetaData").unwrap_or_default();
+                self.next = (!uri.is_empty()).then_some(Media { uri, metadata });
+                // The current stream may already be read to the end
+                let fetched = self.stream.is_none() && !self.tracks.is_empty();
+                if fetched {
+                    if let Some(next) = self.next.take() {
+                        self.start_stream(next)?;
+                    }
+                }
+                Ok(Vec::new())
+            }
+            (soap::AV_TRANSPORT, "Play") => self.play(),
+            (soap::AV_TRANSPORT, "Pause") => {
+                if !matches!(
+                    self.state,
+                    TransportState::Playing | TransportState::Transitioning
+                ) {
+                    return Err(TRANSITION_NOT_AVAILABLE);
+                }
+                if let Some((ref player, _, _)) = self.output {
+                    player.schedule().pause();
+                }
+                self.set_state(TransportState::Paused);
+                Ok(Vec::new())
+            }
+            (soap::AV_TRANSPORT, "Stop") => {
+                self.stop();
+                Ok(Vec::new())
+            }
+            (soap::AV_TRANSPORT, "Seek") => Err(SEEK_NOT_SUPPORTED),
+            (soap::AV_TRANSPORT, "Next" | "Previous") => Err(TRANSITION_NOT_AVAILABLE),
+            (soap::AV_TRANSPORT, "GetTransportInfo") => Ok(vec![
+                ("CurrentTransportState", self.state.as_str().to_string()),
+                ("CurrentTransportStatus", "OK".to_string()),
+                ("CurrentSpeed", "1".to_string()),
+            ]),
+            (soap::AV_TRANSPORT, "GetPositionInfo") => {
+                let position = soap::format_time(self.track_position_ms());
+                Ok(vec![
+                    ("Track", u8::from(self.media.is_some()).to_string()),
+                    ("TrackDuration", duration),
+                    ("TrackMetaData", media.metadata),
+                    ("TrackURI", media.uri),
+                    ("RelTime", position.clone()),
+                    ("AbsTime", position),
+                    ("RelCount", i32::MAX.to_string()),
+                    ("AbsCount", i32::MAX.to_string()),
+                ])
+            }
+            (soap::AV_TRANSPORT, "GetMediaInfo") => Ok(vec![
+                ("NrTracks", u8::from(self.media.is_some()).to_string()),
+                ("MediaDuration", duration),
+                ("CurrentURI", media.uri),
+                ("CurrentURIMetaData", media.metadata),
+                ("NextURI", next.uri),
+                ("NextURIMetaData", next.metadata),
+                (
+                    "PlayMedium",
+                    if self.media.is_some() {
+                        "NETWORK"
+                    } else {
+                        "NONE"
+                    }
+                    .to_string(),
+                ),
+                ("RecordMedium", "NOT_IMPLEMENTED".to_string()),
+                ("WriteStatus", "NOT_IMPLEMENTED".to_string()),
+            ]),
+            (soap::AV_TRANSPORT, "GetDeviceCapabilities") => Ok(vec![
+                ("PlayMedia", "NETWORK".to_string()),
+                ("RecMedia", "NOT_IMPLEMENTED".to_string()),
+                ("RecQualityModes", "NOT_IMPLEMENTED".to_string()),
+            ]),
+            (soap::AV_TRANSPORT, "GetTransportSettings") => Ok(vec![
+                ("PlayMode", "NORMAL".to_string()),
+                ("RecQualityMode", "NOT_IMPLEMENTED".to_string()),
+            ]),
+            (soap::AV_TRANSPORT, "GetCurrentTransportActions") => {
+                Ok(vec![("Actions", self.state.actions().to_string())])
+            }
+            (soap::RENDERING_CONTROL, "GetVolume") => {
+                Ok(vec![("CurrentVolume", self.volume.to_string())])
+            }
+            (soap::RENDERING_CONTROL, "SetVolume") => {
+                let volume = soap::argument(body, "DesiredVolume")
+                    .and_then(|volume| volume.trim().parse::<u8>().ok())
+                    .filter(|volume| *volume <= 100)
+                    .ok_or(INVALID_ARGS)?;
+                self.set_volume(volume, self.muted);
+                Ok(Vec::new())
+            }
+            (soap::RENDERING_CONTROL, "GetMute") => {
+                Ok(vec![("CurrentMute", u8::from(self.muted).to_string())])
+            }
+            (soap::RENDERING_CONTROL, "SetMute") => {
+                let muted = match soap::argument(body, "DesiredMute")
+                    .as_deref()
+                    .map(str::trim)
+                {
+                    Some("1" | "true" | "yes") => true,
+                    Some("0" | "false" | "no") => false,
+                    _ => return Err(INVALID_ARGS),
+                };
+                self.set_volume(self.volume, muted);
+                Ok(Vec::new())
+            }
+            (soap::CONNECTION_MANAGER, "GetProtocolInfo") => Ok(vec![
+                ("Source", String::new()),
+                ("Sink", soap::SINK_PROTOCOLS.to_string()),
+            ]),
+            (soap::CONNECTION_MANAGER, "GetCurrentConnectionIDs") => {
+                Ok(vec![("ConnectionIDs", "0".to_string())])
+            }
+            (soap::CONNECTION_MANAGER, "GetCurrentConnectionInfo") => Ok(vec![
+                ("RcsID", "0".to_string()),
+                ("AVTransportID", "0".to_string()),
+                ("ProtocolInfo", String::new()),
+                ("PeerConnectionManager", String::new()),
+                ("PeerConnectionID", "-1".to_string()),
+                ("Direction", "Input".to_string()),
+                ("Status", "OK".to_string()),
+            ]),
+            _ => Err(INVALID_ACTION),
+        }
+    }
+
+    /// Follow playback through the queued tracks.
+    fn tick(&mut self) {
+        let Some((ref player, _, _)) = self.output else {
+            return;
+        };
+        let (position, buffered) = {
+            let schedule = player.schedule();
+            (schedule.position(), schedule.buffered())
+        };
+        self.instance.inner.stats.set_buffered(buffered, 0);
+        // On to the next track once its first frame has played
+        if self
+            .tracks
+            .get(1)
+            .and_then(|track| track.first_frame)
+            .is_some_and(|first| position > first)
+        {
+            self.tracks.pop_front();
+            if let Some(track) = self.tracks.front() {
+                log::info!("[DLNA] Now playing {}", track.media.uri);
+                self.media = Some(track.media.clone());
+            }
+        }
+        let Some(current) = self.tracks.front_mut() else {
+            return;
+        };
+        if !current.started && current.first_frame.is_some_and(|first| position > first) {
+            current.started = true;
+            self.instance.inner.stats.record_stream_start();
+            if self.state == TransportState::Transitioning {
+                self.set_state(TransportState::Playing);
+            }
+        } else if current.started
+            && self.stream.is_none()
+            && self.tracks.len() == 1
+            && buffered.is_zero()
+            && self.state == TransportState::Playing
+        {
+            // Played out
+            self.tracks.clear();
+            self.set_state(TransportState::Stopped);
+        }
+    }
+
+    /// The stream ended, cleanly or not. A track with nothing queued is
+    /// dropped; playback stops if that leaves nothing to play.
+    fn stream_finished(&mut self) {
+        self.stream = None;
+        if self
+            .tracks
+            .back()
+            .is_some_and(|track| track.first_frame.is_none())
+        {
+            self.tracks.pop_back();
+        }
+        if self.tracks.is_empty() {
+            self.set_state(if self.media.is_some() {
+                TransportState::Stopped
+            } else {
+                TransportState::NoMedia
+            });
+        }
+    }
+
+    async fn handle_stream_event(&mut self, event: StreamEvent) -> Result<(), String> {
+        let Some(ref mut active) = self.stream else {
+            return Ok(());
+        };
+        match event {
+            StreamEvent::Connected => {}
+            StreamEvent::Headers(headers) => {
+                let headers = String::from_utf8_lossy(&headers);
+                let content_type =
+                    http_stream::header(&headers, "content-type").unwrap_or_default();
+                match PcmLayout::for_content_type(content_type) {
+                    Ok(layout) => active.pcm = Some(PcmStream::new(layout)),
+                    Err(e) => {
+                        log::warn!("[DLNA] {}", e);
+                        self.stream_finished();
+                    }
+                }
+            }
+            StreamEvent::Data(bytes) => {
+                let Some(ref mut pcm_stream) = active.pcm else {
+                    return Ok(());
+                };
+                let had_format = pcm_stream.format().is_some();
+                let frames = match pcm_stream.feed(&bytes) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        log::warn!("[DLNA] {}", e);
+                        self.stream_finished();
+                        return Ok(());
+                    }
+                };
+                let Some(format) = pcm_stream.format() else {
+                    return Ok(());
+                };
+                if !had_format {
+                    self.prepare_output(format).await?;
+                }
+                self.queue_frames(frames, format.bit_depth);
+            }
+            StreamEvent::Ended => {
+                log::debug!("[DLNA] Stream ended");
+                match self.next.take() {
+                    // Straight on to the next track
+                    Some(next) => {
+                        self.stream = None;
+                        if self.start_stream(next).is_err() {
+                            self.stream_finished();
+                        }
+                    }
+                    None => self.stream_finished(),
+                }
+            }
+            StreamEvent::Failed(e) => {
+                log::warn!("[DLNA] {}", e);
+                self.stream_finished();
+            }
+        }
+        Ok(())
+    }
+
+    /// Set up resampling for a stream in `format`, and an output to match.
+    async fn prepare_output(&mut self, format: pcm::PcmFormat) -> Result<(), String> {
+        let stream_format = AudioFormat {
+            codec: Codec::Pcm,
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            bit_depth: format.bit_depth,
+            codec_header: None,
+        };
+        let (_, output_rate, _) = output_layout_for_stream(
+            self.config.audio_device_id.as_deref(),
+            &stream_format,
+            false,
+        );
+        log::info!(
+            "[DLNA] Stream format: channels={}, sample_rate={}, bit_depth={}",
+            format.channels,
+            format.sample_rate,
+            format.bit_depth
+        );
+        let Some(ref mut active) = self.stream else {
+            return Ok(());
+        };
+        if output_rate != format.sample_rate {
+            let quality = crate::settings::get_settings().resampler_quality;
+            match StreamResampler::new(
+                format.sample_rate,
+                output_rate,
+                format.channels,
+                format.bit_depth,
+                format.bit_depth,
+                quality,
+            ) {
+                Ok(r) => {
+                    log::info!(
+                        "[DLNA] Resampling {}Hz to {}Hz",
+                        format.sample_rate,
+                        r.output_rate()
+                    );
+                    active.resampler = Some(r);
+                }
+                Err(e) => log::warn!("[DLNA] {e}; playing at the stream rate"),
+            }
+        }
+        let rate = active
+            .resampler
+            .as_ref()
+            .map_or(format.sample_rate, StreamResampler::output_rate);
+        // A new layout needs the output reopened; anything still queued in
+        // the old one is cut short
+        if self
+            .output
+            .as_ref()
+            .is_some_and(|(_, c, r)| (*c, *r) == (format.channels, rate))
+        {
+            return Ok(());
+        }
+        self.output = None;
+        self.tracks.retain(|track| track.first_frame.is_none());
+        if let Some(track) = self.tracks.front() {
+            self.media = Some(track.media.clone());
+        }
+        let device_id = self.config.audio_device_id.clone();
+        let origin = self.origin;
+        let channels = format.channels;
+        let player = tokio::task::spawn_blocking(move || {
+            Player::open(device_id.as_deref(), channels, rate, origin)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        {
+            let mut schedule = player.schedule();
+            schedule.set_volume(self.volume, self.muted);
+            schedule.set_static_delay(self.static_delay_ms);
+            if self.state == TransportState::Paused {
+                schedule.pause();
+            }
+        }
+        self.output = Some((player, format.channels, rate));
+        self.queued_frames = 0;
+        Ok(())
+    }
+
+    /// Queue the stream's next `frames` after what's already queued.
+    fn queue_frames(&mut self, frames: Vec<u8>, bit_depth: u16) {
+        let now = self.now();
+        let paused = self.state == TransportState::Paused;
+        let (Some(active), Some((player, channels, _))) =
+            (self.stream.as_mut(), self.output.as_ref())
+        else {
+            return;
+        };
+        if frames.is_empty() {
+            return;
+        }
+        let data = match active.resampler {
+            Some(ref mut r) => r
+                .process(0, &frames)
+                .into_iter()
+                .flat_map(|(_, data)| data)
+                .collect(),
+            None => frames,
+        };
+        let samples = pcm::to_f32(&data, bit_depth);
+        let count = samples.len() / usize::from((*channels).max(1));
+        if let Some(track) = self.tracks.back_mut() {
+            track.first_frame.get_or_insert(self.queued_frames);
+        }
+        {
+            let mut schedule = player.schedule();
+            // Straight after what's queued, unless that has run out
+            let earliest = now + START_LEAD.as_micros() as i64;
+            let at = match schedule.end() {
+                Some(end) if paused || end >= earliest => end,
+                _ => earliest,
+            };
+            schedule.push(at, samples);
+        }
+        self.queued_frames += count as u64;
+    }
+}
+
+/// Run the DLNA renderer for `instance` until `shutdown_rx` fires.
+pub(super) async fn run_client(
+    instance: &SendspinClient,
+    config: SendspinConfig,
+    player_id: String,
+    mut shutdown_rx: mpsc::Receiver<()>,
+    mut command_rx: mpsc::Receiver<PlaybackCommand>,
+    mut client_command_rx: mpsc::Receiver<ClientCommand>,
+) -> Result<(), BoxError> {
+    let interface = local_address(&config.server_url).await?;
+    let listener = TcpListener::bind((interface, 0))
+        .await
+        .map_err(|e| format!("Failed to start the DLNA server: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let udn = device_udn(&player_id);
+    let version = env!("CARGO_PKG_VERSION");
+    let server = format!(
+        "{}/1.0 UPnP/1.0 MusicAssistantDesktop/{}",
+        std::env::consts::OS,
+        version
+    );
+    let advertisement = ssdp::Advertisement {
+        udn: udn.clone(),
+        location: format!("http://{}:{}/description.xml", interface, port),
+        server: server.clone(),
+    };
+    let identity = Arc::new(server::Identity {
+        description: soap::device_description(&udn, &config.player_name, version),
+        server,
+    });
+    let socket = ssdp::open_socket(interface)?;
+    let (control_tx, mut control_rx) = mpsc::channel(16);
+    let server_task = tokio::spawn(server::serve(listener, identity, control_tx));
+    let ssdp_task = tokio::spawn(ssdp::run(Arc::clone(&socket), advertisement.clone()));
+
+    instance.update_status(ConnectionStatus::Connected);
+    log::info!(
+        "[DLNA] Renderer {} listening on {}:{}",
+        udn,
+        interface,
+        port
+    );
+
+    let result = run_renderer(
+        instance,
+        &config,
+        &udn,
+        &mut control_rx,
+        &mut shutdown_rx,
+        &mut command_rx,
+        &mut client_command_rx,
+    )
+    .await;
+
+    server_task.abort();
+    ssdp_task.abort();
+    ssdp::bye(&socket, &advertisement).await;
+    instance.publish_now_playing(NowPlaying::default());
+    instance.update_status(ConnectionStatus::Disconnected);
+    result
+}
+
+/// The next event of the stream being fetched, if any
+async fn next_stream_event(stream: &mut Option<ActiveStream>) -> Option<StreamEvent> {
+    match stream {
+        Some(stream) => stream.events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn run_renderer(
+    instance: &SendspinClient,
+    config: &SendspinConfig,
+    player_id: &str,
+    control_rx: &mut mpsc::Receiver<Control>,
+    shutdown_rx: &mut mpsc::Receiver<()>,
+    command_rx: &mut mpsc::Receiver<PlaybackCommand>,
+    client_command_rx: &mut mpsc::Receiver<ClientCommand>,
+) -> Result<(), BoxError> {
+    let additional_player = (!instance.is_primary()).then_some(player_id);
+    let (volume, muted) = initial_volume_state(ResolvedVolumeMode::Software, additional_player);
+    let mut renderer = Renderer {
+        instance,
+        config,
+        player_id,
+        additional_player,
+        origin: Instant::now(),
+        state: TransportState::NoMedia,
+        media: None,
+        next: None,
+        tracks: VecDeque::new(),
+        stream: None,
+        output: None,
+        queued_frames: 0,
+        volume,
+        muted,
+        static_delay_ms: super::clamp_static_delay_ms(config.sync_delay_ms),
+    };
+    if additional_player.is_none() {
+        publish_volume(volume);
+    }
+    instance.publish_now_playing(backend_now_playing(config, player_id, false));
+    let mut tick = tokio::time::interval(TICK);
+
+    loop {
+        // Stop reading ahead once enough is queued; the fetch then waits
+        let read_ahead = renderer
+            .output
+            .as_ref()
+            .is_none_or(|(player, _, _)| player.schedule().buffered() < READ_AHEAD);
+
+        tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            _ = tick.tick() => renderer.tick(),
+            Some(control) = control_rx.recv() => {
+                let result = renderer.handle(control.service, &control.action, &control.body);
+                if let Err((code, description)) = result {
+                    log::debug!("[DLNA] {} failed: {} {}", control.action, code, description);
+                }
+                let _ = control.reply.send(result);
+            }
+            Some(event) = next_stream_event(&mut renderer.stream), if read_ahead => {
+                renderer.handle_stream_event(event).await?;
+            }
+            Some(command) = command_rx.recv() => {
+                forward_to_server(player_id, command);
+            }
+            Some(command) = client_command_rx.recv() => {
+                match command {
+                    ClientCommand::SetStaticDelay(delay_ms) => {
+                        log::debug!("[DLNA] Applying static delay: {}ms", delay_ms);
+                        renderer.static_delay_ms = delay_ms;
+                        if let Some((ref player, _, _)) = renderer.output {
+                            player.schedule().set_static_delay(delay_ms);
+                        }
+                    }
+                    ClientCommand::ReloadDsp => {
+                        log::debug!("[DLNA] DSP settings don't apply to DLNA playback");
+                    }
+                    ClientCommand::SetMirrorDelay(_) => {
+                        log::debug!("[DLNA] Mirrored output doesn't apply to DLNA playback");
+                    }
+                    ClientCommand::Rename(name) => {
+                        log::info!("[DLNA] Player name {} applies from the next connection", name);
+                    }
+                    ClientCommand::SwitchOutputDevice(_) => {
+                        log::info!("[DLNA] Reconnecting to play to the new output device");
+                        instance.restart_in_background();
+                    }
+                    ClientCommand::ApplyMaxVolume => {
+                        renderer.set_volume(renderer.volume, renderer.muted);
+                    }
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_a_stable_udn() {
+        let udn = device_udn("desktop-1234");
+        assert_eq!(device_udn("desktop-1234"), udn);
+        assert_ne!(device_udn("desktop-5678"), udn);
+        let uuid = udn.strip_prefix("uuid:").unwrap();
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+    }
+
+    #[test]
+    fn reads_the_duration_from_metadata() {
+        let metadata = "<DIDL-Lite><item><dc:title>Song</dc:title>\
+            <res protocolInfo=\"http-get:*:audio/wav:*\" duration=\"0:03:25.000\">\
+            http://ma.local/a.wav</res></item></DIDL-Lite>";
+        assert_eq!(metadata_duration(metadata), Some(205_000));
+        assert_eq!(metadata_duration("<DIDL-Lite/>"), None);
+    }
+}
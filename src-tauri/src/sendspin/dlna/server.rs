@@ -0,0 +1,154 @@
+//! HTTP side of the DLNA renderer: the device and service descriptions, and
+//! SOAP control requests, which are handed to the renderer loop to answer.
+//!
+//! Event subscriptions are refused, so control points poll for state.
+
+use super::soap::{self, SERVICES};
+use crate::sendspin::http_stream::header;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+
+/// Most bytes of request head and body accepted
+const MAX_REQUEST: usize = 256 * 1024;
+
+/// Output arguments of an action, or a `UPnP` error code and description
+pub(crate) type ActionResult = Result<Vec<(&'static str, String)>, (u16, &'static str)>;
+
+/// A control request for the renderer loop
+pub(crate) struct Control {
+    /// Service type the request is for
+    pub(crate) service: &'static str,
+    pub(crate) action: String,
+    /// The SOAP body, to read arguments from
+    pub(crate) body: String,
+    pub(crate) reply: oneshot::Sender<ActionResult>,
+}
+
+/// What the server says about itself
+pub(crate) struct Identity {
+    pub(crate) description: String,
+    pub(crate) server: String,
+}
+
+/// Serve requests on `listener` until cancelled.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    identity: Arc<Identity>,
+    controls: mpsc::Sender<Control>,
+) {
+    loop {
+        let Ok((stream, from)) = listener.accept().await else {
+            continue;
+        };
+        let identity = Arc::clone(&identity);
+        let controls = controls.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &identity, &controls).await {
+                log::debug!("[DLNA] Request from {} failed: {}", from, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    identity: &Identity,
+    controls: &mpsc::Sender<Control>,
+) -> Result<(), String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_REQUEST {
+            return Err("Request too large".to_string());
+        }
+        let read = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("Connection closed mid-request".to_string());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let content_length = header(&head, "content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0)
+        .min(MAX_REQUEST);
+    while buffer.len() < head_end + content_length {
+        let read = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body = String::from_utf8_lossy(&buffer[head_end..]).into_owned();
+
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+    let (status, body) = respond(method, path, &head, body, identity, controls).await;
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\nContent-Length: {}\r\n\
+         Server: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        identity.server,
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Status line and body for a request
+async fn respond(
+    method: &str,
+    path: &str,
+    head: &str,
+    body: String,
+    identity: &Identity,
+    controls: &mpsc::Sender<Control>,
+) -> (&'static str, String) {
+    let service_at = |prefix: &str, suffix: &str| {
+        let name = path.strip_prefix(prefix)?.strip_suffix(suffix)?;
+        SERVICES.iter().find(|service| service.path == name)
+    };
+    match method {
+        "GET" if path == "/description.xml" => ("200 OK", identity.description.clone()),
+        "GET" => match service_at("/", ".xml") {
+            Some(service) => ("200 OK", soap::scpd(service)),
+            None => ("404 Not Found", String::new()),
+        },
+        "POST" => {
+            let Some(service) = service_at("/control/", "") else {
+                return ("404 Not Found", String::new());
+            };
+            let Some(action) = header(head, "soapaction").and_then(soap::action_name) else {
+                return ("400 Bad Request", String::new());
+            };
+            let (reply, result) = oneshot::channel();
+            let control = Control {
+                service: service.kind,
+                action: action.to_string(),
+                body,
+                reply,
+            };
+            let result = match controls.send(control).await {
+                Ok(()) => result.await.unwrap_or(Err((501, "Action Failed"))),
+                Err(_) => Err((501, "Action Failed")),
+            };
+            match result {
+                Ok(values) => ("200 OK", soap::response(service.kind, action, &values)),
+                Err((code, description)) => {
+                    ("500 Internal Server Error", soap::fault(code, description))
+                }
+            }
+        }
+        "SUBSCRIBE" | "UNSUBSCRIBE" => ("501 Not Implemented", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    }
+}
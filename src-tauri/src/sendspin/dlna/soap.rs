@@ -0,0 +1,543 @@
+//! `UPnP` documents and SOAP messages for the DLNA renderer.
+//!
+//! Only as much XML as control points send and expect: arguments are read
+//! from the flat SOAP body by element name, and the device and service
+//! descriptions are generated from the action tables below.
+
+pub(crate) const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+pub(crate) const RENDERING_CONTROL: &str = "urn:schemas-upnp-org:service:RenderingControl:1";
+pub(crate) const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+pub(crate) const MEDIA_RENDERER: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+
+/// An action argument: name, whether it's an output, and its state variable
+type Argument = (&'static str, bool, &'static str);
+
+/// A service the renderer offers, with the path segment its URLs use
+pub(crate) struct Service {
+    pub(crate) kind: &'static str,
+    pub(crate) path: &'static str,
+    /// Actions and their arguments
+    actions: &'static [(&'static str, &'static [Argument])],
+    /// State variables: name, data type and allowed values
+    variables: &'static [(&'static str, &'static str, &'static [&'static str])],
+}
+
+const INSTANCE: Argument = ("InstanceID", false, "A_ARG_TYPE_InstanceID");
+
+pub(crate) const SERVICES: [Service; 3] = [
+    Service {
+        kind: AV_TRANSPORT,
+        path: "AVTransport",
+        actions: &[
+            (
+                "SetAVTransportURI",
+                &[
+                    INSTANCE,
+                    ("CurrentURI", false, "AVTransportURI"),
+                    ("CurrentURIMetaData", false, "AVTransportURIMetaData"),
+                ],
+            ),
+            (
+                "SetNextAVTransportURI",
+                &[
+                    INSTANCE,
+                    ("NextURI", false, "NextAVTransportURI"),
+                    ("NextURIMetaData", false, "NextAVTransportURIMetaData"),
+                ],
+            ),
+            (
+                "GetMediaInfo",
+                &[
+                    INSTANCE,
+                    ("NrTracks", true, "NumberOfTracks"),
+                    ("MediaDuration", true, "CurrentMediaDuration"),
+                    ("CurrentURI", true, "AVTransportURI"),
+                    ("CurrentURIMetaData", true, "AVTransportURIMetaData"),
+                    ("NextURI", true, "NextAVTransportURI"),
+                    ("NextURIMetaData", true, "NextAVTransportURIMetaData"),
+                    ("PlayMedium", true, "PlaybackStorageMedium"),
+                    ("RecordMedium", true, "RecordStorageMedium"),
+                    ("WriteStatus", true, "RecordMediumWriteStatus"),
+                ],
+            ),
+            (
+                "GetTransportInfo",
+                &[
+                    INSTANCE,
+                    ("CurrentTransportState", true, "TransportState"),
+                    ("CurrentTransportStatus", true, "TransportStatus"),
+                    ("CurrentSpeed", true, "TransportPlaySpeed"),
+                ],
+            ),
+            (
+                "GetPositionInfo",
+                &[
+                    INSTANCE,
+                    ("Track", true, "CurrentTrack"),
+                    ("TrackDuration", true, "CurrentTrackDuration"),
+                    ("TrackMetaData", true, "CurrentTrackMetaData"),
+                    ("TrackURI", true, "CurrentTrackURI"),
+                    ("RelTime", true, "RelativeTimePosition"),
+                    ("AbsTime", true, "AbsoluteTimePosition"),
+                    ("RelCount", true, "RelativeCounterPosition"),
+                    ("AbsCount", true, "AbsoluteCounterPosition"),
+                ],
+            ),
+            (
+                "GetDeviceCapabilities",
+                &[
+                    INSTANCE,
+                    ("PlayMedia", true, "PossiblePlaybackStorageMedia"),
+                    ("RecMedia", true, "PossibleRecordStorageMedia"),
+                    ("RecQualityModes", true, "PossibleRecordQualityModes"),
+                ],
+            ),
+            (
+                "GetTransportSettings",
+                &[
+                    INSTANCE,
+                    ("PlayMode", true, "CurrentPlayMode"),
+                    ("RecQualityMode", true, "CurrentRecordQualityMode"),
+                ],
+            ),
+            (
+                "GetCurrentTransportActions",
+                &[INSTANCE, ("Actions", true, "CurrentTransportActions")],
+            ),
+            ("Stop", &[INSTANCE]),
+            ("Play", &[INSTANCE, ("Speed", false, "TransportPlaySpeed")]),
+            ("Pause", &[INSTANCE]),
+            (
+                "Seek",
+                &[
+                    INSTANCE,
+                    ("Unit", false, "A_ARG_TYPE_SeekMode"),
+                    ("Target", false, "A_ARG_TYPE_SeekTarget"),
+                ],
+            ),
+            ("Next", &[INSTANCE]),
+            ("Previous", &[INSTANCE]),
+        ],
+        variables: &[
+            (
+                "TransportState",
+                "string",
+                &[
+                    "STOPPED",
+                    "PLAYING",
+                    "PAUSED_PLAYBACK",
+                    "TRANSITIONING",
+                    "NO_MEDIA_PRESENT",
+                ],
+            ),
+            ("TransportStatus", "string", &["OK", "ERROR_OCCURRED"]),
+            ("PlaybackStorageMedium", "string", &["NETWORK", "NONE"]),
+            ("RecordStorageMedium", "string", &["NOT_IMPLEMENTED"]),
+            ("PossiblePlaybackStorageMedia", "string", &[]),
+            ("PossibleRecordStorageMedia", "string", &[]),
+            ("CurrentPlayMode", "string", &["NORMAL"]),
+            ("TransportPlaySpeed", "string", &["1"]),
+            ("RecordMediumWriteStatus", "string", &["NOT_IMPLEMENTED"]),
+            ("CurrentRecordQualityMode", "string", &["NOT_IMPLEMENTED"]),
+            ("PossibleRecordQualityModes", "string", &[]),
+            ("NumberOfTracks", "ui4", &[]),
+            ("CurrentTrack", "ui4", &[]),
+            ("CurrentTrackDuration", "string", &[]),
+            ("CurrentMediaDuration", "string", &[]),
+            ("CurrentTrackMetaData", "string", &[]),
+            ("CurrentTrackURI", "string", &[]),
+            ("AVTransportURI", "string", &[]),
+            ("AVTransportURIMetaData", "string", &[]),
+            ("NextAVTransportURI", "string", &[]),
+            ("NextAVTransportURIMetaData", "string", &[]),
+            ("RelativeTimePosition", "string", &[]),
+            ("AbsoluteTimePosition", "string", &[]),
+            ("RelativeCounterPosition", "i4", &[]),
+            ("AbsoluteCounterPosition", "i4", &[]),
+            ("CurrentTransportActions", "string", &[]),
+            ("LastChange", "string", &[]),
+            ("A_ARG_TYPE_SeekMode", "string", &["REL_TIME", "ABS_TIME"]),
+            ("A_ARG_TYPE_SeekTarget", "string", &[]),
+            ("A_ARG_TYPE_InstanceID", "ui4", &[]),
+        ],
+    },
+    Service {
+        kind: RENDERING_CONTROL,
+        path: "RenderingControl",
+        actions: &[
+            (
+                "GetVolume",
+                &[
+                    INSTANCE,
+                    ("Channel", false, "A_ARG_TYPE_Channel"),
+                    ("CurrentVolume", true, "Volume"),
+                ],
+            ),
+            (
+                "SetVolume",
+                &[
+                    INSTANCE,
+                    ("Channel", false, "A_ARG_TYPE_Channel"),
+                    ("DesiredVolume", false, "Volume"),
+                ],
+            ),
+            (
+                "GetMute",
+                &[
+                    INSTANCE,
+                    ("Channel", false, "A_ARG_TYPE_Channel"),
+                    ("CurrentMute", true, "Mute"),
+                ],
+            ),
+            (
+                "SetMute",
+                &[
+                    INSTANCE,
+                    ("Channel", false, "A_ARG_TYPE_Channel"),
+                    ("DesiredMute", false, "Mute"),
+                ],
+            ),
+        ],
+        variables: &[
+            ("Volume", "ui2", &[]),
+            ("Mute", "boolean", &[]),
+            ("LastChange", "string", &[]),
+            ("A_ARG_TYPE_Channel", "string", &["Master"]),
+            ("A_ARG_TYPE_InstanceID", "ui4", &[]),
+        ],
+    },
+    Service {
+        kind: CONNECTION_MANAGER,
+        path: "ConnectionManager",
+        actions: &[
+            (
+                "GetProtocolInfo",
+                &[
+                    ("Source", true, "SourceProtocolInfo"),
+                    ("Sink", true, "SinkProtocolInfo"),
+                ],
+            ),
+            (
+                "GetCurrentConnectionIDs",
+                &[("ConnectionIDs", true, "CurrentConnectionIDs")],
+            ),
+            (
+                "GetCurrentConnectionInfo",
+                &[
+                    ("ConnectionID", false, "A_ARG_TYPE_ConnectionID"),
+                    ("RcsID", true, "A_ARG_TYPE_RcsID"),
+                    ("AVTransportID", true, "A_ARG_TYPE_AVTransportID"),
+                    ("ProtocolInfo", true, "A_ARG_TYPE_ProtocolInfo"),
+                    (
+                        "PeerConnectionManager",
+                        true,
+                        "A_ARG_TYPE_ConnectionManager",
+                    ),
+                    ("PeerConnectionID", true, "A_ARG_TYPE_ConnectionID"),
+                    ("Direction", true, "A_ARG_TYPE_Direction"),
+                    ("Status", true, "A_ARG_TYPE_ConnectionStatus"),
+                ],
+            ),
+        ],
+        variables: &[
+            ("SourceProtocolInfo", "string", &[]),
+            ("SinkProtocolInfo", "string", &[]),
+            ("CurrentConnectionIDs", "string", &[]),
+            (
+                "A_ARG_TYPE_ConnectionStatus",
+                "string",
+                &[
+                    "OK",
+                    "ContentFormatMismatch",
+                    "InsufficientBandwidth",
+                    "UnreliableChannel",
+                    "Unknown",
+                ],
+            ),
+            ("A_ARG_TYPE_ConnectionManager", "string", &[]),
+            ("A_ARG_TYPE_Direction", "string", &["Input", "Output"]),
+            ("A_ARG_TYPE_ProtocolInfo", "string", &[]),
+            ("A_ARG_TYPE_ConnectionID", "i4", &[]),
+            ("A_ARG_TYPE_AVTransportID", "i4", &[]),
+            ("A_ARG_TYPE_RcsID", "i4", &[]),
+        ],
+    },
+];
+
+/// Formats the renderer accepts, as `GetProtocolInfo` lists them
+pub(crate) const SINK_PROTOCOLS: &str = "http-get:*:audio/wav:*,http-get:*:audio/x-wav:*,\
+http-get:*:audio/L16:*,http-get:*:audio/L24:*";
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Text of the first `name` element in `xml`, whatever its namespace
+/// prefix; empty for an empty element.
+pub(crate) fn argument(xml: &str, name: &str) -> Option<String> {
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>')?;
+        let tag = &rest[..end];
+        let tag_name = tag.split_whitespace().next().unwrap_or_default();
+        let local = tag_name.rsplit(':').next().unwrap_or_default();
+        if local.trim_end_matches('/') == name && !tag.starts_with('/') {
+            if tag.ends_with('/') {
+                return Some(String::new());
+            }
+            let body = &rest[end + 1..];
+            let close = format!("</{}>", tag_name);
+            return Some(unescape(&body[..body.find(&close)?]));
+        }
+        rest = &rest[end + 1..];
+    }
+    None
+}
+
+/// Name of the action a `SOAPACTION` header asks for
+pub(crate) fn action_name(soap_action: &str) -> Option<&str> {
+    soap_action
+        .trim()
+        .trim_matches('"')
+        .rsplit_once('#')
+        .map(|(_, name)| name)
+}
+
+/// Reply to `action` of `service` with `values`.
+pub(crate) fn response(service: &str, action: &str, values: &[(&str, String)]) -> String {
+    let body: String = values
+        .iter()
+        .map(|(name, value)| format!("<{0}>{1}</{0}>", name, escape(value)))
+        .collect();
+    envelope(&format!(
+        "<u:{0}Response xmlns:u=\"{1}\">{2}</u:{0}Response>",
+        action, service, body
+    ))
+}
+
+/// A `UPnP` error reply
+pub(crate) fn fault(code: u16, description: &str) -> String {
+    envelope(&format!(
+        "<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>\
+         <detail><UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\">\
+         <errorCode>{}</errorCode><errorDescription>{}</errorDescription>\
+         </UPnPError></detail></s:Fault>",
+        code,
+        escape(description)
+    ))
+}
+
+fn envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body>{}</s:Body></s:Envelope>",
+        body
+    )
+}
+
+/// Root device description
+pub(crate) fn device_description(udn: &str, friendly_name: &str, version: &str) -> String {
+    let services: String = SERVICES
+        .iter()
+        .map(|service| {
+            format!(
+                "<service><serviceType>{0}</serviceType>\
+                 <serviceId>urn:upnp-org:serviceId:{1}</serviceId>\
+                 <SCPDURL>/{1}.xml</SCPDURL>\
+                 <controlURL>/control/{1}</controlURL>\
+                 <eventSubURL>/event/{1}</eventSubURL></service>",
+                service.kind, service.path
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <root xmlns=\"urn:schemas-upnp-org:device-1-0\" xmlns:dlna=\"urn:schemas-dlna-org:device-1-0\">\
+         <specVersion><major>1</major><minor>0</minor></specVersion>\
+         <device><deviceType>{}</deviceType>\
+         <friendlyName>{}</friendlyName>\
+         <manufacturer>Music Assistant</manufacturer>\
+         <manufacturerURL>https://music-assistant.io</manufacturerURL>\
+         <modelName>Music Assistant Desktop</modelName>\
+         <modelNumber>{}</modelNumber>\
+         <UDN>{}</UDN>\
+         <dlna:X_DLNADOC>DMR-1.50</dlna:X_DLNADOC>\
+         <serviceList>{}</serviceList></device></root>",
+        MEDIA_RENDERER,
+        escape(friendly_name),
+        escape(version),
+        udn,
+        services
+    )
+}
+
+/// Service description of `service`
+pub(crate) fn scpd(service: &Service) -> String {
+    let actions: String = service
+        .actions
+        .iter()
+        .map(|(name, arguments)| {
+            let arguments: String = arguments
+                .iter()
+                .map(|(argument, out, variable)| {
+                    format!(
+                        "<argument><name>{}</name><direction>{}</direction>\
+                         <relatedStateVariable>{}</relatedStateVariable></argument>",
+                        argument,
+                        if *out { "out" } else { "in" },
+                        variable
+                    )
+                })
+                .collect();
+            format!(
+                "<action><name>{}</name><argumentList>{}</argumentList></action>",
+                name, arguments
+            )
+        })
+        .collect();
+    let variables: String = service
+        .variables
+        .iter()
+        .map(|(name, data_type, allowed)| {
+            let allowed = if allowed.is_empty() {
+                String::new()
+            } else {
+                let values: String = allowed
+                    .iter()
+                    .map(|value| format!("<allowedValue>{}</allowedValue>", value))
+                    .collect();
+                format!("<allowedValueList>{}</allowedValueList>", values)
+            };
+            let range = if *name == "Volume" {
+                "<allowedValueRange><minimum>0</minimum><maximum>100</maximum>\
+                 <step>1</step></allowedValueRange>"
+            } else {
+                ""
+            };
+            format!(
+                "<stateVariable sendEvents=\"{}\"><name>{}</name><dataType>{}</dataType>{}{}</stateVariable>",
+                if *name == "LastChange" { "yes" } else { "no" },
+                name,
+                data_type,
+                allowed,
+                range
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <scpd xmlns=\"urn:schemas-upnp-org:service-1-0\">\
+         <specVersion><major>1</major><minor>0</minor></specVersion>\
+         <actionList>{}</actionList>\
+         <serviceStateTable>{}</serviceStateTable></scpd>",
+        actions, variables
+    )
+}
+
+/// `H:MM:SS` for a position or duration in milliseconds
+pub(crate) fn format_time(ms: u64) -> String {
+    let seconds = ms / 1_000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Milliseconds for an `H:MM:SS[.fff]` time
+pub(crate) fn parse_time(time: &str) -> Option<u64> {
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut seconds = 0u64;
+    for part in time.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    let millis = match fraction.get(..fraction.len().min(3)) {
+        Some(digits) if !digits.is_empty() => {
+            digits.parse::<u64>().ok()? * 10u64.pow(3 - digits.len() as u32)
+        }
+        _ => 0,
+    };
+    Some(seconds * 1_000 + millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_arguments_from_a_soap_body() {
+        let body = "<?xml version=\"1.0\"?><s:Envelope><s:Body>\
+            <u:SetAVTransportURI xmlns:u=\"urn:schemas-upnp-org:service:AVTransport:1\">\
+            <InstanceID>0</InstanceID>\
+            <CurrentURI>http://ma.local:8097/a.wav?x=1&amp;y=2</CurrentURI>\
+            <CurrentURIMetaData>&lt;DIDL-Lite&gt;&lt;/DIDL-Lite&gt;</CurrentURIMetaData>\
+            <Empty/></u:SetAVTransportURI></s:Body></s:Envelope>";
+        assert_eq!(
+            argument(body, "CurrentURI").as_deref(),
+            Some("http://ma.local:8097/a.wav?x=1&y=2")
+        );
+        assert_eq!(
+            argument(body, "CurrentURIMetaData").as_deref(),
+            Some("<DIDL-Lite></DIDL-Lite>")
+        );
+        assert_eq!(argument(body, "Empty").as_deref(), Some(""));
+        assert_eq!(argument(body, "Speed"), None);
+        assert_eq!(
+            action_name("\"urn:schemas-upnp-org:service:AVTransport:1#Play\""),
+            Some("Play")
+        );
+    }
+
+    #[test]
+    fn converts_times() {
+        assert_eq!(format_time(3_725_400), "1:02:05");
+        assert_eq!(parse_time("1:02:05"), Some(3_725_000));
+        assert_eq!(parse_time("0:00:07.5"), Some(7_500));
+        assert_eq!(parse_time("soon"), None);
+    }
+
+    #[test]
+    fn describes_every_related_variable() {
+        for service in &SERVICES {
+            for (_, arguments) in service.actions {
+                for (_, _, variable) in *arguments {
+                    assert!(
+                        service
+                            .variables
+                            .iter()
+                            .any(|(name, _, _)| name == variable),
+                        "{} has no {}",
+                        service.path,
+                        variable
+                    );
+                }
+            }
+        }
+        let description = device_description("uuid:1", "Desk & Co", "1.0");
+        assert!(description.contains("<friendlyName>Desk &amp; Co</friendlyName>"));
+        assert!(description.contains("<controlURL>/control/AVTransport</controlURL>"));
+    }
+}
@@ -0,0 +1,180 @@
+//! SSDP announcements for the DLNA renderer: answering control points'
+//! searches, and announcing the renderer when it comes and goes.
+
+use super::soap::{MEDIA_RENDERER, SERVICES};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const MULTICAST: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const PORT: u16 = 1900;
+/// How long control points may cache an announcement, in seconds
+const MAX_AGE: u32 = 1800;
+/// How often the renderer re-announces itself, well within [`MAX_AGE`]
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// What the renderer announces and where its description is
+#[derive(Debug, Clone)]
+pub(crate) struct Advertisement {
+    pub(crate) udn: String,
+    pub(crate) location: String,
+    pub(crate) server: String,
+}
+
+impl Advertisement {
+    /// Each notification type, with its unique service name
+    fn targets(&self) -> Vec<(String, String)> {
+        let mut types = vec!["upnp:rootdevice".to_string(), MEDIA_RENDERER.to_string()];
+        types.extend(SERVICES.iter().map(|service| service.kind.to_string()));
+        let mut targets = vec![(self.udn.clone(), self.udn.clone())];
+        targets.extend(types.into_iter().map(|nt| {
+            let usn = format!("{}::{}", self.udn, nt);
+            (nt, usn)
+        }));
+        targets
+    }
+
+    /// `ssdp:alive` or `ssdp:byebye` notifications
+    fn notifications(&self, nts: &str) -> Vec<String> {
+        self.targets()
+            .into_iter()
+            .map(|(nt, usn)| {
+                let mut message = format!(
+                    "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nNT: {}\r\nNTS: {}\r\nUSN: {}\r\n",
+                    MULTICAST, PORT, nt, nts, usn
+                );
+                if nts == "ssdp:alive" {
+                    message.push_str(&format!(
+                        "CACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nSERVER: {}\r\n",
+                        MAX_AGE, self.location, self.server
+                    ));
+                }
+                message.push_str("\r\n");
+                message
+            })
+            .collect()
+    }
+
+    /// Replies to an `M-SEARCH` request, if it's looking for this renderer
+    fn search_responses(&self, request: &str) -> Vec<String> {
+        let mut lines = request.lines();
+        if !lines
+            .next()
+            .is_some_and(|line| line.starts_with("M-SEARCH * "))
+        {
+            return Vec::new();
+        }
+        let Some(search_target) = lines.find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim().eq_ignore_ascii_case("ST").then(|| value.trim())
+        }) else {
+            return Vec::new();
+        };
+        self.targets()
+            .into_iter()
+            .filter(|(nt, _)| search_target == "ssdp:all" || search_target == nt)
+            .map(|(nt, usn)| {
+                format!(
+                    "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nEXT:\r\nLOCATION: {}\r\n\
+                     SERVER: {}\r\nST: {}\r\nUSN: {}\r\n\r\n",
+                    MAX_AGE, self.location, self.server, nt, usn
+                )
+            })
+            .collect()
+    }
+}
+
+/// Join the SSDP group on `interface`.
+pub(crate) fn open_socket(interface: Ipv4Addr) -> Result<Arc<UdpSocket>, String> {
+    let open = || -> std::io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT).into())?;
+        socket.join_multicast_v4(&MULTICAST, &interface)?;
+        socket.set_multicast_if_v4(&interface)?;
+        socket.set_nonblocking(true)?;
+        UdpSocket::from_std(socket.into())
+    };
+    open()
+        .map(Arc::new)
+        .map_err(|e| format!("Failed to open the SSDP socket: {}", e))
+}
+
+async fn send_all(socket: &UdpSocket, messages: Vec<String>, to: SocketAddr) {
+    for message in messages {
+        if let Err(e) = socket.send_to(message.as_bytes(), to).await {
+            log::debug!("[DLNA] Failed to send SSDP message to {}: {}", to, e);
+        }
+    }
+}
+
+/// Announce the renderer and answer searches until cancelled.
+pub(crate) async fn run(socket: Arc<UdpSocket>, advertisement: Advertisement) {
+    let group = SocketAddr::from((MULTICAST, PORT));
+    let mut notify = tokio::time::interval(NOTIFY_INTERVAL);
+    let mut buffer = vec![0u8; 2048];
+    loop {
+        tokio::select! {
+            _ = notify.tick() => {
+                send_all(&socket, advertisement.notifications("ssdp:alive"), group).await;
+            }
+            received = socket.recv_from(&mut buffer) => {
+                let Ok((len, from)) = received else {
+                    continue;
+                };
+                let request = String::from_utf8_lossy(&buffer[..len]);
+                let responses = advertisement.search_responses(&request);
+                send_all(&socket, responses, from).await;
+            }
+        }
+    }
+}
+
+/// Tell control points the renderer is going away.
+pub(crate) async fn bye(socket: &UdpSocket, advertisement: &Advertisement) {
+    let group = SocketAddr::from((MULTICAST, PORT));
+    send_all(socket, advertisement.notifications("ssdp:byebye"), group).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advertisement() -> Advertisement {
+        Advertisement {
+            udn: "uuid:1234".to_string(),
+            location: "http://10.0.0.2:49152/description.xml".to_string(),
+            server: "Linux/1 UPnP/1.0 MusicAssistantDesktop/1.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn answers_searches_for_the_renderer() {
+        let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\
+            MAN: \"ssdp:discover\"\r\nMX: 2\r\nST: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\r\n";
+        let responses = advertisement().search_responses(search);
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0]
+            .contains("USN: uuid:1234::urn:schemas-upnp-org:device:MediaRenderer:1\r\n"));
+        assert!(responses[0].contains("LOCATION: http://10.0.0.2:49152/description.xml\r\n"));
+
+        let all = search.replace("urn:schemas-upnp-org:device:MediaRenderer:1", "ssdp:all");
+        // The device itself, root device, renderer and three services
+        assert_eq!(advertisement().search_responses(&all).len(), 6);
+        let other = search.replace("MediaRenderer", "MediaServer");
+        assert!(advertisement().search_responses(&other).is_empty());
+        assert!(advertisement()
+            .search_responses("NOTIFY * HTTP/1.1\r\n")
+            .is_empty());
+    }
+
+    #[test]
+    fn byebye_has_no_location() {
+        let byebye = advertisement().notifications("ssdp:byebye");
+        assert_eq!(byebye.len(), 6);
+        assert!(byebye.iter().all(|message| !message.contains("LOCATION")));
+        assert!(byebye[0].contains("NT: uuid:1234\r\nNTS: ssdp:byebye\r\nUSN: uuid:1234\r\n"));
+    }
+}
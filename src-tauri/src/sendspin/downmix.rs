@@ -0,0 +1,120 @@
+//! Surround-to-stereo downmix for multichannel streams.
+//!
+//! Channels are assumed to be in WAVE/SMPTE order (FL, FR, FC, LFE, then
+//! back and side pairs). Centre and surround channels are mixed in at -3 dB,
+//! LFE is dropped, and the result is scaled so a full-scale signal on every
+//! channel can't clip.
+
+use super::pcm;
+
+/// -3 dB, the usual ITU-R BS.775 coefficient for centre and surrounds.
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Left/right contribution of each input channel for a layout.
+fn coefficients(channels: usize) -> Vec<(f32, f32)> {
+    const LEFT: (f32, f32) = (1.0, 0.0);
+    const RIGHT: (f32, f32) = (0.0, 1.0);
+    const CENTER: (f32, f32) = (MINUS_3DB, MINUS_3DB);
+    const LFE: (f32, f32) = (0.0, 0.0);
+    const SURROUND_LEFT: (f32, f32) = (MINUS_3DB, 0.0);
+    const SURROUND_RIGHT: (f32, f32) = (0.0, MINUS_3DB);
+    const BACK_CENTER: (f32, f32) = (0.5, 0.5);
+
+    match channels {
+        1 => vec![CENTER],
+        2 => vec![LEFT, RIGHT],
+        3 => vec![LEFT, RIGHT, CENTER],
+        4 => vec![LEFT, RIGHT, SURROUND_LEFT, SURROUND_RIGHT],
+        5 => vec![LEFT, RIGHT, CENTER, SURROUND_LEFT, SURROUND_RIGHT],
+        6 => vec![LEFT, RIGHT, CENTER, LFE, SURROUND_LEFT, SURROUND_RIGHT],
+        7 => vec![
+            LEFT,
+            RIGHT,
+            CENTER,
+            LFE,
+            BACK_CENTER,
+            SURROUND_LEFT,
+            SURROUND_RIGHT,
+        ],
+        8 => vec![
+            LEFT,
+            RIGHT,
+            CENTER,
+            LFE,
+            SURROUND_LEFT,
+            SURROUND_RIGHT,
+            SURROUND_LEFT,
+            SURROUND_RIGHT,
+        ],
+        // Unknown layout: alternate channels between left and right.
+        _ => (0..channels)
+            .map(|index| if index % 2 == 0 { LEFT } else { RIGHT })
+            .collect(),
+    }
+}
+
+/// Fold interleaved `channels`-channel samples down to interleaved stereo.
+pub(crate) fn to_stereo(samples: &[f32], channels: usize) -> Vec<f32> {
+    let coefficients = coefficients(channels);
+    let gain = {
+        let left: f32 = coefficients.iter().map(|c| c.0).sum();
+        let right: f32 = coefficients.iter().map(|c| c.1).sum();
+        1.0 / left.max(right).max(1.0)
+    };
+
+    let mut out = Vec::with_capacity(samples.len() / channels.max(1) * 2);
+    for frame in samples.chunks_exact(channels) {
+        let (mut left, mut right) = (0.0, 0.0);
+        for (sample, (to_left, to_right)) in frame.iter().zip(&coefficients) {
+            left += sample * to_left;
+            right += sample * to_right;
+        }
+        out.push(left * gain);
+        out.push(right * gain);
+    }
+    out
+}
+
+/// Downmix a chunk of little-endian PCM to stereo at the same bit depth.
+pub(crate) fn to_stereo_pcm(bytes: &[u8], channels: u16, bit_depth: u16) -> Vec<u8> {
+    let samples = pcm::to_f32(bytes, bit_depth);
+    pcm::from_f32(&to_stereo(&samples, usize::from(channels)), bit_depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_passes_through_unchanged() {
+        assert_eq!(to_stereo(&[0.25, -0.5], 2), vec![0.25, -0.5]);
+    }
+
+    #[test]
+    fn center_is_split_evenly_and_lfe_dropped() {
+        // 5.1 frame with only centre and LFE
+        let out = to_stereo(&[0.0, 0.0, 1.0, 1.0, 0.0, 0.0], 6);
+        assert!(out[0] > 0.0);
+        assert!((out[0] - out[1]).abs() < f32::EPSILON);
+
+        let lfe_only = to_stereo(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 6);
+        assert_eq!(lfe_only, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn full_scale_on_every_channel_does_not_clip() {
+        for channels in 1..=8 {
+            let out = to_stereo(&vec![1.0; channels], channels);
+            assert!(
+                out.iter().all(|s| *s <= 1.0 + f32::EPSILON),
+                "{channels} channels"
+            );
+        }
+    }
+
+    #[test]
+    fn pcm_downmix_halves_the_frame_size() {
+        let bytes = vec![0u8; 8 * 2 * 10];
+        assert_eq!(to_stereo_pcm(&bytes, 8, 16).len(), 2 * 2 * 10);
+    }
+}
@@ -0,0 +1,87 @@
+//! Balance and mono downmix
+//!
+//! Mono replaces every channel with the average of all of them, so nothing
+//! panned hard to one side is lost on a single speaker or for a listener who
+//! hears in one ear. Balance then turns down the front channel on the side
+//! away from where it points; the other side stays at full level, so the
+//! stage never raises the signal.
+
+use crate::settings::ChannelMix;
+
+#[derive(Debug)]
+pub(crate) struct Balance {
+    mix: ChannelMix,
+}
+
+impl Balance {
+    pub(crate) fn new() -> Self {
+        Self {
+            mix: ChannelMix::default(),
+        }
+    }
+
+    pub(crate) fn configure(&mut self, mix: ChannelMix) {
+        self.mix = mix;
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.mix != ChannelMix::default()
+    }
+
+    /// Gains of the front left and right channels
+    fn gains(&self) -> (f32, f32) {
+        let balance = self.mix.balance.clamp(-100, 100) as f32 / 100.0;
+        (1.0 - balance.max(0.0), 1.0 + balance.min(0.0))
+    }
+
+    pub(crate) fn process(&self, samples: &mut [f32], channels: usize) {
+        if channels < 2 {
+            return;
+        }
+        let (left, right) = self.gains();
+        for frame in samples.chunks_exact_mut(channels) {
+            if self.mix.mono {
+                let average = frame.iter().sum::<f32>() / channels as f32;
+                frame.fill(average);
+            }
+            frame[0] *= left;
+            frame[1] *= right;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(balance: i32, mono: bool) -> Balance {
+        let mut stage = Balance::new();
+        stage.configure(ChannelMix { balance, mono });
+        stage
+    }
+
+    #[test]
+    fn balance_turns_down_the_other_side() {
+        let mut samples = vec![0.5, 0.5];
+        balance(50, false).process(&mut samples, 2);
+        assert_eq!(samples, [0.25, 0.5]);
+
+        let mut samples = vec![0.5, 0.5];
+        balance(-100, false).process(&mut samples, 2);
+        assert_eq!(samples, [0.5, 0.0]);
+        assert!(!balance(0, false).is_active());
+    }
+
+    #[test]
+    fn mono_averages_every_channel() {
+        let mut samples = vec![0.8, 0.0, -0.2, 0.4];
+        balance(0, true).process(&mut samples, 2);
+        assert_eq!(samples, [0.4, 0.4, 0.1, 0.1]);
+
+        // Mono first, so a hard-panned source still reaches the only
+        // speaker left
+        let mut samples = vec![0.0, 0.8];
+        balance(-100, true).process(&mut samples, 2);
+        assert_eq!(samples, [0.4, 0.0]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Headphone crossfeed
+//!
+//! Bauer stereophonic-to-binaural (bs2b) crossfeed: each ear gets a
+//! low-passed, slightly delayed copy of the other channel, and the direct
+//! signal a matching high boost, so the overall tone stays flat. Hard-panned
+//! material then sounds closer to speakers in a room than to two separate
+//! sources pressed against the ears. Only stereo streams are processed.
+
+use crate::settings::Crossfeed as Level;
+
+/// Cutoff of the crossfed signal (Hz) and how much quieter than the direct
+/// signal it is at low frequencies (dB), as in libbs2b's presets
+fn parameters(level: Level) -> Option<(f64, f64)> {
+    match level {
+        Level::Off => None,
+        Level::Mild => Some((700.0, 4.5)),
+        Level::Medium => Some((700.0, 6.0)),
+        Level::Strong => Some((650.0, 9.5)),
+    }
+}
+
+/// Filter coefficients for one sample rate
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    a0_lo: f64,
+    b1_lo: f64,
+    a0_hi: f64,
+    a1_hi: f64,
+    b1_hi: f64,
+    gain: f64,
+}
+
+impl Coefficients {
+    fn new(cutoff_hz: f64, feed_db: f64, sample_rate: u32) -> Self {
+        let fs = f64::from(sample_rate);
+        let gb_lo = feed_db * -5.0 / 6.0 - 3.0;
+        let gb_hi = feed_db / 6.0 - 3.0;
+        let g_lo = 10f64.powf(gb_lo / 20.0);
+        let g_hi = 1.0 - 10f64.powf(gb_hi / 20.0);
+        let fc_hi = cutoff_hz * 2f64.powf((gb_lo - 20.0 * g_hi.log10()) / 12.0);
+
+        let x_lo = (-std::f64::consts::TAU * cutoff_hz / fs).exp();
+        let x_hi = (-std::f64::consts::TAU * fc_hi / fs).exp();
+        Self {
+            a0_lo: g_lo * (1.0 - x_lo),
+            b1_lo: x_lo,
+            a0_hi: 1.0 - g_hi * (1.0 - x_hi),
+            a1_hi: -x_hi,
+            b1_hi: x_hi,
+            gain: 1.0 / (1.0 - g_hi + g_lo),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Crossfeed {
+    level: Level,
+    coefficients: Option<Coefficients>,
+    sample_rate: u32,
+    /// Low-passed crossfeed, high-boosted direct signal and previous input,
+    /// per channel
+    lo: [f64; 2],
+    hi: [f64; 2],
+    previous: [f64; 2],
+}
+
+impl Crossfeed {
+    pub(crate) fn new() -> Self {
+        Self {
+            level: Level::Off,
+            coefficients: None,
+            sample_rate: 0,
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+            previous: [0.0; 2],
+        }
+    }
+
+    /// Use `level`, or turn crossfeed off when the device isn't headphones
+    pub(crate) fn configure(&mut self, level: Level, headphones: bool) {
+        let level = if headphones { level } else { Level::Off };
+        if level != self.level {
+            self.level = level;
+            self.coefficients = None;
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.level != Level::Off
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.lo = [0.0; 2];
+        self.hi = [0.0; 2];
+        self.previous = [0.0; 2];
+    }
+
+    pub(crate) fn process(&mut self, samples: &mut [f32], channels: usize, sample_rate: u32) {
+        if channels != 2 {
+            return;
+        }
+        let Some((cutoff_hz, feed_db)) = parameters(self.level) else {
+            return;
+        };
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.coefficients = None;
+        }
+        let c = *self
+            .coefficients
+            .get_or_insert_with(|| Coefficients::new(cutoff_hz, feed_db, sample_rate));
+
+        for frame in samples.chunks_exact_mut(2) {
+            let input = [f64::from(frame[0]), f64::from(frame[1])];
+            for (channel, x) in input.into_iter().enumerate() {
+                self.lo[channel] = c.a0_lo * x + c.b1_lo * self.lo[channel];
+                self.hi[channel] =
+                    c.a0_hi * x + c.a1_hi * self.previous[channel] + c.b1_hi * self.hi[channel];
+            }
+            self.previous = input;
+            frame[0] = ((self.hi[0] + self.lo[1]) * c.gain) as f32;
+            frame[1] = ((self.hi[1] + self.lo[0]) * c.gain) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// Peak level of each output channel, after settling, for a sine at
+    /// `frequency` on the left channel only
+    fn hard_left(crossfeed: &mut Crossfeed, frequency: f32) -> (f32, f32) {
+        let mut samples: Vec<f32> = (0..RATE)
+            .flat_map(|i| {
+                let sample =
+                    0.5 * (std::f32::consts::TAU * frequency * i as f32 / RATE as f32).sin();
+                [sample, 0.0]
+            })
+            .collect();
+        crossfeed.process(&mut samples, 2, RATE);
+        let tail = &samples[samples.len() / 2..];
+        let peak = |channel: usize| {
+            tail.iter()
+                .skip(channel)
+                .step_by(2)
+                .fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        (peak(0), peak(1))
+    }
+
+    #[test]
+    fn low_frequencies_bleed_into_the_other_ear() {
+        let mut crossfeed = Crossfeed::new();
+        crossfeed.configure(Level::Medium, true);
+        let (left, right) = hard_left(&mut crossfeed, 100.0);
+        assert!(right > 0.1 * left, "left {left}, right {right}");
+        assert!(right < left);
+
+        // Highs barely cross over
+        crossfeed.reset();
+        let (left, right) = hard_left(&mut crossfeed, 10_000.0);
+        assert!(right < 0.05 * left, "left {left}, right {right}");
+    }
+
+    #[test]
+    fn mono_passes_at_unity() {
+        let mut crossfeed = Crossfeed::new();
+        crossfeed.configure(Level::Strong, true);
+        let mut samples = vec![0.5; 2 * RATE as usize];
+        crossfeed.process(&mut samples, 2, RATE);
+        let last = samples[samples.len() - 1];
+        assert!((last - 0.5).abs() < 0.01, "{last}");
+    }
+
+    #[test]
+    fn only_headphones_get_crossfeed() {
+        let mut crossfeed = Crossfeed::new();
+        crossfeed.configure(Level::Mild, false);
+        assert!(!crossfeed.is_active());
+        crossfeed.configure(Level::Mild, true);
+        assert!(crossfeed.is_active());
+    }
+}
@@ -0,0 +1,313 @@
+//! Parametric EQ
+//!
+//! A cascade of biquad filters (from the Audio EQ Cookbook) set up from the
+//! output profile of the player's device. Coefficients are designed for the
+//! stream's sample rate when it's first seen and again when it or the bands
+//! change; filter state carries over so adjusting a band mid-track doesn't
+//! restart the filters.
+
+use crate::settings::{EqBand, EqFilter, OutputProfile};
+use serde::Serialize;
+
+/// A built-in set of bands to start an EQ from
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct EqPreset {
+    pub id: &'static str,
+    pub preamp_db: f32,
+    pub bands: &'static [EqBand],
+}
+
+const fn band(filter: EqFilter, frequency_hz: f32, gain_db: f32, q: f32) -> EqBand {
+    EqBand {
+        filter,
+        frequency_hz,
+        gain_db,
+        q,
+    }
+}
+
+/// Presets offered in settings
+pub const PRESETS: &[EqPreset] = &[
+    EqPreset {
+        id: "flat",
+        preamp_db: 0.0,
+        bands: &[],
+    },
+    EqPreset {
+        id: "bass_boost",
+        preamp_db: -6.0,
+        bands: &[band(EqFilter::LowShelf, 100.0, 6.0, 0.707)],
+    },
+    EqPreset {
+        id: "treble_boost",
+        preamp_db: -4.0,
+        bands: &[band(EqFilter::HighShelf, 8_000.0, 4.0, 0.707)],
+    },
+    EqPreset {
+        id: "vocal",
+        preamp_db: -3.0,
+        bands: &[
+            band(EqFilter::Peaking, 250.0, -2.0, 1.0),
+            band(EqFilter::Peaking, 3_000.0, 3.0, 1.0),
+        ],
+    },
+    EqPreset {
+        id: "loudness",
+        preamp_db: -5.0,
+        bands: &[
+            band(EqFilter::LowShelf, 80.0, 5.0, 0.707),
+            band(EqFilter::HighShelf, 10_000.0, 3.0, 0.707),
+        ],
+    },
+];
+
+/// Normalized biquad coefficients
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Biquad {
+    fn design(band: &EqBand, sample_rate: u32) -> Self {
+        let fs = f64::from(sample_rate);
+        // Keep the corner below Nyquist for low-rate streams
+        let frequency = f64::from(band.frequency_hz).min(fs * 0.49);
+        let w0 = std::f64::consts::TAU * frequency / fs;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * f64::from(band.q));
+        let a = 10f64.powf(f64::from(band.gain_db) / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match band.filter {
+            EqFilter::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            EqFilter::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            EqFilter::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+            EqFilter::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            EqFilter::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Filter one sample (transposed direct form II)
+    fn run(&self, state: &mut [f64; 2], x: f64) -> f64 {
+        let y = self.b0 * x + state[0];
+        state[0] = self.b1 * x - self.a1 * y + state[1];
+        state[1] = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Eq {
+    enabled: bool,
+    preamp: f32,
+    bands: Vec<EqBand>,
+    /// Filters for `bands` at `sample_rate`; empty until designed
+    filters: Vec<Biquad>,
+    sample_rate: u32,
+    /// Filter state per band, per channel
+    state: Vec<Vec<[f64; 2]>>,
+}
+
+impl Eq {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            preamp: 1.0,
+            bands: Vec::new(),
+            filters: Vec::new(),
+            sample_rate: 0,
+            state: Vec::new(),
+        }
+    }
+
+    pub(crate) fn configure(&mut self, profile: &OutputProfile) {
+        self.enabled = profile.eq_enabled;
+        self.preamp = 10f32.powf(profile.eq_preamp_db / 20.0);
+        if profile.eq_bands != self.bands {
+            self.bands.clone_from(&profile.eq_bands);
+            // Redesigned on the next chunk
+            self.filters.clear();
+        }
+    }
+
+    /// Whether the EQ would change the signal
+    pub(crate) fn is_active(&self) -> bool {
+        self.enabled && (!self.bands.is_empty() || self.preamp != 1.0)
+    }
+
+    /// Whether any band or the pre-amp boosts. Filters can overshoot on
+    /// transients as well, so only a net cut everywhere counts as safe.
+    pub(crate) fn can_boost(&self) -> bool {
+        self.is_active()
+            && (self.preamp > 1.0
+                || self.bands.iter().any(|band| {
+                    matches!(band.filter, EqFilter::LowPass | EqFilter::HighPass)
+                        || band.gain_db > 0.0
+                }))
+    }
+
+    /// Clear the filters' memory of past samples, e.g. when the stream is
+    /// cleared
+    pub(crate) fn reset(&mut self) {
+        self.state.clear();
+    }
+
+    pub(crate) fn process(&mut self, samples: &mut [f32], channels: usize, sample_rate: u32) {
+        if sample_rate != self.sample_rate || self.filters.len() != self.bands.len() {
+            self.sample_rate = sample_rate;
+            self.filters = self
+                .bands
+                .iter()
+                .map(|band| Biquad::design(band, sample_rate))
+                .collect();
+        }
+        if self.state.len() != self.filters.len()
+            || self.state.first().is_some_and(|s| s.len() != channels)
+        {
+            self.state = vec![vec![[0.0; 2]; channels]; self.filters.len()];
+        }
+
+        for frame in samples.chunks_exact_mut(channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let mut value = f64::from(*sample * self.preamp);
+                for (filter, state) in self.filters.iter().zip(&mut self.state) {
+                    value = filter.run(&mut state[channel], value);
+                }
+                *sample = value as f32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// Gain in dB the EQ applies to a sine at `frequency`
+    fn response_db(eq: &mut Eq, frequency: f32) -> f32 {
+        let mut samples: Vec<f32> = (0..RATE)
+            .flat_map(|i| {
+                let sample =
+                    0.25 * (std::f32::consts::TAU * frequency * i as f32 / RATE as f32).sin();
+                [sample, sample]
+            })
+            .collect();
+        eq.reset();
+        eq.process(&mut samples, 2, RATE);
+        // Skip the filters settling
+        let tail = &samples[samples.len() / 2..];
+        let peak = tail.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        20.0 * (peak / 0.25).log10()
+    }
+
+    fn eq_with(bands: &[EqBand], preamp_db: f32) -> Eq {
+        let mut eq = Eq::new();
+        eq.configure(&OutputProfile {
+            eq_enabled: true,
+            eq_preamp_db: preamp_db,
+            eq_bands: bands.to_vec(),
+            ..OutputProfile::default()
+        });
+        eq
+    }
+
+    #[test]
+    fn peaking_band_boosts_its_frequency_only() {
+        let mut eq = eq_with(&[band(EqFilter::Peaking, 1_000.0, 6.0, 1.0)], 0.0);
+        assert!((response_db(&mut eq, 1_000.0) - 6.0).abs() < 0.1);
+        assert!(response_db(&mut eq, 50.0).abs() < 0.3);
+        assert!(response_db(&mut eq, 15_000.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn shelves_and_passes_shape_the_ends() {
+        let mut low_shelf = eq_with(&[band(EqFilter::LowShelf, 200.0, -6.0, 0.707)], 0.0);
+        assert!((response_db(&mut low_shelf, 30.0) + 6.0).abs() < 0.3);
+        assert!(response_db(&mut low_shelf, 5_000.0).abs() < 0.3);
+
+        let mut high_pass = eq_with(&[band(EqFilter::HighPass, 1_000.0, 0.0, 0.707)], 0.0);
+        assert!(response_db(&mut high_pass, 100.0) < -30.0);
+        assert!(response_db(&mut high_pass, 10_000.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn preamp_applies_across_the_band() {
+        let mut eq = eq_with(&[], -6.0);
+        assert!(eq.is_active());
+        assert!((response_db(&mut eq, 440.0) + 6.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn disabled_or_flat_eq_is_inactive() {
+        let mut eq = eq_with(&[band(EqFilter::Peaking, 1_000.0, 6.0, 1.0)], 0.0);
+        eq.configure(&OutputProfile {
+            eq_enabled: false,
+            ..OutputProfile::default()
+        });
+        assert!(!eq.is_active());
+
+        let flat = PRESETS.iter().find(|p| p.id == "flat").unwrap();
+        let eq = eq_with(flat.bands, flat.preamp_db);
+        assert!(!eq.is_active());
+    }
+
+    #[test]
+    fn presets_leave_headroom_for_their_boosts() {
+        for preset in PRESETS {
+            let boost = preset
+                .bands
+                .iter()
+                .map(|b| b.gain_db)
+                .fold(0.0f32, f32::max);
+            assert!(preset.preamp_db + boost <= 0.0, "{}", preset.id);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Gain stages
+//!
+//! Loudness normalization (`ReplayGain`) brings tracks to a common loudness
+//! using the integrated loudness Music Assistant measured for the track or
+//! its album, so a queue mixing sources and masterings plays at an even
+//! level. Gain changes are ramped rather than applied in one step so a new
+//! track's gain never clicks. The same ramp fades audio back in when
+//! playback resumes after a clear.
+
+use crate::ma_api::Loudness;
+use crate::settings::ReplayGainMode;
+use std::time::Duration;
+
+/// Loudness normalized tracks are brought to, in LUFS (the `ReplayGain` 2.0
+/// reference level)
+const REFERENCE_LUFS: f64 = -18.0;
+/// Limits of the normalization gain, so a mismeasured track can't blast or
+/// vanish
+const MAX_GAIN_DB: f64 = 12.0;
+const MIN_GAIN_DB: f64 = -24.0;
+/// Time over which a gain change is spread
+const RAMP: Duration = Duration::from_millis(50);
+/// Time over which audio fades in after a pause or seek
+pub(crate) const FADE_IN: Duration = Duration::from_millis(200);
+
+/// Normalization gain in dB for `loudness` under `mode`, including
+/// `preamp_db`. Tracks that weren't measured are left alone.
+pub(crate) fn replay_gain_db(mode: ReplayGainMode, loudness: &Loudness, preamp_db: i32) -> f64 {
+    let measured = match mode {
+        ReplayGainMode::Off => return 0.0,
+        ReplayGainMode::Track => loudness.track_lufs.or(loudness.album_lufs),
+        ReplayGainMode::Album => loudness.album_lufs.or(loudness.track_lufs),
+    };
+    measured.map_or(0.0, |lufs| {
+        (REFERENCE_LUFS - lufs + f64::from(preamp_db)).clamp(MIN_GAIN_DB, MAX_GAIN_DB)
+    })
+}
+
+/// A gain that ramps to each new value
+#[derive(Debug)]
+pub(crate) struct Gain {
+    current: f32,
+    target: f32,
+    ramp: Duration,
+}
+
+impl Gain {
+    pub(crate) fn new() -> Self {
+        Self::with_ramp(RAMP)
+    }
+
+    pub(crate) fn with_ramp(ramp: Duration) -> Self {
+        Self {
+            current: 1.0,
+            target: 1.0,
+            ramp,
+        }
+    }
+
+    /// Start from silence and ramp up to unity
+    pub(crate) fn fade_in(&mut self) {
+        self.current = 0.0;
+        self.target = 1.0;
+    }
+
+    pub(crate) fn set_db(&mut self, db: f64) {
+        self.target = 10f64.powf(db / 20.0) as f32;
+    }
+
+    /// Whether the gain is or is heading above unity
+    pub(crate) fn can_boost(&self) -> bool {
+        self.current > 1.0 || self.target > 1.0
+    }
+
+    /// Whether the stage leaves the signal untouched
+    pub(crate) fn is_unity(&self) -> bool {
+        self.current == 1.0 && self.target == 1.0
+    }
+
+    pub(crate) fn process(&mut self, samples: &mut [f32], channels: usize, sample_rate: u32) {
+        if self.current == self.target {
+            if self.current != 1.0 {
+                samples
+                    .iter_mut()
+                    .for_each(|sample| *sample *= self.current);
+            }
+            return;
+        }
+        let ramp_frames = (sample_rate as f32 * self.ramp.as_secs_f32()).max(1.0);
+        let step = (self.target - self.current) / ramp_frames;
+        for frame in samples.chunks_exact_mut(channels) {
+            if (self.target - self.current).abs() <= step.abs() {
+                self.current = self.target;
+            } else {
+                self.current += step;
+            }
+            frame.iter_mut().for_each(|sample| *sample *= self.current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOUD_MASTER: Loudness = Loudness {
+        track_lufs: Some(-8.0),
+        album_lufs: Some(-10.0),
+    };
+
+    #[test]
+    fn modes_pick_their_measurement() {
+        assert_eq!(replay_gain_db(ReplayGainMode::Off, &LOUD_MASTER, 0), 0.0);
+        assert_eq!(
+            replay_gain_db(ReplayGainMode::Track, &LOUD_MASTER, 0),
+            -10.0
+        );
+        assert_eq!(replay_gain_db(ReplayGainMode::Album, &LOUD_MASTER, 0), -8.0);
+    }
+
+    #[test]
+    fn missing_measurements_fall_back_or_leave_the_track_alone() {
+        let track_only = Loudness {
+            track_lufs: Some(-20.0),
+            album_lufs: None,
+        };
+        assert_eq!(replay_gain_db(ReplayGainMode::Album, &track_only, 0), 2.0);
+        assert_eq!(
+            replay_gain_db(ReplayGainMode::Track, &Loudness::default(), 6),
+            0.0
+        );
+    }
+
+    #[test]
+    fn preamp_adds_and_gain_is_bounded() {
+        assert_eq!(replay_gain_db(ReplayGainMode::Track, &LOUD_MASTER, 3), -7.0);
+        let very_quiet = Loudness {
+            track_lufs: Some(-45.0),
+            album_lufs: None,
+        };
+        assert_eq!(
+            replay_gain_db(ReplayGainMode::Track, &very_quiet, 0),
+            MAX_GAIN_DB
+        );
+    }
+
+    #[test]
+    fn gain_changes_ramp() {
+        let mut gain = Gain::new();
+        assert!(gain.is_unity());
+        gain.set_db(-6.0);
+        let mut samples = vec![1.0; 2 * 4800];
+        gain.process(&mut samples, 2, 48_000);
+
+        // Starts near unity and settles at -6 dB once the ramp is over
+        assert!(samples[0] > 0.99);
+        let settled = 10f32.powf(-6.0 / 20.0);
+        assert!((samples[samples.len() - 1] - settled).abs() < 1e-6);
+        assert!(!gain.is_unity());
+    }
+
+    #[test]
+    fn fade_in_rises_from_silence() {
+        let mut gain = Gain::with_ramp(FADE_IN);
+        gain.fade_in();
+        // 100 ms, half the fade
+        let mut samples = vec![1.0; 4800];
+        gain.process(&mut samples, 1, 48_000);
+        assert!(samples[0] < 0.001);
+        assert!((samples[samples.len() - 1] - 0.5).abs() < 0.01);
+
+        let mut rest = vec![1.0; 48_000];
+        gain.process(&mut rest, 1, 48_000);
+        assert!(gain.is_unity());
+    }
+}
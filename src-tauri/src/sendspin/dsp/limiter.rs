@@ -0,0 +1,202 @@
+//! Lookahead limiter
+//!
+//! Keeps boosted audio under full scale without the harsh distortion of
+//! clamping each sample. The signal is delayed by a few milliseconds so the
+//! gain can come down smoothly before a peak arrives instead of after it,
+//! then recovers over a slower release. Channels share one gain so the
+//! stereo image doesn't shift while limiting.
+//!
+//! The delay means output lags input: [`Limiter::process`] reports how many
+//! frames earlier than its input the output starts, so the caller can shift
+//! timestamps and stay in sync. The first chunk after switching on comes
+//! out shorter by the lookahead, and switching off flushes what is held
+//! back, so toggling doesn't leave a gap or repeat audio.
+
+use std::collections::VecDeque;
+
+/// Highest level let through, in linear full scale (-1 dBFS)
+const CEILING: f32 = 0.891;
+const LOOKAHEAD_SECS: f64 = 0.003;
+const RELEASE_SECS: f64 = 0.1;
+
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    enabled: bool,
+    channels: usize,
+    sample_rate: u32,
+    /// Lookahead in frames; the output is this many frames minus one behind
+    lookahead: usize,
+    /// Per-frame release step toward unity gain
+    release: f32,
+    /// Interleaved frames waiting to be output
+    delayed: VecDeque<f32>,
+    /// Minimum over the lookahead of the gain each frame needs, as a
+    /// monotonic queue of (frame number, gain)
+    needed: VecDeque<(u64, f32)>,
+    /// Gain after release
+    released: f32,
+    /// The last `lookahead` released gains, averaged to smooth the attack
+    recent: VecDeque<f32>,
+    recent_sum: f64,
+    frame: u64,
+}
+
+impl Limiter {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            channels: 0,
+            sample_rate: 0,
+            lookahead: 1,
+            release: 0.0,
+            delayed: VecDeque::new(),
+            needed: VecDeque::new(),
+            released: 1.0,
+            recent: VecDeque::new(),
+            recent_sum: 0.0,
+            frame: 0,
+        }
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether the limiter is on or still holds audio to flush
+    pub(crate) fn is_active(&self) -> bool {
+        self.enabled || !self.delayed.is_empty()
+    }
+
+    /// Drop held-back audio and gain state, e.g. when the stream is cleared
+    pub(crate) fn reset(&mut self) {
+        self.delayed.clear();
+        self.needed.clear();
+        self.recent.clear();
+        self.recent_sum = 0.0;
+        self.released = 1.0;
+    }
+
+    /// Limit interleaved `samples` in place, returning how many frames
+    /// before the input the output starts
+    pub(crate) fn process(
+        &mut self,
+        samples: &mut Vec<f32>,
+        channels: usize,
+        sample_rate: u32,
+    ) -> usize {
+        if channels != self.channels || sample_rate != self.sample_rate {
+            self.reset();
+            self.channels = channels;
+            self.sample_rate = sample_rate;
+            self.lookahead = ((f64::from(sample_rate) * LOOKAHEAD_SECS).ceil() as usize).max(1);
+            self.release = (1.0 - (-1.0 / (f64::from(sample_rate) * RELEASE_SECS)).exp()) as f32;
+        }
+        let held = self.delayed.len() / channels;
+
+        if !self.enabled {
+            let mut flushed: Vec<f32> = self.delayed.drain(..).collect();
+            flushed.append(samples);
+            *samples = flushed;
+            self.reset();
+            return held;
+        }
+
+        let mut output = Vec::with_capacity(samples.len());
+        for frame in samples.chunks_exact(channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            let gain = if peak > CEILING { CEILING / peak } else { 1.0 };
+
+            // Sliding minimum of the needed gain over the lookahead
+            while self.needed.back().is_some_and(|(_, g)| *g >= gain) {
+                self.needed.pop_back();
+            }
+            self.needed.push_back((self.frame, gain));
+            while self
+                .needed
+                .front()
+                .is_some_and(|(n, _)| *n + self.lookahead as u64 <= self.frame)
+            {
+                self.needed.pop_front();
+            }
+            let minimum = self.needed.front().map_or(1.0, |(_, g)| *g);
+            self.released = minimum.min(self.released + (1.0 - self.released) * self.release);
+
+            // Averaging over the lookahead turns steps into ramps that still
+            // reach each peak's gain by the time the peak is output
+            self.recent.push_back(self.released);
+            self.recent_sum += f64::from(self.released);
+            if self.recent.len() > self.lookahead {
+                self.recent_sum -= f64::from(self.recent.pop_front().unwrap_or(1.0));
+            }
+            let smoothed = (self.recent_sum / self.recent.len() as f64) as f32;
+
+            self.delayed.extend(frame);
+            if self.delayed.len() / channels >= self.lookahead {
+                output.extend(self.delayed.drain(..channels).map(|s| s * smoothed));
+            }
+            self.frame += 1;
+        }
+        *samples = output;
+        held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    fn sine(amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let sample =
+                    amplitude * (std::f32::consts::TAU * 100.0 * i as f32 / RATE as f32).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn peaks_stay_under_the_ceiling() {
+        let mut limiter = Limiter::new();
+        limiter.set_enabled(true);
+        let mut samples = sine(2.0, 48_000);
+        limiter.process(&mut samples, 2, RATE);
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak <= CEILING + 1e-6, "{peak}");
+        assert!(peak > 0.8 * CEILING);
+    }
+
+    #[test]
+    fn quiet_audio_passes_unchanged_but_delayed() {
+        let mut limiter = Limiter::new();
+        limiter.set_enabled(true);
+        let input = sine(0.5, 4_800);
+        let mut first = input[..4_800].to_vec();
+        let mut second = input[4_800..].to_vec();
+
+        assert_eq!(limiter.process(&mut first, 2, RATE), 0);
+        let held = limiter.process(&mut second, 2, RATE);
+        assert_eq!(held, limiter.lookahead - 1);
+
+        let output: Vec<f32> = first.into_iter().chain(second).collect();
+        assert_eq!(output, input[..output.len()]);
+    }
+
+    #[test]
+    fn switching_off_flushes_held_audio() {
+        let mut limiter = Limiter::new();
+        limiter.set_enabled(true);
+        let input = sine(0.5, 1_000);
+        let mut first = input.clone();
+        limiter.process(&mut first, 2, RATE);
+
+        limiter.set_enabled(false);
+        let mut second = input.clone();
+        let held = limiter.process(&mut second, 2, RATE);
+        assert_eq!(first.len() + second.len(), 2 * input.len());
+        assert_eq!(second[..2 * held], input[input.len() - 2 * held..]);
+        assert!(!limiter.is_active());
+    }
+}
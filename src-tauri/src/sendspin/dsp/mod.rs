@@ -0,0 +1,289 @@
+//! Signal processing between the stream and the player
+//!
+//! The chain runs on interleaved `f32` samples (see [`pcm`](super::pcm)) in
+//! the client loop, after any downmix and before resampling. It is set up
+//! from settings when a connection starts and reconfigured live when a DSP
+//! setting changes, so adjustments are heard without reconnecting. A chain
+//! with every stage at its neutral setting is skipped entirely and the PCM
+//! passes through untouched.
+//!
+//! Stages run in order: the fade-in after a clear, an alarm's volume ramp
+//! on the main player, loudness normalization, the EQ and headphone
+//! crossfeed of the output device's profile, the player's balance and mono
+//! downmix, the software volume boost, and finally a limiter whenever an
+//! earlier stage can push the signal past full scale.
+
+mod balance;
+mod crossfeed;
+mod eq;
+mod gain;
+mod limiter;
+
+use crate::ma_api::Loudness;
+use crate::settings::{ReplayGainMode, Settings};
+pub use eq::{EqPreset, PRESETS};
+use gain::Gain;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Settings that running players pick up without reconnecting
+pub const SETTINGS: &[&str] = &[
+    "replay_gain_mode",
+    "replay_gain_preamp_db",
+    "software_boost_db",
+];
+
+/// Gain of the main player's alarm ramp in dB, as `f64` bits; 0 dB when no
+/// alarm is ramping up
+static MAIN_PLAYER_RAMP_DB: AtomicU64 = AtomicU64::new(0);
+
+/// Set the gain of the main player's alarm ramp (see `alarms`). Changes
+/// are smoothed like any other gain change.
+pub fn set_main_player_ramp_db(db: f64) {
+    MAIN_PLAYER_RAMP_DB.store(db.min(0.0).to_bits(), Ordering::Relaxed);
+}
+
+pub(crate) struct Chain {
+    /// Device whose output profile applies
+    audio_device_id: Option<String>,
+    /// Additional player whose balance applies; the main player's if `None`
+    additional_player: Option<String>,
+    /// Whether the player's volume is applied in software, which allows
+    /// boosting past 100%
+    software_volume: bool,
+    replay_gain_mode: ReplayGainMode,
+    replay_gain_preamp_db: i32,
+    /// Loudness of the current track, once fetched
+    loudness: Option<Loudness>,
+    fade: Gain,
+    ramp: Gain,
+    replay_gain: Gain,
+    eq: eq::Eq,
+    crossfeed: crossfeed::Crossfeed,
+    balance: balance::Balance,
+    boost: Gain,
+    limiter: limiter::Limiter,
+}
+
+impl Chain {
+    pub(crate) fn new(
+        settings: &Settings,
+        audio_device_id: Option<&str>,
+        additional_player: Option<&str>,
+        software_volume: bool,
+    ) -> Self {
+        let mut chain = Self {
+            audio_device_id: audio_device_id.map(str::to_string),
+            additional_player: additional_player.map(str::to_string),
+            software_volume,
+            replay_gain_mode: ReplayGainMode::Off,
+            replay_gain_preamp_db: 0,
+            loudness: None,
+            fade: Gain::with_ramp(gain::FADE_IN),
+            ramp: Gain::new(),
+            replay_gain: Gain::new(),
+            eq: eq::Eq::new(),
+            crossfeed: crossfeed::Crossfeed::new(),
+            balance: balance::Balance::new(),
+            boost: Gain::new(),
+            limiter: limiter::Limiter::new(),
+        };
+        chain.configure(settings);
+        chain
+    }
+
+    /// Apply the DSP settings in `settings`
+    pub(crate) fn configure(&mut self, settings: &Settings) {
+        self.replay_gain_mode = settings.replay_gain_mode;
+        self.replay_gain_preamp_db = settings.replay_gain_preamp_db;
+        self.update_replay_gain();
+        let profile = crate::settings::output_profile(settings, self.audio_device_id.as_deref());
+        self.eq.configure(&profile);
+        self.crossfeed
+            .configure(profile.crossfeed, profile.headphones);
+        self.balance.configure(crate::settings::channel_mix(
+            settings,
+            self.additional_player.as_deref(),
+        ));
+        let boost_db = if self.software_volume {
+            settings.software_boost_db
+        } else {
+            0
+        };
+        self.boost.set_db(f64::from(boost_db));
+    }
+
+    /// Switch to the output profile of another device
+    pub(crate) fn set_audio_device(&mut self, audio_device_id: Option<&str>, settings: &Settings) {
+        self.audio_device_id = audio_device_id.map(str::to_string);
+        self.configure(settings);
+    }
+
+    /// Whether loudness normalization needs the current track's loudness
+    pub(crate) fn wants_loudness(&self) -> bool {
+        self.replay_gain_mode != ReplayGainMode::Off
+    }
+
+    /// Normalize to the loudness of the track now being streamed
+    pub(crate) fn set_loudness(&mut self, loudness: Loudness) {
+        self.loudness = Some(loudness);
+        self.update_replay_gain();
+    }
+
+    fn update_replay_gain(&mut self) {
+        // Until the new track's loudness is known, keep the previous gain;
+        // consecutive tracks are usually from the same album
+        if let Some(loudness) = self.loudness {
+            self.replay_gain.set_db(gain::replay_gain_db(
+                self.replay_gain_mode,
+                &loudness,
+                self.replay_gain_preamp_db,
+            ));
+        } else if self.replay_gain_mode == ReplayGainMode::Off {
+            self.replay_gain.set_db(0.0);
+        }
+    }
+
+    /// The alarm ramp's gain, which only the main player follows
+    fn ramp_db(&self) -> f64 {
+        if self.additional_player.is_some() {
+            return 0.0;
+        }
+        f64::from_bits(MAIN_PLAYER_RAMP_DB.load(Ordering::Relaxed))
+    }
+
+    /// Whether any stage would change the signal
+    pub(crate) fn is_active(&self) -> bool {
+        !self.fade.is_unity()
+            || !self.ramp.is_unity()
+            || self.ramp_db() != 0.0
+            || !self.replay_gain.is_unity()
+            || self.eq.is_active()
+            || self.crossfeed.is_active()
+            || self.balance.is_active()
+            || !self.boost.is_unity()
+            || self.limiter.is_active()
+    }
+
+    /// Whether a stage can raise peaks, so the signal may need limiting
+    fn can_clip(&self) -> bool {
+        self.replay_gain.can_boost() || self.eq.can_boost() || self.boost.can_boost()
+    }
+
+    /// Fade the next audio in, so playback resuming mid-track doesn't click
+    pub(crate) fn fade_in(&mut self) {
+        self.fade.fade_in();
+    }
+
+    /// Forget past samples, e.g. when the stream is cleared
+    pub(crate) fn reset(&mut self) {
+        self.eq.reset();
+        self.crossfeed.reset();
+        self.limiter.reset();
+    }
+
+    /// Process interleaved `samples` in place. Returns how many frames
+    /// before the input the output starts, for the caller to shift the
+    /// chunk's timestamp by; the limiter's lookahead delays the signal.
+    pub(crate) fn process(
+        &mut self,
+        samples: &mut Vec<f32>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> usize {
+        let channels = usize::from(channels);
+        if channels == 0 {
+            return 0;
+        }
+        self.fade.process(samples, channels, sample_rate);
+        self.ramp.set_db(self.ramp_db());
+        self.ramp.process(samples, channels, sample_rate);
+        self.replay_gain.process(samples, channels, sample_rate);
+        if self.eq.is_active() {
+            self.eq.process(samples, channels, sample_rate);
+        }
+        if self.crossfeed.is_active() {
+            self.crossfeed.process(samples, channels, sample_rate);
+        }
+        if self.balance.is_active() {
+            self.balance.process(samples, channels);
+        }
+        self.boost.process(samples, channels, sample_rate);
+        self.limiter.set_enabled(self.can_clip());
+        if self.limiter.is_active() {
+            self.limiter.process(samples, channels, sample_rate)
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(mode: ReplayGainMode) -> Settings {
+        Settings {
+            replay_gain_mode: mode,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn neutral_chain_is_skipped() {
+        let mut chain = Chain::new(&settings(ReplayGainMode::Track), None, None, false);
+        assert!(!chain.is_active(), "nothing to do until loudness is known");
+        chain.set_loudness(Loudness {
+            track_lufs: Some(-18.0),
+            album_lufs: None,
+        });
+        assert!(!chain.is_active(), "already at the reference level");
+    }
+
+    #[test]
+    fn turning_normalization_off_returns_to_unity() {
+        let mut chain = Chain::new(&settings(ReplayGainMode::Track), None, None, false);
+        chain.set_loudness(Loudness {
+            track_lufs: Some(-8.0),
+            album_lufs: None,
+        });
+        assert!(chain.is_active());
+
+        chain.configure(&settings(ReplayGainMode::Off));
+        let mut samples = vec![0.5; 2 * 4800];
+        chain.process(&mut samples, 2, 48_000);
+        assert!(!chain.is_active());
+        assert_eq!(samples[samples.len() - 1], 0.5);
+    }
+
+    #[test]
+    fn boost_only_applies_with_software_volume_and_is_limited() {
+        let settings = Settings {
+            software_boost_db: 12,
+            ..Settings::default()
+        };
+        assert!(!Chain::new(&settings, None, None, false).is_active());
+
+        let mut chain = Chain::new(&settings, None, None, true);
+        let mut samples = vec![0.5; 2 * 4800];
+        let lead = chain.process(&mut samples, 2, 48_000);
+        assert_eq!(lead, 0);
+        assert!(samples.len() < 2 * 4800, "held back by the limiter");
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak > 0.5 && peak < 1.0, "{peak}");
+    }
+
+    #[test]
+    fn eq_follows_the_devices_profile() {
+        let mut settings = Settings::default();
+        settings.output_profiles.insert(
+            "headphones".to_string(),
+            crate::settings::OutputProfile {
+                eq_enabled: true,
+                eq_preamp_db: -3.0,
+                ..Default::default()
+            },
+        );
+        assert!(Chain::new(&settings, Some("headphones"), None, false).is_active());
+        assert!(!Chain::new(&settings, Some("speakers"), None, false).is_active());
+    }
+}
@@ -0,0 +1,112 @@
+//! Errors of the native player
+//!
+//! Every error carries a category as well as its message, so the frontend
+//! can tell a rejected token (ask to sign in again) from a busy output
+//! device (suggest closing the other app) without parsing text. Errors reach
+//! the frontend as `{ "kind": "device_busy", "message": "..." }`.
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SendspinError {
+    /// The server rejected the auth token; reconnecting with the same token
+    /// can't succeed
+    #[error("{0}")]
+    Auth(String),
+    /// The server couldn't be reached, or the connection failed
+    #[error("{0}")]
+    Connection(String),
+    /// The player isn't running
+    #[error("Sendspin client not connected")]
+    NotConnected,
+    /// The player hasn't been configured with a server yet
+    #[error("Sendspin is not configured")]
+    NotConfigured,
+    /// No running player has this ID
+    #[error("Unknown player: {0}")]
+    UnknownPlayer(String),
+    /// The output device doesn't exist, or was unplugged
+    #[error("{0}")]
+    DeviceNotFound(String),
+    /// Another application holds the output device
+    #[error("{0}")]
+    DeviceBusy(String),
+    /// Any other output device failure
+    #[error("{0}")]
+    Device(String),
+    /// The system volume couldn't be read or set
+    #[error("{0}")]
+    VolumeControl(String),
+    /// The running player didn't take a command
+    #[error("{0}")]
+    Command(String),
+    /// Anything else
+    #[error("{0}")]
+    Other(String),
+}
+
+impl SendspinError {
+    /// Category name the frontend matches on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Auth(_) => "auth",
+            Self::Connection(_) => "connection",
+            Self::NotConnected => "not_connected",
+            Self::NotConfigured => "not_configured",
+            Self::UnknownPlayer(_) => "unknown_player",
+            Self::DeviceNotFound(_) => "device_not_found",
+            Self::DeviceBusy(_) => "device_busy",
+            Self::Device(_) => "device",
+            Self::VolumeControl(_) => "volume_control",
+            Self::Command(_) => "command",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
+impl From<String> for SendspinError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
+}
+
+impl From<&str> for SendspinError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.to_string())
+    }
+}
+
+/// For the many callers outside the player that report errors as text
+impl From<SendspinError> for String {
+    fn from(error: SendspinError) -> Self {
+        error.to_string()
+    }
+}
+
+impl Serialize for SendspinError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("SendspinError", 2)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_serialize_with_their_kind() {
+        let value = serde_json::to_value(SendspinError::Auth("Auth rejected".to_string())).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "kind": "auth", "message": "Auth rejected" })
+        );
+        assert_eq!(
+            serde_json::to_value(SendspinError::NotConnected).unwrap()["message"],
+            "Sendspin client not connected"
+        );
+    }
+}
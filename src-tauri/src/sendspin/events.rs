@@ -0,0 +1,163 @@
+//! Tauri events pushed to the frontend when a player's connection status or
+//! now-playing state changes, so the UI doesn't have to poll.
+
+use super::{ConnectionStatus, SendspinError};
+use crate::now_playing::NowPlaying;
+use parking_lot::RwLock;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// Emitted with a [`StatusEvent`] whenever a player's status changes
+pub const STATUS_CHANGED: &str = "sendspin://status-changed";
+/// Emitted with a [`NowPlayingEvent`] whenever a player's now-playing changes
+pub const NOW_PLAYING_CHANGED: &str = "sendspin://now-playing-changed";
+/// Emitted with a [`DeviceLostEvent`] when a player's output device
+/// disappears mid-stream and playback is paused
+pub const DEVICE_LOST: &str = "sendspin://device-lost";
+/// Emitted with a [`DeviceBusyEvent`] when a player's output device turns
+/// out to be held by another application, and again once it's free or
+/// given up on
+pub const DEVICE_BUSY: &str = "sendspin://device-busy";
+/// Emitted with an [`OutputFallbackEvent`] when a player connects on a
+/// fallback device because its own isn't connected, and when it's back on
+/// its own
+pub const OUTPUT_FALLBACK: &str = "sendspin://output-fallback";
+/// Emitted with an [`AuthRequiredEvent`] when the server rejects the auth
+/// token; the player stays disconnected until it gets a new one
+pub const AUTH_REQUIRED: &str = "sendspin://auth-required";
+/// Emitted with a [`VisualizerEvent`] about 30 times a second while the main
+/// player is playing and the visualizer is enabled
+pub const VISUALIZER: &str = "sendspin://visualizer";
+/// Emitted with an [`OutputLevelsEvent`] about 20 times a second while the
+/// main player is playing and a window has subscribed to levels
+pub const OUTPUT_LEVELS: &str = "sendspin://output-levels";
+
+static APP_HANDLE: RwLock<Option<AppHandle>> = RwLock::new(None);
+
+/// Start delivering events to the app's windows.
+pub fn init(app: AppHandle) {
+    *APP_HANDLE.write() = Some(app);
+}
+
+/// Payload of [`STATUS_CHANGED`]
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusEvent {
+    pub player_id: String,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    pub status: ConnectionStatus,
+    /// Why the last connection attempt failed, with its category (see
+    /// [`SendspinError`]); cleared once connected
+    pub error: Option<SendspinError>,
+    /// Seconds until the next reconnect attempt, counting down while
+    /// `status` is `Reconnecting`
+    pub retry_in_secs: Option<u64>,
+}
+
+/// Payload of [`NOW_PLAYING_CHANGED`]
+#[derive(Debug, Clone, Serialize)]
+pub struct NowPlayingEvent {
+    pub player_id: String,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    pub now_playing: NowPlaying,
+}
+
+/// Payload of [`DEVICE_LOST`]
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceLostEvent {
+    pub player_id: String,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    pub audio_device_id: String,
+}
+
+/// Payload of [`DEVICE_BUSY`]
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceBusyEvent {
+    pub player_id: String,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    pub audio_device_id: Option<String>,
+    /// Whether opening the device is being retried
+    pub busy: bool,
+}
+
+/// Payload of [`OUTPUT_FALLBACK`]
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputFallbackEvent {
+    pub player_id: String,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    /// The device chosen for the player
+    pub audio_device_id: Option<String>,
+    /// The device played on instead, or `None` when back on the chosen one
+    pub fallback_device_id: Option<String>,
+}
+
+/// Payload of [`AUTH_REQUIRED`]
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthRequiredEvent {
+    pub player_id: String,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    pub server_url: String,
+    /// The server's reason for the rejection
+    pub reason: String,
+}
+
+/// Payload of [`VISUALIZER`]
+#[derive(Debug, Clone, Serialize)]
+pub struct VisualizerEvent {
+    pub player_id: String,
+    /// Band levels from 0 to 1, lowest frequency first
+    pub bands: Vec<f32>,
+}
+
+/// Payload of [`OUTPUT_LEVELS`]
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputLevelsEvent {
+    pub player_id: String,
+    /// One entry per output channel; empty once playback has stopped
+    pub channels: Vec<super::levels::ChannelLevel>,
+}
+
+pub(crate) fn emit_status(event: &StatusEvent) {
+    emit(STATUS_CHANGED, event);
+}
+
+pub(crate) fn emit_now_playing(event: &NowPlayingEvent) {
+    emit(NOW_PLAYING_CHANGED, event);
+}
+
+pub(crate) fn emit_device_lost(event: &DeviceLostEvent) {
+    emit(DEVICE_LOST, event);
+}
+
+pub(crate) fn emit_device_busy(event: &DeviceBusyEvent) {
+    emit(DEVICE_BUSY, event);
+}
+
+pub(crate) fn emit_output_fallback(event: &OutputFallbackEvent) {
+    emit(OUTPUT_FALLBACK, event);
+}
+
+pub(crate) fn emit_auth_required(event: &AuthRequiredEvent) {
+    emit(AUTH_REQUIRED, event);
+}
+
+pub(crate) fn emit_visualizer(event: &VisualizerEvent) {
+    emit(VISUALIZER, event);
+}
+
+pub(crate) fn emit_output_levels(event: &OutputLevelsEvent) {
+    emit(OUTPUT_LEVELS, event);
+}
+
+fn emit<S: Serialize + Clone>(name: &str, payload: &S) {
+    if let Some(ref app) = *APP_HANDLE.read() {
+        if let Err(e) = app.emit(name, payload.clone()) {
+            log::warn!("[Sendspin] Failed to emit {}: {}", name, e);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! `CoreAudio` hog mode for exclusive output on macOS
+
+#![allow(unsafe_code)] // `CoreAudio` property calls are all `unsafe`; lift the workspace deny.
+
+use coreaudio_sys::*;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+
+/// Hog mode held on one device; restores the device's rate and releases it on drop.
+pub struct HogMode {
+    device_id: AudioDeviceID,
+    original_rate: Option<f64>,
+}
+
+impl HogMode {
+    /// Take hog mode on the output device named `device_name`.
+    pub fn acquire(device_name: &str) -> Result<Self, String> {
+        let device_id = find_device(device_name)?;
+
+        let owner: i32 = get_property(device_id, kAudioDevicePropertyHogMode)
+            .map_err(|status| format!("Failed to read hog mode: {}", status))?;
+        let pid = std::process::id() as i32;
+        if owner != -1 && owner != pid {
+            return Err(format!(
+                "Device is held exclusively by another process (pid {})",
+                owner
+            ));
+        }
+
+        set_property(device_id, kAudioDevicePropertyHogMode, pid)
+            .map_err(|status| format!("Failed to take hog mode: {}", status))?;
+
+        Ok(Self {
+            device_id,
+            original_rate: get_property(device_id, kAudioDevicePropertyNominalSampleRate).ok(),
+        })
+    }
+
+    /// Switch the device's nominal rate so `CoreAudio` doesn't convert.
+    pub fn set_sample_rate(&self, sample_rate: u32) -> Result<(), String> {
+        let current: f64 = get_property(self.device_id, kAudioDevicePropertyNominalSampleRate)
+            .map_err(|status| format!("Failed to read device sample rate: {}", status))?;
+        if (current - f64::from(sample_rate)).abs() < 0.5 {
+            return Ok(());
+        }
+        set_property(
+            self.device_id,
+            kAudioDevicePropertyNominalSampleRate,
+            f64::from(sample_rate),
+        )
+        .map_err(|status| format!("Device can't switch to {} Hz: {}", sample_rate, status))
+    }
+}
+
+impl Drop for HogMode {
+    fn drop(&mut self) {
+        if let Some(rate) = self.original_rate {
+            let _ = set_property(self.device_id, kAudioDevicePropertyNominalSampleRate, rate);
+        }
+        if let Err(status) = set_property(self.device_id, kAudioDevicePropertyHogMode, -1i32) {
+            log::warn!("[Sendspin] Failed to release hog mode: {}", status);
+        }
+    }
+}
+
+/// Find the `CoreAudio` device whose name matches the cpal device name.
+pub(crate) fn find_device(device_name: &str) -> Result<AudioDeviceID, String> {
+    let address = global_address(kAudioHardwarePropertyDevices);
+    let mut size: u32 = 0;
+    let status = unsafe {
+        AudioObjectGetPropertyDataSize(
+            kAudioObjectSystemObject,
+            &raw const address,
+            0,
+            ptr::null(),
+            &raw mut size,
+        )
+    };
+    if status != 0 {
+        return Err(format!("Failed to list audio devices: {}", status));
+    }
+
+    let mut ids = vec![0 as AudioDeviceID; size as usize / mem::size_of::<AudioDeviceID>()];
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            kAudioObjectSystemObject,
+            &raw const address,
+            0,
+            ptr::null(),
+            &raw mut size,
+            ids.as_mut_ptr().cast(),
+        )
+    };
+    if status != 0 {
+        return Err(format!("Failed to list audio devices: {}", status));
+    }
+
+    ids.into_iter()
+        .find(|&id| device_name_of(id).as_deref() == Some(device_name))
+        .ok_or_else(|| format!("Device not found: {}", device_name))
+}
+
+fn device_name_of(device_id: AudioDeviceID) -> Option<String> {
+    let address = global_address(kAudioDevicePropertyDeviceName);
+    let mut name = [0u8; 256];
+    let mut size = name.len() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &raw const address,
+            0,
+            ptr::null(),
+            &raw mut size,
+            name.as_mut_ptr().cast(),
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    CStr::from_bytes_until_nul(&name)
+        .ok()
+        .map(|s| s.to_string_lossy().into_owned())
+}
+
+fn global_address(selector: AudioObjectPropertySelector) -> AudioObjectPropertyAddress {
+    AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMain,
+    }
+}
+
+pub(crate) fn get_property<T: Copy + Default>(
+    device_id: AudioDeviceID,
+    selector: AudioObjectPropertySelector,
+) -> Result<T, OSStatus> {
+    let address = global_address(selector);
+    let mut value = T::default();
+    let mut size = mem::size_of::<T>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &raw const address,
+            0,
+            ptr::null(),
+            &raw mut size,
+            std::ptr::addr_of_mut!(value).cast(),
+        )
+    };
+    if status == 0 {
+        Ok(value)
+    } else {
+        Err(status)
+    }
+}
+
+pub(crate) fn set_property<T: Copy>(
+    device_id: AudioDeviceID,
+    selector: AudioObjectPropertySelector,
+    value: T,
+) -> Result<(), OSStatus> {
+    let address = global_address(selector);
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            device_id,
+            &raw const address,
+            0,
+            ptr::null(),
+            mem::size_of::<T>() as u32,
+            std::ptr::addr_of!(value).cast(),
+        )
+    };
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(status)
+    }
+}
@@ -0,0 +1,358 @@
+//! Exclusive (bit-perfect) access to the output device
+//!
+//! When enabled, the playback thread tries to take the output device away
+//! from the OS mixer before opening a player, so audio reaches the hardware
+//! without being resampled or mixed:
+//!
+//! - macOS: `CoreAudio` hog mode, with the device's nominal rate switched to
+//!   the stream rate
+//! - Linux: direct ALSA output, i.e. a `hw:` device that bypasses dmix and
+//!   the sound server. It only counts as exclusive once the player has it
+//!   open and the kernel reports no subdevice left for other applications.
+//! - Windows: a WASAPI exclusive-mode stream (`AUDCLNT_SHAREMODE_EXCLUSIVE`)
+//!   played by [`wasapi::ExclusivePlayer`] in place of the shared-mode cpal
+//!   player, since cpal can't open one.
+//!
+//! Elsewhere the option isn't offered at all.
+//!
+//! Any failure falls back to normal shared-mode playback; the outcome is
+//! kept in a status flag the settings UI reads.
+
+use cpal::traits::DeviceTrait;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_os = "macos")]
+pub(crate) mod macos;
+#[cfg(target_os = "windows")]
+pub(crate) mod wasapi;
+
+/// Whether exclusive mode is available on this platform
+pub const SUPPORTED: bool = cfg!(any(
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows"
+));
+
+/// Outcome of the last attempt to open the output device exclusively.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", content = "reason", rename_all = "lowercase")]
+pub enum ExclusiveStatus {
+    /// Exclusive mode is disabled in settings, or no player was opened yet.
+    Off,
+    /// The device is held exclusively by this app.
+    Active,
+    /// Exclusive mode was requested but unavailable; playing in shared mode.
+    Fallback(String),
+    /// This platform has no exclusive mode.
+    Unsupported,
+}
+
+static STATUS: RwLock<ExclusiveStatus> = RwLock::new(ExclusiveStatus::Off);
+
+/// Get the current exclusive mode status.
+pub fn status() -> ExclusiveStatus {
+    if !SUPPORTED {
+        return ExclusiveStatus::Unsupported;
+    }
+    STATUS.read().clone()
+}
+
+fn set_status(status: ExclusiveStatus) {
+    *STATUS.write() = status;
+}
+
+/// Exclusive hold on one output device, released on drop. On Windows the
+/// hold is taken by the [`open_wasapi`] player; this only names the device.
+#[cfg_attr(
+    not(any(target_os = "macos", target_os = "linux", target_os = "windows")),
+    allow(dead_code)
+)]
+pub(crate) struct ExclusiveGuard {
+    device_name: String,
+    #[cfg(target_os = "macos")]
+    hog: macos::HogMode,
+}
+
+impl ExclusiveGuard {
+    fn acquire(device_name: &str, sample_rate: u32) -> Result<Self, String> {
+        #[cfg(target_os = "macos")]
+        {
+            let hog = macos::HogMode::acquire(device_name)?;
+            hog.set_sample_rate(sample_rate)?;
+            Ok(Self {
+                device_name: device_name.to_string(),
+                hog,
+            })
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let _ = sample_rate;
+            if is_direct_alsa_device(device_name) {
+                Ok(Self {
+                    device_name: device_name.to_string(),
+                })
+            } else {
+                Err("Select a direct hardware (hw:) output device for exclusive mode".to_string())
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            let _ = sample_rate;
+            Ok(Self {
+                device_name: device_name.to_string(),
+            })
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+        {
+            let _ = (device_name, sample_rate);
+            Err("Exclusive mode is not supported by the audio backend on this platform".to_string())
+        }
+    }
+
+    /// Check the hold once the player has opened the device.
+    fn confirm(&self) -> Result<(), String> {
+        #[cfg(target_os = "linux")]
+        {
+            let info_path = alsa_pcm_info_path(&self.device_name)
+                .ok_or_else(|| format!("Unrecognized ALSA device {}", self.device_name))?;
+            let info = std::fs::read_to_string(&info_path)
+                .map_err(|e| format!("Failed to read {}: {}", info_path, e))?;
+            match subdevices_avail(&info) {
+                Some(0) => Ok(()),
+                Some(_) => Err("The device has more subdevices; other apps can still play".into()),
+                None => Err(format!("Unexpected contents of {}", info_path)),
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            // Hog mode is held from `acquire` on, and the WASAPI player
+            // holds the device once it has opened
+            Ok(())
+        }
+    }
+
+    /// Switch the held device to a new stream's rate.
+    fn set_sample_rate(&self, sample_rate: u32) -> Result<(), String> {
+        #[cfg(target_os = "macos")]
+        {
+            self.hog.set_sample_rate(sample_rate)
+        }
+        #[cfg(not(target_os = "macos"))]
+        {
+            let _ = sample_rate;
+            Ok(())
+        }
+    }
+}
+
+/// Whether an ALSA device name addresses the hardware directly.
+///
+/// `plughw:` converts formats and rates in software, and `default`/`dmix`
+/// go through the mixer, so only plain `hw:` devices are bit-perfect.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn is_direct_alsa_device(name: &str) -> bool {
+    name.starts_with("hw:")
+}
+
+/// Path of the kernel's info on the playback PCM of a `hw:` device, e.g.
+/// `/proc/asound/card1/pcm0p/info` for `hw:1,0` or
+/// `/proc/asound/DAC/pcm0p/info` for `hw:CARD=DAC,DEV=0`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn alsa_pcm_info_path(name: &str) -> Option<String> {
+    let mut card = None;
+    let mut device = "0";
+    for (index, part) in name.strip_prefix("hw:")?.split(',').enumerate() {
+        let positional = match index {
+            0 => "CARD",
+            1 => "DEV",
+            _ => "",
+        };
+        match part.split_once('=').unwrap_or((positional, part)) {
+            ("CARD", value) => card = Some(value),
+            ("DEV", value) => device = value,
+            _ => {}
+        }
+    }
+    let card = card.filter(|card| !card.is_empty())?;
+    let device: u32 = device.parse().ok()?;
+    // Cards are listed by index as `cardN`, and by ID as a link to that
+    let card = if card.bytes().all(|b| b.is_ascii_digit()) {
+        format!("card{}", card)
+    } else {
+        card.to_string()
+    };
+    Some(format!("/proc/asound/{}/pcm{}p/info", card, device))
+}
+
+/// `subdevices_avail` from a PCM's info: how many more times it can be
+/// opened
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn subdevices_avail(info: &str) -> Option<u32> {
+    info.lines()
+        .find_map(|line| line.strip_prefix("subdevices_avail:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Bring the exclusive hold in line with the current setting before a
+/// player is opened on `device` for a stream at `sample_rate`.
+///
+/// Returns the guard to keep alive for as long as the player plays, or
+/// `None` when playing in shared mode. Failures are logged and reported
+/// through [`status`] rather than returned: playback always goes ahead.
+pub(crate) fn prepare(
+    current: Option<ExclusiveGuard>,
+    device: Option<&cpal::Device>,
+    sample_rate: u32,
+) -> Option<ExclusiveGuard> {
+    if !SUPPORTED {
+        return None;
+    }
+    if !crate::settings::read(|s| s.exclusive_mode) {
+        if current.is_some() {
+            log::info!("[Sendspin] Exclusive mode disabled; releasing output device");
+        }
+        set_status(ExclusiveStatus::Off);
+        return None;
+    }
+
+    let Some(device_name) = device
+        .and_then(|d| d.description().ok())
+        .map(|desc| desc.name().to_string())
+    else {
+        drop(current);
+        fallback("No output device available".to_string());
+        return None;
+    };
+
+    if let Some(guard) = current {
+        if guard.device_name == device_name {
+            return match guard.set_sample_rate(sample_rate) {
+                Ok(()) => Some(guard),
+                Err(e) => {
+                    drop(guard);
+                    fallback(e);
+                    None
+                }
+            };
+        }
+    }
+
+    match ExclusiveGuard::acquire(&device_name, sample_rate) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            fallback(e);
+            None
+        }
+    }
+}
+
+/// Report the hold taken by [`prepare`] as active once the player has
+/// opened the device, or let it go if the device isn't exclusive after all.
+pub(crate) fn confirm(guard: &mut Option<ExclusiveGuard>, sample_rate: u32) {
+    let Some(held) = guard.as_ref() else {
+        return;
+    };
+    match held.confirm() {
+        Ok(()) => {
+            if status() != ExclusiveStatus::Active {
+                log::info!(
+                    "[Sendspin] Exclusive mode active on {} at {} Hz",
+                    held.device_name,
+                    sample_rate
+                );
+            }
+            set_status(ExclusiveStatus::Active);
+        }
+        Err(e) => {
+            *guard = None;
+            fallback(e);
+        }
+    }
+}
+
+/// Open the device held by [`prepare`] in WASAPI exclusive mode. `None`
+/// plays in shared mode: nothing is held, or the device can't be opened
+/// exclusively, which is reported like any other fallback.
+#[cfg(target_os = "windows")]
+pub(crate) fn open_wasapi(
+    guard: &mut Option<ExclusiveGuard>,
+    format: &sendspin::audio::AudioFormat,
+    clock_sync: &std::sync::Arc<parking_lot::Mutex<sendspin::sync::ClockSync>>,
+    level: (u8, bool),
+) -> Option<wasapi::ExclusivePlayer> {
+    let held = guard.as_ref()?;
+    match wasapi::ExclusivePlayer::open(&held.device_name, format, clock_sync, level) {
+        Ok(player) => {
+            confirm(guard, format.sample_rate);
+            Some(player)
+        }
+        Err(e) => {
+            *guard = None;
+            fallback(e);
+            None
+        }
+    }
+}
+
+fn fallback(reason: String) {
+    log::warn!(
+        "[Sendspin] Exclusive mode unavailable, using shared mode: {}",
+        reason
+    );
+    set_status(ExclusiveStatus::Fallback(reason));
+}
+
+/// Reset the status when the playback thread goes away.
+pub(crate) fn release(guard: Option<ExclusiveGuard>) {
+    drop(guard);
+    set_status(ExclusiveStatus::Off);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plain_hw_devices_are_direct() {
+        assert!(is_direct_alsa_device("hw:CARD=DAC,DEV=0"));
+        assert!(!is_direct_alsa_device("plughw:CARD=DAC,DEV=0"));
+        assert!(!is_direct_alsa_device("default"));
+        assert!(!is_direct_alsa_device("dmix:CARD=DAC"));
+    }
+
+    #[test]
+    fn finds_the_kernel_info_of_hw_devices() {
+        assert_eq!(
+            alsa_pcm_info_path("hw:CARD=DAC,DEV=1").as_deref(),
+            Some("/proc/asound/DAC/pcm1p/info")
+        );
+        assert_eq!(
+            alsa_pcm_info_path("hw:2,0").as_deref(),
+            Some("/proc/asound/card2/pcm0p/info")
+        );
+        assert_eq!(
+            alsa_pcm_info_path("hw:1").as_deref(),
+            Some("/proc/asound/card1/pcm0p/info")
+        );
+        assert_eq!(alsa_pcm_info_path("hw:1,x"), None);
+        assert_eq!(alsa_pcm_info_path("plughw:1,0"), None);
+    }
+
+    #[test]
+    fn reads_free_subdevices() {
+        let info =
+            "card: 1\ndevice: 0\nstream: PLAYBACK\nsubdevices_count: 1\nsubdevices_avail: 0\n";
+        assert_eq!(subdevices_avail(info), Some(0));
+        assert_eq!(subdevices_avail("subdevices_avail: 7"), Some(7));
+        assert_eq!(subdevices_avail("card: 1"), None);
+    }
+
+    #[test]
+    fn status_serializes_with_reason() {
+        let json = serde_json::to_string(&ExclusiveStatus::Fallback("busy".to_string())).unwrap();
+        assert_eq!(json, r#"{"state":"fallback","reason":"busy"}"#);
+        let json = serde_json::to_string(&ExclusiveStatus::Active).unwrap();
+        assert_eq!(json, r#"{"state":"active"}"#);
+    }
+}
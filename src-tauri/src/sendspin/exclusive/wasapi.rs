@@ -0,0 +1,467 @@
+//! WASAPI exclusive-mode output on Windows
+//!
+//! cpal only opens shared-mode streams, which the audio engine mixes and
+//! resamples to the endpoint's format. This opens the endpoint itself with
+//! `AUDCLNT_SHAREMODE_EXCLUSIVE`, event-driven, so the samples reach the
+//! driver as they are, and plays a [`Schedule`] on a render thread of its
+//! own the way the timed player does for the other backends.
+//!
+//! The device is opened at the stream's rate and channel count, as 16-bit,
+//! 24-bit or 24-in-32-bit integer PCM, whichever it takes first; a device
+//! that takes none of them is left to shared mode.
+
+#![allow(unsafe_code)] // COM calls are all `unsafe`; lift the workspace deny.
+
+use crate::sendspin::pcm;
+use crate::sendspin::rate_switch::windows::find_device;
+use crate::sendspin::timed_player::Schedule;
+use parking_lot::Mutex;
+use sendspin::audio::{AudioBuffer, AudioFormat};
+use sendspin::sync::ClockSync;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use windows::core::{GUID, PCWSTR};
+use windows::Win32::Foundation::{CloseHandle, HANDLE, S_OK, WAIT_OBJECT_0};
+use windows::Win32::Media::Audio::{
+    IAudioClient, IAudioRenderClient, IMMDevice, AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED,
+    AUDCLNT_E_DEVICE_IN_USE, AUDCLNT_SHAREMODE_EXCLUSIVE, AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
+    WAVEFORMATEX, WAVEFORMATEXTENSIBLE, WAVEFORMATEXTENSIBLE_0,
+};
+use windows::Win32::System::Com::{
+    CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED,
+};
+use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject};
+
+/// `KSDATAFORMAT_SUBTYPE_PCM`: integer PCM
+const SUBTYPE_PCM: GUID = GUID::from_u128(0x0000_0001_0000_0010_8000_00aa_0038_9b71);
+
+/// `wFormatTag` of formats laid out as `WAVEFORMATEXTENSIBLE`
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Size of the `WAVEFORMATEXTENSIBLE` fields past `WAVEFORMATEX`
+const EXTENSIBLE_SIZE: u16 =
+    (std::mem::size_of::<WAVEFORMATEXTENSIBLE>() - std::mem::size_of::<WAVEFORMATEX>()) as u16;
+
+/// How long the device may go without asking for audio before the render
+/// thread gives up on it
+const EVENT_TIMEOUT_MS: u32 = 2_000;
+
+/// Full scale of decoded samples, which are 24-bit
+const SAMPLE_SCALE: f32 = 8_388_608.0;
+
+/// Sample layouts to offer the device, as (container bits, valid bits),
+/// the stream's own depth first
+fn sample_layouts(bit_depth: u16) -> [(u16, u16); 3] {
+    if bit_depth == 16 {
+        [(16, 16), (24, 24), (32, 24)]
+    } else {
+        [(24, 24), (32, 24), (16, 16)]
+    }
+}
+
+/// Speaker positions of the usual layouts for `channels` channels
+fn channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => 0x4,   // front center
+        2 => 0x3,   // front left, right
+        4 => 0x33,  // front and back left, right
+        6 => 0x3F,  // 5.1
+        8 => 0x63F, // 7.1
+        _ => 0,
+    }
+}
+
+fn wave_format(format: &AudioFormat, (container, valid): (u16, u16)) -> WAVEFORMATEXTENSIBLE {
+    let block_align = format.channels * container / 8;
+    WAVEFORMATEXTENSIBLE {
+        Format: WAVEFORMATEX {
+            wFormatTag: WAVE_FORMAT_EXTENSIBLE,
+            nChannels: format.channels,
+            nSamplesPerSec: format.sample_rate,
+            nAvgBytesPerSec: format.sample_rate * u32::from(block_align),
+            nBlockAlign: block_align,
+            wBitsPerSample: container,
+            cbSize: EXTENSIBLE_SIZE,
+        },
+        Samples: WAVEFORMATEXTENSIBLE_0 {
+            wValidBitsPerSample: valid,
+        },
+        dwChannelMask: channel_mask(format.channels),
+        SubFormat: SUBTYPE_PCM,
+    }
+}
+
+/// Microseconds from `origin` to `instant`, negative if it's earlier
+fn micros_since(origin: Instant, instant: Instant) -> i64 {
+    match instant.checked_duration_since(origin) {
+        Some(after) => after.as_micros() as i64,
+        None => -(origin.duration_since(instant).as_micros() as i64),
+    }
+}
+
+/// Plays to an output device held in exclusive mode, released on drop
+pub(crate) struct ExclusivePlayer {
+    schedule: Arc<Mutex<Schedule>>,
+    clock_sync: Arc<Mutex<ClockSync>>,
+    /// Local times in the schedule are counted from here
+    origin: Instant,
+    /// Volume and mute, set together on the schedule
+    level: Cell<(u8, bool)>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ExclusivePlayer {
+    /// Open `device_name` exclusively for `format`.
+    pub(crate) fn open(
+        device_name: &str,
+        format: &AudioFormat,
+        clock_sync: &Arc<Mutex<ClockSync>>,
+        (volume, muted): (u8, bool),
+    ) -> Result<Self, String> {
+        let origin = Instant::now();
+        let mut schedule = Schedule::new(format.channels, format.sample_rate);
+        schedule.set_volume(volume, muted);
+        let schedule = Arc::new(Mutex::new(schedule));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = std_mpsc::channel::<Result<(), String>>();
+        let render = RenderThread {
+            device_name: device_name.to_string(),
+            format: format.clone(),
+            schedule: Arc::clone(&schedule),
+            stop: Arc::clone(&stop),
+            origin,
+        };
+        let thread = thread::Builder::new()
+            .name("wasapi-exclusive".to_string())
+            .spawn(move || render.run(&ready_tx))
+            .map_err(|e| format!("Failed to start the render thread: {}", e))?;
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                schedule,
+                clock_sync: Arc::clone(clock_sync),
+                origin,
+                level: Cell::new((volume, muted)),
+                stop,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("The render thread exited".to_string()),
+        }
+    }
+
+    /// Queue `buffer` to play when its timestamp says. Dropped while the
+    /// clock isn't synchronized, as there is no saying when that is.
+    pub(crate) fn enqueue(&self, buffer: &AudioBuffer) {
+        let Some(plays_at) = self
+            .clock_sync
+            .lock()
+            .server_to_local_instant(buffer.timestamp)
+        else {
+            return;
+        };
+        let samples = buffer
+            .samples
+            .iter()
+            .map(|sample| sample.0 as f32 / SAMPLE_SCALE)
+            .collect();
+        self.schedule
+            .lock()
+            .push(micros_since(self.origin, plays_at), samples);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.schedule.lock().clear();
+    }
+
+    pub(crate) fn set_volume(&self, volume: u8) {
+        let (_, muted) = self.level.get();
+        self.level.set((volume, muted));
+        self.schedule.lock().set_volume(volume, muted);
+    }
+
+    pub(crate) fn set_mute(&self, muted: bool) {
+        let (volume, _) = self.level.get();
+        self.level.set((volume, muted));
+        self.schedule.lock().set_volume(volume, muted);
+    }
+
+    pub(crate) fn set_static_delay(&self, delay_ms: u16) {
+        self.schedule.lock().set_static_delay(delay_ms);
+    }
+}
+
+impl Drop for ExclusivePlayer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // Wakes within a device period, or the event timeout if the
+            // device stopped asking
+            let _ = thread.join();
+        }
+    }
+}
+
+/// What the render thread needs to open the device and play to it
+struct RenderThread {
+    device_name: String,
+    format: AudioFormat,
+    schedule: Arc<Mutex<Schedule>>,
+    stop: Arc<AtomicBool>,
+    origin: Instant,
+}
+
+/// An exclusive-mode stream, opened but not started
+struct Stream {
+    client: IAudioClient,
+    render_client: IAudioRenderClient,
+    event: HANDLE,
+    /// Frames the device asks for at a time
+    frames: u32,
+    layout: (u16, u16),
+    /// From handing a buffer over to hearing its first frame
+    delay: Duration,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.client.Stop();
+            let _ = CloseHandle(self.event);
+        }
+    }
+}
+
+impl RenderThread {
+    fn run(self, ready_tx: &std_mpsc::Sender<Result<(), String>>) {
+        let initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+        match unsafe { self.open() } {
+            Ok(stream) => {
+                let _ = ready_tx.send(Ok(()));
+                if let Err(e) = unsafe { self.render(&stream) } {
+                    log::warn!("[Sendspin] Exclusive output stopped: {}", e);
+                }
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+            }
+        }
+        if initialized {
+            unsafe { CoUninitialize() };
+        }
+    }
+
+    unsafe fn open(&self) -> Result<Stream, String> {
+        let device = find_device(&self.device_name)?;
+        let client = activate(&device)?;
+        let (layout, wave) = sample_layouts(self.format.bit_depth)
+            .into_iter()
+            .map(|layout| (layout, wave_format(&self.format, layout)))
+            .find(|(_, wave)| {
+                client.IsFormatSupported(AUDCLNT_SHAREMODE_EXCLUSIVE, wave_ptr(wave), None) == S_OK
+            })
+            .ok_or_else(|| {
+                format!(
+                    "The device can't play {} channels at {} Hz exclusively",
+                    self.format.channels, self.format.sample_rate
+                )
+            })?;
+
+        let mut period = 0;
+        client
+            .GetDevicePeriod(Some(&mut period), None)
+            .map_err(|e| format!("Failed to read the device period: {}", e))?;
+        let mut client = client;
+        let mut result = initialize(&client, period, &wave);
+        if result
+            .as_ref()
+            .is_err_and(|e| e.code() == AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED)
+        {
+            // Ask again for a period of the whole number of frames the
+            // device suggests
+            let frames = client
+                .GetBufferSize()
+                .map_err(|e| format!("Failed to read the buffer size: {}", e))?;
+            period = (10_000_000 * i64::from(frames) + i64::from(self.format.sample_rate) / 2)
+                / i64::from(self.format.sample_rate);
+            client = activate(&device)?;
+            result = initialize(&client, period, &wave);
+        }
+        result.map_err(|e| {
+            if e.code() == AUDCLNT_E_DEVICE_IN_USE {
+                "The device is in use by another application".to_string()
+            } else {
+                format!("Failed to open the device exclusively: {}", e)
+            }
+        })?;
+
+        let event = CreateEventW(None, false, false, PCWSTR::null())
+            .map_err(|e| format!("Failed to create the render event: {}", e))?;
+        let setup = || -> windows::core::Result<_> {
+            client.SetEventHandle(event)?;
+            let render_client = client.GetService::<IAudioRenderClient>()?;
+            Ok((
+                render_client,
+                client.GetBufferSize()?,
+                client.GetStreamLatency()?,
+            ))
+        };
+        let (render_client, frames, latency) = match setup() {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = CloseHandle(event);
+                return Err(format!("Failed to set up the exclusive stream: {}", e));
+            }
+        };
+        let buffer = u64::from(frames) * 1_000_000 / u64::from(self.format.sample_rate);
+        Ok(Stream {
+            client,
+            render_client,
+            event,
+            frames,
+            layout,
+            // Latency comes in 100ns units
+            delay: Duration::from_micros(buffer + latency.max(0) as u64 / 10),
+        })
+    }
+
+    /// Fill each buffer the device asks for until the player is dropped.
+    unsafe fn render(&self, stream: &Stream) -> Result<(), String> {
+        let channels = usize::from(self.format.channels);
+        let frames = stream.frames as usize;
+        let (container, valid) = stream.layout;
+        let mut samples = vec![0.0; frames * channels];
+        let mut packed = Vec::new();
+        let mut dither = pcm::Dither::new();
+
+        // Silence in the first buffer, so the device has something to play
+        // as soon as it starts
+        self.write(stream, &[], container)?;
+        stream
+            .client
+            .Start()
+            .map_err(|e| format!("Failed to start the device: {}", e))?;
+        log::info!(
+            "[Sendspin] Exclusive output opened: {} channels at {} Hz, {}-bit in {}-bit samples, {} frames a period",
+            self.format.channels,
+            self.format.sample_rate,
+            valid,
+            container,
+            frames
+        );
+
+        while !self.stop.load(Ordering::Relaxed) {
+            if WaitForSingleObject(stream.event, EVENT_TIMEOUT_MS) != WAIT_OBJECT_0 {
+                return Err("The device stopped asking for audio".to_string());
+            }
+            let heard = Instant::now() + stream.delay;
+            self.schedule
+                .lock()
+                .fill(&mut samples, micros_since(self.origin, heard));
+            dither.quantize_into(&samples, channels, valid, &mut packed);
+            self.write(stream, &packed, container)?;
+        }
+        Ok(())
+    }
+
+    /// Hand the device a buffer of `packed` samples, quantized to the valid
+    /// bits; silence where there are none.
+    unsafe fn write(&self, stream: &Stream, packed: &[u8], container: u16) -> Result<(), String> {
+        let bytes =
+            stream.frames as usize * usize::from(self.format.channels) * usize::from(container / 8);
+        let data = stream
+            .render_client
+            .GetBuffer(stream.frames)
+            .map_err(|e| format!("Failed to get a device buffer: {}", e))?;
+        let out = std::slice::from_raw_parts_mut(data, bytes);
+        if container == 32 {
+            // 24 valid bits at the top of each 32-bit sample
+            out.fill(0);
+            for (out, sample) in out.chunks_exact_mut(4).zip(packed.chunks_exact(3)) {
+                out[1..].copy_from_slice(sample);
+            }
+        } else {
+            let len = packed.len().min(bytes);
+            out[..len].copy_from_slice(&packed[..len]);
+            out[len..].fill(0);
+        }
+        stream
+            .render_client
+            .ReleaseBuffer(stream.frames, 0)
+            .map_err(|e| format!("Failed to release a device buffer: {}", e))
+    }
+}
+
+unsafe fn activate(device: &IMMDevice) -> Result<IAudioClient, String> {
+    device
+        .Activate(CLSCTX_ALL, None)
+        .map_err(|e| format!("Failed to activate audio client: {}", e))
+}
+
+unsafe fn initialize(
+    client: &IAudioClient,
+    period: i64,
+    wave: &WAVEFORMATEXTENSIBLE,
+) -> windows::core::Result<()> {
+    // In exclusive mode the buffer is one period, handed over whole
+    client.Initialize(
+        AUDCLNT_SHAREMODE_EXCLUSIVE,
+        AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
+        period,
+        period,
+        wave_ptr(wave),
+        None,
+    )
+}
+
+fn wave_ptr(wave: &WAVEFORMATEXTENSIBLE) -> *const WAVEFORMATEX {
+    std::ptr::from_ref(wave).cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sendspin::audio::Codec;
+
+    #[test]
+    fn offers_the_stream_depth_first() {
+        assert_eq!(sample_layouts(16)[0], (16, 16));
+        assert_eq!(sample_layouts(24)[..2], [(24, 24), (32, 24)]);
+    }
+
+    #[test]
+    fn describes_the_stream_as_extensible_pcm() {
+        let format = AudioFormat {
+            codec: Codec::Pcm,
+            sample_rate: 96_000,
+            channels: 2,
+            bit_depth: 24,
+            codec_header: None,
+        };
+        let wave = wave_format(&format, (32, 24));
+        let block_align = wave.Format.nBlockAlign;
+        let avg_bytes = wave.Format.nAvgBytesPerSec;
+        let valid = unsafe { wave.Samples.wValidBitsPerSample };
+        let mask = wave.dwChannelMask;
+        assert_eq!(block_align, 8);
+        assert_eq!(avg_bytes, 768_000);
+        assert_eq!(valid, 24);
+        assert_eq!(mask, 0x3);
+    }
+
+    #[test]
+    fn counts_local_times_from_the_origin() {
+        let origin = Instant::now();
+        assert_eq!(
+            micros_since(origin, origin + Duration::from_millis(5)),
+            5_000
+        );
+        if let Some(before) = origin.checked_sub(Duration::from_millis(5)) {
+            assert_eq!(micros_since(origin, before), -5_000);
+        }
+    }
+}
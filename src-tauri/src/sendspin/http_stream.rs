@@ -0,0 +1,338 @@
+//! Fetching and unpacking PCM streamed over HTTP, for the Slimproto and
+//! DLNA backends.
+//!
+//! The response body is the track. PCM comes either raw, in a layout the
+//! server gives separately (the Slimproto `strm` command, or the DLNA
+//! stream's content type), or as a WAVE file whose header says.
+
+use super::backend_address;
+use super::pcm::{self, PcmFormat};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+/// Most bytes of HTTP response headers or WAVE header to wait for
+const MAX_HEADER: usize = 64 * 1024;
+const READ_SIZE: usize = 32 * 1024;
+
+/// PCM layout a stream is announced with; `None` fields come from the
+/// stream's WAVE header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PcmLayout {
+    pub(crate) bit_depth: Option<u16>,
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) channels: Option<u16>,
+    pub(crate) big_endian: bool,
+}
+
+impl PcmLayout {
+    /// The layout `content_type` announces: `audio/L16` and `audio/L24`
+    /// carry it in their parameters, WAVE files in their header.
+    pub(crate) fn for_content_type(content_type: &str) -> Result<Self, String> {
+        let mut parts = content_type.split(';').map(str::trim);
+        let mime = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let mut layout = Self {
+            bit_depth: None,
+            sample_rate: None,
+            channels: None,
+            big_endian: false,
+        };
+        match mime.as_str() {
+            "audio/l16" | "audio/l24" => {
+                layout.bit_depth = Some(if mime == "audio/l16" { 16 } else { 24 });
+                layout.big_endian = true;
+                // Mono unless it says otherwise
+                layout.channels = Some(1);
+                for (name, value) in parts.filter_map(|part| part.split_once('=')) {
+                    match name.trim().to_ascii_lowercase().as_str() {
+                        "rate" => layout.sample_rate = value.trim().parse().ok(),
+                        "channels" => layout.channels = value.trim().parse().ok(),
+                        _ => {}
+                    }
+                }
+            }
+            "" | "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => {}
+            _ => return Err(format!("Unsupported stream type: {}", content_type)),
+        }
+        Ok(layout)
+    }
+}
+
+/// Host, port and GET request for a plain HTTP `url`
+pub(crate) fn get_request(url: &str) -> Result<(String, u16, Vec<u8>), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Unsupported stream URL: {}", url))?;
+    let (authority, path) = rest.find('/').map_or((rest, "/"), |i| rest.split_at(i));
+    let (host, port) = backend_address(Some(authority), "", 80)?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: */*\r\ntransferMode.dlna.org: Streaming\r\n\r\n",
+        path, authority
+    );
+    Ok((host, port, request.into_bytes()))
+}
+
+/// Value of the `name` header in an HTTP response head
+pub(crate) fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Progress of a stream fetch
+#[derive(Debug)]
+pub(crate) enum StreamEvent {
+    Connected,
+    /// The response headers, to pass on to the server
+    Headers(Vec<u8>),
+    Data(Vec<u8>),
+    Ended,
+    Failed(String),
+}
+
+/// Send `request` to `host`:`port` and pass the response along until it
+/// ends. The channel's capacity is what's read ahead of playback.
+pub(crate) async fn fetch(
+    host: String,
+    port: u16,
+    request: Vec<u8>,
+    tx: mpsc::Sender<StreamEvent>,
+) {
+    let result = async {
+        let mut stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(|e| format!("Failed to connect to {}:{}: {}", host, port, e))?;
+        stream
+            .write_all(&request)
+            .await
+            .map_err(|e| format!("Failed to request stream: {}", e))?;
+        if tx.send(StreamEvent::Connected).await.is_err() {
+            return Ok(());
+        }
+
+        let mut buffer = Vec::new();
+        let mut chunk = vec![0u8; READ_SIZE];
+        let body_start = loop {
+            if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            if buffer.len() > MAX_HEADER {
+                return Err("Stream response headers too long".to_string());
+            }
+            let read = stream
+                .read(&mut chunk)
+                .await
+                .map_err(|e| format!("Stream read failed: {}", e))?;
+            if read == 0 {
+                return Err("Stream closed before its headers".to_string());
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        };
+        let body = buffer.split_off(body_start);
+        let status = String::from_utf8_lossy(&buffer);
+        let status = status.lines().next().unwrap_or_default();
+        if !status
+            .split_whitespace()
+            .nth(1)
+            .is_some_and(|code| code.starts_with('2'))
+        {
+            return Err(format!("Stream request failed: {}", status));
+        }
+        if tx.send(StreamEvent::Headers(buffer)).await.is_err() {
+            return Ok(());
+        }
+        if !body.is_empty() && tx.send(StreamEvent::Data(body)).await.is_err() {
+            return Ok(());
+        }
+        loop {
+            let read = stream
+                .read(&mut chunk)
+                .await
+                .map_err(|e| format!("Stream read failed: {}", e))?;
+            if read == 0 {
+                let _ = tx.send(StreamEvent::Ended).await;
+                return Ok(());
+            }
+            if tx
+                .send(StreamEvent::Data(chunk[..read].to_vec()))
+                .await
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+    }
+    .await;
+    if let Err(e) = result {
+        let _ = tx.send(StreamEvent::Failed(e)).await;
+    }
+}
+
+/// Turns a PCM response body into whole little-endian frames.
+#[derive(Debug)]
+pub(crate) struct PcmStream {
+    layout: PcmLayout,
+    format: Option<PcmFormat>,
+    big_endian: bool,
+    /// The WAVE header so far, or the start of a frame
+    pending: Vec<u8>,
+}
+
+impl PcmStream {
+    pub(crate) fn new(layout: PcmLayout) -> Self {
+        Self {
+            layout,
+            format: None,
+            big_endian: layout.big_endian,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Layout of the audio, once known
+    pub(crate) fn format(&self) -> Option<PcmFormat> {
+        self.format
+    }
+
+    /// Take the next bytes of the body, returning the whole frames so far.
+    pub(crate) fn feed(&mut self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        self.pending.extend_from_slice(bytes);
+        let format = match self.format {
+            Some(format) => format,
+            None => match self.read_format()? {
+                Some(format) => format,
+                None => return Ok(Vec::new()),
+            },
+        };
+        let frame_bytes =
+            pcm::bytes_per_sample(format.bit_depth).unwrap_or(0) * usize::from(format.channels);
+        let whole = self.pending.len() / frame_bytes * frame_bytes;
+        let mut frames: Vec<u8> = self.pending.drain(..whole).collect();
+        if self.big_endian {
+            let sample_bytes = frame_bytes / usize::from(format.channels);
+            frames
+                .chunks_exact_mut(sample_bytes)
+                .for_each(|sample| sample.reverse());
+        }
+        Ok(frames)
+    }
+
+    /// Work out the layout from the WAVE header, if the stream starts with
+    /// one, or the `strm` command. `None` while the header is incomplete.
+    fn read_format(&mut self) -> Result<Option<PcmFormat>, String> {
+        let format = if b"RIFF".starts_with(&self.pending[..self.pending.len().min(4)]) {
+            match pcm::parse_wave_header(&self.pending) {
+                Ok((format, Some(offset))) => {
+                    self.pending.drain(..offset);
+                    self.big_endian = false;
+                    format
+                }
+                _ if self.pending.len() < MAX_HEADER => return Ok(None),
+                Ok((_, None)) | Err(_) => return Err("Invalid WAVE header".to_string()),
+            }
+        } else {
+            let layout = self.layout;
+            match (layout.sample_rate, layout.channels, layout.bit_depth) {
+                (Some(sample_rate), Some(channels), Some(bit_depth)) => PcmFormat {
+                    sample_rate,
+                    channels,
+                    bit_depth,
+                },
+                _ => return Err("Stream has no WAVE header and no format".to_string()),
+            }
+        };
+        if format.channels == 0 || pcm::bytes_per_sample(format.bit_depth).is_none() {
+            return Err(format!(
+                "Unsupported PCM layout: {} channels at {} bits",
+                format.channels, format.bit_depth
+            ));
+        }
+        self.format = Some(format);
+        Ok(Some(format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(big_endian: bool) -> PcmLayout {
+        PcmLayout {
+            bit_depth: Some(16),
+            sample_rate: Some(44_100),
+            channels: Some(2),
+            big_endian,
+        }
+    }
+
+    #[test]
+    fn passes_whole_frames_and_swaps_big_endian_samples() {
+        let mut stream = PcmStream::new(layout(true));
+        assert_eq!(stream.feed(&[1, 2, 3]), Ok(Vec::new()));
+        assert_eq!(stream.feed(&[4, 5, 6]), Ok(vec![2, 1, 4, 3]));
+        assert_eq!(stream.feed(&[7, 8]), Ok(vec![6, 5, 8, 7]));
+        assert_eq!(
+            stream.format(),
+            Some(PcmFormat {
+                sample_rate: 44_100,
+                channels: 2,
+                bit_depth: 16,
+            })
+        );
+    }
+
+    #[test]
+    fn takes_the_layout_from_a_wave_header() {
+        let mut wave = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+        wave.extend_from_slice(&1u16.to_le_bytes());
+        wave.extend_from_slice(&1u16.to_le_bytes());
+        wave.extend_from_slice(&48_000u32.to_le_bytes());
+        wave.extend_from_slice(&144_000u32.to_le_bytes());
+        wave.extend_from_slice(&3u16.to_le_bytes());
+        wave.extend_from_slice(&24u16.to_le_bytes());
+        wave.extend_from_slice(b"data\xff\xff\xff\xff");
+        wave.extend_from_slice(&[1, 2, 3, 4]);
+
+        // Big-endian raw PCM was asked for, but the header wins
+        let mut stream = PcmStream::new(layout(true));
+        let (head, tail) = wave.split_at(20);
+        assert_eq!(stream.feed(head), Ok(Vec::new()));
+        assert_eq!(stream.feed(tail), Ok(vec![1, 2, 3]));
+        assert_eq!(stream.format().map(|f| f.bit_depth), Some(24));
+
+        let unknown = PcmLayout {
+            sample_rate: None,
+            ..layout(false)
+        };
+        assert!(PcmStream::new(unknown).feed(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn reads_the_layout_from_the_content_type() {
+        assert_eq!(
+            PcmLayout::for_content_type("audio/L16;rate=48000;channels=2"),
+            Ok(PcmLayout {
+                bit_depth: Some(16),
+                sample_rate: Some(48_000),
+                channels: Some(2),
+                big_endian: true,
+            })
+        );
+        let wave = PcmLayout::for_content_type("audio/wav").unwrap();
+        assert_eq!(wave.sample_rate, None);
+        assert!(PcmLayout::for_content_type("audio/flac").is_err());
+    }
+
+    #[test]
+    fn builds_a_request_for_a_url() {
+        let (host, port, request) = get_request("http://192.168.1.5:8097/flow/abc.wav").unwrap();
+        assert_eq!((host.as_str(), port), ("192.168.1.5", 8097));
+        assert!(request.starts_with(b"GET /flow/abc.wav HTTP/1.0\r\nHost: 192.168.1.5:8097\r\n"));
+        assert_eq!(get_request("http://ma.local").unwrap().1, 80);
+        assert!(get_request("https://ma.local/a.wav").is_err());
+
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: audio/L16;rate=44100\r\n";
+        assert_eq!(header(head, "content-type"), Some("audio/L16;rate=44100"));
+        assert_eq!(header(head, "content-length"), None);
+    }
+}
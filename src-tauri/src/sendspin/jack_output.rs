@@ -0,0 +1,197 @@
+//! JACK output
+//!
+//! With the `jack` feature, the playback ports of a running JACK server are
+//! listed as output devices: each client's ports together, and for clients
+//! with more than two, each stereo pair of them on its own, so a channel
+//! pair of a multi-output interface can be picked. Playback goes through
+//! cpal's JACK host, whose client connects itself to the system ports; once
+//! a stream is open its ports are reconnected to the ones picked.
+//!
+//! The JACK server isn't started for the app; with none running, nothing is
+//! listed.
+
+use super::devices::AudioDevice;
+use super::transport::Transport;
+use jack::{Client, ClientOptions, PortFlags};
+use std::time::{Duration, Instant};
+
+/// Prefix of JACK output IDs in the device list and the settings; the rest
+/// is the comma-separated ports to play to, in channel order
+pub const DEVICE_ID_PREFIX: &str = "jack:";
+
+/// Ports of cpal's JACK client, which JACK may suffix to keep unique
+const CPAL_PORTS: &str = "^cpal_client_out[^:]*:";
+
+/// Matches JACK's audio port type, `32 bit float mono audio`
+const AUDIO_PORTS: &str = "audio";
+
+/// How long to wait for a newly opened stream's ports to show up
+const ROUTE_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn connect(name: &str) -> Result<Client, String> {
+    Client::new(name, ClientOptions::NO_START_SERVER)
+        .map(|(client, _)| client)
+        .map_err(|e| format!("JACK server not available: {}", e))
+}
+
+/// The ports to play to behind a device ID, if it is a JACK one
+pub fn ports(device_id: &str) -> Option<Vec<String>> {
+    let ports = device_id.strip_prefix(DEVICE_ID_PREFIX)?;
+    Some(ports.split(',').map(str::to_string).collect())
+}
+
+/// The JACK outputs, if a JACK server is running
+pub fn devices() -> Vec<AudioDevice> {
+    let client = match connect("music-assistant-devices") {
+        Ok(client) => client,
+        Err(e) => {
+            log::debug!("[Sendspin] Not listing JACK outputs: {}", e);
+            return Vec::new();
+        }
+    };
+    let playback_ports = client.ports(None, Some(AUDIO_PORTS), PortFlags::IS_INPUT);
+    group_ports(&playback_ports)
+        .into_iter()
+        .map(|(name, ports)| AudioDevice {
+            id: format!("{}{}", DEVICE_ID_PREFIX, ports.join(",")),
+            name: format!("{} (JACK)", name),
+            is_default: false,
+            sample_rates: vec![client.sample_rate() as u32],
+            max_channels: ports.len() as u16,
+            channel_counts: vec![ports.len() as u16],
+            // JACK ports take 32-bit float
+            bit_depths: vec![16, 24],
+            transport: Transport::Unknown,
+        })
+        .collect()
+}
+
+/// Outputs to list for `ports`, in order: each client's ports, then its
+/// stereo pairs when it has more than two
+fn group_ports(ports: &[String]) -> Vec<(String, Vec<String>)> {
+    let mut clients: Vec<(&str, Vec<String>)> = Vec::new();
+    for port in ports {
+        let Some((client, _)) = port.split_once(':') else {
+            continue;
+        };
+        match clients.iter_mut().find(|(name, _)| *name == client) {
+            Some((_, ports)) => ports.push(port.clone()),
+            None => clients.push((client, vec![port.clone()])),
+        }
+    }
+
+    let mut outputs = Vec::new();
+    for (client, ports) in clients {
+        let short = |port: &String| port[client.len() + 1..].to_string();
+        let pairs: Vec<(String, Vec<String>)> = if ports.len() > 2 {
+            ports
+                .chunks_exact(2)
+                .map(|pair| {
+                    let name = format!("{}: {} + {}", client, short(&pair[0]), short(&pair[1]));
+                    (name, pair.to_vec())
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        outputs.push((client.to_string(), ports));
+        outputs.extend(pairs);
+    }
+    outputs
+}
+
+/// Reconnects the next stream opened on cpal's JACK host to the ports
+/// picked. Made before the stream is opened, applied after.
+pub struct Route {
+    ports: Vec<String>,
+    /// cpal's ports before the stream to route was opened
+    existing_ports: Vec<String>,
+}
+
+impl Route {
+    pub fn new(ports: Vec<String>) -> Result<Self, String> {
+        let client = connect("music-assistant-route")?;
+        Ok(Self {
+            ports,
+            existing_ports: client.ports(Some(CPAL_PORTS), Some(AUDIO_PORTS), PortFlags::IS_OUTPUT),
+        })
+    }
+
+    /// Connect the stream opened since [`Route::new`] to the ports, in
+    /// place of wherever cpal connected it
+    pub fn apply(self) -> Result<(), String> {
+        let client = connect("music-assistant-route")?;
+        let deadline = Instant::now() + ROUTE_TIMEOUT;
+        let stream_ports = loop {
+            let mut new_ports: Vec<String> = client
+                .ports(Some(CPAL_PORTS), Some(AUDIO_PORTS), PortFlags::IS_OUTPUT)
+                .into_iter()
+                .filter(|port| !self.existing_ports.contains(port))
+                .collect();
+            if !new_ports.is_empty() {
+                new_ports.sort();
+                break new_ports;
+            }
+            if Instant::now() >= deadline {
+                return Err("The stream's JACK ports didn't show up".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        for port in &stream_ports {
+            let connections = client
+                .port_by_name(port)
+                .map(|p| p.get_connections())
+                .unwrap_or_default();
+            for connection in connections {
+                let _ = client.disconnect_ports_by_name(port, &connection);
+            }
+        }
+        for (source, destination) in stream_ports.iter().zip(&self.ports) {
+            client
+                .connect_ports_by_name(source, destination)
+                .map_err(|e| format!("Failed to connect {} to {}: {}", source, destination, e))?;
+        }
+        log::info!("[Sendspin] Routed JACK stream to {}", self.ports.join(", "));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(outputs: &[(String, Vec<String>)]) -> Vec<&str> {
+        outputs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn clients_with_many_ports_are_also_listed_by_stereo_pair() {
+        let playback: Vec<String> = [
+            "system:playback_1",
+            "system:playback_2",
+            "system:playback_3",
+            "system:playback_4",
+            "reverb:in_l",
+            "reverb:in_r",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let outputs = group_ports(&playback);
+        assert_eq!(
+            names(&outputs),
+            [
+                "system",
+                "system: playback_1 + playback_2",
+                "system: playback_3 + playback_4",
+                "reverb",
+            ]
+        );
+        assert_eq!(outputs[0].1.len(), 4);
+        assert_eq!(outputs[2].1, ["system:playback_3", "system:playback_4"]);
+        assert_eq!(
+            ports("jack:reverb:in_l,reverb:in_r"),
+            Some(vec!["reverb:in_l".to_string(), "reverb:in_r".to_string()])
+        );
+    }
+}
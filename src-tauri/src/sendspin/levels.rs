@@ -0,0 +1,222 @@
+//! Output level metering
+//!
+//! Peak and RMS level of each channel of the main player's audio, emitted as
+//! [`events::OUTPUT_LEVELS`](super::events::OUTPUT_LEVELS) a few times a
+//! second for level meters. Like the visualizer's frames, levels are held
+//! back until their audio is expected to play. They measure the stream as
+//! sent to the device, before the player's volume.
+//!
+//! Metering only runs while at least one window has subscribed with
+//! [`subscribe`]; a window's subscription ends when it is closed.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often levels are emitted
+pub(crate) const METER_INTERVAL: Duration = Duration::from_millis(50);
+/// Level reported for silence, in dBFS
+pub const FLOOR_DB: f32 = -90.0;
+/// Blocks kept waiting for their play time; well past the longest buffer
+/// the server is asked to keep
+const MAX_PENDING: usize = 500;
+
+/// Labels of the windows showing meters
+static SUBSCRIBERS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+/// Whether `SUBSCRIBERS` is non-empty, for the audio path
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Start or stop sending levels for the window labelled `window`
+pub fn subscribe(window: &str, enabled: bool) {
+    let mut subscribers = SUBSCRIBERS.lock();
+    if enabled {
+        subscribers.insert(window.to_string());
+    } else {
+        subscribers.remove(window);
+    }
+    ENABLED.store(!subscribers.is_empty(), Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Level of one channel, in dBFS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChannelLevel {
+    pub peak_db: f32,
+    pub rms_db: f32,
+}
+
+/// Running peak and energy of one channel
+#[derive(Debug, Clone, Copy, Default)]
+struct Accumulator {
+    peak: f32,
+    sum_squares: f64,
+    samples: u64,
+}
+
+impl Accumulator {
+    fn add(&mut self, other: &Accumulator) {
+        self.peak = self.peak.max(other.peak);
+        self.sum_squares += other.sum_squares;
+        self.samples += other.samples;
+    }
+
+    fn level(&self) -> ChannelLevel {
+        let rms = if self.samples == 0 {
+            0.0
+        } else {
+            (self.sum_squares / self.samples as f64).sqrt() as f32
+        };
+        ChannelLevel {
+            peak_db: to_db(self.peak),
+            rms_db: to_db(rms),
+        }
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(f32::MIN_POSITIVE).log10()).max(FLOOR_DB)
+}
+
+#[derive(Debug)]
+pub(crate) struct LevelMeter {
+    /// Measured blocks and when their audio plays
+    pending: VecDeque<(Instant, Vec<Accumulator>)>,
+    /// Whether the last emitted levels were silence
+    silent: bool,
+}
+
+impl LevelMeter {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            silent: true,
+        }
+    }
+
+    /// Forget measured blocks, e.g. when the stream is cleared
+    pub(crate) fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Measure interleaved `samples` that start playing at `plays_at`
+    pub(crate) fn push(&mut self, samples: &[f32], channels: u16, plays_at: Instant) {
+        let channels = usize::from(channels);
+        if channels == 0 {
+            return;
+        }
+        let mut block = vec![Accumulator::default(); channels];
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in block.iter_mut().zip(frame) {
+                channel.peak = channel.peak.max(sample.abs());
+                channel.sum_squares += f64::from(*sample) * f64::from(*sample);
+                channel.samples += 1;
+            }
+        }
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((plays_at, block));
+    }
+
+    /// Levels over every block that has started playing by `now`. Once
+    /// playback runs out, silence is reported once so meters fall back.
+    pub(crate) fn due(&mut self, now: Instant) -> Option<Vec<ChannelLevel>> {
+        let mut total: Option<Vec<Accumulator>> = None;
+        while self.pending.front().is_some_and(|(at, _)| *at <= now) {
+            let Some((_, block)) = self.pending.pop_front() else {
+                break;
+            };
+            match total {
+                Some(ref mut total) if total.len() == block.len() => {
+                    for (sum, channel) in total.iter_mut().zip(&block) {
+                        sum.add(channel);
+                    }
+                }
+                // A new layout starts over
+                _ => total = Some(block),
+            }
+        }
+        if let Some(total) = total {
+            self.silent = false;
+            return Some(total.iter().map(Accumulator::level).collect());
+        }
+        if self.pending.is_empty() && !self.silent {
+            self.silent = true;
+            return Some(Vec::new());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_are_measured_per_channel() {
+        let t0 = Instant::now();
+        let mut meter = LevelMeter::new();
+        // Left at full scale square wave, right at half
+        let samples: Vec<f32> = (0..480)
+            .flat_map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                [sign, sign * 0.5]
+            })
+            .collect();
+        meter.push(&samples, 2, t0);
+
+        let levels = meter.due(t0).unwrap();
+        assert_eq!(levels.len(), 2);
+        assert!(levels[0].peak_db.abs() < 0.01);
+        assert!(levels[0].rms_db.abs() < 0.01);
+        assert!((levels[1].peak_db + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn silence_reads_as_the_floor() {
+        let t0 = Instant::now();
+        let mut meter = LevelMeter::new();
+        meter.push(&[0.0; 960], 2, t0);
+        let levels = meter.due(t0).unwrap();
+        assert_eq!(levels[0].peak_db, FLOOR_DB);
+        assert_eq!(levels[1].rms_db, FLOOR_DB);
+    }
+
+    #[test]
+    fn blocks_wait_for_their_audio_and_combine() {
+        let t0 = Instant::now();
+        let mut meter = LevelMeter::new();
+        meter.push(&[0.25, 0.25], 2, t0);
+        meter.push(&[0.5, 0.5], 2, t0 + Duration::from_millis(20));
+        meter.push(&[1.0, 1.0], 2, t0 + Duration::from_secs(1));
+
+        let levels = meter.due(t0 + Duration::from_millis(30)).unwrap();
+        // Peak of the two due blocks, not of the one still ahead
+        assert!((levels[0].peak_db + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn silence_follows_once_playback_runs_out() {
+        let t0 = Instant::now();
+        let mut meter = LevelMeter::new();
+        meter.push(&[0.5, 0.5], 2, t0);
+        assert!(meter.due(t0).is_some());
+        assert_eq!(meter.due(t0), Some(Vec::new()));
+        assert_eq!(meter.due(t0), None);
+    }
+
+    #[test]
+    fn subscriptions_are_per_window() {
+        subscribe("main", true);
+        subscribe("mini-player", true);
+        subscribe("main", false);
+        assert!(is_enabled());
+        subscribe("mini-player", false);
+        assert!(!is_enabled());
+    }
+}
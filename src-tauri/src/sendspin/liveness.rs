@@ -0,0 +1,349 @@
+//! Dead connection detection for the Sendspin WebSocket
+//!
+//! A server that vanished without closing the connection (the computer
+//! slept, the Wi-Fi roamed to another access point) leaves reads stalled
+//! until the OS gives up on the socket, which takes many minutes. sendspin-rs
+//! owns the WebSocket once the protocol handshake starts and sends no Ping
+//! frames of its own, so the connection to the server stays here instead:
+//! frames are relayed between it and a loopback WebSocket handed to
+//! sendspin-rs, the server is pinged every third of the timeout, and a
+//! server that sends nothing, not even a Pong, for the whole timeout is
+//! reported through [`Liveness::lost`] and cut off.
+//!
+//! The relay also applies the clock sync settings to sendspin-rs's time
+//! exchanges as they go by (see `time_exchange`), and hands it time replies
+//! ahead of the audio and artwork frames still queued for it, so their
+//! round trip isn't held up behind those.
+
+use super::time_exchange::{self, TimeExchanges};
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::protocol::{Message as WsMessage, Role};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Frames from the server waiting for sendspin-rs to read them, besides
+/// time replies
+const QUEUED_FRAMES: usize = 16;
+
+/// Watch on a relayed connection; stops the relay when dropped.
+pub(super) struct Liveness {
+    lost: oneshot::Receiver<String>,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+impl Liveness {
+    /// Resolves with the reason once the server stops answering. Pending
+    /// forever if the connection ends any other way, which sendspin-rs
+    /// notices itself.
+    pub async fn lost(&mut self) -> String {
+        match (&mut self.lost).await {
+            Ok(reason) => reason,
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Liveness {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Relay `server` through a loopback WebSocket, pinging it and giving up on
+/// it after `timeout` without a frame. Returns the loopback end, for
+/// sendspin-rs.
+pub(super) async fn relay(
+    server: Socket,
+    timeout: Duration,
+    exchanges: TimeExchanges,
+) -> Result<(Socket, Liveness), String> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .map_err(|e| format!("Failed to open the connection relay: {}", e))?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    let client = TcpStream::connect(address)
+        .await
+        .map_err(|e| format!("Failed to open the connection relay: {}", e))?;
+    let own_address = client.local_addr().map_err(|e| e.to_string())?;
+    // Only the connection just made; the port is briefly open to anyone
+    // on this machine
+    let relayed = loop {
+        let (stream, from) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Failed to open the connection relay: {}", e))?;
+        if from == own_address {
+            break stream;
+        }
+    };
+    for stream in [&client, &relayed] {
+        if let Err(e) = stream.set_nodelay(true) {
+            log::warn!("[Sendspin] Failed to disable Nagle's algorithm: {}", e);
+        }
+    }
+
+    let client =
+        WebSocketStream::from_raw_socket(MaybeTlsStream::Plain(client), Role::Client, None).await;
+    let relayed = WebSocketStream::from_raw_socket(relayed, Role::Server, None).await;
+    let (lost_tx, lost) = oneshot::channel();
+    let task = tauri::async_runtime::spawn(async move {
+        if let Err(reason) = run(server, relayed, timeout, exchanges).await {
+            let _ = lost_tx.send(reason);
+        }
+    });
+    Ok((client, Liveness { lost, task }))
+}
+
+/// Relay frames until either side closes, or the server goes quiet (`Err`)
+async fn run(
+    server: Socket,
+    local: WebSocketStream<TcpStream>,
+    timeout: Duration,
+    mut exchanges: TimeExchanges,
+) -> Result<(), String> {
+    let (mut server_tx, mut server_rx) = server.split();
+    let (local_tx, mut local_rx) = local.split();
+    let (time_replies, time_replies_rx) = mpsc::unbounded_channel();
+    let (frames, frames_rx) = mpsc::channel(QUEUED_FRAMES);
+    tauri::async_runtime::spawn(forward(local_tx, time_replies_rx, frames_rx));
+    let mut ping = tokio::time::interval_at(Instant::now() + timeout / 3, timeout / 3);
+    let mut deadline = Instant::now() + timeout;
+    let silent = || format!("No response from the server in {:?}", timeout);
+
+    loop {
+        tokio::select! {
+            frame = server_rx.next() => match frame {
+                Some(Ok(frame)) => {
+                    deadline = Instant::now() + timeout;
+                    match frame {
+                        // Pings are answered by tungstenite as they're read
+                        WsMessage::Ping(_) | WsMessage::Pong(_) | WsMessage::Frame(_) => {}
+                        WsMessage::Close(close) => {
+                            let _ = frames.send(WsMessage::Close(close)).await;
+                            return Ok(());
+                        }
+                        WsMessage::Text(text) if time_exchange::is_time_message(&text) => {
+                            if exchanges.incoming(&text, std::time::Instant::now()) {
+                                let _ = time_replies.send(WsMessage::Text(text));
+                            }
+                        }
+                        frame => {
+                            if frames.send(frame).await.is_err() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+                Some(Err(e)) => return Err(format!("Connection lost: {}", e)),
+                None => return Ok(()),
+            },
+            frame = local_rx.next() => match frame {
+                Some(Ok(WsMessage::Ping(_) | WsMessage::Pong(_) | WsMessage::Frame(_))) => {}
+                Some(Ok(frame)) => {
+                    let closing = matches!(frame, WsMessage::Close(_));
+                    if let WsMessage::Text(text) = &frame {
+                        exchanges.outgoing(text, std::time::Instant::now());
+                    }
+                    // A send only waits when the socket's buffer is full,
+                    // which a server that's gone never drains
+                    match tokio::time::timeout_at(deadline, server_tx.send(frame)).await {
+                        Ok(Ok(())) if !closing => {}
+                        Ok(Ok(())) => return Ok(()),
+                        Ok(Err(e)) => return Err(format!("Connection lost: {}", e)),
+                        Err(_) => return Err(silent()),
+                    }
+                }
+                // sendspin-rs let go of the connection
+                Some(Err(_)) | None => {
+                    let _ = tokio::time::timeout_at(deadline, server_tx.close()).await;
+                    return Ok(());
+                }
+            },
+            _ = ping.tick() => {
+                match tokio::time::timeout_at(deadline, server_tx.send(WsMessage::Ping(Default::default()))).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return Err(format!("Connection lost: {}", e)),
+                    Err(_) => return Err(silent()),
+                }
+            }
+            _ = sleep_until(exchanges.next_request()) => {
+                let Some(request) = exchanges.request(std::time::Instant::now()) else {
+                    continue;
+                };
+                match tokio::time::timeout_at(deadline, server_tx.send(WsMessage::Text(request.into()))).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return Err(format!("Connection lost: {}", e)),
+                    Err(_) => return Err(silent()),
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => return Err(silent()),
+        }
+    }
+}
+
+/// Hand frames from the server to sendspin-rs, time replies first, until it
+/// lets go of the connection or the relay stops
+async fn forward(
+    mut local_tx: SplitSink<WebSocketStream<TcpStream>, WsMessage>,
+    mut time_replies: mpsc::UnboundedReceiver<WsMessage>,
+    mut frames: mpsc::Receiver<WsMessage>,
+) {
+    loop {
+        let frame = tokio::select! {
+            biased;
+            Some(frame) = time_replies.recv() => frame,
+            Some(frame) = frames.recv() => frame,
+            else => return,
+        };
+        if local_tx.send(frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Until `at`, or forever if it's `None`
+async fn sleep_until(at: Option<std::time::Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::{accept_async, connect_async};
+
+    /// A server on loopback and the client's connection to it
+    async fn connect() -> (Socket, WebSocketStream<TcpStream>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (client, server) = tokio::join!(connect_async(url), async {
+            let (stream, _) = listener.accept().await.unwrap();
+            accept_async(stream).await.unwrap()
+        });
+        (client.unwrap().0, server)
+    }
+
+    fn no_exchanges() -> TimeExchanges {
+        TimeExchanges::new(Duration::from_secs(5), 0, 1, Duration::ZERO)
+    }
+
+    #[test]
+    fn relays_frames_both_ways_while_the_server_answers_pings() {
+        tauri::async_runtime::block_on(async {
+            let (client, server) = connect().await;
+            let timeout = Duration::from_millis(300);
+            let (mut local, mut liveness) = relay(client, timeout, no_exchanges()).await.unwrap();
+            let (mut server_tx, mut server_rx) = server.split();
+            // Reading answers the relay's pings
+            let reader = tauri::async_runtime::spawn(async move {
+                let mut texts = Vec::new();
+                while let Some(Ok(frame)) = server_rx.next().await {
+                    if let WsMessage::Text(text) = frame {
+                        texts.push(text.to_string());
+                    }
+                }
+                texts
+            });
+
+            local.send(WsMessage::Text("hello".into())).await.unwrap();
+            // Quiet for longer than the timeout but for the pings
+            tokio::time::sleep(timeout * 2).await;
+            server_tx
+                .send(WsMessage::Binary(vec![4, 2].into()))
+                .await
+                .unwrap();
+
+            let frame = local.next().await.unwrap().unwrap();
+            assert_eq!(frame, WsMessage::Binary(vec![4, 2].into()));
+            let lost = tokio::time::timeout(Duration::from_millis(50), liveness.lost()).await;
+            assert!(lost.is_err(), "a server answering pings was given up on");
+            local.close(None).await.unwrap();
+            assert_eq!(reader.await.unwrap(), vec!["hello".to_string()]);
+        });
+    }
+
+    #[test]
+    fn reports_a_server_that_stops_answering() {
+        tauri::async_runtime::block_on(async {
+            let (client, _server) = connect().await;
+            // Never read, so the relay's pings go unanswered
+            let (_local, mut liveness) = relay(client, Duration::from_millis(300), no_exchanges())
+                .await
+                .unwrap();
+
+            let reason = tokio::time::timeout(Duration::from_secs(2), liveness.lost())
+                .await
+                .expect("a silent server wasn't noticed");
+            assert!(reason.contains("No response"), "{}", reason);
+        });
+    }
+
+    #[test]
+    fn adds_time_requests_after_sendspins_first() {
+        tauri::async_runtime::block_on(async {
+            let (client, mut server) = connect().await;
+            let exchanges = TimeExchanges::new(Duration::from_secs(5), 1, 5, Duration::ZERO);
+            let (mut local, _liveness) = relay(client, Duration::from_secs(5), exchanges)
+                .await
+                .unwrap();
+
+            let request = r#"{"type":"client/time","payload":{"client_transmitted":1000}}"#;
+            local.send(WsMessage::Text(request.into())).await.unwrap();
+            let mut requests = Vec::new();
+            while requests.len() < 2 {
+                let frame = tokio::time::timeout(Duration::from_secs(2), server.next())
+                    .await
+                    .expect("no time request")
+                    .unwrap()
+                    .unwrap();
+                if let WsMessage::Text(text) = frame {
+                    requests.push(text.to_string());
+                }
+            }
+            assert_eq!(requests[0], request);
+            assert!(requests[1].contains("client/time"), "{}", requests[1]);
+        });
+    }
+
+    #[test]
+    fn hands_over_time_replies_ahead_of_queued_audio() {
+        tauri::async_runtime::block_on(async {
+            let (client, server) = connect().await;
+            let (mut local, _liveness) = relay(client, Duration::from_secs(5), no_exchanges())
+                .await
+                .unwrap();
+            let (mut server_tx, _server_rx) = server.split();
+            let chunks = 96;
+            let reply = r#"{"type":"server/time","payload":{"client_transmitted":1,"server_received":2,"server_transmitted":3}}"#;
+            tauri::async_runtime::spawn(async move {
+                for _ in 0..chunks {
+                    let chunk = WsMessage::Binary(vec![0; 256 * 1024].into());
+                    server_tx.send(chunk).await.unwrap();
+                }
+                server_tx.send(WsMessage::Text(reply.into())).await.unwrap();
+            });
+            // Let the audio back up while sendspin-rs is busy
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            let mut position = 0;
+            loop {
+                match local.next().await.unwrap().unwrap() {
+                    WsMessage::Text(text) => {
+                        assert_eq!(text.as_str(), reply);
+                        break;
+                    }
+                    _ => position += 1,
+                }
+            }
+            assert!(position < chunks, "the time reply waited for all the audio");
+        });
+    }
+}
@@ -0,0 +1,314 @@
+//! Mock Sendspin server
+//!
+//! A test-only server implementing enough of the MA proxy auth and the
+//! Sendspin protocol for the client to run against without a Music
+//! Assistant server: it checks the auth message, answers the hello
+//! handshake and time sync, and otherwise sends whatever the test tells it
+//! to through a [`MockConnection`], on a clock of its own.
+//!
+//! The client under test can be started the way the app starts it, with
+//! [`SendspinClient::start`] and a config from [`MockServer::config`].
+
+use super::*;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::accept_async;
+
+/// Binary message types, as in the Sendspin spec
+const AUDIO_CHUNK: u8 = 4;
+const ARTWORK_CHANNEL_0: u8 = 8;
+
+/// How long to wait for the client to connect and complete the handshake,
+/// or to send an expected message
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(super) struct MockServer {
+    listener: TcpListener,
+    url: String,
+    /// Start of the server's clock
+    origin: Instant,
+}
+
+/// One client's connection to the mock server
+pub(super) struct MockConnection {
+    /// The client's `client/hello`
+    pub hello: Value,
+    outgoing: mpsc::UnboundedSender<WsMessage>,
+    received: mpsc::UnboundedReceiver<Value>,
+    origin: Instant,
+}
+
+impl MockServer {
+    /// The only auth token the server accepts
+    pub const TOKEN: &'static str = "mock_token";
+
+    pub async fn start() -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| format!("Failed to start the mock server: {}", e))?;
+        let address = listener.local_addr().map_err(|e| e.to_string())?;
+        Ok(Self {
+            listener,
+            url: format!("ws://{}/sendspin", address),
+            origin: Instant::now(),
+        })
+    }
+
+    /// A config for a player connecting to this server, playing to the
+    /// null output
+    pub fn config(&self, player_id: &str) -> SendspinConfig {
+        SendspinConfig {
+            player_id: player_id.to_string(),
+            player_name: "Mock player".to_string(),
+            server_url: self.url.clone(),
+            audio_device_id: Some(null_output::DEVICE_ID.to_string()),
+            sync_delay_ms: 0,
+            mirror_device_id: None,
+            mirror_delay_ms: 0,
+            auth_token: Self::TOKEN.to_string(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Accept the next client and complete its handshake. A client that
+    /// authenticates first (as [`run_client`] does) must send
+    /// [`Self::TOKEN`]; with any other token it's told so, and this fails.
+    pub async fn accept(&self) -> Result<MockConnection, String> {
+        tokio::time::timeout(TIMEOUT, self.handshake())
+            .await
+            .map_err(|_| "The client didn't complete the handshake".to_string())?
+    }
+
+    async fn handshake(&self) -> Result<MockConnection, String> {
+        let (stream, _) = self.listener.accept().await.map_err(|e| e.to_string())?;
+        let mut ws_stream = accept_async(stream).await.map_err(|e| e.to_string())?;
+
+        let mut first = next_text(&mut ws_stream).await?;
+        if first["type"] == "auth" {
+            if first["token"] != Self::TOKEN {
+                let _ = ws_stream
+                    .send(text(
+                        &json!({ "type": "auth_error", "error": "Invalid token" }),
+                    ))
+                    .await;
+                return Err("The client's token was rejected".to_string());
+            }
+            ws_stream
+                .send(text(&json!({ "type": "auth_ok" })))
+                .await
+                .map_err(|e| e.to_string())?;
+            first = next_text(&mut ws_stream).await?;
+        }
+        if first["type"] != "client/hello" {
+            return Err(format!("Expected client/hello, got {}", first));
+        }
+        ws_stream
+            .send(server_hello())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let (received_tx, received) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(pump(ws_stream, self.origin, outgoing_rx, received_tx));
+        Ok(MockConnection {
+            hello: first,
+            outgoing,
+            received,
+            origin: self.origin,
+        })
+    }
+}
+
+impl MockConnection {
+    /// The server's clock, which audio timestamps are on
+    pub fn now_us(&self) -> i64 {
+        micros_since(self.origin)
+    }
+
+    /// Send a protocol message
+    pub fn send(&self, message: Value) -> Result<(), String> {
+        self.send_frame(text(&message))
+    }
+
+    pub fn stream_start(
+        &self,
+        sample_rate: u32,
+        channels: u16,
+        bit_depth: u16,
+    ) -> Result<(), String> {
+        self.send(json!({
+            "type": "stream/start",
+            "payload": {
+                "player": {
+                    "codec": "pcm",
+                    "sample_rate": sample_rate,
+                    "channels": channels,
+                    "bit_depth": bit_depth,
+                },
+            },
+        }))
+    }
+
+    pub fn stream_end(&self) -> Result<(), String> {
+        self.send(json!({ "type": "stream/end", "payload": {} }))
+    }
+
+    pub fn stream_clear(&self) -> Result<(), String> {
+        self.send(json!({ "type": "stream/clear", "payload": {} }))
+    }
+
+    /// Send a `server/state` with the given metadata
+    pub fn metadata(&self, metadata: Value) -> Result<(), String> {
+        self.send(json!({ "type": "server/state", "payload": { "metadata": metadata } }))
+    }
+
+    pub fn group_update(&self, playback_state: &str) -> Result<(), String> {
+        self.send(json!({
+            "type": "group/update",
+            "payload": { "playback_state": playback_state },
+        }))
+    }
+
+    /// Send audio to play at `timestamp` on the server's clock
+    pub fn audio_chunk(&self, timestamp: i64, data: &[u8]) -> Result<(), String> {
+        self.send_frame(binary(AUDIO_CHUNK, timestamp, data))
+    }
+
+    /// Send an image on artwork channel `channel`
+    pub fn artwork(&self, channel: u8, data: &[u8]) -> Result<(), String> {
+        self.send_frame(binary(ARTWORK_CHANNEL_0 + channel, self.now_us(), data))
+    }
+
+    fn send_frame(&self, frame: WsMessage) -> Result<(), String> {
+        self.outgoing
+            .send(frame)
+            .map_err(|_| "The client disconnected".to_string())
+    }
+
+    /// Wait for the client to send a message of type `kind`, skipping
+    /// others
+    pub async fn expect(&mut self, kind: &str) -> Result<Value, String> {
+        tokio::time::timeout(TIMEOUT, async {
+            while let Some(message) = self.received.recv().await {
+                if message["type"] == kind {
+                    return Ok(message);
+                }
+            }
+            Err(format!("The client disconnected before sending {}", kind))
+        })
+        .await
+        .map_err(|_| format!("The client didn't send {}", kind))?
+    }
+
+    /// Messages the client has sent since last asked, apart from its hello
+    /// and time sync
+    pub fn drain(&mut self) -> Vec<Value> {
+        std::iter::from_fn(|| self.received.try_recv().ok()).collect()
+    }
+}
+
+/// Wait until `condition` holds, for up to [`TIMEOUT`]; whether it did
+pub(super) async fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + TIMEOUT;
+    while !condition() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    true
+}
+
+/// Move frames between the connection and the test, answering time sync on
+/// the way, until either side closes
+async fn pump(
+    ws_stream: WebSocketStream<TcpStream>,
+    origin: Instant,
+    mut outgoing: mpsc::UnboundedReceiver<WsMessage>,
+    received: mpsc::UnboundedSender<Value>,
+) {
+    let (mut tx, mut rx) = ws_stream.split();
+    loop {
+        tokio::select! {
+            frame = rx.next() => match frame {
+                Some(Ok(WsMessage::Text(message))) => {
+                    let received_us = micros_since(origin);
+                    let Ok(message) = serde_json::from_str::<Value>(message.as_str()) else {
+                        log::warn!("[MockServer] The client sent invalid JSON: {}", message);
+                        continue;
+                    };
+                    if message["type"] == "client/time" {
+                        let reply = server_time(&message, received_us, micros_since(origin));
+                        if tx.send(reply).await.is_err() {
+                            break;
+                        }
+                    } else if received.send(message).is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+            frame = outgoing.recv() => match frame {
+                Some(frame) => {
+                    if tx.send(frame).await.is_err() {
+                        break;
+                    }
+                }
+                None => {
+                    let _ = tx.send(WsMessage::Close(None)).await;
+                    break;
+                }
+            },
+        }
+    }
+}
+
+async fn next_text(ws_stream: &mut WebSocketStream<TcpStream>) -> Result<Value, String> {
+    while let Some(frame) = ws_stream.next().await {
+        if let WsMessage::Text(message) = frame.map_err(|e| e.to_string())? {
+            return serde_json::from_str(message.as_str()).map_err(|e| e.to_string());
+        }
+    }
+    Err("The client disconnected during the handshake".to_string())
+}
+
+fn micros_since(origin: Instant) -> i64 {
+    origin.elapsed().as_micros() as i64
+}
+
+fn server_hello() -> WsMessage {
+    text(&json!({
+        "type": "server/hello",
+        "payload": {
+            "server_id": "mock",
+            "name": "Mock server",
+            "version": 1,
+            "active_roles": ["player@v1", "controller@v1", "metadata@v1", "artwork@v1"],
+            "connection_reason": "playback",
+        },
+    }))
+}
+
+fn server_time(client_time: &Value, received_us: i64, transmitted_us: i64) -> WsMessage {
+    text(&json!({
+        "type": "server/time",
+        "payload": {
+            "client_transmitted": client_time["payload"]["client_transmitted"],
+            "server_received": received_us,
+            "server_transmitted": transmitted_us,
+        },
+    }))
+}
+
+fn text(message: &Value) -> WsMessage {
+    WsMessage::Text(message.to_string().into())
+}
+
+fn binary(kind: u8, timestamp: i64, data: &[u8]) -> WsMessage {
+    let mut frame = Vec::with_capacity(9 + data.len());
+    frame.push(kind);
+    frame.extend_from_slice(&timestamp.to_be_bytes());
+    frame.extend_from_slice(data);
+    WsMessage::Binary(frame.into())
+}
@@ -7,30 +7,100 @@
 //! - Controller role for sending commands
 //! - Metadata role for receiving track info
 
+mod adaptive_buffer;
+pub mod audio_process;
+mod bluetooth;
+mod buffer_fill;
+pub mod calibration;
+pub mod capture;
+mod channel_map;
+mod command;
 pub mod devices;
+mod dlna;
+mod downmix;
+pub mod dsp;
+mod error;
+pub mod events;
+pub mod exclusive;
+pub(crate) mod http_stream;
+#[cfg(all(target_os = "linux", feature = "jack"))]
+mod jack_output;
+pub mod levels;
+mod liveness;
+#[cfg(test)]
+mod mock_server;
 mod now_playing_state;
+pub mod null_output;
+mod pause_hold;
+mod pcm;
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+mod pipewire;
+mod player_queue;
+mod rate_switch;
+mod recent_audio;
+pub mod recording;
+#[cfg(test)]
+mod replay;
+mod reported_state;
+mod resampler;
+mod slimproto;
+mod snapcast;
+pub mod stats;
+pub mod test_tone;
+mod time_exchange;
+mod timed_player;
+mod tls;
+pub mod trace;
+pub mod transport;
+mod underrun_fade;
+pub mod visualizer;
 pub mod volume_control;
 
-use crate::now_playing::{self, NowPlaying};
+use crate::ma_api::Loudness;
+use crate::now_playing::{self, NowPlaying, QueueItem};
+use crate::settings::PlayerBackend;
+use adaptive_buffer::AdaptiveBuffer;
+use buffer_fill::BufferFill;
+use channel_map::ChannelMap;
+pub use command::{PlaybackCommand, SEEK_STEP_MS};
+use dsp::Chain as DspChain;
+pub use error::SendspinError;
+use levels::LevelMeter;
 use now_playing_state::NowPlayingState;
+use null_output::NullPlayer;
 use parking_lot::{Mutex, RwLock};
+use pause_hold::PauseHold;
+use recent_audio::RecentAudio;
+use recording::Recorder;
+use reported_state::{Problem, ReportedState};
+use resampler::StreamResampler;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::mpsc as std_mpsc;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
+use underrun_fade::UnderrunFade;
+use visualizer::Visualizer;
 use volume_control::VolumeController;
 
 use futures_util::{SinkExt, StreamExt};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMessage};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tokio_tungstenite::{
+    client_async_tls_with_config, tungstenite::protocol::Message as WsMessage, MaybeTlsStream,
+    WebSocketStream,
+};
 
 use sendspin::audio::decode::{Decoder, PcmDecoder};
 use sendspin::audio::{AudioBuffer, AudioFormat, Codec, SyncedPlayer, SyncedPlayerConfig};
 use sendspin::protocol::messages::{
-    AudioFormatSpec, ClientState, ClientSyncState, Message, PlayerCommandType, PlayerState,
-    PlayerStateCommand, PlayerV1Support, ServerCommand,
+    ArtworkChannel, ArtworkSource, ArtworkV1Support, AudioFormatSpec, ClientState, ClientSyncState,
+    ImageFormat, Message, PlayerCommandType, PlayerState, PlayerStateCommand, PlayerV1Support,
+    ServerCommand,
 };
 use sendspin::sync::ClockSync;
 use sendspin::{Connection, ProtocolClientBuilder, WsSender};
@@ -82,8 +152,12 @@ enum PlayerCommand {
     CreatePlayer(AudioFormat),
     /// Enqueue an audio buffer for playback
     Enqueue(AudioBuffer),
-    /// Clear the playback buffer
+    /// Clear the playback buffer. On a pause the client loop keeps the
+    /// unplayed part and queues it again when playback resumes.
     Clear,
+    /// The stream ended: let the buffered audio play out instead of dropping
+    /// it. Carries the estimated time until the last enqueued sample plays.
+    Drain(Duration),
     /// Shutdown the playback thread
     Shutdown,
     /// Set software volume level (0-100)
@@ -94,16 +168,63 @@ enum PlayerCommand {
     SetMute(bool),
     /// Set the static sync delay in milliseconds.
     SetStaticDelay(u16),
+    /// Set how much later the mirror device plays, in milliseconds.
+    SetMirrorDelay(i32),
+    /// Move playback to another output device, carrying over the audio
+    /// still queued.
+    SwitchOutputDevice(Option<String>),
+}
+
+impl PlayerCommand {
+    /// Where the command goes relative to audio still in the playback
+    /// thread's queue. Settings apply straight away; stream changes wait for
+    /// the audio before them to be handed over, except those that would
+    /// throw it away anyway.
+    fn queue_order(&self) -> player_queue::Order {
+        match self {
+            Self::SetVolume(_)
+            | Self::SetMute(_)
+            | Self::SetStaticDelay(_)
+            | Self::SetMirrorDelay(_) => player_queue::Order::Immediate,
+            Self::CreatePlayer(_)
+            | Self::Enqueue(_)
+            | Self::Drain(_)
+            | Self::SwitchOutputDevice(_) => player_queue::Order::AfterAudio,
+            Self::Clear | Self::Shutdown => player_queue::Order::DiscardAudio,
+        }
+    }
+}
+
+/// The client loop's end of the playback thread's queue (see `player_queue`)
+type PlayerSender = player_queue::Sender<PlayerCommand>;
+
+/// What the playback thread reports about the output device
+enum DeviceEvent {
+    /// The selected device disappeared mid-stream
+    Lost(String),
+    /// Whether another application holds the device, so opening it is
+    /// being retried
+    Busy(bool),
+    /// The device couldn't be opened for the stream
+    OpenFailed,
 }
 
 /// Commands sent to the async client loop for live runtime reconfiguration.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum ClientCommand {
     /// Set the static sync delay in milliseconds.
     SetStaticDelay(u16),
-    /// Set player volume from an app-owned control surface.
-    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
-    SetVolume(u8),
+    /// Set how much later the mirror device plays, in milliseconds.
+    SetMirrorDelay(i32),
+    /// Re-read the DSP settings.
+    ReloadDsp,
+    /// Show the player under a new name.
+    Rename(String),
+    /// Play to another output device, mid-stream if the stream can go on
+    /// as it is, otherwise by reconnecting.
+    SwitchOutputDevice(Option<String>),
+    /// Lower the volume if it's above a new maximum volume.
+    ApplyMaxVolume,
 }
 
 /// Auth message for MA proxy
@@ -127,7 +248,7 @@ struct AuthResponse {
     message: Option<String>,
 }
 
-fn validate_auth_response(text: &str) -> Result<(), String> {
+fn validate_auth_response(text: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let response: AuthResponse = serde_json::from_str(text)
         .map_err(|e| format!("Auth response was not valid JSON: {}", e))?;
 
@@ -143,7 +264,7 @@ fn validate_auth_response(text: &str) -> Result<(), String> {
     }
 
     if let Some(error) = response.error {
-        return Err(format!("Auth rejected: {}", error));
+        return Err(SendspinError::Auth(format!("Auth rejected: {}", error)).into());
     }
     if response.success == Some(false)
         || response.ok == Some(false)
@@ -153,36 +274,73 @@ fn validate_auth_response(text: &str) -> Result<(), String> {
         || response.msg_type.as_deref() == Some("auth_error")
         || response.msg_type.as_deref() == Some("auth/error")
     {
-        return Err(format!(
+        return Err(SendspinError::Auth(format!(
             "Auth rejected{}",
             response
                 .message
                 .as_deref()
                 .map(|message| format!(": {}", message))
                 .unwrap_or_default()
-        ));
+        ))
+        .into());
     }
 
-    Err(format!("Unexpected auth response: {}", text))
+    Err(format!("Unexpected auth response: {}", text).into())
 }
 
-/// Global Sendspin client instance
-static SENDSPIN_CLIENT: RwLock<Option<SendspinClientHandle>> = RwLock::new(None);
-
-/// Whether the Sendspin client is enabled
-pub static SENDSPIN_ENABLED: AtomicBool = AtomicBool::new(false);
-
-/// Shutdown signal
-static SHUTDOWN_TX: RwLock<Option<mpsc::Sender<()>>> = RwLock::new(None);
-
-/// Command channel for sending controller commands
-static COMMAND_TX: RwLock<Option<mpsc::Sender<String>>> = RwLock::new(None);
+/// Owner of the native Sendspin clients.
+///
+/// Runs the main built-in player plus any additional players configured in
+/// settings, each a separate MA player on its own output device.
+///
+/// Registered with `app.manage()`; Tauri commands reach it through
+/// `State<SendspinManager>` and other app code through `app.state()`.
+/// Cloning is cheap and yields another handle to the same clients.
+#[derive(Clone)]
+pub struct SendspinManager {
+    /// The main player, configured from the top-level Sendspin settings
+    primary: SendspinClient,
+    /// Running additional players, in settings order
+    additional: Arc<RwLock<Vec<SendspinClient>>>,
+    /// Serializes reconciling `additional` against settings
+    sync_lock: Arc<tokio::sync::Mutex<()>>,
+}
 
-/// Runtime command channel for live Sendspin client reconfiguration.
-static CLIENT_COMMAND_TX: RwLock<Option<mpsc::Sender<ClientCommand>>> = RwLock::new(None);
+/// One built-in player: a Sendspin connection with its own config, output
+/// device, player id and now-playing state.
+///
+/// Cloning is cheap and yields another handle to the same client.
+#[derive(Clone)]
+pub struct SendspinClient {
+    inner: Arc<ClientInner>,
+}
 
-/// Task handle for the running client
-static CLIENT_TASK: RwLock<Option<tokio::task::JoinHandle<()>>> = RwLock::new(None);
+#[derive(Default)]
+struct ClientInner {
+    /// Whether this is the main player. Only the main player drives the
+    /// system volume, the published volume and the app-wide now-playing
+    /// state; additional players use software volume and persist their
+    /// state in their own settings entry.
+    primary: bool,
+    /// The running client, if any
+    client: RwLock<Option<SendspinClientHandle>>,
+    /// Whether the Sendspin client is enabled
+    enabled: AtomicBool,
+    /// Shutdown signal for the current connection
+    shutdown_tx: RwLock<Option<mpsc::Sender<()>>>,
+    /// Command channel for app playback commands
+    command_tx: RwLock<Option<mpsc::Sender<PlaybackCommand>>>,
+    /// Runtime command channel for live client reconfiguration
+    client_command_tx: RwLock<Option<mpsc::Sender<ClientCommand>>>,
+    /// Task handle for the reconnect loop
+    client_task: RwLock<Option<tokio::task::JoinHandle<()>>>,
+    /// Latest now-playing snapshot for this player
+    now_playing: RwLock<Option<NowPlaying>>,
+    /// Where the latest snapshot's playback is now, if it is extrapolated
+    position: RwLock<Option<now_playing::PositionSource>>,
+    /// Playback and clock-sync statistics
+    stats: stats::StatsRecorder,
+}
 
 /// Sentinel for "the client loop has not reported a volume yet".
 const VOLUME_UNKNOWN: u8 = u8::MAX;
@@ -250,13 +408,18 @@ fn resolve_volume_mode(
 }
 
 /// Client configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SendspinConfig {
     pub player_id: String,
     pub player_name: String,
     pub server_url: String,
     pub audio_device_id: Option<String>,
     pub sync_delay_ms: i32,
+    /// Second output device playing the same stream; main player only
+    pub mirror_device_id: Option<String>,
+    /// How much later the mirror device plays than the main one (ms);
+    /// negative delays the main device instead
+    pub mirror_delay_ms: i32,
     /// Auth token for MA server proxy authentication (required)
     pub auth_token: String,
     /// App version advertised to the server (sourced from the Tauri config, not `Cargo.toml`)
@@ -264,21 +427,30 @@ pub struct SendspinConfig {
 }
 
 /// Connection status
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub enum ConnectionStatus {
     Disconnected,
     Connecting,
     Connected,
     Reconnecting,
-    Error(String),
+    Error(SendspinError),
 }
 
 /// Sendspin client handle
 pub struct SendspinClientHandle {
-    #[allow(dead_code)]
     pub config: SendspinConfig,
     pub status: ConnectionStatus,
     pub player_id: String,
+    /// Why the last connection attempt failed, until the next successful one
+    pub last_error: Option<SendspinError>,
+    /// Seconds until the next reconnect attempt while reconnecting
+    pub retry_in_secs: Option<u64>,
+    /// Device played on instead of `config.audio_device_id` while that one
+    /// isn't connected
+    pub fallback_device_id: Option<String>,
+    /// Whether another application holds the output device, while opening
+    /// it is retried
+    pub device_busy: bool,
 }
 
 impl SendspinClientHandle {
@@ -288,37 +460,370 @@ impl SendspinClientHandle {
             config,
             status: ConnectionStatus::Disconnected,
             player_id,
+            last_error: None,
+            retry_in_secs: None,
+            fallback_device_id: None,
+            device_busy: false,
         }
     }
 }
 
-/// Get the current connection status
-pub fn get_status() -> ConnectionStatus {
-    SENDSPIN_CLIENT
-        .read()
-        .as_ref()
-        .map_or(ConnectionStatus::Disconnected, |c| c.status.clone())
+/// A built-in player as reported to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerInfo {
+    pub player_id: String,
+    pub player_name: String,
+    pub audio_device_id: Option<String>,
+    /// Device played on instead of `audio_device_id` while that one isn't
+    /// connected
+    pub fallback_device_id: Option<String>,
+    /// Whether another application holds the output device, while opening
+    /// it is retried
+    pub device_busy: bool,
+    /// Whether this is the main player rather than an additional one
+    pub primary: bool,
+    pub status: ConnectionStatus,
+    /// Why the last connection attempt failed, until the next successful one
+    pub error: Option<SendspinError>,
+    pub now_playing: Option<NowPlaying>,
 }
 
-/// Get the current player ID (if connected)
-pub fn get_player_id() -> Option<String> {
-    SENDSPIN_CLIENT.read().as_ref().map(|c| c.player_id.clone())
+/// Config for an additional player, sharing the main player's server
+/// connection details.
+fn additional_player_config(
+    base: &SendspinConfig,
+    player: &crate::settings::AdditionalPlayer,
+) -> SendspinConfig {
+    SendspinConfig {
+        player_id: player.player_id.clone(),
+        player_name: player.player_name.clone(),
+        audio_device_id: player.audio_device_id.clone(),
+        sync_delay_ms: player.sync_delay_ms,
+        mirror_device_id: None,
+        mirror_delay_ms: 0,
+        ..base.clone()
+    }
+}
+
+/// Whether two configs differ at most in static delay and name, which a
+/// running client can apply without reconnecting.
+fn same_except_live_settings(a: &SendspinConfig, b: &SendspinConfig) -> bool {
+    SendspinConfig {
+        sync_delay_ms: b.sync_delay_ms,
+        player_name: b.player_name.clone(),
+        ..a.clone()
+    } == *b
 }
 
-/// Check if Sendspin is enabled
-pub fn is_enabled() -> bool {
-    SENDSPIN_ENABLED.load(Ordering::SeqCst)
+impl Default for SendspinManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-/// Set Sendspin enabled state
-pub fn set_enabled(enabled: bool) {
-    SENDSPIN_ENABLED.store(enabled, Ordering::SeqCst);
+impl SendspinManager {
+    pub fn new() -> Self {
+        Self {
+            primary: SendspinClient::new(true),
+            additional: Arc::default(),
+            sync_lock: Arc::default(),
+        }
+    }
+
+    /// Get the main player's connection status
+    pub fn get_status(&self) -> ConnectionStatus {
+        self.primary.get_status()
+    }
+
+    /// Get the main player's ID (if connected)
+    pub fn get_player_id(&self) -> Option<String> {
+        self.primary.get_player_id()
+    }
+
+    /// Check if Sendspin is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.primary.is_enabled()
+    }
+
+    /// Set Sendspin enabled state
+    pub fn set_enabled(&self, enabled: bool) {
+        self.primary.set_enabled(enabled);
+    }
+
+    /// List the running built-in players, main player first
+    pub fn players(&self) -> Vec<PlayerInfo> {
+        std::iter::once(&self.primary)
+            .chain(self.additional.read().iter())
+            .filter_map(SendspinClient::info)
+            .collect()
+    }
+
+    /// Whether any running player is playing
+    pub fn is_playing(&self) -> bool {
+        self.players()
+            .iter()
+            .any(|player| player.now_playing.as_ref().is_some_and(|np| np.is_playing))
+    }
+
+    /// Playback and clock-sync statistics of the running players, main
+    /// player first
+    pub fn player_stats(&self) -> Vec<stats::PlayerStats> {
+        std::iter::once(&self.primary)
+            .chain(self.additional.read().iter())
+            .filter_map(SendspinClient::stats)
+            .collect()
+    }
+
+    /// Find a running player by ID
+    fn player(&self, player_id: &str) -> Option<SendspinClient> {
+        std::iter::once(&self.primary)
+            .chain(self.additional.read().iter())
+            .find(|c| c.get_player_id().as_deref() == Some(player_id))
+            .cloned()
+    }
 }
 
-fn update_status(status: ConnectionStatus) {
-    let mut client = SENDSPIN_CLIENT.write();
-    if let Some(ref mut c) = *client {
-        c.status = status;
+impl SendspinClient {
+    fn new(primary: bool) -> Self {
+        Self {
+            inner: Arc::new(ClientInner {
+                primary,
+                ..ClientInner::default()
+            }),
+        }
+    }
+
+    fn is_primary(&self) -> bool {
+        self.inner.primary
+    }
+
+    /// Get the current connection status
+    pub fn get_status(&self) -> ConnectionStatus {
+        self.inner
+            .client
+            .read()
+            .as_ref()
+            .map_or(ConnectionStatus::Disconnected, |c| c.status.clone())
+    }
+
+    /// Get the current player ID (if connected)
+    pub fn get_player_id(&self) -> Option<String> {
+        self.inner
+            .client
+            .read()
+            .as_ref()
+            .map(|c| c.player_id.clone())
+    }
+
+    /// Check if this client is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Set this client's enabled state
+    pub fn set_enabled(&self, enabled: bool) {
+        self.inner.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    fn config(&self) -> Option<SendspinConfig> {
+        self.inner.client.read().as_ref().map(|c| c.config.clone())
+    }
+
+    fn info(&self) -> Option<PlayerInfo> {
+        let client = self.inner.client.read();
+        let c = client.as_ref()?;
+        Some(PlayerInfo {
+            player_id: c.player_id.clone(),
+            player_name: c.config.player_name.clone(),
+            audio_device_id: c.config.audio_device_id.clone(),
+            fallback_device_id: c.fallback_device_id.clone(),
+            device_busy: c.device_busy,
+            primary: self.inner.primary,
+            status: c.status.clone(),
+            error: c.last_error.clone(),
+            now_playing: self.now_playing(),
+        })
+    }
+
+    fn stats(&self) -> Option<stats::PlayerStats> {
+        let player_id = self.get_player_id()?;
+        Some(self.inner.stats.snapshot(player_id))
+    }
+
+    fn update_status(&self, status: ConnectionStatus) {
+        self.set_status(status, None);
+    }
+
+    /// Record a status change and tell the frontend about it, and webhooks
+    /// when a connection is lost. Repeats of the current status are not
+    /// re-emitted, so a countdown only emits when the remaining seconds
+    /// change.
+    fn set_status(&self, status: ConnectionStatus, retry_in_secs: Option<u64>) {
+        let (event, lost) = {
+            let mut client = self.inner.client.write();
+            let Some(ref mut c) = *client else {
+                return;
+            };
+            match status {
+                ConnectionStatus::Connected => c.last_error = None,
+                ConnectionStatus::Error(ref e) => c.last_error = Some(e.clone()),
+                _ => {}
+            }
+            if c.status == status && c.retry_in_secs == retry_in_secs {
+                return;
+            }
+            let lost = c.status == ConnectionStatus::Connected
+                && matches!(
+                    status,
+                    ConnectionStatus::Reconnecting | ConnectionStatus::Error(_)
+                );
+            c.status = status;
+            c.retry_in_secs = retry_in_secs;
+            (self.status_event(c), lost)
+        };
+        events::emit_status(&event);
+        if lost {
+            crate::webhooks::connection_lost(&event);
+        }
+    }
+
+    fn status_event(&self, c: &SendspinClientHandle) -> events::StatusEvent {
+        events::StatusEvent {
+            player_id: c.player_id.clone(),
+            primary: self.inner.primary,
+            status: c.status.clone(),
+            error: c.last_error.clone(),
+            retry_in_secs: c.retry_in_secs,
+        }
+    }
+
+    /// Store this player's now-playing snapshot and tell the frontend; the
+    /// main player's also becomes the app-wide now-playing state.
+    fn publish_now_playing(&self, np: NowPlaying) {
+        self.publish(np, None);
+    }
+
+    /// Publish `state` like [`publish_now_playing`](Self::publish_now_playing),
+    /// with its position read back from it until the next publish.
+    fn publish_now_playing_state(&self, state: &NowPlayingState) {
+        let live = state.clone();
+        let position: now_playing::PositionSource = Arc::new(move || live.position());
+        self.publish(state.snapshot(), Some(position));
+    }
+
+    fn publish(&self, np: NowPlaying, position: Option<now_playing::PositionSource>) {
+        if self.inner.primary {
+            match position.clone() {
+                Some(position) => {
+                    now_playing::update_now_playing_with_position(np.clone(), position)
+                }
+                None => now_playing::update_now_playing(np.clone()),
+            }
+        }
+        if let Some(player_id) = self.get_player_id() {
+            events::emit_now_playing(&events::NowPlayingEvent {
+                player_id,
+                primary: self.inner.primary,
+                now_playing: np.clone(),
+            });
+        }
+        *self.inner.now_playing.write() = Some(np);
+        *self.inner.position.write() = position;
+    }
+
+    /// This player's latest now-playing snapshot, with the position as of now
+    fn now_playing(&self) -> Option<NowPlaying> {
+        let mut np = self.inner.now_playing.read().clone()?;
+        if let Some(position) = self.inner.position.read().as_ref() {
+            np.elapsed = position();
+        }
+        Some(np)
+    }
+
+    /// Play `config` on the first connected fallback device if its own
+    /// device isn't connected. Only the main player has fallbacks.
+    fn apply_device_fallback(&self, config: &mut SendspinConfig) {
+        if !self.inner.primary {
+            return;
+        }
+        let fallbacks = crate::settings::get_settings().fallback_device_ids;
+        let fallback = devices::fallback_device(config.audio_device_id.as_deref(), &fallbacks);
+        if let Some(ref device_id) = fallback {
+            log::info!(
+                "[Sendspin] Output device {} isn't connected; playing on {}",
+                config.audio_device_id.as_deref().unwrap_or_default(),
+                device_id
+            );
+            config.audio_device_id = Some(device_id.clone());
+        }
+        self.set_fallback_device(fallback);
+    }
+
+    /// Record the device played on in place of the chosen one, and tell the
+    /// frontend when that changes
+    fn set_fallback_device(&self, fallback_device_id: Option<String>) {
+        let event = {
+            let mut client = self.inner.client.write();
+            let Some(ref mut c) = *client else {
+                return;
+            };
+            if c.fallback_device_id == fallback_device_id {
+                return;
+            }
+            c.fallback_device_id.clone_from(&fallback_device_id);
+            events::OutputFallbackEvent {
+                player_id: c.player_id.clone(),
+                primary: self.inner.primary,
+                audio_device_id: c.config.audio_device_id.clone(),
+                fallback_device_id,
+            }
+        };
+        events::emit_output_fallback(&event);
+    }
+
+    /// Record whether another application holds the output device, and
+    /// tell the frontend when that changes
+    fn set_device_busy(&self, busy: bool) {
+        let event = {
+            let mut client = self.inner.client.write();
+            let Some(ref mut c) = *client else {
+                return;
+            };
+            if c.device_busy == busy {
+                return;
+            }
+            c.device_busy = busy;
+            events::DeviceBusyEvent {
+                player_id: c.player_id.clone(),
+                primary: self.inner.primary,
+                audio_device_id: c.config.audio_device_id.clone(),
+                busy,
+            }
+        };
+        events::emit_device_busy(&event);
+    }
+
+    /// `config` with the player's current settings applied, or `None` if an
+    /// additional player has been removed from settings.
+    fn refreshed_config(&self, mut config: SendspinConfig) -> Option<SendspinConfig> {
+        let settings = crate::settings::get_settings();
+        if self.inner.primary {
+            config.audio_device_id = settings.audio_device_id;
+            config.sync_delay_ms = settings.sync_delay_ms;
+            config.mirror_device_id = settings.mirror_device_id;
+            config.mirror_delay_ms = settings.mirror_delay_ms;
+            config.player_name = settings.sendspin_player_name;
+            if let Some(player_id) = settings.sendspin_player_id {
+                config.player_id = player_id;
+            }
+            Some(config)
+        } else {
+            settings
+                .additional_players
+                .iter()
+                .find(|p| p.player_id == config.player_id)
+                .map(|p| additional_player_config(&config, p))
+        }
     }
 }
 
@@ -326,8 +831,55 @@ const PLAYER_BUFFER_CAPACITY: u32 = 16 * 1024 * 1024;
 // Startup/system lead time: enough for codec setup and audio-device/DAC readiness,
 // without adding the larger ongoing network-jitter buffer to initial playback.
 const REQUIRED_LEAD_TIME_MS: u32 = 50;
-// Ongoing playback buffer: intentionally conservative until we have adaptive tuning.
+// Ongoing playback buffer at the start of a connection; grown on network
+// hiccups and shrunk back when stable (see `adaptive_buffer`).
 const MIN_BUFFER_MS: u32 = 500;
+// Bluetooth outputs take longer to start and their latency varies; ask for
+// more lead time and buffer so the server doesn't have to send late chunks.
+const BLUETOOTH_REQUIRED_LEAD_TIME_MS: u32 = 250;
+const BLUETOOTH_MIN_BUFFER_MS: u32 = 1500;
+/// Edge length of the artwork the server is asked to push, matching the
+/// largest variant the app shows
+const PUSHED_ARTWORK_PIXELS: u32 = 512;
+/// Extra time a draining player is kept alive past the estimated end of its
+/// buffer. The estimate can't see how far ahead of playback the server sent
+/// the audio, so err long: an idle player only outputs silence, while
+/// retiring it early would truncate the end of the previous track.
+const DRAIN_MARGIN: Duration = Duration::from_secs(5);
+/// How often the playback thread checks that a selected output device is
+/// still connected while a player is open on it
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// First wait before opening an output device held by another application
+/// again; doubled after each failed attempt up to [`BUSY_RETRY_MAX`]
+const BUSY_RETRY_FIRST: Duration = Duration::from_millis(500);
+const BUSY_RETRY_MAX: Duration = Duration::from_secs(5);
+/// How long without audio before giving up on a busy output device; the
+/// stream has stopped, and the next stream start tries again
+const BUSY_RETRY_IDLE: Duration = Duration::from_secs(10);
+/// Chunks of audio the playback thread's queue holds: many seconds, so it
+/// only fills up when the thread falls behind
+const PLAYER_QUEUE_CAPACITY: usize = 1024;
+/// Commands the playback thread's queue holds next to the audio; sending
+/// one waits for room rather than dropping it
+const PLAYER_CONTROL_CAPACITY: usize = 64;
+/// How long audio waits for room in the playback thread's queue before it
+/// is dropped
+const PLAYER_QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a restarted playback thread has to stay up before the player
+/// counts as recovered
+const PLAYBACK_RECOVERY: Duration = Duration::from_secs(2);
+/// Playback thread restarts allowed within [`PLAYBACK_RESTART_WINDOW`]
+/// before the connection is dropped and retried as a whole
+const PLAYBACK_RESTART_LIMIT: usize = 3;
+const PLAYBACK_RESTART_WINDOW: Duration = Duration::from_secs(60);
+/// Seconds without audio before an open output device is released, unless
+/// set otherwise (see `Settings::idle_release_secs`)
+pub const DEFAULT_IDLE_RELEASE_SECS: u32 = 300;
+/// Time over which playing audio fades out before the buffer is cleared on
+/// pause or stop; an abrupt cut clicks on some DACs
+const FADE_OUT: Duration = Duration::from_millis(200);
+/// Volume steps the fade-out is taken in
+const FADE_OUT_STEPS: u32 = 20;
 
 fn clamp_static_delay_ms(sync_delay_ms: i32) -> u16 {
     sync_delay_ms.clamp(0, 5_000) as u16
@@ -356,21 +908,41 @@ fn build_player_support(
     }
 }
 
+/// Artwork the server pushes over the connection instead of the client
+/// fetching it: one channel of album covers, at the size the OS media
+/// controls show.
+fn build_artwork_support() -> ArtworkV1Support {
+    ArtworkV1Support {
+        channels: vec![ArtworkChannel {
+            source: ArtworkSource::Album,
+            format: ImageFormat::Jpeg,
+            media_width: PUSHED_ARTWORK_PIXELS,
+            media_height: PUSHED_ARTWORK_PIXELS,
+        }],
+    }
+}
+
 fn build_initial_player_state(
     resolved_mode: ResolvedVolumeMode,
     volume: u8,
     muted: bool,
     sync_delay_ms: i32,
+    bluetooth: bool,
 ) -> PlayerState {
     let report_volume = (resolved_mode != ResolvedVolumeMode::None).then_some(volume);
     let report_muted = (resolved_mode != ResolvedVolumeMode::None).then_some(muted);
+    let (required_lead_time_ms, min_buffer_ms) = if bluetooth {
+        (BLUETOOTH_REQUIRED_LEAD_TIME_MS, BLUETOOTH_MIN_BUFFER_MS)
+    } else {
+        (REQUIRED_LEAD_TIME_MS, MIN_BUFFER_MS)
+    };
 
     PlayerState {
         volume: report_volume,
         muted: report_muted,
         static_delay_ms: Some(clamp_static_delay_ms(sync_delay_ms)),
-        required_lead_time_ms: Some(REQUIRED_LEAD_TIME_MS),
-        min_buffer_ms: Some(MIN_BUFFER_MS),
+        required_lead_time_ms: Some(required_lead_time_ms),
+        min_buffer_ms: Some(min_buffer_ms),
         supported_commands: Some(vec![PlayerStateCommand::SetStaticDelay]),
     }
 }
@@ -387,139 +959,312 @@ fn build_protocol_client_builder(
         .manufacturer(Some("Music Assistant".to_string()))
         .software_version(Some(config.app_version.clone()))
         .player_v1_support(player_support)
+        .artwork_v1_support(build_artwork_support())
         .controller()
         .metadata()
         .initial_player_state(initial_player_state)
         .build()
 }
 
-/// Start the Sendspin client
-///
-/// This connects to the Sendspin server and starts audio playback.
-/// The client will run in the background and update `now_playing` state.
-pub async fn start(config: SendspinConfig) -> Result<String, String> {
-    // Stop any existing client
-    stop().await;
-
-    // Create client handle
-    let mut handle = SendspinClientHandle::new(config.clone());
-    handle.status = ConnectionStatus::Connecting;
-
-    let player_id = handle.player_id.clone();
-
-    // Store the handle
-    {
-        let mut client = SENDSPIN_CLIENT.write();
-        *client = Some(handle);
+impl SendspinManager {
+    /// Start the main player, then the additional players configured in
+    /// settings on the same server.
+    pub async fn start(&self, config: SendspinConfig) -> Result<String, SendspinError> {
+        let player_id = self.primary.start(config).await?;
+        self.sync_additional_players().await;
+        Ok(player_id)
     }
 
-    set_enabled(true);
-
-    // Spawn the client task with reconnection loop
-    let config_clone = config.clone();
-    let player_id_clone = player_id.clone();
-    let task_handle = tokio::spawn(async move {
-        const MAX_BACKOFF: Duration = Duration::from_secs(30);
-        let mut backoff = Duration::from_secs(1);
-
-        loop {
-            // Create fresh channels for this connection attempt
-            let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
-            let (command_tx, command_rx) = mpsc::channel::<String>(32);
-            let (client_command_tx, client_command_rx) = mpsc::channel::<ClientCommand>(32);
+    /// Reconnect every player with a new auth token after the server
+    /// rejected the previous one, keeping the rest of the configuration.
+    pub async fn reauthenticate(&self, auth_token: String) -> Result<(), SendspinError> {
+        let config = self
+            .primary
+            .config()
+            .and_then(|config| self.primary.refreshed_config(config))
+            .ok_or(SendspinError::NotConfigured)?;
+        log::info!("[Sendspin] Reconnecting with a new auth token");
+        self.start(SendspinConfig {
+            auth_token,
+            ..config
+        })
+        .await
+        .map(|_| ())
+    }
 
-            // Update globals so stop()/send_command()/runtime reconfiguration reach the current connection
-            {
-                *SHUTDOWN_TX.write() = Some(shutdown_tx);
-            }
-            {
-                *COMMAND_TX.write() = Some(command_tx);
-            }
-            {
-                *CLIENT_COMMAND_TX.write() = Some(client_command_tx);
+    /// Bring the running additional players in line with settings: start
+    /// new ones, stop removed ones and reconnect those whose config changed.
+    ///
+    /// Additional players connect with the main player's server details, so
+    /// none run while the main player is stopped.
+    pub async fn sync_additional_players(&self) {
+        let _sync = self.sync_lock.lock().await;
+
+        let base = self.primary.config().filter(|_| self.primary.is_enabled());
+        let desired: Vec<SendspinConfig> = base
+            .map(|base| {
+                crate::settings::get_settings()
+                    .additional_players
+                    .iter()
+                    .map(|p| additional_player_config(&base, p))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut running = std::mem::take(&mut *self.additional.write());
+        let mut next = Vec::with_capacity(desired.len());
+        for config in desired {
+            let existing = running
+                .iter()
+                .position(|c| c.get_player_id().as_deref() == Some(config.player_id.as_str()))
+                .map(|index| running.swap_remove(index));
+            let client = existing.unwrap_or_else(|| SendspinClient::new(false));
+
+            match client.config() {
+                // Static delay and name are applied live; anything else
+                // needs a reconnect.
+                Some(current) if same_except_live_settings(&current, &config) => {
+                    if current.sync_delay_ms != config.sync_delay_ms {
+                        if let Err(e) = client.set_static_delay(config.sync_delay_ms) {
+                            log::warn!("[Sendspin] {}", e);
+                        }
+                    }
+                    if current.player_name != config.player_name {
+                        if let Err(e) = client.rename(&config.player_name) {
+                            log::warn!("[Sendspin] {}", e);
+                        }
+                    }
+                }
+                _ => {
+                    log::info!(
+                        "[Sendspin] Starting additional player {} ({})",
+                        config.player_name,
+                        config.player_id
+                    );
+                    if let Err(e) = client.start(config).await {
+                        log::error!("[Sendspin] Failed to start additional player: {}", e);
+                    }
+                }
             }
+            next.push(client);
+        }
 
-            let connected_at = Instant::now();
+        for client in running {
+            log::info!(
+                "[Sendspin] Stopping additional player {}",
+                client.get_player_id().unwrap_or_default()
+            );
+            client.stop().await;
+        }
+        *self.additional.write() = next;
+    }
+}
 
-            let mut attempt_config = config_clone.clone();
-            attempt_config.sync_delay_ms = crate::settings::get_settings().sync_delay_ms;
+impl SendspinClient {
+    /// Start the Sendspin client
+    ///
+    /// This connects to the Sendspin server and starts audio playback.
+    /// The client will run in the background and update `now_playing` state.
+    pub async fn start(&self, config: SendspinConfig) -> Result<String, SendspinError> {
+        // Stop any existing client
+        self.stop().await;
+
+        // Create client handle
+        let mut handle = SendspinClientHandle::new(config.clone());
+        handle.status = ConnectionStatus::Connecting;
+
+        let player_id = handle.player_id.clone();
+
+        // Store the handle
+        let event = self.status_event(&handle);
+        {
+            let mut client = self.inner.client.write();
+            *client = Some(handle);
+        }
+        events::emit_status(&event);
+
+        self.set_enabled(true);
+
+        // Spawn the client task with reconnection loop
+        let config_clone = config.clone();
+        let player_id_clone = player_id.clone();
+        let instance = self.clone();
+        let task_handle = tokio::spawn(async move {
+            const MAX_BACKOFF: Duration = Duration::from_secs(30);
+            let mut backoff = Duration::from_secs(1);
+
+            loop {
+                // Create fresh channels for this connection attempt
+                let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
+                let (command_tx, command_rx) = mpsc::channel::<PlaybackCommand>(32);
+                let (client_command_tx, client_command_rx) = mpsc::channel::<ClientCommand>(32);
+
+                // Update the client so stop()/send_command()/runtime reconfiguration reach the current connection
+                {
+                    *instance.inner.shutdown_tx.write() = Some(shutdown_tx);
+                }
+                {
+                    *instance.inner.command_tx.write() = Some(command_tx);
+                }
+                {
+                    *instance.inner.client_command_tx.write() = Some(client_command_tx);
+                }
 
-            let result = run_client(
-                attempt_config,
-                player_id_clone.clone(),
-                shutdown_rx,
-                command_rx,
-                client_command_rx,
-            )
-            .await;
+                let connected_at = Instant::now();
 
-            // If stop() was called, exit cleanly
-            if !is_enabled() {
-                break;
-            }
+                let mut attempt_config = config_clone.clone();
+                if let Some(current) = instance.refreshed_config(config_clone.clone()) {
+                    attempt_config.sync_delay_ms = current.sync_delay_ms;
+                }
+                instance.apply_device_fallback(&mut attempt_config);
+
+                let result = match crate::settings::read(|s| s.player_backend) {
+                    PlayerBackend::Sendspin => {
+                        run_client(
+                            &instance,
+                            attempt_config,
+                            player_id_clone.clone(),
+                            shutdown_rx,
+                            command_rx,
+                            client_command_rx,
+                        )
+                        .await
+                    }
+                    PlayerBackend::Snapcast => {
+                        snapcast::run_client(
+                            &instance,
+                            attempt_config,
+                            player_id_clone.clone(),
+                            shutdown_rx,
+                            command_rx,
+                            client_command_rx,
+                        )
+                        .await
+                    }
+                    PlayerBackend::Slimproto => {
+                        slimproto::run_client(
+                            &instance,
+                            attempt_config,
+                            player_id_clone.clone(),
+                            shutdown_rx,
+                            command_rx,
+                            client_command_rx,
+                        )
+                        .await
+                    }
+                    PlayerBackend::Dlna => {
+                        dlna::run_client(
+                            &instance,
+                            attempt_config,
+                            player_id_clone.clone(),
+                            shutdown_rx,
+                            command_rx,
+                            client_command_rx,
+                        )
+                        .await
+                    }
+                };
 
-            // Reset backoff if the connection was alive for >10 seconds
-            // (meaning it was a real session, not an immediate failure)
-            if connected_at.elapsed() > Duration::from_secs(10) {
-                backoff = Duration::from_secs(1);
-            }
+                // If stop() was called, exit cleanly
+                if !instance.is_enabled() {
+                    break;
+                }
 
-            match result {
-                Ok(()) => {
-                    log::warn!("[Sendspin] Disconnected, reconnecting in {:?}...", backoff);
+                // Reset backoff if the connection was alive for >10 seconds
+                // (meaning it was a real session, not an immediate failure)
+                if connected_at.elapsed() > Duration::from_secs(10) {
+                    backoff = Duration::from_secs(1);
                 }
-                Err(e) => {
-                    log::error!(
-                        "[Sendspin] Client error: {}, reconnecting in {:?}...",
-                        e,
-                        backoff
-                    );
+
+                // Errors the client loops didn't categorize are connection
+                // failures
+                let result = result.map_err(|e| match e.downcast::<SendspinError>() {
+                    Ok(e) => *e,
+                    Err(e) => SendspinError::Connection(e.to_string()),
+                });
+                match result {
+                    Ok(()) => {
+                        log::warn!("[Sendspin] Disconnected, reconnecting in {:?}...", backoff);
+                    }
+                    Err(e @ SendspinError::Auth(_)) => {
+                        log::error!("[Sendspin] {}; waiting for a new token", e);
+                        instance.update_status(ConnectionStatus::Error(e.clone()));
+                        events::emit_auth_required(&events::AuthRequiredEvent {
+                            player_id: player_id_clone.clone(),
+                            primary: instance.is_primary(),
+                            server_url: config_clone.server_url.clone(),
+                            reason: e.to_string(),
+                        });
+                        // Retrying with the same token can't succeed;
+                        // `SendspinManager::reauthenticate` starts over.
+                        break;
+                    }
+                    Err(e) => {
+                        log::error!(
+                            "[Sendspin] Client error: {}, reconnecting in {:?}...",
+                            e,
+                            backoff
+                        );
+                        instance.update_status(ConnectionStatus::Error(e));
+                    }
                 }
-            }
 
-            update_status(ConnectionStatus::Reconnecting);
+                instance.inner.stats.record_reconnect();
 
-            // Sleep in small increments so stop() can interrupt quickly
-            let deadline = Instant::now() + backoff;
-            while Instant::now() < deadline {
-                tokio::time::sleep(Duration::from_millis(250)).await;
-                if !is_enabled() {
+                // Sleep in small increments so stop() can interrupt quickly,
+                // counting down to the next attempt for the UI
+                let deadline = Instant::now() + backoff;
+                while Instant::now() < deadline {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    instance.set_status(
+                        ConnectionStatus::Reconnecting,
+                        Some(remaining.as_secs_f64().ceil() as u64),
+                    );
+                    tokio::time::sleep(Duration::from_millis(250)).await;
+                    if !instance.is_enabled() {
+                        break;
+                    }
+                }
+                if !instance.is_enabled() {
                     break;
                 }
-            }
-            if !is_enabled() {
-                break;
-            }
 
-            // Exponential backoff with jitter. The cap is intentionally soft —
-            // jitter is added after clamping, so actual delay can exceed MAX_BACKOFF
-            // by up to ~25%. This is fine; the jitter exists to spread out reconnects.
-            let jitter = Duration::from_millis(rand_jitter_ms(backoff.as_millis() as u64));
-            backoff = (backoff * 2).min(MAX_BACKOFF) + jitter;
+                // Exponential backoff with jitter. The cap is intentionally soft —
+                // jitter is added after clamping, so actual delay can exceed MAX_BACKOFF
+                // by up to ~25%. This is fine; the jitter exists to spread out reconnects.
+                let jitter = Duration::from_millis(rand_jitter_ms(backoff.as_millis() as u64));
+                backoff = (backoff * 2).min(MAX_BACKOFF) + jitter;
 
-            update_status(ConnectionStatus::Connecting);
+                instance.update_status(ConnectionStatus::Connecting);
+            }
+        });
+
+        // Store the task handle so we can await it on stop
+        {
+            let mut handle = self.inner.client_task.write();
+            *handle = Some(task_handle);
         }
-    });
 
-    // Store the task handle so we can await it on stop
-    {
-        let mut handle = CLIENT_TASK.write();
-        *handle = Some(task_handle);
+        Ok(player_id)
     }
-
-    Ok(player_id)
 }
 
 /// Main client loop
 async fn run_client(
-    config: SendspinConfig,
+    instance: &SendspinClient,
+    mut config: SendspinConfig,
     player_id: String,
     shutdown_rx: mpsc::Receiver<()>,
-    command_rx: mpsc::Receiver<String>,
+    command_rx: mpsc::Receiver<PlaybackCommand>,
     client_command_rx: mpsc::Receiver<ClientCommand>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Initialize hardware volume controller
-    let volume_controller = VolumeController::new();
+    // Initialize hardware volume controller. Only the main player drives the
+    // system volume; additional players always use software gain.
+    let volume_controller = if instance.is_primary() {
+        VolumeController::new()
+    } else {
+        None
+    };
     let has_volume_control = volume_controller
         .as_ref()
         .is_some_and(|vc| vc.is_available());
@@ -575,16 +1320,18 @@ async fn run_client(
     // Resolve output device once per connection and derive supported formats for this device.
     // This avoids negotiating formats that the selected Windows output cannot open.
     let output_device = devices::resolve_output_device(config.audio_device_id.as_deref());
-    let mut supported_formats: Vec<AudioFormatSpec> =
-        devices::derive_supported_pcm_formats(output_device.as_ref())
-            .into_iter()
-            .map(|f| AudioFormatSpec {
-                codec: "pcm".to_string(),
-                channels: f.channels as _,
-                sample_rate: f.sample_rate,
-                bit_depth: f.bit_depth as _,
-            })
-            .collect();
+    let mut supported_formats: Vec<AudioFormatSpec> = devices::derive_supported_pcm_formats(
+        output_device.as_ref(),
+        crate::settings::read(|s| s.downmix_to_stereo),
+    )
+    .into_iter()
+    .map(|f| AudioFormatSpec {
+        codec: "pcm".to_string(),
+        channels: f.channels as _,
+        sample_rate: f.sample_rate,
+        bit_depth: f.bit_depth as _,
+    })
+    .collect();
 
     if supported_formats.is_empty() {
         supported_formats = fallback_supported_formats();
@@ -599,14 +1346,32 @@ async fn run_client(
         );
     }
 
-    let (initial_volume, initial_muted) = initial_volume_state(resolved_mode);
+    let additional_player = (!instance.is_primary()).then_some(player_id.as_str());
+
+    let bluetooth = output_device.as_ref().is_some_and(bluetooth::is_bluetooth);
+    if bluetooth {
+        log::info!("[Sendspin] Output is a Bluetooth device; using a larger buffer");
+        if let Some(delay) =
+            crate::settings::seed_device_delay(additional_player, bluetooth::DEFAULT_SYNC_DELAY_MS)
+        {
+            log::info!(
+                "[Sendspin] Applying default Bluetooth sync delay: {}ms",
+                delay
+            );
+            config.sync_delay_ms = delay;
+        }
+    }
+
+    let (initial_volume, initial_muted) = initial_volume_state(resolved_mode, additional_player);
     let player_support = build_player_support(supported_formats, supported_commands);
     let initial_player_state = build_initial_player_state(
         resolved_mode,
         initial_volume,
         initial_muted,
         config.sync_delay_ms,
+        bluetooth,
     );
+    let base_buffer_ms = initial_player_state.min_buffer_ms.unwrap_or(MIN_BUFFER_MS);
     let protocol_builder =
         build_protocol_client_builder(&config, player_support, initial_player_state);
 
@@ -616,9 +1381,9 @@ async fn run_client(
         config.server_url,
         player_id
     );
-    let (ws_stream, _response) = connect_async(&config.server_url)
-        .await
-        .map_err(|e| format!("WebSocket connection failed: {}", e))?;
+    let keepalive_timeout_secs = crate::settings::read(|s| s.keepalive_timeout_secs);
+    let keepalive_timeout = Duration::from_secs(u64::from(keepalive_timeout_secs));
+    let ws_stream = connect_websocket(&config.server_url, keepalive_timeout).await?;
     log::debug!("[Sendspin] WebSocket connected; authenticating");
 
     let (mut ws_tx, mut ws_rx) = ws_stream.split();
@@ -677,6 +1442,8 @@ async fn run_client(
     let ws_stream = ws_tx
         .reunite(ws_rx)
         .map_err(|_| "Failed to reunite authenticated WebSocket halves")?;
+    let exchanges = time_exchange::TimeExchanges::from_settings(&settings);
+    let (ws_stream, liveness) = liveness::relay(ws_stream, keepalive_timeout, exchanges).await?;
 
     let protocol_client = protocol_builder
         .accept(ws_stream)
@@ -684,7 +1451,7 @@ async fn run_client(
         .map_err(|e| format!("Sendspin protocol handshake failed: {}", e))?;
     let connection = protocol_client.split();
 
-    update_status(ConnectionStatus::Connected);
+    instance.update_status(ConnectionStatus::Connected);
     log::info!("[Sendspin] Connected to server (player {})", player_id);
 
     // The cpal::Device resolved above is intentionally not passed onward.
@@ -696,7 +1463,9 @@ async fn run_client(
     //
     // Run the authenticated WebSocket protocol loop
     run_authenticated_client(
+        instance,
         connection,
+        liveness,
         config,
         player_id,
         shutdown_rx,
@@ -706,17 +1475,44 @@ async fn run_client(
         resolved_mode,
         initial_volume,
         initial_muted,
+        base_buffer_ms,
     )
     .await
 }
 
-fn initial_volume_state(resolved_mode: ResolvedVolumeMode) -> (u8, bool) {
+/// Volume and mute to start a connection with. `additional_player` is the
+/// player ID when this is an additional player, whose state is kept in its
+/// own settings entry.
+fn initial_volume_state(
+    resolved_mode: ResolvedVolumeMode,
+    additional_player: Option<&str>,
+) -> (u8, bool) {
     let saved_settings = crate::settings::get_settings();
+    if let Some(player_id) = additional_player {
+        let saved = saved_settings
+            .additional_players
+            .iter()
+            .find(|p| p.player_id == player_id);
+        return match (resolved_mode, saved) {
+            (ResolvedVolumeMode::None, _) => (100, false),
+            (_, Some(p)) => (capped_volume(p.software_volume), p.muted),
+            (_, None) => (100, false),
+        };
+    }
     match resolved_mode {
         ResolvedVolumeMode::Hardware => {
             let vol_ctrl = VOLUME_CONTROLLER.read();
             if let Some(ref vc) = *vol_ctrl {
-                let vol = vc.get_volume().unwrap_or(100);
+                let os_volume = vc.get_volume().unwrap_or(100);
+                let vol = capped_volume(os_volume);
+                if vol < os_volume {
+                    if let Err(e) = vc.set_volume(vol) {
+                        log::warn!(
+                            "[Sendspin] Failed to lower the volume to the maximum: {}",
+                            e
+                        );
+                    }
+                }
                 // Hardware volume comes from OS; mute state is persisted since it is lost on reconnect.
                 let muted = vc.get_mute().unwrap_or(saved_settings.muted);
                 log::debug!(
@@ -735,7 +1531,10 @@ fn initial_volume_state(resolved_mode: ResolvedVolumeMode) -> (u8, bool) {
                 saved_settings.software_volume,
                 saved_settings.muted
             );
-            (saved_settings.software_volume, saved_settings.muted)
+            (
+                capped_volume(saved_settings.software_volume),
+                saved_settings.muted,
+            )
         }
         ResolvedVolumeMode::None => (100, false),
     }
@@ -744,7 +1543,23 @@ fn initial_volume_state(resolved_mode: ResolvedVolumeMode) -> (u8, bool) {
 /// Persist volume/mute state to settings so it survives reconnects.
 /// Called on every volume/mute change. We get a new connection on every
 /// track change, so without this, volume resets between songs.
-fn save_volume_state(resolved_mode: ResolvedVolumeMode, volume: u8, muted: bool) {
+fn save_volume_state(
+    resolved_mode: ResolvedVolumeMode,
+    additional_player: Option<&str>,
+    volume: u8,
+    muted: bool,
+) {
+    if let Some(player_id) = additional_player {
+        // Additional players never use hardware volume.
+        if resolved_mode != ResolvedVolumeMode::None {
+            let _ = crate::settings::update_additional_player(player_id, |p| {
+                p.software_volume = volume;
+                p.muted = muted;
+            });
+        }
+        return;
+    }
+
     let mut settings = crate::settings::get_settings();
     let mut changed = false;
 
@@ -761,24 +1576,47 @@ fn save_volume_state(resolved_mode: ResolvedVolumeMode, volume: u8, muted: bool)
     }
 
     if changed {
-        let _ = crate::settings::save_settings(&settings);
+        if let Err(e) = crate::settings::save_settings(&settings) {
+            log::warn!("[Sendspin] Failed to save the volume: {}", e);
+        }
     }
 }
 
-fn save_static_delay_state(static_delay_ms: u16) {
-    let mut settings = crate::settings::get_settings();
+fn save_static_delay_state(additional_player: Option<&str>, static_delay_ms: u16) {
     let value = i32::from(static_delay_ms);
+    if let Some(player_id) = additional_player {
+        let _ = crate::settings::update_additional_player(player_id, |p| {
+            p.sync_delay_ms = value;
+        });
+        return;
+    }
+
+    if crate::settings::read(|s| s.sync_delay_ms) != value {
+        let _ = crate::settings::set_int_setting("sync_delay_ms", value);
+    }
+}
 
-    if settings.sync_delay_ms != value {
-        settings.sync_delay_ms = value;
-        let _ = crate::settings::save_settings(&settings);
+/// The `client/state` state for `reported`
+fn client_sync_state(reported: &ReportedState) -> ClientSyncState {
+    if reported.is_error() {
+        ClientSyncState::Error
+    } else {
+        ClientSyncState::Synchronized
     }
 }
 
+/// Build a `ClientState` message reporting only whether the player is in sync.
+fn build_sync_state_msg(state: ClientSyncState) -> Message {
+    Message::ClientState(ClientState {
+        state: Some(state),
+        player: None,
+    })
+}
+
 /// Build a `ClientState` message echoing the current volume/mute state back to the server.
-fn build_volume_state_msg(volume: u8, muted: bool) -> Message {
+fn build_volume_state_msg(state: ClientSyncState, volume: u8, muted: bool) -> Message {
     Message::ClientState(ClientState {
-        state: Some(ClientSyncState::Synchronized),
+        state: Some(state),
         player: Some(PlayerState {
             volume: Some(volume),
             muted: Some(muted),
@@ -788,9 +1626,9 @@ fn build_volume_state_msg(volume: u8, muted: bool) -> Message {
 }
 
 /// Build a `ClientState` message echoing the current static sync delay back to the server.
-fn build_static_delay_state_msg(static_delay_ms: u16) -> Message {
+fn build_static_delay_state_msg(state: ClientSyncState, static_delay_ms: u16) -> Message {
     Message::ClientState(ClientState {
-        state: Some(ClientSyncState::Synchronized),
+        state: Some(state),
         player: Some(PlayerState {
             static_delay_ms: Some(static_delay_ms),
             ..PlayerState::default()
@@ -798,12 +1636,24 @@ fn build_static_delay_state_msg(static_delay_ms: u16) -> Message {
     })
 }
 
-fn send_player_command(
-    player_tx: &std_mpsc::Sender<PlayerCommand>,
+/// Build a `ClientState` message telling the server how much audio to keep buffered.
+fn build_min_buffer_state_msg(state: ClientSyncState, min_buffer_ms: u32) -> Message {
+    Message::ClientState(ClientState {
+        state: Some(state),
+        player: Some(PlayerState {
+            min_buffer_ms: Some(min_buffer_ms),
+            ..PlayerState::default()
+        }),
+    })
+}
+
+async fn send_player_command(
+    player_tx: &mut PlayerSender,
     command: PlayerCommand,
     description: &str,
 ) -> bool {
-    if let Err(e) = player_tx.send(command) {
+    let order = command.queue_order();
+    if let Err(e) = player_tx.send(command, order).await {
         log::warn!(
             "[Sendspin] Failed to send playback command {}: {}",
             description,
@@ -815,9 +1665,66 @@ fn send_player_command(
     }
 }
 
-fn apply_volume(
+/// Look up the loudness of the current track for normalization, unless
+/// it's off or the track was already looked up. The result arrives on
+/// `loudness_tx` tagged with the track's identity.
+fn request_loudness(
+    dsp: &DspChain,
+    player_id: &str,
+    np_state: &NowPlayingState,
+    requested: &mut Option<String>,
+    loudness_tx: &mpsc::Sender<(String, Loudness)>,
+) {
+    let identity = np_state.track_identity();
+    if !dsp.wants_loudness() || requested.as_deref() == Some(identity.as_str()) {
+        return;
+    }
+    *requested = Some(identity.clone());
+    // Blocking HTTP, so keep it off the client loop
+    let player_id = player_id.to_string();
+    let loudness_tx = loudness_tx.clone();
+    thread::spawn(move || match crate::ma_api::current_loudness(&player_id) {
+        Ok(loudness) => {
+            let _ = loudness_tx.blocking_send((identity, loudness));
+        }
+        Err(e) => log::warn!("[Sendspin] Failed to look up track loudness: {}", e),
+    });
+}
+
+/// Look up the track queued after the current one, unless it was already
+/// looked up for this track. The result arrives on `up_next_tx` tagged with
+/// the track's identity.
+fn request_up_next(
+    player_id: &str,
+    np_state: &NowPlayingState,
+    requested: &mut Option<String>,
+    up_next_tx: &mpsc::Sender<(String, Option<QueueItem>)>,
+) {
+    let identity = np_state.track_identity();
+    if requested.as_deref() == Some(identity.as_str()) {
+        return;
+    }
+    *requested = Some(identity.clone());
+    // Blocking HTTP, so keep it off the client loop
+    let player_id = player_id.to_string();
+    let up_next_tx = up_next_tx.clone();
+    thread::spawn(move || match crate::ma_api::next_item(&player_id) {
+        Ok(next) => {
+            let _ = up_next_tx.blocking_send((identity, next));
+        }
+        Err(e) => log::debug!("[Sendspin] Failed to look up the next track: {}", e),
+    });
+}
+
+/// `volume` limited to the maximum volume set in settings. Every volume a
+/// player applies, whoever asked for it, goes through here.
+fn capped_volume(volume: u8) -> u8 {
+    volume.min(crate::settings::read(|s| s.max_volume))
+}
+
+async fn apply_volume(
     resolved_mode: ResolvedVolumeMode,
-    player_tx: &std_mpsc::Sender<PlayerCommand>,
+    player_tx: &mut PlayerSender,
     volume: u8,
     description: &str,
 ) -> bool {
@@ -828,7 +1735,9 @@ fn apply_volume(
                 if let Some(ref vc) = *vol_ctrl {
                     vc.set_volume(volume)
                 } else {
-                    Err("Volume controller not available".to_string())
+                    Err(SendspinError::VolumeControl(
+                        "Volume controller not available".to_string(),
+                    ))
                 }
             };
             if let Err(e) = &volume_result {
@@ -836,11 +1745,14 @@ fn apply_volume(
             }
             volume_result.is_ok()
         }
-        ResolvedVolumeMode::Software => send_player_command(
-            player_tx,
-            PlayerCommand::SetVolume(volume),
-            "set software volume",
-        ),
+        ResolvedVolumeMode::Software => {
+            send_player_command(
+                player_tx,
+                PlayerCommand::SetVolume(volume),
+                "set software volume",
+            )
+            .await
+        }
         ResolvedVolumeMode::None => {
             log::debug!(
                 "[Sendspin] Ignoring volume command ({description}): volume control is disabled"
@@ -850,10 +1762,50 @@ fn apply_volume(
     }
 }
 
-/// Record the client loop's current volume and notify the listener when it
-/// actually changed.
-fn publish_volume(volume: u8) {
-    // Enforce the 0..=100 invariant here so no caller can collide with the
+async fn apply_mute(
+    resolved_mode: ResolvedVolumeMode,
+    player_tx: &mut PlayerSender,
+    muted: bool,
+    description: &str,
+) -> bool {
+    match resolved_mode {
+        ResolvedVolumeMode::Hardware => {
+            let mute_result = {
+                let vol_ctrl = VOLUME_CONTROLLER.read();
+                if let Some(ref vc) = *vol_ctrl {
+                    vc.set_mute(muted)
+                } else {
+                    Err(SendspinError::VolumeControl(
+                        "Volume controller not available".to_string(),
+                    ))
+                }
+            };
+            if let Err(e) = &mute_result {
+                log::warn!("[Sendspin] Failed to set hardware mute ({description}): {e}");
+            }
+            mute_result.is_ok()
+        }
+        ResolvedVolumeMode::Software => {
+            send_player_command(
+                player_tx,
+                PlayerCommand::SetMute(muted),
+                "set software mute",
+            )
+            .await
+        }
+        ResolvedVolumeMode::None => {
+            log::debug!(
+                "[Sendspin] Ignoring mute command ({description}): volume control is disabled"
+            );
+            false
+        }
+    }
+}
+
+/// Record the client loop's current volume and notify the listener when it
+/// actually changed.
+fn publish_volume(volume: u8) {
+    // Enforce the 0..=100 invariant here so no caller can collide with the
     // VOLUME_UNKNOWN sentinel.
     let volume = volume.min(100);
     let previous = CURRENT_VOLUME.swap(volume, Ordering::Relaxed);
@@ -885,40 +1837,154 @@ pub fn set_volume_listener(listener: impl Fn(u8) + Send + Sync + 'static) {
 }
 
 /// Publish an applied volume/mute change locally (atomic + listener +
-/// persisted settings) and report the new state to the server.
+/// persisted settings) and report the new state to the server. Only the
+/// main player's volume is published to the app's control surfaces.
+#[allow(clippy::too_many_arguments)]
 async fn broadcast_volume_state(
     sender: &WsSender,
+    player_id: &str,
+    state: ClientSyncState,
     resolved_mode: ResolvedVolumeMode,
+    additional_player: Option<&str>,
     volume: u8,
     muted: bool,
     what: &str,
 ) {
-    publish_volume(volume);
-    save_volume_state(resolved_mode, volume, muted);
-    let msg = build_volume_state_msg(volume, muted);
+    if additional_player.is_none() {
+        publish_volume(volume);
+    }
+    save_volume_state(resolved_mode, additional_player, volume, muted);
+    send_message(
+        sender,
+        player_id,
+        build_volume_state_msg(state, volume, muted),
+        &format!("{what} state"),
+    )
+    .await;
+}
+
+/// Send `msg` to the server, adding it to the protocol trace
+async fn send_message(sender: &WsSender, player_id: &str, msg: Message, what: &str) {
+    trace::message(player_id, trace::Direction::Out, &msg);
     if let Err(e) = sender.send_message(msg).await {
-        log::warn!("[Sendspin] Failed to send {what} state: {e}");
+        log::warn!("[Sendspin] Failed to send {what}: {e}");
+    }
+}
+
+/// Note whether `problem` is present, and tell the server if that changes
+/// whether the player is in sync
+async fn report_problem(
+    sender: &WsSender,
+    player_id: &str,
+    reported: &mut ReportedState,
+    problem: Problem,
+    present: bool,
+) {
+    if !reported.set(problem, present) {
+        return;
+    }
+    match reported.problem() {
+        Some(problem) => log::warn!(
+            "[Sendspin] Reporting an error state: {}",
+            problem.describe()
+        ),
+        None => log::info!("[Sendspin] Reporting the player synchronized again"),
+    }
+    send_message(
+        sender,
+        player_id,
+        build_sync_state_msg(client_sync_state(reported)),
+        "sync state",
+    )
+    .await;
+}
+
+/// Open the WebSocket, giving up after `timeout` rather than waiting out the
+/// OS's connect timeout or a handshake the server never answers. Once
+/// connected, [`liveness`] notices a server that stops answering.
+///
+/// Nagle's algorithm is off, as small messages like `client/time` would
+/// otherwise wait for the server to acknowledge the previous one, adding a
+/// delayed ACK's worth to the measured round trip. The Sendspin protocol
+/// has no separate time channel, so time replies share the connection with
+/// the audio; the relay in `liveness` passes them on ahead of it.
+async fn connect_websocket(
+    url: &str,
+    timeout: Duration,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Box<dyn std::error::Error + Send + Sync>> {
+    let request = url
+        .into_client_request()
+        .map_err(|e| format!("Invalid server URL: {}", e))?;
+    let host = request
+        .uri()
+        .host()
+        .ok_or("Server URL has no host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = request.uri().port_u16().unwrap_or_else(|| {
+        if request.uri().scheme_str() == Some("wss") {
+            443
+        } else {
+            80
+        }
+    });
+
+    let tcp = tokio::time::timeout(timeout, TcpStream::connect((host.as_str(), port)))
+        .await
+        .map_err(|_| format!("WebSocket connection timed out after {:?}", timeout))?
+        .map_err(|e| format!("WebSocket connection failed: {}", e))?;
+    if let Err(e) = tcp.set_nodelay(true) {
+        log::warn!("[Sendspin] Failed to disable Nagle's algorithm: {}", e);
     }
+
+    // Servers with custom TLS trust get their own connector; the rest use
+    // the platform's TLS stack
+    let connector = crate::settings::server_tls(url)
+        .map(|settings| tls::connector(&settings))
+        .transpose()?;
+    let handshake = client_async_tls_with_config(request, tcp, None, connector);
+    let (ws_stream, _response) = tokio::time::timeout(timeout, handshake)
+        .await
+        .map_err(|_| format!("WebSocket handshake timed out after {:?}", timeout))?
+        .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
+            match e {
+                // A proxy in front of MA may reject the token at the upgrade
+                WsError::Http(ref response) if matches!(response.status().as_u16(), 401 | 403) => {
+                    SendspinError::Auth(format!(
+                        "Server rejected the token ({})",
+                        response.status()
+                    ))
+                    .into()
+                }
+                e => format!("WebSocket connection failed: {}", e).into(),
+            }
+        })?;
+    Ok(ws_stream)
 }
 
 /// Run the Sendspin client on an already-authenticated WebSocket connection
 /// This is used when connecting through the MA proxy which requires auth first
 #[allow(clippy::too_many_arguments)]
 async fn run_authenticated_client(
+    instance: &SendspinClient,
     connection: Connection,
-    config: SendspinConfig,
+    mut liveness: liveness::Liveness,
+    mut config: SendspinConfig,
     player_id: String,
     mut shutdown_rx: mpsc::Receiver<()>,
-    mut command_rx: mpsc::Receiver<String>,
+    mut command_rx: mpsc::Receiver<PlaybackCommand>,
     mut client_command_rx: mpsc::Receiver<ClientCommand>,
     mut volume_change_rx: mpsc::Receiver<(u8, bool)>,
     resolved_mode: ResolvedVolumeMode,
     initial_volume: u8,
     initial_muted: bool,
+    base_buffer_ms: u32,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let Connection {
         mut messages,
         mut audio,
+        mut artwork,
         clock_sync,
         sender,
         controller,
@@ -926,92 +1992,458 @@ async fn run_authenticated_client(
         ..
     } = connection;
 
-    // Create channel for sending commands to the playback thread
-    let (player_tx, player_rx) = std_mpsc::channel::<PlayerCommand>();
+    // The playback thread reports on the output device here
+    let (device_event_tx, mut device_event_rx) = mpsc::channel::<DeviceEvent>(8);
 
     // Spawn playback thread that owns the SyncedPlayer.
     // Pass the configured device id (not a resolved cpal::Device); the
     // thread re-resolves on each player creation so a stale handle from
     // a Bluetooth sleep/reconnect cycle can't permanently break audio.
-    let clock_sync_for_thread = Arc::clone(&clock_sync);
+    instance
+        .inner
+        .stats
+        .set_clock_sync(Some(Arc::clone(&clock_sync)));
     let use_software_volume = resolved_mode == ResolvedVolumeMode::Software;
-    let audio_device_id_for_thread = config.audio_device_id.clone();
-    let initial_static_delay_ms = clamp_static_delay_ms(config.sync_delay_ms);
-    let _playback_handle = thread::spawn(move || {
-        run_playback_thread(
-            player_rx,
-            clock_sync_for_thread,
-            audio_device_id_for_thread,
-            use_software_volume,
-            initial_volume,
-            initial_muted,
-            initial_static_delay_ms,
-        );
-    });
+    // Kept to start the playback thread again with if it dies
+    let mut current_static_delay_ms = clamp_static_delay_ms(config.sync_delay_ms);
+    let mut current_mirror_delay_ms = config.mirror_delay_ms;
+    let (mut player_tx, playback_handle) = spawn_playback_thread(
+        device_event_tx.clone(),
+        &clock_sync,
+        (
+            config.audio_device_id.clone(),
+            config.mirror_device_id.clone(),
+        ),
+        use_software_volume,
+        (initial_volume, initial_muted),
+        (current_static_delay_ms, current_mirror_delay_ms),
+    );
+    let mut playback_handle = Some(playback_handle);
+    let mut playback_restarts = RestartBudget::new();
+    // When the playback thread was last restarted, until it has stayed up
+    // for a while
+    let mut recovering: Option<Instant> = None;
+    // What the connection ends with: an error if playback couldn't be kept
+    // running, for the reconnect loop to report and retry
+    let mut outcome: Result<(), Box<dyn std::error::Error + Send + Sync>> = Ok(());
 
     // Message handling variables
     let mut decoder: Option<PcmDecoder> = None;
     let mut audio_format: Option<AudioFormat> = None;
+    // Format the player was created with; differs from `audio_format` in
+    // sample rate when the stream is resampled for the device, and in
+    // channel count when a surround stream is downmixed.
+    let mut output_format: Option<AudioFormat> = None;
+    let mut stream_resampler: Option<StreamResampler> = None;
+    // Input channel count of a surround stream being folded down to stereo.
+    let mut stream_downmix: Option<u16> = None;
+    // Swap or routing of the stream's channels on the device
+    let mut channel_map: Option<ChannelMap> = None;
+    // Rounds processed or depth-reduced audio back to integer PCM
+    let mut dither = pcm::Dither::new();
+    // Reused from chunk to chunk by the stages that rewrite the audio, so a
+    // stream doesn't allocate for each of those per chunk
+    let mut float_buffer: Vec<f32> = Vec::new();
+    let mut pcm_buffer: Vec<u8> = Vec::new();
+    let mut mapped_buffer: Vec<u8> = Vec::new();
+    // Unplayed audio kept across a pause
+    let mut pause_hold = PauseHold::new();
+    // End of the queued audio, held back to fade out if the buffer runs dry
+    let mut underrun_fade = UnderrunFade::new();
+    // What keeps the player from playing in sync, reported in `client/state`
+    let mut reported_state = ReportedState::default();
+    let mut playout = PlayoutEstimate::default();
+    let mut adaptive_buffer = AdaptiveBuffer::new(base_buffer_ms, Instant::now());
+    // Keeps what the player has queued within the capacity told to the server
+    let mut buffer_fill = BufferFill::new(u64::from(PLAYER_BUFFER_CAPACITY));
+    // Server timestamp the audio queued on the player reaches up to
+    let mut queued_end: Option<i64> = None;
+    instance.inner.stats.set_target_buffer(base_buffer_ms);
 
     // Folds protocol deltas into a coherent now-playing snapshot.
     let mut np_state = NowPlayingState::new(player_id.clone(), config.player_name.clone());
 
+    // Spectrum of the main player's audio, emitted as it plays
+    let mut visualizer = Visualizer::new();
+    let mut visualizer_tick = tokio::time::interval(visualizer::FRAME_INTERVAL);
+    visualizer_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // Per-channel levels of the main player's audio, emitted as it plays
+    let mut level_meter = LevelMeter::new();
+    let mut level_tick = tokio::time::interval(levels::METER_INTERVAL);
+    level_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // Writes the main player's stream to FLAC files while recording is on
+    let mut recorder = Recorder::new();
+
+    // Processing applied to the stream before it reaches the player
+    let mut dsp = DspChain::new(
+        &crate::settings::get_settings(),
+        config.audio_device_id.as_deref(),
+        (!instance.is_primary()).then_some(player_id.as_str()),
+        use_software_volume,
+    );
+    // Loudness lookups report back here with the track they were made for
+    let (loudness_tx, mut loudness_rx) = mpsc::channel::<(String, Loudness)>(4);
+    // Track whose loudness was last looked up
+    let mut loudness_requested: Option<String> = None;
+    // Next-track lookups, the same way
+    let (up_next_tx, mut up_next_rx) = mpsc::channel::<(String, Option<QueueItem>)>(4);
+    let mut up_next_requested: Option<String> = None;
+
     // Volume state — initialized from the same read used for the initial ClientState
     let mut current_volume: u8 = initial_volume;
     let mut current_muted: bool = initial_muted;
-    publish_volume(current_volume);
+    let additional_player = (!instance.is_primary()).then(|| player_id.clone());
+    let additional_player = additional_player.as_deref();
+    if additional_player.is_none() {
+        publish_volume(current_volume);
+    }
 
     loop {
+        // The playback thread is checked each time around rather than on a
+        // timer, which would keep the loop from ending with the connection.
+        // Every chunk of a stream comes through here, so it's noticed
+        // promptly while playing.
+        if let Some(finished) = playback_handle.take_if(|handle| handle.is_finished()) {
+            let reason = playback_exit_reason(finished.join());
+            if !playback_restarts.allow(Instant::now()) {
+                log::error!(
+                    "[Sendspin] Playback thread died again ({}); reconnecting",
+                    reason
+                );
+                let error = SendspinError::Device(format!("Playback keeps stopping: {reason}"));
+                outcome = Err(error.into());
+                break;
+            }
+            log::error!(
+                "[Sendspin] Playback thread died ({}); restarting it",
+                reason
+            );
+            instance.update_status(ConnectionStatus::Error(SendspinError::Device(format!(
+                "Playback stopped unexpectedly: {reason}"
+            ))));
+            let (tx, handle) = spawn_playback_thread(
+                device_event_tx.clone(),
+                &clock_sync,
+                (
+                    config.audio_device_id.clone(),
+                    config.mirror_device_id.clone(),
+                ),
+                use_software_volume,
+                (current_volume, current_muted),
+                (current_static_delay_ms, current_mirror_delay_ms),
+            );
+            player_tx = tx;
+            playback_handle = Some(handle);
+            recovering = Some(Instant::now());
+            // Pick the stream up again; the audio queued on the dead player
+            // is lost
+            if let Some(ref fmt) = output_format {
+                send_player_command(
+                    &mut player_tx,
+                    PlayerCommand::CreatePlayer(fmt.clone()),
+                    "recreate player",
+                )
+                .await;
+            }
+        } else if recovering.is_some_and(|at| at.elapsed() >= PLAYBACK_RECOVERY) {
+            recovering = None;
+            log::info!("[Sendspin] Playback thread recovered");
+            instance.update_status(ConnectionStatus::Connected);
+        }
+
         tokio::select! {
             _ = shutdown_rx.recv() => {
                 break;
             }
+            reason = liveness.lost() => {
+                log::warn!("[Sendspin] {}; reconnecting", reason);
+                outcome = Err(SendspinError::Connection(reason).into());
+                break;
+            }
             Some(cmd) = command_rx.recv() => {
-                let Some(controller) = controller.as_ref() else {
-                    log::warn!("[Sendspin] Cannot send controller command; server did not grant controller role");
-                    continue;
-                };
-                log::debug!("[Sendspin] Sending controller command to server: {}", cmd);
-                let result = match cmd.as_str() {
-                    "play" => controller.play().await,
-                    "pause" => controller.pause().await,
-                    "stop" => controller.stop().await,
-                    "next" => controller.next().await,
-                    "previous" => controller.previous().await,
-                    _ => {
-                        log::warn!("[Sendspin] Unknown controller command from app: {}", cmd);
+                match cmd {
+                    PlaybackCommand::SetVolume(requested) => {
+                        let volume = capped_volume(requested);
+                        log::debug!("[Sendspin] Applying app volume command: {}%", volume);
+                        if apply_volume(resolved_mode, &mut player_tx, volume, "app").await {
+                            current_volume = volume;
+                            broadcast_volume_state(&sender, &player_id, client_sync_state(&reported_state), resolved_mode, additional_player, current_volume, current_muted, "app volume").await;
+                            if volume < requested && additional_player.is_none() {
+                                // Snap the requesting surface back to the maximum
+                                renotify_volume();
+                            }
+                        } else if additional_player.is_none() {
+                            // The set was rejected; snap the requesting
+                            // surface back to the actual value.
+                            renotify_volume();
+                        }
+                    }
+                    PlaybackCommand::SetMute(muted) => {
+                        log::debug!("[Sendspin] Applying app mute command: {}", muted);
+                        if apply_mute(resolved_mode, &mut player_tx, muted, "app").await {
+                            current_muted = muted;
+                            broadcast_volume_state(&sender, &player_id, client_sync_state(&reported_state), resolved_mode, additional_player, current_volume, current_muted, "app mute").await;
+                        }
+                    }
+                    PlaybackCommand::Seek(position_ms) => {
+                        np_state.seek(position_ms);
+                        instance.publish_now_playing_state(&np_state);
+
+                        // The Sendspin controller role has no seek; go through
+                        // the MA API for this player's queue instead. Blocking
+                        // HTTP, so keep it off the client loop.
+                        let player_id = player_id.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = crate::ma_api::seek_active_queue(&player_id, position_ms) {
+                                log::warn!("[Sendspin] Failed to seek to {}ms: {}", position_ms, e);
+                            }
+                        });
+                    }
+                    PlaybackCommand::SeekBy(offset_ms) => {
+                        np_state.seek_by(offset_ms);
+                        instance.publish_now_playing_state(&np_state);
+                        // Relative, so the server resolves it against the
+                        // position it has rather than our extrapolated one
+                        forward_to_server(&player_id, cmd);
+                    }
+                    PlaybackCommand::SetShuffle(enabled) => {
+                        np_state.set_shuffle(enabled);
+                        instance.publish_now_playing_state(&np_state);
+                        // Queue settings, like seek, go through the MA API
+                        forward_to_server(&player_id, cmd);
+                    }
+                    PlaybackCommand::SetRepeat(mode) => {
+                        np_state.set_repeat(mode);
+                        instance.publish_now_playing_state(&np_state);
+                        forward_to_server(&player_id, cmd);
+                    }
+                    PlaybackCommand::Favorite => {
+                        // The metadata names the track, so the right one is
+                        // favorited even if the queue has moved on
+                        let np = np_state.snapshot();
+                        let player_id = player_id.clone();
+                        thread::spawn(move || {
+                            match crate::ma_api::favorite_playing_track(&player_id, np.track.as_deref(), np.artist.as_deref()) {
+                                Ok(()) => log::info!("[Sendspin] Added {} to favorites", now_playing::format_now_playing(&np)),
+                                Err(e) => log::warn!("[Sendspin] Failed to add to favorites: {}", e),
+                            }
+                        });
+                    }
+                    PlaybackCommand::Play => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.play().await);
+                        }
+                    }
+                    PlaybackCommand::Pause => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.pause().await);
+                        }
+                    }
+                    PlaybackCommand::Stop => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.stop().await);
+                        }
+                    }
+                    PlaybackCommand::Next => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.next().await);
+                        }
+                    }
+                    PlaybackCommand::Previous => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.previous().await);
+                        }
+                    }
+                    PlaybackCommand::SetGroupVolume(volume) => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.volume(volume.min(100)).await);
+                        }
+                    }
+                    PlaybackCommand::SetGroupMute(muted) => {
+                        if let Some(controller) = granted_controller(controller.as_ref(), &player_id, &cmd) {
+                            log_controller_result(&cmd, controller.mute(muted).await);
+                        }
+                    }
+                }
+            }
+            _ = visualizer_tick.tick(), if instance.is_primary() && visualizer::is_enabled() => {
+                if let Some(bands) = visualizer.due(Instant::now()) {
+                    events::emit_visualizer(&events::VisualizerEvent {
+                        player_id: player_id.clone(),
+                        bands,
+                    });
+                }
+            }
+            _ = tokio::time::sleep_until(underrun_fade.deadline().unwrap_or_else(Instant::now).into()), if underrun_fade.deadline().is_some() => {
+                // Nothing came to follow the held tail in time: fade out
+                // rather than stop dead, and back in once audio resumes
+                if let Some((timestamp, data)) = underrun_fade.fade_out() {
+                    if let (Some(dec), Some(out_fmt)) = (&decoder, &output_format) {
+                        log::debug!("[Sendspin] Buffer about to run dry; fading out");
+                        enqueue_pcm(&mut player_tx, dec, channel_map.as_ref(), timestamp, data, out_fmt, &mut mapped_buffer).await;
+                        instance.inner.stats.record_underrun_fade();
+                        dsp.fade_in();
+                        report_problem(&sender, &player_id, &mut reported_state, Problem::Underrun, true).await;
+                    }
+                }
+            }
+            _ = level_tick.tick(), if instance.is_primary() && levels::is_enabled() => {
+                if let Some(channels) = level_meter.due(Instant::now()) {
+                    events::emit_output_levels(&events::OutputLevelsEvent {
+                        player_id: player_id.clone(),
+                        channels,
+                    });
+                }
+            }
+            Some(image) = artwork.recv() => {
+                trace::artwork(&player_id, image.data.len());
+                // An empty image means the track has no artwork
+                let key = (!image.data.is_empty()).then(|| {
+                    crate::artwork_cache::pushed_key(&player_id, &np_state.track_identity())
+                });
+                if let Some(ref key) = key {
+                    let (key, data) = (key.clone(), image.data.to_vec());
+                    let stored = tokio::task::spawn_blocking(move || {
+                        crate::artwork_cache::store_pushed(&key, &data)
+                    })
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|stored| stored);
+                    if let Err(e) = stored {
+                        log::warn!("[Sendspin] Failed to cache pushed artwork: {}", e);
                         continue;
                     }
-                };
-                if let Err(e) = result {
-                    log::warn!("[Sendspin] Failed to send controller command {}: {}", cmd, e);
                 }
+                log::debug!("[Sendspin] Server pushed artwork: {}", key.as_deref().unwrap_or("none"));
+                np_state.set_pushed_artwork(key);
+                instance.publish_now_playing_state(&np_state);
             }
+            Some(event) = device_event_rx.recv() => match event {
+                DeviceEvent::Busy(busy) => {
+                    instance.set_device_busy(busy);
+                    report_problem(&sender, &player_id, &mut reported_state, Problem::DeviceBusy, busy).await;
+                }
+                DeviceEvent::OpenFailed => report_problem(&sender, &player_id, &mut reported_state, Problem::DeviceFailed, true).await,
+                DeviceEvent::Lost(audio_device_id) => {
+                    report_problem(&sender, &player_id, &mut reported_state, Problem::DeviceLost, true).await;
+                    events::emit_device_lost(&events::DeviceLostEvent {
+                        player_id: player_id.clone(),
+                        primary: instance.is_primary(),
+                        audio_device_id,
+                    });
+                    match controller.as_ref() {
+                        Some(controller) => {
+                            if let Err(e) = controller.pause().await {
+                                log::warn!("[Sendspin] Failed to pause after losing the output device: {}", e);
+                            }
+                        }
+                        None => log::warn!("[Sendspin] Cannot pause after losing the output device; server did not grant controller role"),
+                    }
+                }
+            },
             Some(cmd) = client_command_rx.recv() => {
                 match cmd {
                     ClientCommand::SetStaticDelay(delay_ms) => {
                         log::debug!("[Sendspin] Applying static delay: {}ms", delay_ms);
-                        if send_player_command(&player_tx, PlayerCommand::SetStaticDelay(delay_ms), "set static delay") {
-                            let msg = build_static_delay_state_msg(delay_ms);
-                            if let Err(e) = sender.send_message(msg).await {
-                                log::warn!("[Sendspin] Failed to send static delay state: {}", e);
+                        current_static_delay_ms = delay_ms;
+                        if send_player_command(&mut player_tx, PlayerCommand::SetStaticDelay(delay_ms), "set static delay").await {
+                            send_message(&sender, &player_id, build_static_delay_state_msg(client_sync_state(&reported_state), delay_ms), "static delay state").await;
+                        }
+                    }
+                    ClientCommand::SetMirrorDelay(delay_ms) => {
+                        log::debug!("[Sendspin] Applying mirror delay: {}ms", delay_ms);
+                        current_mirror_delay_ms = delay_ms;
+                        send_player_command(&mut player_tx, PlayerCommand::SetMirrorDelay(delay_ms), "set mirror delay").await;
+                    }
+                    ClientCommand::ReloadDsp => {
+                        log::debug!("[Sendspin] Reloading DSP settings");
+                        let settings = crate::settings::get_settings();
+                        dsp.configure(&settings);
+                        if let Some(ref out_fmt) = output_format {
+                            // Remap live unless the device would need opening
+                            // with a different channel count
+                            let channels = channel_map.as_ref().map_or(out_fmt.channels, ChannelMap::input_channels);
+                            let profile = crate::settings::output_profile(&settings, config.audio_device_id.as_deref());
+                            let updated = route_channels(config.audio_device_id.as_deref(), &profile, channels);
+                            if updated.as_ref().map_or(channels, ChannelMap::output_channels) == out_fmt.channels {
+                                channel_map = updated;
+                            } else {
+                                log::info!("[Sendspin] New channel routing applies from the next stream");
                             }
                         }
+                        request_loudness(&dsp, &player_id, &np_state, &mut loudness_requested, &loudness_tx);
                     }
-                    ClientCommand::SetVolume(volume) => {
-                        let volume = volume.min(100);
-                        log::debug!("[Sendspin] Applying app volume command: {}%", volume);
-                        if apply_volume(resolved_mode, &player_tx, volume, "app") {
+                    ClientCommand::Rename(name) => {
+                        log::info!("[Sendspin] Renaming player to {}", name);
+                        np_state.set_player_name(name.clone());
+                        instance.publish_now_playing_state(&np_state);
+                        // The name in the hello can't be changed without
+                        // reconnecting; rename the player on the server's
+                        // side instead. Blocking HTTP, so off the client loop.
+                        let player_id = player_id.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = crate::ma_api::rename_player(&player_id, &name) {
+                                log::warn!("[Sendspin] Failed to rename the player on the server: {}", e);
+                            }
+                        });
+                    }
+                    ClientCommand::SwitchOutputDevice(audio_device_id) => {
+                        let settings = crate::settings::get_settings();
+                        let live = match (&audio_format, &output_format) {
+                            (Some(fmt), Some(out_fmt)) => live_device_switch(
+                                (config.audio_device_id.as_deref(), audio_device_id.as_deref()),
+                                &settings,
+                                fmt,
+                                out_fmt,
+                                channel_map.as_ref(),
+                            ),
+                            _ => None,
+                        };
+                        match live {
+                            Some(updated) => {
+                                log::info!("[Sendspin] Moving playback to output device {:?}", audio_device_id);
+                                config.audio_device_id.clone_from(&audio_device_id);
+                                dsp.set_audio_device(audio_device_id.as_deref(), &settings);
+                                channel_map = updated;
+                                send_player_command(&mut player_tx, PlayerCommand::SwitchOutputDevice(audio_device_id), "switch output device").await;
+                            }
+                            None => {
+                                // The formats offered in the hello were for
+                                // the old device; offer the new device's
+                                log::info!("[Sendspin] Reconnecting to play to output device {:?}", audio_device_id);
+                                instance.restart_in_background();
+                            }
+                        }
+                    }
+                    ClientCommand::ApplyMaxVolume => {
+                        let volume = capped_volume(current_volume);
+                        if volume < current_volume && apply_volume(resolved_mode, &mut player_tx, volume, "volume cap").await {
+                            log::info!("[Sendspin] Lowered the volume to the maximum of {}%", volume);
                             current_volume = volume;
-                            broadcast_volume_state(&sender, resolved_mode, current_volume, current_muted, "app volume").await;
-                        } else {
-                            // The set was rejected; snap the requesting
-                            // surface back to the actual value.
-                            renotify_volume();
+                            broadcast_volume_state(&sender, &player_id, client_sync_state(&reported_state), resolved_mode, additional_player, current_volume, current_muted, "volume cap").await;
                         }
                     }
                 }
             }
+            Some((identity, loudness)) = loudness_rx.recv() => {
+                // Ignore lookups the track has since moved on from
+                if identity == np_state.track_identity() {
+                    log::debug!("[Sendspin] Track loudness: {:?}", loudness);
+                    dsp.set_loudness(loudness);
+                }
+            }
+            Some((identity, next)) = up_next_rx.recv() => {
+                if identity == np_state.track_identity() {
+                    // Ready for the notification and media controls when
+                    // the track comes up
+                    if let Some(url) = next.as_ref().and_then(|item| item.image_url.as_deref()) {
+                        crate::artwork_cache::prefetch(url, crate::artwork_cache::ArtworkSize::Notification);
+                        crate::artwork_cache::prefetch(url, crate::artwork_cache::ArtworkSize::MediaControls);
+                    }
+                    np_state.set_next(next);
+                    instance.publish_now_playing_state(&np_state);
+                }
+            }
             Some((volume, muted)) = volume_change_rx.recv() => {
                 // This channel only carries OS-level volume change notifications
                 // from the hardware callback. Guard on mode so a future refactor
@@ -1019,12 +2451,19 @@ async fn run_authenticated_client(
                 // correct volume path.
                 if resolved_mode == ResolvedVolumeMode::Hardware {
                     log::debug!("[Sendspin] OS volume changed: {}%, muted: {}", volume, muted);
+                    let capped = capped_volume(volume);
+                    if capped < volume && apply_volume(resolved_mode, &mut player_tx, capped, "volume cap").await {
+                        // Turned up past the maximum in the OS mixer; the OS
+                        // reports the lowered volume in turn
+                        continue;
+                    }
                     current_volume = volume;
                     current_muted = muted;
-                    broadcast_volume_state(&sender, resolved_mode, current_volume, current_muted, "hardware volume").await;
+                    broadcast_volume_state(&sender, &player_id, client_sync_state(&reported_state), resolved_mode, additional_player, current_volume, current_muted, "hardware volume").await;
                 }
             }
             Some(msg) = messages.recv() => {
+                trace::message(&player_id, trace::Direction::In, &msg);
                 match msg {
                     Message::StreamStart(stream_start) => {
                         let Some(player_config) = stream_start.player else {
@@ -1039,6 +2478,12 @@ async fn run_authenticated_client(
                             player_config.bit_depth
                         );
 
+                        // A new stream opens the device afresh
+                        report_problem(&sender, &player_id, &mut reported_state, Problem::DeviceLost, false).await;
+                        report_problem(&sender, &player_id, &mut reported_state, Problem::DeviceFailed, false).await;
+                        let unsupported = player_config.codec != "pcm" || !matches!(player_config.bit_depth, 16 | 24);
+                        report_problem(&sender, &player_id, &mut reported_state, Problem::UnsupportedFormat, unsupported).await;
+
                         if player_config.codec != "pcm" {
                             log::error!("[Sendspin] Unsupported codec: {}", player_config.codec);
                             continue;
@@ -1060,47 +2505,162 @@ async fn run_authenticated_client(
                             continue;
                         }
 
-                        decoder = Some(PcmDecoder::new(fmt.bit_depth));
-                        audio_format = Some(fmt.clone());
-                        send_player_command(&player_tx, PlayerCommand::CreatePlayer(fmt), "create player");
+                        // The previous stream's held tail plays ahead of this one
+                        if let Some((timestamp, data)) = underrun_fade.flush() {
+                            if let (Some(dec), Some(out_fmt)) = (&decoder, &output_format) {
+                                enqueue_pcm(&mut player_tx, dec, channel_map.as_ref(), timestamp, data, out_fmt, &mut mapped_buffer).await;
+                            }
+                        }
+
+                        let settings = crate::settings::get_settings();
+                        // Going through the device's configs can take a while
+                        let (output_channels, output_rate, output_bit_depth) = {
+                            let (device_id, stream_fmt, downmix) =
+                                (config.audio_device_id.clone(), fmt.clone(), settings.downmix_to_stereo);
+                            tokio::task::spawn_blocking(move || {
+                                output_layout_for_stream(device_id.as_deref(), &stream_fmt, downmix)
+                            })
+                            .await
+                            .unwrap_or((fmt.channels, fmt.sample_rate, fmt.bit_depth))
+                        };
+                        stream_resampler = None;
+                        stream_downmix = None;
+                        let mut player_fmt = fmt.clone();
+                        if output_channels != fmt.channels {
+                            log::info!("[Sendspin] Downmixing {}-channel stream to stereo", fmt.channels);
+                            player_fmt.channels = output_channels;
+                            stream_downmix = Some(fmt.channels);
+                        }
+                        if output_bit_depth != fmt.bit_depth {
+                            log::info!(
+                                "[Sendspin] Output device can't play {}-bit at {}Hz; dithering to {}-bit",
+                                fmt.bit_depth,
+                                output_rate,
+                                output_bit_depth
+                            );
+                            player_fmt.bit_depth = output_bit_depth;
+                        }
+                        if output_rate != fmt.sample_rate {
+                            let quality = settings.resampler_quality;
+                            match StreamResampler::new(fmt.sample_rate, output_rate, player_fmt.channels, fmt.bit_depth, output_bit_depth, quality) {
+                                Ok(r) => {
+                                    log::info!(
+                                        "[Sendspin] Output device can't play {}Hz; resampling to {}Hz ({:?} quality)",
+                                        fmt.sample_rate,
+                                        r.output_rate(),
+                                        quality
+                                    );
+                                    player_fmt.sample_rate = r.output_rate();
+                                    stream_resampler = Some(r);
+                                }
+                                Err(e) => log::warn!("[Sendspin] {e}; playing at the stream rate"),
+                            }
+                        }
+
+                        pause_hold.set_format(player_fmt.sample_rate, player_fmt.channels, player_fmt.bit_depth);
+                        underrun_fade.set_format(player_fmt.sample_rate, player_fmt.channels, player_fmt.bit_depth);
+                        let profile = crate::settings::output_profile(&settings, config.audio_device_id.as_deref());
+                        channel_map = route_channels(config.audio_device_id.as_deref(), &profile, player_fmt.channels);
+                        if let Some(ref map) = channel_map {
+                            log::info!("[Sendspin] Mapping channels onto {} device outputs", map.output_channels());
+                            player_fmt.channels = map.output_channels();
+                        }
+
+                        decoder = Some(PcmDecoder::new(player_fmt.bit_depth));
+                        audio_format = Some(fmt);
+                        output_format = Some(player_fmt.clone());
+                        playout = PlayoutEstimate::default();
+                        adaptive_buffer.reset_arrivals();
+                        instance.inner.stats.record_stream_start();
+                        request_loudness(&dsp, &player_id, &np_state, &mut loudness_requested, &loudness_tx);
+                        send_player_command(&mut player_tx, PlayerCommand::CreatePlayer(player_fmt), "create player").await;
                     }
                     Message::ServerState(state) => {
+                        if let Some(controller) = &state.controller {
+                            if np_state.apply_controller_state(controller) {
+                                instance.publish_now_playing_state(&np_state);
+                            }
+                        }
                         if let Some(md) = state.metadata {
                             log::trace!("[Sendspin] Server metadata update received");
                             np_state.apply_metadata(&md);
-                            now_playing::update_now_playing(np_state.snapshot());
+                            instance.publish_now_playing_state(&np_state);
+                            request_loudness(&dsp, &player_id, &np_state, &mut loudness_requested, &loudness_tx);
+                            request_up_next(&player_id, &np_state, &mut up_next_requested, &up_next_tx);
                         }
                     }
-                    Message::StreamEnd(_) | Message::StreamClear(_) => {
-                        log::debug!("[Sendspin] Server stream end/clear");
-                        send_player_command(&player_tx, PlayerCommand::Clear, "clear player");
+                    Message::StreamEnd(_) => {
+                        // Keep the buffered tail: the next StreamStart (if any)
+                        // is queued behind it so consecutive tracks play gaplessly.
+                        let remaining = playout.remaining(Instant::now());
+                        log::debug!("[Sendspin] Server stream end; draining ~{:?} of buffered audio", remaining);
+                        // The last partial block waiting in the resampler
+                        if let (Some(r), Some(dec), Some(out_fmt)) = (stream_resampler.as_mut(), &decoder, &output_format) {
+                            let now = Instant::now();
+                            let plays_at = |timestamp| clock_sync.lock().server_to_local_instant(timestamp);
+                            for (timestamp, data) in r.flush() {
+                                for (timestamp, data) in pause_hold.admit(timestamp, &data) {
+                                    for (timestamp, data) in underrun_fade.pass(timestamp, &data, plays_at, now).into_iter().flatten() {
+                                        enqueue_pcm(&mut player_tx, dec, channel_map.as_ref(), timestamp, data, out_fmt, &mut mapped_buffer).await;
+                                    }
+                                }
+                            }
+                        }
+                        if let Some((timestamp, data)) = underrun_fade.flush() {
+                            if let (Some(dec), Some(out_fmt)) = (&decoder, &output_format) {
+                                enqueue_pcm(&mut player_tx, dec, channel_map.as_ref(), timestamp, data, out_fmt, &mut mapped_buffer).await;
+                            }
+                        }
+                        playout = PlayoutEstimate::default();
+                        adaptive_buffer.reset_arrivals();
+                        send_player_command(&mut player_tx, PlayerCommand::Drain(remaining), "drain player").await;
+                        report_problem(&sender, &player_id, &mut reported_state, Problem::Underrun, false).await;
+                    }
+                    Message::StreamClear(_) => {
+                        log::debug!("[Sendspin] Server stream clear");
+                        // Keep what hadn't played yet in case this is a pause
+                        {
+                            let clock = clock_sync.lock();
+                            pause_hold.hold(|timestamp| clock.server_to_local_instant(timestamp), Instant::now(), np_state.track_identity());
+                        }
+                        underrun_fade.discard();
+                        playout = PlayoutEstimate::default();
+                        queued_end = None;
+                        adaptive_buffer.reset_arrivals();
+                        instance.inner.stats.set_buffered(Duration::ZERO, 0);
+                        visualizer.reset();
+                        level_meter.reset();
+                        dsp.reset();
+                        if let Some(ref mut r) = stream_resampler {
+                            r.reset();
+                        }
+                        send_player_command(&mut player_tx, PlayerCommand::Clear, "clear player").await;
+                        report_problem(&sender, &player_id, &mut reported_state, Problem::Underrun, false).await;
                     }
                     Message::ServerCommand(ServerCommand { player: Some(player_cmd) }) => {
                         if player_cmd.command == PlayerCommandType::SetStaticDelay {
                             if let Some(static_delay_ms) = player_cmd.static_delay_ms {
                                 let delay_ms = clamp_static_delay_ms(i32::from(static_delay_ms));
                                 log::debug!("[Sendspin] Server static delay command: {}ms", delay_ms);
+                                current_static_delay_ms = delay_ms;
 
-                                if send_player_command(&player_tx, PlayerCommand::SetStaticDelay(delay_ms), "set static delay") {
-                                    save_static_delay_state(delay_ms);
-                                    let msg = build_static_delay_state_msg(delay_ms);
-                                    if let Err(e) = sender.send_message(msg).await {
-                                        log::warn!("[Sendspin] Failed to send static delay state: {}", e);
-                                    }
+                                if send_player_command(&mut player_tx, PlayerCommand::SetStaticDelay(delay_ms), "set static delay").await {
+                                    save_static_delay_state(additional_player, delay_ms);
+                                    send_message(&sender, &player_id, build_static_delay_state_msg(client_sync_state(&reported_state), delay_ms), "static delay state").await;
                                 }
                             }
                         }
 
                         if player_cmd.command == PlayerCommandType::Volume {
                             if let Some(volume) = player_cmd.volume {
-                                let vol = volume.min(100);
+                                let vol = capped_volume(volume);
                                 log::debug!("[Sendspin] Server volume command: {}%", vol);
 
-                                let success = apply_volume(resolved_mode, &player_tx, vol, "server");
+                                let success = apply_volume(resolved_mode, &mut player_tx, vol, "server").await;
 
                                 if success {
                                     current_volume = vol;
-                                    broadcast_volume_state(&sender, resolved_mode, current_volume, current_muted, "server volume").await;
+                                    broadcast_volume_state(&sender, &player_id, client_sync_state(&reported_state), resolved_mode, additional_player, current_volume, current_muted, "server volume").await;
                                 }
                             }
                         }
@@ -1108,72 +2668,164 @@ async fn run_authenticated_client(
                         if player_cmd.command == PlayerCommandType::Mute {
                             if let Some(mute) = player_cmd.mute {
                                 log::debug!("[Sendspin] Server mute command: {}", mute);
-                                let success = match resolved_mode {
-                                    ResolvedVolumeMode::Hardware => {
-                                        let mute_result = {
-                                            let vol_ctrl = VOLUME_CONTROLLER.read();
-                                            if let Some(ref vc) = *vol_ctrl {
-                                                vc.set_mute(mute)
-                                            } else {
-                                                Err("Volume controller not available".to_string())
-                                            }
-                                        };
-                                        if let Err(e) = &mute_result {
-                                            log::warn!("[Sendspin] Failed to set hardware mute: {}", e);
-                                        }
-                                        mute_result.is_ok()
-                                    }
-                                    ResolvedVolumeMode::Software => send_player_command(
-                                        &player_tx,
-                                        PlayerCommand::SetMute(mute),
-                                        "set software mute",
-                                    ),
-                                    ResolvedVolumeMode::None => {
-                                        log::debug!("[Sendspin] Ignoring mute command: volume control is disabled");
-                                        false
-                                    }
-                                };
+                                let success = apply_mute(resolved_mode, &mut player_tx, mute, "server").await;
 
                                 if success {
                                     current_muted = mute;
-                                    broadcast_volume_state(&sender, resolved_mode, current_volume, current_muted, "mute").await;
+                                    broadcast_volume_state(&sender, &player_id, client_sync_state(&reported_state), resolved_mode, additional_player, current_volume, current_muted, "mute").await;
                                 }
                             }
                         }
                     }
                     Message::GroupUpdate(gu) => {
                         np_state.apply_group_update(&gu);
-                        now_playing::update_now_playing(np_state.snapshot());
+                        if !np_state.is_playing() {
+                            pause_hold.mark_paused();
+                            report_problem(&sender, &player_id, &mut reported_state, Problem::Underrun, false).await;
+                        }
+                        instance.publish_now_playing_state(&np_state);
                     }
                     _ => {}
                 }
             }
             Some(chunk) = audio.recv() => {
-                let Some(ref fmt) = audio_format else {
+                trace::audio_chunk(&player_id, chunk.timestamp, chunk.data.len());
+                let stats = &instance.inner.stats;
+                let (Some(fmt), Some(out_fmt), Some(dec)) = (&audio_format, &output_format, &decoder) else {
+                    stats.record_dropped_chunk();
                     continue;
                 };
 
-                let bytes_per_sample = match fmt.bit_depth {
-                    16 => 2,
-                    24 => 3,
-                    _ => continue,
-                } as usize;
+                let Some(bytes_per_sample) = pcm::bytes_per_sample(fmt.bit_depth) else {
+                    stats.record_dropped_chunk();
+                    continue;
+                };
                 let frame_size = bytes_per_sample * fmt.channels as usize;
 
                 if chunk.data.len() % frame_size != 0 {
+                    stats.record_dropped_chunk();
                     continue;
                 }
+                let now = Instant::now();
+                let bytes_per_sec = u64::from(fmt.sample_rate) * frame_size as u64;
+                // By the chunks' timestamps, as the player plays them
+                let buffered = queued_end
+                    .and_then(|end| clock_sync.lock().server_to_local_instant(end))
+                    .map_or(Duration::ZERO, |end| end.saturating_duration_since(now));
+                let admission = buffer_fill.admit(buffered.as_micros() as u64 * bytes_per_sec / 1_000_000, chunk.data.len() as u64);
+                match admission.nearly_full {
+                    Some(true) => {
+                        log::warn!("[Sendspin] Player buffer is nearly full ({}ms queued)", buffered.as_millis());
+                        if let Some(target) = adaptive_buffer.back_off(now) {
+                            stats.set_target_buffer(target);
+                            send_message(&sender, &player_id, build_min_buffer_state_msg(client_sync_state(&reported_state), target), "buffer target").await;
+                        }
+                    }
+                    Some(false) => log::info!("[Sendspin] Player buffer has room again"),
+                    None => {}
+                }
+                if !admission.queue {
+                    stats.record_dropped_chunk();
+                    continue;
+                }
+                queued_end = Some(chunk.timestamp + pcm::frames_to_micros(chunk.data.len() / frame_size, fmt.sample_rate));
+                let ran_dry = playout.is_started() && playout.remaining(now).is_zero();
+                let plays_at = now + playout.remaining(now);
+                playout.record_chunk(now, (chunk.data.len() / frame_size) as u64, fmt.sample_rate);
+                let underrun = reported_state.underrun(ran_dry, playout.remaining(now));
+                report_problem(&sender, &player_id, &mut reported_state, Problem::Underrun, underrun).await;
+                let unsynced = !clock_sync.lock().is_synchronized();
+                report_problem(&sender, &player_id, &mut reported_state, Problem::ClockUnsynced, unsynced).await;
+                stats.record_chunk(
+                    chunk.data.len(),
+                    playout.remaining(now),
+                    bytes_per_sec,
+                    ran_dry,
+                );
+                if let Some(target) = adaptive_buffer.on_chunk(now, ran_dry) {
+                    log::info!("[Sendspin] Adjusting buffer target to {}ms", target);
+                    stats.set_target_buffer(target);
+                    send_message(&sender, &player_id, build_min_buffer_state_msg(client_sync_state(&reported_state), target), "buffer target").await;
+                }
+                if instance.is_primary() {
+                    // As received, before any processing
+                    recorder.push(&chunk.data, fmt.sample_rate, fmt.channels, fmt.bit_depth, &np_state);
+                }
 
-                if let Some(ref dec) = decoder {
-                    if let Ok(samples) = dec.decode(&chunk.data) {
-                        let buffer = AudioBuffer {
-                            timestamp: chunk.timestamp,
-                            samples,
-                            format: fmt.clone(),
-                        };
-                        send_player_command(&player_tx, PlayerCommand::Enqueue(buffer), "enqueue audio");
+                let downmixed;
+                let data: &[u8] = if let Some(channels) = stream_downmix {
+                    downmixed = downmix::to_stereo_pcm(&chunk.data, channels, fmt.bit_depth);
+                    &downmixed
+                } else {
+                    &chunk.data
+                };
+                if pause_hold.is_holding() && !pause_hold.resumes(&np_state.track_identity()) {
+                    // Not picking up from held audio; whatever plays next
+                    // starts mid-track
+                    dsp.fade_in();
+                }
+                if ran_dry {
+                    // Back after a gap, however the audio before it ended
+                    dsp.fade_in();
+                }
+                let visualizing = instance.is_primary() && visualizer::is_enabled();
+                let metering = instance.is_primary() && levels::is_enabled();
+                let processing = dsp.is_active();
+                // Channels of the stream before any mapping onto the device
+                let channels = channel_map.as_ref().map_or(out_fmt.channels, ChannelMap::input_channels);
+                // The resampler converts to the output depth itself
+                let quantize_depth = if stream_resampler.is_some() {
+                    fmt.bit_depth
+                } else {
+                    out_fmt.bit_depth
+                };
+                let requantizing = quantize_depth != fmt.bit_depth;
+                let mut timestamp = chunk.timestamp;
+                let data: &[u8] = if processing || requantizing || visualizing || metering {
+                    pcm::to_f32_into(data, fmt.bit_depth, &mut float_buffer);
+                    let samples = &mut float_buffer;
+                    if processing {
+                        // The limiter holds audio back; shift the timestamp
+                        // so the processed audio stays in sync
+                        let lead = dsp.process(samples, channels, fmt.sample_rate);
+                        timestamp -= pcm::frames_to_micros(lead, fmt.sample_rate);
+                    }
+                    if visualizing {
+                        visualizer.push(samples, channels, fmt.sample_rate, plays_at);
+                    }
+                    if metering {
+                        level_meter.push(samples, channels, plays_at);
+                    }
+                    if processing || requantizing {
+                        dither.quantize_into(samples, usize::from(channels), quantize_depth, &mut pcm_buffer);
+                        &pcm_buffer
+                    } else {
+                        data
+                    }
+                } else {
+                    data
+                };
+
+                // Borrowed all the way to the decoder unless resampled
+                let resampled = stream_resampler.as_mut().map(|r| r.process(timestamp, data));
+                let output = resampled
+                    .iter()
+                    .flatten()
+                    .map(|(timestamp, data)| (*timestamp, data.as_slice()))
+                    .chain(resampled.is_none().then_some((timestamp, data)));
+                let plays_at = |timestamp| clock_sync.lock().server_to_local_instant(timestamp);
+                let mut queued = true;
+                for (timestamp, data) in output {
+                    for (timestamp, data) in pause_hold.admit(timestamp, data) {
+                        for (timestamp, data) in underrun_fade.pass(timestamp, &data, plays_at, now).into_iter().flatten() {
+                            queued &= enqueue_pcm(&mut player_tx, dec, channel_map.as_ref(), timestamp, data, out_fmt, &mut mapped_buffer).await;
+                        }
                     }
                 }
+                stats.record_decode(now.elapsed());
+                if !queued {
+                    stats.record_dropped_chunk();
+                }
             }
             else => {
                 break;
@@ -1182,9 +2834,12 @@ async fn run_authenticated_client(
     }
 
     // Shutdown playback thread
-    send_player_command(&player_tx, PlayerCommand::Shutdown, "shutdown player");
+    send_player_command(&mut player_tx, PlayerCommand::Shutdown, "shutdown player").await;
+    instance.set_device_busy(false);
+    instance.inner.stats.set_clock_sync(None);
+    instance.inner.stats.set_buffered(Duration::ZERO, 0);
 
-    update_status(ConnectionStatus::Disconnected);
+    instance.update_status(ConnectionStatus::Disconnected);
 
     let np = NowPlaying {
         is_playing: false,
@@ -1196,14 +2851,431 @@ async fn run_authenticated_client(
         player_id: None,
         duration: None,
         elapsed: None,
+        next: None,
+        shuffle: None,
+        repeat: None,
+        group_name: None,
+        group_volume: None,
+        group_muted: None,
         can_play: false,
         can_pause: false,
         can_next: false,
         can_previous: false,
     };
-    now_playing::update_now_playing(np);
+    instance.publish_now_playing(np);
+
+    outcome
+}
+
+/// Start a connection's playback thread (see [`run_playback_thread`]),
+/// returning the sender for its commands
+fn spawn_playback_thread(
+    device_event_tx: mpsc::Sender<DeviceEvent>,
+    clock_sync: &Arc<Mutex<ClockSync>>,
+    (audio_device_id, mirror_device_id): (Option<String>, Option<String>),
+    use_software_volume: bool,
+    (volume, muted): (u8, bool),
+    (static_delay_ms, mirror_delay_ms): (u16, i32),
+) -> (PlayerSender, thread::JoinHandle<()>) {
+    let (player_tx, player_rx) =
+        player_queue::channel(PLAYER_QUEUE_CAPACITY, PLAYER_CONTROL_CAPACITY);
+    let clock_sync = Arc::clone(clock_sync);
+    let handle = thread::spawn(move || {
+        run_playback_thread(
+            player_rx,
+            device_event_tx,
+            clock_sync,
+            audio_device_id,
+            mirror_device_id,
+            use_software_volume,
+            volume,
+            muted,
+            static_delay_ms,
+            mirror_delay_ms,
+        );
+    });
+    (player_tx, handle)
+}
+
+/// Why a playback thread that wasn't told to stop has finished
+fn playback_exit_reason(joined: thread::Result<()>) -> String {
+    match joined {
+        Ok(()) => "exited".to_string(),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| (*s).to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown cause".to_string());
+            format!("panicked: {message}")
+        }
+    }
+}
 
-    Ok(())
+/// Decode a chunk of PCM and queue it on the playback thread. Returns
+/// whether it was queued. `mapped` is reused for the channel-mapped audio.
+///
+/// While the queue is backed up this waits for room, which also stops the
+/// client loop reading from the server until the playback thread catches
+/// up. The chunk is dropped if none comes up in [`PLAYER_QUEUE_TIMEOUT`],
+/// and later chunks are dropped without waiting until the thread takes
+/// audio again.
+async fn enqueue_pcm(
+    player_tx: &mut PlayerSender,
+    decoder: &PcmDecoder,
+    channel_map: Option<&ChannelMap>,
+    timestamp: i64,
+    data: &[u8],
+    format: &AudioFormat,
+    mapped: &mut Vec<u8>,
+) -> bool {
+    let data = match (channel_map, pcm::bytes_per_sample(format.bit_depth)) {
+        (Some(map), Some(bytes_per_sample)) => {
+            map.apply(data, bytes_per_sample, mapped);
+            mapped.as_slice()
+        }
+        _ => data,
+    };
+    let Ok(samples) = decoder.decode(data) else {
+        return false;
+    };
+    let buffer = AudioBuffer {
+        timestamp,
+        samples,
+        format: format.clone(),
+    };
+    match player_tx
+        .send_audio(PlayerCommand::Enqueue(buffer), PLAYER_QUEUE_TIMEOUT)
+        .await
+    {
+        Ok(()) => true,
+        Err(std_mpsc::TrySendError::Full(_)) => {
+            log::warn!("[Sendspin] Playback thread isn't keeping up; dropping audio");
+            false
+        }
+        Err(std_mpsc::TrySendError::Disconnected(_)) => {
+            log::warn!(
+                "[Sendspin] Failed to send playback command enqueue audio: playback thread is gone"
+            );
+            false
+        }
+    }
+}
+
+/// The channel mapping `profile` asks for on a `channels`-channel stream,
+/// widened to a channel count the device can be opened with. Routing to
+/// outputs the device doesn't have is dropped.
+fn route_channels(
+    audio_device_id: Option<&str>,
+    profile: &crate::settings::OutputProfile,
+    channels: u16,
+) -> Option<ChannelMap> {
+    let mut map = ChannelMap::for_profile(profile, channels)?;
+    if map.output_channels() == channels {
+        return Some(map);
+    }
+    let Some(device) = devices::resolve_output_device(audio_device_id) else {
+        return Some(map);
+    };
+    match devices::output_channels_at_least(&device, map.output_channels()) {
+        Some(available) => {
+            map.widen(available);
+            Some(map)
+        }
+        None => {
+            log::warn!(
+                "[Sendspin] Output device has no config with {} channels; ignoring channel routing",
+                map.output_channels()
+            );
+            // Still honour a swap
+            ChannelMap::for_profile(
+                &crate::settings::OutputProfile {
+                    stereo_channels: None,
+                    ..profile.clone()
+                },
+                channels,
+            )
+        }
+    }
+}
+
+/// Channel count, rate and bit depth to open the output device with for a
+/// stream.
+///
+/// Surround streams are folded down to stereo when downmixing is enabled or
+/// the device has no config with that many channels. The rate is the
+/// stream's own when the device supports it, otherwise the device's native
+/// rate. 24-bit streams drop to 16 bits when the device can't take 24 at
+/// that layout and rate.
+fn output_layout_for_stream(
+    audio_device_id: Option<&str>,
+    fmt: &AudioFormat,
+    downmix_to_stereo: bool,
+) -> (u16, u32, u16) {
+    let Some(device) = devices::resolve_output_device(audio_device_id) else {
+        return (fmt.channels, fmt.sample_rate, fmt.bit_depth);
+    };
+    let channels = if fmt.channels > 2
+        && (downmix_to_stereo || !devices::supports_channel_count(&device, fmt.channels))
+    {
+        2
+    } else {
+        fmt.channels
+    };
+    let (ranges, native_rate) = devices::output_rate_capabilities(&device, channels);
+    let rate = resampler::target_rate(fmt.sample_rate, &ranges, native_rate);
+    let bit_depth = if fmt.bit_depth > 16 && !devices::supports_24bit(&device, channels, rate) {
+        16
+    } else {
+        fmt.bit_depth
+    };
+    (channels, rate, bit_depth)
+}
+
+/// The channel mapping to carry on playing a stream with on device `to`
+/// instead of `from`, without reopening it. `None` if the new device needs
+/// the stream processed or buffered differently: a different output layout,
+/// or a move to or from Bluetooth, which gets a larger buffer.
+fn live_device_switch(
+    (from, to): (Option<&str>, Option<&str>),
+    settings: &crate::settings::Settings,
+    fmt: &AudioFormat,
+    out_fmt: &AudioFormat,
+    channel_map: Option<&ChannelMap>,
+) -> Option<Option<ChannelMap>> {
+    let is_bluetooth = |id: Option<&str>| {
+        devices::resolve_output_device(id)
+            .as_ref()
+            .is_some_and(bluetooth::is_bluetooth)
+    };
+    if is_bluetooth(from) != is_bluetooth(to) {
+        return None;
+    }
+    let channels = channel_map.map_or(out_fmt.channels, ChannelMap::input_channels);
+    let layout = output_layout_for_stream(to, fmt, settings.downmix_to_stereo);
+    if layout != (channels, out_fmt.sample_rate, out_fmt.bit_depth) {
+        return None;
+    }
+    let profile = crate::settings::output_profile(settings, to);
+    let updated = route_channels(to, &profile, channels);
+    let output_channels = updated
+        .as_ref()
+        .map_or(channels, ChannelMap::output_channels);
+    (output_channels == out_fmt.channels).then_some(updated)
+}
+
+/// Address of a backend's server: `configured` (`host`, `host:port`,
+/// `[v6]` or `[v6]:port`) if set, otherwise the MA server's host on
+/// `default_port`.
+fn backend_address(
+    configured: Option<&str>,
+    server_url: &str,
+    default_port: u16,
+) -> Result<(String, u16), String> {
+    if let Some(address) = configured.map(str::trim).filter(|a| !a.is_empty()) {
+        // A bare IPv6 address has too many colons to carry a port
+        let (host, port) = match address.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']').ok_or("Invalid server address")?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match address.split_once(':') {
+                Some((host, port)) if !port.contains(':') => (host, Some(port)),
+                _ => (address, None),
+            },
+        };
+        let port = port
+            .map(|port| port.parse().map_err(|_| format!("Invalid port: {}", port)))
+            .transpose()?
+            .unwrap_or(default_port);
+        return Ok((host.to_string(), port));
+    }
+    let request = server_url
+        .into_client_request()
+        .map_err(|e| format!("Invalid server URL: {}", e))?;
+    let host = request
+        .uri()
+        .host()
+        .ok_or("Server URL has no host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    Ok((host, default_port))
+}
+
+/// Now-playing state for backends that only know whether audio is playing;
+/// the rest comes from the MA API.
+fn backend_now_playing(config: &SendspinConfig, player_id: &str, is_playing: bool) -> NowPlaying {
+    NowPlaying {
+        is_playing,
+        player_name: Some(config.player_name.clone()),
+        player_id: Some(player_id.to_string()),
+        can_play: !is_playing,
+        can_pause: is_playing,
+        can_next: true,
+        can_previous: true,
+        ..NowPlaying::default()
+    }
+}
+
+/// The controller to send `command` through, if the server granted the
+/// controller role
+fn granted_controller<'a, C>(
+    controller: Option<&'a C>,
+    player_id: &str,
+    command: &PlaybackCommand,
+) -> Option<&'a C> {
+    let Some(controller) = controller else {
+        log::warn!(
+            "[Sendspin] Cannot send controller command; server did not grant controller role"
+        );
+        return None;
+    };
+    log::debug!(
+        "[Sendspin] Sending controller command to server: {:?}",
+        command
+    );
+    trace::controller_command(player_id, command);
+    Some(controller)
+}
+
+/// Log a controller command the server connection failed to send
+fn log_controller_result<T, E: std::fmt::Display>(command: &PlaybackCommand, result: Result<T, E>) {
+    if let Err(e) = result {
+        log::warn!(
+            "[Sendspin] Failed to send controller command {:?}: {}",
+            command,
+            e
+        );
+    }
+}
+
+/// Carry out `command` for `player_id` through the MA API, for backends
+/// whose protocol can't send it. Runs on its own thread, as the API calls
+/// block.
+fn forward_to_server(player_id: &str, command: PlaybackCommand) {
+    let player_id = player_id.to_string();
+    thread::spawn(move || {
+        let result = match command {
+            PlaybackCommand::Play => crate::ma_api::player_command(&player_id, "play"),
+            PlaybackCommand::Pause => crate::ma_api::player_command(&player_id, "pause"),
+            PlaybackCommand::Stop => crate::ma_api::player_command(&player_id, "stop"),
+            PlaybackCommand::Next => crate::ma_api::player_command(&player_id, "next"),
+            PlaybackCommand::Previous => crate::ma_api::player_command(&player_id, "previous"),
+            PlaybackCommand::Seek(position_ms) => {
+                crate::ma_api::seek_active_queue(&player_id, position_ms)
+            }
+            PlaybackCommand::SeekBy(offset_ms) => {
+                crate::ma_api::skip_active_queue(&player_id, offset_ms)
+            }
+            PlaybackCommand::SetVolume(volume) => {
+                crate::ma_api::set_player_volume(&player_id, volume.min(100))
+            }
+            PlaybackCommand::SetMute(muted) => crate::ma_api::set_player_mute(&player_id, muted),
+            PlaybackCommand::SetShuffle(enabled) => crate::ma_api::set_shuffle(&player_id, enabled),
+            PlaybackCommand::SetRepeat(mode) => crate::ma_api::set_repeat(&player_id, mode),
+            PlaybackCommand::Favorite => {
+                crate::ma_api::favorite_playing_track(&player_id, None, None)
+            }
+            PlaybackCommand::SetGroupVolume(volume) => {
+                crate::ma_api::set_group_volume(&player_id, volume.min(100))
+            }
+            PlaybackCommand::SetGroupMute(muted) => {
+                crate::ma_api::set_group_mute(&player_id, muted)
+            }
+        };
+        if let Err(e) = result {
+            log::warn!(
+                "[Sendspin] Failed to send {:?} to the server: {}",
+                command,
+                e
+            );
+        }
+    });
+}
+
+/// The player's volume stepping down to silence over [`FADE_OUT`] before
+/// it is cleared. The playback thread sets each step as it comes due, and
+/// keeps taking commands meanwhile.
+struct FadeOut {
+    started: Instant,
+    volume: u8,
+}
+
+impl FadeOut {
+    fn new(volume: u8, now: Instant) -> Self {
+        Self {
+            started: now,
+            volume,
+        }
+    }
+
+    /// Steps passed by `now`
+    fn steps_at(&self, now: Instant) -> u32 {
+        let step = FADE_OUT / FADE_OUT_STEPS;
+        (now.saturating_duration_since(self.started).as_nanos() / step.as_nanos()) as u32
+    }
+
+    /// The volume due at `now`, or `None` once the fade is over
+    fn volume_at(&self, now: Instant) -> Option<u8> {
+        let step = self.steps_at(now) + 1;
+        (step <= FADE_OUT_STEPS)
+            .then(|| (u32::from(self.volume) * (FADE_OUT_STEPS - step) / FADE_OUT_STEPS) as u8)
+    }
+
+    /// When the step after the one due at `now` is
+    fn next_step(&self, now: Instant) -> Instant {
+        self.started + FADE_OUT / FADE_OUT_STEPS * (self.steps_at(now) + 1)
+    }
+}
+
+/// Clear `player` at the end of a fade-out, putting its volume back
+fn finish_fade_out(player: Option<&Output>, volume: u8) {
+    if let Some(player) = player {
+        player.clear();
+        player.set_volume(volume);
+    }
+}
+
+/// Rough wall-clock estimate of how much audio of the current stream is still
+/// buffered, used to decide how long to keep a draining player alive.
+///
+/// Playback is assumed to start when the first chunk arrives and run in real
+/// time; audio the server sent further ahead is covered by [`DRAIN_MARGIN`].
+#[derive(Debug, Default)]
+struct PlayoutEstimate {
+    first_chunk_at: Option<Instant>,
+    enqueued: Duration,
+}
+
+impl PlayoutEstimate {
+    fn record_chunk(&mut self, now: Instant, frames: u64, sample_rate: u32) {
+        if sample_rate == 0 {
+            return;
+        }
+        self.first_chunk_at.get_or_insert(now);
+        self.enqueued += Duration::from_micros(frames * 1_000_000 / u64::from(sample_rate));
+    }
+
+    /// Whether any audio of the current stream has been enqueued.
+    fn is_started(&self) -> bool {
+        self.first_chunk_at.is_some()
+    }
+
+    /// Estimated time until the last enqueued sample has played.
+    fn remaining(&self, now: Instant) -> Duration {
+        let Some(started) = self.first_chunk_at else {
+            return Duration::ZERO;
+        };
+        self.enqueued
+            .saturating_sub(now.saturating_duration_since(started))
+    }
+}
+
+/// Whether a player created for `a` can keep playing a stream in format `b`
+/// without being recreated.
+fn same_pcm_format(a: &AudioFormat, b: &AudioFormat) -> bool {
+    a.sample_rate == b.sample_rate && a.channels == b.channels && a.bit_depth == b.bit_depth
 }
 
 /// Volume/mute state owned by the playback thread.
@@ -1257,7 +3329,8 @@ impl PlaybackVolumeState {
     }
 }
 
-/// Playback thread - owns the `SyncedPlayer` and processes commands.
+/// Playback thread - owns the `SyncedPlayer` (or the null output) and
+/// processes commands.
 ///
 /// The cpal output device is re-resolved fresh on every `CreatePlayer`
 /// command rather than being captured once at thread start. Two reasons:
@@ -1273,86 +3346,355 @@ impl PlaybackVolumeState {
 ///    by user action via the MA UI, i.e., the user just hit play and
 ///    expects audio to come out somewhere.
 ///
+/// When no audio has arrived for `Settings::idle_release_secs`, the player
+/// is closed so the device (and any exclusive hold on it) is released and
+/// the thread sleeps until the next command; the connection stays up. The
+/// next `CreatePlayer` opens the device again, as does audio arriving
+/// without one, e.g. held audio queued again on resume.
+///
 /// We deliberately do not auto-recover from mid-stream device loss (no
-/// watching cpal's `error_callback`, no spontaneous re-create). The user's
-/// next play action is the only trigger — preventing surprise audio
-/// redirection when, e.g., they take their `AirPods` out mid-song.
+/// spontaneous re-create). When a selected device disappears the player is
+/// closed and its name sent on `device_event_tx` so the client pauses
+/// playback on the server; the user's next play action is the only trigger
+/// to start again — preventing surprise audio redirection when, e.g., they
+/// take their `AirPods` out mid-song.
+///
+/// A device held by another application is different: it is still the one
+/// the user chose, so opening it is retried with backoff for as long as the
+/// stream sends audio, and the audio kept meanwhile is queued once it opens
+/// so playback joins in sync. The client is told while the device is busy.
 #[allow(clippy::too_many_arguments)]
 fn run_playback_thread(
-    rx: std_mpsc::Receiver<PlayerCommand>,
+    mut rx: player_queue::Receiver<PlayerCommand>,
+    device_event_tx: mpsc::Sender<DeviceEvent>,
     clock_sync: Arc<Mutex<ClockSync>>,
-    audio_device_id: Option<String>,
+    mut audio_device_id: Option<String>,
+    mirror_device_id: Option<String>,
     use_software_volume: bool,
     initial_volume: u8,
     initial_muted: bool,
     initial_static_delay_ms: u16,
+    initial_mirror_delay_ms: i32,
 ) {
-    let mut synced_player: Option<SyncedPlayer> = None;
+    let mut synced_player: Option<Output> = None;
+    let mut player_format: Option<AudioFormat> = None;
+    // Set by `Drain`: when the current player's buffered audio is expected
+    // to have played out.
+    let mut drain_deadline: Option<Instant> = None;
+    // A player whose stream ended, kept alive until its tail has played
+    // while the next stream (in a different format) already buffers.
+    let mut draining: Option<(Output, Instant)> = None;
     let mut volume_state =
         PlaybackVolumeState::new(use_software_volume, initial_volume, initial_muted);
     let mut static_delay_ms = initial_static_delay_ms;
+    let mut mirror_delay_ms = initial_mirror_delay_ms;
+    // Whether audio was enqueued since the last clear, so clearing has
+    // something to fade out
+    let mut playing = false;
+    // The open player fading out before it is cleared
+    let mut fading: Option<FadeOut> = None;
+    // Exclusive hold on the output device, kept across players on the same
+    // device so consecutive streams don't bounce the device back to the mixer.
+    let mut exclusive_guard: Option<exclusive::ExclusiveGuard> = None;
+    // The output device's switch to the stream rate, kept the same way
+    let mut rate_guard: Option<rate_switch::RateSwitch> = None;
+    // How long the open player may go without audio before it is closed,
+    // read from the settings whenever a player is opened
+    let mut idle_release: Option<Duration> = None;
+    // When audio was last enqueued, or the player opened
+    let mut last_audio = Instant::now();
+    // Format of the player closed for being idle, to reopen it with if audio
+    // arrives before the next stream start
+    let mut idle_format: Option<AudioFormat> = None;
+    // What the open player was given lately, to carry over to another
+    // device, or the audio that arrived while the device was busy
+    let mut recent = RecentAudio::new();
+    // Opening the output device again while another application holds it
+    let mut busy_retry: Option<BusyRetry> = None;
+    let set_busy_retry = |slot: &mut Option<BusyRetry>, retry: Option<BusyRetry>| {
+        if slot.is_some() != retry.is_some() {
+            let _ = device_event_tx.try_send(DeviceEvent::Busy(retry.is_some()));
+        }
+        *slot = retry;
+    };
 
     loop {
-        match rx.recv() {
+        // Wake for a draining player's deadline, to close an idle player
+        // and, while a player is open on a selected device, to check the
+        // device is still there.
+        let drain_wait = draining
+            .as_ref()
+            .map(|(_, deadline)| deadline.saturating_duration_since(Instant::now()));
+        let watched_device = audio_device_id
+            .as_deref()
+            .filter(|_| synced_player.is_some());
+        let idle_wait = idle_release
+            .filter(|_| synced_player.is_some())
+            .map(|idle| idle_deadline(last_audio, idle, drain_deadline))
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let busy_wait = busy_retry
+            .as_ref()
+            .map(|retry| retry.next.saturating_duration_since(Instant::now()));
+        let fade_wait = fading.as_ref().map(|fade| {
+            let now = Instant::now();
+            fade.next_step(now).saturating_duration_since(now)
+        });
+        let wait = [
+            drain_wait,
+            watched_device.map(|_| DEVICE_CHECK_INTERVAL),
+            idle_wait,
+            busy_wait,
+            fade_wait,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let command = match wait {
+            Some(wait) => match rx.recv_timeout(wait) {
+                Err(std_mpsc::RecvTimeoutError::Timeout) => {
+                    if let Some(ref fade) = fading {
+                        match fade.volume_at(Instant::now()) {
+                            Some(volume) => {
+                                if let Some(ref player) = synced_player {
+                                    player.set_volume(volume);
+                                }
+                            }
+                            None => {
+                                let (volume, _) = volume_state.player_create_state();
+                                finish_fade_out(synced_player.as_ref(), volume);
+                                fading = None;
+                            }
+                        }
+                    }
+                    if draining
+                        .as_ref()
+                        .is_some_and(|(_, deadline)| *deadline <= Instant::now())
+                    {
+                        log::debug!("[Sendspin] Previous stream finished draining");
+                        draining = None;
+                    }
+                    if let Some(device_id) = watched_device {
+                        if devices::is_output_device_present(device_id) == Some(false) {
+                            log::warn!(
+                                "[Sendspin] Output device {} disappeared mid-stream; pausing",
+                                device_id
+                            );
+                            if let Some(player) = synced_player.take() {
+                                player.clear();
+                            }
+                            player_format = None;
+                            playing = false;
+                            drain_deadline = None;
+                            recent.clear();
+                            let _ =
+                                device_event_tx.try_send(DeviceEvent::Lost(device_id.to_string()));
+                        }
+                    }
+                    if let Some(idle) = idle_release.filter(|_| synced_player.is_some()) {
+                        if idle_deadline(last_audio, idle, drain_deadline) <= Instant::now() {
+                            log::info!(
+                                "[Sendspin] No audio for {:?}; releasing the output device",
+                                idle
+                            );
+                            if let Some((previous, _)) = draining.take() {
+                                previous.clear();
+                            }
+                            synced_player = None;
+                            exclusive::release(exclusive_guard.take());
+                            rate_guard = None;
+                            idle_format = player_format.take();
+                            playing = false;
+                            drain_deadline = None;
+                            recent.clear();
+                        }
+                    }
+                    if let Some(retry) = busy_retry
+                        .as_mut()
+                        .filter(|retry| retry.next <= Instant::now())
+                    {
+                        if last_audio.elapsed() > BUSY_RETRY_IDLE {
+                            log::info!("[Sendspin] Stream stopped while the output device was busy; giving up on it");
+                            recent.clear();
+                            set_busy_retry(&mut busy_retry, None);
+                            continue;
+                        }
+                        match open_player(
+                            &retry.format,
+                            &clock_sync,
+                            (audio_device_id.as_deref(), mirror_device_id.as_deref()),
+                            volume_state.player_create_state(),
+                            (static_delay_ms, mirror_delay_ms),
+                            &mut exclusive_guard,
+                            &mut rate_guard,
+                        ) {
+                            Ok(output) => {
+                                let carried = recent.unplayed(Instant::now());
+                                log::info!(
+                                    "[Sendspin] Output device free again, joining the stream with {} buffers",
+                                    carried.len()
+                                );
+                                playing = !carried.is_empty();
+                                for buffer in carried {
+                                    output.enqueue(buffer);
+                                }
+                                synced_player = Some(output);
+                                player_format = Some(retry.format.clone());
+                                idle_release = idle_release_from_settings();
+                                set_busy_retry(&mut busy_retry, None);
+                            }
+                            Err(SendspinError::DeviceBusy(_)) => retry.retry_later(),
+                            Err(_) => {
+                                recent.clear();
+                                set_busy_retry(&mut busy_retry, None);
+                                let _ = device_event_tx.try_send(DeviceEvent::OpenFailed);
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Err(std_mpsc::RecvTimeoutError::Disconnected) => Err(std_mpsc::RecvError),
+                Ok(command) => Ok(command),
+            },
+            None => rx.recv(),
+        };
+        if fading.take().is_some() {
+            // Cut short: whatever comes next follows the cleared audio
+            let (volume, _) = volume_state.player_create_state();
+            finish_fade_out(synced_player.as_ref(), volume);
+        }
+        match command {
             Ok(PlayerCommand::CreatePlayer(format)) => {
-                // Clear existing player if any
-                if let Some(ref player) = synced_player {
-                    player.clear();
+                let deadline = drain_deadline.take();
+                if let (Some(_), Some(current)) = (&synced_player, &player_format) {
+                    if deadline.is_some() && same_pcm_format(current, &format) {
+                        // Same format after a stream end: keep the player so
+                        // the new stream's audio queues right behind the tail
+                        // of the previous one.
+                        log::debug!("[Sendspin] Reusing audio player for gapless stream start");
+                        continue;
+                    }
+                }
+                match (synced_player.take(), deadline) {
+                    (Some(previous), Some(deadline)) if deadline > Instant::now() => {
+                        // Different format: the old output stream can't play
+                        // the new audio, so let it finish on its own.
+                        if let Some((older, _)) = draining.replace((previous, deadline)) {
+                            older.clear();
+                        }
+                    }
+                    (Some(previous), _) => previous.clear(),
+                    (None, _) => {}
                 }
 
-                // Create new SyncedPlayer with current volume/mute state
-                let (vol, mute) = volume_state.player_create_state();
-
-                // Re-resolve the output device fresh. See the function-level
-                // doc comment for why we do this on every CreatePlayer rather
-                // than caching a handle.
-                let device = devices::resolve_output_device(audio_device_id.as_deref());
-
-                let player_config = SyncedPlayerConfig {
-                    device,
-                    volume: vol,
-                    muted: mute,
-                    buffer_size: None,
-                };
-
-                match SyncedPlayer::new(format.clone(), Arc::clone(&clock_sync), player_config) {
-                    Ok(player) => {
-                        player.set_static_delay(static_delay_ms);
-                        log::info!(
-                            "[Sendspin] Audio player created: channels={}, sample_rate={}, bit_depth={}, static_delay_ms={}",
-                            format.channels,
-                            format.sample_rate,
-                            format.bit_depth,
-                            static_delay_ms
-                        );
-                        synced_player = Some(player);
+                idle_format = None;
+                recent.clear();
+                last_audio = Instant::now();
+                match open_player(
+                    &format,
+                    &clock_sync,
+                    (audio_device_id.as_deref(), mirror_device_id.as_deref()),
+                    volume_state.player_create_state(),
+                    (static_delay_ms, mirror_delay_ms),
+                    &mut exclusive_guard,
+                    &mut rate_guard,
+                ) {
+                    Ok(output) => {
+                        synced_player = Some(output);
+                        player_format = Some(format);
+                        playing = false;
+                        idle_release = idle_release_from_settings();
+                        set_busy_retry(&mut busy_retry, None);
                     }
-                    Err(e) => {
-                        log::error!(
-                            "[Sendspin] Failed to create SyncedPlayer for channels={}, sample_rate={}, bit_depth={}: {}",
-                            format.channels,
-                            format.sample_rate,
-                            format.bit_depth,
-                            e
-                        );
+                    Err(SendspinError::DeviceBusy(_)) => {
+                        log::warn!("[Sendspin] Output device is busy; retrying until it's free");
+                        set_busy_retry(&mut busy_retry, Some(BusyRetry::new(format)));
+                    }
+                    Err(_) => {
+                        set_busy_retry(&mut busy_retry, None);
+                        let _ = device_event_tx.try_send(DeviceEvent::OpenFailed);
                     }
                 }
             }
             Ok(PlayerCommand::Enqueue(buffer)) => {
+                if synced_player.is_none() {
+                    if let Some(format) = idle_format.take() {
+                        log::info!("[Sendspin] Audio after idling; reopening the output device");
+                        match open_player(
+                            &format,
+                            &clock_sync,
+                            (audio_device_id.as_deref(), mirror_device_id.as_deref()),
+                            volume_state.player_create_state(),
+                            (static_delay_ms, mirror_delay_ms),
+                            &mut exclusive_guard,
+                            &mut rate_guard,
+                        ) {
+                            Ok(output) => {
+                                synced_player = Some(output);
+                                player_format = Some(format);
+                                idle_release = idle_release_from_settings();
+                            }
+                            Err(SendspinError::DeviceBusy(_)) => {
+                                log::warn!(
+                                    "[Sendspin] Output device is busy; retrying until it's free"
+                                );
+                                set_busy_retry(&mut busy_retry, Some(BusyRetry::new(format)));
+                            }
+                            Err(_) => {
+                                let _ = device_event_tx.try_send(DeviceEvent::OpenFailed);
+                            }
+                        }
+                    }
+                }
                 if let Some(ref player) = synced_player {
+                    last_audio = Instant::now();
+                    recent.push(&buffer, last_audio);
                     player.enqueue(buffer);
+                    playing = true;
+                    if player.exited() {
+                        log::warn!("[Sendspin] Playback process died; closing the output");
+                        synced_player = None;
+                        player_format = None;
+                        playing = false;
+                        drain_deadline = None;
+                        recent.clear();
+                        let _ = device_event_tx.try_send(DeviceEvent::OpenFailed);
+                    }
+                } else if busy_retry.is_some() {
+                    // Kept to queue once the device opens
+                    last_audio = Instant::now();
+                    recent.push(&buffer, last_audio);
                 }
             }
             Ok(PlayerCommand::Clear) => {
+                drain_deadline = None;
+                if let Some((previous, _)) = draining.take() {
+                    previous.clear();
+                }
                 if let Some(ref player) = synced_player {
-                    player.clear();
+                    let (volume, muted) = volume_state.player_create_state();
+                    if playing && !muted {
+                        let now = Instant::now();
+                        let fade = FadeOut::new(volume, now);
+                        player.set_volume(fade.volume_at(now).unwrap_or_default());
+                        fading = Some(fade);
+                    } else {
+                        finish_fade_out(Some(player), volume);
+                    }
                 }
+                recent.clear();
+                playing = false;
+            }
+            Ok(PlayerCommand::Drain(remaining)) => {
+                drain_deadline = Some(Instant::now() + remaining + DRAIN_MARGIN);
             }
             Ok(PlayerCommand::SetVolume(volume)) => {
                 if volume_state.set_volume(volume) {
                     if let Some(ref player) = synced_player {
                         player.set_volume(volume);
                     }
+                    if let Some((ref player, _)) = draining {
+                        player.set_volume(volume);
+                    }
                 }
             }
             Ok(PlayerCommand::SetMute(muted)) => {
@@ -1360,16 +3702,60 @@ fn run_playback_thread(
                     if let Some(ref player) = synced_player {
                         player.set_mute(muted);
                     }
+                    if let Some((ref player, _)) = draining {
+                        player.set_mute(muted);
+                    }
                 }
             }
             Ok(PlayerCommand::SetStaticDelay(delay_ms)) => {
                 static_delay_ms = delay_ms;
                 if let Some(ref player) = synced_player {
-                    player.set_static_delay(delay_ms);
+                    player.set_static_delay(delay_ms, mirror_delay_ms);
+                }
+            }
+            Ok(PlayerCommand::SetMirrorDelay(delay_ms)) => {
+                mirror_delay_ms = delay_ms;
+                if let Some(ref player) = synced_player {
+                    player.set_static_delay(static_delay_ms, delay_ms);
+                }
+            }
+            Ok(PlayerCommand::SwitchOutputDevice(device_id)) => {
+                audio_device_id = device_id;
+                // Without an open player the next one opens on the new device
+                let Some(format) = player_format.clone().filter(|_| synced_player.is_some()) else {
+                    continue;
+                };
+                let Ok(output) = open_player(
+                    &format,
+                    &clock_sync,
+                    (audio_device_id.as_deref(), mirror_device_id.as_deref()),
+                    volume_state.player_create_state(),
+                    (static_delay_ms, mirror_delay_ms),
+                    &mut exclusive_guard,
+                    &mut rate_guard,
+                ) else {
+                    log::warn!("[Sendspin] Couldn't open the new output device; the stream plays out where it was");
+                    continue;
+                };
+                // Fill the new player before silencing the old one, so the
+                // handover is seamless
+                let carried = recent.unplayed(Instant::now());
+                log::info!(
+                    "[Sendspin] Switched output device mid-stream, carrying over {} buffers",
+                    carried.len()
+                );
+                for buffer in carried {
+                    output.enqueue(buffer);
+                }
+                if let Some(previous) = synced_player.replace(output) {
+                    previous.clear();
                 }
             }
             Ok(PlayerCommand::Shutdown) | Err(_) => {
                 // Clean up and exit
+                if let Some((previous, _)) = draining.take() {
+                    previous.clear();
+                }
                 if let Some(ref player) = synced_player {
                     player.clear();
                 }
@@ -1377,188 +3763,762 @@ fn run_playback_thread(
             }
         }
     }
+
+    // Drop the players before handing the device back to the mixer
+    drop(synced_player);
+    drop(draining);
+    exclusive::release(exclusive_guard);
+}
+
+/// What the playback thread plays to: a player on an output device, the
+/// same mirrored to a second device, the null output, a playback process
+/// (see [`audio_process`]), or on Windows a device held in exclusive mode
+enum Output {
+    Device(SyncedPlayer),
+    Mirrored {
+        main: SyncedPlayer,
+        mirror: SyncedPlayer,
+    },
+    Null(NullPlayer),
+    Isolated(audio_process::IsolatedPlayer),
+    #[cfg(target_os = "windows")]
+    Exclusive(exclusive::wasapi::ExclusivePlayer),
+}
+
+impl Output {
+    fn enqueue(&self, buffer: AudioBuffer) {
+        match self {
+            Self::Device(player) => player.enqueue(buffer),
+            Self::Mirrored { main, mirror } => {
+                mirror.enqueue(recent_audio::copy(&buffer));
+                main.enqueue(buffer);
+            }
+            Self::Null(player) => player.enqueue(&buffer),
+            Self::Isolated(player) => player.enqueue(&buffer),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(player) => player.enqueue(&buffer),
+        }
+    }
+
+    fn clear(&self) {
+        match self {
+            Self::Device(player) => player.clear(),
+            Self::Mirrored { main, mirror } => {
+                main.clear();
+                mirror.clear();
+            }
+            Self::Null(player) => {
+                log::trace!(
+                    "[Sendspin] Null output dropped {:?} of unplayed audio",
+                    player.clear()
+                );
+            }
+            Self::Isolated(player) => player.clear(),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(player) => player.clear(),
+        }
+    }
+
+    fn set_volume(&self, volume: u8) {
+        if let Self::Isolated(player) = self {
+            player.set_volume(volume);
+        }
+        #[cfg(target_os = "windows")]
+        if let Self::Exclusive(player) = self {
+            player.set_volume(volume);
+        }
+        for player in self.players().into_iter().flatten() {
+            player.set_volume(volume);
+        }
+    }
+
+    fn set_mute(&self, muted: bool) {
+        if let Self::Isolated(player) = self {
+            player.set_mute(muted);
+        }
+        #[cfg(target_os = "windows")]
+        if let Self::Exclusive(player) = self {
+            player.set_mute(muted);
+        }
+        for player in self.players().into_iter().flatten() {
+            player.set_mute(muted);
+        }
+    }
+
+    /// Set the static delay, with the mirror device `mirror_delay_ms` behind
+    fn set_static_delay(&self, delay_ms: u16, mirror_delay_ms: i32) {
+        match self {
+            Self::Device(player) => player.set_static_delay(delay_ms),
+            Self::Mirrored { main, mirror } => {
+                let (main_delay_ms, mirror_delay_ms) = mirror_delays(delay_ms, mirror_delay_ms);
+                main.set_static_delay(main_delay_ms);
+                mirror.set_static_delay(mirror_delay_ms);
+            }
+            Self::Null(_) => {}
+            Self::Isolated(player) => player.set_static_delay(delay_ms),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(player) => player.set_static_delay(delay_ms),
+        }
+    }
+
+    /// Whether the output is gone for good: only a playback process can
+    /// end on its own
+    fn exited(&self) -> bool {
+        matches!(self, Self::Isolated(player) if player.exited())
+    }
+
+    /// The sendspin-rs players behind this output
+    fn players(&self) -> [Option<&SyncedPlayer>; 2] {
+        match self {
+            Self::Device(player) => [Some(player), None],
+            Self::Mirrored { main, mirror } => [Some(main), Some(mirror)],
+            Self::Null(_) | Self::Isolated(_) => [None, None],
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(_) => [None, None],
+        }
+    }
+}
+
+/// Static delays of the main and the mirror device, with the mirror playing
+/// `mirror_delay_ms` after the main device. A negative offset delays the
+/// main device instead, since neither can play ahead of the static delay.
+fn mirror_delays(static_delay_ms: u16, mirror_delay_ms: i32) -> (u16, u16) {
+    let offset = mirror_delay_ms.unsigned_abs().min(u32::from(u16::MAX)) as u16;
+    let delayed = static_delay_ms.saturating_add(offset);
+    if mirror_delay_ms >= 0 {
+        (static_delay_ms, delayed)
+    } else {
+        (delayed, static_delay_ms)
+    }
+}
+
+/// Open a player for `format` on the configured output device, and the
+/// mirror device if one is set, with the current volume, mute and static
+/// delays.
+///
+/// Re-resolves the output device fresh every time; see
+/// [`run_playback_thread`] for why rather than caching a handle.
+fn open_player(
+    format: &AudioFormat,
+    clock_sync: &Arc<Mutex<ClockSync>>,
+    (audio_device_id, mirror_device_id): (Option<&str>, Option<&str>),
+    (volume, muted): (u8, bool),
+    (static_delay_ms, mirror_delay_ms): (u16, i32),
+    exclusive_guard: &mut Option<exclusive::ExclusiveGuard>,
+    rate_guard: &mut Option<rate_switch::RateSwitch>,
+) -> Result<Output, SendspinError> {
+    if audio_device_id == Some(null_output::DEVICE_ID) {
+        exclusive::release(exclusive_guard.take());
+        *rate_guard = None;
+        log::info!(
+            "[Sendspin] Null output opened: channels={}, sample_rate={}, bit_depth={}",
+            format.channels,
+            format.sample_rate,
+            format.bit_depth
+        );
+        return Ok(Output::Null(NullPlayer::new(format)));
+    }
+
+    let device = devices::resolve_output_device(audio_device_id);
+    *rate_guard = rate_switch::prepare(rate_guard.take(), device.as_ref(), format.sample_rate);
+
+    if crate::settings::read(|s| s.isolated_playback) {
+        // The child couldn't open a device this process holds exclusively
+        exclusive::release(exclusive_guard.take());
+        if mirror_device_id.is_some() {
+            log::info!("[Sendspin] Not mirroring while playing in a separate process");
+        }
+        let player = audio_process::IsolatedPlayer::open(
+            audio_device_id,
+            format,
+            clock_sync,
+            (volume, muted),
+        )
+        .inspect_err(|e| {
+            log::error!(
+                "[Sendspin] Failed to start playback process for channels={}, sample_rate={}, bit_depth={}: {}",
+                format.channels,
+                format.sample_rate,
+                format.bit_depth,
+                e
+            );
+        })?;
+        log::info!(
+            "[Sendspin] Audio playing in a separate process: channels={}, sample_rate={}, bit_depth={}, static_delay_ms={}",
+            format.channels,
+            format.sample_rate,
+            format.bit_depth,
+            static_delay_ms
+        );
+        let output = Output::Isolated(player);
+        output.set_static_delay(static_delay_ms, mirror_delay_ms);
+        return Ok(output);
+    }
+
+    *exclusive_guard =
+        exclusive::prepare(exclusive_guard.take(), device.as_ref(), format.sample_rate);
+    let route = devices::stream_route(audio_device_id);
+
+    #[cfg(target_os = "windows")]
+    if let Some(player) =
+        exclusive::open_wasapi(exclusive_guard, format, clock_sync, (volume, muted))
+    {
+        if mirror_device_id.is_some() {
+            log::info!("[Sendspin] Not mirroring while the output device is held exclusively");
+        }
+        let output = Output::Exclusive(player);
+        output.set_static_delay(static_delay_ms, mirror_delay_ms);
+        return Ok(output);
+    }
+
+    let opened_on = device.clone();
+    let player_config = SyncedPlayerConfig {
+        device,
+        volume,
+        muted,
+        buffer_size: None,
+    };
+
+    match SyncedPlayer::new(format.clone(), Arc::clone(clock_sync), player_config) {
+        Ok(player) => {
+            exclusive::confirm(exclusive_guard, format.sample_rate);
+            if let Some(route) = route {
+                route.apply_in_background();
+            }
+            log::info!(
+                "[Sendspin] Audio player created: channels={}, sample_rate={}, bit_depth={}, static_delay_ms={}",
+                format.channels,
+                format.sample_rate,
+                format.bit_depth,
+                static_delay_ms
+            );
+            let mirror = mirror_device_id
+                .filter(|&id| id != null_output::DEVICE_ID && Some(id) != audio_device_id)
+                .and_then(|id| open_mirror(format, clock_sync, id, (volume, muted)));
+            let output = match mirror {
+                Some(mirror) => Output::Mirrored {
+                    main: player,
+                    mirror,
+                },
+                None => Output::Device(player),
+            };
+            output.set_static_delay(static_delay_ms, mirror_delay_ms);
+            Ok(output)
+        }
+        Err(e) => {
+            exclusive::release(exclusive_guard.take());
+            log::error!(
+                "[Sendspin] Failed to create SyncedPlayer for channels={}, sample_rate={}, bit_depth={}: {}",
+                format.channels,
+                format.sample_rate,
+                format.bit_depth,
+                e
+            );
+            Err(devices::open_failure(
+                opened_on.as_ref(),
+                format.channels,
+                format.sample_rate,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Open a player on the mirror device for the same stream as the main one.
+/// If that fails, the main device plays on its own.
+///
+/// The mirror isn't moved to a `PipeWire` sink or JACK ports the way the
+/// main device is; those play to the default output.
+fn open_mirror(
+    format: &AudioFormat,
+    clock_sync: &Arc<Mutex<ClockSync>>,
+    mirror_device_id: &str,
+    (volume, muted): (u8, bool),
+) -> Option<SyncedPlayer> {
+    let device = match devices::get_device_by_id(mirror_device_id) {
+        Ok(device) => device,
+        Err(e) => {
+            log::warn!(
+                "[Sendspin] Mirror device {} unavailable, playing without it: {}",
+                mirror_device_id,
+                e
+            );
+            return None;
+        }
+    };
+    let player_config = SyncedPlayerConfig {
+        device: Some(device),
+        volume,
+        muted,
+        buffer_size: None,
+    };
+    match SyncedPlayer::new(format.clone(), Arc::clone(clock_sync), player_config) {
+        Ok(player) => {
+            log::info!("[Sendspin] Mirroring audio to {}", mirror_device_id);
+            Some(player)
+        }
+        Err(e) => {
+            log::warn!(
+                "[Sendspin] Failed to open mirror device {}, playing without it: {}",
+                mirror_device_id,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// How long an open player may go without audio, or `None` to keep it open
+fn idle_release_from_settings() -> Option<Duration> {
+    match crate::settings::read(|s| s.idle_release_secs) {
+        0 => None,
+        secs => Some(Duration::from_secs(u64::from(secs))),
+    }
+}
+
+/// Opening an output device again for a stream in `format` while another
+/// application holds it
+struct BusyRetry {
+    format: AudioFormat,
+    /// When to try next
+    next: Instant,
+    /// How long was waited before `next`
+    backoff: Duration,
+}
+
+impl BusyRetry {
+    fn new(format: AudioFormat) -> Self {
+        Self {
+            format,
+            next: Instant::now() + BUSY_RETRY_FIRST,
+            backoff: BUSY_RETRY_FIRST,
+        }
+    }
+
+    /// Try again after another failed attempt, waiting twice as long
+    fn retry_later(&mut self) {
+        self.backoff = (self.backoff * 2).min(BUSY_RETRY_MAX);
+        self.next = Instant::now() + self.backoff;
+    }
+}
+
+/// Recent restarts of a connection's playback thread, to stop restarting
+/// one that keeps dying
+struct RestartBudget {
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartBudget {
+    fn new() -> Self {
+        Self {
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Whether another restart at `now` is allowed, counting it if so
+    fn allow(&mut self, now: Instant) -> bool {
+        while self
+            .restarts
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= PLAYBACK_RESTART_WINDOW)
+        {
+            self.restarts.pop_front();
+        }
+        if self.restarts.len() >= PLAYBACK_RESTART_LIMIT {
+            return false;
+        }
+        self.restarts.push_back(now);
+        true
+    }
 }
 
-/// Stop the Sendspin client
-pub async fn stop() {
-    set_enabled(false);
+/// When a player that last got audio at `last_audio` counts as idle: `idle`
+/// later, but not before a stream that ended has played out
+fn idle_deadline(last_audio: Instant, idle: Duration, drain_deadline: Option<Instant>) -> Instant {
+    let deadline = last_audio + idle;
+    drain_deadline.map_or(deadline, |drained| deadline.max(drained))
+}
 
-    // Take the volume controller out of the global (under the write lock), then
-    // drop it outside the lock. The Drop impl joins the polling thread, which
-    // can block up to 2s. We drop explicitly here rather than letting it fall
-    // out of scope at end-of-function so the polling thread is fully stopped
-    // before we send the shutdown signal below.
-    let old_vol_ctrl = {
-        let mut vol_ctrl = VOLUME_CONTROLLER.write();
-        vol_ctrl.take()
-    };
-    drop(old_vol_ctrl);
+impl SendspinManager {
+    /// Stop the main player and all additional players
+    pub async fn stop(&self) {
+        self.primary.stop().await;
 
-    // Send shutdown signal
-    {
-        let tx = SHUTDOWN_TX.read();
-        if let Some(ref sender) = *tx {
-            let _ = sender.try_send(());
+        let _sync = self.sync_lock.lock().await;
+        let additional = std::mem::take(&mut *self.additional.write());
+        for client in additional {
+            client.stop().await;
         }
     }
 
-    // Wait for the client task to finish (with timeout)
-    let task_handle = {
-        let mut handle = CLIENT_TASK.write();
-        handle.take()
-    };
-    if let Some(mut handle) = task_handle {
-        // Wait up to 2 seconds for graceful shutdown. If the task does not stop,
-        // abort it so a stale reconnect loop cannot survive a later start().
-        match tokio::time::timeout(Duration::from_secs(2), &mut handle).await {
-            Ok(Ok(())) => {}
-            Ok(Err(e)) if e.is_cancelled() => {}
-            Ok(Err(e)) => {
-                log::warn!(
-                    "[Sendspin] Client task exited with error during stop: {}",
-                    e
-                );
-            }
-            Err(_) => {
-                log::warn!("[Sendspin] Client task did not stop gracefully; aborting");
-                handle.abort();
-                let _ = handle.await;
-            }
+    /// Restart every running player with its current settings.
+    /// Used when settings change (e.g., volume control mode, audio device)
+    /// to make the new settings take effect immediately.
+    pub async fn restart(&self) {
+        self.primary.restart().await;
+
+        let additional = self.additional.read().clone();
+        for client in additional {
+            client.restart().await;
         }
+        self.sync_additional_players().await;
     }
 
-    // Clear shutdown sender
-    {
-        let mut tx = SHUTDOWN_TX.write();
-        *tx = None;
+    /// Live-update the main player's static sync delay without reconnecting.
+    pub fn set_static_delay(&self, sync_delay_ms: i32) -> Result<(), SendspinError> {
+        self.primary.set_static_delay(sync_delay_ms)
     }
 
-    // Clear command channel
-    {
-        let mut tx = COMMAND_TX.write();
-        *tx = None;
+    /// Live-update how much later the main player's mirror device plays.
+    pub fn set_mirror_delay(&self, mirror_delay_ms: i32) -> Result<(), SendspinError> {
+        self.primary.set_mirror_delay(mirror_delay_ms)
     }
 
-    // Clear runtime reconfiguration channel
-    {
-        let mut tx = CLIENT_COMMAND_TX.write();
-        *tx = None;
+    /// Rename the main player without reconnecting
+    pub fn rename(&self, player_name: &str) -> Result<(), SendspinError> {
+        self.primary.rename(player_name)
     }
 
-    // Clear client handle
-    {
-        let mut client = SENDSPIN_CLIENT.write();
-        *client = None;
+    /// Move the main player to another output device without stopping
+    /// what's playing
+    pub fn switch_output_device(
+        &self,
+        audio_device_id: Option<String>,
+    ) -> Result<(), SendspinError> {
+        self.primary.switch_output_device(audio_device_id)
     }
 
-    // Volume is unknown until the next client loop publishes one.
-    CURRENT_VOLUME.store(VOLUME_UNKNOWN, Ordering::Relaxed);
-}
+    /// Apply changed DSP settings on every player without reconnecting.
+    pub fn reload_dsp(&self) -> Result<(), SendspinError> {
+        self.primary.reload_dsp()?;
+        for player in self.additional.read().iter() {
+            player.reload_dsp()?;
+        }
+        Ok(())
+    }
 
-/// Restart the Sendspin client with the existing config.
-/// Used when settings change (e.g., volume control mode, audio device)
-/// to make the new settings take effect immediately.
-/// Does nothing if no client is currently running.
-pub async fn restart() {
-    // Read lock is scoped to this block so it's released before start()
-    // calls stop(), which takes a write lock on SENDSPIN_CLIENT.
-    let config = {
-        SENDSPIN_CLIENT.read().as_ref().map(|c| {
-            let mut config = c.config.clone();
-            let settings = crate::settings::get_settings();
-            config.audio_device_id = settings.audio_device_id;
-            config.sync_delay_ms = settings.sync_delay_ms;
-            config.player_name = settings.sendspin_player_name;
-            config
-        })
-    };
-    if let Some(config) = config {
-        log::info!("[Sendspin] Restarting client to apply new settings");
-        if let Err(e) = start(config).await {
-            log::error!("[Sendspin] Failed to restart client: {}", e);
+    /// Lower the volume of players above a new maximum volume.
+    pub fn apply_max_volume(&self) -> Result<(), SendspinError> {
+        self.primary.apply_max_volume()?;
+        for player in self.additional.read().iter() {
+            player.apply_max_volume()?;
         }
-    } else {
-        log::warn!("[Sendspin] Restart requested but no active client configuration is available");
+        Ok(())
     }
-}
 
-/// Live-update the static sync delay without reconnecting Sendspin.
-pub fn set_static_delay(sync_delay_ms: i32) -> Result<(), String> {
-    let delay_ms = clamp_static_delay_ms(sync_delay_ms);
+    /// Send a playback command to the main player
+    pub fn send_command(&self, command: PlaybackCommand) -> Result<(), SendspinError> {
+        self.primary.send_command(command)
+    }
 
-    let client = SENDSPIN_CLIENT.read();
-    if client.is_none() {
-        return Ok(());
+    /// Send a playback command to the player with `player_id`, or to the
+    /// main player when `None`.
+    pub fn send_command_to(
+        &self,
+        player_id: Option<&str>,
+        command: PlaybackCommand,
+    ) -> Result<(), SendspinError> {
+        match player_id {
+            None => self.send_command(command),
+            Some(player_id) => self
+                .player(player_id)
+                .ok_or_else(|| SendspinError::UnknownPlayer(player_id.to_string()))?
+                .send_command(command),
+        }
     }
-    drop(client);
 
-    let tx = CLIENT_COMMAND_TX.read();
-    if let Some(ref sender) = *tx {
-        sender
-            .try_send(ClientCommand::SetStaticDelay(delay_ms))
-            .map_err(|e| format!("Failed to set static delay: {}", e))?;
+    /// Get the main player's runtime volume as a percentage (0..=100).
+    /// Reads the lock-free snapshot published by the client loop, so this never
+    /// blocks and is safe to call from latency-sensitive contexts.
+    pub fn get_volume_percent(&self) -> Result<u8, SendspinError> {
+        if self.primary.get_player_id().is_none() {
+            return Err(SendspinError::NotConnected);
+        }
+
+        match CURRENT_VOLUME.load(Ordering::Relaxed) {
+            VOLUME_UNKNOWN => Err(SendspinError::VolumeControl(
+                "Volume not reported yet".to_string(),
+            )),
+            volume => Ok(volume.min(100)),
+        }
     }
 
-    Ok(())
+    /// Set the main player's volume as a percentage. Values greater than 100 are clamped.
+    pub fn set_volume_percent(&self, volume: u8) -> Result<(), SendspinError> {
+        self.send_command(PlaybackCommand::SetVolume(volume.min(100)))
+    }
 }
 
-/// Send a playback command (play, pause, stop, next, previous)
-pub fn send_command(command: &str) -> Result<(), String> {
-    let client = SENDSPIN_CLIENT.read();
+impl SendspinClient {
+    /// Stop the Sendspin client
+    pub async fn stop(&self) {
+        self.set_enabled(false);
+
+        // Take the volume controller out of the global (under the write lock), then
+        // drop it outside the lock. The Drop impl joins the polling thread, which
+        // can block up to 2s. We drop explicitly here rather than letting it fall
+        // out of scope at end-of-function so the polling thread is fully stopped
+        // before we send the shutdown signal below.
+        if self.inner.primary {
+            let old_vol_ctrl = {
+                let mut vol_ctrl = VOLUME_CONTROLLER.write();
+                vol_ctrl.take()
+            };
+            drop(old_vol_ctrl);
+        }
+
+        // Send shutdown signal
+        {
+            let tx = self.inner.shutdown_tx.read();
+            if let Some(ref sender) = *tx {
+                let _ = sender.try_send(());
+            }
+        }
+
+        // Wait for the client task to finish (with timeout)
+        let task_handle = {
+            let mut handle = self.inner.client_task.write();
+            handle.take()
+        };
+        if let Some(mut handle) = task_handle {
+            // Wait up to 2 seconds for graceful shutdown. If the task does not stop,
+            // abort it so a stale reconnect loop cannot survive a later start().
+            match tokio::time::timeout(Duration::from_secs(2), &mut handle).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) if e.is_cancelled() => {}
+                Ok(Err(e)) => {
+                    log::warn!(
+                        "[Sendspin] Client task exited with error during stop: {}",
+                        e
+                    );
+                }
+                Err(_) => {
+                    log::warn!("[Sendspin] Client task did not stop gracefully; aborting");
+                    handle.abort();
+                    let _ = handle.await;
+                }
+            }
+        }
+
+        // Clear shutdown sender
+        {
+            let mut tx = self.inner.shutdown_tx.write();
+            *tx = None;
+        }
+
+        // Clear command channel
+        {
+            let mut tx = self.inner.command_tx.write();
+            *tx = None;
+        }
+
+        // Clear runtime reconfiguration channel
+        {
+            let mut tx = self.inner.client_command_tx.write();
+            *tx = None;
+        }
+
+        // Clear client handle
+        self.update_status(ConnectionStatus::Disconnected);
+        {
+            let mut client = self.inner.client.write();
+            *client = None;
+        }
+
+        *self.inner.now_playing.write() = None;
+        *self.inner.position.write() = None;
+
+        // Volume is unknown until the next client loop publishes one.
+        if self.inner.primary {
+            CURRENT_VOLUME.store(VOLUME_UNKNOWN, Ordering::Relaxed);
+        }
+    }
+
+    /// Restart the Sendspin client with the existing config.
+    /// Used when settings change (e.g., volume control mode, audio device)
+    /// to make the new settings take effect immediately.
+    /// Does nothing if no client is currently running.
+    pub async fn restart(&self) {
+        // `config()` releases its read lock before start() calls stop(),
+        // which takes a write lock on the client.
+        let config = self
+            .config()
+            .and_then(|config| self.refreshed_config(config));
+        if let Some(config) = config {
+            log::info!("[Sendspin] Restarting client to apply new settings");
+            if let Err(e) = self.start(config).await {
+                log::error!("[Sendspin] Failed to restart client: {}", e);
+            }
+        } else {
+            log::warn!(
+                "[Sendspin] Restart requested but no active client configuration is available"
+            );
+        }
+    }
+
+    /// Live-update the static sync delay without reconnecting Sendspin.
+    pub fn set_static_delay(&self, sync_delay_ms: i32) -> Result<(), SendspinError> {
+        let delay_ms = clamp_static_delay_ms(sync_delay_ms);
+
+        let client = self.inner.client.read();
+        if client.is_none() {
+            return Ok(());
+        }
+        drop(client);
 
-    if client.is_none() {
-        return Err("Sendspin client not connected".to_string());
+        let tx = self.inner.client_command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender
+                .try_send(ClientCommand::SetStaticDelay(delay_ms))
+                .map_err(|e| {
+                    SendspinError::Command(format!("Failed to set static delay: {}", e))
+                })?;
+        }
+
+        Ok(())
     }
 
-    // Send command via the command channel to the client loop
-    let tx = COMMAND_TX.read();
-    if let Some(ref sender) = *tx {
-        sender
-            .try_send(command.to_string())
-            .map_err(|e| format!("Failed to send command: {}", e))?;
+    /// Live-update the mirror device's delay without reconnecting.
+    pub fn set_mirror_delay(&self, mirror_delay_ms: i32) -> Result<(), SendspinError> {
+        if let Some(client) = self.inner.client.write().as_mut() {
+            client.config.mirror_delay_ms = mirror_delay_ms;
+        } else {
+            return Ok(());
+        }
+
+        let tx = self.inner.client_command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender
+                .try_send(ClientCommand::SetMirrorDelay(mirror_delay_ms))
+                .map_err(|e| {
+                    SendspinError::Command(format!("Failed to set mirror delay: {}", e))
+                })?;
+        }
+
         Ok(())
-    } else {
-        Err("Command channel not available".to_string())
     }
-}
 
-/// Get the current runtime player volume as a percentage (0..=100).
-/// Reads the lock-free snapshot published by the client loop, so this never
-/// blocks and is safe to call from latency-sensitive contexts.
-#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
-pub fn get_volume_percent() -> Result<u8, String> {
-    if SENDSPIN_CLIENT.read().is_none() {
-        return Err("Sendspin client not connected".to_string());
+    /// Rename the running player without reconnecting. The next
+    /// connection introduces itself with the new name.
+    pub fn rename(&self, player_name: &str) -> Result<(), SendspinError> {
+        if let Some(client) = self.inner.client.write().as_mut() {
+            client.config.player_name = player_name.to_string();
+        } else {
+            return Ok(());
+        }
+
+        let tx = self.inner.client_command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender
+                .try_send(ClientCommand::Rename(player_name.to_string()))
+                .map_err(|e| SendspinError::Command(format!("Failed to rename player: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Move playback to another output device. Audio still queued carries
+    /// over to the new device; a stream it can't play as it is makes the
+    /// player reconnect instead.
+    pub fn switch_output_device(
+        &self,
+        audio_device_id: Option<String>,
+    ) -> Result<(), SendspinError> {
+        if let Some(client) = self.inner.client.write().as_mut() {
+            client.config.audio_device_id.clone_from(&audio_device_id);
+        } else {
+            return Ok(());
+        }
+        self.set_fallback_device(None);
+
+        let tx = self.inner.client_command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender
+                .try_send(ClientCommand::SwitchOutputDevice(audio_device_id))
+                .map_err(|e| {
+                    SendspinError::Command(format!("Failed to switch output device: {}", e))
+                })?;
+        }
+
+        Ok(())
     }
 
-    match CURRENT_VOLUME.load(Ordering::Relaxed) {
-        VOLUME_UNKNOWN => Err("Volume not reported yet".to_string()),
-        volume => Ok(volume.min(100)),
+    /// Restart from within the client's own task, which the restart stops
+    fn restart_in_background(&self) {
+        let instance = self.clone();
+        tokio::spawn(async move {
+            instance.restart().await;
+        });
     }
-}
 
-/// Set the player volume as a percentage. Values greater than 100 are clamped.
-#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
-pub fn set_volume_percent(volume: u8) -> Result<(), String> {
-    if SENDSPIN_CLIENT.read().is_none() {
-        return Err("Sendspin client not connected".to_string());
+    /// Apply changed DSP settings without reconnecting Sendspin.
+    pub fn reload_dsp(&self) -> Result<(), SendspinError> {
+        let tx = self.inner.client_command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender.try_send(ClientCommand::ReloadDsp).map_err(|e| {
+                SendspinError::Command(format!("Failed to reload DSP settings: {}", e))
+            })?;
+        }
+
+        Ok(())
     }
 
-    let tx = CLIENT_COMMAND_TX.read();
-    if let Some(ref sender) = *tx {
-        sender
-            .try_send(ClientCommand::SetVolume(volume.min(100)))
-            .map_err(|e| format!("Failed to set volume: {}", e))?;
+    /// Lower the volume if it's above a new maximum volume.
+    pub fn apply_max_volume(&self) -> Result<(), SendspinError> {
+        let tx = self.inner.client_command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender
+                .try_send(ClientCommand::ApplyMaxVolume)
+                .map_err(|e| {
+                    SendspinError::Command(format!("Failed to apply the maximum volume: {}", e))
+                })?;
+        }
+
         Ok(())
-    } else {
-        Err("Client command channel not available".to_string())
+    }
+
+    /// Send a playback command to the running client
+    pub fn send_command(&self, command: PlaybackCommand) -> Result<(), SendspinError> {
+        let client = self.inner.client.read();
+
+        if client.is_none() {
+            return Err(SendspinError::NotConnected);
+        }
+
+        // Send command via the command channel to the client loop
+        let tx = self.inner.command_tx.read();
+        if let Some(ref sender) = *tx {
+            sender
+                .try_send(command)
+                .map_err(|e| SendspinError::Command(format!("Failed to send command: {}", e)))?;
+            Ok(())
+        } else {
+            Err(SendspinError::Command(
+                "Command channel not available".to_string(),
+            ))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::mock_server::{eventually, MockServer};
     use super::*;
     use crate::settings::VolumeControlMode;
+    use serde_json::json;
+
+    #[test]
+    fn fade_out_steps_down_to_silence_without_blocking() {
+        let start = Instant::now();
+        let fade = FadeOut::new(80, start);
+        let step = FADE_OUT / FADE_OUT_STEPS;
+        assert_eq!(fade.volume_at(start), Some(76));
+        assert_eq!(fade.next_step(start), start + step);
+        assert_eq!(fade.volume_at(start + step * 10), Some(36));
+        assert_eq!(fade.volume_at(start + FADE_OUT - step), Some(0));
+        assert_eq!(fade.next_step(start + FADE_OUT - step), start + FADE_OUT);
+        assert_eq!(fade.volume_at(start + FADE_OUT), None);
+    }
 
     #[test]
     fn resolve_volume_mode_auto_with_hardware() {
@@ -1662,6 +4622,112 @@ mod tests {
         assert_eq!(hardware.player_create_state(), (100, false));
     }
 
+    #[test]
+    fn playout_estimate_counts_down_buffered_audio() {
+        let start = Instant::now();
+        let mut playout = PlayoutEstimate::default();
+        assert_eq!(playout.remaining(start), Duration::ZERO);
+
+        // Two seconds of 48 kHz audio arrive up front
+        playout.record_chunk(start, 48_000, 48_000);
+        playout.record_chunk(start, 48_000, 48_000);
+        assert_eq!(playout.remaining(start), Duration::from_secs(2));
+        assert_eq!(
+            playout.remaining(start + Duration::from_millis(500)),
+            Duration::from_millis(1_500)
+        );
+        // Never negative once everything has played
+        assert_eq!(
+            playout.remaining(start + Duration::from_secs(10)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn idle_player_waits_for_a_draining_stream() {
+        let last_audio = Instant::now();
+        let idle = Duration::from_secs(30);
+        assert_eq!(idle_deadline(last_audio, idle, None), last_audio + idle);
+        let drained = last_audio + Duration::from_secs(45);
+        assert_eq!(idle_deadline(last_audio, idle, Some(drained)), drained);
+        let drained = last_audio + Duration::from_secs(5);
+        assert_eq!(
+            idle_deadline(last_audio, idle, Some(drained)),
+            last_audio + idle
+        );
+    }
+
+    #[test]
+    fn playback_restarts_are_limited_within_the_window() {
+        let start = Instant::now();
+        let mut budget = RestartBudget::new();
+        assert!(budget.allow(start));
+        assert!(budget.allow(start + Duration::from_secs(10)));
+        assert!(budget.allow(start + Duration::from_secs(20)));
+        assert!(!budget.allow(start + Duration::from_secs(30)));
+        // The first restart has left the window
+        assert!(budget.allow(start + Duration::from_secs(61)));
+        assert!(!budget.allow(start + Duration::from_secs(62)));
+    }
+
+    #[test]
+    fn playback_exit_reason_carries_the_panic_message() {
+        let joined = thread::spawn(|| panic!("device callback failed")).join();
+        assert_eq!(
+            playback_exit_reason(joined),
+            "panicked: device callback failed"
+        );
+        let joined = thread::spawn(|| panic!("{} failed", "stream")).join();
+        assert_eq!(playback_exit_reason(joined), "panicked: stream failed");
+        assert_eq!(playback_exit_reason(Ok(())), "exited");
+    }
+
+    #[test]
+    fn busy_retries_back_off_up_to_a_limit() {
+        let mut retry = BusyRetry::new(AudioFormat {
+            codec: Codec::Pcm,
+            sample_rate: 48_000,
+            channels: 2,
+            bit_depth: 16,
+            codec_header: None,
+        });
+        assert_eq!(retry.backoff, BUSY_RETRY_FIRST);
+        let mut waits = Vec::new();
+        for _ in 0..6 {
+            retry.retry_later();
+            waits.push(retry.backoff.as_millis());
+        }
+        assert_eq!(waits, [1_000, 2_000, 4_000, 5_000, 5_000, 5_000]);
+        assert!(retry.next > Instant::now() + Duration::from_secs(4));
+    }
+
+    #[test]
+    fn same_pcm_format_compares_stream_parameters() {
+        let format = |sample_rate, channels, bit_depth| AudioFormat {
+            codec: Codec::Pcm,
+            sample_rate,
+            channels,
+            bit_depth,
+            codec_header: None,
+        };
+        assert!(same_pcm_format(
+            &format(48_000, 2, 16),
+            &format(48_000, 2, 16)
+        ));
+        assert!(!same_pcm_format(
+            &format(48_000, 2, 16),
+            &format(44_100, 2, 16)
+        ));
+        assert!(!same_pcm_format(
+            &format(48_000, 2, 16),
+            &format(48_000, 2, 24)
+        ));
+        assert!(!same_pcm_format(
+            &format(48_000, 2, 16),
+            &format(48_000, 1, 16)
+        ));
+    }
+
     #[test]
     fn auth_response_validation_requires_explicit_success() {
         assert!(validate_auth_response(r#"{"success":true}"#).is_ok());
@@ -1677,9 +4743,24 @@ mod tests {
         assert!(validate_auth_response("not json").is_err());
     }
 
+    #[test]
+    fn auth_rejections_are_distinct_from_bad_responses() {
+        let is_rejection = |text: &str| {
+            matches!(
+                validate_auth_response(text)
+                    .unwrap_err()
+                    .downcast_ref::<SendspinError>(),
+                Some(SendspinError::Auth(_))
+            )
+        };
+        assert!(is_rejection(r#"{"error":"token expired"}"#));
+        assert!(is_rejection(r#"{"type":"auth_error"}"#));
+        assert!(!is_rejection("not json"));
+    }
+
     #[test]
     fn test_build_volume_state_msg_produces_client_state() {
-        let msg = build_volume_state_msg(75, false);
+        let msg = build_volume_state_msg(ClientSyncState::Synchronized, 75, false);
         let value = serde_json::to_value(&msg).unwrap();
 
         assert_eq!(value["type"], "client/state");
@@ -1690,16 +4771,28 @@ mod tests {
 
     #[test]
     fn test_build_volume_state_msg_muted() {
-        let msg = build_volume_state_msg(0, true);
+        let msg = build_volume_state_msg(ClientSyncState::Synchronized, 0, true);
         let value = serde_json::to_value(&msg).unwrap();
 
         assert_eq!(value["payload"]["player"]["volume"], 0);
         assert_eq!(value["payload"]["player"]["muted"], true);
     }
 
+    #[test]
+    fn sync_state_msg_reports_errors() {
+        let mut reported = ReportedState::default();
+        reported.set(Problem::DeviceLost, true);
+        let msg = build_sync_state_msg(client_sync_state(&reported));
+        let value = serde_json::to_value(&msg).unwrap();
+
+        assert_eq!(value["type"], "client/state");
+        assert_eq!(value["payload"]["state"], "error");
+        assert!(value["payload"].get("player").is_none());
+    }
+
     #[test]
     fn test_build_static_delay_state_msg_produces_client_state() {
-        let msg = build_static_delay_state_msg(250);
+        let msg = build_static_delay_state_msg(ClientSyncState::Synchronized, 250);
         let value = serde_json::to_value(&msg).unwrap();
 
         assert_eq!(value["type"], "client/state");
@@ -1711,7 +4804,7 @@ mod tests {
 
     #[test]
     fn initial_player_state_omits_volume_when_volume_control_disabled() {
-        let state = build_initial_player_state(ResolvedVolumeMode::None, 42, true, 123);
+        let state = build_initial_player_state(ResolvedVolumeMode::None, 42, true, 123, false);
 
         assert_eq!(state.volume, None);
         assert_eq!(state.muted, None);
@@ -1726,12 +4819,121 @@ mod tests {
 
     #[test]
     fn initial_player_state_reports_volume_when_available() {
-        let state = build_initial_player_state(ResolvedVolumeMode::Software, 42, true, 123);
+        let state = build_initial_player_state(ResolvedVolumeMode::Software, 42, true, 123, false);
 
         assert_eq!(state.volume, Some(42));
         assert_eq!(state.muted, Some(true));
     }
 
+    #[test]
+    fn initial_player_state_asks_for_more_buffer_on_bluetooth() {
+        let state = build_initial_player_state(ResolvedVolumeMode::Software, 42, true, 0, true);
+
+        assert_eq!(
+            state.required_lead_time_ms,
+            Some(BLUETOOTH_REQUIRED_LEAD_TIME_MS)
+        );
+        assert_eq!(state.min_buffer_ms, Some(BLUETOOTH_MIN_BUFFER_MS));
+    }
+
+    #[test]
+    fn idle_manager_reports_no_client() {
+        let manager = SendspinManager::new();
+        assert_eq!(manager.get_status(), ConnectionStatus::Disconnected);
+        assert_eq!(manager.get_player_id(), None);
+        assert!(!manager.is_enabled());
+        assert!(manager.send_command(PlaybackCommand::Play).is_err());
+        assert!(manager.set_static_delay(100).is_ok());
+    }
+
+    #[test]
+    fn manager_clones_share_state() {
+        let manager = SendspinManager::new();
+        manager.clone().set_enabled(true);
+        assert!(manager.is_enabled());
+    }
+
+    #[test]
+    fn status_keeps_last_error_until_connected() {
+        let client = SendspinClient::new(true);
+        *client.inner.client.write() = Some(SendspinClientHandle::new(test_config()));
+
+        let refused = SendspinError::Connection("refused".to_string());
+        client.update_status(ConnectionStatus::Error(refused.clone()));
+        client.set_status(ConnectionStatus::Reconnecting, Some(3));
+        let info = client.info().unwrap();
+        assert_eq!(info.status, ConnectionStatus::Reconnecting);
+        assert_eq!(info.error, Some(refused));
+
+        client.update_status(ConnectionStatus::Connected);
+        let info = client.info().unwrap();
+        assert_eq!(info.error, None);
+        assert_eq!(
+            client.inner.client.read().as_ref().unwrap().retry_in_secs,
+            None
+        );
+    }
+
+    #[test]
+    fn idle_manager_lists_no_players() {
+        let manager = SendspinManager::new();
+        assert!(manager.players().is_empty());
+        assert!(manager
+            .send_command_to(Some("ma_companion_other"), PlaybackCommand::Play)
+            .is_err());
+    }
+
+    fn test_config() -> SendspinConfig {
+        SendspinConfig {
+            player_id: "main".to_string(),
+            player_name: "Desk".to_string(),
+            server_url: "ws://ma.local:8095/sendspin".to_string(),
+            audio_device_id: Some("speakers".to_string()),
+            sync_delay_ms: 120,
+            mirror_device_id: None,
+            mirror_delay_ms: 0,
+            auth_token: "token".to_string(),
+            app_version: "1.0.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn additional_player_config_shares_server_details() {
+        let base = test_config();
+        let player = crate::settings::AdditionalPlayer {
+            player_id: "extra".to_string(),
+            player_name: "Headphones".to_string(),
+            audio_device_id: Some("headphones".to_string()),
+            sync_delay_ms: 0,
+            software_volume: 40,
+            muted: false,
+            channel_mix: Default::default(),
+        };
+
+        let config = additional_player_config(&base, &player);
+        assert!(!same_except_live_settings(&base, &config));
+        assert!(same_except_live_settings(
+            &base,
+            &SendspinConfig {
+                sync_delay_ms: 0,
+                ..base.clone()
+            }
+        ));
+        assert!(same_except_live_settings(
+            &base,
+            &SendspinConfig {
+                player_name: "Kitchen".to_string(),
+                ..base.clone()
+            }
+        ));
+        assert_eq!(config.player_id, "extra");
+        assert_eq!(config.player_name, "Headphones");
+        assert_eq!(config.audio_device_id.as_deref(), Some("headphones"));
+        assert_eq!(config.sync_delay_ms, 0);
+        assert_eq!(config.server_url, base.server_url);
+        assert_eq!(config.auth_token, base.auth_token);
+    }
+
     #[test]
     fn static_delay_is_clamped_to_protocol_range() {
         assert_eq!(clamp_static_delay_ms(-1), 0);
@@ -1742,6 +4944,14 @@ mod tests {
         assert_eq!(clamp_static_delay_ms(6_000), 5_000);
     }
 
+    #[test]
+    fn mirror_delay_holds_back_whichever_device_plays_later() {
+        assert_eq!(mirror_delays(100, 0), (100, 100));
+        assert_eq!(mirror_delays(100, 250), (100, 350));
+        assert_eq!(mirror_delays(100, -250), (350, 100));
+        assert_eq!(mirror_delays(u16::MAX, 10), (u16::MAX, u16::MAX));
+    }
+
     #[test]
     fn player_support_preserves_formats_capacity_and_commands() {
         let formats = vec![AudioFormatSpec {
@@ -1774,6 +4984,8 @@ mod tests {
             server_url: "ws://localhost/sendspin".to_string(),
             audio_device_id: None,
             sync_delay_ms: 0,
+            mirror_device_id: None,
+            mirror_delay_ms: 0,
             auth_token: "token".to_string(),
             app_version: "9.9.9".to_string(),
         };
@@ -1784,7 +4996,8 @@ mod tests {
             bit_depth: 16,
         }];
         let player_support = build_player_support(formats.clone(), vec!["volume".to_string()]);
-        let initial_state = build_initial_player_state(ResolvedVolumeMode::Software, 100, false, 0);
+        let initial_state =
+            build_initial_player_state(ResolvedVolumeMode::Software, 100, false, 0, false);
 
         let builder = build_protocol_client_builder(&config, player_support, initial_state);
 
@@ -1816,4 +5029,160 @@ mod tests {
         assert_eq!(advertised.buffer_capacity, PLAYER_BUFFER_CAPACITY);
         assert_eq!(advertised.supported_commands, vec!["volume".to_string()]);
     }
+
+    #[test]
+    fn backend_address_defaults_to_the_ma_host() {
+        assert_eq!(
+            backend_address(None, "ws://192.168.1.5:8095/sendspin", 1704),
+            Ok(("192.168.1.5".to_string(), 1704))
+        );
+        assert_eq!(
+            backend_address(Some(" "), "wss://ma.example.com/sendspin", 3483),
+            Ok(("ma.example.com".to_string(), 3483))
+        );
+    }
+
+    #[test]
+    fn backend_address_takes_an_optional_port() {
+        let url = "ws://ma.local:8095/sendspin";
+        assert_eq!(
+            backend_address(Some("snapserver.local"), url, 1704),
+            Ok(("snapserver.local".to_string(), 1704))
+        );
+        assert_eq!(
+            backend_address(Some("10.0.0.2:1800"), url, 1704),
+            Ok(("10.0.0.2".to_string(), 1800))
+        );
+        assert_eq!(
+            backend_address(Some("[fd00::2]:1800"), url, 1704),
+            Ok(("fd00::2".to_string(), 1800))
+        );
+        assert_eq!(
+            backend_address(Some("fd00::2"), url, 1704),
+            Ok(("fd00::2".to_string(), 1704))
+        );
+        assert!(backend_address(Some("snapserver.local:snap"), url, 1704).is_err());
+    }
+
+    #[test]
+    fn client_plays_a_stream_from_the_mock_server() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await.unwrap();
+            let instance = SendspinClient::new(false);
+            instance
+                .start(server.config("ma_companion_mock"))
+                .await
+                .unwrap();
+            let mut connection = server.accept().await.unwrap();
+            assert_eq!(
+                connection.hello["payload"]["client_id"],
+                "ma_companion_mock"
+            );
+            assert!(eventually(|| instance.get_status() == ConnectionStatus::Connected).await);
+
+            connection.group_update("playing").unwrap();
+            connection
+                .metadata(json!({ "timestamp": 0, "title": "So What", "artist": "Miles Davis" }))
+                .unwrap();
+            connection.stream_start(48_000, 2, 16).unwrap();
+            let stats = || instance.stats().unwrap().buffer;
+            assert!(eventually(|| stats().streams_started == 1).await);
+            // 20 ms chunks of silence, the first due in 300 ms
+            let first = connection.now_us() + 300_000;
+            for i in 0..10 {
+                connection
+                    .audio_chunk(first + i * 20_000, &[0; 3840])
+                    .unwrap();
+            }
+            connection.stream_end().unwrap();
+            assert!(eventually(|| stats().chunks_received == 10).await);
+            assert_eq!(stats().dropped_chunks, 0);
+            let now_playing = instance.inner.now_playing.read().clone().unwrap();
+            assert!(now_playing.is_playing);
+            assert_eq!(now_playing.track.as_deref(), Some("So What"));
+
+            connection
+                .send(json!({
+                    "type": "server/command",
+                    "payload": { "player": { "command": "volume", "volume": 30 } },
+                }))
+                .unwrap();
+            loop {
+                let state = connection.expect("client/state").await.unwrap();
+                if state["payload"]["player"]["volume"] == 30 {
+                    break;
+                }
+            }
+
+            instance.stop().await;
+            assert_eq!(instance.get_status(), ConnectionStatus::Disconnected);
+        });
+    }
+
+    #[test]
+    fn client_reports_synchronized_once_a_stream_that_ran_dry_is_cleared() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await.unwrap();
+            let instance = SendspinClient::new(false);
+            instance
+                .start(server.config("ma_companion_mock_underrun"))
+                .await
+                .unwrap();
+            let mut connection = server.accept().await.unwrap();
+            let synchronized = || {
+                instance
+                    .stats()
+                    .and_then(|stats| stats.clock)
+                    .is_some_and(|clock| clock.synchronized)
+            };
+            assert!(eventually(synchronized).await);
+
+            connection.group_update("playing").unwrap();
+            connection.stream_start(48_000, 2, 16).unwrap();
+            let stats = || instance.stats().unwrap().buffer;
+            assert!(eventually(|| stats().streams_started == 1).await);
+            // 20 ms of audio, long played out by the time the next arrives
+            let first = connection.now_us() + 300_000;
+            connection.audio_chunk(first, &[0; 3840]).unwrap();
+            assert!(eventually(|| stats().chunks_received == 1).await);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            connection.audio_chunk(first + 20_000, &[0; 3840]).unwrap();
+            loop {
+                let state = connection.expect("client/state").await.unwrap();
+                if state["payload"]["state"] == "error" {
+                    break;
+                }
+            }
+
+            connection.stream_clear().unwrap();
+            loop {
+                let state = connection.expect("client/state").await.unwrap();
+                if state["payload"]["state"] == "synchronized" {
+                    break;
+                }
+            }
+
+            instance.stop().await;
+        });
+    }
+
+    #[test]
+    fn client_stops_retrying_when_the_mock_server_rejects_its_token() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await.unwrap();
+            let instance = SendspinClient::new(false);
+            let mut config = server.config("ma_companion_mock_rejected");
+            config.auth_token = "expired".to_string();
+            instance.start(config).await.unwrap();
+            assert!(server.accept().await.is_err());
+            assert!(
+                eventually(|| matches!(
+                    instance.get_status(),
+                    ConnectionStatus::Error(SendspinError::Auth(_))
+                ))
+                .await
+            );
+            instance.stop().await;
+        });
+    }
 }
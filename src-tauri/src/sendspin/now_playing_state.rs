@@ -9,17 +9,24 @@
 //! - `group/update.playback_state` is the **only** authoritative play/stop
 //!   signal. `stream/end` arrives late mid-transition and must not touch
 //!   now-playing state.
+//!
+//! Progress ticks are sparse, so the position is extrapolated from the last
+//! tick at the reported playback speed while playing.
 
-use crate::now_playing::NowPlaying;
-use sendspin::protocol::messages::{GroupUpdate, MetadataState, PlaybackState};
+use crate::now_playing::{NowPlaying, QueueItem, RepeatMode};
+use sendspin::protocol::messages::{ControllerState, GroupUpdate, MetadataState, PlaybackState};
+use std::time::Instant;
 
 /// Server progress fields are milliseconds; `NowPlaying` is seconds.
 const MILLIS_PER_SEC: f64 = 1000.0;
+/// `playback_speed` is reported in thousandths (1000 = normal speed).
+const SPEED_SCALE: f64 = 1000.0;
 
 /// Folds protocol messages into a coherent now-playing view.
 ///
 /// `is_playing` is driven exclusively by `group/update`; metadata fields are
 /// merged from `server/state` deltas.
+#[derive(Clone)]
 pub struct NowPlayingState {
     player_id: String,
     player_name: String,
@@ -28,8 +35,25 @@ pub struct NowPlayingState {
     artist: Option<String>,
     album: Option<String>,
     image_url: Option<String>,
+    /// Cache key of artwork the server pushed over the connection; takes
+    /// precedence over `image_url`
+    pushed_artwork: Option<String>,
     duration: Option<f64>,
+    /// Position at `progress_at`, in seconds
     elapsed: Option<f64>,
+    /// When `elapsed` was last reported (or set by a seek)
+    progress_at: Option<Instant>,
+    /// Playback speed as a multiple of real time
+    playback_speed: f64,
+    /// Track queued after this one, looked up from the server's queue as
+    /// `server/state` doesn't carry it
+    next: Option<QueueItem>,
+    shuffle: Option<bool>,
+    repeat: Option<RepeatMode>,
+    group_name: Option<String>,
+    /// Group volume and mute, as the controller role reports them
+    group_volume: Option<u8>,
+    group_muted: Option<bool>,
 }
 
 impl NowPlayingState {
@@ -42,17 +66,56 @@ impl NowPlayingState {
             artist: None,
             album: None,
             image_url: None,
+            pushed_artwork: None,
             duration: None,
             elapsed: None,
+            progress_at: None,
+            playback_speed: 1.0,
+            next: None,
+            shuffle: None,
+            repeat: None,
+            group_name: None,
+            group_volume: None,
+            group_muted: None,
         }
     }
 
     /// Apply a `group/update`. Only `playback_state` is authoritative for
     /// play/stop; an update without it leaves state untouched.
     pub fn apply_group_update(&mut self, gu: &GroupUpdate) {
+        self.apply_group_update_at(gu, Instant::now());
+    }
+
+    fn apply_group_update_at(&mut self, gu: &GroupUpdate, now: Instant) {
         if let Some(ps) = &gu.playback_state {
-            self.is_playing = matches!(ps, PlaybackState::Playing);
+            self.set_playing_at(matches!(ps, PlaybackState::Playing), now);
+        }
+        if let Some(group_name) = &gu.group_name {
+            self.group_name = Some(group_name.clone());
+        }
+    }
+
+    /// Start or stop the position advancing. The position reached so far is
+    /// kept as the new starting point, so it neither jumps back to the last
+    /// progress tick on a pause nor forward by the paused time on resume.
+    fn set_playing_at(&mut self, playing: bool, now: Instant) {
+        if playing == self.is_playing {
+            return;
+        }
+        if self.elapsed.is_some() {
+            self.elapsed = self.position_at(now);
+            self.progress_at = Some(now);
         }
+        self.is_playing = playing;
+    }
+
+    /// Take the group's volume and mute from the `controller` state of a
+    /// `server/state`. Returns whether either changed.
+    pub fn apply_controller_state(&mut self, controller: &ControllerState) -> bool {
+        let before = (self.group_volume, self.group_muted);
+        self.group_volume = Some(controller.volume.min(100));
+        self.group_muted = Some(controller.muted);
+        (self.group_volume, self.group_muted) != before
     }
 
     /// Merge a `server/state` metadata delta: a present field overwrites, an
@@ -61,6 +124,7 @@ impl NowPlayingState {
     /// keep, since the server signals clears via `group/update: Stopped`, not
     /// null titles.
     pub fn apply_metadata(&mut self, md: &MetadataState) {
+        let identity = self.track_identity();
         if let Some(title) = &md.title {
             self.title = Some(title.clone());
         }
@@ -76,12 +140,109 @@ impl NowPlayingState {
         if let Some(p) = &md.progress {
             // Don't crash on negative values
             self.elapsed = Some(p.track_progress.max(0) as f64 / MILLIS_PER_SEC);
+            self.progress_at = Some(Instant::now());
+            self.playback_speed = (p.playback_speed as f64 / SPEED_SCALE).max(0.0);
             // 0 = live/unknown stream (no finite length). Represent as absent
             // rather than a bogus zero-length track so the UI can show
             // elapsed-only instead of a 0:00/0:00 progress bar.
             self.duration =
                 (p.track_duration > 0).then(|| p.track_duration as f64 / MILLIS_PER_SEC);
         }
+        // Read by their protocol names, whatever types sendspin-rs gives them
+        if let Ok(fields) = serde_json::to_value(md) {
+            if let Some(shuffle) = fields.get("shuffle").and_then(serde_json::Value::as_bool) {
+                self.shuffle = Some(shuffle);
+            }
+            if let Some(repeat) = fields
+                .get("repeat")
+                .and_then(serde_json::Value::as_str)
+                .and_then(RepeatMode::parse)
+            {
+                self.repeat = Some(repeat);
+            }
+        }
+        // What was next is likely playing now
+        if self.track_identity() != identity {
+            self.next = None;
+        }
+    }
+
+    /// Whether the group is playing, as of the last `group/update`.
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    /// What identifies the current track, for keying its pushed artwork
+    pub fn track_identity(&self) -> String {
+        [&self.title, &self.artist, &self.album]
+            .map(|field| field.as_deref().unwrap_or_default())
+            .join("\n")
+    }
+
+    pub fn set_player_name(&mut self, player_name: String) {
+        self.player_name = player_name;
+    }
+
+    /// Show the artwork the server pushed, stored under `key`, or go back to
+    /// the metadata's artwork URL with `None` when the server cleared it.
+    pub fn set_pushed_artwork(&mut self, key: Option<String>) {
+        self.pushed_artwork = key;
+    }
+
+    /// Set the track queued after the current one
+    pub fn set_next(&mut self, next: Option<QueueItem>) {
+        self.next = next;
+    }
+
+    /// Record a shuffle change ahead of the server's confirmation, so the
+    /// tray doesn't flip back in the meantime.
+    pub fn set_shuffle(&mut self, enabled: bool) {
+        self.shuffle = Some(enabled);
+    }
+
+    /// Record a repeat change ahead of the server's confirmation.
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = Some(mode);
+    }
+
+    /// Record a seek to `position_ms` ahead of the server's next progress
+    /// tick, so the scrub bar doesn't jump back in the meantime.
+    pub fn seek(&mut self, position_ms: u64) {
+        let position = position_ms as f64 / MILLIS_PER_SEC;
+        self.elapsed = Some(self.duration.map_or(position, |d| position.min(d)));
+        self.progress_at = Some(Instant::now());
+    }
+
+    /// Record a seek by `offset_ms` from the current position, like
+    /// [`seek`](Self::seek). Does nothing while the position is unknown.
+    pub fn seek_by(&mut self, offset_ms: i64) {
+        let Some(position) = self.position_at(Instant::now()) else {
+            return;
+        };
+        let position_ms = (position * MILLIS_PER_SEC) as i64 + offset_ms;
+        self.seek(position_ms.max(0) as u64);
+    }
+
+    /// Extrapolated position now, in seconds. This is the one place the
+    /// position is carried forward between progress ticks; published states
+    /// are read through it (see [`now_playing::PositionSource`]).
+    ///
+    /// [`now_playing::PositionSource`]: crate::now_playing::PositionSource
+    pub fn position(&self) -> Option<f64> {
+        self.position_at(Instant::now())
+    }
+
+    /// Extrapolated position at `now`, in seconds, capped at the duration.
+    fn position_at(&self, now: Instant) -> Option<f64> {
+        let elapsed = self.elapsed?;
+        let played = match self.progress_at {
+            Some(at) if self.is_playing => {
+                now.saturating_duration_since(at).as_secs_f64() * self.playback_speed
+            }
+            _ => 0.0,
+        };
+        let position = elapsed + played;
+        Some(self.duration.map_or(position, |d| position.min(d)))
     }
 
     /// Render the current accumulated state as a [`NowPlaying`] for the UI/tray.
@@ -91,11 +252,20 @@ impl NowPlayingState {
             track: self.title.clone(),
             artist: self.artist.clone(),
             album: self.album.clone(),
-            image_url: self.image_url.clone(),
+            image_url: self
+                .pushed_artwork
+                .clone()
+                .or_else(|| self.image_url.clone()),
             player_name: Some(self.player_name.clone()),
             player_id: Some(self.player_id.clone()),
             duration: self.duration,
-            elapsed: self.elapsed,
+            elapsed: self.position(),
+            next: self.next.clone(),
+            shuffle: self.shuffle,
+            repeat: self.repeat,
+            group_name: self.group_name.clone(),
+            group_volume: self.group_volume,
+            group_muted: self.group_muted,
             can_play: !self.is_playing,
             can_pause: self.is_playing,
             can_next: true,
@@ -122,12 +292,16 @@ mod tests {
 
     /// Empty metadata delta with only `timestamp` and `progress` set.
     fn progress_delta(progress_ms: i64, duration_ms: i64) -> MetadataState {
+        progress_delta_at_speed(progress_ms, duration_ms, 1000)
+    }
+
+    fn progress_delta_at_speed(progress_ms: i64, duration_ms: i64, speed: i64) -> MetadataState {
         metadata_from_json(serde_json::json!({
             "timestamp": 0,
             "progress": {
                 "track_progress": progress_ms,
                 "track_duration": duration_ms,
-                "playback_speed": 1000,
+                "playback_speed": speed,
             },
         }))
     }
@@ -188,6 +362,92 @@ mod tests {
         assert_eq!(snap.duration, Some(210.0));
     }
 
+    #[test]
+    fn position_advances_only_while_playing() {
+        let mut s = state();
+        s.apply_metadata(&progress_delta(30_000, 210_000));
+        let at = s.progress_at.unwrap();
+        let later = at + std::time::Duration::from_secs(2);
+
+        assert_eq!(s.position_at(later), Some(30.0), "paused position holds");
+
+        s.apply_group_update_at(&group_update(PlaybackState::Playing), at);
+        assert_eq!(s.position_at(later), Some(32.0));
+    }
+
+    #[test]
+    fn pause_and_resume_keep_the_position_reached() {
+        let mut s = state();
+        s.apply_metadata(&progress_delta(30_000, 210_000));
+        let at = s.progress_at.unwrap();
+        let secs = std::time::Duration::from_secs;
+        s.apply_group_update_at(&group_update(PlaybackState::Playing), at);
+
+        // Paused after 5 s of playing
+        s.apply_group_update_at(&group_update(PlaybackState::Stopped), at + secs(5));
+        assert_eq!(s.position_at(at + secs(5)), Some(35.0));
+        assert_eq!(
+            s.position_at(at + secs(65)),
+            Some(35.0),
+            "holds through the pause"
+        );
+
+        // Resumed a minute later, then 3 s of playing
+        s.apply_group_update_at(&group_update(PlaybackState::Playing), at + secs(65));
+        assert_eq!(s.position_at(at + secs(65)), Some(35.0));
+        assert_eq!(s.position_at(at + secs(68)), Some(38.0));
+
+        // A repeated update doesn't move the starting point
+        s.apply_group_update_at(&group_update(PlaybackState::Playing), at + secs(68));
+        assert_eq!(s.position_at(at + secs(70)), Some(40.0));
+    }
+
+    #[test]
+    fn position_follows_playback_speed_and_caps_at_duration() {
+        let mut s = state();
+        s.apply_group_update(&group_update(PlaybackState::Playing));
+        s.apply_metadata(&progress_delta_at_speed(10_000, 12_000, 500));
+        let at = s.progress_at.unwrap();
+
+        assert_eq!(
+            s.position_at(at + std::time::Duration::from_secs(2)),
+            Some(11.0)
+        );
+        assert_eq!(
+            s.position_at(at + std::time::Duration::from_secs(60)),
+            Some(12.0)
+        );
+    }
+
+    #[test]
+    fn seek_moves_position_until_next_tick() {
+        let mut s = state();
+        s.apply_metadata(&progress_delta(30_000, 210_000));
+        s.seek(90_000);
+        assert_eq!(s.snapshot().elapsed, Some(90.0));
+
+        s.seek(500_000);
+        assert_eq!(s.snapshot().elapsed, Some(210.0), "seek clamps to duration");
+
+        s.apply_metadata(&progress_delta(91_000, 210_000));
+        assert_eq!(s.snapshot().elapsed, Some(91.0));
+    }
+
+    #[test]
+    fn seek_by_is_relative_and_stays_within_the_track() {
+        let mut s = state();
+        s.seek_by(10_000);
+        assert_eq!(s.snapshot().elapsed, None, "position unknown");
+
+        s.apply_metadata(&progress_delta(30_000, 210_000));
+        s.seek_by(10_000);
+        assert_eq!(s.snapshot().elapsed, Some(40.0));
+        s.seek_by(-60_000);
+        assert_eq!(s.snapshot().elapsed, Some(0.0));
+        s.seek_by(300_000);
+        assert_eq!(s.snapshot().elapsed, Some(210.0));
+    }
+
     #[test]
     fn group_update_drives_is_playing() {
         let mut s = state();
@@ -229,6 +489,96 @@ mod tests {
         assert_eq!(snap.track.as_deref(), Some(TITLE));
     }
 
+    #[test]
+    fn pushed_artwork_overrides_the_artwork_url() {
+        let mut s = state();
+        s.apply_metadata(&metadata_from_json(serde_json::json!({
+            "timestamp": 0,
+            "artwork_url": "http://ma/imageproxy?path=cover",
+        })));
+        s.set_pushed_artwork(Some("sendspin-artwork://player-1/abc".to_string()));
+        assert_eq!(
+            s.snapshot().image_url.as_deref(),
+            Some("sendspin-artwork://player-1/abc")
+        );
+
+        s.set_pushed_artwork(None);
+        assert_eq!(
+            s.snapshot().image_url.as_deref(),
+            Some("http://ma/imageproxy?path=cover")
+        );
+    }
+
+    #[test]
+    fn track_identity_follows_the_track() {
+        let mut s = state();
+        s.apply_metadata(&track_delta(TITLE, ARTIST));
+        let first = s.track_identity();
+        s.apply_metadata(&progress_delta(1_000, 210_000));
+        assert_eq!(s.track_identity(), first, "progress ticks don't change it");
+        s.apply_metadata(&track_delta("Another", ARTIST));
+        assert_ne!(s.track_identity(), first);
+    }
+
+    #[test]
+    fn next_track_lasts_until_the_track_changes() {
+        let mut s = state();
+        s.apply_metadata(&track_delta(TITLE, ARTIST));
+        let next = QueueItem {
+            track: Some("Another".to_string()),
+            ..QueueItem::default()
+        };
+        s.set_next(Some(next.clone()));
+        s.apply_metadata(&progress_delta(1_000, 210_000));
+        assert_eq!(s.snapshot().next, Some(next));
+        s.apply_metadata(&track_delta("Another", ARTIST));
+        assert_eq!(s.snapshot().next, None);
+    }
+
+    #[test]
+    fn shuffle_and_repeat_merge_like_other_fields() {
+        let mut s = state();
+        assert_eq!(s.snapshot().shuffle, None);
+        s.apply_metadata(&metadata_from_json(serde_json::json!({
+            "timestamp": 0,
+            "shuffle": true,
+            "repeat": "all",
+        })));
+        s.apply_metadata(&progress_delta(1_000, 210_000));
+        let snap = s.snapshot();
+        assert_eq!(snap.shuffle, Some(true));
+        assert_eq!(snap.repeat, Some(RepeatMode::All));
+
+        s.set_repeat(RepeatMode::One);
+        s.apply_metadata(&metadata_from_json(serde_json::json!({
+            "timestamp": 0,
+            "shuffle": false,
+        })));
+        let snap = s.snapshot();
+        assert_eq!(snap.shuffle, Some(false));
+        assert_eq!(snap.repeat, Some(RepeatMode::One));
+    }
+
+    fn controller_state(volume: u8, muted: bool) -> ControllerState {
+        serde_json::from_value(serde_json::json!({
+            "supported_commands": ["volume", "mute"],
+            "volume": volume,
+            "muted": muted,
+        }))
+        .expect("test controller state JSON should deserialize")
+    }
+
+    #[test]
+    fn group_volume_comes_from_the_controller_state() {
+        let mut s = state();
+        assert!(s.apply_controller_state(&controller_state(45, false)));
+        assert!(!s.apply_controller_state(&controller_state(45, false)));
+        assert!(s.apply_controller_state(&controller_state(45, true)));
+        let snap = s.snapshot();
+        assert_eq!(snap.group_volume, Some(45));
+        assert_eq!(snap.group_muted, Some(true));
+    }
+
     #[test]
     fn snapshot_carries_player_identity() {
         let snap = state().snapshot();
@@ -0,0 +1,116 @@
+//! Null output
+//!
+//! An output device that plays nothing, for CI, containers and servers
+//! without a sound card. It is listed with the real devices and chosen the
+//! same way. Audio sent to it is dropped as it arrives but counted as
+//! playing in real time, so a stream that ends drains for as long as its
+//! tail would have played and the server still sees a player keeping pace.
+//! cpal is never touched.
+
+use super::devices::AudioDevice;
+use super::transport::Transport;
+use sendspin::audio::{AudioBuffer, AudioFormat};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// ID of the null output in the device list and the settings
+pub const DEVICE_ID: &str = "null";
+
+/// Rates listed for the null output; it takes any
+const SAMPLE_RATES: [u32; 7] = [44_100, 48_000, 88_200, 96_000, 176_400, 192_000, 384_000];
+
+/// The null output's entry in the device list
+pub fn device() -> AudioDevice {
+    AudioDevice {
+        id: DEVICE_ID.to_string(),
+        name: crate::i18n::tr("desktop.settings.no_audio_output"),
+        is_default: false,
+        sample_rates: SAMPLE_RATES.to_vec(),
+        max_channels: 8,
+        channel_counts: (1..=8).collect(),
+        bit_depths: vec![16, 24],
+        transport: Transport::Virtual,
+    }
+}
+
+/// Stands in for a player on an output device
+pub(crate) struct NullPlayer {
+    sample_rate: u32,
+    channels: u16,
+    /// When the audio taken so far will have played out
+    plays_until: Cell<Option<Instant>>,
+}
+
+impl NullPlayer {
+    pub(crate) fn new(format: &AudioFormat) -> Self {
+        Self {
+            sample_rate: format.sample_rate.max(1),
+            channels: format.channels.max(1),
+            plays_until: Cell::new(None),
+        }
+    }
+
+    pub(crate) fn enqueue(&self, buffer: &AudioBuffer) {
+        self.take(buffer.samples.len(), Instant::now());
+    }
+
+    /// Take `samples` interleaved samples at `now`, to play after what's
+    /// already queued
+    fn take(&self, samples: usize, now: Instant) {
+        let frames = samples / usize::from(self.channels);
+        let duration =
+            Duration::from_nanos(frames as u64 * 1_000_000_000 / u64::from(self.sample_rate));
+        let start = self
+            .plays_until
+            .get()
+            .filter(|until| *until > now)
+            .unwrap_or(now);
+        self.plays_until.set(Some(start + duration));
+    }
+
+    /// Drop what hasn't played yet; returns how much that was
+    pub(crate) fn clear(&self) -> Duration {
+        let buffered = self.buffered(Instant::now());
+        self.plays_until.set(None);
+        buffered
+    }
+
+    /// Audio still to play at `now`
+    fn buffered(&self, now: Instant) -> Duration {
+        self.plays_until
+            .get()
+            .map_or(Duration::ZERO, |until| until.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sendspin::audio::Codec;
+
+    #[test]
+    fn audio_plays_out_in_real_time() {
+        let player = NullPlayer::new(&AudioFormat {
+            codec: Codec::Pcm,
+            sample_rate: 48_000,
+            channels: 2,
+            bit_depth: 16,
+            codec_header: None,
+        });
+        let start = Instant::now();
+        // Two 20 ms chunks queue back to back
+        player.take(1920, start);
+        player.take(1920, start);
+        assert_eq!(player.buffered(start), Duration::from_millis(40));
+        assert_eq!(
+            player.buffered(start + Duration::from_millis(30)),
+            Duration::from_millis(10)
+        );
+        // After running dry, a chunk plays from when it arrives
+        let later = start + Duration::from_secs(1);
+        player.take(1920, later);
+        assert_eq!(player.buffered(later), Duration::from_millis(20));
+        player.clear();
+        assert_eq!(player.buffered(later), Duration::ZERO);
+    }
+}
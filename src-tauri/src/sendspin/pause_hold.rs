@@ -0,0 +1,366 @@
+//! Holding buffered audio across a pause.
+//!
+//! The server pauses by clearing the stream, which throws away everything
+//! the player had buffered, and resumes with a fresh stream from roughly
+//! where it thinks playback got to. Whatever was queued but not yet heard
+//! is lost in between, so the track comes back a beat late. Instead, the
+//! client keeps the last few seconds it sent to the player; on a clear it
+//! holds the part that hadn't played yet, going by the chunks' timestamps on
+//! the synchronized clock rather than by when they arrived, so audio the
+//! server sent ahead or a player that started late doesn't throw it off.
+//! When the same track resumes
+//! it plays that first, at the timestamp of the first resumed chunk, and
+//! skips the same amount of the new stream so the group stays in sync.
+//!
+//! Audio is kept as it goes to the player, after processing and resampling
+//! but before channel mapping.
+
+use super::pcm;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Most audio kept for a hold
+const MAX_HOLD: Duration = Duration::from_secs(10);
+/// Held audio is only resumed within this long of the pause
+const MAX_PAUSE: Duration = Duration::from_secs(30 * 60);
+/// Length of the fade back in at the start of the held audio
+const FADE_IN: Duration = Duration::from_millis(200);
+/// Size of the chunks held audio is queued in
+const RESUME_CHUNK: Duration = Duration::from_millis(100);
+
+/// Audio waiting for a resume
+#[derive(Debug)]
+struct Held {
+    pcm: Vec<u8>,
+    track: String,
+    at: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct PauseHold {
+    sample_rate: u32,
+    channels: u16,
+    bit_depth: u16,
+    /// Recently queued audio with its timestamps, oldest first
+    recent: VecDeque<(i64, Vec<u8>)>,
+    recent_bytes: usize,
+    held: Option<Held>,
+    /// Whether the server reported playback stopped since the clear; a clear
+    /// while still playing is a seek, and the held audio doesn't fit
+    paused: bool,
+    /// Frames of the resumed stream still to drop, already played from the
+    /// held audio
+    skip_frames: usize,
+    dither: pcm::Dither,
+}
+
+impl PauseHold {
+    pub(crate) fn new() -> Self {
+        Self {
+            sample_rate: 0,
+            channels: 0,
+            bit_depth: 0,
+            recent: VecDeque::new(),
+            recent_bytes: 0,
+            held: None,
+            paused: false,
+            skip_frames: 0,
+            dither: pcm::Dither::new(),
+        }
+    }
+
+    /// Set the format of the audio going to the player. Anything held in
+    /// another format is dropped.
+    pub(crate) fn set_format(&mut self, sample_rate: u32, channels: u16, bit_depth: u16) {
+        if (sample_rate, channels, bit_depth) != (self.sample_rate, self.channels, self.bit_depth) {
+            self.sample_rate = sample_rate;
+            self.channels = channels;
+            self.bit_depth = bit_depth;
+            self.discard();
+            self.forget();
+        }
+    }
+
+    fn frame_bytes(&self) -> usize {
+        pcm::bytes_per_sample(self.bit_depth).unwrap_or(0) * usize::from(self.channels)
+    }
+
+    fn bytes_for(&self, duration: Duration) -> usize {
+        let frames = duration.as_micros() * u128::from(self.sample_rate) / 1_000_000;
+        frames as usize * self.frame_bytes()
+    }
+
+    /// Stream cleared at `now` during `track`. Keep the queued audio that
+    /// hadn't played yet in case this is a pause, finding it with
+    /// `plays_at`, which maps a server timestamp to when it plays locally
+    /// (`None` while the clock isn't synchronized, when nothing is kept).
+    pub(crate) fn hold(
+        &mut self,
+        plays_at: impl Fn(i64) -> Option<Instant>,
+        now: Instant,
+        track: String,
+    ) {
+        let mut tail = Vec::new();
+        for (timestamp, chunk) in self.recent.iter().rev() {
+            let Some(start) = plays_at(*timestamp) else {
+                tail.clear();
+                break;
+            };
+            let played = self
+                .bytes_for(now.saturating_duration_since(start))
+                .min(chunk.len());
+            if played < chunk.len() {
+                tail.push(&chunk[played..]);
+            }
+            if played > 0 {
+                // Everything older has played too
+                break;
+            }
+        }
+        tail.reverse();
+        let pcm = tail.concat();
+        self.forget();
+        self.skip_frames = 0;
+        self.paused = false;
+        self.held = Some(Held {
+            pcm,
+            track,
+            at: Instant::now(),
+        });
+    }
+
+    /// Whether a clear is waiting for the stream to come back
+    pub(crate) fn is_holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// The server reported playback stopped.
+    pub(crate) fn mark_paused(&mut self) {
+        if self.held.is_some() {
+            self.paused = true;
+        }
+    }
+
+    /// Whether audio is coming back after a pause of `track` with something
+    /// to resume from. Drop the held audio if not.
+    pub(crate) fn resumes(&mut self, track: &str) -> bool {
+        let resumes = self.paused
+            && self.held.as_ref().is_some_and(|held| {
+                held.track == track && !held.pcm.is_empty() && held.at.elapsed() < MAX_PAUSE
+            });
+        if !resumes {
+            self.discard();
+        }
+        resumes
+    }
+
+    /// Forget anything held.
+    pub(crate) fn discard(&mut self) {
+        self.held = None;
+        self.paused = false;
+        self.skip_frames = 0;
+    }
+
+    fn forget(&mut self) {
+        self.recent.clear();
+        self.recent_bytes = 0;
+    }
+
+    /// Pass a chunk of audio for the player through, returning what to
+    /// queue in its place: the held audio first if this is where the stream
+    /// resumes, then whatever of the chunk the held audio doesn't cover.
+    /// That part is borrowed from `data`, so a chunk passing straight
+    /// through isn't copied.
+    pub(crate) fn admit<'a>(
+        &mut self,
+        timestamp: i64,
+        data: &'a [u8],
+    ) -> impl Iterator<Item = (i64, Cow<'a, [u8]>)> {
+        let frame_bytes = self.frame_bytes();
+        let mut resumed = Vec::new();
+        let mut rest = Some((timestamp, data));
+        if let Some(frames) = data.len().checked_div(frame_bytes) {
+            if let Some(held) = self.held.take() {
+                resumed = self.resume_at(timestamp, held.pcm);
+                self.skip_frames = resumed
+                    .iter()
+                    .map(|(_, chunk)| chunk.len() / frame_bytes)
+                    .sum();
+                self.paused = false;
+            }
+            let skip = self.skip_frames.min(frames);
+            self.skip_frames -= skip;
+            let data = &data[skip * frame_bytes..];
+            rest = (!data.is_empty()).then(|| {
+                let timestamp = timestamp + pcm::frames_to_micros(skip, self.sample_rate);
+                self.remember(timestamp, data);
+                (timestamp, data)
+            });
+        }
+        resumed
+            .into_iter()
+            .map(|(timestamp, data)| (timestamp, Cow::Owned(data)))
+            .chain(rest.map(|(timestamp, data)| (timestamp, Cow::Borrowed(data))))
+    }
+
+    /// Keep a copy of `data`, starting at `timestamp`, as the newest queued
+    /// audio, in the buffer of audio that ages out when there is one.
+    fn remember(&mut self, timestamp: i64, data: &[u8]) {
+        let limit = self.bytes_for(MAX_HOLD);
+        let mut copy = Vec::new();
+        while self
+            .recent
+            .front()
+            .is_some_and(|(_, oldest)| self.recent_bytes + data.len() - oldest.len() >= limit)
+        {
+            if let Some((_, oldest)) = self.recent.pop_front() {
+                self.recent_bytes -= oldest.len();
+                copy = oldest;
+            }
+        }
+        copy.clear();
+        copy.extend_from_slice(data);
+        self.recent_bytes += data.len();
+        self.recent.push_back((timestamp, copy));
+    }
+
+    /// The held audio as chunks starting at `timestamp`, fading in.
+    fn resume_at(&mut self, timestamp: i64, mut held: Vec<u8>) -> Vec<(i64, Vec<u8>)> {
+        let frame_bytes = self.frame_bytes();
+        let channels = usize::from(self.channels);
+        let fade_bytes = self.bytes_for(FADE_IN).min(held.len());
+        let mut samples = pcm::to_f32(&held[..fade_bytes], self.bit_depth);
+        let fade_frames = samples.len() / channels.max(1);
+        for (index, frame) in samples.chunks_exact_mut(channels).enumerate() {
+            let gain = index as f32 / fade_frames as f32;
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+        let faded = self.dither.quantize(&samples, channels, self.bit_depth);
+        held.splice(..fade_bytes, faded);
+
+        let chunk_bytes = self.bytes_for(RESUME_CHUNK).max(frame_bytes);
+        let mut start = timestamp;
+        held.chunks(chunk_bytes)
+            .map(|chunk| {
+                let chunk_start = start;
+                start += pcm::frames_to_micros(chunk.len() / frame_bytes, self.sample_rate);
+                (chunk_start, chunk.to_vec())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1_000;
+    const TRACK: &str = "Title\nArtist\nAlbum";
+
+    /// 16-bit mono, so one frame is two bytes and one millisecond
+    fn hold() -> PauseHold {
+        let mut hold = PauseHold::new();
+        hold.set_format(RATE, 1, 16);
+        hold
+    }
+
+    fn frames(count: usize, value: i16) -> Vec<u8> {
+        value.to_le_bytes().repeat(count)
+    }
+
+    fn admit(hold: &mut PauseHold, timestamp: i64, data: &[u8]) -> Vec<(i64, Vec<u8>)> {
+        hold.admit(timestamp, data)
+            .map(|(timestamp, data)| (timestamp, data.into_owned()))
+            .collect()
+    }
+
+    /// A synchronized clock on which playback of server time zero starts
+    /// `delay` after `origin`
+    fn clock(origin: Instant, delay: Duration) -> impl Fn(i64) -> Option<Instant> {
+        move |timestamp| Some(origin + delay + Duration::from_micros(timestamp as u64))
+    }
+
+    fn total_frames(chunks: &[(i64, Vec<u8>)]) -> usize {
+        chunks.iter().map(|(_, chunk)| chunk.len() / 2).sum()
+    }
+
+    #[test]
+    fn resumes_the_unplayed_audio_after_a_pause() {
+        let mut hold = hold();
+        let origin = Instant::now();
+        admit(&mut hold, 0, &frames(500, 1_000));
+        admit(&mut hold, 500_000, &frames(500, 2_000));
+        // The last 300ms hadn't played when the server paused
+        let now = origin + Duration::from_millis(700);
+        hold.hold(clock(origin, Duration::ZERO), now, TRACK.into());
+        hold.mark_paused();
+        assert!(hold.resumes(TRACK));
+
+        let out = admit(&mut hold, 9_000_000, &frames(1_000, 3_000));
+        // 300ms held, then the 700ms of the new chunk it doesn't cover
+        assert_eq!(out[0].0, 9_000_000);
+        assert_eq!(total_frames(&out), 1_000);
+        let (last_start, last) = out.last().unwrap();
+        assert_eq!(*last_start, 9_300_000);
+        assert_eq!(last.len(), 700 * 2);
+        // Held audio fades in, then plays as it was
+        let first = i16::from_le_bytes([out[0].1[0], out[0].1[1]]);
+        assert!(first.abs() <= 1);
+        assert_eq!(&out[2].1[..2], &2_000i16.to_le_bytes());
+    }
+
+    #[test]
+    fn drops_the_held_audio_unless_the_same_track_was_paused() {
+        let mut hold = hold();
+        let origin = Instant::now();
+        let clock = clock(origin, Duration::ZERO);
+        admit(&mut hold, 0, &frames(500, 1_000));
+
+        // Cleared while playing: a seek
+        hold.hold(&clock, origin + Duration::from_millis(300), TRACK.into());
+        assert!(!hold.resumes(TRACK));
+        assert!(!hold.is_holding());
+        assert_eq!(admit(&mut hold, 0, &frames(100, 1)).len(), 1);
+
+        // Paused, but something else plays next
+        hold.hold(&clock, origin + Duration::from_millis(50), TRACK.into());
+        hold.mark_paused();
+        assert!(!hold.resumes("Another\nArtist\nAlbum"));
+        assert_eq!(total_frames(&admit(&mut hold, 0, &frames(100, 1))), 100);
+    }
+
+    #[test]
+    fn keeps_at_most_the_hold_limit() {
+        let mut hold = hold();
+        for second in 0..15 {
+            admit(&mut hold, second * 1_000_000, &frames(1_000, 1));
+        }
+        assert_eq!(hold.recent_bytes, hold.bytes_for(MAX_HOLD));
+        let origin = Instant::now();
+        hold.hold(clock(origin, Duration::ZERO), origin, TRACK.into());
+        assert_eq!(
+            hold.held.as_ref().unwrap().pcm.len(),
+            hold.bytes_for(MAX_HOLD)
+        );
+    }
+
+    #[test]
+    fn holds_by_timestamp_when_playback_started_late() {
+        let mut hold = hold();
+        let origin = Instant::now();
+        // A second of audio arrives at once, but the player only starts
+        // two seconds later, as the timestamps say
+        admit(&mut hold, 0, &frames(500, 1_000));
+        admit(&mut hold, 500_000, &frames(500, 2_000));
+        let now = origin + Duration::from_millis(2_700);
+        hold.hold(clock(origin, Duration::from_secs(2)), now, TRACK.into());
+        assert_eq!(hold.held.as_ref().unwrap().pcm, frames(300, 2_000));
+
+        // Nothing to go by while the clock isn't synchronized
+        hold.discard();
+        admit(&mut hold, 0, &frames(500, 1_000));
+        hold.hold(|_| None, now, TRACK.into());
+        assert!(hold.held.as_ref().unwrap().pcm.is_empty());
+    }
+}
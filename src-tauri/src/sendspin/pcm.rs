@@ -0,0 +1,343 @@
+//! Raw PCM helpers for the processing stages that sit in front of the decoder.
+//!
+//! Sendspin delivers little-endian signed integer PCM. Stages that need to
+//! touch the signal (resampling, gain, ...) convert a chunk to interleaved
+//! `f32` in `-1.0..1.0`, process it, and convert back to the same bit depth,
+//! so the `PcmDecoder`/`SyncedPlayer` path downstream is unchanged.
+//!
+//! Processed audio, and 24-bit streams played on a 16-bit-only device, are
+//! written back at 16 bits through [`Dither`] rather than plainly rounded,
+//! so quiet passages and fades don't pick up correlated distortion.
+
+/// Bytes per sample for the PCM bit depths the client accepts.
+pub(crate) fn bytes_per_sample(bit_depth: u16) -> Option<usize> {
+    match bit_depth {
+        16 => Some(2),
+        24 => Some(3),
+        _ => None,
+    }
+}
+
+/// Duration of `frames` at `rate`, in microseconds.
+pub(crate) fn frames_to_micros(frames: usize, rate: u32) -> i64 {
+    (frames as u64 * 1_000_000 / u64::from(rate.max(1))) as i64
+}
+
+/// Convert little-endian signed PCM to interleaved `f32` samples.
+pub(crate) fn to_f32(bytes: &[u8], bit_depth: u16) -> Vec<f32> {
+    let mut samples = Vec::new();
+    to_f32_into(bytes, bit_depth, &mut samples);
+    samples
+}
+
+/// Like [`to_f32`], but into `out`, replacing its contents, so a buffer
+/// kept across chunks is reused instead of allocating for each.
+pub(crate) fn to_f32_into(bytes: &[u8], bit_depth: u16, out: &mut Vec<f32>) {
+    out.clear();
+    match bit_depth {
+        16 => out.extend(
+            bytes
+                .chunks_exact(2)
+                .map(|b| f32::from(i16::from_le_bytes([b[0], b[1]])) / 32_768.0),
+        ),
+        24 => out.extend(bytes.chunks_exact(3).map(|b| {
+            // Place the 24-bit value in the top of an i32 to sign-extend it.
+            let value = i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8;
+            value as f32 / 8_388_608.0
+        })),
+        _ => {}
+    }
+}
+
+/// Convert little-endian signed PCM to interleaved integer samples at its
+/// own depth, for the FLAC encoder.
+pub(crate) fn to_i32(bytes: &[u8], bit_depth: u16) -> Vec<i32> {
+    match bit_depth {
+        16 => bytes
+            .chunks_exact(2)
+            .map(|b| i32::from(i16::from_le_bytes([b[0], b[1]])))
+            .collect(),
+        24 => bytes
+            .chunks_exact(3)
+            .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Convert interleaved `f32` samples back to little-endian signed PCM,
+/// clamping anything outside full scale.
+pub(crate) fn from_f32(samples: &[f32], bit_depth: u16) -> Vec<u8> {
+    let mut out = Vec::new();
+    from_f32_into(samples, bit_depth, &mut out);
+    out
+}
+
+/// Like [`from_f32`], but into `out`, replacing its contents.
+fn from_f32_into(samples: &[f32], bit_depth: u16, out: &mut Vec<u8>) {
+    out.clear();
+    match bit_depth {
+        16 => {
+            out.reserve(samples.len() * 2);
+            for sample in samples {
+                let value = (sample * 32_768.0).round().clamp(-32_768.0, 32_767.0) as i16;
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        24 => {
+            out.reserve(samples.len() * 3);
+            for sample in samples {
+                let value = (sample * 8_388_608.0)
+                    .round()
+                    .clamp(-8_388_608.0, 8_388_607.0) as i32;
+                out.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// TPDF dither with first-order noise shaping for converting `f32` to
+/// 16-bit PCM.
+///
+/// Each sample gets triangular noise of ±1 LSB before rounding, which turns
+/// the rounding error into a steady hiss independent of the signal. The
+/// error of the previous sample is fed back so that hiss is pushed toward
+/// high frequencies, where hearing is less sensitive. 24-bit output is
+/// rounded as is: its rounding error is already below any DAC's noise floor.
+#[derive(Debug)]
+pub(crate) struct Dither {
+    /// Rounding error of the last sample, per channel
+    error: Vec<f32>,
+    /// xorshift32 state
+    rng: u32,
+}
+
+impl Dither {
+    pub(crate) fn new() -> Self {
+        Self {
+            error: Vec::new(),
+            rng: 0x9E37_79B9,
+        }
+    }
+
+    /// Uniform random value in `0.0..1.0`
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / 16_777_216.0
+    }
+
+    /// Convert interleaved `f32` samples to little-endian signed PCM like
+    /// [`from_f32`], dithering when the target is 16-bit.
+    pub(crate) fn quantize(&mut self, samples: &[f32], channels: usize, bit_depth: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.quantize_into(samples, channels, bit_depth, &mut out);
+        out
+    }
+
+    /// Like [`quantize`](Self::quantize), but into `out`, replacing its
+    /// contents.
+    pub(crate) fn quantize_into(
+        &mut self,
+        samples: &[f32],
+        channels: usize,
+        bit_depth: u16,
+        out: &mut Vec<u8>,
+    ) {
+        if bit_depth != 16 || channels == 0 {
+            from_f32_into(samples, bit_depth, out);
+            return;
+        }
+        self.error.resize(channels, 0.0);
+        out.clear();
+        out.reserve(samples.len() * 2);
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let shaped = sample * 32_768.0 - self.error[channel];
+                let noise = self.random() - self.random();
+                let value = (shaped + noise).round().clamp(-32_768.0, 32_767.0);
+                // Clipped samples would feed back a huge error; don't let
+                // a full-scale peak disturb the samples after it.
+                self.error[channel] = (value - shaped).clamp(-1.5, 1.5);
+                out.extend_from_slice(&(value as i16).to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Layout of uncompressed audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PcmFormat {
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: u16,
+    pub(crate) bit_depth: u16,
+}
+
+/// Read the layout from a RIFF/WAVE header, along with where the samples
+/// start if `header` reaches the `data` chunk.
+pub(crate) fn parse_wave_header(header: &[u8]) -> Result<(PcmFormat, Option<usize>), String> {
+    let invalid = || "Invalid WAVE header".to_string();
+    if header.len() < 12 || &header[..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(invalid());
+    }
+    let mut format = None;
+    let mut offset = 12;
+    while let Some(chunk) = header.get(offset..offset + 8) {
+        if &chunk[..4] == b"data" {
+            let format = format.ok_or_else(invalid)?;
+            return Ok((format, Some(offset + 8)));
+        }
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        if &chunk[..4] == b"fmt " {
+            let body = header
+                .get(offset + 8..offset + 8 + size)
+                .ok_or_else(invalid)?;
+            if body.len() < 16 {
+                return Err(invalid());
+            }
+            format = Some(PcmFormat {
+                channels: u16::from_le_bytes([body[2], body[3]]),
+                sample_rate: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+                bit_depth: u16::from_le_bytes([body[14], body[15]]),
+            });
+        }
+        // Chunks are padded to an even size
+        offset += 8 + size + size % 2;
+    }
+    format.map(|format| (format, None)).ok_or_else(invalid)
+}
+
+/// A WAVE header for a stream of unknown length in `format`, with the
+/// sizes left at their maximum.
+pub(crate) fn wave_header(format: PcmFormat) -> Vec<u8> {
+    let block_align = format.channels * format.bit_depth / 8;
+    let mut header = b"RIFF\xff\xff\xff\xffWAVEfmt \x10\0\0\0".to_vec();
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&format.channels.to_le_bytes());
+    header.extend_from_slice(&format.sample_rate.to_le_bytes());
+    header.extend_from_slice(&(format.sample_rate * u32::from(block_align)).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&format.bit_depth.to_le_bytes());
+    header.extend_from_slice(b"data\xff\xff\xff\xff");
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_16bit_samples() {
+        let samples: Vec<i16> = vec![0, 1, -1, 16_384, -16_384, i16::MAX, i16::MIN];
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(from_f32(&to_f32(&bytes, 16), 16), bytes);
+    }
+
+    #[test]
+    fn round_trips_24bit_samples() {
+        let samples: Vec<i32> = vec![0, 1, -1, 4_194_304, -4_194_304, 8_388_607, -8_388_608];
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|s| s.to_le_bytes()[..3].to_vec())
+            .collect();
+        assert_eq!(from_f32(&to_f32(&bytes, 24), 24), bytes);
+    }
+
+    #[test]
+    fn sign_extends_negative_24bit_values() {
+        let minus_half = to_f32(&(-4_194_304i32).to_le_bytes()[..3], 24);
+        assert!((minus_half[0] + 0.5).abs() < f32::EPSILON);
+        assert_eq!(
+            to_i32(&(-4_194_304i32).to_le_bytes()[..3], 24),
+            vec![-4_194_304]
+        );
+    }
+
+    #[test]
+    fn clamps_out_of_range_samples() {
+        let bytes = from_f32(&[2.0, -2.0], 16);
+        assert_eq!(
+            bytes,
+            [i16::MAX.to_le_bytes(), i16::MIN.to_le_bytes()].concat()
+        );
+    }
+
+    fn to_i16(bytes: &[u8]) -> Vec<i16> {
+        bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn dither_keeps_detail_below_one_step() {
+        // A constant a third of a step up rounds to zero without dither,
+        // but averages out at the right level with it
+        let level = 1.0 / 3.0 / 32_768.0;
+        assert!(to_i16(&from_f32(&[level; 1000], 16))
+            .iter()
+            .all(|&s| s == 0));
+
+        let samples = vec![level; 48_000];
+        let dithered = to_i16(&Dither::new().quantize(&samples, 1, 16));
+        let mean = dithered.iter().map(|&s| f64::from(s)).sum::<f64>() / dithered.len() as f64;
+        assert!((mean - 1.0 / 3.0).abs() < 0.02, "{mean}");
+        assert!(dithered.iter().all(|&s| s.abs() <= 3));
+    }
+
+    #[test]
+    fn dither_only_applies_to_16bit() {
+        let samples = [0.25, -0.5, 0.125, 1.0];
+        assert_eq!(
+            Dither::new().quantize(&samples, 2, 24),
+            from_f32(&samples, 24)
+        );
+        let clipped = to_i16(&Dither::new().quantize(&[2.0, -2.0], 2, 16));
+        assert_eq!(clipped, [i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn rejects_unsupported_bit_depths() {
+        assert_eq!(bytes_per_sample(8), None);
+        assert!(to_f32(&[0, 0, 0, 0], 32).is_empty());
+        assert!(from_f32(&[0.0], 32).is_empty());
+    }
+
+    #[test]
+    fn reused_buffers_are_replaced() {
+        let mut samples = vec![9.0; 8];
+        to_f32_into(&[0, 0x40], 16, &mut samples);
+        assert_eq!(samples, [0.5]);
+        let mut bytes = vec![7; 8];
+        Dither::new().quantize_into(&samples, 1, 24, &mut bytes);
+        assert_eq!(bytes, [0, 0, 0x40]);
+    }
+
+    #[test]
+    fn reads_the_layout_from_a_wave_header() {
+        let mut wave = b"RIFF\0\0\0\0WAVE".to_vec();
+        // An unrelated chunk of odd size, padded
+        wave.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        wave.extend_from_slice(b"fmt \x10\0\0\0");
+        wave.extend_from_slice(&1u16.to_le_bytes());
+        wave.extend_from_slice(&2u16.to_le_bytes());
+        wave.extend_from_slice(&48_000u32.to_le_bytes());
+        wave.extend_from_slice(&192_000u32.to_le_bytes());
+        wave.extend_from_slice(&4u16.to_le_bytes());
+        wave.extend_from_slice(&16u16.to_le_bytes());
+        let format = PcmFormat {
+            sample_rate: 48_000,
+            channels: 2,
+            bit_depth: 16,
+        };
+        assert_eq!(parse_wave_header(&wave), Ok((format, None)));
+        wave.extend_from_slice(b"data\xff\xff\xff\xff");
+        assert_eq!(parse_wave_header(&wave), Ok((format, Some(wave.len()))));
+        assert!(parse_wave_header(b"fLaC").is_err());
+
+        let header = wave_header(format);
+        assert_eq!(parse_wave_header(&header), Ok((format, Some(44))));
+    }
+}
@@ -0,0 +1,309 @@
+//! `PipeWire` graph access
+//!
+//! On Linux desktops running `PipeWire`, output devices are listed from the
+//! `PipeWire` graph instead of from ALSA: one entry per sink node, named and
+//! defaulted as in the desktop's sound settings. Playback to a sink still
+//! goes through cpal, on the `pipewire` ALSA device, and the new stream is
+//! then moved to the chosen sink by setting its `target.object` in the
+//! session manager's default metadata, the way sound settings move a
+//! stream.
+//!
+//! Every call here connects, looks at the graph, and disconnects; the
+//! `PipeWire` volume backend keeps a connection of its own.
+
+use ::pipewire as pw;
+use pw::context::Context;
+use pw::core::Core;
+use pw::main_loop::MainLoop;
+use pw::metadata::Metadata;
+use pw::properties::Properties;
+use pw::registry::{GlobalObject, Registry};
+use pw::types::ObjectType;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Prefix of `PipeWire` sink IDs in the device list and the settings; the
+/// rest is the sink's node name
+pub const DEVICE_ID_PREFIX: &str = "pipewire:";
+
+/// The ALSA device (from pipewire-alsa) streams to `PipeWire` sinks are
+/// opened on
+pub const ALSA_DEVICE: &str = "pipewire";
+
+/// How long to wait for the `PipeWire` daemon to answer
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to wait for a newly opened stream to show up in the graph
+const ROUTE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// An audio sink node
+#[derive(Debug, Clone)]
+pub struct Sink {
+    /// `node.name`, stable across restarts
+    pub name: String,
+    /// `node.description`, as the desktop shows it
+    pub description: String,
+    pub is_default: bool,
+    /// `audio.channels`, when the node reports it
+    pub channels: Option<u16>,
+    /// `object.serial`, which streams are routed by
+    serial: Option<String>,
+}
+
+/// A connection to the `PipeWire` daemon
+pub(crate) struct Connection {
+    pub mainloop: MainLoop,
+    _context: Context,
+    pub core: Core,
+    pub registry: Rc<Registry>,
+}
+
+impl Connection {
+    pub fn new() -> Result<Self, String> {
+        pw::init();
+        let mainloop = MainLoop::new(None)
+            .map_err(|e| format!("Failed to create PipeWire main loop: {}", e))?;
+        let context = Context::new(&mainloop)
+            .map_err(|e| format!("Failed to create PipeWire context: {}", e))?;
+        let core = context
+            .connect(None)
+            .map_err(|e| format!("Failed to connect to PipeWire: {}", e))?;
+        let registry = core
+            .get_registry()
+            .map_err(|e| format!("Failed to get the PipeWire registry: {}", e))?;
+        Ok(Self {
+            mainloop,
+            _context: context,
+            core,
+            registry: Rc::new(registry),
+        })
+    }
+
+    /// Run the loop until the daemon has handled everything sent so far,
+    /// and its replies have been dispatched
+    pub fn roundtrip(&self) -> Result<(), String> {
+        let done = Rc::new(Cell::new(false));
+        let pending = self
+            .core
+            .sync(0)
+            .map_err(|e| format!("PipeWire sync failed: {}", e))?;
+        let _done_listener = self
+            .core
+            .add_listener_local()
+            .done({
+                let done = Rc::clone(&done);
+                let mainloop = self.mainloop.clone();
+                move |id, seq| {
+                    if id == pw::core::PW_ID_CORE && seq == pending {
+                        done.set(true);
+                        mainloop.quit();
+                    }
+                }
+            })
+            .register();
+        let timer = self.mainloop.loop_().add_timer({
+            let mainloop = self.mainloop.clone();
+            move |_| mainloop.quit()
+        });
+        let _ = timer.update_timer(Some(TIMEOUT), None);
+        self.mainloop.run();
+        if done.get() {
+            Ok(())
+        } else {
+            Err("PipeWire didn't respond".to_string())
+        }
+    }
+}
+
+/// Property `key` of a global, if it has it
+pub(crate) fn prop<'a>(global: &'a GlobalObject<Properties>, key: &str) -> Option<&'a str> {
+    global.props.as_ref()?.get(key)
+}
+
+/// Whether `global` is an audio sink node
+pub(crate) fn is_sink(global: &GlobalObject<Properties>) -> bool {
+    global.type_ == ObjectType::Node && prop(global, "media.class") == Some("Audio/Sink")
+}
+
+/// Whether `global` is the session manager's default metadata, which holds
+/// the default sink and stream targets
+pub(crate) fn is_default_metadata(global: &GlobalObject<Properties>) -> bool {
+    global.type_ == ObjectType::Metadata && prop(global, "metadata.name") == Some("default")
+}
+
+/// The node name in a `default.audio.sink` metadata value, which looks like
+/// `{"name":"alsa_output.pci-0000_00_1f.3.analog-stereo"}`
+pub(crate) fn sink_name_from_metadata(value: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(value).ok()?;
+    value["name"].as_str().map(str::to_string)
+}
+
+/// What's in the graph, with the connection it was read over
+struct Graph {
+    connection: Connection,
+    sinks: Vec<Sink>,
+    /// IDs of this process's playback stream nodes
+    own_streams: Vec<u32>,
+    metadata: Option<Metadata>,
+}
+
+impl Graph {
+    fn read() -> Result<Self, String> {
+        let connection = Connection::new()?;
+        let globals: Rc<RefCell<Vec<GlobalObject<Properties>>>> = Rc::default();
+        let _registry_listener = connection
+            .registry
+            .add_listener_local()
+            .global({
+                let globals = Rc::clone(&globals);
+                move |global| globals.borrow_mut().push(global.to_owned())
+            })
+            .register();
+        connection.roundtrip()?;
+        let globals = globals.take();
+
+        // Read the default sink off the default metadata
+        let default_sink: Rc<RefCell<Option<String>>> = Rc::default();
+        let metadata = match globals.iter().find(|global| is_default_metadata(global)) {
+            Some(global) => {
+                let metadata: Metadata = connection
+                    .registry
+                    .bind(global)
+                    .map_err(|e| format!("Failed to bind PipeWire metadata: {}", e))?;
+                let _metadata_listener = metadata
+                    .add_listener_local()
+                    .property({
+                        let default_sink = Rc::clone(&default_sink);
+                        move |subject, key, _, value| {
+                            if subject == pw::core::PW_ID_CORE && key == Some("default.audio.sink")
+                            {
+                                *default_sink.borrow_mut() =
+                                    value.and_then(sink_name_from_metadata);
+                            }
+                            0
+                        }
+                    })
+                    .register();
+                connection.roundtrip()?;
+                Some(metadata)
+            }
+            None => None,
+        };
+        let default_sink = default_sink.take();
+
+        let process_id = std::process::id().to_string();
+        let sinks = globals
+            .iter()
+            .filter(|global| is_sink(global))
+            .filter_map(|global| {
+                let name = prop(global, "node.name")?.to_string();
+                Some(Sink {
+                    description: prop(global, "node.description")
+                        .or_else(|| prop(global, "node.nick"))
+                        .unwrap_or(&name)
+                        .to_string(),
+                    is_default: default_sink.as_ref() == Some(&name),
+                    channels: prop(global, "audio.channels").and_then(|c| c.parse().ok()),
+                    serial: prop(global, "object.serial").map(str::to_string),
+                    name,
+                })
+            })
+            .collect();
+        let own_streams = globals
+            .iter()
+            .filter(|global| {
+                global.type_ == ObjectType::Node
+                    && prop(global, "media.class") == Some("Stream/Output/Audio")
+                    && prop(global, "application.process.id") == Some(process_id.as_str())
+            })
+            .map(|global| global.id)
+            .collect();
+
+        Ok(Self {
+            connection,
+            sinks,
+            own_streams,
+            metadata,
+        })
+    }
+}
+
+/// The audio sinks in the graph, if `PipeWire` is running
+pub fn list_sinks() -> Result<Vec<Sink>, String> {
+    Graph::read().map(|graph| graph.sinks)
+}
+
+/// The node name of the sink behind a device ID, if it is a `PipeWire` one
+pub fn sink_name(device_id: &str) -> Option<&str> {
+    device_id.strip_prefix(DEVICE_ID_PREFIX)
+}
+
+/// Moves the next stream this process opens to a sink. Made before the
+/// stream is opened, applied after.
+pub struct Route {
+    sink_name: String,
+    /// This process's streams before the one to route
+    existing_streams: Vec<u32>,
+}
+
+impl Route {
+    pub fn new(sink_name: &str) -> Result<Self, String> {
+        Ok(Self {
+            sink_name: sink_name.to_string(),
+            existing_streams: Graph::read()?.own_streams,
+        })
+    }
+
+    /// Move the stream opened since [`Route::new`] to the sink, once it
+    /// shows up in the graph
+    pub fn apply(self) -> Result<(), String> {
+        let deadline = Instant::now() + ROUTE_TIMEOUT;
+        loop {
+            let graph = Graph::read()?;
+            let stream = graph
+                .own_streams
+                .iter()
+                .find(|id| !self.existing_streams.contains(id));
+            if let Some(&stream) = stream {
+                let serial = graph
+                    .sinks
+                    .iter()
+                    .find(|sink| sink.name == self.sink_name)
+                    .and_then(|sink| sink.serial.clone())
+                    .ok_or_else(|| format!("PipeWire sink not found: {}", self.sink_name))?;
+                let metadata = graph
+                    .metadata
+                    .as_ref()
+                    .ok_or("No session manager metadata to route streams with")?;
+                metadata.set_property(stream, "target.object", Some("Spa:Id"), Some(&serial));
+                graph.connection.roundtrip()?;
+                log::info!(
+                    "[Sendspin] Routed PipeWire stream {} to {}",
+                    stream,
+                    self.sink_name
+                );
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err("The stream to route didn't show up in the PipeWire graph".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_sink_name_is_read_from_metadata_json() {
+        assert_eq!(
+            sink_name_from_metadata(r#"{"name":"alsa_output.usb-dac.analog-stereo"}"#).as_deref(),
+            Some("alsa_output.usb-dac.analog-stereo")
+        );
+        assert_eq!(sink_name_from_metadata("alsa_output.usb-dac"), None);
+        assert_eq!(sink_name_from_metadata(r#"{"other":1}"#), None);
+    }
+}
@@ -0,0 +1,358 @@
+//! Queue from the client loop to the playback thread
+//!
+//! Audio and control commands travel separately. Audio goes through a
+//! bounded single-producer, single-consumer ring (`rtrb`) rather than a
+//! channel: queueing a chunk neither allocates nor takes a lock, and a
+//! playback thread that falls behind can't make the queue grow without
+//! bound during a burst. Commands have a small channel of their own, which
+//! the receiver looks at first, so a full ring of audio never holds them up
+//! or crowds them out; sending one waits for room instead of failing.
+//!
+//! Each command says where it belongs relative to the audio queued before
+//! it (see [`Order`]). The client loop is the only sender and the playback
+//! thread the only receiver, which parks while both are empty and is
+//! unparked by the sender after each send. The receiver in turn signals the
+//! sender whenever it takes audio off a full ring.
+//!
+//! Receiving follows `std::sync::mpsc` and reuses its error types.
+
+use std::sync::atomic::{fence, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TrySendError};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+
+/// Where a command goes relative to the audio queued before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Order {
+    /// Ahead of it: received as soon as the receiver next looks
+    Immediate,
+    /// Behind it: the audio is received first
+    AfterAudio,
+    /// In place of it: the audio is dropped without being received
+    DiscardAudio,
+}
+
+/// A command with its place in the audio: how much audio had been queued
+/// when it was sent
+struct Control<T> {
+    value: T,
+    order: Order,
+    audio_mark: u64,
+}
+
+/// State shared by both ends
+struct Shared {
+    /// The receiving thread, to unpark once it has first looked for values
+    receiver_thread: OnceLock<Thread>,
+    /// Signalled when audio leaves the ring, or the receiver goes away
+    room: Notify,
+}
+
+impl Shared {
+    fn wake_receiver(&self) {
+        // Pairs with the fence in `Receiver::recv_until`: either the
+        // receiver sees what was just sent, or this sees its thread
+        fence(Ordering::SeqCst);
+        if let Some(thread) = self.receiver_thread.get() {
+            thread.unpark();
+        }
+    }
+}
+
+pub(crate) struct Sender<T> {
+    audio: rtrb::Producer<T>,
+    control: mpsc::Sender<Control<T>>,
+    shared: Arc<Shared>,
+    /// Audio queued so far
+    audio_sent: u64,
+    /// Whether the last audio timed out waiting for room; the next doesn't
+    /// wait again until the receiver has caught up
+    stalled: bool,
+}
+
+pub(crate) struct Receiver<T> {
+    audio: rtrb::Consumer<T>,
+    control: mpsc::Receiver<Control<T>>,
+    shared: Arc<Shared>,
+    /// Audio taken off the ring so far, received or dropped
+    audio_taken: u64,
+    /// A command waiting for the audio queued before it
+    pending: Option<Control<T>>,
+}
+
+/// A queue holding up to `audio_capacity` chunks of audio and
+/// `control_capacity` commands
+pub(crate) fn channel<T>(
+    audio_capacity: usize,
+    control_capacity: usize,
+) -> (Sender<T>, Receiver<T>) {
+    let (producer, consumer) = rtrb::RingBuffer::new(audio_capacity);
+    let (control_tx, control_rx) = mpsc::channel(control_capacity);
+    let shared = Arc::new(Shared {
+        receiver_thread: OnceLock::new(),
+        room: Notify::new(),
+    });
+    (
+        Sender {
+            audio: producer,
+            control: control_tx,
+            shared: Arc::clone(&shared),
+            audio_sent: 0,
+            stalled: false,
+        },
+        Receiver {
+            audio: consumer,
+            control: control_rx,
+            shared,
+            audio_taken: 0,
+            pending: None,
+        },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Queue a command, waiting for room if the receiver is behind on
+    /// commands. Fails only once the receiver is gone.
+    pub(crate) async fn send(&mut self, value: T, order: Order) -> Result<(), SendError<T>> {
+        let control = Control {
+            value,
+            order,
+            audio_mark: self.audio_sent,
+        };
+        self.control
+            .send(control)
+            .await
+            .map_err(|mpsc::error::SendError(control)| SendError(control.value))?;
+        self.shared.wake_receiver();
+        Ok(())
+    }
+
+    /// Queue audio, waiting up to `timeout` for room while the ring is full.
+    /// After a wait times out, audio is refused straight away until the
+    /// receiver takes some off the ring.
+    pub(crate) async fn send_audio(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), TrySendError<T>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut value = value;
+        loop {
+            if self.audio.is_abandoned() {
+                return Err(TrySendError::Disconnected(value));
+            }
+            match self.audio.push(value) {
+                Ok(()) => {
+                    self.audio_sent += 1;
+                    self.stalled = false;
+                    self.shared.wake_receiver();
+                    return Ok(());
+                }
+                Err(rtrb::PushError::Full(returned)) => value = returned,
+            }
+            if self.stalled || tokio::time::Instant::now() >= deadline {
+                self.stalled = true;
+                return Err(TrySendError::Full(value));
+            }
+            // A signal sent since the push failed is kept as a permit, so
+            // room made meanwhile isn't missed
+            let _ = tokio::time::timeout_at(deadline, self.shared.room.notified()).await;
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Let a parked receiver see the disconnect
+        self.shared.wake_receiver();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Wait for the next value
+    pub(crate) fn recv(&mut self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    /// Wait up to `timeout` for the next value
+    pub(crate) fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        if self.shared.receiver_thread.get().is_none() {
+            let _ = self.shared.receiver_thread.set(thread::current());
+            // Pairs with the fence in `Shared::wake_receiver`
+            fence(Ordering::SeqCst);
+        }
+        loop {
+            if let Some(value) = self.next_due() {
+                return Ok(value);
+            }
+            if self.control.is_closed() && self.audio.is_abandoned() {
+                // The sender may have sent right before it was dropped
+                return self.next_due().ok_or(RecvTimeoutError::Disconnected);
+            }
+            match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    thread::park_timeout(left);
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    /// The next value due, if any: commands first, unless one is still
+    /// waiting for the audio queued before it
+    fn next_due(&mut self) -> Option<T> {
+        if self.pending.is_none() {
+            self.pending = self.control.try_recv().ok();
+        }
+        if let Some(control) = self.pending.take() {
+            let audio_before = control.audio_mark.saturating_sub(self.audio_taken);
+            match control.order {
+                Order::Immediate => return Some(control.value),
+                Order::DiscardAudio => {
+                    for _ in 0..audio_before {
+                        drop(self.take_audio());
+                    }
+                    return Some(control.value);
+                }
+                Order::AfterAudio if audio_before == 0 => return Some(control.value),
+                Order::AfterAudio => self.pending = Some(control),
+            }
+        }
+        self.take_audio()
+    }
+
+    fn take_audio(&mut self) -> Option<T> {
+        let value = self.audio.pop().ok()?;
+        self.audio_taken += 1;
+        self.shared.room.notify_one();
+        Some(value)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // Let a sender waiting for room see the disconnect
+        self.shared.room.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_millis(10);
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn queues_audio_in_order_up_to_capacity() {
+        let (mut tx, mut rx) = channel(2, 2);
+        block_on(async {
+            tx.send_audio(1, WAIT).await.unwrap();
+            tx.send_audio(2, WAIT).await.unwrap();
+            assert!(matches!(
+                tx.send_audio(3, WAIT).await,
+                Err(TrySendError::Full(3))
+            ));
+        });
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv_timeout(WAIT), Err(RecvTimeoutError::Timeout));
+
+        block_on(async { tx.send_audio(4, WAIT).await.unwrap() });
+        drop(tx);
+        assert_eq!(rx.recv(), Ok(4), "queued before the sender was dropped");
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn commands_keep_their_place_in_the_audio() {
+        let (mut tx, mut rx) = channel(8, 8);
+        block_on(async {
+            tx.send_audio(1, WAIT).await.unwrap();
+            tx.send_audio(2, WAIT).await.unwrap();
+            tx.send(10, Order::AfterAudio).await.unwrap();
+            tx.send(20, Order::Immediate).await.unwrap();
+            tx.send_audio(3, WAIT).await.unwrap();
+            tx.send(30, Order::DiscardAudio).await.unwrap();
+            tx.send_audio(4, WAIT).await.unwrap();
+        });
+        let received: Vec<_> = std::iter::from_fn(|| rx.recv_timeout(WAIT).ok()).collect();
+        assert_eq!(received, [1, 2, 10, 20, 30, 4]);
+    }
+
+    #[test]
+    fn commands_get_through_a_full_ring() {
+        let (mut tx, mut rx) = channel(1, 4);
+        block_on(async {
+            tx.send_audio(1, WAIT).await.unwrap();
+            tx.send(10, Order::Immediate).await.unwrap();
+        });
+        assert_eq!(rx.recv(), Ok(10));
+        assert_eq!(rx.recv(), Ok(1));
+    }
+
+    #[test]
+    fn audio_waits_for_room_and_then_stops_waiting() {
+        let (mut tx, mut rx) = channel(1, 1);
+        let receiver = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let first = rx.recv();
+            (first, rx)
+        });
+        block_on(async {
+            tx.send_audio(1, WAIT).await.unwrap();
+            // Room comes up while this waits
+            tx.send_audio(2, Duration::from_secs(5)).await.unwrap();
+        });
+        let (first, mut rx) = receiver.join().unwrap();
+        assert_eq!(first, Ok(1));
+
+        block_on(async {
+            assert!(tx.send_audio(3, WAIT).await.is_err());
+            let start = Instant::now();
+            assert!(tx.send_audio(4, Duration::from_secs(5)).await.is_err());
+            assert!(start.elapsed() < Duration::from_secs(1), "waited again");
+        });
+        assert_eq!(rx.recv(), Ok(2));
+        block_on(async { tx.send_audio(5, WAIT).await.unwrap() });
+    }
+
+    #[test]
+    fn wakes_a_parked_receiver() {
+        let (mut tx, mut rx) = channel(4, 4);
+        let receiver = thread::spawn(move || {
+            let start = Instant::now();
+            let value = rx.recv();
+            (value, start.elapsed())
+        });
+        thread::sleep(Duration::from_millis(20));
+        block_on(async { tx.send(7, Order::Immediate).await.unwrap() });
+        let (value, waited) = receiver.join().unwrap();
+        assert_eq!(value, Ok(7));
+        assert!(waited < Duration::from_secs(1), "{waited:?}");
+        block_on(async {
+            assert!(tx.send(8, Order::Immediate).await.is_err());
+            assert!(matches!(
+                tx.send_audio(9, WAIT).await,
+                Err(TrySendError::Disconnected(9))
+            ));
+        });
+    }
+}
@@ -0,0 +1,44 @@
+//! `CoreAudio` nominal sample rate
+
+use crate::sendspin::exclusive::macos::{find_device, get_property, set_property};
+use coreaudio_sys::kAudioDevicePropertyNominalSampleRate;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the device to report a rate it was switched to
+const SWITCH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The device's current nominal rate.
+pub fn sample_rate(device_name: &str) -> Result<u32, String> {
+    let device_id = find_device(device_name)?;
+    let rate: f64 = get_property(device_id, kAudioDevicePropertyNominalSampleRate)
+        .map_err(|status| format!("Failed to read device sample rate: {}", status))?;
+    Ok(rate.round() as u32)
+}
+
+/// Switch the device's nominal rate, waiting for the switch to take effect
+/// so the player opened next sees the new rate.
+pub fn set_sample_rate(device_name: &str, sample_rate: u32) -> Result<(), String> {
+    let device_id = find_device(device_name)?;
+    set_property(
+        device_id,
+        kAudioDevicePropertyNominalSampleRate,
+        f64::from(sample_rate),
+    )
+    .map_err(|status| format!("Device can't switch to {} Hz: {}", sample_rate, status))?;
+
+    // The switch completes asynchronously
+    let deadline = Instant::now() + SWITCH_TIMEOUT;
+    while Instant::now() < deadline {
+        if sample_rate_of(device_id) == Some(sample_rate) {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Err(format!("Device didn't switch to {} Hz", sample_rate))
+}
+
+fn sample_rate_of(device_id: coreaudio_sys::AudioDeviceID) -> Option<u32> {
+    get_property::<f64>(device_id, kAudioDevicePropertyNominalSampleRate)
+        .ok()
+        .map(|rate| rate.round() as u32)
+}
@@ -0,0 +1,148 @@
+//! Switching the output device to the stream's sample rate
+//!
+//! When enabled (the default), the playback thread sets the output device to
+//! each stream's rate before opening a player, so the OS mixer doesn't
+//! resample it:
+//!
+//! - macOS: the `CoreAudio` nominal sample rate
+//! - Windows: the endpoint's shared-mode format, the one in the Sound
+//!   control panel, after checking the device takes the rate
+//! - Linux: nothing to do; ALSA devices are opened at the stream rate, and
+//!   `PipeWire` follows the streams' rates where its configuration allows
+//!
+//! A device that can't take a rate is left as it is and played to as
+//! before. The device's own rate is put back once it is no longer played
+//! to. Exclusive mode switches the rate itself, so this stays out of its way.
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "windows")]
+pub(crate) mod windows;
+
+#[cfg(target_os = "macos")]
+use macos as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+/// A device switched away from its own rate; switched back on drop.
+#[cfg_attr(not(any(target_os = "macos", target_os = "windows")), allow(dead_code))]
+pub(crate) struct RateSwitch {
+    device_name: String,
+    /// The device's rate before the first switch; taken over by the next
+    /// switch on the same device
+    original_rate: Option<u32>,
+    /// The rate it was switched to, or 0 if unknown
+    rate: u32,
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+impl Drop for RateSwitch {
+    fn drop(&mut self) {
+        let Some(original_rate) = self.original_rate.filter(|&rate| rate != self.rate) else {
+            return;
+        };
+        match platform::set_sample_rate(&self.device_name, original_rate) {
+            Ok(()) => log::info!(
+                "[Sendspin] Restored {} to {} Hz",
+                self.device_name,
+                original_rate
+            ),
+            Err(e) => log::warn!(
+                "[Sendspin] Failed to restore {} to {} Hz: {}",
+                self.device_name,
+                original_rate,
+                e
+            ),
+        }
+    }
+}
+
+/// Bring the device's rate in line with a stream at `sample_rate` before a
+/// player is opened on `device`.
+///
+/// Takes the switch made for the previous player, if any, and returns the
+/// one to keep for as long as the new player plays. Failures are logged;
+/// playback goes ahead at whatever rate the device is at.
+#[cfg_attr(
+    not(any(target_os = "macos", target_os = "windows")),
+    allow(clippy::needless_pass_by_value, clippy::unnecessary_wraps)
+)]
+pub(crate) fn prepare(
+    current: Option<RateSwitch>,
+    device: Option<&cpal::Device>,
+    sample_rate: u32,
+) -> Option<RateSwitch> {
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    {
+        use cpal::traits::DeviceTrait;
+
+        let settings = crate::settings::get_settings();
+        if !settings.device_rate_switching || settings.exclusive_mode {
+            return None;
+        }
+        let device_name = device
+            .and_then(|d| d.description().ok())
+            .map(|desc| desc.name().to_string())?;
+
+        let original_rate = match current {
+            Some(mut switch) if switch.device_name == device_name => switch.original_rate.take(),
+            other => {
+                drop(other);
+                None
+            }
+        };
+        let rate = match platform::sample_rate(&device_name) {
+            Ok(rate) => rate,
+            Err(e) => {
+                log::debug!(
+                    "[Sendspin] Can't read the sample rate of {}: {}",
+                    device_name,
+                    e
+                );
+                drop(RateSwitch {
+                    device_name,
+                    original_rate,
+                    rate: 0,
+                });
+                return None;
+            }
+        };
+        let mut switch = RateSwitch {
+            device_name,
+            original_rate: Some(original_rate.unwrap_or(rate)),
+            rate,
+        };
+        if rate == sample_rate {
+            return Some(switch);
+        }
+
+        match platform::set_sample_rate(&switch.device_name, sample_rate) {
+            Ok(()) => {
+                log::info!(
+                    "[Sendspin] Switched {} from {} Hz to {} Hz",
+                    switch.device_name,
+                    rate,
+                    sample_rate
+                );
+                switch.rate = sample_rate;
+                Some(switch)
+            }
+            Err(e) => {
+                log::info!(
+                    "[Sendspin] Not switching {} to {} Hz, the OS will resample: {}",
+                    switch.device_name,
+                    sample_rate,
+                    e
+                );
+                // Dropping it puts back the rate an earlier stream switched
+                // away from
+                None
+            }
+        }
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        let _ = (current, device, sample_rate);
+        None
+    }
+}
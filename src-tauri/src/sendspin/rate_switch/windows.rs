@@ -0,0 +1,154 @@
+//! WASAPI shared-mode device format
+//!
+//! In shared mode the audio engine mixes at the endpoint's device format,
+//! the "Default Format" on the device's Advanced tab in the Sound control
+//! panel, and resamples every stream to it. Windows has no public API to
+//! change it; this goes through `IPolicyConfig`, the undocumented interface
+//! the control panel itself uses. Whether the device takes a rate is asked
+//! first through format negotiation with `IsFormatSupported`.
+
+#![allow(unsafe_code)] // COM calls are all `unsafe`; lift the workspace deny.
+
+use windows::core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PCWSTR};
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+use windows::Win32::Foundation::S_OK;
+use windows::Win32::Media::Audio::{
+    eRender, IAudioClient, IMMDevice, IMMDeviceEnumerator, MMDeviceEnumerator,
+    AUDCLNT_SHAREMODE_EXCLUSIVE, DEVICE_STATE_ACTIVE, WAVEFORMATEX, WAVEFORMATEXTENSIBLE,
+};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL,
+    COINIT_MULTITHREADED, STGM_READ,
+};
+
+/// `CPolicyConfigClient`, which implements `IPolicyConfig`
+const CLSID_POLICY_CONFIG_CLIENT: GUID = GUID::from_u128(0x870a_f99c_171d_4f9e_af0d_e63d_f40c_2bc9);
+
+/// `wFormatTag` of formats laid out as `WAVEFORMATEXTENSIBLE`
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Size of the `WAVEFORMATEXTENSIBLE` fields past `WAVEFORMATEX`
+const EXTENSIBLE_SIZE: u16 =
+    (std::mem::size_of::<WAVEFORMATEXTENSIBLE>() - std::mem::size_of::<WAVEFORMATEX>()) as u16;
+
+#[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
+unsafe trait IPolicyConfig: IUnknown {
+    fn GetMixFormat(&self, device_id: PCWSTR, format: *mut *mut WAVEFORMATEX) -> HRESULT;
+    fn GetDeviceFormat(
+        &self,
+        device_id: PCWSTR,
+        default: i32,
+        format: *mut *mut WAVEFORMATEX,
+    ) -> HRESULT;
+    fn ResetDeviceFormat(&self, device_id: PCWSTR) -> HRESULT;
+    fn SetDeviceFormat(
+        &self,
+        device_id: PCWSTR,
+        endpoint_format: *const WAVEFORMATEX,
+        mix_format: *const WAVEFORMATEX,
+    ) -> HRESULT;
+}
+
+/// The device's current shared-mode rate.
+pub fn sample_rate(device_name: &str) -> Result<u32, String> {
+    let device_name = device_name.to_string();
+    with_com(move || unsafe {
+        let device = find_device(&device_name)?;
+        let format = mix_format(&device)?;
+        Ok(format.Format.nSamplesPerSec)
+    })
+}
+
+/// Switch the device's shared-mode format to `sample_rate`, keeping its
+/// sample format and channels.
+pub fn set_sample_rate(device_name: &str, sample_rate: u32) -> Result<(), String> {
+    let device_name = device_name.to_string();
+    with_com(move || unsafe {
+        let device = find_device(&device_name)?;
+        let client: IAudioClient = device
+            .Activate(CLSCTX_ALL, None)
+            .map_err(|e| format!("Failed to activate audio client: {}", e))?;
+        let mut format = mix_format(&device)?;
+        format.Format.nSamplesPerSec = sample_rate;
+        format.Format.nAvgBytesPerSec = sample_rate * u32::from(format.Format.nBlockAlign);
+        let format_ptr = (&raw const format).cast::<WAVEFORMATEX>();
+
+        // The device format is what the hardware is opened with, so only
+        // formats it can be opened exclusively with are taken
+        if client.IsFormatSupported(AUDCLNT_SHAREMODE_EXCLUSIVE, format_ptr, None) != S_OK {
+            return Err(format!("Device doesn't support {} Hz", sample_rate));
+        }
+
+        let policy: IPolicyConfig = CoCreateInstance(&CLSID_POLICY_CONFIG_CLIENT, None, CLSCTX_ALL)
+            .map_err(|e| format!("Failed to create policy config: {}", e))?;
+        let id = device
+            .GetId()
+            .map_err(|e| format!("Failed to get device ID: {}", e))?;
+        let result = policy
+            .SetDeviceFormat(PCWSTR(id.0), format_ptr, format_ptr)
+            .ok();
+        CoTaskMemFree(Some(id.0 as *const _));
+        result.map_err(|e| format!("Failed to set device format: {}", e))
+    })
+}
+
+/// Run `f` on a thread of its own with COM initialized, so the caller's
+/// COM state doesn't matter
+fn with_com<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    std::thread::spawn(move || {
+        let initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+        let result = f();
+        if initialized {
+            unsafe { CoUninitialize() };
+        }
+        result
+    })
+    .join()
+    .map_err(|_| "Device format thread panicked".to_string())?
+}
+
+/// The active render endpoint with the friendly name cpal lists it by.
+pub(crate) unsafe fn find_device(device_name: &str) -> Result<IMMDevice, String> {
+    let enumerator: IMMDeviceEnumerator =
+        CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+            .map_err(|e| format!("Failed to create device enumerator: {}", e))?;
+    let devices = enumerator
+        .EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)
+        .map_err(|e| format!("Failed to list devices: {}", e))?;
+    let count = devices
+        .GetCount()
+        .map_err(|e| format!("Failed to list devices: {}", e))?;
+    for index in 0..count {
+        let Ok(device) = devices.Item(index) else {
+            continue;
+        };
+        let name = device
+            .OpenPropertyStore(STGM_READ)
+            .and_then(|store| store.GetValue(&PKEY_Device_FriendlyName));
+        if name.is_ok_and(|name| name.to_string() == device_name) {
+            return Ok(device);
+        }
+    }
+    Err(format!("Device not found: {}", device_name))
+}
+
+/// The device's shared-mode format, widened to `WAVEFORMATEXTENSIBLE`.
+unsafe fn mix_format(device: &IMMDevice) -> Result<WAVEFORMATEXTENSIBLE, String> {
+    let client: IAudioClient = device
+        .Activate(CLSCTX_ALL, None)
+        .map_err(|e| format!("Failed to activate audio client: {}", e))?;
+    let format = client
+        .GetMixFormat()
+        .map_err(|e| format!("Failed to read device format: {}", e))?;
+    let mut extensible = WAVEFORMATEXTENSIBLE::default();
+    if (*format).wFormatTag == WAVE_FORMAT_EXTENSIBLE && (*format).cbSize >= EXTENSIBLE_SIZE {
+        extensible = format.cast::<WAVEFORMATEXTENSIBLE>().read_unaligned();
+    } else {
+        extensible.Format = format.read_unaligned();
+        extensible.Format.cbSize = 0;
+    }
+    CoTaskMemFree(Some(format as *const _));
+    Ok(extensible)
+}
@@ -0,0 +1,113 @@
+//! Audio recently sent to the player.
+//!
+//! Switching the output device mid-stream opens a new player, which starts
+//! out empty while the old one still had seconds of audio queued. The
+//! playback thread keeps what it queued recently so it can be queued again
+//! on the new player. Each buffer carries its play time; the player lines it
+//! up against the server clock and skips what is already late, so the new
+//! device picks up where the old one was, in sync with the group.
+
+use sendspin::audio::AudioBuffer;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long queued audio is kept: longer than the most the client buffers
+/// ahead (see `adaptive_buffer`), so nothing unplayed is missed
+const KEEP: Duration = Duration::from_secs(6);
+
+#[derive(Default)]
+pub(crate) struct RecentAudio {
+    /// Queued audio with when it was queued, oldest first
+    buffers: VecDeque<(Instant, AudioBuffer)>,
+}
+
+impl RecentAudio {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep a copy of `buffer`, queued at `now`
+    pub(crate) fn push(&mut self, buffer: &AudioBuffer, now: Instant) {
+        self.prune(now);
+        self.buffers.push_back((now, copy(buffer)));
+    }
+
+    /// Forget everything kept, when the player's queue is cleared
+    pub(crate) fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
+    /// Copies of the audio that may not have played yet at `now`, oldest
+    /// first
+    pub(crate) fn unplayed(&mut self, now: Instant) -> Vec<AudioBuffer> {
+        self.prune(now);
+        self.buffers
+            .iter()
+            .map(|(_, buffer)| copy(buffer))
+            .collect()
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self
+            .buffers
+            .front()
+            .is_some_and(|(queued, _)| now.saturating_duration_since(*queued) > KEEP)
+        {
+            self.buffers.pop_front();
+        }
+    }
+}
+
+/// A copy of `buffer` to queue on a second player
+pub(crate) fn copy(buffer: &AudioBuffer) -> AudioBuffer {
+    AudioBuffer {
+        timestamp: buffer.timestamp,
+        samples: buffer.samples.clone(),
+        format: buffer.format.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sendspin::audio::decode::{Decoder, PcmDecoder};
+    use sendspin::audio::{AudioFormat, Codec};
+
+    fn buffer(timestamp: i64) -> AudioBuffer {
+        AudioBuffer {
+            timestamp,
+            samples: PcmDecoder::new(16).decode(&[0; 4]).unwrap(),
+            format: AudioFormat {
+                codec: Codec::Pcm,
+                sample_rate: 48_000,
+                channels: 2,
+                bit_depth: 16,
+                codec_header: None,
+            },
+        }
+    }
+
+    #[test]
+    fn keeps_only_audio_that_may_not_have_played() {
+        let start = Instant::now();
+        let mut recent = RecentAudio::new();
+        recent.push(&buffer(0), start);
+        recent.push(&buffer(1_000_000), start + Duration::from_secs(1));
+        recent.push(&buffer(2_000_000), start + Duration::from_secs(2));
+
+        let timestamps = |buffers: Vec<AudioBuffer>| -> Vec<i64> {
+            buffers.iter().map(|b| b.timestamp).collect()
+        };
+        assert_eq!(
+            timestamps(recent.unplayed(start + Duration::from_secs(3))),
+            [0, 1_000_000, 2_000_000]
+        );
+        assert_eq!(
+            timestamps(recent.unplayed(start + Duration::from_millis(7_500))),
+            [2_000_000]
+        );
+
+        recent.clear();
+        assert!(recent.unplayed(start + Duration::from_secs(8)).is_empty());
+    }
+}
@@ -0,0 +1,498 @@
+//! A small FLAC encoder for recordings.
+//!
+//! Encodes integer PCM with the fixed linear predictors (orders 0-4, the
+//! best per block and channel) and Rice-coded residuals, falling back to
+//! verbatim samples where prediction doesn't pay off. That gets most of the
+//! way to what the reference encoder's fast presets achieve, without its
+//! LPC search.
+//!
+//! The stream starts with STREAMINFO and a `VORBIS_COMMENT` block of tags.
+//! The sample count and frame sizes in STREAMINFO are filled in by
+//! [`FlacWriter::finish`]; the MD5 signature is left unset, which decoders
+//! read as "unknown".
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Samples per channel in each frame
+const BLOCK_SIZE: usize = 4096;
+/// Highest fixed predictor order
+const MAX_ORDER: usize = 4;
+/// Highest Rice parameter; 15 marks an escaped partition
+const MAX_RICE_PARAM: u32 = 14;
+/// Offset of the STREAMINFO block: after `fLaC` and its block header
+const STREAMINFO_OFFSET: u64 = 8;
+const VENDOR: &str = concat!("Music Assistant Companion ", env!("CARGO_PKG_VERSION"));
+
+/// Format of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FlacFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 16 or 24
+    pub bits_per_sample: u16,
+}
+
+/// Writes interleaved integer samples to `out` as a FLAC stream
+pub(crate) struct FlacWriter<W: Write + Seek> {
+    out: W,
+    format: FlacFormat,
+    /// Interleaved samples waiting for a full block
+    pending: Vec<i32>,
+    frame_number: u64,
+    /// Samples per channel written so far
+    total_samples: u64,
+    min_frame_bytes: usize,
+    max_frame_bytes: usize,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// Start a stream in `format` with the given Vorbis comment tags, such
+    /// as `("TITLE", ...)`.
+    pub(crate) fn new(mut out: W, format: FlacFormat, tags: &[(&str, &str)]) -> io::Result<Self> {
+        if !(1..=8).contains(&format.channels)
+            || !matches!(format.bits_per_sample, 16 | 24)
+            || format.sample_rate == 0
+            || format.sample_rate >= 1 << 20
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("FLAC can't hold {:?}", format),
+            ));
+        }
+        out.write_all(b"fLaC")?;
+        out.write_all(&block_header(false, 0, 34))?;
+        out.write_all(&streaminfo(format, 0, 0, 0))?;
+        let comment = vorbis_comment(tags);
+        out.write_all(&block_header(true, 4, comment.len()))?;
+        out.write_all(&comment)?;
+        Ok(Self {
+            out,
+            format,
+            pending: Vec::with_capacity(BLOCK_SIZE * usize::from(format.channels)),
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_bytes: 0,
+            max_frame_bytes: 0,
+        })
+    }
+
+    /// Add interleaved samples, encoding every block that fills up
+    pub(crate) fn write(&mut self, samples: &[i32]) -> io::Result<()> {
+        let block_len = BLOCK_SIZE * usize::from(self.format.channels);
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let take = (block_len - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() == block_len {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes written so far
+    pub(crate) fn bytes_written(&mut self) -> io::Result<u64> {
+        self.out.stream_position()
+    }
+
+    /// Encode what's left, fill in STREAMINFO and return the output
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        // A trailing partial frame of a channel is dropped
+        let channels = usize::from(self.format.channels);
+        self.pending
+            .truncate(self.pending.len() / channels * channels);
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+        self.out.write_all(&streaminfo(
+            self.format,
+            self.total_samples,
+            self.min_frame_bytes,
+            self.max_frame_bytes,
+        ))?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let channels = usize::from(self.format.channels);
+        let block_size = self.pending.len() / channels;
+        let bits = u32::from(self.format.bits_per_sample);
+
+        let mut frame = BitWriter::default();
+        frame.put(0b11_1111_1111_1110, 14); // sync code
+        frame.put(0, 1); // reserved
+        frame.put(0, 1); // fixed block size
+        frame.put(0b0111, 4); // block size - 1 follows as 16 bits
+        let (rate_code, rate_extra) = sample_rate_code(self.format.sample_rate);
+        frame.put(u64::from(rate_code), 4);
+        frame.put(channels as u64 - 1, 4); // independent channels
+        frame.put(if bits == 16 { 0b100 } else { 0b110 }, 3);
+        frame.put(0, 1); // reserved
+        for byte in utf8_number(self.frame_number) {
+            frame.put(u64::from(byte), 8);
+        }
+        frame.put(block_size as u64 - 1, 16);
+        if let Some((value, len)) = rate_extra {
+            frame.put(u64::from(value), len);
+        }
+        let header_crc = crc8(frame.bytes());
+        frame.put(u64::from(header_crc), 8);
+
+        let mut channel = Vec::with_capacity(block_size);
+        for c in 0..channels {
+            channel.clear();
+            channel.extend(self.pending.iter().skip(c).step_by(channels));
+            write_subframe(&mut frame, &channel, bits);
+        }
+        frame.align();
+        let footer_crc = crc16(frame.bytes());
+        frame.put(u64::from(footer_crc), 16);
+
+        let bytes = frame.into_bytes();
+        self.out.write_all(&bytes)?;
+        self.min_frame_bytes = if self.frame_number == 0 {
+            bytes.len()
+        } else {
+            self.min_frame_bytes.min(bytes.len())
+        };
+        self.max_frame_bytes = self.max_frame_bytes.max(bytes.len());
+        self.frame_number += 1;
+        self.total_samples += block_size as u64;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// Sample rate code of a frame header, and the value that follows the
+/// header for rates without a code of their own
+fn sample_rate_code(rate: u32) -> (u8, Option<(u32, u32)>) {
+    match rate {
+        88_200 => (0b0001, None),
+        176_400 => (0b0010, None),
+        192_000 => (0b0011, None),
+        8_000 => (0b0100, None),
+        16_000 => (0b0101, None),
+        22_050 => (0b0110, None),
+        24_000 => (0b0111, None),
+        32_000 => (0b1000, None),
+        44_100 => (0b1001, None),
+        48_000 => (0b1010, None),
+        96_000 => (0b1011, None),
+        rate if rate <= 0xFFFF => (0b1101, Some((rate, 16))),
+        rate if rate % 10 == 0 && rate / 10 <= 0xFFFF => (0b1110, Some((rate / 10, 16))),
+        // Read from STREAMINFO
+        _ => (0b0000, None),
+    }
+}
+
+/// Encode one channel of a block as the smallest of the fixed predictors,
+/// or verbatim
+fn write_subframe(out: &mut BitWriter, samples: &[i32], bits: u32) {
+    let verbatim_bits = samples.len() as u64 * u64::from(bits);
+    let best = (0..=MAX_ORDER.min(samples.len().saturating_sub(1)))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (param, residual_bits) = rice_parameter(&residual);
+            (order, residual, param, residual_bits)
+        })
+        .min_by_key(|(order, _, _, residual_bits)| *order as u64 * u64::from(bits) + residual_bits);
+
+    match best {
+        Some((order, residual, param, residual_bits))
+            if order as u64 * u64::from(bits) + residual_bits < verbatim_bits =>
+        {
+            out.put(0, 1);
+            out.put(0b001_000 | order as u64, 6);
+            out.put(0, 1); // no wasted bits
+            for &sample in &samples[..order] {
+                out.put_signed(i64::from(sample), bits);
+            }
+            out.put(0b00, 2); // 4-bit Rice parameters
+            out.put(0, 4); // one partition
+            out.put(u64::from(param), 4);
+            for value in residual {
+                out.put_rice(fold(value), param);
+            }
+        }
+        _ => {
+            out.put(0, 1);
+            out.put(0b000_001, 6);
+            out.put(0, 1);
+            for &sample in samples {
+                out.put_signed(i64::from(sample), bits);
+            }
+        }
+    }
+}
+
+/// Prediction error of the fixed predictor of `order`, after its warm-up
+/// samples
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i64> {
+    let s = |i: usize| i64::from(samples[i]);
+    (order..samples.len())
+        .map(|i| match order {
+            0 => s(i),
+            1 => s(i) - s(i - 1),
+            2 => s(i) - 2 * s(i - 1) + s(i - 2),
+            3 => s(i) - 3 * s(i - 1) + 3 * s(i - 2) - s(i - 3),
+            _ => s(i) - 4 * s(i - 1) + 6 * s(i - 2) - 4 * s(i - 3) + s(i - 4),
+        })
+        .collect()
+}
+
+/// Map signed to unsigned: 0, -1, 1, -2, ... to 0, 1, 2, 3, ...
+fn fold(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// The Rice parameter that codes `residual` in the fewest bits, and that
+/// many bits
+fn rice_parameter(residual: &[i64]) -> (u32, u64) {
+    let folded: Vec<u64> = residual.iter().map(|&value| fold(value)).collect();
+    (0..=MAX_RICE_PARAM)
+        .map(|param| {
+            let bits = folded
+                .iter()
+                .map(|&value| (value >> param) + 1 + u64::from(param))
+                .sum::<u64>();
+            (param, bits)
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap_or((0, 0))
+}
+
+fn block_header(last: bool, block_type: u8, len: usize) -> [u8; 4] {
+    let len = len as u32;
+    [
+        (u8::from(last) << 7) | block_type,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+    ]
+}
+
+fn streaminfo(
+    format: FlacFormat,
+    total_samples: u64,
+    min_frame: usize,
+    max_frame: usize,
+) -> Vec<u8> {
+    let mut info = BitWriter::default();
+    info.put(BLOCK_SIZE as u64, 16); // min block size
+    info.put(BLOCK_SIZE as u64, 16); // max block size
+    info.put(min_frame as u64, 24);
+    info.put(max_frame as u64, 24);
+    info.put(u64::from(format.sample_rate), 20);
+    info.put(u64::from(format.channels) - 1, 3);
+    info.put(u64::from(format.bits_per_sample) - 1, 5);
+    info.put(total_samples, 36);
+    info.put(0, 64); // MD5 unknown
+    info.put(0, 64);
+    info.into_bytes()
+}
+
+fn vorbis_comment(tags: &[(&str, &str)]) -> Vec<u8> {
+    fn put_string(out: &mut Vec<u8>, text: &str) {
+        out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        out.extend_from_slice(text.as_bytes());
+    }
+    let mut out = Vec::new();
+    put_string(&mut out, VENDOR);
+    out.extend_from_slice(&(tags.len() as u32).to_le_bytes());
+    for (key, value) in tags {
+        put_string(&mut out, &format!("{}={}", key, value));
+    }
+    out
+}
+
+/// Frame number in the UTF-8-like variable-length coding of frame headers
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    // Continuation bytes carry 6 bits each, and each one added leaves a
+    // bit less room in the first byte next to its length marker
+    let mut continuation = Vec::new();
+    let mut rest = value;
+    let mut first_bits = 6;
+    while rest >= 1 << first_bits {
+        continuation.push(0x80 | (rest & 0x3F) as u8);
+        rest >>= 6;
+        first_bits -= 1;
+    }
+    let len = continuation.len() + 1;
+    let marker = !(0xFFu8 >> len);
+    let mut bytes = vec![marker | rest as u8];
+    bytes.extend(continuation.into_iter().rev());
+    bytes
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Big-endian bit packing
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits not yet in `bytes`, in the low `pending_bits` bits
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    /// Append the low `bits` bits of `value`
+    fn put(&mut self, value: u64, bits: u32) {
+        if bits > 32 {
+            self.put(value >> 32, bits - 32);
+            self.put(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        let mask = if bits == 0 {
+            0
+        } else {
+            u64::MAX >> (64 - bits)
+        };
+        self.pending = (self.pending << bits) | (value & mask);
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+    }
+
+    /// Append `value` as a two's complement number of `bits` bits
+    fn put_signed(&mut self, value: i64, bits: u32) {
+        self.put(value as u64, bits);
+    }
+
+    /// Append `value` Rice coded with `param`: the high part in unary, then
+    /// the low `param` bits
+    fn put_rice(&mut self, value: u64, param: u32) {
+        let mut quotient = value >> param;
+        while quotient >= 32 {
+            self.put(0, 32);
+            quotient -= 32;
+        }
+        self.put(1, quotient as u32 + 1);
+        self.put(value, param);
+    }
+
+    /// Pad with zeros to a whole byte
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.put(0, 8 - self.pending_bits);
+        }
+    }
+
+    /// The complete bytes so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FORMAT: FlacFormat = FlacFormat {
+        sample_rate: 44_100,
+        channels: 2,
+        bits_per_sample: 16,
+    };
+
+    #[test]
+    fn checksums_match_the_reference_values() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+
+    #[test]
+    fn frame_numbers_use_the_utf8_coding() {
+        assert_eq!(utf8_number(0x7F), vec![0x7F]);
+        assert_eq!(utf8_number(0x80), vec![0xC2, 0x80]);
+        assert_eq!(utf8_number(0x7FF), vec![0xDF, 0xBF]);
+        assert_eq!(utf8_number(0x800), vec![0xE0, 0xA0, 0x80]);
+    }
+
+    #[test]
+    fn rice_codes_are_msb_first() {
+        let mut out = BitWriter::default();
+        // 5 with parameter 1: quotient 2 as "001", remainder "1"
+        out.put_rice(5, 1);
+        out.put(0b1111, 4);
+        assert_eq!(out.into_bytes(), vec![0b0011_1111]);
+    }
+
+    #[test]
+    fn finish_fills_in_streaminfo() {
+        let samples: Vec<i32> = (0..5000 * 2).map(|i| (i % 300) - 150).collect();
+        let mut writer =
+            FlacWriter::new(Cursor::new(Vec::new()), FORMAT, &[("TITLE", "Song")]).unwrap();
+        writer.write(&samples).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        assert_eq!(&data[..4], b"fLaC");
+        let info = &data[8..42];
+        // Sample rate, channels - 1 and bits - 1 in 20 + 3 + 5 bits, then
+        // the 36-bit total
+        assert_eq!(
+            u32::from_be_bytes([0, info[10], info[11], info[12]]) >> 4,
+            44_100
+        );
+        assert_eq!((info[12] >> 1) & 0b111, 1);
+        let total = (u64::from(info[13] & 0x0F) << 32)
+            | u64::from(u32::from_be_bytes([info[14], info[15], info[16], info[17]]));
+        assert_eq!(total, 5000);
+        // The first frame follows the tags, starting with its sync code
+        let min_frame = u32::from_be_bytes([0, info[4], info[5], info[6]]);
+        assert!(min_frame > 0);
+        let comment_len = u32::from_be_bytes([0, data[43], data[44], data[45]]) as usize;
+        let first_frame = 46 + comment_len;
+        assert_eq!(&data[first_frame..first_frame + 2], &[0xFF, 0xF8]);
+    }
+
+    #[test]
+    fn rejects_formats_flac_cannot_hold() {
+        let format = FlacFormat {
+            bits_per_sample: 32,
+            ..FORMAT
+        };
+        assert!(FlacWriter::new(Cursor::new(Vec::new()), format, &[]).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+//! Recording what the main player plays
+//!
+//! While switched on with [`set_enabled`], the audio the main player
+//! receives is written to one FLAC file per track in the recordings folder,
+//! tagged with the title, artist and album from the server's metadata. The
+//! audio is taken as it arrives, before volume, EQ or any other processing.
+//! Recording is never a saved setting: it is off again after a restart.
+//!
+//! A new file starts whenever the metadata names another track, so the split
+//! between files is only as exact as the metadata's timing; a seek or pause
+//! carries on in the same file.
+//!
+//! Files are encoded and written on a thread of their own. Once the FLAC
+//! files in the folder reach `recording_quota_mb`, recording stops; nothing
+//! is ever deleted to make room.
+
+mod flac;
+
+use self::flac::{FlacFormat, FlacWriter};
+use super::now_playing_state::NowPlayingState;
+use super::pcm;
+use crate::now_playing::NowPlaying;
+use crate::settings;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// Default for `recording_quota_mb`
+pub const DEFAULT_QUOTA_MB: u32 = 4096;
+/// Folder in the user's music folder used when none is set
+const DEFAULT_DIR_NAME: &str = "Music Assistant Recordings";
+/// Samples written between checks of the quota, about 12 seconds of 44.1 kHz
+/// stereo
+const QUOTA_CHECK_SAMPLES: u64 = 1 << 20;
+/// Longest file name, before the extension and any numbering
+const MAX_NAME_CHARS: usize = 120;
+const QUOTA_REACHED: &str = "The recordings folder has reached its quota";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Queue of the writer thread, started on first use
+static QUEUE: Mutex<Option<Sender<Event>>> = Mutex::new(None);
+
+/// Start or stop recording. Stopping finishes the file being written.
+pub fn set_enabled(enabled: bool) {
+    let was_enabled = ENABLED.swap(enabled, Ordering::Relaxed);
+    if was_enabled && !enabled {
+        send(Event::Finish);
+    }
+    if was_enabled != enabled {
+        log::info!(
+            "[Recording] {}",
+            if enabled { "Started" } else { "Stopped" }
+        );
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether recording is on, where it goes and how much of the quota is used
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingStatus {
+    pub enabled: bool,
+    pub dir: Option<String>,
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+}
+
+pub fn status() -> RecordingStatus {
+    let dir = recordings_dir();
+    RecordingStatus {
+        enabled: is_enabled(),
+        used_bytes: dir.as_deref().map_or(0, used_bytes),
+        dir: dir.map(|dir| dir.to_string_lossy().into_owned()),
+        quota_bytes: quota_bytes(),
+    }
+}
+
+/// The folder recordings go to: the one set in the settings, or one in the
+/// user's music folder
+pub fn recordings_dir() -> Option<PathBuf> {
+    settings::get_settings()
+        .recording_dir
+        .map(PathBuf::from)
+        .or_else(|| {
+            dirs::audio_dir()
+                .or_else(dirs::home_dir)
+                .map(|dir| dir.join(DEFAULT_DIR_NAME))
+        })
+}
+
+fn quota_bytes() -> u64 {
+    u64::from(settings::get_settings().recording_quota_mb) * 1024 * 1024
+}
+
+/// Size of the FLAC files in `dir`
+fn used_bytes(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+        })
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+enum Event {
+    /// Finish any file and start one for a new track
+    Start {
+        format: FlacFormat,
+        tags: Vec<(&'static str, String)>,
+        name: String,
+    },
+    /// Interleaved samples of the current track
+    Samples(Vec<i32>),
+    Finish,
+}
+
+fn send(event: Event) {
+    if let Ok(mut queue) = QUEUE.lock() {
+        let queue = queue.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || run(&rx));
+            tx
+        });
+        let _ = queue.send(event);
+    }
+}
+
+/// Feeds the main player's stream to the writer thread, starting a file
+/// whenever the track or the stream format changes
+pub(crate) struct Recorder {
+    /// Track identity and format of the file being written
+    current: Option<(String, FlacFormat)>,
+}
+
+impl Recorder {
+    pub(crate) fn new() -> Self {
+        Self { current: None }
+    }
+
+    /// Record a chunk of the stream (little-endian PCM, 16 or 24 bits) as
+    /// part of the track `np_state` describes
+    pub(crate) fn push(
+        &mut self,
+        data: &[u8],
+        sample_rate: u32,
+        channels: u16,
+        bit_depth: u16,
+        np_state: &NowPlayingState,
+    ) {
+        if !is_enabled() {
+            // `set_enabled` already finished the file
+            self.current = None;
+            return;
+        }
+        let format = FlacFormat {
+            sample_rate,
+            channels,
+            bits_per_sample: bit_depth,
+        };
+        let track = np_state.track_identity();
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|(current, current_format)| *current != track || *current_format != format)
+        {
+            let now_playing = np_state.snapshot();
+            send(Event::Start {
+                format,
+                tags: tags(&now_playing),
+                name: file_name(&now_playing),
+            });
+            self.current = Some((track, format));
+        }
+        send(Event::Samples(pcm::to_i32(data, bit_depth)));
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.current.is_some() {
+            send(Event::Finish);
+        }
+    }
+}
+
+fn tags(now_playing: &NowPlaying) -> Vec<(&'static str, String)> {
+    [
+        ("TITLE", &now_playing.track),
+        ("ARTIST", &now_playing.artist),
+        ("ALBUM", &now_playing.album),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value.clone()?)))
+    .collect()
+}
+
+/// "Artist - Title", safe to use as a file name on every platform
+fn file_name(now_playing: &NowPlaying) -> String {
+    let name = match (&now_playing.artist, &now_playing.track) {
+        (Some(artist), Some(track)) => format!("{} - {}", artist, track),
+        (None, Some(track)) => track.clone(),
+        _ => "Recording".to_string(),
+    };
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_NAME_CHARS)
+        .collect();
+    // Windows drops trailing dots and spaces, and a leading dot hides the
+    // file elsewhere
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        "Recording".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// A file being recorded
+struct Recording {
+    writer: FlacWriter<BufWriter<File>>,
+    path: PathBuf,
+    /// Size of the other recordings in the folder when this one started
+    others_bytes: u64,
+    quota_bytes: u64,
+    samples: u64,
+    /// Samples since the quota was last checked
+    unchecked_samples: u64,
+}
+
+impl Recording {
+    fn start(format: FlacFormat, tags: &[(&str, String)], name: &str) -> Result<Self, String> {
+        let dir = recordings_dir().ok_or("No folder to record to")?;
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let others_bytes = used_bytes(&dir);
+        let quota_bytes = quota_bytes();
+        if others_bytes >= quota_bytes {
+            return Err(QUOTA_REACHED.to_string());
+        }
+        let (file, path) = create_unique(&dir, name)?;
+        let tags: Vec<(&str, &str)> = tags.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let writer = FlacWriter::new(BufWriter::new(file), format, &tags)
+            .map_err(|e| format!("Failed to start {}: {}", path.display(), e))?;
+        log::info!("[Recording] Recording to {}", path.display());
+        Ok(Self {
+            writer,
+            path,
+            others_bytes,
+            quota_bytes,
+            samples: 0,
+            unchecked_samples: 0,
+        })
+    }
+
+    /// Write `samples`; fails once the quota is reached
+    fn write(&mut self, samples: &[i32]) -> Result<(), String> {
+        self.writer
+            .write(samples)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+        self.samples += samples.len() as u64;
+        self.unchecked_samples += samples.len() as u64;
+        if self.unchecked_samples >= QUOTA_CHECK_SAMPLES {
+            self.unchecked_samples = 0;
+            let written = self
+                .writer
+                .bytes_written()
+                .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+            if self.others_bytes + written >= self.quota_bytes {
+                return Err(QUOTA_REACHED.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete the file, or remove it if it holds no audio
+    fn finish(self) {
+        if self.samples == 0 {
+            drop(self.writer);
+            let _ = fs::remove_file(&self.path);
+            return;
+        }
+        match self.writer.finish() {
+            Ok(_) => log::info!("[Recording] Saved {}", self.path.display()),
+            Err(e) => log::warn!(
+                "[Recording] Failed to finish {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Create `<name>.flac` in `dir`, or `<name> (2).flac` and so on if taken
+fn create_unique(dir: &Path, name: &str) -> Result<(File, PathBuf), String> {
+    for n in 1..1000 {
+        let path = if n == 1 {
+            dir.join(format!("{}.flac", name))
+        } else {
+            dir.join(format!("{} ({}).flac", name, n))
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(format!("Failed to create {}: {}", path.display(), e)),
+        }
+    }
+    Err(format!("Too many recordings named {}", name))
+}
+
+fn run(rx: &Receiver<Event>) {
+    let mut recording: Option<Recording> = None;
+    for event in rx {
+        match event {
+            Event::Start { format, tags, name } => {
+                if let Some(done) = recording.take() {
+                    done.finish();
+                }
+                match Recording::start(format, &tags, &name) {
+                    Ok(started) => recording = Some(started),
+                    Err(e) => stop(&e),
+                }
+            }
+            Event::Samples(samples) => {
+                if let Some(current) = recording.as_mut() {
+                    if let Err(e) = current.write(&samples) {
+                        if let Some(done) = recording.take() {
+                            done.finish();
+                        }
+                        stop(&e);
+                    }
+                }
+            }
+            Event::Finish => {
+                if let Some(done) = recording.take() {
+                    done.finish();
+                }
+            }
+        }
+    }
+}
+
+/// Stop recording after a failure, rather than retrying on every track
+fn stop(reason: &str) {
+    log::warn!("[Recording] Stopped: {}", reason);
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_are_safe() {
+        let now_playing = NowPlaying {
+            track: Some("What? / Why: \"Now\".".to_string()),
+            artist: Some("AC/DC".to_string()),
+            ..NowPlaying::default()
+        };
+        assert_eq!(file_name(&now_playing), "AC_DC - What_ _ Why_ _Now_");
+        assert_eq!(file_name(&NowPlaying::default()), "Recording");
+        let dots = NowPlaying {
+            track: Some("...".to_string()),
+            ..NowPlaying::default()
+        };
+        assert_eq!(file_name(&dots), "Recording");
+    }
+
+    #[test]
+    fn names_are_numbered_when_taken() {
+        let dir = std::env::temp_dir().join(format!("recording-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let (_, first) = create_unique(&dir, "Song").unwrap();
+        let (_, second) = create_unique(&dir, "Song").unwrap();
+        assert_eq!(first.file_name().unwrap(), "Song.flac");
+        assert_eq!(second.file_name().unwrap(), "Song (2).flac");
+        assert_eq!(used_bytes(&dir), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_known_tags_are_written() {
+        let now_playing = NowPlaying {
+            track: Some("Song".to_string()),
+            album: Some("Album".to_string()),
+            ..NowPlaying::default()
+        };
+        assert_eq!(
+            tags(&now_playing),
+            vec![
+                ("TITLE", "Song".to_string()),
+                ("ALBUM", "Album".to_string())
+            ]
+        );
+    }
+}
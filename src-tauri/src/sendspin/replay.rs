@@ -0,0 +1,275 @@
+//! Replaying protocol traces
+//!
+//! [`Replay`] runs the client loop ([`run_authenticated_client`]) against an
+//! in-process mock server that sends what the server sent in a trace
+//! recorded with [`trace`](super::trace), at the pace it was recorded. A
+//! trace of a timing, metadata or stream lifecycle bug, trimmed down to the
+//! lines that matter and kept in `testdata`, becomes a regression test.
+//!
+//! Only one player's messages from the server are replayed: the first
+//! player in the trace. The [mock server](super::mock_server) answers the
+//! hello handshake and time sync itself, as traces don't hold them. Traced
+//! audio chunks carry only their size, so the mock sends silence of that
+//! size, with the traced timestamps moved onto its own clock; traced artwork
+//! is sent as that many zero bytes.
+
+use super::mock_server::MockServer;
+use super::stats::PlayerStats;
+use super::trace::FORMAT_VERSION;
+use super::*;
+use serde_json::Value;
+
+/// How far ahead of the mock's clock the first audio chunk is stamped to
+/// play
+const AUDIO_LEAD_US: i64 = 300_000;
+
+/// How long to keep the connection open after the last entry, for the
+/// client to act on it
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Something the server sent, as traced
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Message(Value),
+    Audio { timestamp: i64, bytes: usize },
+    Artwork { bytes: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    t_us: u64,
+    event: Event,
+}
+
+/// The server's side of a trace, ready to be replayed
+pub(super) struct Replay {
+    entries: Vec<Entry>,
+}
+
+/// What the client did during a replay
+pub(super) struct Outcome {
+    /// Messages the client sent, in order, apart from time sync
+    pub sent: Vec<Value>,
+    /// The player's now-playing state after the last entry
+    pub now_playing: Option<NowPlaying>,
+    /// The player's statistics after the last entry
+    pub stats: Option<PlayerStats>,
+}
+
+impl Outcome {
+    /// Messages of one type the client sent
+    pub fn sent_of_type<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.sent.iter().filter(move |msg| msg["type"] == kind)
+    }
+}
+
+/// Read the entries the server sent the first player in a trace
+fn parse(trace: &str) -> Result<Vec<Entry>, String> {
+    let mut lines = trace
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("The trace is empty")?;
+    let header: Value =
+        serde_json::from_str(header).map_err(|e| format!("Invalid trace header: {}", e))?;
+    if header["trace"] != "sendspin" {
+        return Err("Not a Sendspin protocol trace".to_string());
+    }
+    match header["version"].as_u64() {
+        Some(version) if (1..=u64::from(FORMAT_VERSION)).contains(&version) => {}
+        _ => return Err(format!("Unsupported trace version {}", header["version"])),
+    }
+
+    let mut player: Option<String> = None;
+    let mut entries = Vec::new();
+    for (index, line) in lines {
+        let malformed = || format!("Malformed trace entry on line {}", index + 1);
+        let line: Value = serde_json::from_str(line).map_err(|_| malformed())?;
+        let player_id = line["player"].as_str().ok_or_else(malformed)?;
+        if line["dir"] != "in" || *player.get_or_insert_with(|| player_id.to_string()) != player_id
+        {
+            continue;
+        }
+        let t_us = line["t_us"].as_u64().ok_or_else(malformed)?;
+        let event = if let Some(message) = line.get("message") {
+            // Answered by the mock itself
+            if matches!(
+                message["type"].as_str(),
+                Some("server/hello" | "server/time")
+            ) {
+                continue;
+            }
+            Event::Message(message.clone())
+        } else if let Some(audio) = line.get("audio") {
+            Event::Audio {
+                timestamp: audio["timestamp"].as_i64().ok_or_else(malformed)?,
+                bytes: audio["bytes"].as_u64().ok_or_else(malformed)? as usize,
+            }
+        } else if let Some(artwork) = line.get("artwork") {
+            Event::Artwork {
+                bytes: artwork["bytes"].as_u64().ok_or_else(malformed)? as usize,
+            }
+        } else {
+            return Err(malformed());
+        };
+        entries.push(Entry { t_us, event });
+    }
+    Ok(entries)
+}
+
+impl Replay {
+    pub(super) fn new(trace: &str) -> Result<Self, String> {
+        let entries = parse(trace)?;
+        if entries.is_empty() {
+            return Err("The trace holds nothing the server sent".to_string());
+        }
+        Ok(Self { entries })
+    }
+
+    /// Connect `instance` to a mock server replaying the trace, and report
+    /// what it did once the trace has played out
+    pub(super) async fn run(&self, instance: &SendspinClient) -> Result<Outcome, String> {
+        let server = MockServer::start().await?;
+        let config = server.config("ma_companion_replay");
+        *instance.inner.client.write() = Some(SendspinClientHandle::new(config.clone()));
+
+        let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
+        // Nothing is sent on these; held so they stay open for the run
+        let (_command_tx, command_rx) = mpsc::channel::<PlaybackCommand>(1);
+        let (_client_command_tx, client_command_rx) = mpsc::channel::<ClientCommand>(1);
+        let (_volume_change_tx, volume_change_rx) = mpsc::channel::<(u8, bool)>(1);
+
+        // Connected as by `run_client`, without the MA proxy auth
+        let client = async {
+            let mode = ResolvedVolumeMode::Software;
+            let ws_stream = connect_websocket(&config.server_url, Duration::from_secs(30))
+                .await
+                .map_err(|e| e.to_string())?;
+            let player_state = build_initial_player_state(mode, 50, false, 0, false);
+            let base_buffer_ms = player_state.min_buffer_ms.unwrap_or(MIN_BUFFER_MS);
+            let player_support = build_player_support(
+                fallback_supported_formats(),
+                supported_volume_commands(mode),
+            );
+            let connection = build_protocol_client_builder(&config, player_support, player_state)
+                .accept(ws_stream)
+                .await
+                .map_err(|e| format!("Sendspin protocol handshake failed: {}", e))?
+                .split();
+            run_authenticated_client(
+                instance,
+                connection,
+                config.clone(),
+                config.player_id.clone(),
+                shutdown_rx,
+                command_rx,
+                client_command_rx,
+                volume_change_rx,
+                mode,
+                50,
+                false,
+                base_buffer_ms,
+            )
+            .await
+            .map_err(|e| e.to_string())
+        };
+        let server = async {
+            let outcome = self.serve(&server, instance).await;
+            let _ = shutdown_tx.send(()).await;
+            outcome
+        };
+        let (client, outcome) = tokio::join!(client, server);
+        client?;
+        outcome
+    }
+
+    /// The mock server's side: send the entries at their traced times
+    async fn serve(
+        &self,
+        server: &MockServer,
+        instance: &SendspinClient,
+    ) -> Result<Outcome, String> {
+        let mut connection = server.accept().await?;
+        let started = Instant::now();
+        let first_t_us = self.entries[0].t_us;
+        // Moves traced audio timestamps onto the mock's clock
+        let mut audio_offset: Option<i64> = None;
+        for entry in &self.entries {
+            let due = started + Duration::from_micros(entry.t_us.saturating_sub(first_t_us));
+            tokio::time::sleep_until(due.into()).await;
+            match &entry.event {
+                Event::Message(message) => connection.send(message.clone()),
+                Event::Audio { timestamp, bytes } => {
+                    let offset = *audio_offset
+                        .get_or_insert_with(|| connection.now_us() + AUDIO_LEAD_US - timestamp);
+                    connection.audio_chunk(timestamp + offset, &vec![0; *bytes])
+                }
+                Event::Artwork { bytes } => connection.artwork(0, &vec![0; *bytes]),
+            }?;
+        }
+        tokio::time::sleep(SETTLE).await;
+
+        let mut sent = vec![connection.hello.clone()];
+        sent.extend(connection.drain());
+        Ok(Outcome {
+            sent,
+            now_playing: instance.now_playing(),
+            stats: instance.stats(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAPLESS_TRACK_CHANGE: &str = include_str!("testdata/gapless_track_change.jsonl");
+
+    #[test]
+    fn parse_keeps_what_the_server_sent_the_first_player() {
+        let entries = parse(GAPLESS_TRACK_CHANGE).unwrap();
+        assert_eq!(entries.len(), 15);
+        assert_eq!(entries[0].t_us, 1180);
+        assert!(matches!(&entries[2].event, Event::Message(msg) if msg["type"] == "stream/start"));
+        assert_eq!(
+            entries[3].event,
+            Event::Audio {
+                timestamp: 88_600_000,
+                bytes: 3840
+            }
+        );
+        assert!(entries
+            .iter()
+            .all(|e| !matches!(&e.event, Event::Message(msg) if msg["type"] == "client/state")));
+    }
+
+    #[test]
+    fn parse_rejects_what_is_not_a_trace() {
+        assert!(parse("").is_err());
+        assert!(parse("{\"type\":\"server/state\"}").is_err());
+        assert!(parse("{\"trace\":\"sendspin\",\"version\":99}").is_err());
+        let broken =
+            "{\"trace\":\"sendspin\",\"version\":1}\n{\"t_us\":1,\"player\":\"p\",\"dir\":\"in\"}";
+        assert_eq!(
+            parse(broken).unwrap_err(),
+            "Malformed trace entry on line 2"
+        );
+    }
+
+    #[test]
+    fn replayed_gapless_track_change_updates_now_playing() {
+        let replay = Replay::new(GAPLESS_TRACK_CHANGE).unwrap();
+        let instance = SendspinClient::new(false);
+        let outcome = tauri::async_runtime::block_on(replay.run(&instance)).unwrap();
+
+        assert_eq!(outcome.sent_of_type("client/hello").count(), 1);
+        let now_playing = outcome.now_playing.unwrap();
+        assert!(now_playing.is_playing);
+        assert_eq!(now_playing.track.as_deref(), Some("Freddie Freeloader"));
+        // Carried over from the first track's metadata
+        assert_eq!(now_playing.album.as_deref(), Some("Kind of Blue"));
+        let stats = outcome.stats.unwrap();
+        assert_eq!(stats.buffer.streams_started, 1);
+        assert_eq!(stats.buffer.chunks_received, 10);
+    }
+}
@@ -0,0 +1,122 @@
+//! The playback state reported to the server in `client/state`
+//!
+//! The protocol knows `synchronized` and `error`: the player either plays in
+//! sync or can't. It has no buffering state, so a buffer that ran dry is
+//! reported as an error until it has refilled to [`REFILLED`], or the stream
+//! is cleared, ends or pauses, when there's nothing left to play. Every
+//! `client/state` carries the current state, whatever else it updates.
+
+use std::time::Duration;
+
+/// Buffered audio after which a buffer that ran dry counts as recovered
+const REFILLED: Duration = Duration::from_millis(500);
+
+/// Something keeping the player from playing in sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Problem {
+    /// The clock isn't synchronized with the server's yet
+    ClockUnsynced,
+    /// The output device disappeared
+    DeviceLost,
+    /// Another application holds the output device
+    DeviceBusy,
+    /// The output device couldn't be opened for the stream's format
+    DeviceFailed,
+    /// The stream's codec or bit depth isn't supported
+    UnsupportedFormat,
+    /// The buffer ran dry
+    Underrun,
+}
+
+impl Problem {
+    const ALL: [Self; 6] = [
+        Self::ClockUnsynced,
+        Self::DeviceLost,
+        Self::DeviceBusy,
+        Self::DeviceFailed,
+        Self::UnsupportedFormat,
+        Self::Underrun,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            Self::ClockUnsynced => "clock not synchronized",
+            Self::DeviceLost => "output device lost",
+            Self::DeviceBusy => "output device busy",
+            Self::DeviceFailed => "output device failed to open",
+            Self::UnsupportedFormat => "unsupported stream format",
+            Self::Underrun => "buffer ran dry",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ReportedState {
+    /// Bits of the current problems
+    problems: u8,
+}
+
+impl ReportedState {
+    /// Set whether `problem` is present. Returns whether that turned the
+    /// state from synchronized to error or back, so it has to be reported.
+    pub(crate) fn set(&mut self, problem: Problem, present: bool) -> bool {
+        let was_error = self.is_error();
+        if present {
+            self.problems |= problem.bit();
+        } else {
+            self.problems &= !problem.bit();
+        }
+        self.is_error() != was_error
+    }
+
+    /// Whether the buffer counts as having run dry on a chunk's arrival,
+    /// given whether it had and how much is buffered with the chunk queued
+    pub(crate) fn underrun(&self, ran_dry: bool, buffered: Duration) -> bool {
+        ran_dry || (self.has(Problem::Underrun) && buffered < REFILLED)
+    }
+
+    pub(crate) fn has(&self, problem: Problem) -> bool {
+        self.problems & problem.bit() != 0
+    }
+
+    pub(crate) fn is_error(&self) -> bool {
+        self.problems != 0
+    }
+
+    /// The first of the current problems, for the log
+    pub(crate) fn problem(&self) -> Option<Problem> {
+        Problem::ALL.into_iter().find(|&problem| self.has(problem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_changes_between_synchronized_and_error() {
+        let mut state = ReportedState::default();
+        assert!(!state.is_error());
+        assert!(state.set(Problem::DeviceBusy, true));
+        assert!(!state.set(Problem::DeviceLost, true));
+        assert_eq!(state.problem(), Some(Problem::DeviceLost));
+        assert!(!state.set(Problem::DeviceLost, false));
+        assert!(state.set(Problem::DeviceBusy, false));
+        assert_eq!(state.problem(), None);
+        assert!(!state.set(Problem::DeviceBusy, false));
+    }
+
+    #[test]
+    fn underrun_lasts_until_the_buffer_refills() {
+        let mut state = ReportedState::default();
+        assert!(!state.underrun(false, Duration::ZERO));
+        assert!(state.underrun(true, Duration::from_millis(20)));
+        state.set(Problem::Underrun, true);
+        assert!(state.underrun(false, Duration::from_millis(100)));
+        assert!(!state.underrun(false, REFILLED));
+    }
+}
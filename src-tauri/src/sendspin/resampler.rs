@@ -0,0 +1,325 @@
+//! Sample-rate conversion for streams the output device can't open natively.
+//!
+//! The server only sends formats we advertised, but the output device can
+//! change underneath a session (default device switched, Bluetooth profile
+//! change), and shared-mode WASAPI in particular refuses any rate other than
+//! the mixer's. Instead of failing to create the player, the client loop
+//! converts such streams to the device rate with rubato before decoding.
+
+use super::pcm;
+use crate::settings::ResamplerQuality;
+use rubato::{
+    FastFixedIn, PolynomialDegree, Resampler, SincFixedIn, SincInterpolationParameters,
+    SincInterpolationType, WindowFunction,
+};
+
+/// Input frames fed to rubato per processing block.
+const CHUNK_FRAMES: usize = 1024;
+
+/// The concrete rubato resampler for the configured quality.
+enum Engine {
+    Fast(FastFixedIn<f32>),
+    Sinc(SincFixedIn<f32>),
+}
+
+impl Engine {
+    fn input_frames_next(&self) -> usize {
+        match self {
+            Self::Fast(r) => r.input_frames_next(),
+            Self::Sinc(r) => r.input_frames_next(),
+        }
+    }
+
+    fn output_delay(&self) -> usize {
+        match self {
+            Self::Fast(r) => r.output_delay(),
+            Self::Sinc(r) => r.output_delay(),
+        }
+    }
+
+    fn process(&mut self, block: &[Vec<f32>]) -> rubato::ResampleResult<Vec<Vec<f32>>> {
+        match self {
+            Self::Fast(r) => r.process(block, None),
+            Self::Sinc(r) => r.process(block, None),
+        }
+    }
+
+    /// Like `process`, for a block shorter than needed (padded with silence)
+    /// or, with `None`, silence alone
+    fn process_partial(
+        &mut self,
+        block: Option<&[Vec<f32>]>,
+    ) -> rubato::ResampleResult<Vec<Vec<f32>>> {
+        match self {
+            Self::Fast(r) => r.process_partial(block, None),
+            Self::Sinc(r) => r.process_partial(block, None),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::Fast(r) => r.reset(),
+            Self::Sinc(r) => r.reset(),
+        }
+    }
+}
+
+/// Streaming resampler for one interleaved PCM stream.
+pub(crate) struct StreamResampler {
+    inner: Engine,
+    channels: usize,
+    input_rate: u32,
+    output_rate: u32,
+    input_bit_depth: u16,
+    output_bit_depth: u16,
+    dither: pcm::Dither,
+    /// Per-channel input waiting for a full processing block.
+    pending: Vec<Vec<f32>>,
+    /// Timestamp (µs) of the first pending input frame.
+    pending_timestamp: Option<i64>,
+}
+
+impl StreamResampler {
+    pub(crate) fn new(
+        input_rate: u32,
+        output_rate: u32,
+        channels: u16,
+        input_bit_depth: u16,
+        output_bit_depth: u16,
+        quality: ResamplerQuality,
+    ) -> Result<Self, String> {
+        let channels = usize::from(channels);
+        let ratio = f64::from(output_rate) / f64::from(input_rate);
+        let inner = match quality {
+            ResamplerQuality::Fast => Engine::Fast(
+                FastFixedIn::<f32>::new(
+                    ratio,
+                    1.0,
+                    PolynomialDegree::Cubic,
+                    CHUNK_FRAMES,
+                    channels,
+                )
+                .map_err(|e| format!("Failed to create resampler: {e}"))?,
+            ),
+            ResamplerQuality::Balanced | ResamplerQuality::High => {
+                let (sinc_len, oversampling_factor, interpolation) =
+                    if quality == ResamplerQuality::High {
+                        (256, 256, SincInterpolationType::Cubic)
+                    } else {
+                        (128, 128, SincInterpolationType::Linear)
+                    };
+                let params = SincInterpolationParameters {
+                    sinc_len,
+                    f_cutoff: 0.95,
+                    interpolation,
+                    oversampling_factor,
+                    window: WindowFunction::BlackmanHarris2,
+                };
+                Engine::Sinc(
+                    SincFixedIn::<f32>::new(ratio, 1.0, params, CHUNK_FRAMES, channels)
+                        .map_err(|e| format!("Failed to create resampler: {e}"))?,
+                )
+            }
+        };
+
+        Ok(Self {
+            inner,
+            channels,
+            input_rate,
+            output_rate,
+            input_bit_depth,
+            output_bit_depth,
+            dither: pcm::Dither::new(),
+            pending: vec![Vec::new(); channels],
+            pending_timestamp: None,
+        })
+    }
+
+    pub(crate) fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Feed one chunk of little-endian PCM captured at `timestamp` (µs).
+    ///
+    /// Returns zero or more resampled chunks, each with the timestamp of its
+    /// first output frame, encoded at the output bit depth.
+    pub(crate) fn process(&mut self, timestamp: i64, bytes: &[u8]) -> Vec<(i64, Vec<u8>)> {
+        let samples = pcm::to_f32(bytes, self.input_bit_depth);
+        if self.pending_timestamp.is_none() || self.pending[0].is_empty() {
+            self.pending_timestamp = Some(timestamp);
+        }
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in self.pending.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+
+        let mut output = Vec::new();
+        loop {
+            let needed = self.inner.input_frames_next();
+            if self.pending[0].len() < needed {
+                break;
+            }
+            let block: Vec<Vec<f32>> = self
+                .pending
+                .iter_mut()
+                .map(|channel| channel.drain(..needed).collect())
+                .collect();
+            let block_timestamp = self.pending_timestamp.unwrap_or(timestamp);
+            self.pending_timestamp =
+                Some(block_timestamp + pcm::frames_to_micros(needed, self.input_rate));
+
+            match self.inner.process(&block) {
+                Ok(resampled) => {
+                    let frames = resampled.first().map_or(0, Vec::len);
+                    output.push(self.encode(block_timestamp, &resampled, frames));
+                }
+                Err(e) => {
+                    log::warn!("[Sendspin] Resampler failed, dropping block: {e}");
+                }
+            }
+        }
+        output
+    }
+
+    /// Resample the input still waiting for a full block, padded with
+    /// silence, and what the filter still holds, e.g. at the end of a
+    /// stream. Returns chunks like [`Self::process`] and starts over.
+    pub(crate) fn flush(&mut self) -> Vec<(i64, Vec<u8>)> {
+        let frames = self.pending[0].len();
+        let mut output = Vec::new();
+        let Some(mut timestamp) = self.pending_timestamp.filter(|_| frames > 0) else {
+            self.reset();
+            return output;
+        };
+        let ratio = f64::from(self.output_rate) / f64::from(self.input_rate);
+        // The tail comes out after the filter delay; the padding after it
+        // is dropped
+        let mut wanted = (frames as f64 * ratio).ceil() as usize + self.inner.output_delay();
+        let mut block: Option<Vec<Vec<f32>>> =
+            Some(self.pending.iter_mut().map(std::mem::take).collect());
+        while wanted > 0 {
+            let resampled = match self.inner.process_partial(block.take().as_deref()) {
+                Ok(resampled) => resampled,
+                Err(e) => {
+                    log::warn!("[Sendspin] Resampler failed, dropping the stream's tail: {e}");
+                    break;
+                }
+            };
+            let produced = resampled.first().map_or(0, Vec::len);
+            if produced == 0 {
+                break;
+            }
+            let frames = produced.min(wanted);
+            output.push(self.encode(timestamp, &resampled, frames));
+            timestamp += pcm::frames_to_micros(produced, self.output_rate);
+            wanted -= frames;
+        }
+        self.reset();
+        output
+    }
+
+    /// Interleave the first `frames` of resampled audio whose input started
+    /// at `timestamp` and encode them at the output depth
+    fn encode(&mut self, timestamp: i64, resampled: &[Vec<f32>], frames: usize) -> (i64, Vec<u8>) {
+        let mut interleaved = Vec::with_capacity(frames * self.channels);
+        for index in 0..frames {
+            for channel in resampled {
+                interleaved.push(channel[index]);
+            }
+        }
+        // Output lags the input by the filter delay; shift the timestamp
+        // back so resampled audio stays in sync.
+        let delay = pcm::frames_to_micros(self.inner.output_delay(), self.output_rate);
+        (
+            timestamp - delay,
+            self.dither
+                .quantize(&interleaved, self.channels, self.output_bit_depth),
+        )
+    }
+
+    /// Drop buffered input and filter state, e.g. after a seek.
+    pub(crate) fn reset(&mut self) {
+        self.inner.reset();
+        for channel in &mut self.pending {
+            channel.clear();
+        }
+        self.pending_timestamp = None;
+    }
+}
+
+/// Pick the rate to play a stream at: the stream's own rate if the device
+/// supports it, otherwise the device's native rate.
+pub(crate) fn target_rate(
+    stream_rate: u32,
+    device_rates: &[(u32, u32)],
+    native_rate: Option<u32>,
+) -> u32 {
+    let supported = device_rates
+        .iter()
+        .any(|&(min, max)| (min..=max).contains(&stream_rate));
+    if supported || device_rates.is_empty() && native_rate.is_none() {
+        return stream_rate;
+    }
+    native_rate
+        .or_else(|| device_rates.iter().map(|&(_, max)| max).max())
+        .unwrap_or(stream_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_rate_keeps_supported_stream_rate() {
+        assert_eq!(
+            target_rate(96_000, &[(44_100, 192_000)], Some(48_000)),
+            96_000
+        );
+    }
+
+    #[test]
+    fn target_rate_falls_back_to_native_rate() {
+        assert_eq!(
+            target_rate(96_000, &[(48_000, 48_000)], Some(48_000)),
+            48_000
+        );
+    }
+
+    #[test]
+    fn target_rate_without_capabilities_trusts_the_stream() {
+        assert_eq!(target_rate(96_000, &[], None), 96_000);
+    }
+
+    #[test]
+    fn resamples_to_expected_length() {
+        let mut resampler =
+            StreamResampler::new(96_000, 48_000, 2, 16, 16, ResamplerQuality::Fast).unwrap();
+        // One second of stereo 16-bit silence at 96 kHz
+        let input = vec![0u8; 96_000 * 2 * 2];
+        let output: usize = resampler
+            .process(0, &input)
+            .iter()
+            .map(|(_, bytes)| bytes.len() / 4)
+            .sum();
+        // Whole blocks only; the remainder stays pending
+        let expected = 48_000;
+        assert!(
+            output <= expected && output > expected - CHUNK_FRAMES,
+            "got {output} frames"
+        );
+
+        // The remainder, and the filter delay, come out when flushed
+        let tail: usize = resampler
+            .flush()
+            .iter()
+            .map(|(_, bytes)| bytes.len() / 4)
+            .sum();
+        let delay = resampler.inner.output_delay();
+        assert!(
+            (output + tail).abs_diff(expected + delay) <= 2,
+            "got {output} + {tail} frames"
+        );
+        assert!(resampler.flush().is_empty());
+    }
+}
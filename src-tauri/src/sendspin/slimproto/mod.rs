@@ -0,0 +1,745 @@
+//! Slimproto client backend.
+//!
+//! For users on MA's Squeezelite provider, the built-in player can connect
+//! the way squeezelite does instead of speaking Sendspin. It runs in place
+//! of the Sendspin connection inside the same reconnect loop, so it shares
+//! the output device, static delay, volume reporting and status handling.
+//!
+//! The server identifies Slimproto players by MAC address, so the player
+//! presents one derived from its Sendspin player ID; MA lists it under that
+//! address. Volume is the server's: it sends a gain, which is applied to the
+//! samples, and volume changes made here go through the MA API, as do
+//! transport controls, which Slimproto has no way to send.
+//!
+//! Only PCM streams are supported, so the player advertises nothing else.
+
+mod protocol;
+
+use super::http_stream::{self, PcmStream, StreamEvent};
+use super::resampler::StreamResampler;
+use super::timed_player::Player;
+use super::{
+    backend_address, backend_now_playing, capped_volume, forward_to_server,
+    output_layout_for_stream, pcm, publish_volume, save_volume_state, AudioFormat, ClientCommand,
+    Codec, ConnectionStatus, PlaybackCommand, ResolvedVolumeMode, SendspinClient, SendspinConfig,
+};
+use crate::now_playing::NowPlaying;
+use protocol::{ServerMessage, Status, StreamCommand, Strm};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How often playback progress is checked
+const TICK: Duration = Duration::from_millis(100);
+/// How often the server hears where playback is
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+/// How far ahead of playback audio is queued
+const READ_AHEAD: Duration = Duration::from_secs(10);
+/// Delay before the first audio of a stream plays, so the output doesn't
+/// run dry while the stream gets going
+const START_LEAD: Duration = Duration::from_millis(500);
+/// The server's volume curve: 50 dB from volume 0 to 100, so a step is
+/// just under half a decibel
+const DB_PER_STEP: f32 = 50.0 / 101.0;
+
+/// What the player tells the server it can play
+const CAPABILITIES: &str =
+    "Model=squeezelite,ModelName=Music Assistant Desktop,MaxSampleRate=192000,pcm";
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// MAC address and UUID to present for `player_id`. The MAC is a locally
+/// administered unicast one.
+fn player_identity(player_id: &str) -> ([u8; 6], [u8; 16]) {
+    let hash = Sha256::digest(player_id.as_bytes());
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(&hash[..6]);
+    mac[0] = (mac[0] | 0x02) & !0x01;
+    let mut uuid = [0u8; 16];
+    uuid.copy_from_slice(&hash[6..22]);
+    (mac, uuid)
+}
+
+/// How the server names a player with `mac`
+fn mac_to_string(mac: [u8; 6]) -> String {
+    mac.iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Volume (0-100) for the linear `gain` the server sent
+fn volume_for_gain(gain: f32) -> u8 {
+    if gain <= 0.0 {
+        return 0;
+    }
+    let db = 20.0 * gain.log10();
+    (100.0 + db / DB_PER_STEP).round().clamp(0.0, 100.0) as u8
+}
+
+/// Linear gain the server sends for `volume` (0-100)
+fn gain_for_volume(volume: u8) -> f32 {
+    if volume == 0 {
+        return 0.0;
+    }
+    10f32.powf((f32::from(volume) - 100.0) * DB_PER_STEP / 20.0)
+}
+
+/// Read messages off the connection until it closes or breaks.
+async fn read_messages(mut reader: OwnedReadHalf, tx: mpsc::Sender<Result<ServerMessage, String>>) {
+    let result = async {
+        loop {
+            let mut length = [0u8; 2];
+            reader
+                .read_exact(&mut length)
+                .await
+                .map_err(|e| format!("Connection to Slimproto server lost: {}", e))?;
+            let mut frame = vec![0u8; usize::from(u16::from_be_bytes(length))];
+            reader
+                .read_exact(&mut frame)
+                .await
+                .map_err(|e| format!("Connection to Slimproto server lost: {}", e))?;
+            let Some((command, payload)) = frame.split_first_chunk::<4>() else {
+                return Err("Slimproto message without a command".to_string());
+            };
+            let message = ServerMessage::parse(command, payload)?;
+            if tx.send(Ok(message)).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    .await;
+    if let Err(e) = result {
+        let _ = tx.send(Err(e)).await;
+    }
+}
+
+/// A stream being fetched
+struct ActiveStream {
+    events: mpsc::Receiver<StreamEvent>,
+    task: JoinHandle<()>,
+    pcm: PcmStream,
+    resampler: Option<StreamResampler>,
+    autostart: bool,
+    threshold_bytes: u64,
+    bytes_received: u64,
+    /// Whether the server was told the threshold was reached
+    threshold_sent: bool,
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A track from the moment its stream starts until it has played out
+struct Track {
+    /// Output position its first frame plays at, once queued
+    first_frame: Option<u64>,
+    started: bool,
+    /// Its stream has been read to the end
+    ended: bool,
+}
+
+/// The Slimproto connection's half of the client loop
+struct Session {
+    writer: OwnedWriteHalf,
+    origin: Instant,
+    host: String,
+    server_timestamp: u32,
+}
+
+impl Session {
+    /// Milliseconds since the connection started, the player's clock
+    fn jiffies(&self) -> u32 {
+        self.origin.elapsed().as_millis() as u32
+    }
+
+    /// Microseconds on the playback clock
+    fn now(&self) -> i64 {
+        self.origin.elapsed().as_micros() as i64
+    }
+
+    async fn send(&mut self, message: Vec<u8>) -> Result<(), String> {
+        self.writer
+            .write_all(&message)
+            .await
+            .map_err(|e| format!("Failed to write to Slimproto server: {}", e))
+    }
+}
+
+/// Where playback is, for a `STAT` message
+fn status(
+    session: &Session,
+    output: Option<&Player>,
+    stream: Option<&ActiveStream>,
+    track: Option<&Track>,
+    sample_rate: u32,
+) -> Status {
+    let (position, buffered) = output.map_or((0, Duration::ZERO), |player| {
+        let schedule = player.schedule();
+        (schedule.position(), schedule.buffered())
+    });
+    let elapsed_frames = track
+        .and_then(|track| track.first_frame)
+        .map_or(0, |first| position.saturating_sub(first));
+    Status {
+        bytes_received: stream.map_or(0, |s| s.bytes_received),
+        jiffies: session.jiffies(),
+        output_buffer_size: READ_AHEAD.as_millis() as u32,
+        output_buffer_fullness: buffered.as_millis() as u32,
+        elapsed_ms: (elapsed_frames * 1_000 / u64::from(sample_rate.max(1))) as u32,
+        server_timestamp: session.server_timestamp,
+    }
+}
+
+/// Run one Slimproto connection for `instance` until it drops or
+/// `shutdown_rx` fires.
+pub(super) async fn run_client(
+    instance: &SendspinClient,
+    config: SendspinConfig,
+    player_id: String,
+    mut shutdown_rx: mpsc::Receiver<()>,
+    mut command_rx: mpsc::Receiver<PlaybackCommand>,
+    mut client_command_rx: mpsc::Receiver<ClientCommand>,
+) -> Result<(), BoxError> {
+    let settings = crate::settings::get_settings();
+    let (host, port) = backend_address(
+        settings.slimproto_server.as_deref(),
+        &config.server_url,
+        protocol::DEFAULT_PORT,
+    )?;
+    let (mac, uuid) = player_identity(&player_id);
+    let server_player_id = mac_to_string(mac);
+    log::info!(
+        "[Slimproto] Connecting to {}:{} as player {}",
+        host,
+        port,
+        server_player_id
+    );
+    let keepalive_timeout = Duration::from_secs(u64::from(settings.keepalive_timeout_secs));
+    let tcp = tokio::time::timeout(keepalive_timeout, TcpStream::connect((host.as_str(), port)))
+        .await
+        .map_err(|_| "Timed out connecting to Slimproto server".to_string())?
+        .map_err(|e| format!("Slimproto connection failed: {}", e))?;
+    let _ = tcp.set_nodelay(true);
+    let (reader, writer) = tcp.into_split();
+    let (message_tx, mut message_rx) = mpsc::channel(64);
+    let reader_task = tokio::spawn(read_messages(reader, message_tx));
+
+    let mut session = Session {
+        writer,
+        origin: Instant::now(),
+        host,
+        server_timestamp: 0,
+    };
+    session
+        .send(protocol::helo(mac, uuid, CAPABILITIES))
+        .await?;
+
+    instance.update_status(ConnectionStatus::Connected);
+    log::info!(
+        "[Slimproto] Connected to Slimproto server (player {})",
+        server_player_id
+    );
+
+    let result = run_session(
+        instance,
+        &config,
+        &server_player_id,
+        &mut session,
+        &mut message_rx,
+        &mut shutdown_rx,
+        &mut command_rx,
+        &mut client_command_rx,
+    )
+    .await;
+
+    reader_task.abort();
+    instance.publish_now_playing(NowPlaying::default());
+    instance.update_status(ConnectionStatus::Disconnected);
+    result
+}
+
+/// The next event of the stream being fetched, if any
+async fn next_stream_event(stream: &mut Option<ActiveStream>) -> Option<StreamEvent> {
+    match stream {
+        Some(stream) => stream.events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_session(
+    instance: &SendspinClient,
+    config: &SendspinConfig,
+    player_id: &str,
+    session: &mut Session,
+    message_rx: &mut mpsc::Receiver<Result<ServerMessage, String>>,
+    shutdown_rx: &mut mpsc::Receiver<()>,
+    command_rx: &mut mpsc::Receiver<PlaybackCommand>,
+    client_command_rx: &mut mpsc::Receiver<ClientCommand>,
+) -> Result<(), BoxError> {
+    let additional_player = (!instance.is_primary()).then_some(player_id);
+    let mut static_delay_ms = super::clamp_static_delay_ms(config.sync_delay_ms);
+    // Until the server sets it
+    let mut gain = 1.0f32;
+    let mut volume: Option<u8> = None;
+    let mut muted = false;
+
+    // The open output, with its channel count and rate
+    let mut output: Option<(Player, u16, u32)> = None;
+    // Output frames queued since it opened
+    let mut queued_frames: u64 = 0;
+    let mut stream: Option<ActiveStream> = None;
+    let mut track: Option<Track> = None;
+    let mut paused = false;
+    // End of a brief pause the server asked for
+    let mut resume_at: Option<Instant> = None;
+    let mut last_status = Instant::now();
+    let mut tick = tokio::time::interval(TICK);
+    instance.publish_now_playing(backend_now_playing(config, player_id, false));
+
+    loop {
+        // Stop reading ahead once enough is queued; the fetch then waits
+        let read_ahead = output
+            .as_ref()
+            .is_none_or(|(player, _, _)| player.schedule().buffered() < READ_AHEAD);
+
+        tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            _ = tick.tick() => {
+                if resume_at.is_some_and(|at| at <= Instant::now()) {
+                    resume_at = None;
+                    paused = false;
+                    if let Some((ref player, _, _)) = output {
+                        player.schedule().resume_at(session.now());
+                    }
+                }
+                let Some((ref player, _, rate)) = output else {
+                    continue;
+                };
+                let (position, buffered) = {
+                    let schedule = player.schedule();
+                    (schedule.position(), schedule.buffered())
+                };
+                instance.inner.stats.set_buffered(buffered, 0);
+                let mut event: Option<&[u8; 4]> = None;
+                if let Some(ref mut current) = track {
+                    if !current.started && current.first_frame.is_some_and(|first| position > first) {
+                        current.started = true;
+                        event = Some(b"STMs");
+                        instance.inner.stats.record_stream_start();
+                        instance.publish_now_playing(backend_now_playing(config, player_id, true));
+                    } else if current.started && current.ended && buffered.is_zero() {
+                        // Played out
+                        event = Some(b"STMu");
+                        track = None;
+                        instance.publish_now_playing(backend_now_playing(config, player_id, false));
+                    } else if current.started && !paused && last_status.elapsed() >= STATUS_INTERVAL {
+                        event = Some(b"STMt");
+                    }
+                }
+                if let Some(event) = event {
+                    let status = status(session, Some(player), stream.as_ref(), track.as_ref(), rate);
+                    session.send(protocol::stat(event, &status)).await?;
+                    last_status = Instant::now();
+                }
+            }
+            Some(message) = message_rx.recv() => {
+                match message? {
+                    ServerMessage::Strm(strm) => {
+                        let command = strm.command;
+                        let rate = output.as_ref().map_or(0, |(_, _, rate)| *rate);
+                        handle_strm(
+                            strm,
+                            session,
+                            output.as_ref().map(|(player, _, _)| player),
+                            rate,
+                            &mut stream,
+                            &mut track,
+                            &mut paused,
+                            &mut resume_at,
+                        )
+                        .await?;
+                        if matches!(
+                            command,
+                            StreamCommand::Pause
+                                | StreamCommand::Unpause
+                                | StreamCommand::Stop
+                                | StreamCommand::Flush
+                        ) {
+                            let is_playing = track.as_ref().is_some_and(|t| t.started) && !paused;
+                            instance.publish_now_playing(backend_now_playing(config, player_id, is_playing));
+                        }
+                    }
+                    ServerMessage::Audg(update) => {
+                        gain = update.map_or(1.0, |(left, right)| left.max(right));
+                        let mut level = volume_for_gain(gain);
+                        let capped = capped_volume(level);
+                        if capped < level {
+                            // Above the maximum volume; play at the maximum
+                            // and have the server show that
+                            level = capped;
+                            gain = gain_for_volume(level);
+                            forward_to_server(player_id, PlaybackCommand::SetVolume(level));
+                        }
+                        if let Some((ref player, _, _)) = output {
+                            player.schedule().set_gain(if muted { 0.0 } else { gain });
+                        }
+                        if volume != Some(level) {
+                            volume = Some(level);
+                            if additional_player.is_none() {
+                                publish_volume(level);
+                            }
+                            save_volume_state(ResolvedVolumeMode::Software, additional_player, level, muted);
+                        }
+                    }
+                    ServerMessage::Aude { enabled } => {
+                        muted = !enabled;
+                        if let Some((ref player, _, _)) = output {
+                            player.schedule().set_gain(if muted { 0.0 } else { gain });
+                        }
+                        if let Some(level) = volume {
+                            save_volume_state(ResolvedVolumeMode::Software, additional_player, level, muted);
+                        }
+                    }
+                    ServerMessage::SetName { name: None } => {
+                        session.send(protocol::setd_name(&config.player_name)).await?;
+                    }
+                    ServerMessage::SetName { name: Some(name) } => {
+                        // The name is set in the app's settings
+                        log::debug!("[Slimproto] Server named the player {:?}", name);
+                    }
+                    ServerMessage::Other => {}
+                }
+            }
+            Some(event) = next_stream_event(&mut stream), if read_ahead => {
+                let Some(ref mut active) = stream else {
+                    continue;
+                };
+                match event {
+                    StreamEvent::Connected => {
+                        session.send(protocol::stat(b"STMc", &Status::default())).await?;
+                    }
+                    StreamEvent::Headers(headers) => {
+                        session.send(protocol::resp(&headers)).await?;
+                    }
+                    StreamEvent::Data(bytes) => {
+                        active.bytes_received += bytes.len() as u64;
+                        let had_format = active.pcm.format().is_some();
+                        let frames = match active.pcm.feed(&bytes) {
+                            Ok(frames) => frames,
+                            Err(e) => {
+                                log::warn!("[Slimproto] {}", e);
+                                stream = None;
+                                session.send(protocol::stat(b"STMn", &Status::default())).await?;
+                                continue;
+                            }
+                        };
+                        let Some(format) = active.pcm.format() else {
+                            continue;
+                        };
+                        if !had_format {
+                            let stream_format = AudioFormat {
+                                codec: Codec::Pcm,
+                                sample_rate: format.sample_rate,
+                                channels: format.channels,
+                                bit_depth: format.bit_depth,
+                                codec_header: None,
+                            };
+                            let (_, output_rate, _) = output_layout_for_stream(
+                                config.audio_device_id.as_deref(),
+                                &stream_format,
+                                false,
+                            );
+                            log::info!(
+                                "[Slimproto] Stream format: channels={}, sample_rate={}, bit_depth={}",
+                                format.channels,
+                                format.sample_rate,
+                                format.bit_depth
+                            );
+                            if output_rate != format.sample_rate {
+                                let quality = crate::settings::get_settings().resampler_quality;
+                                match StreamResampler::new(format.sample_rate, output_rate, format.channels, format.bit_depth, format.bit_depth, quality) {
+                                    Ok(r) => {
+                                        log::info!("[Slimproto] Resampling {}Hz to {}Hz", format.sample_rate, r.output_rate());
+                                        active.resampler = Some(r);
+                                    }
+                                    Err(e) => log::warn!("[Slimproto] {e}; playing at the stream rate"),
+                                }
+                            }
+                            let rate = active
+                                .resampler
+                                .as_ref()
+                                .map_or(format.sample_rate, StreamResampler::output_rate);
+                            // A new layout needs the output reopened; anything
+                            // still queued in the old one is cut short
+                            if !output.as_ref().is_some_and(|(_, c, r)| (*c, *r) == (format.channels, rate)) {
+                                output = None;
+                                let device_id = config.audio_device_id.clone();
+                                let origin = session.origin;
+                                let channels = format.channels;
+                                let player = tokio::task::spawn_blocking(move || {
+                                    Player::open(device_id.as_deref(), channels, rate, origin)
+                                })
+                                .await
+                                .map_err(|e| e.to_string())??;
+                                {
+                                    let mut schedule = player.schedule();
+                                    schedule.set_gain(if muted { 0.0 } else { gain });
+                                    schedule.set_static_delay(static_delay_ms);
+                                    if paused {
+                                        schedule.pause();
+                                    }
+                                }
+                                output = Some((player, format.channels, rate));
+                                queued_frames = 0;
+                            }
+                        }
+                        if frames.is_empty() {
+                            continue;
+                        }
+                        let Some((ref player, channels, _)) = output else {
+                            continue;
+                        };
+                        let data = match active.resampler {
+                            Some(ref mut r) => r
+                                .process(0, &frames)
+                                .into_iter()
+                                .flat_map(|(_, data)| data)
+                                .collect(),
+                            None => frames,
+                        };
+                        let samples = pcm::to_f32(&data, format.bit_depth);
+                        let count = samples.len() / usize::from(channels.max(1));
+                        if let Some(ref mut current) = track {
+                            current.first_frame.get_or_insert(queued_frames);
+                        }
+                        {
+                            let mut schedule = player.schedule();
+                            // Straight after what's queued, unless that has
+                            // run out
+                            let earliest = session.now() + START_LEAD.as_micros() as i64;
+                            let at = match schedule.end() {
+                                Some(end) if paused || end >= earliest => end,
+                                _ => earliest,
+                            };
+                            schedule.push(at, samples);
+                        }
+                        queued_frames += count as u64;
+                        if !active.autostart
+                            && !active.threshold_sent
+                            && active.bytes_received >= active.threshold_bytes
+                        {
+                            active.threshold_sent = true;
+                            session.send(protocol::stat(b"STMl", &Status::default())).await?;
+                        }
+                    }
+                    StreamEvent::Ended => {
+                        let threshold_pending = !active.autostart && !active.threshold_sent;
+                        stream = None;
+                        if let Some(ref mut current) = track {
+                            current.ended = true;
+                        }
+                        if threshold_pending {
+                            session.send(protocol::stat(b"STMl", &Status::default())).await?;
+                        }
+                        // Ready for the next track
+                        session.send(protocol::stat(b"STMd", &Status::default())).await?;
+                        session.send(protocol::dsco(0)).await?;
+                    }
+                    StreamEvent::Failed(e) => {
+                        log::warn!("[Slimproto] {}", e);
+                        stream = None;
+                        if let Some(ref mut current) = track {
+                            current.ended = true;
+                        }
+                        session.send(protocol::stat(b"STMn", &Status::default())).await?;
+                    }
+                }
+            }
+            Some(command) = command_rx.recv() => {
+                forward_to_server(player_id, command);
+            }
+            Some(command) = client_command_rx.recv() => {
+                match command {
+                    ClientCommand::SetStaticDelay(delay_ms) => {
+                        log::debug!("[Slimproto] Applying static delay: {}ms", delay_ms);
+                        static_delay_ms = delay_ms;
+                        if let Some((ref player, _, _)) = output {
+                            player.schedule().set_static_delay(delay_ms);
+                        }
+                    }
+                    ClientCommand::ReloadDsp => {
+                        log::debug!("[Slimproto] DSP settings don't apply to Slimproto playback");
+                    }
+                    ClientCommand::SetMirrorDelay(_) => {
+                        log::debug!("[Slimproto] Mirrored output doesn't apply to Slimproto playback");
+                    }
+                    ClientCommand::Rename(name) => {
+                        log::info!("[Slimproto] Player name {} applies from the next connection", name);
+                    }
+                    ClientCommand::SwitchOutputDevice(_) => {
+                        log::info!("[Slimproto] Reconnecting to play to the new output device");
+                        instance.restart_in_background();
+                    }
+                    ClientCommand::ApplyMaxVolume => {
+                        // The server sends the lowered volume back as a gain
+                        if let Some(level) = volume.filter(|&level| capped_volume(level) < level) {
+                            forward_to_server(player_id, PlaybackCommand::SetVolume(capped_volume(level)));
+                        }
+                    }
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
+
+/// Carry out a `strm` command.
+#[allow(clippy::too_many_arguments)]
+async fn handle_strm(
+    strm: Strm,
+    session: &mut Session,
+    player: Option<&Player>,
+    rate: u32,
+    stream: &mut Option<ActiveStream>,
+    track: &mut Option<Track>,
+    paused: &mut bool,
+    resume_at: &mut Option<Instant>,
+) -> Result<(), String> {
+    match strm.command {
+        StreamCommand::Start => {
+            *stream = None;
+            if strm.format != b'p' {
+                log::warn!(
+                    "[Slimproto] Server sent a {:?} stream; only PCM is supported",
+                    char::from(strm.format)
+                );
+                session
+                    .send(protocol::stat(b"STMn", &Status::default()))
+                    .await?;
+                return Ok(());
+            }
+            let host = if strm.server_ip == 0 {
+                session.host.clone()
+            } else {
+                std::net::Ipv4Addr::from(strm.server_ip).to_string()
+            };
+            log::info!(
+                "[Slimproto] Starting stream from {}:{}",
+                host,
+                strm.server_port
+            );
+            let (events_tx, events) = mpsc::channel(4);
+            let task = tokio::spawn(http_stream::fetch(
+                host,
+                strm.server_port,
+                strm.request,
+                events_tx,
+            ));
+            *stream = Some(ActiveStream {
+                events,
+                task,
+                pcm: PcmStream::new(strm.pcm),
+                resampler: None,
+                autostart: strm.autostart,
+                threshold_bytes: u64::from(strm.threshold_kb) * 1024,
+                bytes_received: 0,
+                threshold_sent: false,
+            });
+            *track = Some(Track {
+                first_frame: None,
+                started: false,
+                ended: false,
+            });
+            // Without autostart the server unpauses once the threshold is
+            // buffered; a track following on from another just queues
+            let idle = player.is_none_or(|player| player.schedule().buffered().is_zero());
+            if !strm.autostart && idle {
+                *paused = true;
+                if let Some(player) = player {
+                    player.schedule().pause();
+                }
+            }
+        }
+        StreamCommand::Pause => {
+            *paused = true;
+            if let Some(player) = player {
+                player.schedule().pause();
+            }
+            if strm.interval > 0 {
+                *resume_at = Some(Instant::now() + Duration::from_millis(u64::from(strm.interval)));
+            } else {
+                let status = status(session, player, stream.as_ref(), track.as_ref(), rate);
+                session.send(protocol::stat(b"STMp", &status)).await?;
+            }
+        }
+        StreamCommand::Unpause => {
+            *paused = false;
+            *resume_at = None;
+            // At the given time on our clock, or straight away
+            let at = (i64::from(strm.interval) * 1_000).max(session.now());
+            if let Some(player) = player {
+                player.schedule().resume_at(at);
+            }
+            let status = status(session, player, stream.as_ref(), track.as_ref(), rate);
+            session.send(protocol::stat(b"STMr", &status)).await?;
+        }
+        StreamCommand::Stop | StreamCommand::Flush => {
+            *stream = None;
+            *track = None;
+            *paused = false;
+            *resume_at = None;
+            if let Some(player) = player {
+                player.schedule().clear();
+            }
+            session
+                .send(protocol::stat(b"STMf", &Status::default()))
+                .await?;
+        }
+        StreamCommand::Status => {
+            session.server_timestamp = strm.interval;
+            let status = status(session, player, stream.as_ref(), track.as_ref(), rate);
+            session.send(protocol::stat(b"STMt", &status)).await?;
+        }
+        StreamCommand::Skip | StreamCommand::Other(_) => {
+            log::debug!("[Slimproto] Ignoring stream command {:?}", strm.command);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_a_stable_local_mac() {
+        let (mac, _) = player_identity("desktop-1234");
+        assert_eq!(player_identity("desktop-1234").0, mac);
+        assert_ne!(player_identity("desktop-5678").0, mac);
+        // Locally administered, unicast
+        assert_eq!(mac[0] & 0x03, 0x02);
+        assert_eq!(
+            mac_to_string([0x02, 0xab, 0, 1, 0x10, 0xff]),
+            "02:ab:00:01:10:ff"
+        );
+    }
+
+    #[test]
+    fn maps_gain_back_to_volume() {
+        assert_eq!(volume_for_gain(0.0), 0);
+        assert_eq!(volume_for_gain(1.0), 100);
+        // Half a decibel a step
+        let gain = 10f32.powf(-25.0 * DB_PER_STEP / 20.0);
+        assert_eq!(volume_for_gain(gain), 75);
+        assert_eq!(volume_for_gain(gain_for_volume(70)), 70);
+    }
+}
@@ -0,0 +1,329 @@
+//! Slimproto wire format.
+//!
+//! Server messages are a big-endian 16-bit length, a four-letter command and
+//! its payload; client messages are a four-letter opcode, a big-endian 32-bit
+//! length and the payload. All integers are big-endian.
+
+use crate::sendspin::http_stream::PcmLayout;
+
+pub(crate) const DEFAULT_PORT: u16 = 3483;
+
+/// Device ID squeezelite reports, which servers treat as a software player
+const DEVICE_ID: u8 = 12;
+
+/// Sample rates by index, as the `strm` command encodes them
+const SAMPLE_RATES: [u32; 15] = [
+    11_025, 22_050, 32_000, 44_100, 48_000, 8_000, 12_000, 16_000, 24_000, 96_000, 88_200, 176_400,
+    192_000, 352_800, 384_000,
+];
+
+/// What a `strm` command asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StreamCommand {
+    /// Fetch and play a new stream
+    Start,
+    /// Pause, for `interval` ms if nonzero (a brief pause to resync)
+    Pause,
+    /// Resume at `interval` on the jiffies clock, or right away if zero
+    Unpause,
+    Stop,
+    /// Drop what's buffered without stopping the stream
+    Flush,
+    /// Report status, echoing `interval`
+    Status,
+    /// Skip `interval` ms of audio to catch up
+    Skip,
+    Other(u8),
+}
+
+/// A `strm` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Strm {
+    pub(crate) command: StreamCommand,
+    /// Start playing as soon as enough is buffered, rather than on unpause
+    pub(crate) autostart: bool,
+    /// Stream format: `b'p'` for PCM
+    pub(crate) format: u8,
+    pub(crate) pcm: PcmLayout,
+    /// Kilobytes to buffer before playback may start
+    pub(crate) threshold_kb: u8,
+    /// Meaning depends on `command`
+    pub(crate) interval: u32,
+    pub(crate) server_port: u16,
+    /// Where to fetch the stream; 0 means the Slimproto server
+    pub(crate) server_ip: u32,
+    /// HTTP request to send for the stream
+    pub(crate) request: Vec<u8>,
+}
+
+/// A message from the server
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ServerMessage {
+    Strm(Strm),
+    /// New gain for each channel, as linear multipliers; `None` when the
+    /// player should leave volume to the server (`dvc` unset)
+    Audg(Option<(f32, f32)>),
+    /// Enable or disable the outputs, which servers use to mute
+    Aude {
+        enabled: bool,
+    },
+    /// Player name: a query if `name` is `None`
+    SetName {
+        name: Option<String>,
+    },
+    Other,
+}
+
+impl ServerMessage {
+    /// Parse a message, `command` being its four-letter name.
+    pub(crate) fn parse(command: &[u8; 4], payload: &[u8]) -> Result<Self, String> {
+        let truncated = || {
+            format!(
+                "Truncated Slimproto {} message",
+                String::from_utf8_lossy(command)
+            )
+        };
+        Ok(match command {
+            b"strm" => {
+                if payload.len() < 24 {
+                    return Err(truncated());
+                }
+                let field = |index: usize, values: &[u32]| match payload[index] {
+                    b'?' => None,
+                    code => values.get(usize::from(code.wrapping_sub(b'0'))).copied(),
+                };
+                Self::Strm(Strm {
+                    command: match payload[0] {
+                        b's' => StreamCommand::Start,
+                        b'p' => StreamCommand::Pause,
+                        b'u' => StreamCommand::Unpause,
+                        b'q' => StreamCommand::Stop,
+                        b'f' => StreamCommand::Flush,
+                        b't' => StreamCommand::Status,
+                        b'a' => StreamCommand::Skip,
+                        other => StreamCommand::Other(other),
+                    },
+                    autostart: matches!(payload[1], b'1' | b'3'),
+                    format: payload[2],
+                    pcm: PcmLayout {
+                        bit_depth: field(3, &[8, 16, 24, 32]).map(|bits| bits as u16),
+                        sample_rate: field(4, &SAMPLE_RATES),
+                        channels: match payload[5] {
+                            b'1' => Some(1),
+                            b'2' => Some(2),
+                            _ => None,
+                        },
+                        big_endian: payload[6] == b'0',
+                    },
+                    threshold_kb: payload[7],
+                    interval: be_u32(&payload[14..18]),
+                    server_port: u16::from_be_bytes([payload[18], payload[19]]),
+                    server_ip: be_u32(&payload[20..24]),
+                    request: payload[24..].to_vec(),
+                })
+            }
+            b"audg" => {
+                if payload.len() < 18 {
+                    return Err(truncated());
+                }
+                let gain = |bytes: &[u8]| be_u32(bytes) as f32 / 65_536.0;
+                let digital_volume = payload[8] != 0;
+                Self::Audg(digital_volume.then(|| (gain(&payload[10..14]), gain(&payload[14..18]))))
+            }
+            b"aude" => {
+                if payload.len() < 2 {
+                    return Err(truncated());
+                }
+                Self::Aude {
+                    enabled: payload[0] != 0 || payload[1] != 0,
+                }
+            }
+            b"setd" => match payload.split_first() {
+                // Only the player name (ID 0) matters here
+                Some((0, rest)) => Self::SetName {
+                    name: (!rest.is_empty()).then(|| {
+                        String::from_utf8_lossy(rest)
+                            .trim_end_matches('\0')
+                            .to_string()
+                    }),
+                },
+                _ => Self::Other,
+            },
+            _ => Self::Other,
+        })
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Player state a `STAT` message reports
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Status {
+    pub(crate) bytes_received: u64,
+    /// Milliseconds on the player's clock
+    pub(crate) jiffies: u32,
+    /// Output buffer capacity and use, in milliseconds
+    pub(crate) output_buffer_size: u32,
+    pub(crate) output_buffer_fullness: u32,
+    /// Playback position in the current track
+    pub(crate) elapsed_ms: u32,
+    /// Echo of the last status request's timestamp
+    pub(crate) server_timestamp: u32,
+}
+
+fn message(opcode: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + payload.len());
+    out.extend_from_slice(opcode);
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Introduce the player, identified by `mac`; `capabilities` lists formats
+/// and limits, e.g. `Model=squeezelite,pcm`.
+pub(crate) fn helo(mac: [u8; 6], uuid: [u8; 16], capabilities: &str) -> Vec<u8> {
+    let mut payload = vec![DEVICE_ID, 0];
+    payload.extend_from_slice(&mac);
+    payload.extend_from_slice(&uuid);
+    // WLAN channel list and bytes received so far
+    payload.extend_from_slice(&0u16.to_be_bytes());
+    payload.extend_from_slice(&0u64.to_be_bytes());
+    payload.extend_from_slice(b"en");
+    payload.extend_from_slice(capabilities.as_bytes());
+    message(b"HELO", &payload)
+}
+
+/// Report `event` (e.g. `STMs`, track started) with the player's state.
+pub(crate) fn stat(event: &[u8; 4], status: &Status) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(53);
+    payload.extend_from_slice(event);
+    // CR/LFs seen, MAS initialized, MAS mode
+    payload.extend_from_slice(&[0, 0, 0]);
+    // Stream buffer size and fullness
+    payload.extend_from_slice(&[0; 8]);
+    payload.extend_from_slice(&status.bytes_received.to_be_bytes());
+    // Signal strength: wired
+    payload.extend_from_slice(&0xffffu16.to_be_bytes());
+    payload.extend_from_slice(&status.jiffies.to_be_bytes());
+    payload.extend_from_slice(&status.output_buffer_size.to_be_bytes());
+    payload.extend_from_slice(&status.output_buffer_fullness.to_be_bytes());
+    payload.extend_from_slice(&(status.elapsed_ms / 1_000).to_be_bytes());
+    // Voltage
+    payload.extend_from_slice(&0u16.to_be_bytes());
+    payload.extend_from_slice(&status.elapsed_ms.to_be_bytes());
+    payload.extend_from_slice(&status.server_timestamp.to_be_bytes());
+    // Error code
+    payload.extend_from_slice(&0u16.to_be_bytes());
+    message(b"STAT", &payload)
+}
+
+/// Pass the stream's HTTP response headers on to the server.
+pub(crate) fn resp(headers: &[u8]) -> Vec<u8> {
+    message(b"RESP", headers)
+}
+
+/// The stream connection closed; 0 for a normal close.
+pub(crate) fn dsco(reason: u8) -> Vec<u8> {
+    message(b"DSCO", &[reason])
+}
+
+/// Tell the server the player's name.
+pub(crate) fn setd_name(name: &str) -> Vec<u8> {
+    let mut payload = vec![0];
+    payload.extend_from_slice(name.as_bytes());
+    payload.push(0);
+    message(b"SETD", &payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strm(command: u8, pcm: [u8; 4], interval: u32, request: &[u8]) -> Vec<u8> {
+        let mut payload = vec![command, b'1', b'p', pcm[0], pcm[1], pcm[2], pcm[3], 255];
+        payload.extend_from_slice(&[0; 6]);
+        payload.extend_from_slice(&interval.to_be_bytes());
+        payload.extend_from_slice(&9000u16.to_be_bytes());
+        payload.extend_from_slice(&0u32.to_be_bytes());
+        payload.extend_from_slice(request);
+        payload
+    }
+
+    #[test]
+    fn parses_stream_commands() {
+        let request = b"GET /stream HTTP/1.0\r\n\r\n";
+        let ServerMessage::Strm(start) =
+            ServerMessage::parse(b"strm", &strm(b's', *b"1421", 0, request)).unwrap()
+        else {
+            panic!("not a strm");
+        };
+        assert_eq!(start.command, StreamCommand::Start);
+        assert!(start.autostart);
+        assert_eq!(
+            start.pcm,
+            PcmLayout {
+                bit_depth: Some(16),
+                sample_rate: Some(48_000),
+                channels: Some(2),
+                big_endian: false,
+            }
+        );
+        assert_eq!(start.server_port, 9000);
+        assert_eq!(start.request, request);
+
+        let ServerMessage::Strm(unpause) =
+            ServerMessage::parse(b"strm", &strm(b'u', *b"????", 1234, &[])).unwrap()
+        else {
+            panic!("not a strm");
+        };
+        assert_eq!(unpause.command, StreamCommand::Unpause);
+        assert_eq!(unpause.interval, 1234);
+        assert_eq!(unpause.pcm.sample_rate, None);
+
+        assert!(ServerMessage::parse(b"strm", &[b't'; 10]).is_err());
+    }
+
+    #[test]
+    fn parses_gain_and_name() {
+        let mut audg = vec![0; 8];
+        audg.extend_from_slice(&[1, 255]);
+        audg.extend_from_slice(&0x8000u32.to_be_bytes());
+        audg.extend_from_slice(&0x10000u32.to_be_bytes());
+        assert_eq!(
+            ServerMessage::parse(b"audg", &audg),
+            Ok(ServerMessage::Audg(Some((0.5, 1.0))))
+        );
+        audg[8] = 0;
+        assert_eq!(
+            ServerMessage::parse(b"audg", &audg),
+            Ok(ServerMessage::Audg(None))
+        );
+        assert_eq!(
+            ServerMessage::parse(b"setd", &[0]),
+            Ok(ServerMessage::SetName { name: None })
+        );
+        assert_eq!(
+            ServerMessage::parse(b"setd", b"\0Kitchen\0"),
+            Ok(ServerMessage::SetName {
+                name: Some("Kitchen".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn builds_fixed_size_status() {
+        let status = Status {
+            elapsed_ms: 61_500,
+            ..Status::default()
+        };
+        let message = stat(b"STMt", &status);
+        assert_eq!(&message[..4], b"STAT");
+        assert_eq!(be_u32(&message[4..8]), 53);
+        assert_eq!(&message[8..12], b"STMt");
+        // Elapsed seconds, then milliseconds after the voltage
+        assert_eq!(be_u32(&message[8 + 37..8 + 41]), 61);
+        assert_eq!(be_u32(&message[8 + 43..8 + 47]), 61_500);
+    }
+}
@@ -0,0 +1,420 @@
+//! Snapcast client backend.
+//!
+//! For Music Assistant setups that do multiroom through Snapserver, the
+//! built-in player can connect as a Snapcast client instead of speaking
+//! Sendspin. It runs in place of the Sendspin connection inside the same
+//! reconnect loop, so the player keeps its ID, output device, static delay,
+//! volume persistence and status reporting; Snapcast has no transport
+//! controls, so play/pause and friends go through the MA API.
+//!
+//! Only uncompressed streams are supported: the Snapserver stream has to
+//! use `codec=pcm`.
+
+mod protocol;
+
+use super::resampler::StreamResampler;
+use super::timed_player::{Player, TimeSync};
+use super::{
+    backend_address, backend_now_playing, capped_volume, clamp_static_delay_ms, forward_to_server,
+    initial_volume_state, output_layout_for_stream, pcm, publish_volume, save_volume_state,
+    AudioFormat, ClientCommand, Codec, ConnectionStatus, PlaybackCommand, ResolvedVolumeMode,
+    SendspinClient, SendspinConfig,
+};
+use crate::now_playing::NowPlaying;
+use protocol::{ClientInfo, Header, Hello, ServerMessage, ServerSettings, HEADER_LEN};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+/// Time requests while the clock offset settles, then every
+/// `Settings::clock_sync_interval_ms`
+const QUICK_SYNC_INTERVAL: Duration = Duration::from_millis(100);
+/// Playback counts as stopped once no audio arrived for this long
+const IDLE_AFTER: Duration = Duration::from_secs(2);
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Read messages off the connection until it closes or breaks.
+async fn read_messages(
+    mut reader: OwnedReadHalf,
+    tx: mpsc::Sender<Result<(Header, ServerMessage), String>>,
+) {
+    let result = async {
+        loop {
+            let mut header = [0u8; HEADER_LEN];
+            reader
+                .read_exact(&mut header)
+                .await
+                .map_err(|e| format!("Connection to Snapserver lost: {}", e))?;
+            let header = Header::parse(&header);
+            let size = header.size as usize;
+            if size > protocol::MAX_PAYLOAD {
+                return Err(format!("Snapcast message too large: {} bytes", size));
+            }
+            let mut payload = vec![0u8; size];
+            reader
+                .read_exact(&mut payload)
+                .await
+                .map_err(|e| format!("Connection to Snapserver lost: {}", e))?;
+            let message = ServerMessage::parse(&header, &payload)?;
+            if tx.send(Ok((header, message))).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    .await;
+    if let Err(e) = result {
+        let _ = tx.send(Err(e)).await;
+    }
+}
+
+/// The Snapcast connection's half of the client loop
+struct Session {
+    writer: OwnedWriteHalf,
+    origin: Instant,
+    next_id: u16,
+}
+
+impl Session {
+    /// Microseconds on our clock
+    fn now(&self) -> i64 {
+        self.origin.elapsed().as_micros() as i64
+    }
+
+    fn next_id(&mut self) -> u16 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
+    }
+
+    async fn send(&mut self, message: Vec<u8>) -> Result<(), String> {
+        self.writer
+            .write_all(&message)
+            .await
+            .map_err(|e| format!("Failed to write to Snapserver: {}", e))
+    }
+
+    async fn send_client_info(&mut self, volume: u8, muted: bool) {
+        let (id, sent) = (self.next_id(), self.now());
+        let message = protocol::client_info(id, sent, ClientInfo { volume, muted });
+        if let Err(e) = self.send(message).await {
+            log::warn!("[Snapcast] Failed to report volume: {}", e);
+        }
+    }
+}
+
+/// Run one Snapcast connection for `instance` until it drops or
+/// `shutdown_rx` fires.
+pub(super) async fn run_client(
+    instance: &SendspinClient,
+    config: SendspinConfig,
+    player_id: String,
+    mut shutdown_rx: mpsc::Receiver<()>,
+    mut command_rx: mpsc::Receiver<PlaybackCommand>,
+    mut client_command_rx: mpsc::Receiver<ClientCommand>,
+) -> Result<(), BoxError> {
+    let settings = crate::settings::get_settings();
+    let (host, port) = backend_address(
+        settings.snapcast_server.as_deref(),
+        &config.server_url,
+        protocol::DEFAULT_PORT,
+    )?;
+    log::info!(
+        "[Snapcast] Connecting to {}:{} as player {}",
+        host,
+        port,
+        player_id
+    );
+    let keepalive_timeout = Duration::from_secs(u64::from(settings.keepalive_timeout_secs));
+    let tcp = tokio::time::timeout(keepalive_timeout, TcpStream::connect((host.as_str(), port)))
+        .await
+        .map_err(|_| "Timed out connecting to Snapserver".to_string())?
+        .map_err(|e| format!("Snapserver connection failed: {}", e))?;
+    let _ = tcp.set_nodelay(true);
+    let (reader, writer) = tcp.into_split();
+    let (message_tx, mut message_rx) = mpsc::channel(64);
+    let reader_task = tokio::spawn(read_messages(reader, message_tx));
+
+    let mut session = Session {
+        writer,
+        origin: Instant::now(),
+        next_id: 0,
+    };
+    let hello = Hello {
+        arch: std::env::consts::ARCH.to_string(),
+        client_name: "Snapclient".to_string(),
+        host_name: config.player_name.clone(),
+        id: player_id.clone(),
+        instance: 1,
+        mac: "00:00:00:00:00:00".to_string(),
+        os: std::env::consts::OS.to_string(),
+        snap_stream_protocol_version: 2,
+        version: config.app_version.clone(),
+    };
+    let (id, sent) = (session.next_id(), session.now());
+    session.send(protocol::hello(id, sent, &hello)).await?;
+
+    instance.update_status(ConnectionStatus::Connected);
+    log::info!("[Snapcast] Connected to Snapserver (player {})", player_id);
+
+    let result = run_session(
+        instance,
+        &config,
+        &player_id,
+        &mut session,
+        &mut message_rx,
+        &mut shutdown_rx,
+        &mut command_rx,
+        &mut client_command_rx,
+        keepalive_timeout,
+    )
+    .await;
+
+    reader_task.abort();
+    instance.publish_now_playing(NowPlaying::default());
+    instance.update_status(ConnectionStatus::Disconnected);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_session(
+    instance: &SendspinClient,
+    config: &SendspinConfig,
+    player_id: &str,
+    session: &mut Session,
+    message_rx: &mut mpsc::Receiver<Result<(Header, ServerMessage), String>>,
+    shutdown_rx: &mut mpsc::Receiver<()>,
+    command_rx: &mut mpsc::Receiver<PlaybackCommand>,
+    client_command_rx: &mut mpsc::Receiver<ClientCommand>,
+    keepalive_timeout: Duration,
+) -> Result<(), BoxError> {
+    // Snapcast volume is always applied to the samples, like snapclient's
+    // software mixer; it's persisted with the software volume
+    let additional_player = (!instance.is_primary()).then_some(player_id);
+    let (mut volume, mut muted) =
+        initial_volume_state(ResolvedVolumeMode::Software, additional_player);
+    if additional_player.is_none() {
+        publish_volume(volume);
+    }
+    let mut static_delay_ms = clamp_static_delay_ms(config.sync_delay_ms);
+
+    let settings = crate::settings::get_settings();
+    let mut time_sync = TimeSync::new(
+        settings.clock_sync_window as usize,
+        i64::from(settings.clock_sync_outlier_ms) * 1_000,
+    );
+    // The replies are what keeps an idle connection from timing out
+    let sync_interval = Duration::from_millis(u64::from(settings.clock_sync_interval_ms))
+        .min(keepalive_timeout / 2);
+    // Quick time requests still to send after connecting
+    let mut quick_syncs = settings.clock_sync_burst;
+    let mut server_settings: Option<ServerSettings> = None;
+    let mut output: Option<(Player, pcm::PcmFormat)> = None;
+    let mut resampler: Option<StreamResampler> = None;
+    let mut next_sync = tokio::time::Instant::now();
+    let mut last_message = Instant::now();
+    let mut last_chunk: Option<Instant> = None;
+    instance.publish_now_playing(backend_now_playing(config, player_id, false));
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            _ = tokio::time::sleep_until(next_sync) => {
+                if last_message.elapsed() > keepalive_timeout {
+                    return Err("Snapserver stopped responding".into());
+                }
+                let (id, sent) = (session.next_id(), session.now());
+                session.send(protocol::time_request(id, sent)).await?;
+                quick_syncs = quick_syncs.saturating_sub(1);
+                next_sync += if time_sync.is_ready() && quick_syncs == 0 { sync_interval } else { QUICK_SYNC_INTERVAL };
+
+                if last_chunk.is_some_and(|at| at.elapsed() > IDLE_AFTER) {
+                    last_chunk = None;
+                    instance.publish_now_playing(backend_now_playing(config, player_id, false));
+                }
+                if let Some((ref player, _)) = output {
+                    instance.inner.stats.set_buffered(player.schedule().buffered(), 0);
+                }
+            }
+            Some(message) = message_rx.recv() => {
+                let (header, message) = message?;
+                last_message = Instant::now();
+                match message {
+                    ServerMessage::Time { latency } => {
+                        // Server clock minus ours as the reply arrived
+                        let s2c = session.now() - header.sent;
+                        time_sync.add(latency, s2c);
+                    }
+                    ServerMessage::ServerSettings(update) => {
+                        log::debug!("[Snapcast] Server settings: {:?}", update);
+                        let volume_changed = (update.volume, update.muted) != (volume, muted);
+                        volume = capped_volume(update.volume);
+                        muted = update.muted;
+                        if let Some((ref player, _)) = output {
+                            player.schedule().set_volume(volume, muted);
+                        }
+                        if volume_changed {
+                            if additional_player.is_none() {
+                                publish_volume(volume);
+                            }
+                            save_volume_state(ResolvedVolumeMode::Software, additional_player, volume, muted);
+                        }
+                        if volume < update.volume {
+                            // Above the maximum volume; show the server what plays
+                            session.send_client_info(volume, muted).await;
+                        }
+                        server_settings = Some(update);
+                    }
+                    ServerMessage::CodecHeader { codec, payload } => {
+                        if codec != "pcm" {
+                            return Err(format!(
+                                "Snapserver stream uses the {} codec; only pcm is supported",
+                                codec
+                            )
+                            .into());
+                        }
+                        let (format, _) = pcm::parse_wave_header(&payload)?;
+                        log::info!(
+                            "[Snapcast] Stream format: channels={}, sample_rate={}, bit_depth={}",
+                            format.channels,
+                            format.sample_rate,
+                            format.bit_depth
+                        );
+                        if pcm::bytes_per_sample(format.bit_depth).is_none() {
+                            return Err(format!("Unsupported PCM bit depth: {}", format.bit_depth).into());
+                        }
+                        output = None;
+                        resampler = None;
+                        let stream_format = AudioFormat {
+                            codec: Codec::Pcm,
+                            sample_rate: format.sample_rate,
+                            channels: format.channels,
+                            bit_depth: format.bit_depth,
+                            codec_header: None,
+                        };
+                        let (_, output_rate, _) =
+                            output_layout_for_stream(config.audio_device_id.as_deref(), &stream_format, false);
+                        if output_rate != format.sample_rate {
+                            let quality = crate::settings::get_settings().resampler_quality;
+                            match StreamResampler::new(format.sample_rate, output_rate, format.channels, format.bit_depth, format.bit_depth, quality) {
+                                Ok(r) => {
+                                    log::info!("[Snapcast] Resampling {}Hz to {}Hz", format.sample_rate, r.output_rate());
+                                    resampler = Some(r);
+                                }
+                                Err(e) => log::warn!("[Snapcast] {e}; playing at the stream rate"),
+                            }
+                        }
+                        let rate = resampler.as_ref().map_or(format.sample_rate, StreamResampler::output_rate);
+                        let device_id = config.audio_device_id.clone();
+                        let origin = session.origin;
+                        let channels = format.channels;
+                        let player = tokio::task::spawn_blocking(move || {
+                            Player::open(device_id.as_deref(), channels, rate, origin)
+                        })
+                        .await
+                        .map_err(|e| e.to_string())??;
+                        {
+                            let mut schedule = player.schedule();
+                            schedule.set_volume(volume, muted);
+                            schedule.set_static_delay(static_delay_ms);
+                        }
+                        instance.inner.stats.record_stream_start();
+                        output = Some((player, format));
+                    }
+                    ServerMessage::WireChunk { timestamp, payload } => {
+                        let (Some((ref player, format)), Some(offset), Some(ref settings)) =
+                            (&output, time_sync.offset(), &server_settings)
+                        else {
+                            continue;
+                        };
+                        // When the chunk is heard, on our clock
+                        let delay = (settings.buffer_ms - settings.latency) * 1_000;
+                        let at = timestamp + delay - offset;
+                        let chunks = match resampler {
+                            Some(ref mut r) => r.process(at, &payload),
+                            None => vec![(at, payload)],
+                        };
+                        let chunks: Vec<_> = chunks
+                            .into_iter()
+                            .map(|(at, data)| (at, pcm::to_f32(&data, format.bit_depth)))
+                            .collect();
+                        let mut schedule = player.schedule();
+                        for (at, samples) in chunks {
+                            schedule.push(at, samples);
+                        }
+                        drop(schedule);
+                        if last_chunk.is_none() {
+                            instance.publish_now_playing(backend_now_playing(config, player_id, true));
+                        }
+                        last_chunk = Some(Instant::now());
+                    }
+                    ServerMessage::Other => {}
+                }
+            }
+            Some(command) = command_rx.recv() => {
+                match command {
+                    PlaybackCommand::SetVolume(level) => {
+                        volume = capped_volume(level);
+                    }
+                    PlaybackCommand::SetMute(mute) => {
+                        muted = mute;
+                    }
+                    command => {
+                        // Snapcast has no transport controls
+                        forward_to_server(player_id, command);
+                        continue;
+                    }
+                }
+                // Volume or mute changed
+                if let Some((ref player, _)) = output {
+                    player.schedule().set_volume(volume, muted);
+                }
+                if additional_player.is_none() {
+                    publish_volume(volume);
+                }
+                save_volume_state(ResolvedVolumeMode::Software, additional_player, volume, muted);
+                session.send_client_info(volume, muted).await;
+            }
+            Some(command) = client_command_rx.recv() => {
+                match command {
+                    ClientCommand::SetStaticDelay(delay_ms) => {
+                        log::debug!("[Snapcast] Applying static delay: {}ms", delay_ms);
+                        static_delay_ms = delay_ms;
+                        if let Some((ref player, _)) = output {
+                            player.schedule().set_static_delay(delay_ms);
+                        }
+                    }
+                    ClientCommand::ReloadDsp => {
+                        log::debug!("[Snapcast] DSP settings don't apply to Snapcast playback");
+                    }
+                    ClientCommand::SetMirrorDelay(_) => {
+                        log::debug!("[Snapcast] Mirrored output doesn't apply to Snapcast playback");
+                    }
+                    ClientCommand::Rename(name) => {
+                        log::info!("[Snapcast] Player name {} applies from the next connection", name);
+                    }
+                    ClientCommand::SwitchOutputDevice(_) => {
+                        log::info!("[Snapcast] Reconnecting to play to the new output device");
+                        instance.restart_in_background();
+                    }
+                    ClientCommand::ApplyMaxVolume => {
+                        let capped = capped_volume(volume);
+                        if capped < volume {
+                            volume = capped;
+                            if let Some((ref player, _)) = output {
+                                player.schedule().set_volume(volume, muted);
+                            }
+                            if additional_player.is_none() {
+                                publish_volume(volume);
+                            }
+                            save_volume_state(ResolvedVolumeMode::Software, additional_player, volume, muted);
+                            session.send_client_info(volume, muted).await;
+                        }
+                    }
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Snapcast binary protocol
+//!
+//! Every message is a 26-byte little-endian header followed by a payload of
+//! the size the header gives. Timestamps are `(seconds, microseconds)` pairs;
+//! the client's are on its own monotonic clock, the server's on the server's.
+
+use serde::{Deserialize, Serialize};
+
+/// Default port of the Snapserver stream protocol
+pub(crate) const DEFAULT_PORT: u16 = 1704;
+pub(crate) const HEADER_LEN: usize = 26;
+/// Largest payload accepted; wire chunks are a few kilobytes
+pub(crate) const MAX_PAYLOAD: usize = 4 * 1024 * 1024;
+
+const CODEC_HEADER: u16 = 1;
+const WIRE_CHUNK: u16 = 2;
+const SERVER_SETTINGS: u16 = 3;
+const TIME: u16 = 4;
+const HELLO: u16 = 5;
+const CLIENT_INFO: u16 = 7;
+
+/// Message header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub(crate) kind: u16,
+    pub(crate) id: u16,
+    pub(crate) refers_to: u16,
+    /// When the sender sent the message, in microseconds of its clock
+    pub(crate) sent: i64,
+    /// When the receiver received it; filled in by the server on replies
+    pub(crate) received: i64,
+    pub(crate) size: u32,
+}
+
+impl Header {
+    pub(crate) fn parse(bytes: &[u8; HEADER_LEN]) -> Self {
+        let mut reader = Reader::new(bytes);
+        Self {
+            kind: reader.u16().unwrap_or_default(),
+            id: reader.u16().unwrap_or_default(),
+            refers_to: reader.u16().unwrap_or_default(),
+            sent: reader.timestamp().unwrap_or_default(),
+            received: reader.timestamp().unwrap_or_default(),
+            size: reader.u32().unwrap_or_default(),
+        }
+    }
+}
+
+/// Sent on connecting to introduce the client
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Hello {
+    pub(crate) arch: String,
+    pub(crate) client_name: String,
+    pub(crate) host_name: String,
+    #[serde(rename = "ID")]
+    pub(crate) id: String,
+    pub(crate) instance: u32,
+    #[serde(rename = "MAC")]
+    pub(crate) mac: String,
+    #[serde(rename = "OS")]
+    pub(crate) os: String,
+    pub(crate) snap_stream_protocol_version: u32,
+    pub(crate) version: String,
+}
+
+/// Playback settings the server assigns the client
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ServerSettings {
+    /// End-to-end delay from the chunk timestamps to playback
+    pub(crate) buffer_ms: i64,
+    /// Extra output latency configured for this client on the server
+    #[serde(default)]
+    pub(crate) latency: i64,
+    #[serde(default)]
+    pub(crate) muted: bool,
+    #[serde(default = "full_volume")]
+    pub(crate) volume: u8,
+}
+
+fn full_volume() -> u8 {
+    100
+}
+
+/// Volume state reported back to the server
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct ClientInfo {
+    pub(crate) volume: u8,
+    pub(crate) muted: bool,
+}
+
+/// A message from the server
+#[derive(Debug)]
+pub(crate) enum ServerMessage {
+    /// Stream codec and its setup data
+    CodecHeader {
+        codec: String,
+        payload: Vec<u8>,
+    },
+    /// Encoded audio to play at `timestamp` on the server's clock, plus the
+    /// buffer from [`ServerSettings`]
+    WireChunk {
+        timestamp: i64,
+        payload: Vec<u8>,
+    },
+    ServerSettings(ServerSettings),
+    /// Reply to a time request; `latency` is how long the request took to
+    /// reach the server
+    Time {
+        latency: i64,
+    },
+    /// Anything this client doesn't handle
+    Other,
+}
+
+impl ServerMessage {
+    pub(crate) fn parse(header: &Header, payload: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(payload);
+        let truncated = || format!("Truncated Snapcast message of type {}", header.kind);
+        Ok(match header.kind {
+            CODEC_HEADER => Self::CodecHeader {
+                codec: reader.string().ok_or_else(truncated)?,
+                payload: reader.blob().ok_or_else(truncated)?.to_vec(),
+            },
+            WIRE_CHUNK => Self::WireChunk {
+                timestamp: reader.timestamp().ok_or_else(truncated)?,
+                payload: reader.blob().ok_or_else(truncated)?.to_vec(),
+            },
+            SERVER_SETTINGS => {
+                let json = reader.string().ok_or_else(truncated)?;
+                Self::ServerSettings(
+                    serde_json::from_str(&json)
+                        .map_err(|e| format!("Invalid Snapcast server settings: {}", e))?,
+                )
+            }
+            TIME => Self::Time {
+                latency: reader.timestamp().ok_or_else(truncated)?,
+            },
+            _ => Self::Other,
+        })
+    }
+}
+
+/// A complete message ready to send
+fn message(kind: u16, id: u16, sent: i64, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(&kind.to_le_bytes());
+    out.extend_from_slice(&id.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    put_timestamp(&mut out, sent);
+    put_timestamp(&mut out, 0);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// A JSON payload, as a length-prefixed string
+fn json_message(kind: u16, id: u16, sent: i64, value: &impl Serialize) -> Vec<u8> {
+    let json = serde_json::to_string(value).unwrap_or_default();
+    let mut payload = Vec::with_capacity(4 + json.len());
+    payload.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    payload.extend_from_slice(json.as_bytes());
+    message(kind, id, sent, &payload)
+}
+
+pub(crate) fn hello(id: u16, sent: i64, hello: &Hello) -> Vec<u8> {
+    json_message(HELLO, id, sent, hello)
+}
+
+pub(crate) fn client_info(id: u16, sent: i64, info: ClientInfo) -> Vec<u8> {
+    json_message(CLIENT_INFO, id, sent, &info)
+}
+
+/// A time request. The reply's `latency` and header give the remaining
+/// timestamps of the exchange.
+pub(crate) fn time_request(id: u16, sent: i64) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(8);
+    put_timestamp(&mut payload, 0);
+    message(TIME, id, sent, &payload)
+}
+
+fn put_timestamp(out: &mut Vec<u8>, micros: i64) {
+    let sec = micros.div_euclid(1_000_000) as i32;
+    let usec = micros.rem_euclid(1_000_000) as i32;
+    out.extend_from_slice(&sec.to_le_bytes());
+    out.extend_from_slice(&usec.to_le_bytes());
+}
+
+/// Little-endian cursor over a payload
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (head, tail) = self.bytes.split_at_checked(len)?;
+        self.bytes = tail;
+        Some(head)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|v| v as i32)
+    }
+
+    /// A `(seconds, microseconds)` pair, in microseconds
+    fn timestamp(&mut self) -> Option<i64> {
+        let sec = i64::from(self.i32()?);
+        let usec = i64::from(self.i32()?);
+        Some(sec * 1_000_000 + usec)
+    }
+
+    /// A payload prefixed with its `u32` length
+    fn blob(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.blob()
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_of(message: &[u8]) -> Header {
+        Header::parse(message[..HEADER_LEN].try_into().unwrap())
+    }
+
+    #[test]
+    fn time_request_round_trips_its_header() {
+        let request = time_request(7, 12_345_678);
+        let header = header_of(&request);
+        assert_eq!(header.kind, TIME);
+        assert_eq!(header.id, 7);
+        assert_eq!(header.sent, 12_345_678);
+        assert_eq!(header.size as usize, request.len() - HEADER_LEN);
+
+        let reply = ServerMessage::parse(&header, &request[HEADER_LEN..]).unwrap();
+        assert!(matches!(reply, ServerMessage::Time { latency: 0 }));
+    }
+
+    #[test]
+    fn parses_server_messages() {
+        let settings = br#"{"bufferMs":1000,"latency":20,"muted":false,"volume":57}"#;
+        let mut payload = (settings.len() as u32).to_le_bytes().to_vec();
+        payload.extend_from_slice(settings);
+        let header = Header {
+            kind: SERVER_SETTINGS,
+            id: 0,
+            refers_to: 0,
+            sent: 0,
+            received: 0,
+            size: payload.len() as u32,
+        };
+        let ServerMessage::ServerSettings(parsed) =
+            ServerMessage::parse(&header, &payload).unwrap()
+        else {
+            panic!("expected server settings");
+        };
+        assert_eq!(parsed.buffer_ms, 1000);
+        assert_eq!(parsed.latency, 20);
+        assert_eq!(parsed.volume, 57);
+
+        // Timestamp 2.5s, four bytes of audio
+        let mut payload = Vec::new();
+        put_timestamp(&mut payload, 2_500_000);
+        payload.extend_from_slice(&4u32.to_le_bytes());
+        payload.extend_from_slice(&[1, 2, 3, 4]);
+        let header = Header {
+            kind: WIRE_CHUNK,
+            size: payload.len() as u32,
+            ..header
+        };
+        let ServerMessage::WireChunk { timestamp, payload } =
+            ServerMessage::parse(&header, &payload).unwrap()
+        else {
+            panic!("expected a wire chunk");
+        };
+        assert_eq!(timestamp, 2_500_000);
+        assert_eq!(payload, [1, 2, 3, 4]);
+
+        assert!(ServerMessage::parse(&header, &[0; 6]).is_err());
+    }
+}
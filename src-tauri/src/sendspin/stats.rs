@@ -0,0 +1,249 @@
+//! Per-player playback and clock-sync statistics, collected while a
+//! connection runs and read back by the UI and for diagnostics.
+
+use parking_lot::{Mutex, RwLock};
+use sendspin::sync::ClockSync;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Statistics for one built-in player
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerStats {
+    pub player_id: String,
+    /// Times the connection was lost and retried since the player started
+    pub reconnects: u64,
+    /// Clock sync with the server; `None` while not connected
+    pub clock: Option<ClockStats>,
+    pub buffer: BufferStats,
+    pub decode: DecodeStats,
+}
+
+/// State of the clock sync with the server
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockStats {
+    pub synchronized: bool,
+    /// Round-trip time of the last time exchange
+    pub rtt_us: Option<i64>,
+    /// Estimated offset of the server clock from the local clock
+    pub offset_us: Option<i64>,
+    /// Estimated drift of the server clock relative to the local clock
+    pub drift_ppm: Option<f64>,
+}
+
+/// Audio received and buffered since the client started
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BufferStats {
+    pub streams_started: u64,
+    pub chunks_received: u64,
+    pub bytes_received: u64,
+    /// Estimated audio of the current stream still waiting to play
+    pub buffered_ms: u64,
+    /// Buffer the server is currently asked to keep ahead
+    pub target_buffer_ms: u64,
+    /// `buffered_ms` as a fraction (0-1) of the buffer capacity advertised
+    /// to the server
+    pub fill_level: f64,
+    /// Times the buffer ran dry mid-stream before the next chunk arrived
+    pub underruns: u64,
+    /// Times playback faded to silence because the buffer was about to run
+    /// dry
+    pub underrun_fades: u64,
+    /// Chunks discarded instead of being queued for playback
+    pub dropped_chunks: u64,
+}
+
+/// Time spent turning received chunks into playable samples (downmixing,
+/// resampling and decoding)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecodeStats {
+    pub avg_us: u64,
+    pub max_us: u64,
+}
+
+/// Collects statistics from the client loop. Counters are lock-free so the
+/// audio path doesn't contend with readers.
+#[derive(Default)]
+pub(crate) struct StatsRecorder {
+    clock_sync: RwLock<Option<Arc<Mutex<ClockSync>>>>,
+    reconnects: AtomicU64,
+    streams_started: AtomicU64,
+    chunks_received: AtomicU64,
+    bytes_received: AtomicU64,
+    buffered_ms: AtomicU64,
+    buffered_bytes: AtomicU64,
+    target_buffer_ms: AtomicU64,
+    underruns: AtomicU64,
+    underrun_fades: AtomicU64,
+    dropped_chunks: AtomicU64,
+    decoded_chunks: AtomicU64,
+    decode_total_us: AtomicU64,
+    decode_max_us: AtomicU64,
+}
+
+fn clock_stats(clock: &ClockSync) -> ClockStats {
+    ClockStats {
+        synchronized: clock.is_synchronized(),
+        rtt_us: clock.rtt_micros(),
+        offset_us: clock.offset_micros(),
+        drift_ppm: clock.drift_ppm(),
+    }
+}
+
+impl StatsRecorder {
+    /// Track the clock sync of a new connection, or stop tracking with `None`
+    pub(crate) fn set_clock_sync(&self, clock_sync: Option<Arc<Mutex<ClockSync>>>) {
+        *self.clock_sync.write() = clock_sync;
+    }
+
+    pub(crate) fn record_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_stream_start(&self) {
+        self.streams_started.fetch_add(1, Ordering::Relaxed);
+        self.set_buffered(Duration::ZERO, 0);
+    }
+
+    /// Record a chunk accepted for playback. `buffered` is the audio still
+    /// waiting to play including this chunk, at `bytes_per_sec`; `ran_dry`
+    /// whether nothing was left to play when it arrived.
+    pub(crate) fn record_chunk(
+        &self,
+        bytes: usize,
+        buffered: Duration,
+        bytes_per_sec: u64,
+        ran_dry: bool,
+    ) {
+        self.chunks_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        if ran_dry {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        self.set_buffered(buffered, bytes_per_sec);
+    }
+
+    pub(crate) fn record_underrun_fade(&self) {
+        self.underrun_fades.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped_chunk(&self) {
+        self.dropped_chunks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_decode(&self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        self.decoded_chunks.fetch_add(1, Ordering::Relaxed);
+        self.decode_total_us.fetch_add(us, Ordering::Relaxed);
+        self.decode_max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_buffered(&self, buffered: Duration, bytes_per_sec: u64) {
+        let buffered_us = buffered.as_micros() as u64;
+        self.buffered_ms
+            .store(buffered_us / 1000, Ordering::Relaxed);
+        self.buffered_bytes
+            .store(buffered_us * bytes_per_sec / 1_000_000, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_target_buffer(&self, target_ms: u32) {
+        self.target_buffer_ms
+            .store(u64::from(target_ms), Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, player_id: String) -> PlayerStats {
+        let clock = self
+            .clock_sync
+            .read()
+            .as_ref()
+            .map(|clock| clock_stats(&clock.lock()));
+        let decoded_chunks = self.decoded_chunks.load(Ordering::Relaxed);
+        let buffered_bytes = self.buffered_bytes.load(Ordering::Relaxed);
+        PlayerStats {
+            player_id,
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            clock,
+            buffer: BufferStats {
+                streams_started: self.streams_started.load(Ordering::Relaxed),
+                chunks_received: self.chunks_received.load(Ordering::Relaxed),
+                bytes_received: self.bytes_received.load(Ordering::Relaxed),
+                buffered_ms: self.buffered_ms.load(Ordering::Relaxed),
+                target_buffer_ms: self.target_buffer_ms.load(Ordering::Relaxed),
+                fill_level: (buffered_bytes as f64 / f64::from(super::PLAYER_BUFFER_CAPACITY))
+                    .min(1.0),
+                underruns: self.underruns.load(Ordering::Relaxed),
+                underrun_fades: self.underrun_fades.load(Ordering::Relaxed),
+                dropped_chunks: self.dropped_chunks.load(Ordering::Relaxed),
+            },
+            decode: DecodeStats {
+                avg_us: self
+                    .decode_total_us
+                    .load(Ordering::Relaxed)
+                    .checked_div(decoded_chunks)
+                    .unwrap_or(0),
+                max_us: self.decode_max_us.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_reports_recorded_chunks() {
+        let stats = StatsRecorder::default();
+        stats.record_stream_start();
+        stats.record_chunk(960, Duration::from_millis(20), 192_000, false);
+        stats.record_chunk(960, Duration::from_millis(40), 192_000, false);
+
+        let snapshot = stats.snapshot("player".to_string());
+        assert!(snapshot.clock.is_none());
+        assert_eq!(snapshot.buffer.streams_started, 1);
+        assert_eq!(snapshot.buffer.chunks_received, 2);
+        assert_eq!(snapshot.buffer.bytes_received, 1920);
+        assert_eq!(snapshot.buffer.buffered_ms, 40);
+        assert_eq!(snapshot.buffer.underruns, 0);
+        assert!(snapshot.buffer.fill_level > 0.0);
+    }
+
+    #[test]
+    fn counts_underruns_drops_and_reconnects() {
+        let stats = StatsRecorder::default();
+        stats.record_chunk(960, Duration::from_millis(20), 192_000, true);
+        stats.record_underrun_fade();
+        stats.record_dropped_chunk();
+        stats.record_dropped_chunk();
+        stats.record_reconnect();
+
+        let snapshot = stats.snapshot(String::new());
+        assert_eq!(snapshot.buffer.underruns, 1);
+        assert_eq!(snapshot.buffer.underrun_fades, 1);
+        assert_eq!(snapshot.buffer.dropped_chunks, 2);
+        assert_eq!(snapshot.reconnects, 1);
+    }
+
+    #[test]
+    fn decode_time_tracks_average_and_peak() {
+        let stats = StatsRecorder::default();
+        assert_eq!(stats.snapshot(String::new()).decode.avg_us, 0);
+
+        stats.record_decode(Duration::from_micros(100));
+        stats.record_decode(Duration::from_micros(300));
+
+        let decode = stats.snapshot(String::new()).decode;
+        assert_eq!(decode.avg_us, 200);
+        assert_eq!(decode.max_us, 300);
+    }
+
+    #[test]
+    fn stream_start_resets_buffered_audio() {
+        let stats = StatsRecorder::default();
+        stats.record_chunk(960, Duration::from_millis(500), 192_000, false);
+        stats.record_stream_start();
+        assert_eq!(stats.snapshot(String::new()).buffer.buffered_ms, 0);
+    }
+}
@@ -0,0 +1,163 @@
+//! Output device test tone
+//!
+//! Plays a short rising sweep on the left channel, then on the right, so
+//! users can tell they picked the right output, and that its channels
+//! aren't swapped, before a player is set to it. Mono devices hear both
+//! sweeps.
+
+use super::{devices, null_output};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::time::Duration;
+
+/// Amplitude of the sweeps (0-1), kept moderate as the volume is unknown
+const AMPLITUDE: f32 = 0.3;
+/// Frequency each sweep starts at
+const START_HZ: f32 = 250.0;
+/// Frequency each sweep ends at
+const END_HZ: f32 = 2000.0;
+/// Silence before the first sweep, so the stream has settled
+const LEAD_IN: Duration = Duration::from_millis(200);
+/// Length of each sweep
+const SWEEP_LENGTH: Duration = Duration::from_millis(800);
+/// Silence between the left and the right sweep
+const GAP: Duration = Duration::from_millis(300);
+/// Fade at either end of a sweep, so it doesn't click
+const FADE: Duration = Duration::from_millis(20);
+
+fn frames(duration: Duration, sample_rate: u32) -> u64 {
+    duration.as_micros() as u64 * u64::from(sample_rate) / 1_000_000
+}
+
+/// Sample of a sweep `offset` frames after it started
+fn sweep(offset: u64, sample_rate: u32) -> f32 {
+    let rate = sample_rate as f32;
+    let t = offset as f32 / rate;
+    let length = SWEEP_LENGTH.as_secs_f32();
+    let fade = FADE.as_secs_f32();
+    // Exponential sweep: the frequency rises by the same ratio every second
+    let ratio = END_HZ / START_HZ;
+    let phase = 2.0 * std::f32::consts::PI * START_HZ * length / ratio.ln()
+        * (ratio.powf(t / length) - 1.0);
+    let envelope = (t / fade).min((length - t) / fade).clamp(0.0, 1.0);
+    AMPLITUDE * envelope * phase.sin()
+}
+
+/// Left and right sample of the test signal at output frame `frame`
+fn test_signal(frame: u64, sample_rate: u32) -> (f32, f32) {
+    let lead_in = frames(LEAD_IN, sample_rate);
+    let sweep_length = frames(SWEEP_LENGTH, sample_rate);
+    let right_start = lead_in + sweep_length + frames(GAP, sample_rate);
+    if (lead_in..lead_in + sweep_length).contains(&frame) {
+        (sweep(frame - lead_in, sample_rate), 0.0)
+    } else if (right_start..right_start + sweep_length).contains(&frame) {
+        (0.0, sweep(frame - right_start, sample_rate))
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+/// Total length of the test signal
+fn signal_length() -> Duration {
+    LEAD_IN + SWEEP_LENGTH * 2 + GAP
+}
+
+/// Play the test signal on `audio_device_id`, the default output if `None`.
+///
+/// Unlike a player, this doesn't fall back to the default output when the
+/// device is missing, as that would sound like the test passed. Blocks for
+/// the length of the test signal.
+pub fn play(audio_device_id: Option<&str>) -> Result<(), String> {
+    if audio_device_id == Some(null_output::DEVICE_ID) {
+        return Err("The null output doesn't play any sound".to_string());
+    }
+    let device = match audio_device_id {
+        Some(id) => devices::get_device_by_id(id),
+        None => devices::get_default_device(),
+    }
+    .map_err(|e| e.to_string())?;
+    let supported = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get output config: {}", e))?;
+    let config = supported.config();
+    let sample_rate = config.sample_rate;
+    let channels = usize::from(config.channels);
+    let route = devices::stream_route(audio_device_id);
+
+    log::info!(
+        "[Sendspin] Playing a test tone on {} at {}Hz",
+        audio_device_id.unwrap_or("the default output"),
+        sample_rate
+    );
+
+    let mut frames_written: u64 = 0;
+    let stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                for (i, frame) in data.chunks_mut(channels).enumerate() {
+                    let (left, right) = test_signal(frames_written + i as u64, sample_rate);
+                    match frame {
+                        [mono] => *mono = left + right,
+                        [l, r, rest @ ..] => {
+                            *l = left;
+                            *r = right;
+                            rest.fill(0.0);
+                        }
+                        [] => {}
+                    }
+                }
+                frames_written += (data.len() / channels.max(1)) as u64;
+            },
+            |e| log::warn!("[Sendspin] Test tone stream error: {}", e),
+            None,
+        )
+        .map_err(|e| format!("Failed to open output stream: {}", e))?;
+    if let Some(route) = route {
+        route.apply_in_background();
+    }
+    stream
+        .play()
+        .map_err(|e| format!("Failed to start output stream: {}", e))?;
+
+    // Let the device play out what it has buffered
+    std::thread::sleep(signal_length() + Duration::from_millis(300));
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)] // silence is exactly 0.0
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweeps_left_then_right() {
+        let rate = 48_000;
+        let lead_in = frames(LEAD_IN, rate);
+        let sweep_length = frames(SWEEP_LENGTH, rate);
+        let right_start = lead_in + sweep_length + frames(GAP, rate);
+        assert_eq!(test_signal(0, rate), (0.0, 0.0));
+
+        let left = (lead_in..lead_in + sweep_length).map(|n| test_signal(n, rate));
+        assert!(left.clone().all(|(_, r)| r == 0.0));
+        let peak = left.fold(0.0f32, |peak, (l, _)| peak.max(l.abs()));
+        assert!(peak > AMPLITUDE * 0.9 && peak <= AMPLITUDE, "{peak}");
+
+        assert_eq!(test_signal(right_start - 1, rate), (0.0, 0.0));
+        let right = (right_start..right_start + sweep_length).map(|n| test_signal(n, rate));
+        assert!(right.clone().all(|(l, _)| l == 0.0));
+        assert!(right.clone().any(|(_, r)| r.abs() > AMPLITUDE * 0.9));
+        assert_eq!(test_signal(right_start + sweep_length, rate), (0.0, 0.0));
+        assert_eq!(frames(signal_length(), rate), right_start + sweep_length);
+    }
+
+    #[test]
+    fn sweeps_fade_in_and_out() {
+        let rate = 44_100;
+        assert_eq!(sweep(0, rate), 0.0);
+        let fade = frames(FADE, rate);
+        let ramp = (0..fade / 4).map(|n| sweep(n, rate).abs());
+        assert!(ramp.fold(0.0f32, f32::max) < AMPLITUDE * 0.3);
+        let end = frames(SWEEP_LENGTH, rate);
+        assert!(sweep(end - 1, rate).abs() < AMPLITUDE * 0.01);
+    }
+}
@@ -0,0 +1,227 @@
+//! The clock sync settings, applied to sendspin-rs's time exchanges
+//!
+//! sendspin-rs sends a `client/time` request every 5 seconds and feeds the
+//! replies to its `ClockSync`, neither of which takes settings. The
+//! connection relay (see `liveness`) sees the exchanges go by and adds to
+//! them:
+//!
+//! - `Settings::clock_sync_burst` quick requests once sendspin-rs sends its
+//!   first, then one every `Settings::clock_sync_interval_ms` if that is
+//!   sooner than sendspin-rs's own. They're stamped on sendspin-rs's clock,
+//!   read off its own requests, so the replies update its `ClockSync` like
+//!   the replies to its own requests.
+//! - A reply whose round trip, timed here, is more than
+//!   `Settings::clock_sync_outlier_ms` over the quickest of the last
+//!   `Settings::clock_sync_window` exchanges was held up on the way, and
+//!   isn't passed on.
+//!
+//! The filter sendspin-rs smooths the offset with keeps its own parameters.
+
+use crate::settings::Settings;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often sendspin-rs sends a time request of its own
+const OWN_INTERVAL: Duration = Duration::from_secs(5);
+/// Between the quick requests after connecting
+const QUICK_INTERVAL: Duration = Duration::from_millis(100);
+/// Requests kept waiting for a reply; older ones are taken as lost
+const MAX_IN_FLIGHT: usize = 32;
+
+#[derive(Debug)]
+pub(super) struct TimeExchanges {
+    interval: Duration,
+    /// Quick requests still to send
+    burst: u32,
+    window: usize,
+    outlier: Duration,
+    /// `client_transmitted` of sendspin-rs's latest request, and when it
+    /// went by
+    clock: Option<(i64, Instant)>,
+    /// When the requests waiting for a reply went out, by their
+    /// `client_transmitted`
+    in_flight: VecDeque<(i64, Instant)>,
+    /// Round trips of the latest exchanges, newest last
+    round_trips: VecDeque<Duration>,
+    next: Option<Instant>,
+}
+
+impl TimeExchanges {
+    pub(super) fn new(interval: Duration, burst: u32, window: usize, outlier: Duration) -> Self {
+        Self {
+            interval,
+            burst,
+            window: window.max(1),
+            outlier,
+            clock: None,
+            in_flight: VecDeque::new(),
+            round_trips: VecDeque::new(),
+            next: None,
+        }
+    }
+
+    pub(super) fn from_settings(settings: &Settings) -> Self {
+        Self::new(
+            Duration::from_millis(u64::from(settings.clock_sync_interval_ms)),
+            settings.clock_sync_burst,
+            settings.clock_sync_window as usize,
+            Duration::from_millis(u64::from(settings.clock_sync_outlier_ms)),
+        )
+    }
+
+    /// A text frame going to the server
+    pub(super) fn outgoing(&mut self, text: &str, now: Instant) {
+        let Some(sent) = time_field(text, "client/time") else {
+            return;
+        };
+        if self.clock.is_none() {
+            self.schedule(now);
+        }
+        self.clock = Some((sent, now));
+        self.track(sent, now);
+    }
+
+    /// A text frame from the server; whether to pass it on
+    pub(super) fn incoming(&mut self, text: &str, now: Instant) -> bool {
+        let Some(sent) = time_field(text, "server/time") else {
+            return true;
+        };
+        let Some(index) = self.in_flight.iter().position(|(id, _)| *id == sent) else {
+            return true;
+        };
+        let Some((_, sent_at)) = self.in_flight.remove(index) else {
+            return true;
+        };
+        let round_trip = now.saturating_duration_since(sent_at);
+        if self.round_trips.len() == self.window {
+            self.round_trips.pop_front();
+        }
+        self.round_trips.push_back(round_trip);
+        let quickest = self.round_trips.iter().min().copied().unwrap_or(round_trip);
+        self.outlier.is_zero() || round_trip - quickest <= self.outlier
+    }
+
+    /// When the next request of our own is due
+    pub(super) fn next_request(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /// A `client/time` request of our own, to send at `now`
+    pub(super) fn request(&mut self, now: Instant) -> Option<String> {
+        let (own, seen) = self.clock?;
+        let sent = own + now.saturating_duration_since(seen).as_micros() as i64;
+        self.burst = self.burst.saturating_sub(1);
+        self.schedule(now);
+        self.track(sent, now);
+        Some(
+            json!({
+                "type": "client/time",
+                "payload": { "client_transmitted": sent },
+            })
+            .to_string(),
+        )
+    }
+
+    fn schedule(&mut self, now: Instant) {
+        self.next = if self.burst > 0 {
+            Some(now + QUICK_INTERVAL)
+        } else if self.interval < OWN_INTERVAL {
+            Some(now + self.interval)
+        } else {
+            None
+        };
+    }
+
+    fn track(&mut self, sent: i64, now: Instant) {
+        if self.in_flight.len() == MAX_IN_FLIGHT {
+            self.in_flight.pop_front();
+        }
+        self.in_flight.push_back((sent, now));
+    }
+}
+
+/// Whether a text frame is a time request or reply
+pub(super) fn is_time_message(text: &str) -> bool {
+    time_field(text, "server/time").is_some() || time_field(text, "client/time").is_some()
+}
+
+/// `client_transmitted` of a `kind` message
+fn time_field(text: &str, kind: &str) -> Option<i64> {
+    if !text.contains(kind) {
+        return None;
+    }
+    let message: Value = serde_json::from_str(text).ok()?;
+    if message["type"] != kind {
+        return None;
+    }
+    message["payload"]["client_transmitted"].as_i64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_time(sent: i64) -> String {
+        json!({ "type": "client/time", "payload": { "client_transmitted": sent } }).to_string()
+    }
+
+    fn server_time(sent: i64) -> String {
+        json!({
+            "type": "server/time",
+            "payload": {
+                "client_transmitted": sent,
+                "server_received": 5,
+                "server_transmitted": 6,
+            },
+        })
+        .to_string()
+    }
+
+    fn sent(request: &str) -> i64 {
+        time_field(request, "client/time").unwrap()
+    }
+
+    #[test]
+    fn bursts_then_keeps_to_the_interval_on_sendspins_clock() {
+        let mut exchanges = TimeExchanges::new(Duration::from_secs(1), 2, 10, Duration::ZERO);
+        let start = Instant::now();
+        assert_eq!(exchanges.next_request(), None);
+
+        exchanges.outgoing(&client_time(1_000_000), start);
+        let quick = start + QUICK_INTERVAL;
+        assert_eq!(exchanges.next_request(), Some(quick));
+        assert_eq!(sent(&exchanges.request(quick).unwrap()), 1_100_000);
+        assert_eq!(exchanges.next_request(), Some(quick + QUICK_INTERVAL));
+        let later = quick + QUICK_INTERVAL;
+        assert_eq!(sent(&exchanges.request(later).unwrap()), 1_200_000);
+        assert_eq!(
+            exchanges.next_request(),
+            Some(later + Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn leaves_sendspin_alone_at_its_own_interval_or_slower() {
+        let mut exchanges = TimeExchanges::new(Duration::from_secs(5), 0, 10, Duration::ZERO);
+        exchanges.outgoing(&client_time(0), Instant::now());
+        assert_eq!(exchanges.next_request(), None);
+    }
+
+    #[test]
+    fn holds_back_replies_that_were_held_up() {
+        let mut exchanges =
+            TimeExchanges::new(Duration::from_secs(5), 0, 10, Duration::from_millis(5));
+        let start = Instant::now();
+        let reply_after = |exchanges: &mut TimeExchanges, sent: i64, millis: u64| {
+            exchanges.outgoing(&client_time(sent), start);
+            exchanges.incoming(&server_time(sent), start + Duration::from_millis(millis))
+        };
+        assert!(reply_after(&mut exchanges, 1, 10));
+        assert!(reply_after(&mut exchanges, 2, 14));
+        assert!(!reply_after(&mut exchanges, 3, 40));
+        // Replies to requests it didn't see, and everything else, pass
+        assert!(exchanges.incoming(&server_time(99), start));
+        assert!(exchanges.incoming(r#"{"type":"server/state","payload":{}}"#, start));
+    }
+}
@@ -0,0 +1,464 @@
+//! Timed playback for the backends that don't use sendspin-rs's player.
+//!
+//! Chunks are queued with the local time they should be heard; the output
+//! callback plays them contiguously and only skips or inserts silence when
+//! playback drifts more than [`TOLERANCE_US`] from schedule. Snapcast
+//! timestamps audio on the server's clock, which [`TimeSync`] relates to
+//! ours, and the tolerance also absorbs the drift between that clock and
+//! the DAC's; Slimproto queues each stream back to back from when it starts.
+
+use crate::sendspin::{devices, SendspinError};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How far playback may run ahead of or behind schedule before it's pulled
+/// back in line
+const TOLERANCE_US: i64 = 5_000;
+/// Samples needed before the offset is trusted
+const MIN_TIME_SAMPLES: usize = 5;
+
+/// Offset between the server's clock and ours, from time exchanges
+#[derive(Debug)]
+pub(crate) struct TimeSync {
+    /// Offsets of the exchanges kept, newest last
+    samples: VecDeque<i64>,
+    /// Round trips of the latest exchanges, kept or not
+    round_trips: VecDeque<i64>,
+    /// Exchanges the median is taken over
+    window: usize,
+    /// How much slower than the quickest recent round trip an exchange may
+    /// be before it's ignored, in microseconds; 0 keeps every exchange
+    outlier_us: i64,
+}
+
+impl TimeSync {
+    pub(crate) fn new(window: usize, outlier_us: i64) -> Self {
+        let window = window.max(MIN_TIME_SAMPLES);
+        Self {
+            samples: VecDeque::with_capacity(window),
+            round_trips: VecDeque::with_capacity(window),
+            window,
+            outlier_us,
+        }
+    }
+
+    /// Record an exchange: `c2s` is how far the server's clock read ahead
+    /// of ours as the request arrived, `s2c` how far ours read ahead of the
+    /// server's as the reply arrived. Network delay inflates both alike, so
+    /// half their difference is the offset, and their sum is the round trip.
+    ///
+    /// An exchange whose round trip is more than `outlier_us` over the
+    /// quickest of the recent ones was held up on the way, and is ignored.
+    /// Ignored exchanges still count towards the recent round trips, so the
+    /// bar rises once the network stays slower.
+    pub(crate) fn add(&mut self, c2s: i64, s2c: i64) {
+        let round_trip = c2s + s2c;
+        if self.round_trips.len() == self.window {
+            self.round_trips.pop_front();
+        }
+        self.round_trips.push_back(round_trip);
+        let quickest = self.round_trips.iter().copied().min().unwrap_or(round_trip);
+        if self.outlier_us > 0 && round_trip - quickest > self.outlier_us {
+            return;
+        }
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((c2s - s2c) / 2);
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.samples.len() >= MIN_TIME_SAMPLES
+    }
+
+    /// Server time minus local time, in microseconds; the median of recent
+    /// exchanges so a delayed reply doesn't throw it off
+    pub(crate) fn offset(&self) -> Option<i64> {
+        if !self.is_ready() {
+            return None;
+        }
+        let mut sorted: Vec<i64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        Some(sorted[sorted.len() / 2])
+    }
+}
+
+/// Queued audio and where playback is in it
+#[derive(Debug)]
+pub(crate) struct Schedule {
+    channels: usize,
+    sample_rate: u32,
+    /// Chunks with the local time, in microseconds, of their first frame
+    queue: VecDeque<(i64, Vec<f32>)>,
+    /// Frames of the front chunk already played
+    cursor: usize,
+    /// Frames played or skipped since the schedule was created
+    position: u64,
+    /// Holding the queue where it is
+    paused: bool,
+    gain: f32,
+    /// Added to the output latency the device reports
+    static_delay_us: i64,
+}
+
+impl Schedule {
+    pub(crate) fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels: usize::from(channels).max(1),
+            sample_rate: sample_rate.max(1),
+            queue: VecDeque::new(),
+            cursor: 0,
+            position: 0,
+            paused: false,
+            gain: 1.0,
+            static_delay_us: 0,
+        }
+    }
+
+    fn frames_to_micros(&self, frames: usize) -> i64 {
+        frames as i64 * 1_000_000 / i64::from(self.sample_rate)
+    }
+
+    fn micros_to_frames(&self, micros: i64) -> usize {
+        (micros.max(0) * i64::from(self.sample_rate) / 1_000_000) as usize
+    }
+
+    pub(crate) fn push(&mut self, at: i64, samples: Vec<f32>) {
+        if !samples.is_empty() {
+            self.queue.push_back((at, samples));
+        }
+    }
+
+    /// Drop everything queued, and play whatever is queued next.
+    pub(crate) fn clear(&mut self) {
+        self.queue.clear();
+        self.cursor = 0;
+        self.paused = false;
+    }
+
+    /// Output gain for `volume` (0-100); squared so the slider feels even
+    pub(crate) fn set_volume(&mut self, volume: u8, muted: bool) {
+        let level = f32::from(volume.min(100)) / 100.0;
+        self.set_gain(if muted { 0.0 } else { level * level });
+    }
+
+    pub(crate) fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// Frames played or skipped so far
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// When the frame after the last queued one is due
+    pub(crate) fn end(&self) -> Option<i64> {
+        let (at, samples) = self.queue.back()?;
+        Some(at + self.frames_to_micros(samples.len() / self.channels))
+    }
+
+    /// Stop playing, keeping what's queued.
+    pub(crate) fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Carry on from where playback paused, with the next frame due at local
+    /// time `at`.
+    pub(crate) fn resume_at(&mut self, at: i64) {
+        self.paused = false;
+        let Some(due) = self.next_due() else {
+            return;
+        };
+        for (start, _) in &mut self.queue {
+            *start += at - due;
+        }
+    }
+
+    pub(crate) fn set_static_delay(&mut self, delay_ms: u16) {
+        self.static_delay_us = i64::from(delay_ms) * 1_000;
+    }
+
+    /// Audio queued but not played yet
+    pub(crate) fn buffered(&self) -> Duration {
+        let frames: usize = self
+            .queue
+            .iter()
+            .map(|(_, samples)| samples.len() / self.channels)
+            .sum();
+        Duration::from_micros(self.frames_to_micros(frames - self.cursor) as u64)
+    }
+
+    /// When the next unplayed frame is due
+    fn next_due(&self) -> Option<i64> {
+        let (at, _) = self.queue.front()?;
+        Some(at + self.frames_to_micros(self.cursor))
+    }
+
+    /// Drop `frames` frames of queued audio.
+    fn skip(&mut self, mut frames: usize) {
+        while let Some((_, samples)) = self.queue.front() {
+            let left = samples.len() / self.channels - self.cursor;
+            if frames < left {
+                self.cursor += frames;
+                self.position += frames as u64;
+                return;
+            }
+            frames -= left;
+            self.position += left as u64;
+            self.queue.pop_front();
+            self.cursor = 0;
+        }
+    }
+
+    /// Fill `out` with the audio due from local time `at`, in microseconds,
+    /// on; silence where nothing is.
+    pub(crate) fn fill(&mut self, out: &mut [f32], at: i64) {
+        out.fill(0.0);
+        if self.paused {
+            return;
+        }
+        let at = at + self.static_delay_us;
+        let frames = out.len() / self.channels;
+        let mut frame = 0;
+        while frame < frames {
+            let Some(due) = self.next_due() else {
+                return;
+            };
+            let error = due - (at + self.frames_to_micros(frame));
+            if error > TOLERANCE_US {
+                // Early: wait in silence
+                frame += self.micros_to_frames(error).max(1);
+                continue;
+            }
+            if error < -TOLERANCE_US {
+                // Late: catch up
+                self.skip(self.micros_to_frames(-error).max(1));
+                continue;
+            }
+            let Some((_, samples)) = self.queue.front() else {
+                return;
+            };
+            let left = samples.len() / self.channels - self.cursor;
+            let count = left.min(frames - frame);
+            let from = self.cursor * self.channels;
+            let to = frame * self.channels;
+            for (out, sample) in out[to..to + count * self.channels]
+                .iter_mut()
+                .zip(&samples[from..])
+            {
+                *out = sample * self.gain;
+            }
+            frame += count;
+            self.cursor += count;
+            self.position += count as u64;
+            if self.cursor * self.channels >= samples.len() {
+                self.queue.pop_front();
+                self.cursor = 0;
+            }
+        }
+    }
+}
+
+/// An open output stream playing a [`Schedule`]. The stream lives on its own
+/// thread, as cpal streams can't move between threads on every platform.
+pub(crate) struct Player {
+    schedule: Arc<Mutex<Schedule>>,
+    stop_tx: std_mpsc::Sender<()>,
+}
+
+impl Player {
+    /// Open `audio_device_id` (the default output if `None`) for
+    /// `channels`-channel audio at `sample_rate`. Local times are counted in
+    /// microseconds from `origin`.
+    pub(crate) fn open(
+        audio_device_id: Option<&str>,
+        channels: u16,
+        sample_rate: u32,
+        origin: Instant,
+    ) -> Result<Self, SendspinError> {
+        let schedule = Arc::new(Mutex::new(Schedule::new(channels, sample_rate)));
+        let (stop_tx, stop_rx) = std_mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = std_mpsc::channel::<Result<(), SendspinError>>();
+        let device_id = audio_device_id.map(str::to_string);
+        let playing = Arc::clone(&schedule);
+        thread::spawn(move || {
+            let stream =
+                match open_stream(device_id.as_deref(), channels, sample_rate, origin, playing) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+            let _ = ready_tx.send(Ok(()));
+            // Until the player is dropped
+            let _ = stop_rx.recv();
+            drop(stream);
+        });
+        ready_rx
+            .recv()
+            .map_err(|_| SendspinError::Device("Audio output thread exited".to_string()))??;
+        Ok(Self { schedule, stop_tx })
+    }
+
+    pub(crate) fn schedule(&self) -> parking_lot::MutexGuard<'_, Schedule> {
+        self.schedule.lock()
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+    }
+}
+
+fn open_stream(
+    audio_device_id: Option<&str>,
+    channels: u16,
+    sample_rate: u32,
+    origin: Instant,
+    schedule: Arc<Mutex<Schedule>>,
+) -> Result<cpal::Stream, SendspinError> {
+    let device = devices::resolve_output_device(audio_device_id)
+        .ok_or_else(|| SendspinError::DeviceNotFound("No output device available".to_string()))?;
+    let config = cpal::StreamConfig {
+        channels,
+        sample_rate,
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                let ts = info.timestamp();
+                let latency = ts.playback.duration_since(&ts.callback).unwrap_or_default();
+                let heard = Instant::now() + latency;
+                let at = heard.saturating_duration_since(origin).as_micros() as i64;
+                schedule.lock().fill(data, at);
+            },
+            |e| log::warn!("[Snapcast] Output stream error: {}", e),
+            None,
+        )
+        .map_err(|e| {
+            let message = format!("Failed to open output stream: {}", e);
+            match e {
+                cpal::BuildStreamError::DeviceNotAvailable => {
+                    devices::unavailable_device_error(&device, message)
+                }
+                _ => SendspinError::Device(message),
+            }
+        })?;
+    stream.play().map_err(|e| {
+        let message = format!("Failed to start output stream: {}", e);
+        match e {
+            cpal::PlayStreamError::DeviceNotAvailable => {
+                devices::unavailable_device_error(&device, message)
+            }
+            _ => SendspinError::Device(message),
+        }
+    })?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)] // silence is exactly 0.0
+mod tests {
+    use super::*;
+
+    /// Mono at 1 kHz, so a frame is a millisecond
+    fn schedule() -> Schedule {
+        Schedule::new(1, 1_000)
+    }
+
+    #[test]
+    fn time_sync_takes_the_median_offset() {
+        let mut sync = TimeSync::new(50, 0);
+        for _ in 0..4 {
+            sync.add(1_010, 990);
+        }
+        assert_eq!(sync.offset(), None);
+        // A slow reply
+        sync.add(5_000, 990);
+        assert_eq!(sync.offset(), Some(10));
+    }
+
+    #[test]
+    fn time_sync_ignores_slow_exchanges() {
+        let mut sync = TimeSync::new(5, 1_000);
+        for _ in 0..5 {
+            sync.add(1_010, 990);
+        }
+        // Held up by 3 ms on the way to the server
+        sync.add(4_010, 990);
+        assert_eq!(sync.samples.len(), 5);
+        assert!(sync.samples.iter().all(|&offset| offset == 10));
+
+        // Once only slow exchanges are recent, they're kept again
+        for _ in 0..5 {
+            sync.add(4_010, 990);
+        }
+        assert!(sync.samples.contains(&1_510));
+    }
+
+    #[test]
+    fn plays_audio_when_it_is_due() {
+        let mut schedule = schedule();
+        schedule.push(10_000, vec![1.0; 20]);
+        let mut out = vec![0.5; 20];
+        schedule.fill(&mut out, 0);
+        // Ten frames of silence, then the start of the chunk
+        assert!(out[..10].iter().all(|&s| s == 0.0));
+        assert!(out[10..].iter().all(|&s| s == 1.0));
+        assert_eq!(schedule.buffered(), Duration::from_millis(10));
+
+        // Picks up where it left off
+        let mut out = vec![0.0; 20];
+        schedule.fill(&mut out, 20_000);
+        assert!(out[..10].iter().all(|&s| s == 1.0));
+        assert!(out[10..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn skips_audio_that_is_late_and_applies_gain() {
+        let mut schedule = schedule();
+        schedule.push(0, (0..100).map(|i| i as f32).collect());
+        schedule.set_volume(50, false);
+        let mut out = vec![0.0; 4];
+        schedule.fill(&mut out, 50_000);
+        assert_eq!(out, [12.5, 12.75, 13.0, 13.25]);
+
+        // Small drift is played through
+        let mut out = vec![0.0; 1];
+        schedule.fill(&mut out, 57_000);
+        assert_eq!(out, [13.5]);
+
+        schedule.set_volume(100, true);
+        schedule.fill(&mut out, 58_000);
+        assert_eq!(out, [0.0]);
+    }
+
+    #[test]
+    fn pauses_and_resumes_where_it_left_off() {
+        let mut schedule = schedule();
+        schedule.push(0, (0..100).map(|i| i as f32).collect());
+        assert_eq!(schedule.end(), Some(100_000));
+        let mut out = vec![0.0; 10];
+        schedule.fill(&mut out, 0);
+        schedule.pause();
+        schedule.fill(&mut out, 10_000);
+        assert_eq!(out, [0.0; 10]);
+        assert_eq!(schedule.position(), 10);
+
+        schedule.resume_at(500_000);
+        assert_eq!(schedule.end(), Some(590_000));
+        let mut out = vec![0.0; 2];
+        schedule.fill(&mut out, 500_000);
+        assert_eq!(out, [10.0, 11.0]);
+        assert_eq!(schedule.position(), 12);
+    }
+}
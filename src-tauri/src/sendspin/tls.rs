@@ -0,0 +1,183 @@
+//! TLS trust for servers with self-signed or privately issued certificates
+//!
+//! Connections to servers without `ServerTls` settings use the platform TLS
+//! stack and trust store as before. For the others a rustls connector is
+//! built that trusts an extra CA bundle, accepts one pinned certificate, or
+//! (only with explicit opt-in) accepts anything.
+
+use crate::settings::ServerTls;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio_tungstenite::Connector;
+
+/// Parse a SHA-256 fingerprint written as hex, optionally separated by
+/// colons or spaces as certificate viewers show it.
+fn parse_fingerprint(text: &str) -> Result<[u8; 32], String> {
+    let hex: String = text.chars().filter(|c| !matches!(c, ':' | ' ')).collect();
+    let invalid = || format!("Invalid SHA-256 fingerprint: {}", text);
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut fingerprint = [0u8; 32];
+    for (byte, pair) in fingerprint.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(fingerprint)
+}
+
+/// Accepts the server certificate whose SHA-256 matches `pin`, or any
+/// certificate without one. Handshake signatures are still verified, so
+/// the server has to hold the certificate's key.
+#[derive(Debug)]
+struct FingerprintVerifier {
+    pin: Option<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.pin {
+            Some(pin) if Sha256::digest(end_entity.as_ref()).as_slice() != pin => {
+                Err(rustls::Error::General(
+                    "Server certificate doesn't match the pinned fingerprint".into(),
+                ))
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// System trust store plus the certificates in the PEM file at `path`
+fn roots_with_bundle(path: &str) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for e in &native.errors {
+        log::warn!("[Sendspin] Failed to load a system certificate: {}", e);
+    }
+    roots.add_parsable_certificates(native.certs);
+
+    let bundle = CertificateDer::pem_file_iter(path)
+        .map_err(|e| format!("Failed to read CA bundle {}: {}", path, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid CA bundle {}: {}", path, e))?;
+    if bundle.is_empty() {
+        return Err(format!("CA bundle {} contains no certificates", path));
+    }
+    let (added, ignored) = roots.add_parsable_certificates(bundle);
+    log::debug!(
+        "[Sendspin] Trusting {} certificates from {} ({} unusable)",
+        added,
+        path,
+        ignored
+    );
+    Ok(roots)
+}
+
+/// Build the connector for a server with custom TLS settings
+pub(crate) fn connector(tls: &ServerTls) -> Result<Connector, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Failed to set up TLS: {}", e))?;
+
+    let pin = tls
+        .pinned_sha256
+        .as_deref()
+        .filter(|pin| !pin.trim().is_empty())
+        .map(parse_fingerprint)
+        .transpose()?;
+    let config = if tls.allow_insecure || pin.is_some() {
+        let pin = if tls.allow_insecure { None } else { pin };
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(FingerprintVerifier { pin, provider }))
+            .with_no_client_auth()
+    } else if let Some(path) = tls.ca_bundle_path.as_deref() {
+        builder
+            .with_root_certificates(roots_with_bundle(path)?)
+            .with_no_client_auth()
+    } else {
+        return Err("No TLS settings to apply".to_string());
+    };
+    Ok(Connector::Rustls(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "5E:FF:56:A2:AF:15:88:25:23:1F:4E:2B:F2:5A:D3:EB:D6:81:2A:42:D4:1E:38:17:A2:9F:24:5B:6C:6D:1E:42";
+
+    #[test]
+    fn fingerprints_parse_with_or_without_colons() {
+        let with_colons = parse_fingerprint(FINGERPRINT).unwrap();
+        let plain = parse_fingerprint(&FINGERPRINT.replace(':', "").to_lowercase()).unwrap();
+        assert_eq!(with_colons, plain);
+        assert_eq!(with_colons[0], 0x5e);
+        assert_eq!(with_colons[31], 0x42);
+    }
+
+    #[test]
+    fn malformed_fingerprints_are_rejected() {
+        assert!(parse_fingerprint("5E:FF").is_err());
+        assert!(parse_fingerprint(&"zz".repeat(32)).is_err());
+        assert!(parse_fingerprint(&"é".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn pinned_connector_builds() {
+        let tls = ServerTls {
+            pinned_sha256: Some(FINGERPRINT.to_string()),
+            ..ServerTls::default()
+        };
+        assert!(matches!(connector(&tls), Ok(Connector::Rustls(_))));
+        assert!(connector(&ServerTls::default()).is_err());
+    }
+}
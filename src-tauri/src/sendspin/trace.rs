@@ -0,0 +1,254 @@
+//! Protocol trace
+//!
+//! While switched on with [`set_enabled`], the Sendspin messages the players
+//! exchange with the server are appended to a trace file, one JSON object
+//! per line, each stamped with the microseconds since the trace started.
+//! Audio chunks and artwork are traced as headers only (server timestamp
+//! and size), so a trace stays small enough to attach to a bug report.
+//!
+//! The trace starts with a header line naming the app version and the wall
+//! clock time it started at, followed by entries like
+//!
+//! ```text
+//! {"t_us":1520,"player":"ma_companion_…","dir":"in","message":{"type":"stream/start",…}}
+//! {"t_us":1873,"player":"ma_companion_…","dir":"in","audio":{"timestamp":88123456,"bytes":7680}}
+//! ```
+//!
+//! Messages sendspin-rs handles by itself (the hello handshake and time
+//! sync) never reach the app, so they aren't traced; nor is the auth
+//! message, which holds the token. Playback commands sent through its
+//! controller are traced as the command the app asked for.
+//!
+//! Like recording, tracing is never a saved setting: it is off again after
+//! a restart.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Version of the trace format, in the header line
+pub const FORMAT_VERSION: u32 = 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+struct Trace {
+    file: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+    entries: u64,
+}
+
+/// Direction of a traced message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the server
+    In,
+    /// To the server
+    Out,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::In => "in",
+            Self::Out => "out",
+        }
+    }
+}
+
+/// Whether tracing is on, where to and how much has been traced
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceStatus {
+    pub enabled: bool,
+    pub path: Option<String>,
+    pub entries: u64,
+}
+
+/// Folder trace files are written to
+pub fn traces_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|p| p.join("music-assistant-companion").join("traces"))
+}
+
+/// The most recent trace file, finished or not
+pub fn latest() -> Option<PathBuf> {
+    fs::read_dir(traces_dir()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .max()
+}
+
+/// Start a new trace file or finish the current one
+pub fn set_enabled(enabled: bool) -> Result<TraceStatus, String> {
+    let Ok(mut trace) = TRACE.lock() else {
+        return Err("The protocol trace is unavailable".to_string());
+    };
+    if enabled && trace.is_none() {
+        *trace = Some(open()?);
+        ENABLED.store(true, Ordering::Relaxed);
+        log::info!(
+            "[Trace] Tracing the Sendspin protocol to {}",
+            trace
+                .as_ref()
+                .map_or_else(String::new, |t| t.path.display().to_string())
+        );
+    } else if !enabled {
+        ENABLED.store(false, Ordering::Relaxed);
+        if let Some(mut finished) = trace.take() {
+            if let Err(e) = finished.file.flush() {
+                log::warn!("[Trace] Failed to finish the trace file: {}", e);
+            }
+            log::info!(
+                "[Trace] Stopped after {} entries; trace is in {}",
+                finished.entries,
+                finished.path.display()
+            );
+        }
+    }
+    Ok(status_of(trace.as_ref()))
+}
+
+pub fn status() -> TraceStatus {
+    match TRACE.lock() {
+        Ok(trace) => status_of(trace.as_ref()),
+        Err(_) => status_of(None),
+    }
+}
+
+fn status_of(trace: Option<&Trace>) -> TraceStatus {
+    TraceStatus {
+        enabled: trace.is_some(),
+        path: trace.map(|t| t.path.to_string_lossy().into_owned()),
+        entries: trace.map_or(0, |t| t.entries),
+    }
+}
+
+fn open() -> Result<Trace, String> {
+    let dir = traces_dir().ok_or("Could not determine the data folder")?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let started_unix_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let path = dir.join(format!("sendspin-{}.jsonl", started_unix_ms));
+    let file =
+        File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut file = BufWriter::new(file);
+    writeln!(file, "{}", header(started_unix_ms))
+        .and_then(|()| file.flush())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Trace {
+        file,
+        path,
+        started: Instant::now(),
+        entries: 0,
+    })
+}
+
+fn header(started_unix_ms: u64) -> Value {
+    json!({
+        "trace": "sendspin",
+        "version": FORMAT_VERSION,
+        "app_version": env!("CARGO_PKG_VERSION"),
+        "started_unix_ms": started_unix_ms,
+    })
+}
+
+/// One trace line; `body` is the entry's kind and content, e.g.
+/// `("message", …)`
+fn entry(t_us: u64, player_id: &str, direction: Direction, (kind, body): (&str, Value)) -> Value {
+    let mut entry = json!({
+        "t_us": t_us,
+        "player": player_id,
+        "dir": direction.as_str(),
+    });
+    entry[kind] = body;
+    entry
+}
+
+/// Append an entry, built only while tracing
+fn record(player_id: &str, direction: Direction, body: impl FnOnce() -> (&'static str, Value)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(mut guard) = TRACE.lock() else {
+        return;
+    };
+    let Some(trace) = guard.as_mut() else {
+        return;
+    };
+    let t_us = trace.started.elapsed().as_micros() as u64;
+    let line = entry(t_us, player_id, direction, body());
+    // Flushed line by line, so a trace of a crash is complete
+    let written = writeln!(trace.file, "{}", line).and_then(|()| trace.file.flush());
+    match written {
+        Ok(()) => trace.entries += 1,
+        Err(e) => {
+            log::warn!("[Trace] Failed to write the trace, stopping: {}", e);
+            ENABLED.store(false, Ordering::Relaxed);
+            *guard = None;
+        }
+    }
+}
+
+fn to_value(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).unwrap_or_else(|e| json!({ "unserializable": e.to_string() }))
+}
+
+/// Trace a protocol message
+pub(crate) fn message(player_id: &str, direction: Direction, message: &impl Serialize) {
+    record(player_id, direction, || ("message", to_value(message)));
+}
+
+/// Trace a playback command sent through sendspin-rs's controller, which
+/// builds the message itself
+pub(crate) fn controller_command(player_id: &str, command: &impl Serialize) {
+    record(player_id, Direction::Out, || {
+        ("controller", to_value(command))
+    });
+}
+
+/// Trace an audio chunk from the server by its header
+pub(crate) fn audio_chunk(player_id: &str, timestamp: i64, bytes: usize) {
+    record(player_id, Direction::In, || {
+        ("audio", json!({ "timestamp": timestamp, "bytes": bytes }))
+    });
+}
+
+/// Trace an artwork image from the server by its size
+pub(crate) fn artwork(player_id: &str, bytes: usize) {
+    record(player_id, Direction::In, || {
+        ("artwork", json!({ "bytes": bytes }))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_carry_time_player_and_direction() {
+        let line = entry(
+            1520,
+            "ma_companion_test",
+            Direction::Out,
+            ("message", json!({ "type": "client/state" })),
+        );
+        assert_eq!(
+            line,
+            json!({
+                "t_us": 1520,
+                "player": "ma_companion_test",
+                "dir": "out",
+                "message": { "type": "client/state" },
+            })
+        );
+        assert_eq!(header(0)["version"], FORMAT_VERSION);
+    }
+}
@@ -0,0 +1,123 @@
+//! How an output device is attached
+//!
+//! Listed with each device so the settings can warn before connecting, e.g.
+//! that a Bluetooth output lags behind the rest of a group. `CoreAudio`
+//! reports it on macOS; elsewhere it is told from the device name, which on
+//! Linux carries the bus (`alsa_output.usb-…`, `…hdmi-stereo`,
+//! `bluez_output.…`).
+
+use super::bluetooth;
+use cpal::traits::DeviceTrait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Built into the computer
+    Builtin,
+    Usb,
+    Bluetooth,
+    /// HDMI or `DisplayPort`, to a display or receiver
+    Hdmi,
+    /// `AirPlay` and other outputs over the network
+    Network,
+    /// A software output with no hardware of its own
+    Virtual,
+    #[default]
+    Unknown,
+}
+
+/// Name fragments of each wired transport, checked in order
+const NAME_HINTS: &[(&str, Transport)] = &[
+    ("usb", Transport::Usb),
+    ("hdmi", Transport::Hdmi),
+    ("displayport", Transport::Hdmi),
+    ("airplay", Transport::Network),
+    ("built-in", Transport::Builtin),
+    ("internal", Transport::Builtin),
+];
+
+/// The transport a device name suggests
+pub fn from_name(name: &str) -> Transport {
+    if bluetooth::name_suggests_bluetooth(name) {
+        return Transport::Bluetooth;
+    }
+    let name = name.to_lowercase();
+    NAME_HINTS
+        .iter()
+        .find(|(hint, _)| name.contains(hint))
+        .map_or(Transport::Unknown, |&(_, transport)| transport)
+}
+
+/// How `device` is attached
+pub fn of_device(device: &cpal::Device) -> Transport {
+    let Ok(desc) = device.description() else {
+        return Transport::Unknown;
+    };
+    let name = desc.name();
+
+    #[cfg(target_os = "macos")]
+    if let Some(transport) = macos::transport(name) {
+        return transport;
+    }
+
+    from_name(name)
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::super::exclusive::macos::{find_device, get_property};
+    use super::Transport;
+    use coreaudio_sys::{
+        kAudioDevicePropertyTransportType, kAudioDeviceTransportTypeAVB,
+        kAudioDeviceTransportTypeAggregate, kAudioDeviceTransportTypeAirPlay,
+        kAudioDeviceTransportTypeAutoAggregate, kAudioDeviceTransportTypeBluetooth,
+        kAudioDeviceTransportTypeBluetoothLE, kAudioDeviceTransportTypeBuiltIn,
+        kAudioDeviceTransportTypeDisplayPort, kAudioDeviceTransportTypeHDMI,
+        kAudioDeviceTransportTypeUSB, kAudioDeviceTransportTypeVirtual,
+    };
+
+    /// Ask `CoreAudio` for the device's transport type
+    pub fn transport(device_name: &str) -> Option<Transport> {
+        let device_id = find_device(device_name).ok()?;
+        let transport: u32 = get_property(device_id, kAudioDevicePropertyTransportType).ok()?;
+        #[allow(non_upper_case_globals)]
+        let transport = match transport {
+            kAudioDeviceTransportTypeBuiltIn => Transport::Builtin,
+            kAudioDeviceTransportTypeUSB => Transport::Usb,
+            kAudioDeviceTransportTypeBluetooth | kAudioDeviceTransportTypeBluetoothLE => {
+                Transport::Bluetooth
+            }
+            kAudioDeviceTransportTypeHDMI | kAudioDeviceTransportTypeDisplayPort => Transport::Hdmi,
+            kAudioDeviceTransportTypeAirPlay | kAudioDeviceTransportTypeAVB => Transport::Network,
+            kAudioDeviceTransportTypeVirtual
+            | kAudioDeviceTransportTypeAggregate
+            | kAudioDeviceTransportTypeAutoAggregate => Transport::Virtual,
+            _ => Transport::Unknown,
+        };
+        Some(transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_transport_from_device_names() {
+        assert_eq!(
+            from_name("alsa_output.usb-Topping_D10-00.analog-stereo"),
+            Transport::Usb
+        );
+        assert_eq!(
+            from_name("alsa_output.pci-0000_00_1f.3.hdmi-stereo"),
+            Transport::Hdmi
+        );
+        assert_eq!(
+            from_name("bluez_output.00_1B_66.a2dp-sink"),
+            Transport::Bluetooth
+        );
+        assert_eq!(from_name("Speakers (USB Audio Device)"), Transport::Usb);
+        assert_eq!(from_name("Speakers (Realtek(R) Audio)"), Transport::Unknown);
+    }
+}
@@ -0,0 +1,244 @@
+//! Fading out instead of cutting off when the buffer runs dry
+//!
+//! The player plays silence once it runs out of audio, so a network stall
+//! long enough to empty the buffer stops the music mid-waveform with a
+//! click. The client loop holds back the last [`FADE`] of the audio it
+//! queues and passes it on in front of the next chunk. If no chunk has come
+//! by the time that tail is nearly due, it is queued fading to silence
+//! instead, and the audio after the gap fades back in (see `dsp`).
+//!
+//! When the tail is due comes from its timestamp on the synchronized clock,
+//! as the player plays it. Holding back only works while more than
+//! [`MARGIN`] is buffered; with less, as at the start of a stream, or while
+//! the clock isn't synchronized, audio passes straight through.
+
+use super::pcm;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// Length of the fade to silence
+const FADE: Duration = Duration::from_millis(20);
+/// How long before the held tail is due it has to be queued, leaving the
+/// player its lead time even on Bluetooth outputs
+const MARGIN: Duration = Duration::from_millis(300);
+
+#[derive(Debug)]
+pub(crate) struct UnderrunFade {
+    sample_rate: u32,
+    channels: u16,
+    bit_depth: u16,
+    /// End of the queued audio, held back
+    tail: Vec<u8>,
+    /// Server timestamp of the held tail's first frame
+    tail_timestamp: i64,
+    /// The tail last passed on, or faded; kept to reuse its allocation
+    spare: Vec<u8>,
+    /// When the held tail has to be queued if nothing follows it
+    deadline: Option<Instant>,
+    samples: Vec<f32>,
+    dither: pcm::Dither,
+}
+
+impl UnderrunFade {
+    pub(crate) fn new() -> Self {
+        Self {
+            sample_rate: 0,
+            channels: 0,
+            bit_depth: 0,
+            tail: Vec::new(),
+            tail_timestamp: 0,
+            spare: Vec::new(),
+            deadline: None,
+            samples: Vec::new(),
+            dither: pcm::Dither::new(),
+        }
+    }
+
+    /// Set the format of the audio going to the player. A tail held in
+    /// another format has to be flushed first.
+    pub(crate) fn set_format(&mut self, sample_rate: u32, channels: u16, bit_depth: u16) {
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.bit_depth = bit_depth;
+        self.discard();
+    }
+
+    fn frame_bytes(&self) -> usize {
+        pcm::bytes_per_sample(self.bit_depth).unwrap_or(0) * usize::from(self.channels)
+    }
+
+    /// Pass `data`, starting at server time `timestamp`, on its way to the
+    /// player, with `plays_at` telling when a server time is due here.
+    /// Returns what to queue now, in order: the tail held back before, then
+    /// `data` but for the tail now held back.
+    pub(crate) fn pass<'a>(
+        &'a mut self,
+        timestamp: i64,
+        data: &'a [u8],
+        plays_at: impl Fn(i64) -> Option<Instant>,
+        now: Instant,
+    ) -> [Option<(i64, &'a [u8])>; 2] {
+        mem::swap(&mut self.tail, &mut self.spare);
+        self.tail.clear();
+        let previous_timestamp = self.tail_timestamp;
+
+        let frame_bytes = self.frame_bytes();
+        let end =
+            timestamp + pcm::frames_to_micros(data.len() / frame_bytes.max(1), self.sample_rate);
+        let hold = frame_bytes != 0
+            && plays_at(end).is_some_and(|end| end.saturating_duration_since(now) > FADE + MARGIN);
+        let hold_frames = if hold {
+            let fade_frames = FADE.as_micros() * u128::from(self.sample_rate) / 1_000_000;
+            (fade_frames as usize).min(data.len() / frame_bytes)
+        } else {
+            0
+        };
+        let (head, tail) = data.split_at(data.len() - hold_frames * frame_bytes);
+        self.tail.extend_from_slice(tail);
+        self.tail_timestamp =
+            timestamp + pcm::frames_to_micros(head.len() / frame_bytes.max(1), self.sample_rate);
+        self.deadline = (!self.tail.is_empty())
+            .then(|| plays_at(self.tail_timestamp))
+            .flatten()
+            .and_then(|due| due.checked_sub(MARGIN));
+
+        [
+            (!self.spare.is_empty()).then_some((previous_timestamp, self.spare.as_slice())),
+            (!head.is_empty()).then_some((timestamp, head)),
+        ]
+    }
+
+    /// When the held tail has to be queued if nothing follows it
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Nothing followed the held tail in time: the tail to queue, fading to
+    /// silence
+    pub(crate) fn fade_out(&mut self) -> Option<(i64, &[u8])> {
+        self.deadline = None;
+        if self.tail.is_empty() {
+            return None;
+        }
+        let channels = usize::from(self.channels);
+        pcm::to_f32_into(&self.tail, self.bit_depth, &mut self.samples);
+        let frames = self.samples.len() / channels.max(1);
+        for (index, frame) in self.samples.chunks_exact_mut(channels).enumerate() {
+            let gain = 1.0 - (index + 1) as f32 / frames as f32;
+            for sample in frame {
+                *sample *= gain;
+            }
+        }
+        self.dither
+            .quantize_into(&self.samples, channels, self.bit_depth, &mut self.spare);
+        self.tail.clear();
+        Some((self.tail_timestamp, self.spare.as_slice()))
+    }
+
+    /// The held tail to queue as it is, when the stream ends
+    pub(crate) fn flush(&mut self) -> Option<(i64, &[u8])> {
+        self.deadline = None;
+        mem::swap(&mut self.tail, &mut self.spare);
+        self.tail.clear();
+        (!self.spare.is_empty()).then_some((self.tail_timestamp, self.spare.as_slice()))
+    }
+
+    /// Drop the held tail, when the stream is cleared
+    pub(crate) fn discard(&mut self) {
+        self.deadline = None;
+        self.tail.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 16-bit mono at 1 kHz, so one frame is two bytes and one millisecond
+    fn fade() -> UnderrunFade {
+        let mut fade = UnderrunFade::new();
+        fade.set_format(1_000, 1, 16);
+        fade
+    }
+
+    fn frames(count: usize, value: i16) -> Vec<u8> {
+        value.to_le_bytes().repeat(count)
+    }
+
+    fn lengths(pieces: [Option<(i64, &[u8])>; 2]) -> [Option<(i64, usize)>; 2] {
+        pieces.map(|piece| piece.map(|(timestamp, data)| (timestamp, data.len() / 2)))
+    }
+
+    /// A clock on which server time 0 is due `lead` after `now`
+    fn clock(now: Instant, lead: Duration) -> impl Fn(i64) -> Option<Instant> {
+        move |timestamp| Some(now + lead + Duration::from_micros(timestamp as u64))
+    }
+
+    #[test]
+    fn holds_back_the_tail_until_the_next_chunk() {
+        let mut fade = fade();
+        let now = Instant::now();
+        let ahead = clock(now, Duration::from_millis(900));
+        let chunk = frames(100, 1_000);
+        assert_eq!(
+            lengths(fade.pass(0, &chunk, &ahead, now)),
+            [None, Some((0, 80))]
+        );
+        // The tail starts at 80 ms, due 980 ms from now
+        assert_eq!(fade.deadline(), Some(now + Duration::from_millis(680)));
+        assert_eq!(
+            lengths(fade.pass(100_000, &chunk, &ahead, now)),
+            [Some((80_000, 20)), Some((100_000, 80))]
+        );
+        assert_eq!(fade.flush().map(|(timestamp, _)| timestamp), Some(180_000));
+        assert_eq!(fade.flush(), None);
+    }
+
+    #[test]
+    fn passes_everything_while_little_is_buffered() {
+        let mut fade = fade();
+        let now = Instant::now();
+        let chunk = frames(100, 1_000);
+        assert_eq!(
+            lengths(fade.pass(0, &chunk, clock(now, Duration::ZERO), now)),
+            [None, Some((0, 100))]
+        );
+        assert_eq!(fade.deadline(), None);
+    }
+
+    #[test]
+    fn passes_everything_while_the_clock_is_unsynchronized() {
+        let mut fade = fade();
+        let now = Instant::now();
+        let chunk = frames(100, 1_000);
+        assert_eq!(
+            lengths(fade.pass(0, &chunk, |_| None, now)),
+            [None, Some((0, 100))]
+        );
+        assert_eq!(fade.deadline(), None);
+    }
+
+    #[test]
+    fn fades_the_tail_to_silence() {
+        let mut fade = fade();
+        let now = Instant::now();
+        let _ = fade.pass(
+            0,
+            &frames(100, 10_000),
+            clock(now, Duration::from_millis(900)),
+            now,
+        );
+        let (timestamp, tail) = fade.fade_out().unwrap();
+        assert_eq!(timestamp, 80_000);
+        let samples: Vec<i16> = tail
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples.len(), 20);
+        assert!(samples[0] > 9_000, "{}", samples[0]);
+        assert!(samples.windows(2).all(|pair| pair[1] <= pair[0] + 1));
+        assert!(samples[19].abs() <= 1);
+        assert_eq!(fade.deadline(), None);
+        assert_eq!(fade.flush(), None);
+    }
+}
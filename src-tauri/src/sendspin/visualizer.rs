@@ -0,0 +1,292 @@
+//! Spectrum visualizer
+//!
+//! Analyses the PCM the main player is about to play into log-spaced
+//! frequency bands and pushes them to the frontend as
+//! [`events::VISUALIZER`](super::events::VISUALIZER) frames. Frames are
+//! timed to when their audio is expected to come out of the speakers, not to
+//! when it arrives, so the display follows what is heard rather than running
+//! a buffer's length ahead.
+//!
+//! Frames are computed locally from the decoded stream, so the client needs
+//! nothing from the server for them and leaves `visualizer_v1_support`
+//! unset. Analysis only runs while a page has asked for it with
+//! [`set_enabled`].
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of bands in a frame
+pub const BANDS: usize = 32;
+/// Analysis window, in samples
+const FFT_SIZE: usize = 2048;
+/// Frames produced per second of audio
+const FRAMES_PER_SEC: u32 = 30;
+/// How often due frames are checked for and emitted
+pub(crate) const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / FRAMES_PER_SEC as u64);
+/// Lowest and highest band edges, in Hz
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16_000.0;
+/// Level shown as an empty band, in dBFS
+const FLOOR_DB: f32 = -70.0;
+/// Frames kept waiting for their play time; about the longest buffer the
+/// server is asked to keep
+const MAX_PENDING: usize = 5 * FRAMES_PER_SEC as usize;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Start or stop producing visualizer frames
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub(crate) struct Visualizer {
+    /// Hann window
+    window: Vec<f32>,
+    /// `e^(-2πik/N)` for the first half of the FFT
+    twiddles: Vec<(f32, f32)>,
+    /// The last `FFT_SIZE` samples, mixed to mono
+    history: VecDeque<f32>,
+    /// Samples since the last frame
+    since_frame: usize,
+    sample_rate: u32,
+    /// Frames and when their audio plays
+    pending: VecDeque<(Instant, Vec<f32>)>,
+    /// Whether the last emitted frame was silence
+    silent: bool,
+}
+
+impl Visualizer {
+    pub(crate) fn new() -> Self {
+        let n = FFT_SIZE as f32;
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n).cos())
+            .collect();
+        let twiddles = (0..FFT_SIZE / 2)
+            .map(|k| {
+                let angle = -std::f32::consts::TAU * k as f32 / n;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        Self {
+            window,
+            twiddles,
+            history: VecDeque::with_capacity(FFT_SIZE),
+            since_frame: 0,
+            sample_rate: 0,
+            pending: VecDeque::new(),
+            silent: true,
+        }
+    }
+
+    /// Forget buffered audio and frames, e.g. when the stream is cleared
+    pub(crate) fn reset(&mut self) {
+        self.history.clear();
+        self.pending.clear();
+        self.since_frame = 0;
+    }
+
+    /// Analyse interleaved `samples` whose first frame plays at `plays_at`
+    pub(crate) fn push(
+        &mut self,
+        samples: &[f32],
+        channels: u16,
+        sample_rate: u32,
+        plays_at: Instant,
+    ) {
+        if channels == 0 || sample_rate == 0 {
+            return;
+        }
+        if sample_rate != self.sample_rate {
+            self.reset();
+            self.sample_rate = sample_rate;
+        }
+        let hop = (sample_rate / FRAMES_PER_SEC) as usize;
+        let channels = usize::from(channels);
+        for (i, frame) in samples.chunks_exact(channels).enumerate() {
+            if self.history.len() == FFT_SIZE {
+                self.history.pop_front();
+            }
+            self.history
+                .push_back(frame.iter().sum::<f32>() / channels as f32);
+            self.since_frame += 1;
+            if self.since_frame >= hop && self.history.len() == FFT_SIZE {
+                self.since_frame = 0;
+                let offset = Duration::from_secs_f64(i as f64 / f64::from(sample_rate));
+                if self.pending.len() == MAX_PENDING {
+                    self.pending.pop_front();
+                }
+                self.pending.push_back((plays_at + offset, self.bands()));
+            }
+        }
+    }
+
+    /// The latest frame whose audio has started playing by `now`, dropping
+    /// older ones. Once playback runs out a single frame of silence follows
+    /// so the display doesn't freeze on the last one.
+    pub(crate) fn due(&mut self, now: Instant) -> Option<Vec<f32>> {
+        let mut latest = None;
+        while self.pending.front().is_some_and(|(at, _)| *at <= now) {
+            latest = self.pending.pop_front().map(|(_, bands)| bands);
+        }
+        if latest.is_some() {
+            self.silent = false;
+            return latest;
+        }
+        if self.pending.is_empty() && !self.silent {
+            self.silent = true;
+            return Some(vec![0.0; BANDS]);
+        }
+        None
+    }
+
+    /// Band levels of the current history, from 0 (at or below
+    /// [`FLOOR_DB`]) to 1 (full scale)
+    fn bands(&self) -> Vec<f32> {
+        let mut re: Vec<f32> = self
+            .history
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| sample * weight)
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im, &self.twiddles);
+
+        // A full-scale sine peaks at N/4 through a Hann window
+        let full_scale = FFT_SIZE as f32 / 4.0;
+        let bin_hz = self.sample_rate as f32 / FFT_SIZE as f32;
+        let max_freq = MAX_FREQ.min(self.sample_rate as f32 / 2.0);
+        let ratio = (max_freq / MIN_FREQ).powf(1.0 / BANDS as f32);
+        (0..BANDS)
+            .map(|band| {
+                let low = MIN_FREQ * ratio.powi(band as i32);
+                // Low bands are narrower than a bin; give each at least one
+                let first = ((low / bin_hz).round() as usize).clamp(1, FFT_SIZE / 2 - 1);
+                let last = ((low * ratio / bin_hz).round() as usize).clamp(first + 1, FFT_SIZE / 2);
+                let peak = (first..last)
+                    .map(|k| re[k].hypot(im[k]))
+                    .fold(0.0, f32::max);
+                let db = 20.0 * (peak / full_scale).max(f32::MIN_POSITIVE).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// In-place iterative radix-2 FFT; the length must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32], twiddles: &[(f32, f32)]) {
+    let n = re.len();
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let stride = n / len;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (wr, wi) = twiddles[k * stride];
+                let a = start + k;
+                let b = a + len / 2;
+                let tr = re[b] * wr - im[b] * wi;
+                let ti = re[b] * wi + im[b] * wr;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    fn sine(freq: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let sample =
+                    amplitude * (std::f32::consts::TAU * freq * i as f32 / RATE as f32).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    fn loudest_band(bands: &[f32]) -> usize {
+        bands
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(band, _)| band)
+            .unwrap()
+    }
+
+    #[test]
+    fn a_tone_lights_up_its_band() {
+        let t0 = Instant::now();
+        let mut low = Visualizer::new();
+        low.push(&sine(100.0, 0.5, 4096), 2, RATE, t0);
+        let mut high = Visualizer::new();
+        high.push(&sine(5_000.0, 0.5, 4096), 2, RATE, t0);
+
+        let low_bands = low.due(t0 + Duration::from_secs(1)).unwrap();
+        let high_bands = high.due(t0 + Duration::from_secs(1)).unwrap();
+        assert!(loudest_band(&low_bands) < loudest_band(&high_bands));
+        // -6 dBFS sits near the top of a 70 dB range
+        assert!(low_bands[loudest_band(&low_bands)] > 0.8);
+    }
+
+    #[test]
+    fn silence_is_empty() {
+        let t0 = Instant::now();
+        let mut visualizer = Visualizer::new();
+        visualizer.push(&vec![0.0; 8192], 2, RATE, t0);
+        let bands = visualizer.due(t0 + Duration::from_secs(1)).unwrap();
+        assert!(bands.iter().all(|level| *level == 0.0));
+    }
+
+    #[test]
+    fn frames_wait_for_their_audio_to_play() {
+        let t0 = Instant::now();
+        let plays_at = t0 + Duration::from_millis(500);
+        let mut visualizer = Visualizer::new();
+        visualizer.push(&sine(440.0, 0.5, 4096), 2, RATE, plays_at);
+
+        assert_eq!(visualizer.due(t0), None);
+        assert!(visualizer
+            .due(plays_at + Duration::from_millis(100))
+            .is_some());
+    }
+
+    #[test]
+    fn silence_follows_once_playback_runs_out() {
+        let t0 = Instant::now();
+        let mut visualizer = Visualizer::new();
+        visualizer.push(&sine(440.0, 0.5, 4096), 2, RATE, t0);
+        let later = t0 + Duration::from_secs(1);
+        assert!(visualizer.due(later).is_some());
+
+        assert_eq!(visualizer.due(later), Some(vec![0.0; BANDS]));
+        assert_eq!(visualizer.due(later), None);
+    }
+}
@@ -1,6 +1,6 @@
 //! Linux volume control implementation using `PulseAudio`
 
-use super::{VolumeChangeCallback, VolumeControlImpl};
+use super::{SendspinError, VolumeChangeCallback, VolumeControlImpl};
 use libpulse_binding::{
     callbacks::ListResult,
     context::{
@@ -18,12 +18,12 @@ use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 enum VolumeCommand {
-    SetVolume(u8, Sender<Result<(), String>>),
-    SetMute(bool, Sender<Result<(), String>>),
-    GetVolume(Sender<Result<u8, String>>),
-    GetMute(Sender<Result<bool, String>>),
+    SetVolume(u8, Sender<Result<(), SendspinError>>),
+    SetMute(bool, Sender<Result<(), SendspinError>>),
+    GetVolume(Sender<Result<u8, SendspinError>>),
+    GetMute(Sender<Result<bool, SendspinError>>),
     IsAvailable(Sender<bool>),
-    SetChangeCallback(VolumeChangeCallback, Sender<Result<(), String>>),
+    SetChangeCallback(VolumeChangeCallback, Sender<Result<(), SendspinError>>),
     Shutdown,
 }
 
@@ -204,17 +204,17 @@ impl LinuxVolumeControl {
         context: &Context,
         sink_idx: &Arc<Mutex<Option<u32>>>,
         volume: u8,
-    ) -> Result<(), String> {
+    ) -> Result<(), SendspinError> {
         use libpulse_binding::volume::ChannelVolumes;
 
         let idx = *sink_idx.lock().unwrap();
         if idx.is_none() {
-            return Err("Sink not found".to_string());
+            return Err(SendspinError::DeviceNotFound("Sink not found".to_string()));
         }
 
         let idx = idx.unwrap();
 
-        let (result_tx, result_rx) = channel::<Result<ChannelVolumes, String>>();
+        let (result_tx, result_rx) = channel::<Result<ChannelVolumes, SendspinError>>();
         let result_tx = Arc::new(Mutex::new(Some(result_tx)));
 
         // Get current sink info to determine channel count
@@ -234,7 +234,7 @@ impl LinuxVolumeControl {
 
         let new_volume = result_rx
             .recv_timeout(Duration::from_secs(1))
-            .map_err(|_| "Timeout getting sink info".to_string())??;
+            .map_err(|_| SendspinError::VolumeControl("Timeout getting sink info".to_string()))??;
 
         // Set the sink volume
         let (set_result_tx, set_result_rx) = channel();
@@ -253,12 +253,14 @@ impl LinuxVolumeControl {
 
         let success = set_result_rx
             .recv_timeout(Duration::from_secs(1))
-            .map_err(|_| "Timeout setting volume".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Timeout setting volume".to_string()))?;
 
         if success {
             Ok(())
         } else {
-            Err("Failed to set volume".to_string())
+            Err(SendspinError::VolumeControl(
+                "Failed to set volume".to_string(),
+            ))
         }
     }
 
@@ -266,10 +268,10 @@ impl LinuxVolumeControl {
         context: &Context,
         sink_idx: &Arc<Mutex<Option<u32>>>,
         muted: bool,
-    ) -> Result<(), String> {
+    ) -> Result<(), SendspinError> {
         let idx = *sink_idx.lock().unwrap();
         if idx.is_none() {
-            return Err("Sink not found".to_string());
+            return Err(SendspinError::DeviceNotFound("Sink not found".to_string()));
         }
 
         let idx = idx.unwrap();
@@ -291,22 +293,24 @@ impl LinuxVolumeControl {
 
         let success = result_rx
             .recv_timeout(Duration::from_secs(1))
-            .map_err(|_| "Timeout setting mute".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Timeout setting mute".to_string()))?;
 
         if success {
             Ok(())
         } else {
-            Err("Failed to set mute".to_string())
+            Err(SendspinError::VolumeControl(
+                "Failed to set mute".to_string(),
+            ))
         }
     }
 
     fn handle_get_volume(
         context: &Context,
         sink_idx: &Arc<Mutex<Option<u32>>>,
-    ) -> Result<u8, String> {
+    ) -> Result<u8, SendspinError> {
         let idx = *sink_idx.lock().unwrap();
         if idx.is_none() {
-            return Err("Sink not found".to_string());
+            return Err(SendspinError::DeviceNotFound("Sink not found".to_string()));
         }
 
         let idx = idx.unwrap();
@@ -328,16 +332,16 @@ impl LinuxVolumeControl {
 
         result_rx
             .recv_timeout(Duration::from_secs(1))
-            .map_err(|_| "Timeout getting volume".to_string())
+            .map_err(|_| SendspinError::VolumeControl("Timeout getting volume".to_string()))
     }
 
     fn handle_get_mute(
         context: &Context,
         sink_idx: &Arc<Mutex<Option<u32>>>,
-    ) -> Result<bool, String> {
+    ) -> Result<bool, SendspinError> {
         let idx = *sink_idx.lock().unwrap();
         if idx.is_none() {
-            return Err("Sink not found".to_string());
+            return Err(SendspinError::DeviceNotFound("Sink not found".to_string()));
         }
 
         let idx = idx.unwrap();
@@ -357,7 +361,7 @@ impl LinuxVolumeControl {
 
         result_rx
             .recv_timeout(Duration::from_secs(1))
-            .map_err(|_| "Timeout getting mute state".to_string())
+            .map_err(|_| SendspinError::VolumeControl("Timeout getting mute state".to_string()))
     }
 
     fn handle_set_change_callback(
@@ -366,13 +370,13 @@ impl LinuxVolumeControl {
         change_callback: &Arc<Mutex<Option<VolumeChangeCallback>>>,
         callback: VolumeChangeCallback,
         last_self_change: &Arc<AtomicU64>,
-    ) -> Result<(), String> {
+    ) -> Result<(), SendspinError> {
         // Store the callback
         *change_callback.lock().unwrap() = Some(callback);
 
         let idx = *sink_idx.lock().unwrap();
         if idx.is_none() {
-            return Err("Sink not found".to_string());
+            return Err(SendspinError::DeviceNotFound("Sink not found".to_string()));
         }
 
         // Subscribe to sink events
@@ -388,10 +392,14 @@ impl LinuxVolumeControl {
 
         let success = result_rx
             .recv_timeout(Duration::from_secs(1))
-            .map_err(|_| "Timeout subscribing to events".to_string())?;
+            .map_err(|_| {
+                SendspinError::VolumeControl("Timeout subscribing to events".to_string())
+            })?;
 
         if !success {
-            return Err("Failed to subscribe to sink events".to_string());
+            return Err(SendspinError::VolumeControl(
+                "Failed to subscribe to sink events".to_string(),
+            ));
         }
 
         // Set up subscription callback
@@ -451,44 +459,44 @@ impl LinuxVolumeControl {
 }
 
 impl VolumeControlImpl for LinuxVolumeControl {
-    fn set_volume(&mut self, volume: u8) -> Result<(), String> {
+    fn set_volume(&mut self, volume: u8) -> Result<(), SendspinError> {
         let (response_tx, response_rx) = channel();
         self.command_tx
             .send(VolumeCommand::SetVolume(volume, response_tx))
-            .map_err(|_| "Failed to send command".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Failed to send command".to_string()))?;
         response_rx
             .recv_timeout(Duration::from_secs(2))
-            .map_err(|_| "Timeout waiting for response".to_string())?
+            .map_err(|_| SendspinError::VolumeControl("Timeout waiting for response".to_string()))?
     }
 
-    fn set_mute(&mut self, muted: bool) -> Result<(), String> {
+    fn set_mute(&mut self, muted: bool) -> Result<(), SendspinError> {
         let (response_tx, response_rx) = channel();
         self.command_tx
             .send(VolumeCommand::SetMute(muted, response_tx))
-            .map_err(|_| "Failed to send command".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Failed to send command".to_string()))?;
         response_rx
             .recv_timeout(Duration::from_secs(2))
-            .map_err(|_| "Timeout waiting for response".to_string())?
+            .map_err(|_| SendspinError::VolumeControl("Timeout waiting for response".to_string()))?
     }
 
-    fn get_volume(&self) -> Result<u8, String> {
+    fn get_volume(&self) -> Result<u8, SendspinError> {
         let (response_tx, response_rx) = channel();
         self.command_tx
             .send(VolumeCommand::GetVolume(response_tx))
-            .map_err(|_| "Failed to send command".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Failed to send command".to_string()))?;
         response_rx
             .recv_timeout(Duration::from_secs(2))
-            .map_err(|_| "Timeout waiting for response".to_string())?
+            .map_err(|_| SendspinError::VolumeControl("Timeout waiting for response".to_string()))?
     }
 
-    fn get_mute(&self) -> Result<bool, String> {
+    fn get_mute(&self) -> Result<bool, SendspinError> {
         let (response_tx, response_rx) = channel();
         self.command_tx
             .send(VolumeCommand::GetMute(response_tx))
-            .map_err(|_| "Failed to send command".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Failed to send command".to_string()))?;
         response_rx
             .recv_timeout(Duration::from_secs(2))
-            .map_err(|_| "Timeout waiting for response".to_string())?
+            .map_err(|_| SendspinError::VolumeControl("Timeout waiting for response".to_string()))?
     }
 
     fn is_available(&self) -> bool {
@@ -505,14 +513,14 @@ impl VolumeControlImpl for LinuxVolumeControl {
             .unwrap_or(false)
     }
 
-    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), String> {
+    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), SendspinError> {
         let (response_tx, response_rx) = channel();
         self.command_tx
             .send(VolumeCommand::SetChangeCallback(callback, response_tx))
-            .map_err(|_| "Failed to send command".to_string())?;
+            .map_err(|_| SendspinError::VolumeControl("Failed to send command".to_string()))?;
         response_rx
             .recv_timeout(Duration::from_secs(2))
-            .map_err(|_| "Timeout waiting for response".to_string())?
+            .map_err(|_| SendspinError::VolumeControl("Timeout waiting for response".to_string()))?
     }
 }
 
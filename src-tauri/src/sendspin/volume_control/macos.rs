@@ -1,6 +1,6 @@
 //! macOS volume control implementation using `CoreAudio`
 
-use super::{VolumeChangeCallback, VolumeControlImpl};
+use super::{SendspinError, VolumeChangeCallback, VolumeControlImpl};
 use coreaudio_sys::*;
 use std::mem;
 use std::ptr;
@@ -96,7 +96,7 @@ impl MacOSVolumeControl {
         })
     }
 
-    fn set_volume_scalar(&self, volume_scalar: f32) -> Result<(), String> {
+    fn set_volume_scalar(&self, volume_scalar: f32) -> Result<(), SendspinError> {
         unsafe {
             let property_address = AudioObjectPropertyAddress {
                 mSelector: kAudioDevicePropertyVolumeScalar,
@@ -114,14 +114,17 @@ impl MacOSVolumeControl {
             );
 
             if status != 0 {
-                return Err(format!("Failed to set volume: {}", status));
+                return Err(SendspinError::VolumeControl(format!(
+                    "Failed to set volume: {}",
+                    status
+                )));
             }
 
             Ok(())
         }
     }
 
-    fn get_volume_scalar(&self) -> Result<f32, String> {
+    fn get_volume_scalar(&self) -> Result<f32, SendspinError> {
         unsafe {
             let property_address = AudioObjectPropertyAddress {
                 mSelector: kAudioDevicePropertyVolumeScalar,
@@ -142,7 +145,10 @@ impl MacOSVolumeControl {
             );
 
             if status != 0 {
-                return Err(format!("Failed to get volume: {}", status));
+                return Err(SendspinError::VolumeControl(format!(
+                    "Failed to get volume: {}",
+                    status
+                )));
             }
 
             Ok(volume)
@@ -151,7 +157,7 @@ impl MacOSVolumeControl {
 }
 
 impl VolumeControlImpl for MacOSVolumeControl {
-    fn set_volume(&mut self, volume: u8) -> Result<(), String> {
+    fn set_volume(&mut self, volume: u8) -> Result<(), SendspinError> {
         // Record timestamp to prevent feedback loop
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -163,7 +169,7 @@ impl VolumeControlImpl for MacOSVolumeControl {
         self.set_volume_scalar(volume_scalar)
     }
 
-    fn set_mute(&mut self, muted: bool) -> Result<(), String> {
+    fn set_mute(&mut self, muted: bool) -> Result<(), SendspinError> {
         // Record timestamp to prevent feedback loop
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -180,7 +186,9 @@ impl VolumeControlImpl for MacOSVolumeControl {
 
             // Check if device supports mute
             if AudioObjectHasProperty(self.device_id, &raw const property_address) == 0 {
-                return Err("Device does not support mute".to_string());
+                return Err(SendspinError::VolumeControl(
+                    "Device does not support mute".to_string(),
+                ));
             }
 
             let mute_value: u32 = u32::from(muted);
@@ -195,19 +203,22 @@ impl VolumeControlImpl for MacOSVolumeControl {
             );
 
             if status != 0 {
-                return Err(format!("Failed to set mute: {}", status));
+                return Err(SendspinError::VolumeControl(format!(
+                    "Failed to set mute: {}",
+                    status
+                )));
             }
 
             Ok(())
         }
     }
 
-    fn get_volume(&self) -> Result<u8, String> {
+    fn get_volume(&self) -> Result<u8, SendspinError> {
         let volume_scalar = self.get_volume_scalar()?;
         Ok((volume_scalar * 100.0) as u8)
     }
 
-    fn get_mute(&self) -> Result<bool, String> {
+    fn get_mute(&self) -> Result<bool, SendspinError> {
         unsafe {
             let property_address = AudioObjectPropertyAddress {
                 mSelector: kAudioDevicePropertyMute,
@@ -233,7 +244,10 @@ impl VolumeControlImpl for MacOSVolumeControl {
             );
 
             if status != 0 {
-                return Err(format!("Failed to get mute state: {}", status));
+                return Err(SendspinError::VolumeControl(format!(
+                    "Failed to get mute state: {}",
+                    status
+                )));
             }
 
             Ok(mute_value != 0)
@@ -244,7 +258,7 @@ impl VolumeControlImpl for MacOSVolumeControl {
         true
     }
 
-    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), String> {
+    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), SendspinError> {
         // Stop any existing polling thread before starting a new one
         self.stop_flag.store(true, Ordering::Relaxed);
         if let Some(thread) = self.worker_thread.take() {
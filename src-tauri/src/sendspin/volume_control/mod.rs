@@ -5,13 +5,15 @@
 //!
 //! - Windows: Controls endpoint volume via WASAPI
 //! - macOS: Controls output device volume via `CoreAudio`
-//! - Linux: Controls sink volume via `PulseAudio`
+//! - Linux: Controls the default sink's volume via `PipeWire` when it's
+//!   running (and the `pipewire` feature is on), otherwise via `PulseAudio`
 //!
 //! Note: Platform-specific volume control requires unsafe code to interface with
 //! system APIs. This module explicitly allows unsafe code for this purpose.
 
 #![allow(unsafe_code)]
 
+use super::SendspinError;
 use parking_lot::Mutex;
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -21,6 +23,8 @@ use std::sync::Arc;
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+mod pipewire;
 #[cfg(target_os = "windows")]
 mod windows;
 
@@ -44,28 +48,28 @@ impl VolumeController {
 
     /// Set up a callback to be notified when the OS volume changes
     /// The callback will receive (volume: u8, muted: bool) when changes are detected
-    pub fn set_change_callback(&self, callback: VolumeChangeCallback) -> Result<(), String> {
+    pub fn set_change_callback(&self, callback: VolumeChangeCallback) -> Result<(), SendspinError> {
         self.inner.lock().set_change_callback(callback)
     }
 
     /// Set volume level (0-100)
-    pub fn set_volume(&self, volume: u8) -> Result<(), String> {
+    pub fn set_volume(&self, volume: u8) -> Result<(), SendspinError> {
         let volume = volume.min(100);
         self.inner.lock().set_volume(volume)
     }
 
     /// Set mute state
-    pub fn set_mute(&self, muted: bool) -> Result<(), String> {
+    pub fn set_mute(&self, muted: bool) -> Result<(), SendspinError> {
         self.inner.lock().set_mute(muted)
     }
 
     /// Get current volume level (0-100)
-    pub fn get_volume(&self) -> Result<u8, String> {
+    pub fn get_volume(&self) -> Result<u8, SendspinError> {
         self.inner.lock().get_volume()
     }
 
     /// Get current mute state
-    pub fn get_mute(&self) -> Result<bool, String> {
+    pub fn get_mute(&self) -> Result<bool, SendspinError> {
         self.inner.lock().get_mute()
     }
 
@@ -75,15 +79,17 @@ impl VolumeController {
     }
 }
 
-/// Trait for platform-specific volume control implementations
+/// Trait for platform-specific volume control implementations. Errors are
+/// [`SendspinError::DeviceNotFound`] when there's no device to control, and
+/// [`SendspinError::VolumeControl`] otherwise.
 trait VolumeControlImpl {
-    fn set_volume(&mut self, volume: u8) -> Result<(), String>;
-    fn set_mute(&mut self, muted: bool) -> Result<(), String>;
-    fn get_volume(&self) -> Result<u8, String>;
-    fn get_mute(&self) -> Result<bool, String>;
+    fn set_volume(&mut self, volume: u8) -> Result<(), SendspinError>;
+    fn set_mute(&mut self, muted: bool) -> Result<(), SendspinError>;
+    fn get_volume(&self) -> Result<u8, SendspinError>;
+    fn get_mute(&self) -> Result<bool, SendspinError>;
     fn is_available(&self) -> bool;
     /// Set up a callback to be notified when the OS volume changes
-    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), String>;
+    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), SendspinError>;
 }
 
 /// Create a platform-specific volume controller
@@ -95,7 +101,13 @@ fn create_platform_controller() -> Option<Box<dyn VolumeControlImpl + Send>> {
     return macos::MacOSVolumeControl::new();
 
     #[cfg(target_os = "linux")]
-    return linux::LinuxVolumeControl::new();
+    {
+        #[cfg(feature = "pipewire")]
+        if let Some(controller) = pipewire::PipeWireVolumeControl::new() {
+            return Some(controller);
+        }
+        return linux::LinuxVolumeControl::new();
+    }
 
     #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
     {
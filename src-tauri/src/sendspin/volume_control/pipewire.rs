@@ -0,0 +1,477 @@
+//! Linux volume control implementation using `PipeWire`
+//!
+//! Sets the default sink node's volume directly rather than through the
+//! `PulseAudio` compatibility layer, and follows the default sink when the
+//! desktop switches it. Volumes are cubic, as desktop sound settings show
+//! them.
+
+use super::{SendspinError, VolumeChangeCallback, VolumeControlImpl};
+use crate::sendspin::pipewire::{self as graph, Connection};
+use ::pipewire as pw;
+use parking_lot::Mutex;
+use pw::metadata::{Metadata, MetadataListener};
+use pw::node::{Node, NodeListener};
+use pw::properties::Properties;
+use pw::registry::{GlobalObject, Registry};
+use pw::spa::param::ParamType;
+use pw::spa::pod::deserialize::PodDeserializer;
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{Object, Pod, Property, PropertyFlags, Value, ValueArray};
+use pw::spa::sys::{
+    SPA_PARAM_Props, SPA_PROP_channelVolumes, SPA_PROP_mute, SPA_TYPE_OBJECT_Props,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Changes reported this soon after our own are taken as its echo
+const SELF_CHANGE_GRACE_PERIOD_MS: u64 = 200;
+
+enum VolumeCommand {
+    SetVolume(u8, Sender<Result<(), SendspinError>>),
+    SetMute(bool, Sender<Result<(), SendspinError>>),
+    Shutdown,
+}
+
+/// The default sink's volume, as last reported by `PipeWire`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SinkVolume {
+    volume: u8,
+    muted: bool,
+    channels: usize,
+}
+
+/// State shared with the `PipeWire` thread
+#[derive(Default)]
+struct Shared {
+    sink: Mutex<Option<SinkVolume>>,
+    callback: Mutex<Option<VolumeChangeCallback>>,
+    /// Timestamp of last self-initiated change (to prevent feedback loops)
+    last_self_change: AtomicU64,
+}
+
+pub struct PipeWireVolumeControl {
+    command_tx: pw::channel::Sender<VolumeCommand>,
+    shared: Arc<Shared>,
+}
+
+impl PipeWireVolumeControl {
+    /// Connect to `PipeWire`; `None` if it isn't running
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> Option<Box<dyn VolumeControlImpl + Send>> {
+        let (command_tx, command_rx) = pw::channel::channel();
+        let (ready_tx, ready_rx) = channel();
+        let shared = Arc::new(Shared::default());
+
+        // PipeWire proxies are not Send, so they live on a thread of their own
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || run(command_rx, thread_shared, ready_tx));
+
+        match ready_rx.recv_timeout(Duration::from_secs(1)) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                log::info!("[VolumeControl] PipeWire not available: {}", e);
+                return None;
+            }
+            Err(_) => log::warn!("[VolumeControl] PipeWire has no default sink yet"),
+        }
+        log::info!("[VolumeControl] Linux PipeWire volume control initialized successfully");
+        Some(Box::new(Self { command_tx, shared }))
+    }
+
+    fn request(
+        &self,
+        command: impl FnOnce(Sender<Result<(), SendspinError>>) -> VolumeCommand,
+    ) -> Result<(), SendspinError> {
+        let (response_tx, response_rx) = channel();
+        self.command_tx
+            .send(command(response_tx))
+            .map_err(|_| SendspinError::VolumeControl("Failed to send command".to_string()))?;
+        response_rx
+            .recv_timeout(Duration::from_secs(2))
+            .map_err(|_| SendspinError::VolumeControl("Timeout waiting for response".to_string()))?
+    }
+
+    fn sink(&self) -> Result<SinkVolume, SendspinError> {
+        self.shared
+            .sink
+            .lock()
+            .ok_or_else(|| SendspinError::DeviceNotFound("Sink not found".to_string()))
+    }
+}
+
+impl VolumeControlImpl for PipeWireVolumeControl {
+    fn set_volume(&mut self, volume: u8) -> Result<(), SendspinError> {
+        self.request(|response_tx| VolumeCommand::SetVolume(volume, response_tx))
+    }
+
+    fn set_mute(&mut self, muted: bool) -> Result<(), SendspinError> {
+        self.request(|response_tx| VolumeCommand::SetMute(muted, response_tx))
+    }
+
+    fn get_volume(&self) -> Result<u8, SendspinError> {
+        self.sink().map(|sink| sink.volume)
+    }
+
+    fn get_mute(&self) -> Result<bool, SendspinError> {
+        self.sink().map(|sink| sink.muted)
+    }
+
+    fn is_available(&self) -> bool {
+        self.shared.sink.lock().is_some()
+    }
+
+    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), SendspinError> {
+        *self.shared.callback.lock() = Some(callback);
+        log::info!("[VolumeControl] Linux PipeWire sink volume change listener registered");
+        Ok(())
+    }
+}
+
+impl Drop for PipeWireVolumeControl {
+    fn drop(&mut self) {
+        let _ = self.command_tx.send(VolumeCommand::Shutdown);
+    }
+}
+
+/// Connect and follow the default sink until shut down. Sends on `ready`
+/// once the sink's volume is known, or if `PipeWire` can't be reached.
+fn run(
+    command_rx: pw::channel::Receiver<VolumeCommand>,
+    shared: Arc<Shared>,
+    ready: Sender<Result<(), String>>,
+) {
+    let connection = match Connection::new() {
+        Ok(connection) => connection,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let tracker = Rc::new(RefCell::new(Tracker {
+        registry: Rc::clone(&connection.registry),
+        shared,
+        ready: Some(ready),
+        sinks: HashMap::new(),
+        default_sink: None,
+        metadata: None,
+        sink: None,
+    }));
+
+    let _registry_listener = connection
+        .registry
+        .add_listener_local()
+        .global({
+            let tracker = Rc::downgrade(&tracker);
+            move |global| {
+                if let Some(tracker) = tracker.upgrade() {
+                    Tracker::add(&tracker, global.to_owned());
+                }
+            }
+        })
+        .global_remove({
+            let tracker = Rc::downgrade(&tracker);
+            move |id| {
+                if let Some(tracker) = tracker.upgrade() {
+                    Tracker::remove(&tracker, id);
+                }
+            }
+        })
+        .register();
+
+    let _commands = command_rx.attach(connection.mainloop.loop_(), {
+        let tracker = Rc::clone(&tracker);
+        let mainloop = connection.mainloop.clone();
+        move |command| match command {
+            VolumeCommand::SetVolume(volume, response_tx) => {
+                let tracker = tracker.borrow();
+                tracker.mark_self_change();
+                let channels = tracker.shared.sink.lock().map_or(2, |sink| sink.channels);
+                let _ = response_tx.send(tracker.set_props(Property {
+                    key: SPA_PROP_channelVolumes,
+                    flags: PropertyFlags::empty(),
+                    value: Value::ValueArray(ValueArray::Float(vec![
+                        percent_to_linear(volume);
+                        channels
+                    ])),
+                }));
+            }
+            VolumeCommand::SetMute(muted, response_tx) => {
+                let tracker = tracker.borrow();
+                tracker.mark_self_change();
+                let _ = response_tx.send(tracker.set_props(Property {
+                    key: SPA_PROP_mute,
+                    flags: PropertyFlags::empty(),
+                    value: Value::Bool(muted),
+                }));
+            }
+            VolumeCommand::Shutdown => mainloop.quit(),
+        }
+    });
+
+    log::info!("[VolumeControl] PipeWire connected");
+    connection.mainloop.run();
+}
+
+/// A sink node bound to follow its volume
+struct BoundSink {
+    id: u32,
+    node: Node,
+    _listener: NodeListener,
+}
+
+/// Follows the default sink, on the `PipeWire` thread
+struct Tracker {
+    registry: Rc<Registry>,
+    shared: Arc<Shared>,
+    /// Taken once the sink's volume is first known
+    ready: Option<Sender<Result<(), String>>>,
+    /// Sink nodes by node name
+    sinks: HashMap<String, GlobalObject<Properties>>,
+    default_sink: Option<String>,
+    metadata: Option<(Metadata, MetadataListener)>,
+    sink: Option<BoundSink>,
+}
+
+impl Tracker {
+    /// A global appeared in the registry
+    fn add(this: &Rc<RefCell<Self>>, global: GlobalObject<Properties>) {
+        if graph::is_default_metadata(&global) {
+            let metadata: Metadata = match this.borrow().registry.bind(&global) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::warn!("[VolumeControl] Failed to bind PipeWire metadata: {}", e);
+                    return;
+                }
+            };
+            let listener = metadata
+                .add_listener_local()
+                .property({
+                    let tracker = Rc::downgrade(this);
+                    move |subject, key, _, value| {
+                        if subject == pw::core::PW_ID_CORE && key == Some("default.audio.sink") {
+                            Self::set_default_sink(
+                                &tracker,
+                                value.and_then(graph::sink_name_from_metadata),
+                            );
+                        }
+                        0
+                    }
+                })
+                .register();
+            this.borrow_mut().metadata = Some((metadata, listener));
+        } else if graph::is_sink(&global) {
+            let Some(name) = graph::prop(&global, "node.name").map(str::to_string) else {
+                return;
+            };
+            this.borrow_mut().sinks.insert(name, global);
+            Self::follow(this);
+        }
+    }
+
+    /// A global went away
+    fn remove(this: &Rc<RefCell<Self>>, id: u32) {
+        this.borrow_mut().sinks.retain(|_, global| global.id != id);
+        Self::follow(this);
+    }
+
+    fn set_default_sink(this: &Weak<RefCell<Self>>, name: Option<String>) {
+        let Some(this) = this.upgrade() else {
+            return;
+        };
+        log::debug!("[VolumeControl] Default sink: {:?}", name);
+        this.borrow_mut().default_sink = name;
+        Self::follow(&this);
+    }
+
+    /// Bind the default sink if it isn't already
+    fn follow(this: &Rc<RefCell<Self>>) {
+        let mut guard = this.borrow_mut();
+        let tracker = &mut *guard;
+        let target = tracker
+            .default_sink
+            .as_ref()
+            .and_then(|name| tracker.sinks.get(name));
+        if tracker.sink.as_ref().map(|sink| sink.id) == target.map(|global| global.id) {
+            return;
+        }
+        tracker.sink = None;
+        let Some(global) = target else {
+            *tracker.shared.sink.lock() = None;
+            return;
+        };
+
+        let node: Node = match tracker.registry.bind(global) {
+            Ok(node) => node,
+            Err(e) => {
+                log::warn!("[VolumeControl] Failed to bind PipeWire sink: {}", e);
+                return;
+            }
+        };
+        let listener = node
+            .add_listener_local()
+            .param({
+                let tracker = Rc::downgrade(this);
+                move |_, id, _, _, param| {
+                    if id != ParamType::Props {
+                        return;
+                    }
+                    if let (Some(tracker), Some(param)) = (tracker.upgrade(), param) {
+                        tracker.borrow_mut().update(param);
+                    }
+                }
+            })
+            .register();
+        node.subscribe_params(&[ParamType::Props]);
+        tracker.sink = Some(BoundSink {
+            id: global.id,
+            node,
+            _listener: listener,
+        });
+    }
+
+    /// The sink's props changed
+    fn update(&mut self, param: &Pod) {
+        let Some((volumes, muted)) = parse_props(param) else {
+            return;
+        };
+        let average = volumes.iter().sum::<f32>() / volumes.len() as f32;
+        let sink = SinkVolume {
+            volume: linear_to_percent(average),
+            muted,
+            channels: volumes.len(),
+        };
+        let previous = self.shared.sink.lock().replace(sink);
+        if let Some(ready) = self.ready.take() {
+            let _ = ready.send(Ok(()));
+        }
+        if previous
+            .is_none_or(|previous| (previous.volume, previous.muted) == (sink.volume, sink.muted))
+        {
+            return;
+        }
+
+        let last_self_ms = self.shared.last_self_change.load(Ordering::Relaxed);
+        if now_ms().saturating_sub(last_self_ms) < SELF_CHANGE_GRACE_PERIOD_MS {
+            // Skip notification - this was triggered by our own volume change
+            return;
+        }
+        if let Some(ref callback) = *self.shared.callback.lock() {
+            let _ = callback.send((sink.volume, sink.muted));
+        }
+    }
+
+    fn mark_self_change(&self) {
+        self.shared
+            .last_self_change
+            .store(now_ms(), Ordering::Relaxed);
+    }
+
+    /// Set one of the bound sink's props
+    fn set_props(&self, property: Property) -> Result<(), SendspinError> {
+        let sink = self
+            .sink
+            .as_ref()
+            .ok_or_else(|| SendspinError::DeviceNotFound("Sink not found".to_string()))?;
+        let props = Value::Object(Object {
+            type_: SPA_TYPE_OBJECT_Props,
+            id: SPA_PARAM_Props,
+            properties: vec![property],
+        });
+        let bytes = PodSerializer::serialize(Cursor::new(Vec::new()), &props)
+            .map_err(|e| {
+                SendspinError::VolumeControl(format!("Failed to build sink props: {:?}", e))
+            })?
+            .0
+            .into_inner();
+        let pod = Pod::from_bytes(&bytes).ok_or_else(|| {
+            SendspinError::VolumeControl("Failed to build sink props".to_string())
+        })?;
+        sink.node.set_param(ParamType::Props, 0, pod);
+        Ok(())
+    }
+}
+
+/// Channel volumes and mute in a sink's `Props` param; `None` for props
+/// without volumes
+fn parse_props(param: &Pod) -> Option<(Vec<f32>, bool)> {
+    let (_, Value::Object(object)) =
+        PodDeserializer::deserialize_any_from(param.as_bytes()).ok()?
+    else {
+        return None;
+    };
+    let mut volumes = None;
+    let mut muted = false;
+    for property in object.properties {
+        match (property.key, property.value) {
+            (SPA_PROP_channelVolumes, Value::ValueArray(ValueArray::Float(values))) => {
+                volumes = Some(values);
+            }
+            (SPA_PROP_mute, Value::Bool(value)) => muted = value,
+            _ => {}
+        }
+    }
+    volumes
+        .filter(|volumes| !volumes.is_empty())
+        .map(|volumes| (volumes, muted))
+}
+
+fn linear_to_percent(linear: f32) -> u8 {
+    (linear.max(0.0).cbrt() * 100.0).round().min(100.0) as u8
+}
+
+fn percent_to_linear(percent: u8) -> f32 {
+    (f32::from(percent) / 100.0).powi(3)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_is_cubic() {
+        assert!((percent_to_linear(50) - 0.125).abs() < f32::EPSILON);
+        for percent in [0, 1, 33, 50, 99, 100] {
+            assert_eq!(linear_to_percent(percent_to_linear(percent)), percent);
+        }
+        assert_eq!(linear_to_percent(2.0), 100);
+    }
+
+    #[test]
+    fn props_carry_channel_volumes_and_mute() {
+        let props = Value::Object(Object {
+            type_: SPA_TYPE_OBJECT_Props,
+            id: SPA_PARAM_Props,
+            properties: vec![
+                Property {
+                    key: SPA_PROP_mute,
+                    flags: PropertyFlags::empty(),
+                    value: Value::Bool(true),
+                },
+                Property {
+                    key: SPA_PROP_channelVolumes,
+                    flags: PropertyFlags::empty(),
+                    value: Value::ValueArray(ValueArray::Float(vec![0.5, 0.25])),
+                },
+            ],
+        });
+        let bytes = PodSerializer::serialize(Cursor::new(Vec::new()), &props)
+            .unwrap()
+            .0
+            .into_inner();
+        let pod = Pod::from_bytes(&bytes).unwrap();
+        assert_eq!(parse_props(pod), Some((vec![0.5, 0.25], true)));
+    }
+}
@@ -1,23 +1,85 @@
 //! Windows volume control implementation using WASAPI
 
-use super::{VolumeChangeCallback, VolumeControlImpl};
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use super::{SendspinError, VolumeChangeCallback, VolumeControlImpl};
+use parking_lot::Mutex;
 use std::thread::ThreadId;
-use std::time::{SystemTime, UNIX_EPOCH};
+use windows::core::{implement, GUID};
 use windows::Win32::Foundation::{RPC_E_CHANGED_MODE, S_FALSE, S_OK};
-use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
-use windows::Win32::Media::Audio::{eRender, ERole, IMMDeviceEnumerator, MMDeviceEnumerator};
+use windows::Win32::Media::Audio::Endpoints::{
+    IAudioEndpointVolume, IAudioEndpointVolumeCallback, IAudioEndpointVolumeCallback_Impl,
+};
+use windows::Win32::Media::Audio::{
+    eRender, ERole, IMMDeviceEnumerator, MMDeviceEnumerator, AUDCLNT_E_DEVICE_INVALIDATED,
+    AUDIO_VOLUME_NOTIFICATION_DATA,
+};
 use windows::Win32::System::Com::{
     CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED,
 };
 
+/// Event context our own volume and mute changes are tagged with, so their
+/// notifications aren't reported back as external changes
+const SELF_CHANGE_CONTEXT: GUID = GUID::from_u128(0x6d1c_43a2_9b7e_4f0a_8e35_2c9d_71f4_b8e6);
+
 // SAFETY: `IAudioEndpointVolume` is free-threaded and internally synchronized.
 // App-initiated calls are also serialized by `VolumeController`.
 struct SendableEndpointVolume(IAudioEndpointVolume);
 unsafe impl Send for SendableEndpointVolume {}
 unsafe impl Sync for SendableEndpointVolume {}
 
+// SAFETY: the callback object is ours (`VolumeNotifications`), whose state
+// is `Send + Sync`; the interface pointer is only used to unregister it.
+struct SendableVolumeCallback(IAudioEndpointVolumeCallback);
+unsafe impl Send for SendableVolumeCallback {}
+unsafe impl Sync for SendableVolumeCallback {}
+
+fn scalar_to_percent(scalar: f32) -> u8 {
+    (scalar * 100.0) as u8
+}
+
+/// An endpoint volume call failing; the endpoint is invalidated once its
+/// device is unplugged or disabled
+fn endpoint_error(what: &str, error: &windows::core::Error) -> SendspinError {
+    let message = format!("{}: {}", what, error);
+    if error.code() == AUDCLNT_E_DEVICE_INVALIDATED {
+        SendspinError::DeviceNotFound(message)
+    } else {
+        SendspinError::VolumeControl(message)
+    }
+}
+
+fn not_available() -> SendspinError {
+    SendspinError::DeviceNotFound("Endpoint volume not available".to_string())
+}
+
+/// Receives endpoint volume changes from the audio service, on its threads,
+/// as they happen
+#[implement(IAudioEndpointVolumeCallback)]
+struct VolumeNotifications {
+    callback: VolumeChangeCallback,
+    /// Last values reported, or read when notifications started
+    last_values: Mutex<Option<(u8, bool)>>,
+}
+
+impl IAudioEndpointVolumeCallback_Impl for VolumeNotifications_Impl {
+    fn OnNotify(&self, pnotify: *mut AUDIO_VOLUME_NOTIFICATION_DATA) -> windows::core::Result<()> {
+        // SAFETY: the audio service passes notification data that is valid
+        // for the duration of the call
+        let Some(data) = (unsafe { pnotify.as_ref() }) else {
+            return Ok(());
+        };
+        let values = (scalar_to_percent(data.fMasterVolume), data.bMuted.as_bool());
+        let mut last_values = self.last_values.lock();
+        if *last_values == Some(values) {
+            return Ok(());
+        }
+        let own_change = data.guidEventContext == SELF_CHANGE_CONTEXT;
+        if own_change || self.callback.send(values).is_ok() {
+            *last_values = Some(values);
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ComInitialization {
     /// `S_OK`: this thread now owns a COM initialization count.
@@ -82,9 +144,8 @@ pub struct WindowsVolumeControl {
     endpoint_volume: Option<SendableEndpointVolume>,
     com_initialization: ComInitialization,
     com_thread_id: ThreadId,
-    last_self_change: Arc<AtomicU64>,
-    stop_flag: Arc<AtomicBool>,
-    polling_thread: Option<std::thread::JoinHandle<()>>,
+    /// Registered for endpoint volume notifications, while set
+    notifications: Option<SendableVolumeCallback>,
 }
 
 impl WindowsVolumeControl {
@@ -129,76 +190,68 @@ impl WindowsVolumeControl {
             endpoint_volume: Some(SendableEndpointVolume(endpoint_volume)),
             com_initialization,
             com_thread_id,
-            last_self_change: Arc::new(AtomicU64::new(0)),
-            stop_flag: Arc::new(AtomicBool::new(false)),
-            polling_thread: None,
+            notifications: None,
         })
     }
+
+    fn unregister_notifications(&mut self) {
+        let (Some(endpoint_volume), Some(notifications)) =
+            (self.endpoint_volume.as_ref(), self.notifications.take())
+        else {
+            return;
+        };
+        if let Err(e) = unsafe {
+            endpoint_volume
+                .0
+                .UnregisterControlChangeNotify(&notifications.0)
+        } {
+            log::warn!(
+                "[VolumeControl] Failed to unregister volume notifications: {}",
+                e
+            );
+        }
+    }
 }
 
 impl VolumeControlImpl for WindowsVolumeControl {
-    fn set_volume(&mut self, volume: u8) -> Result<(), String> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        self.last_self_change.store(now, Ordering::Relaxed);
-
-        let endpoint_volume = self
-            .endpoint_volume
-            .as_ref()
-            .ok_or("Endpoint volume not available")?;
+    fn set_volume(&mut self, volume: u8) -> Result<(), SendspinError> {
+        let endpoint_volume = self.endpoint_volume.as_ref().ok_or_else(not_available)?;
 
         let volume_scalar = f32::from(volume) / 100.0;
 
         unsafe {
             endpoint_volume
                 .0
-                .SetMasterVolumeLevelScalar(volume_scalar, std::ptr::null())
+                .SetMasterVolumeLevelScalar(volume_scalar, &SELF_CHANGE_CONTEXT)
         }
-        .map_err(|e| format!("Failed to set volume: {}", e))?;
+        .map_err(|e| endpoint_error("Failed to set volume", &e))?;
 
         Ok(())
     }
 
-    fn set_mute(&mut self, muted: bool) -> Result<(), String> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        self.last_self_change.store(now, Ordering::Relaxed);
-
-        let endpoint_volume = self
-            .endpoint_volume
-            .as_ref()
-            .ok_or("Endpoint volume not available")?;
+    fn set_mute(&mut self, muted: bool) -> Result<(), SendspinError> {
+        let endpoint_volume = self.endpoint_volume.as_ref().ok_or_else(not_available)?;
 
-        unsafe { endpoint_volume.0.SetMute(muted, std::ptr::null()) }
-            .map_err(|e| format!("Failed to set mute: {}", e))?;
+        unsafe { endpoint_volume.0.SetMute(muted, &SELF_CHANGE_CONTEXT) }
+            .map_err(|e| endpoint_error("Failed to set mute", &e))?;
 
         Ok(())
     }
 
-    fn get_volume(&self) -> Result<u8, String> {
-        let endpoint_volume = self
-            .endpoint_volume
-            .as_ref()
-            .ok_or("Endpoint volume not available")?;
+    fn get_volume(&self) -> Result<u8, SendspinError> {
+        let endpoint_volume = self.endpoint_volume.as_ref().ok_or_else(not_available)?;
 
         let volume_scalar = unsafe { endpoint_volume.0.GetMasterVolumeLevelScalar() }
-            .map_err(|e| format!("Failed to get volume: {}", e))?;
+            .map_err(|e| endpoint_error("Failed to get volume", &e))?;
 
-        Ok((volume_scalar * 100.0) as u8)
+        Ok(scalar_to_percent(volume_scalar))
     }
 
-    fn get_mute(&self) -> Result<bool, String> {
-        let endpoint_volume = self
-            .endpoint_volume
-            .as_ref()
-            .ok_or("Endpoint volume not available")?;
+    fn get_mute(&self) -> Result<bool, SendspinError> {
+        let endpoint_volume = self.endpoint_volume.as_ref().ok_or_else(not_available)?;
 
         let muted = unsafe { endpoint_volume.0.GetMute() }
-            .map_err(|e| format!("Failed to get mute state: {}", e))?;
+            .map_err(|e| endpoint_error("Failed to get mute state", &e))?;
 
         Ok(muted.as_bool())
     }
@@ -207,106 +260,38 @@ impl VolumeControlImpl for WindowsVolumeControl {
         self.endpoint_volume.is_some()
     }
 
-    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), String> {
-        self.stop_flag.store(true, Ordering::Relaxed);
-        if let Some(thread) = self.polling_thread.take() {
-            let _ = thread.join();
-        }
-        self.stop_flag = Arc::new(AtomicBool::new(false));
-
-        // Polling keeps volume-change behavior consistent across platforms.
-        let endpoint_volume = Arc::new(SendableEndpointVolume(
-            self.endpoint_volume
-                .as_ref()
-                .ok_or("Endpoint volume not available")?
-                .0
-                .clone(),
-        ));
-        let last_self_change = Arc::clone(&self.last_self_change);
-        let stop_flag = Arc::clone(&self.stop_flag);
+    fn set_change_callback(&mut self, callback: VolumeChangeCallback) -> Result<(), SendspinError> {
+        self.unregister_notifications();
 
-        // Read initial volume/mute so the polling thread doesn't fire a
-        // spurious "changed" notification on its first tick.
+        // Start from the current values, so the first notification about
+        // something else (e.g. a channel balance change) isn't reported
         let initial_values = match (self.get_volume(), self.get_mute()) {
             (Ok(v), Ok(m)) => Some((v, m)),
             _ => None,
         };
+        let notifications: IAudioEndpointVolumeCallback = VolumeNotifications {
+            callback,
+            last_values: Mutex::new(initial_values),
+        }
+        .into();
 
-        let polling_thread = std::thread::spawn(move || {
-            use std::time::Duration;
-            const POLL_INTERVAL: Duration = Duration::from_secs(2);
-            const SELF_CHANGE_GRACE_PERIOD_MS: u64 = 1000;
-
-            let com_initialization = match initialize_com_for_volume_control() {
-                Ok(initialization) => initialization,
-                Err(e) => {
-                    log::error!("[VolumeControl] Failed to initialize COM on polling thread: {e}");
-                    return;
-                }
-            };
-            let _com_guard = ComUninitializeGuard::new(com_initialization);
-
-            let mut last_values: Option<(u8, bool)> = initial_values;
-
-            loop {
-                std::thread::sleep(POLL_INTERVAL);
-
-                if stop_flag.load(Ordering::Relaxed) {
-                    break;
-                }
-
-                let now_ms = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as u64;
-                let last_self_ms = last_self_change.load(Ordering::Relaxed);
-                if now_ms.saturating_sub(last_self_ms) < SELF_CHANGE_GRACE_PERIOD_MS {
-                    continue;
-                }
-
-                let volume_result = unsafe {
-                    match endpoint_volume.0.GetMasterVolumeLevelScalar() {
-                        Ok(scalar) => Some((scalar * 100.0) as u8),
-                        Err(_) => None,
-                    }
-                };
-
-                let mute_result = unsafe {
-                    match endpoint_volume.0.GetMute() {
-                        Ok(muted) => Some(muted.as_bool()),
-                        Err(_) => None,
-                    }
-                };
-
-                if let (Some(volume), Some(muted)) = (volume_result, mute_result) {
-                    let current_values = (volume, muted);
-
-                    if last_values != Some(current_values) {
-                        if callback.send(current_values).is_ok() {
-                            last_values = Some(current_values);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-            }
-        });
-
-        self.polling_thread = Some(polling_thread);
+        let endpoint_volume = self.endpoint_volume.as_ref().ok_or_else(not_available)?;
+        unsafe {
+            endpoint_volume
+                .0
+                .RegisterControlChangeNotify(&notifications)
+        }
+        .map_err(|e| endpoint_error("Failed to register for volume notifications", &e))?;
+        self.notifications = Some(SendableVolumeCallback(notifications));
 
-        log::info!("[VolumeControl] Windows volume polling enabled (2s interval)");
+        log::info!("[VolumeControl] Windows volume change notifications enabled");
         Ok(())
     }
 }
 
 impl Drop for WindowsVolumeControl {
     fn drop(&mut self) {
-        self.stop_flag.store(true, Ordering::Relaxed);
-
-        if let Some(thread) = self.polling_thread.take() {
-            let _ = thread.join();
-        }
-
+        self.unregister_notifications();
         self.endpoint_volume = None;
 
         // COM init counts are thread-local; never balance ours from a different
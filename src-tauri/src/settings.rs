@@ -1,8 +1,66 @@
+use crate::sendspin::SendspinManager;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::RwLock;
-use tauri_plugin_autostart::ManagerExt;
+use tauri::Manager;
+
+/// Default for `Settings::keepalive_timeout_secs`
+const DEFAULT_KEEPALIVE_TIMEOUT_SECS: u32 = 20;
+/// Default for `Settings::clock_sync_interval_ms`
+const DEFAULT_CLOCK_SYNC_INTERVAL_MS: u32 = 1_000;
+/// Default for `Settings::clock_sync_burst`
+const DEFAULT_CLOCK_SYNC_BURST: u32 = 5;
+/// Default for `Settings::clock_sync_window`
+const DEFAULT_CLOCK_SYNC_WINDOW: u32 = 50;
+
+/// Version of the settings file layout. Bump it with a migration in
+/// [`MIGRATIONS`] whenever a field is renamed, moved or changes meaning;
+/// new fields with a serde default need neither.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Upgrades the raw settings file by one version, the first from version 1
+/// to 2. Files from before versioning are version 1.
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+const MIGRATIONS: &[Migration] = &[remember_delays_per_device, move_tokens_to_credential_store];
+
+/// 1 to 2: before delays were kept per device, each player had one delay
+/// for whichever device it was on. Store it as that device's delay, so
+/// moving the player to another device and back brings it back.
+fn remember_delays_per_device(raw: &mut Value) -> Result<(), String> {
+    let delays: Vec<(String, i64)> = std::iter::once(&*raw)
+        .chain(raw["additional_players"].as_array().into_iter().flatten())
+        .filter_map(|player| {
+            let delay = player["sync_delay_ms"]
+                .as_i64()
+                .filter(|&delay| delay != 0)?;
+            let device = player["audio_device_id"].as_str();
+            Some((device_key(device), delay))
+        })
+        .collect();
+    let stored = raw
+        .as_object_mut()
+        .ok_or("The settings are not a JSON object")?
+        .entry("device_sync_delays")
+        .or_insert_with(|| Value::Object(Default::default()))
+        .as_object_mut()
+        .ok_or("device_sync_delays is not a JSON object")?;
+    for (device, delay) in delays {
+        stored.entry(device).or_insert_with(|| delay.into());
+    }
+    Ok(())
+}
+
+/// 2 to 3: auth tokens moved out of this file into the OS credential store.
+/// Any an older build left here are moved there, or dropped if they can't
+/// be, and never written back.
+fn move_tokens_to_credential_store(raw: &mut Value) -> Result<(), String> {
+    crate::secrets::migrate_plaintext_tokens(raw);
+    Ok(())
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -19,13 +77,256 @@ pub enum VolumeControlMode {
     Disabled,
 }
 
+/// Quality of the resampler used when a stream's rate isn't supported by the
+/// output device.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ResamplerQuality {
+    /// Cubic polynomial interpolation; cheapest
+    Fast,
+    /// Windowed sinc with a moderate filter length
+    #[default]
+    Balanced,
+    /// Long windowed sinc filter; transparent but the most CPU intensive
+    High,
+}
+
+/// Which loudness measurement normalization brings to the reference level
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    /// Each track on its own
+    Track,
+    /// Whole albums, keeping the loudness differences between their tracks
+    Album,
+}
+
+/// Protocol the built-in player connects to the server with
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerBackend {
+    #[default]
+    Sendspin,
+    /// A Snapcast client, for setups that use Snapserver for multiroom
+    Snapcast,
+    /// A Squeezelite-style Slimproto client, for MA's Squeezelite provider
+    Slimproto,
+    /// A UPnP/DLNA `MediaRenderer`, for MA's DLNA provider or other control points
+    Dlna,
+}
+
+/// What the now-playing file holds (see `now_playing_file`)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NowPlayingFileFormat {
+    /// One line, "Artist - Track"
+    #[default]
+    Text,
+    /// The track's details and the path of its artwork
+    Json,
+}
+
+/// Where on the screen the on-screen display shows (see `osd`)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OsdPosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    Center,
+    BottomLeft,
+    #[default]
+    BottomCenter,
+    BottomRight,
+}
+
+/// Colors of the on-screen display
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OsdTheme {
+    /// Dark or light, following the system
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+/// Response of a parametric EQ band
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EqFilter {
+    #[default]
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+/// One band of a parametric EQ
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct EqBand {
+    #[serde(default)]
+    pub filter: EqFilter,
+    pub frequency_hz: f32,
+    // Ignored by the low- and high-pass filters
+    #[serde(default)]
+    pub gain_db: f32,
+    #[serde(default = "default_eq_q")]
+    pub q: f32,
+}
+
+/// Strength of headphone crossfeed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Crossfeed {
+    #[default]
+    Off,
+    /// 700 Hz, 4.5 dB (bs2b default)
+    Mild,
+    /// 700 Hz, 6 dB (Chu Moy)
+    Medium,
+    /// 650 Hz, 9.5 dB (Jan Meier)
+    Strong,
+}
+
+/// Processing tied to one output device, so corrections for headphones and
+/// for speakers follow whichever a player is using.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct OutputProfile {
+    #[serde(default)]
+    pub eq_enabled: bool,
+    // Gain (dB) ahead of the EQ, to leave headroom for boosted bands
+    #[serde(default)]
+    pub eq_preamp_db: f32,
+    #[serde(default)]
+    pub eq_bands: Vec<EqBand>,
+    // The device is headphones, which enables crossfeed
+    #[serde(default)]
+    pub headphones: bool,
+    #[serde(default)]
+    pub crossfeed: Crossfeed,
+    // Swap the left and right channels
+    #[serde(default)]
+    pub swap_channels: bool,
+    // Device outputs (numbered from 1) that stereo streams play on, for
+    // multichannel interfaces; the first two when unset
+    #[serde(default)]
+    pub stereo_channels: Option<[u16; 2]>,
+}
+
+/// Balance and mono downmix of one player, for odd speaker placements or
+/// hearing in one ear
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelMix {
+    /// -100 (left only) through 0 (centered) to 100 (right only)
+    #[serde(default)]
+    pub balance: i32,
+    /// Play the same mix of all channels on every channel
+    #[serde(default)]
+    pub mono: bool,
+}
+
+/// An extra built-in player on its own output device, shown in Music
+/// Assistant as a separate player.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AdditionalPlayer {
+    pub player_id: String,
+    pub player_name: String,
+    #[serde(default)]
+    pub audio_device_id: Option<String>,
+    #[serde(default)]
+    pub sync_delay_ms: i32,
+    // Additional players always use software volume; persisted like the
+    // main player's so it survives reconnects.
+    #[serde(default = "default_software_volume")]
+    pub software_volume: u8,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub channel_mix: ChannelMix,
+}
+
+/// TLS trust for one server whose certificate the system doesn't trust.
+/// Checked in order: `allow_insecure`, `pinned_sha256`, `ca_bundle_path`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ServerTls {
+    /// PEM file with CA certificates to trust in addition to the system's
+    #[serde(default)]
+    pub ca_bundle_path: Option<String>,
+    /// SHA-256 fingerprint of the server certificate (hex, colons optional)
+    /// to accept in place of validating its chain
+    #[serde(default)]
+    pub pinned_sha256: Option<String>,
+    /// Accept any certificate. Never on unless the user opts in for this
+    /// server.
+    #[serde(default)]
+    pub allow_insecure: bool,
+}
+
+/// Playback event a webhook can fire on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    TrackChanged,
+    Playing,
+    Paused,
+    /// The built-in player lost its connection to the server
+    ConnectionLost,
+}
+
+/// A URL sent a JSON request on playback events (see `webhooks`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// JSON body with `{{variable}}` placeholders; all variables if unset
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// A time the built-in player starts playing (see `alarms`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Alarm {
+    pub id: String,
+    #[serde(default = "default_alarm_enabled")]
+    pub enabled: bool,
+    /// Local time as `HH:MM`
+    pub time: String,
+    /// Days of the week it repeats on, 0 being Monday; once if empty
+    #[serde(default)]
+    pub days: Vec<u8>,
+    /// What to play, e.g. `library://playlist/12`; resumes the queue if unset
+    #[serde(default)]
+    pub media_uri: Option<String>,
+    /// Player volume to start at; left as it is if unset
+    #[serde(default)]
+    pub volume: Option<u8>,
+    /// Minutes to fade in over; 0 to start at full level
+    #[serde(default)]
+    pub ramp_minutes: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
+    // Layout version of the file, see `SCHEMA_VERSION`
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
     pub discord_rpc_enabled: bool,
     pub start_minimized: bool,
     #[serde(default = "default_close_to_tray")]
     pub close_to_tray: bool,
+    // Hide to the tray instead of quitting when the window is closed mid-playback
+    #[serde(default = "default_keep_playing_on_close")]
+    pub keep_playing_on_close: bool,
     pub autostart: bool,
+    // Start hidden in the tray when launched at login
+    #[serde(default)]
+    pub minimize_at_login: bool,
     // Last connected server (HTTP URL for launcher to reconnect)
     #[serde(default)]
     pub last_server_url: Option<String>,
@@ -40,13 +341,101 @@ pub struct Settings {
     pub sendspin_player_name: String,
     #[serde(default)]
     pub sendspin_server_url: Option<String>,
+    // Protocol the built-in player speaks to the server
+    #[serde(default)]
+    pub player_backend: PlayerBackend,
+    // Snapserver host[:port] for the Snapcast backend; the MA server's host when unset
+    #[serde(default)]
+    pub snapcast_server: Option<String>,
+    // Slimproto server host[:port] for the Slimproto backend; the MA server's host when unset
+    #[serde(default)]
+    pub slimproto_server: Option<String>,
     #[serde(default)]
     pub audio_device_id: Option<String>,
     #[serde(default)]
     pub sync_delay_ms: i32,
+    // Sync delay last used on each output device (see `device_key`), so
+    // switching devices brings back the delay that suits the new one
+    #[serde(default)]
+    pub device_sync_delays: BTreeMap<String, i32>,
+    // Second output device playing the same stream as the main player
+    #[serde(default)]
+    pub mirror_device_id: Option<String>,
+    // How much later the mirror device plays than the main one (ms); negative
+    // delays the main device instead
+    #[serde(default)]
+    pub mirror_delay_ms: i32,
+    // Output devices to play on, in order of preference, when `audio_device_id`
+    // isn't connected at connect time
+    #[serde(default)]
+    pub fallback_device_ids: Vec<String>,
+    // Seconds of silence after which the Sendspin connection counts as dead
+    #[serde(default = "default_keepalive_timeout_secs")]
+    pub keepalive_timeout_secs: u32,
+    // Clock sync with the server. Milliseconds between time requests once
+    // synced; Sendspin's own every 5 s are only ever added to
+    #[serde(default = "default_clock_sync_interval_ms")]
+    pub clock_sync_interval_ms: u32,
+    // Time requests sent in quick succession after connecting, for a faster lock
+    #[serde(default = "default_clock_sync_burst")]
+    pub clock_sync_burst: u32,
+    // Recent time exchanges the clock offset is the median of (Snapcast; for
+    // Sendspin, the outlier check's); more smooths out jitter but follows
+    // clock changes more slowly
+    #[serde(default = "default_clock_sync_window")]
+    pub clock_sync_window: u32,
+    // Milliseconds a time exchange's round trip may exceed the quickest recent
+    // one's before it's ignored as held up (0 keeps every exchange)
+    #[serde(default)]
+    pub clock_sync_outlier_ms: u32,
+    // TLS trust per server (see `tls_key`) for self-signed or privately issued certificates
+    #[serde(default)]
+    pub server_tls: BTreeMap<String, ServerTls>,
     // Volume control mode
     #[serde(default)]
     pub volume_control_mode: VolumeControlMode,
+    // Resampler quality for streams the output device can't play natively
+    #[serde(default)]
+    pub resampler_quality: ResamplerQuality,
+    // Loudness normalization from the loudness Music Assistant measured
+    #[serde(default)]
+    pub replay_gain_mode: ReplayGainMode,
+    // Extra gain (dB) on top of loudness normalization
+    #[serde(default)]
+    pub replay_gain_preamp_db: i32,
+    // Gain (dB) above 100% volume when volume is applied in software
+    #[serde(default)]
+    pub software_boost_db: i32,
+    // Balance and mono downmix of the main player; additional players have their own
+    #[serde(default)]
+    pub channel_mix: ChannelMix,
+    // EQ and other processing per output device (see `device_key`)
+    #[serde(default)]
+    pub output_profiles: BTreeMap<String, OutputProfile>,
+    // Open the output device exclusively (bit-perfect) when the platform allows it
+    #[serde(default)]
+    pub exclusive_mode: bool,
+    // Switch the output device to each stream's sample rate instead of
+    // letting the OS resample
+    #[serde(default = "default_device_rate_switching")]
+    pub device_rate_switching: bool,
+    // Fold surround streams down to stereo instead of playing them natively
+    #[serde(default)]
+    pub downmix_to_stereo: bool,
+    // Play from a separate process, so a crash in the audio driver doesn't
+    // take the app down with it
+    #[serde(default)]
+    pub isolated_playback: bool,
+    // Seconds without audio after which the output device is closed until the
+    // next stream; 0 keeps it open
+    #[serde(default = "default_idle_release_secs")]
+    pub idle_release_secs: u32,
+    // While playing, keep the display on as well as preventing system sleep
+    #[serde(default)]
+    pub keep_display_awake: bool,
+    // Pause playback when the session locks and resume it on unlock
+    #[serde(default)]
+    pub pause_on_lock: bool,
     // Persisted software volume (0-100). Used to restore volume across
     // reconnects, which happen on every track change. Only written in
     // software volume mode; hardware volume uses the OS as source of truth.
@@ -56,28 +445,143 @@ pub struct Settings {
     // since mute is lost on every reconnect (new connection per track).
     #[serde(default)]
     pub muted: bool,
+    // Volume change per press of the volume shortcuts or scroll over the tray icon (%)
+    #[serde(default = "default_volume_step")]
+    pub volume_step: u8,
+    // Highest volume (%) the built-in players can be set to, by the app,
+    // the server or the OS mixer alike
+    #[serde(default = "default_max_volume")]
+    pub max_volume: u8,
+    // Extra built-in players, each on its own output device
+    #[serde(default)]
+    pub additional_players: Vec<AdditionalPlayer>,
     // Whether to show the menubar/system tray icon
     #[serde(default = "default_show_tray_icon")]
     pub show_tray_icon: bool,
     // Whether to show now-playing text next to the menubar/system tray icon
     #[serde(default)]
     pub show_tray_now_playing: bool,
+    // Briefly show volume changes and new tracks on screen (see `osd`)
+    #[serde(default)]
+    pub osd_enabled: bool,
+    #[serde(default)]
+    pub osd_position: OsdPosition,
+    #[serde(default)]
+    pub osd_theme: OsdTheme,
     // Whether verbose debug logging is enabled.
     #[serde(default)]
     pub debug_logging: bool,
     // Whether very verbose trace logging is enabled. Only effective when debug logging is enabled.
     #[serde(default)]
     pub trace_logging: bool,
+    // Per-module log level overrides (module path -> level name)
+    #[serde(default)]
+    pub log_levels: BTreeMap<String, String>,
+    // Global shortcuts (action name -> accelerator)
+    #[serde(default)]
+    pub hotkeys: BTreeMap<String, String>,
+    // Local HTTP/WebSocket control API for scripts and tools (see `control_api`)
+    #[serde(default)]
+    pub control_api_enabled: bool,
+    #[serde(default = "default_control_api_port")]
+    pub control_api_port: u16,
+    // Token control API clients must send; generated when the API is first enabled
+    #[serde(default)]
+    pub control_api_token: Option<String>,
+    // URLs notified of track changes, play/pause and connection loss
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    // File kept up to date with the current track, for streaming overlays
+    #[serde(default)]
+    pub now_playing_file_path: Option<String>,
+    #[serde(default)]
+    pub now_playing_file_format: NowPlayingFileFormat,
+    // Folder stream recordings go to; the music folder's "Music Assistant Recordings" if unset
+    #[serde(default)]
+    pub recording_dir: Option<String>,
+    // Recording stops once the recordings in the folder take this much space
+    #[serde(default = "default_recording_quota_mb")]
+    pub recording_quota_mb: u32,
+    // Record plays in the local listening history
+    #[serde(default = "default_listening_history_enabled")]
+    pub listening_history_enabled: bool,
+    // Times the built-in player starts playing on its own
+    #[serde(default)]
+    pub alarms: Vec<Alarm>,
+    // Fields this version doesn't know, e.g. written by a newer version;
+    // kept so that saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+fn default_schema_version() -> u32 {
+    SCHEMA_VERSION
 }
 
 fn default_close_to_tray() -> bool {
     false
 }
 
+fn default_keep_playing_on_close() -> bool {
+    true
+}
+
 fn default_software_volume() -> u8 {
     100
 }
 
+fn default_volume_step() -> u8 {
+    5
+}
+
+fn default_max_volume() -> u8 {
+    100
+}
+
+fn default_eq_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+fn default_keepalive_timeout_secs() -> u32 {
+    DEFAULT_KEEPALIVE_TIMEOUT_SECS
+}
+
+fn default_clock_sync_interval_ms() -> u32 {
+    DEFAULT_CLOCK_SYNC_INTERVAL_MS
+}
+
+fn default_clock_sync_burst() -> u32 {
+    DEFAULT_CLOCK_SYNC_BURST
+}
+
+fn default_clock_sync_window() -> u32 {
+    DEFAULT_CLOCK_SYNC_WINDOW
+}
+
+fn default_control_api_port() -> u16 {
+    crate::control_api::DEFAULT_PORT
+}
+
+fn default_listening_history_enabled() -> bool {
+    true
+}
+
+fn default_alarm_enabled() -> bool {
+    true
+}
+
+fn default_device_rate_switching() -> bool {
+    true
+}
+
+fn default_idle_release_secs() -> u32 {
+    crate::sendspin::DEFAULT_IDLE_RELEASE_SECS
+}
+
+fn default_recording_quota_mb() -> u32 {
+    crate::sendspin::recording::DEFAULT_QUOTA_MB
+}
+
 fn default_show_tray_icon() -> bool {
     true
 }
@@ -103,49 +607,148 @@ fn default_player_name() -> String {
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             discord_rpc_enabled: true,
             start_minimized: false,
             close_to_tray: false,
+            keep_playing_on_close: default_keep_playing_on_close(),
             autostart: false,
+            minimize_at_login: false,
             last_server_url: None,
             last_server_name: None,
             sendspin_enabled: true, // Enabled by default - main purpose of companion app
             sendspin_player_id: None,
             sendspin_player_name: default_player_name(),
             sendspin_server_url: None,
+            player_backend: PlayerBackend::default(),
+            snapcast_server: None,
+            slimproto_server: None,
             audio_device_id: None,
             sync_delay_ms: 0,
+            device_sync_delays: BTreeMap::new(),
+            mirror_device_id: None,
+            mirror_delay_ms: 0,
+            fallback_device_ids: Vec::new(),
+            keepalive_timeout_secs: default_keepalive_timeout_secs(),
+            clock_sync_interval_ms: default_clock_sync_interval_ms(),
+            clock_sync_burst: default_clock_sync_burst(),
+            clock_sync_window: default_clock_sync_window(),
+            clock_sync_outlier_ms: 0,
+            server_tls: BTreeMap::new(),
             volume_control_mode: VolumeControlMode::default(),
+            resampler_quality: ResamplerQuality::default(),
+            replay_gain_mode: ReplayGainMode::default(),
+            replay_gain_preamp_db: 0,
+            software_boost_db: 0,
+            channel_mix: ChannelMix::default(),
+            output_profiles: BTreeMap::new(),
+            exclusive_mode: false,
+            device_rate_switching: true,
+            downmix_to_stereo: false,
+            isolated_playback: false,
+            idle_release_secs: default_idle_release_secs(),
+            keep_display_awake: false,
+            pause_on_lock: false,
             software_volume: default_software_volume(),
             muted: false,
+            volume_step: default_volume_step(),
+            max_volume: default_max_volume(),
+            additional_players: Vec::new(),
             show_tray_icon: true,
             show_tray_now_playing: false,
+            osd_enabled: false,
+            osd_position: OsdPosition::default(),
+            osd_theme: OsdTheme::default(),
             debug_logging: false,
             trace_logging: false,
+            log_levels: BTreeMap::new(),
+            hotkeys: BTreeMap::new(),
+            control_api_enabled: false,
+            control_api_port: default_control_api_port(),
+            control_api_token: None,
+            webhooks: Vec::new(),
+            now_playing_file_path: None,
+            now_playing_file_format: NowPlayingFileFormat::default(),
+            recording_dir: None,
+            recording_quota_mb: default_recording_quota_mb(),
+            listening_history_enabled: default_listening_history_enabled(),
+            alarms: Vec::new(),
+            extra: BTreeMap::new(),
         }
     }
 }
 
 static SETTINGS: RwLock<Settings> = RwLock::new(Settings {
+    schema_version: SCHEMA_VERSION,
     discord_rpc_enabled: true,
     start_minimized: false,
     close_to_tray: false,
+    keep_playing_on_close: true,
     autostart: false,
+    minimize_at_login: false,
     last_server_url: None,
     last_server_name: None,
     sendspin_enabled: true, // Enabled by default
     sendspin_player_id: None,
     sendspin_player_name: String::new(), // Will be replaced by load_settings
     sendspin_server_url: None,
+    player_backend: PlayerBackend::Sendspin,
+    snapcast_server: None,
+    slimproto_server: None,
     audio_device_id: None,
     sync_delay_ms: 0,
+    device_sync_delays: BTreeMap::new(),
+    mirror_device_id: None,
+    mirror_delay_ms: 0,
+    fallback_device_ids: Vec::new(),
+    keepalive_timeout_secs: DEFAULT_KEEPALIVE_TIMEOUT_SECS,
+    clock_sync_interval_ms: DEFAULT_CLOCK_SYNC_INTERVAL_MS,
+    clock_sync_burst: DEFAULT_CLOCK_SYNC_BURST,
+    clock_sync_window: DEFAULT_CLOCK_SYNC_WINDOW,
+    clock_sync_outlier_ms: 0,
+    server_tls: BTreeMap::new(),
     volume_control_mode: VolumeControlMode::Auto,
+    resampler_quality: ResamplerQuality::Balanced,
+    replay_gain_mode: ReplayGainMode::Off,
+    replay_gain_preamp_db: 0,
+    software_boost_db: 0,
+    channel_mix: ChannelMix {
+        balance: 0,
+        mono: false,
+    },
+    output_profiles: BTreeMap::new(),
+    exclusive_mode: false,
+    device_rate_switching: true,
+    downmix_to_stereo: false,
+    isolated_playback: false,
+    idle_release_secs: crate::sendspin::DEFAULT_IDLE_RELEASE_SECS,
+    keep_display_awake: false,
+    pause_on_lock: false,
     software_volume: 100,
     muted: false,
+    volume_step: 5,
+    max_volume: 100,
+    additional_players: Vec::new(),
     show_tray_icon: true,
     show_tray_now_playing: false,
+    osd_enabled: false,
+    osd_position: OsdPosition::BottomCenter,
+    osd_theme: OsdTheme::System,
     debug_logging: false,
     trace_logging: false,
+    log_levels: BTreeMap::new(),
+    hotkeys: BTreeMap::new(),
+    control_api_enabled: false,
+    control_api_port: crate::control_api::DEFAULT_PORT,
+    control_api_token: None,
+    webhooks: Vec::new(),
+    now_playing_file_path: None,
+    now_playing_file_format: NowPlayingFileFormat::Text,
+    recording_dir: None,
+    recording_quota_mb: crate::sendspin::recording::DEFAULT_QUOTA_MB,
+    listening_history_enabled: true,
+    alarms: Vec::new(),
+    extra: BTreeMap::new(),
 });
 
 fn get_settings_path() -> Option<PathBuf> {
@@ -153,18 +756,36 @@ fn get_settings_path() -> Option<PathBuf> {
 }
 
 pub fn load_settings() -> Settings {
-    let mut settings = if let Some(path) = get_settings_path() {
-        match fs::read_to_string(&path) {
-            Ok(content) => serde_json::from_str::<Settings>(&content).unwrap_or_default(),
-            Err(_) => Settings::default(),
-        }
-    } else {
-        Settings::default()
+    let path = get_settings_path();
+    let mut write_back = true;
+    let mut settings = match path.as_ref().map(fs::read_to_string) {
+        Some(Ok(content)) => match parse(&content, MIGRATIONS) {
+            Ok((settings, newer)) => {
+                // Keep what a newer version wrote until something changes
+                write_back = !newer;
+                settings
+            }
+            Err(e) => {
+                log::error!(
+                    "[Settings] Can't read the settings file, using defaults: {}",
+                    e
+                );
+                if let Some(path) = &path {
+                    back_up_unreadable(path);
+                }
+                Settings::default()
+            }
+        },
+        _ => Settings::default(),
     };
 
     if !settings.debug_logging {
         settings.trace_logging = false;
     }
+    // Whatever is loaded can be saved again
+    for problem in settings.normalize() {
+        log::warn!("[Settings] Fixed in the settings file: {}", problem);
+    }
 
     // Update in-memory settings
     if let Ok(mut s) = SETTINGS.write() {
@@ -172,12 +793,59 @@ pub fn load_settings() -> Settings {
     }
 
     // Write settings back to file to ensure all fields are persisted
-    let _ = save_settings(&settings);
+    if write_back {
+        if let Err(e) = save_settings(&settings) {
+            log::warn!("[Settings] Failed to save the settings: {}", e);
+        }
+    }
 
     settings
 }
 
+/// Parse a settings file, upgrading it from an older layout with
+/// `migrations`. Also returns whether the file is from a newer version of
+/// the app; its version and the fields this one doesn't know are then kept
+/// as they are, to be saved along with the rest.
+fn parse(content: &str, migrations: &[Migration]) -> Result<(Settings, bool), String> {
+    let mut raw: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+
+    let latest = migrations.len() as u32 + 1;
+    let version = raw
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map_or(1, |version| version as u32)
+        .max(1);
+    if version > latest {
+        log::warn!(
+            "[Settings] Settings file is from a newer version (schema {}, this version reads {})",
+            version,
+            latest
+        );
+    }
+    for (from, migrate) in migrations.iter().enumerate().skip(version as usize - 1) {
+        migrate(&mut raw).map_err(|e| format!("Migrating from schema {}: {}", from + 1, e))?;
+        log::info!("[Settings] Migrated settings to schema {}", from + 2);
+    }
+    if let Some(fields) = raw.as_object_mut() {
+        fields.insert("schema_version".to_string(), version.max(latest).into());
+    }
+
+    let settings = serde_json::from_value(raw).map_err(|e| e.to_string())?;
+    Ok((settings, version > latest))
+}
+
+/// Keep a copy of a settings file that couldn't be read before it is
+/// overwritten with defaults, so it can be fixed by hand
+fn back_up_unreadable(path: &std::path::Path) {
+    let backup = path.with_extension("json.bak");
+    match fs::copy(path, &backup) {
+        Ok(_) => log::warn!("[Settings] Kept a copy of it at {}", backup.display()),
+        Err(e) => log::warn!("[Settings] Failed to keep a copy of it: {}", e),
+    }
+}
+
 pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    settings.validate()?;
     let path =
         get_settings_path().ok_or_else(|| "Could not determine settings path".to_string())?;
 
@@ -198,15 +866,148 @@ pub fn save_settings(settings: &Settings) -> Result<(), String> {
     Ok(())
 }
 
+impl Settings {
+    /// Check the settings before they are saved. The error describes the
+    /// first problem, for the frontend to show.
+    pub fn validate(&self) -> Result<(), String> {
+        for webhook in &self.webhooks {
+            crate::webhooks::validate(webhook)?;
+        }
+        for alarm in &self.alarms {
+            crate::alarms::validate(alarm)?;
+        }
+        if let Some(problem) = self.clone().normalize().into_iter().next() {
+            return Err(problem);
+        }
+        Ok(())
+    }
+
+    /// Bring the settings back into shape, e.g. after the file was edited
+    /// by hand: numbers go back into their ranges, and webhooks and alarms
+    /// that can't be used are dropped. Returns what was wrong.
+    fn normalize(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut clamp = |name: &str, value: i64, min: i64, max: i64| {
+            if (min..=max).contains(&value) {
+                return value;
+            }
+            problems.push(format!(
+                "{} must be between {} and {}, not {}",
+                name, min, max, value
+            ));
+            value.clamp(min, max)
+        };
+        self.sync_delay_ms = clamp("sync_delay_ms", self.sync_delay_ms.into(), 0, 5_000) as i32;
+        self.mirror_delay_ms = clamp(
+            "mirror_delay_ms",
+            self.mirror_delay_ms.into(),
+            -5_000,
+            5_000,
+        ) as i32;
+        self.keepalive_timeout_secs = clamp(
+            "keepalive_timeout_secs",
+            self.keepalive_timeout_secs.into(),
+            5,
+            300,
+        ) as u32;
+        self.clock_sync_interval_ms = clamp(
+            "clock_sync_interval_ms",
+            self.clock_sync_interval_ms.into(),
+            100,
+            10_000,
+        ) as u32;
+        self.clock_sync_burst =
+            clamp("clock_sync_burst", self.clock_sync_burst.into(), 0, 100) as u32;
+        self.clock_sync_window =
+            clamp("clock_sync_window", self.clock_sync_window.into(), 5, 500) as u32;
+        self.clock_sync_outlier_ms = clamp(
+            "clock_sync_outlier_ms",
+            self.clock_sync_outlier_ms.into(),
+            0,
+            1_000,
+        ) as u32;
+        self.software_volume = clamp("software_volume", self.software_volume.into(), 0, 100) as u8;
+        self.volume_step = clamp("volume_step", self.volume_step.into(), 1, 25) as u8;
+        self.max_volume = clamp("max_volume", self.max_volume.into(), 10, 100) as u8;
+        self.replay_gain_preamp_db = clamp(
+            "replay_gain_preamp_db",
+            self.replay_gain_preamp_db.into(),
+            -12,
+            12,
+        ) as i32;
+        self.software_boost_db =
+            clamp("software_boost_db", self.software_boost_db.into(), 0, 12) as i32;
+        self.control_api_port = clamp(
+            "control_api_port",
+            self.control_api_port.into(),
+            1024,
+            u16::MAX.into(),
+        ) as u16;
+        self.idle_release_secs = clamp(
+            "idle_release_secs",
+            self.idle_release_secs.into(),
+            0,
+            86_400,
+        ) as u32;
+        self.recording_quota_mb = clamp(
+            "recording_quota_mb",
+            self.recording_quota_mb.into(),
+            100,
+            1_000_000,
+        ) as u32;
+        for player in &mut self.additional_players {
+            player.sync_delay_ms =
+                clamp("sync_delay_ms", player.sync_delay_ms.into(), 0, 5_000) as i32;
+            player.software_volume =
+                clamp("software_volume", player.software_volume.into(), 0, 100) as u8;
+        }
+        for webhook in std::mem::take(&mut self.webhooks) {
+            let url = webhook.url.clone();
+            match clean_webhook(webhook) {
+                Ok(webhook) => self.webhooks.push(webhook),
+                Err(e) => problems.push(format!("dropped the webhook for {}: {}", url, e)),
+            }
+        }
+        for alarm in std::mem::take(&mut self.alarms) {
+            let time = alarm.time.clone();
+            match clean_alarm(alarm) {
+                Ok(alarm) => self.alarms.push(alarm),
+                Err(e) => problems.push(format!("dropped the alarm at {}: {}", time, e)),
+            }
+        }
+        problems
+    }
+}
+
 pub fn get_settings() -> Settings {
     SETTINGS
         .read()
         .map_or_else(|_| Settings::default(), |s| s.clone())
 }
 
+/// Read settings without copying all of them, e.g.
+/// `settings::read(|s| s.sync_delay_ms)`
+pub fn read<T>(get: impl FnOnce(&Settings) -> T) -> T {
+    match SETTINGS.read() {
+        Ok(settings) => get(&settings),
+        Err(_) => get(&Settings::default()),
+    }
+}
+
+/// Change settings and save them, e.g. `settings::update(|s| s.muted = true)`.
+/// Nothing is saved if the change leaves them invalid; the error says why.
+pub fn update<T>(change: impl FnOnce(&mut Settings) -> T) -> Result<T, String> {
+    let mut settings = get_settings();
+    let result = change(&mut settings);
+    save_settings(&settings)?;
+    Ok(result)
+}
+
 pub fn set_setting(app: tauri::AppHandle, key: &str, value: bool) -> Result<(), String> {
     let mut settings = get_settings();
     let mut should_refresh_tray_now_playing = false;
+    let mut should_restart_sendspin = false;
+    let mut should_refresh_sleep_inhibit = false;
 
     match key {
         "discord_rpc_enabled" => {
@@ -220,23 +1021,60 @@ pub fn set_setting(app: tauri::AppHandle, key: &str, value: bool) -> Result<(),
             crate::discord_rpc::refresh();
         }
         "start_minimized" => settings.start_minimized = value,
+        "minimize_at_login" => settings.minimize_at_login = value,
         "close_to_tray" => settings.close_to_tray = value,
+        "keep_playing_on_close" => settings.keep_playing_on_close = value,
+        // Picked up by the playback thread when it next opens the device
+        "exclusive_mode" => {
+            if value && !crate::sendspin::exclusive::SUPPORTED {
+                return Err("Exclusive mode is not available on this platform".to_string());
+            }
+            settings.exclusive_mode = value;
+        }
+        "device_rate_switching" => settings.device_rate_switching = value,
+        "downmix_to_stereo" => {
+            // Changes the advertised formats, so renegotiate
+            settings.downmix_to_stereo = value;
+            should_restart_sendspin = true;
+        }
+        // Picked up by the playback thread when it next opens the device
+        "isolated_playback" => settings.isolated_playback = value,
+        "keep_display_awake" => {
+            settings.keep_display_awake = value;
+            should_refresh_sleep_inhibit = true;
+        }
+        "pause_on_lock" => settings.pause_on_lock = value,
+        "listening_history_enabled" => settings.listening_history_enabled = value,
+        "control_api_enabled" => {
+            if value {
+                if settings.control_api_token.is_none() {
+                    settings.control_api_token = Some(crate::control_api::new_token());
+                }
+                // Surface a port that's taken instead of saving an API that
+                // isn't listening
+                crate::control_api::start(app.state::<SendspinManager>().inner().clone())?;
+            } else {
+                crate::control_api::stop();
+            }
+            settings.control_api_enabled = value;
+        }
         "autostart" => {
             // Update the platform autostart registration before persisting the
             // setting, so a portal/plugin failure is surfaced to the UI instead
             // of saving a state the OS did not actually apply.
-            set_autostart(value, app)?;
+            crate::autostart::set_enabled(&app, value)?;
             settings.autostart = value;
         }
         "sendspin_enabled" => {
             settings.sendspin_enabled = value;
-            crate::sendspin::set_enabled(value);
+            let sendspin = app.state::<SendspinManager>().inner().clone();
+            sendspin.set_enabled(value);
             if value {
                 log::info!("[Sendspin] Native player enabled");
             } else {
                 log::info!("[Sendspin] Native player disabled; stopping local client");
-                tauri::async_runtime::spawn(async {
-                    crate::sendspin::stop().await;
+                tauri::async_runtime::spawn(async move {
+                    sendspin.stop().await;
                 });
             }
         }
@@ -248,6 +1086,12 @@ pub fn set_setting(app: tauri::AppHandle, key: &str, value: bool) -> Result<(),
             settings.show_tray_now_playing = value;
             should_refresh_tray_now_playing = true;
         }
+        "osd_enabled" => {
+            settings.osd_enabled = value;
+            if !value {
+                crate::osd::hide();
+            }
+        }
         "debug_logging" => {
             settings.debug_logging = value;
             if !value {
@@ -288,11 +1132,253 @@ pub fn set_setting(app: tauri::AppHandle, key: &str, value: bool) -> Result<(),
         crate::refresh_tray_now_playing();
     }
 
+    if should_refresh_sleep_inhibit {
+        crate::power::inhibit::refresh();
+    }
+
+    if should_restart_sendspin && settings.sendspin_enabled {
+        let sendspin = app.state::<SendspinManager>().inner().clone();
+        tauri::async_runtime::spawn(async move {
+            sendspin.restart().await;
+        });
+    }
+
     Ok(())
 }
 
+/// Key of a server in `server_tls`: the lowercased `host:port` of its URL,
+/// so the HTTP and WebSocket URLs of one server share an entry.
+fn tls_key(server_url: &str) -> String {
+    let without_scheme = server_url
+        .split_once("://")
+        .map_or(server_url, |(_, rest)| rest);
+    without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// TLS settings for the server at `server_url`, if any are set
+pub fn server_tls(server_url: &str) -> Option<ServerTls> {
+    get_settings()
+        .server_tls
+        .get(&tls_key(server_url))
+        .filter(|tls| **tls != ServerTls::default())
+        .cloned()
+}
+
+/// Set (or with `None`, clear) the TLS settings for the server at
+/// `server_url`. Takes effect on the next connection.
+pub fn set_server_tls(server_url: &str, tls: Option<ServerTls>) -> Result<(), String> {
+    let key = tls_key(server_url);
+    if key.is_empty() {
+        return Err(format!("Invalid server URL: {}", server_url));
+    }
+    let mut settings = get_settings();
+    match tls {
+        Some(tls) => {
+            if tls.allow_insecure {
+                log::warn!(
+                    "[Settings] Certificate checks disabled for {} at the user's request",
+                    key
+                );
+            }
+            settings.server_tls.insert(key, tls);
+        }
+        None => {
+            settings.server_tls.remove(&key);
+        }
+    }
+    save_settings(&settings)
+}
+
+/// Key of an output device in `device_sync_delays` and `output_profiles`; the
+/// system default output is stored under the empty string.
+fn device_key(audio_device_id: Option<&str>) -> String {
+    audio_device_id.unwrap_or_default().to_string()
+}
+
+/// Remember `sync_delay_ms` as the delay for `audio_device_id`
+fn remember_device_delay(
+    settings: &mut Settings,
+    audio_device_id: Option<&str>,
+    sync_delay_ms: i32,
+) {
+    settings
+        .device_sync_delays
+        .insert(device_key(audio_device_id), sync_delay_ms);
+}
+
+/// The delay last used on `audio_device_id`, if any
+fn device_delay(settings: &Settings, audio_device_id: Option<&str>) -> Option<i32> {
+    settings
+        .device_sync_delays
+        .get(&device_key(audio_device_id))
+        .copied()
+}
+
+/// Give a player's device a default sync delay if none is stored for it
+/// yet; the main player unless `additional_player` names another. A delay
+/// the player already uses is kept and stored instead of being replaced.
+///
+/// Returns the delay if it was applied.
+pub fn seed_device_delay(additional_player: Option<&str>, default_delay_ms: i32) -> Option<i32> {
+    let mut settings = get_settings();
+    let (audio_device_id, current) = match additional_player {
+        Some(id) => {
+            let p = settings
+                .additional_players
+                .iter()
+                .find(|p| p.player_id == id)?;
+            (p.audio_device_id.clone(), p.sync_delay_ms)
+        }
+        None => (settings.audio_device_id.clone(), settings.sync_delay_ms),
+    };
+    if device_delay(&settings, audio_device_id.as_deref()).is_some() {
+        return None;
+    }
+
+    let delay = if current == 0 {
+        default_delay_ms
+    } else {
+        current
+    };
+    remember_device_delay(&mut settings, audio_device_id.as_deref(), delay);
+    match additional_player {
+        Some(id) => {
+            if let Some(p) = settings
+                .additional_players
+                .iter_mut()
+                .find(|p| p.player_id == id)
+            {
+                p.sync_delay_ms = delay;
+            }
+        }
+        None => settings.sync_delay_ms = delay,
+    }
+    if let Err(e) = save_settings(&settings) {
+        log::warn!("[Settings] Failed to save default sync delay: {e}");
+    }
+    (delay != current).then_some(delay)
+}
+
+/// Most devices the fallback list may hold
+const MAX_FALLBACK_DEVICES: usize = 8;
+
+/// Set the output devices the main player falls back to, in order, when its
+/// device isn't connected. Blank and repeated entries are dropped.
+pub fn set_fallback_devices(device_ids: Vec<String>) -> Result<(), String> {
+    let mut fallbacks: Vec<String> = Vec::new();
+    for id in device_ids {
+        let id = id.trim();
+        if !id.is_empty() && !fallbacks.iter().any(|f| f == id) {
+            fallbacks.push(id.to_string());
+        }
+    }
+    if fallbacks.len() > MAX_FALLBACK_DEVICES {
+        return Err(format!(
+            "At most {} fallback devices can be set",
+            MAX_FALLBACK_DEVICES
+        ));
+    }
+    let mut settings = get_settings();
+    settings.fallback_device_ids = fallbacks;
+    save_settings(&settings)
+}
+
+/// Most bands an EQ may have
+const MAX_EQ_BANDS: usize = 16;
+
+/// Highest device output stereo can be routed to
+const MAX_OUTPUT_CHANNELS: u16 = 32;
+
+/// The output profile of `audio_device_id`; devices without one get the
+/// default, which leaves the signal alone.
+pub fn output_profile(settings: &Settings, audio_device_id: Option<&str>) -> OutputProfile {
+    settings
+        .output_profiles
+        .get(&device_key(audio_device_id))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Set the output profile of `audio_device_id`, or with `None` go back to
+/// the default. Out-of-range values are clamped. The caller reloads the
+/// running players' DSP.
+pub fn set_output_profile(
+    audio_device_id: Option<&str>,
+    profile: Option<OutputProfile>,
+) -> Result<(), String> {
+    let mut settings = get_settings();
+    let key = device_key(audio_device_id);
+    match profile.filter(|p| *p != OutputProfile::default()) {
+        Some(mut profile) => {
+            if profile.eq_bands.len() > MAX_EQ_BANDS {
+                return Err(format!("An EQ can have at most {} bands", MAX_EQ_BANDS));
+            }
+            if profile
+                .eq_bands
+                .iter()
+                .any(|b| !(b.frequency_hz.is_finite() && b.gain_db.is_finite() && b.q.is_finite()))
+            {
+                return Err("Invalid EQ band".to_string());
+            }
+            if let Some(outputs) = &mut profile.stereo_channels {
+                for output in outputs.iter_mut() {
+                    *output = (*output).clamp(1, MAX_OUTPUT_CHANNELS);
+                }
+                if outputs[0] == outputs[1] {
+                    return Err("Left and right must play on different outputs".to_string());
+                }
+            }
+            profile.eq_preamp_db = profile.eq_preamp_db.clamp(-24.0, 12.0);
+            for band in &mut profile.eq_bands {
+                band.frequency_hz = band.frequency_hz.clamp(20.0, 20_000.0);
+                band.gain_db = band.gain_db.clamp(-24.0, 24.0);
+                band.q = band.q.clamp(0.1, 10.0);
+            }
+            settings.output_profiles.insert(key, profile);
+        }
+        None => {
+            settings.output_profiles.remove(&key);
+        }
+    }
+    save_settings(&settings)
+}
+
+/// Balance and mono downmix of the main player, or of `additional_player`
+pub fn channel_mix(settings: &Settings, additional_player: Option<&str>) -> ChannelMix {
+    match additional_player {
+        Some(id) => settings
+            .additional_players
+            .iter()
+            .find(|p| p.player_id == id)
+            .map(|p| p.channel_mix)
+            .unwrap_or_default(),
+        None => settings.channel_mix,
+    }
+}
+
+/// Set the balance and mono downmix of the main player, or of
+/// `additional_player`. The caller reloads the running players' DSP.
+pub fn set_channel_mix(additional_player: Option<&str>, mut mix: ChannelMix) -> Result<(), String> {
+    mix.balance = mix.balance.clamp(-100, 100);
+    match additional_player {
+        Some(id) => update_additional_player(id, |p| p.channel_mix = mix),
+        None => {
+            let mut settings = get_settings();
+            settings.channel_mix = mix;
+            save_settings(&settings)
+        }
+    }
+}
+
 /// Set a string setting value
-pub fn set_string_setting(key: &str, value: Option<String>) -> Result<(), String> {
+///
+/// Returns whether the running Sendspin client has to reconnect for the new
+/// value to take effect; the caller owns the client and does the restart.
+pub fn set_string_setting(key: &str, value: Option<String>) -> Result<bool, String> {
     let mut settings = get_settings();
     let mut should_restart_sendspin = false;
 
@@ -300,15 +1386,45 @@ pub fn set_string_setting(key: &str, value: Option<String>) -> Result<(), String
         "last_server_url" => settings.last_server_url = value,
         "last_server_name" => settings.last_server_name = value,
         "sendspin_player_id" => settings.sendspin_player_id = value,
+        // Running players are renamed without reconnecting (see `SendspinManager::rename`)
         "sendspin_player_name" => {
-            settings.sendspin_player_name = value.unwrap_or_else(default_player_name);
-            should_restart_sendspin = true;
+            settings.sendspin_player_name = value
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(default_player_name);
         }
         "sendspin_server_url" => settings.sendspin_server_url = value,
+        "player_backend" => {
+            if let Some(backend) = value {
+                settings.player_backend = match backend.as_str() {
+                    "sendspin" => PlayerBackend::Sendspin,
+                    "snapcast" => PlayerBackend::Snapcast,
+                    "slimproto" => PlayerBackend::Slimproto,
+                    "dlna" => PlayerBackend::Dlna,
+                    _ => return Err(format!("Invalid player backend: {}", backend)),
+                };
+                should_restart_sendspin = true;
+            }
+        }
+        "snapcast_server" => {
+            settings.snapcast_server = value.filter(|server| !server.trim().is_empty());
+            should_restart_sendspin = settings.player_backend == PlayerBackend::Snapcast;
+        }
+        "slimproto_server" => {
+            settings.slimproto_server = value.filter(|server| !server.trim().is_empty());
+            should_restart_sendspin = settings.player_backend == PlayerBackend::Slimproto;
+        }
         "audio_device_id" => {
+            if let Some(delay) = device_delay(&settings, value.as_deref()) {
+                settings.sync_delay_ms = delay;
+            }
             settings.audio_device_id = value;
             should_restart_sendspin = true;
         }
+        "mirror_device_id" => {
+            settings.mirror_device_id = value;
+            should_restart_sendspin = true;
+        }
         "volume_control_mode" => {
             if let Some(mode_str) = value {
                 settings.volume_control_mode = match mode_str.as_str() {
@@ -320,95 +1436,390 @@ pub fn set_string_setting(key: &str, value: Option<String>) -> Result<(), String
                 };
             }
         }
+        "resampler_quality" => {
+            if let Some(quality) = value {
+                settings.resampler_quality = match quality.as_str() {
+                    "fast" => ResamplerQuality::Fast,
+                    "balanced" => ResamplerQuality::Balanced,
+                    "high" => ResamplerQuality::High,
+                    _ => return Err(format!("Invalid resampler quality: {}", quality)),
+                };
+            }
+        }
+        "replay_gain_mode" => {
+            if let Some(mode) = value {
+                settings.replay_gain_mode = match mode.as_str() {
+                    "off" => ReplayGainMode::Off,
+                    "track" => ReplayGainMode::Track,
+                    "album" => ReplayGainMode::Album,
+                    _ => return Err(format!("Invalid ReplayGain mode: {}", mode)),
+                };
+            }
+        }
+        "now_playing_file_path" => {
+            settings.now_playing_file_path = value.filter(|path| !path.trim().is_empty());
+        }
+        "now_playing_file_format" => {
+            if let Some(format) = value {
+                settings.now_playing_file_format = match format.as_str() {
+                    "text" => NowPlayingFileFormat::Text,
+                    "json" => NowPlayingFileFormat::Json,
+                    _ => return Err(format!("Invalid now-playing file format: {}", format)),
+                };
+            }
+        }
+        "recording_dir" => {
+            settings.recording_dir = value.filter(|dir| !dir.trim().is_empty());
+        }
+        "osd_position" => {
+            if let Some(position) = value {
+                settings.osd_position = match position.as_str() {
+                    "top_left" => OsdPosition::TopLeft,
+                    "top_center" => OsdPosition::TopCenter,
+                    "top_right" => OsdPosition::TopRight,
+                    "center" => OsdPosition::Center,
+                    "bottom_left" => OsdPosition::BottomLeft,
+                    "bottom_center" => OsdPosition::BottomCenter,
+                    "bottom_right" => OsdPosition::BottomRight,
+                    _ => return Err(format!("Invalid OSD position: {}", position)),
+                };
+            }
+        }
+        "osd_theme" => {
+            if let Some(theme) = value {
+                settings.osd_theme = match theme.as_str() {
+                    "system" => OsdTheme::System,
+                    "dark" => OsdTheme::Dark,
+                    "light" => OsdTheme::Light,
+                    _ => return Err(format!("Invalid OSD theme: {}", theme)),
+                };
+            }
+        }
         _ => return Err(format!("Unknown string setting: {}", key)),
     }
 
     save_settings(&settings)?;
-
-    if should_restart_sendspin && settings.sendspin_enabled {
-        tauri::async_runtime::spawn(async {
-            crate::sendspin::restart().await;
-        });
+    if key.starts_with("now_playing_file_") {
+        crate::now_playing_file::refresh();
     }
 
-    Ok(())
+    Ok(should_restart_sendspin && settings.sendspin_enabled)
 }
 
 /// Set a numeric setting value
 pub fn set_int_setting(key: &str, value: i32) -> Result<(), String> {
     let mut settings = get_settings();
+    let mut should_restart_control_api = false;
 
     match key {
         "sync_delay_ms" => {
             settings.sync_delay_ms = value.clamp(0, 5_000);
+            let device = settings.audio_device_id.clone();
+            remember_device_delay(&mut settings, device.as_deref(), settings.sync_delay_ms);
+        }
+        "mirror_delay_ms" => settings.mirror_delay_ms = value.clamp(-5_000, 5_000),
+        "keepalive_timeout_secs" => {
+            settings.keepalive_timeout_secs = value.clamp(5, 300) as u32;
+        }
+        // Picked up by the Snapcast backend when it next connects
+        "clock_sync_interval_ms" => {
+            settings.clock_sync_interval_ms = value.clamp(100, 10_000) as u32;
         }
+        "clock_sync_burst" => settings.clock_sync_burst = value.clamp(0, 100) as u32,
+        "clock_sync_window" => settings.clock_sync_window = value.clamp(5, 500) as u32,
+        "clock_sync_outlier_ms" => settings.clock_sync_outlier_ms = value.clamp(0, 1_000) as u32,
+        "replay_gain_preamp_db" => settings.replay_gain_preamp_db = value.clamp(-12, 12),
+        "software_boost_db" => settings.software_boost_db = value.clamp(0, 12),
+        "volume_step" => settings.volume_step = value.clamp(1, 25) as u8,
+        // Applied to the running players by the caller
+        "max_volume" => settings.max_volume = value.clamp(10, 100) as u8,
+        "control_api_port" => {
+            settings.control_api_port = value.clamp(1024, i32::from(u16::MAX)) as u16;
+            should_restart_control_api = true;
+        }
+        // Picked up by the playback thread when it next opens the device
+        "idle_release_secs" => settings.idle_release_secs = value.clamp(0, 86_400) as u32,
+        "recording_quota_mb" => settings.recording_quota_mb = value.clamp(100, 1_000_000) as u32,
         _ => return Err(format!("Unknown int setting: {}", key)),
     }
 
     save_settings(&settings)?;
 
-    if settings.sendspin_enabled {
-        crate::sendspin::set_static_delay(value)?;
+    if should_restart_control_api {
+        crate::control_api::restart()?;
     }
 
     Ok(())
 }
 
-fn set_autostart(enabled: bool, app: tauri::AppHandle) -> Result<(), String> {
-    #[cfg(target_os = "linux")]
-    if std::env::var_os("FLATPAK_ID").is_some() {
-        return set_flatpak_autostart(enabled).map_err(|error| {
-            let message = format!("Failed to update Flatpak autostart: {error}");
-            log::warn!("[Settings] {message}");
-            message
-        });
+/// Set the log level override for one module, or clear it with `None`.
+/// Takes effect immediately.
+pub fn set_log_level(module: &str, level: Option<String>) -> Result<(), String> {
+    let module = module.trim();
+    if module.is_empty() {
+        return Err("Module must not be empty".to_string());
     }
 
-    let autostart_manager = app.autolaunch();
+    let mut settings = get_settings();
+    match level {
+        Some(level) => {
+            let level = crate::logging::parse_level(&level)?;
+            settings
+                .log_levels
+                .insert(module.to_string(), level.as_str().to_lowercase());
+        }
+        None => {
+            settings.log_levels.remove(module);
+        }
+    }
+    crate::logging::set_module_levels(&settings.log_levels)?;
+    log::info!("[App] Log levels: {:?}", settings.log_levels);
 
-    let result = if enabled {
-        autostart_manager.enable()
-    } else {
-        autostart_manager.disable()
-    };
+    save_settings(&settings)
+}
 
-    result.map_err(|error| {
-        let message = format!("Failed to update autostart: {error}");
-        log::warn!("[Settings] {message}");
-        message
-    })
-}
-
-#[cfg(all(desktop, target_os = "linux"))]
-fn set_flatpak_autostart(enabled: bool) -> std::io::Result<()> {
-    const DESKTOP_FILE_NAME: &str = "io.music_assistant.Companion.desktop";
-    const AUTOSTART_DESKTOP_ENTRY: &str = include_str!("../templates/flatpak-autostart.desktop");
-
-    // In a Flatpak sandbox, XDG_CONFIG_HOME points at the app-private config
-    // dir. The manifest grants `xdg-config/autostart:create`, so write through
-    // $HOME/.config/autostart to reach the host XDG autostart directory.
-    let autostart_dir = dirs::home_dir()
-        .ok_or_else(|| std::io::Error::other("Could not determine home directory"))?
-        .join(".config")
-        .join("autostart");
-    let autostart_file = autostart_dir.join(DESKTOP_FILE_NAME);
-
-    if !enabled {
-        match std::fs::remove_file(&autostart_file) {
-            Ok(()) => {}
-            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
-            Err(error) => return Err(error),
+/// Bind the global shortcut `accelerator` to `action`, or clear the binding
+/// with `None`. Takes effect immediately.
+pub fn set_hotkey(
+    app: &tauri::AppHandle,
+    action: &str,
+    accelerator: Option<String>,
+) -> Result<(), String> {
+    let mut settings = get_settings();
+    match accelerator {
+        Some(accelerator) => {
+            crate::hotkeys::validate(action, &accelerator, &settings.hotkeys)?;
+            settings.hotkeys.insert(action.to_string(), accelerator);
         }
+        None => {
+            settings.hotkeys.remove(action);
+        }
+    }
+    if let Err(e) = crate::hotkeys::apply(app, &settings.hotkeys) {
+        // Put back the shortcuts that worked before
+        let _ = crate::hotkeys::apply(app, &get_settings().hotkeys);
+        return Err(e);
+    }
+    log::info!("[App] Global shortcuts: {:?}", settings.hotkeys);
+
+    save_settings(&settings)
+}
+
+/// Replace the control API token, disconnecting clients of the old one.
+/// Returns the new token.
+pub fn regenerate_control_api_token() -> Result<String, String> {
+    let token = crate::control_api::new_token();
+    update(|s| s.control_api_token = Some(token.clone()))?;
+    crate::control_api::restart()?;
+    Ok(token)
+}
+
+/// Add a webhook with a newly generated ID
+pub fn add_webhook(
+    url: String,
+    events: Vec<WebhookEvent>,
+    template: Option<String>,
+) -> Result<Webhook, String> {
+    let webhook = clean_webhook(Webhook {
+        id: uuid::Uuid::new_v4().to_string(),
+        url,
+        events,
+        template,
+    })?;
+
+    let mut settings = get_settings();
+    settings.webhooks.push(webhook.clone());
+    save_settings(&settings)?;
+
+    Ok(webhook)
+}
+
+/// Replace the webhook with the ID of `webhook`
+pub fn update_webhook(webhook: Webhook) -> Result<(), String> {
+    let webhook = clean_webhook(webhook)?;
+    let mut settings = get_settings();
+    let existing = settings
+        .webhooks
+        .iter_mut()
+        .find(|w| w.id == webhook.id)
+        .ok_or_else(|| format!("Unknown webhook: {}", webhook.id))?;
+    if *existing == webhook {
         return Ok(());
     }
+    *existing = webhook;
+
+    save_settings(&settings)
+}
 
-    std::fs::create_dir_all(&autostart_dir)?;
+/// Remove a webhook
+pub fn remove_webhook(id: &str) -> Result<(), String> {
+    let mut settings = get_settings();
+    let count = settings.webhooks.len();
+    settings.webhooks.retain(|w| w.id != id);
+    if settings.webhooks.len() == count {
+        return Err(format!("Unknown webhook: {}", id));
+    }
 
-    let temp_file = autostart_file.with_extension("desktop.tmp");
-    std::fs::write(&temp_file, AUTOSTART_DESKTOP_ENTRY)?;
-    std::fs::rename(temp_file, autostart_file)?;
+    save_settings(&settings)
+}
 
-    Ok(())
+/// Trim a webhook's URL and template and check them before saving
+fn clean_webhook(mut webhook: Webhook) -> Result<Webhook, String> {
+    webhook.url = webhook.url.trim().to_string();
+    webhook.template = webhook
+        .template
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    crate::webhooks::validate(&webhook)?;
+    Ok(webhook)
+}
+
+/// Add an alarm with a newly generated ID
+pub fn add_alarm(
+    time: String,
+    days: Vec<u8>,
+    media_uri: Option<String>,
+    volume: Option<u8>,
+    ramp_minutes: u32,
+) -> Result<Alarm, String> {
+    let alarm = clean_alarm(Alarm {
+        id: uuid::Uuid::new_v4().to_string(),
+        enabled: true,
+        time,
+        days,
+        media_uri,
+        volume,
+        ramp_minutes,
+    })?;
+
+    let mut settings = get_settings();
+    settings.alarms.push(alarm.clone());
+    save_settings(&settings)?;
+
+    Ok(alarm)
+}
+
+/// Replace the alarm with the ID of `alarm`
+pub fn update_alarm(alarm: Alarm) -> Result<(), String> {
+    let alarm = clean_alarm(alarm)?;
+    let mut settings = get_settings();
+    let existing = settings
+        .alarms
+        .iter_mut()
+        .find(|a| a.id == alarm.id)
+        .ok_or_else(|| format!("Unknown alarm: {}", alarm.id))?;
+    if *existing == alarm {
+        return Ok(());
+    }
+    *existing = alarm;
+
+    save_settings(&settings)
+}
+
+/// Remove an alarm
+pub fn remove_alarm(id: &str) -> Result<(), String> {
+    let mut settings = get_settings();
+    let count = settings.alarms.len();
+    settings.alarms.retain(|a| a.id != id);
+    if settings.alarms.len() == count {
+        return Err(format!("Unknown alarm: {}", id));
+    }
+
+    save_settings(&settings)
+}
+
+/// Trim and sort an alarm's fields and check them before saving
+fn clean_alarm(mut alarm: Alarm) -> Result<Alarm, String> {
+    alarm.time = alarm.time.trim().to_string();
+    alarm.days.sort_unstable();
+    alarm.days.dedup();
+    alarm.media_uri = alarm
+        .media_uri
+        .map(|uri| uri.trim().to_string())
+        .filter(|uri| !uri.is_empty());
+    crate::alarms::validate(&alarm)?;
+    Ok(alarm)
+}
+
+/// Add an additional built-in player with a newly generated player ID
+pub fn add_additional_player(
+    player_name: String,
+    audio_device_id: Option<String>,
+) -> Result<AdditionalPlayer, String> {
+    let player_name = player_name.trim().to_string();
+    if player_name.is_empty() {
+        return Err("Player name must not be empty".to_string());
+    }
+
+    let player = AdditionalPlayer {
+        player_id: format!("ma_companion_{}", uuid::Uuid::new_v4()),
+        player_name,
+        audio_device_id,
+        sync_delay_ms: 0,
+        software_volume: default_software_volume(),
+        muted: false,
+        channel_mix: ChannelMix::default(),
+    };
+
+    let mut settings = get_settings();
+    settings.additional_players.push(player.clone());
+    save_settings(&settings)?;
+
+    Ok(player)
+}
+
+/// Remove an additional built-in player
+pub fn remove_additional_player(player_id: &str) -> Result<(), String> {
+    let mut settings = get_settings();
+    let count = settings.additional_players.len();
+    settings
+        .additional_players
+        .retain(|p| p.player_id != player_id);
+    if settings.additional_players.len() == count {
+        return Err(format!("Unknown player: {}", player_id));
+    }
+
+    save_settings(&settings)
+}
+
+/// Apply `update` to an additional player's settings, saving only if
+/// something changed.
+///
+/// Moving the player to another device without also setting its delay
+/// switches to the delay last used on that device.
+pub fn update_additional_player(
+    player_id: &str,
+    update: impl FnOnce(&mut AdditionalPlayer),
+) -> Result<(), String> {
+    let mut settings = get_settings();
+    let index = settings
+        .additional_players
+        .iter()
+        .position(|p| p.player_id == player_id)
+        .ok_or_else(|| format!("Unknown player: {}", player_id))?;
+
+    let before = settings.additional_players[index].clone();
+    let mut player = before.clone();
+    update(&mut player);
+    player.sync_delay_ms = player.sync_delay_ms.clamp(0, 5_000);
+    player.software_volume = player.software_volume.min(100);
+    if player.sync_delay_ms != before.sync_delay_ms {
+        remember_device_delay(
+            &mut settings,
+            player.audio_device_id.as_deref(),
+            player.sync_delay_ms,
+        );
+    } else if player.audio_device_id != before.audio_device_id {
+        if let Some(delay) = device_delay(&settings, player.audio_device_id.as_deref()) {
+            player.sync_delay_ms = delay;
+        }
+    }
+    if player == before {
+        return Ok(());
+    }
+
+    settings.additional_players[index] = player;
+    save_settings(&settings)
 }
 
 #[cfg(test)]
@@ -420,6 +1831,14 @@ mod tests {
         assert_eq!(VolumeControlMode::default(), VolumeControlMode::Auto);
     }
 
+    #[test]
+    fn tls_key_matches_http_and_websocket_urls() {
+        assert_eq!(tls_key("https://MA.example:8095/"), "ma.example:8095");
+        assert_eq!(tls_key("wss://ma.example:8095/sendspin"), "ma.example:8095");
+        assert_eq!(tls_key("https://[::1]:8095"), "[::1]:8095");
+        assert_eq!(tls_key(""), "");
+    }
+
     #[test]
     fn software_volume_default_is_100() {
         let settings = Settings::default();
@@ -454,6 +1873,72 @@ mod tests {
         assert!(!settings.muted);
     }
 
+    #[test]
+    fn additional_players_missing_from_json_default_to_none() {
+        let json = r#"{"discord_rpc_enabled":true,"start_minimized":false,"autostart":false,"sendspin_enabled":true,"sendspin_player_name":"test","sync_delay_ms":0,"volume_control_mode":"auto"}"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        assert!(settings.additional_players.is_empty());
+    }
+
+    #[test]
+    fn additional_player_defaults_fill_missing_fields() {
+        let json = r#"{"player_id":"ma_companion_x","player_name":"Headphones"}"#;
+        let player: AdditionalPlayer = serde_json::from_str(json).unwrap();
+        assert_eq!(player.audio_device_id, None);
+        assert_eq!(player.sync_delay_ms, 0);
+        assert_eq!(player.software_volume, 100);
+        assert!(!player.muted);
+        assert_eq!(player.channel_mix, ChannelMix::default());
+    }
+
+    #[test]
+    fn channel_mix_is_per_player() {
+        let mut settings = Settings::default();
+        settings.channel_mix.mono = true;
+        settings.additional_players.push(AdditionalPlayer {
+            player_id: "ma_companion_x".into(),
+            player_name: "Kitchen".into(),
+            audio_device_id: None,
+            sync_delay_ms: 0,
+            software_volume: 100,
+            muted: false,
+            channel_mix: ChannelMix {
+                balance: -40,
+                mono: false,
+            },
+        });
+        assert!(channel_mix(&settings, None).mono);
+        assert_eq!(channel_mix(&settings, Some("ma_companion_x")).balance, -40);
+        assert_eq!(channel_mix(&settings, Some("gone")), ChannelMix::default());
+    }
+
+    #[test]
+    fn keepalive_timeout_missing_from_json_uses_default() {
+        let json = r#"{"discord_rpc_enabled":true,"start_minimized":false,"autostart":false,"sendspin_enabled":true,"sendspin_player_name":"test","sync_delay_ms":0,"volume_control_mode":"auto"}"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            settings.keepalive_timeout_secs,
+            DEFAULT_KEEPALIVE_TIMEOUT_SECS
+        );
+    }
+
+    #[test]
+    fn device_delays_are_keyed_by_device() {
+        let mut settings = Settings::default();
+        remember_device_delay(&mut settings, Some("Headphones"), 180);
+        remember_device_delay(&mut settings, None, 20);
+        assert_eq!(device_delay(&settings, Some("Headphones")), Some(180));
+        assert_eq!(device_delay(&settings, None), Some(20));
+        assert_eq!(device_delay(&settings, Some("DAC")), None);
+    }
+
+    #[test]
+    fn device_sync_delays_missing_from_json_default_to_empty() {
+        let json = r#"{"discord_rpc_enabled":true,"start_minimized":false,"autostart":false,"sendspin_enabled":true,"sendspin_player_name":"test","sync_delay_ms":0,"volume_control_mode":"auto"}"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        assert!(settings.device_sync_delays.is_empty());
+    }
+
     #[test]
     fn volume_control_mode_serde_roundtrip() {
         // Verify all variants serialize to lowercase and deserialize back
@@ -471,6 +1956,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn replay_gain_mode_serde_roundtrip() {
+        assert_eq!(ReplayGainMode::default(), ReplayGainMode::Off);
+        for (mode, expected_json) in [
+            (ReplayGainMode::Off, "\"off\""),
+            (ReplayGainMode::Track, "\"track\""),
+            (ReplayGainMode::Album, "\"album\""),
+        ] {
+            let json = serde_json::to_string(&mode).unwrap();
+            assert_eq!(json, expected_json);
+            let deserialized: ReplayGainMode = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized, mode);
+        }
+    }
+
+    #[test]
+    fn eq_bands_deserialize_with_defaults() {
+        let profile: OutputProfile = serde_json::from_str(
+            r#"{"eq_enabled": true, "eq_bands": [{"frequency_hz": 100.0, "gain_db": 3.0},
+                {"filter": "high_shelf", "frequency_hz": 8000.0, "gain_db": -2.0, "q": 0.5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(profile.eq_preamp_db, 0.0);
+        assert_eq!(profile.eq_bands[0].filter, EqFilter::Peaking);
+        assert_eq!(profile.eq_bands[0].q, std::f32::consts::FRAC_1_SQRT_2);
+        assert_eq!(profile.eq_bands[1].filter, EqFilter::HighShelf);
+    }
+
+    #[test]
+    fn crossfeed_serde_roundtrip() {
+        assert_eq!(Crossfeed::default(), Crossfeed::Off);
+        for (level, expected_json) in [
+            (Crossfeed::Off, "\"off\""),
+            (Crossfeed::Mild, "\"mild\""),
+            (Crossfeed::Medium, "\"medium\""),
+            (Crossfeed::Strong, "\"strong\""),
+        ] {
+            let json = serde_json::to_string(&level).unwrap();
+            assert_eq!(json, expected_json);
+            let deserialized: Crossfeed = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized, level);
+        }
+    }
+
+    #[test]
+    fn devices_without_a_profile_get_the_default() {
+        let mut settings = Settings::default();
+        let headphones = OutputProfile {
+            eq_enabled: true,
+            ..OutputProfile::default()
+        };
+        settings
+            .output_profiles
+            .insert(device_key(Some("headphones")), headphones.clone());
+        assert_eq!(output_profile(&settings, Some("headphones")), headphones);
+        assert_eq!(output_profile(&settings, None), OutputProfile::default());
+    }
+
+    #[test]
+    fn resampler_quality_serde_roundtrip() {
+        assert_eq!(ResamplerQuality::default(), ResamplerQuality::Balanced);
+        for (quality, expected_json) in [
+            (ResamplerQuality::Fast, "\"fast\""),
+            (ResamplerQuality::Balanced, "\"balanced\""),
+            (ResamplerQuality::High, "\"high\""),
+        ] {
+            let json = serde_json::to_string(&quality).unwrap();
+            assert_eq!(json, expected_json);
+            let deserialized: ResamplerQuality = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized, quality);
+        }
+    }
+
     #[test]
     fn test_invalid_volume_control_mode_returns_error() {
         let result = set_string_setting("volume_control_mode", Some("invalid".to_string()));
@@ -496,6 +2054,184 @@ mod tests {
         assert!(!settings.muted);
     }
 
+    /// A settings file with the fields every version has written, and `fields`
+    fn settings_file(fields: &str) -> String {
+        format!(
+            r#"{{"discord_rpc_enabled": true, "start_minimized": false, "autostart": false, {}}}"#,
+            fields
+        )
+    }
+
+    #[test]
+    fn older_files_are_migrated_in_order() {
+        fn rename_volume(raw: &mut Value) -> Result<(), String> {
+            let volume = raw["volume"].take();
+            raw["software_volume"] = volume;
+            Ok(())
+        }
+        fn halve_volume(raw: &mut Value) -> Result<(), String> {
+            let volume = raw["software_volume"].as_u64().ok_or("no volume")?;
+            raw["software_volume"] = (volume / 2).into();
+            Ok(())
+        }
+        let migrations: &[Migration] = &[rename_volume, halve_volume];
+
+        // Unversioned, so version 1: both migrations run
+        let (settings, newer) = parse(&settings_file(r#""volume": 80"#), migrations).unwrap();
+        assert_eq!(settings.software_volume, 40);
+        assert_eq!(settings.schema_version, 3);
+        assert!(!newer);
+
+        // Version 2 only needs the second
+        let (settings, _) = parse(
+            &settings_file(r#""schema_version": 2, "software_volume": 80"#),
+            migrations,
+        )
+        .unwrap();
+        assert_eq!(settings.software_volume, 40);
+
+        let error = parse(r#"{"schema_version": 2}"#, migrations).unwrap_err();
+        assert!(error.contains("schema 2"), "{}", error);
+    }
+
+    #[test]
+    fn older_files_get_their_delays_per_device() {
+        let (settings, _) = parse(
+            &settings_file(
+                r#""audio_device_id": "hw:1,0", "sync_delay_ms": 40,
+                "additional_players": [
+                    {"player_id": "a", "player_name": "Kitchen", "sync_delay_ms": 120},
+                    {"player_id": "b", "player_name": "Porch", "audio_device_id": "hw:2,0"}
+                ]"#,
+            ),
+            MIGRATIONS,
+        )
+        .unwrap();
+        assert_eq!(settings.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            settings.device_sync_delays,
+            BTreeMap::from([("hw:1,0".to_string(), 40), (String::new(), 120)])
+        );
+
+        // Files that already have delays per device keep them
+        let (settings, _) = parse(
+            &settings_file(
+                r#""schema_version": 2, "audio_device_id": "hw:1,0", "sync_delay_ms": 40,
+                "device_sync_delays": {"hw:1,0": 60}"#,
+            ),
+            MIGRATIONS,
+        )
+        .unwrap();
+        assert_eq!(settings.device_sync_delays["hw:1,0"], 60);
+    }
+
+    #[test]
+    fn newer_files_keep_what_this_version_does_not_know() {
+        let (settings, newer) = parse(
+            &settings_file(r#""schema_version": 99, "software_volume": 30, "unknown": [1]"#),
+            MIGRATIONS,
+        )
+        .unwrap();
+        assert!(newer);
+        assert_eq!(settings.software_volume, 30);
+
+        let saved = serde_json::to_value(&settings).unwrap();
+        assert_eq!(saved["schema_version"], 99);
+        assert_eq!(saved["unknown"], serde_json::json!([1]));
+    }
+
+    #[test]
+    fn broken_entries_are_dropped_so_the_file_saves_again() {
+        let (mut settings, _) = parse(
+            &settings_file(
+                r#""software_volume": 150,
+                "webhooks": [
+                    {"id": "1", "url": "ftp://example.com"},
+                    {"id": "2", "url": " https://example.com/hook "}
+                ],
+                "alarms": [
+                    {"id": "1", "time": "25:00"},
+                    {"id": "2", "time": "07:30", "days": [4, 0, 4]}
+                ]"#,
+            ),
+            MIGRATIONS,
+        )
+        .unwrap();
+        assert!(settings.validate().is_err());
+
+        let problems = settings.normalize();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.software_volume, 100);
+        assert_eq!(settings.webhooks.len(), 1);
+        assert_eq!(settings.webhooks[0].url, "https://example.com/hook");
+        assert_eq!(settings.alarms.len(), 1);
+        assert_eq!(settings.alarms[0].days, [0, 4]);
+    }
+
+    #[test]
+    fn baseline_files_load_without_a_token() {
+        // What the first native-player release wrote. The auth token was
+        // never part of it; the frontend kept that in its own storage.
+        let baseline = r#"{
+            "discord_rpc_enabled": false,
+            "start_minimized": true,
+            "close_to_tray": true,
+            "autostart": false,
+            "last_server_url": "http://ma.local:8095",
+            "last_server_name": "Home",
+            "sendspin_enabled": true,
+            "sendspin_player_id": "ma-desktop-1234",
+            "sendspin_player_name": "Study",
+            "sendspin_server_url": null,
+            "audio_device_id": "hw:1,0",
+            "sync_delay_ms": 40,
+            "volume_control_mode": "software",
+            "software_volume": 55,
+            "muted": true,
+            "show_tray_icon": true,
+            "show_tray_now_playing": false,
+            "debug_logging": false,
+            "trace_logging": false
+        }"#;
+        let (settings, newer) = parse(baseline, MIGRATIONS).unwrap();
+        assert!(!newer);
+        assert!(!settings.discord_rpc_enabled);
+        assert!(settings.close_to_tray);
+        assert_eq!(
+            settings.last_server_url.as_deref(),
+            Some("http://ma.local:8095")
+        );
+        assert_eq!(
+            settings.sendspin_player_id.as_deref(),
+            Some("ma-desktop-1234")
+        );
+        assert_eq!(settings.sendspin_player_name, "Study");
+        assert_eq!(settings.audio_device_id.as_deref(), Some("hw:1,0"));
+        assert_eq!(settings.sync_delay_ms, 40);
+        assert_eq!(settings.volume_control_mode, VolumeControlMode::Software);
+        assert_eq!(settings.software_volume, 55);
+        assert!(settings.muted);
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected_and_corrected() {
+        let mut settings = Settings {
+            software_volume: 150,
+            sync_delay_ms: -20,
+            max_volume: 0,
+            ..Settings::default()
+        };
+        let error = settings.validate().unwrap_err();
+        assert!(error.contains("sync_delay_ms"), "{}", error);
+
+        assert_eq!(settings.normalize().len(), 3);
+        assert_eq!(settings.software_volume, 100);
+        assert_eq!(settings.max_volume, 10);
+        assert_eq!(settings.sync_delay_ms, 0);
+        assert!(settings.validate().is_ok());
+    }
+
     #[test]
     fn test_unknown_setting_keys_return_errors() {
         // Test unknown string setting key
@@ -0,0 +1,261 @@
+//! Sleep timer
+//!
+//! Pauses the built-in player after a number of minutes, or once the current
+//! track has ended. A timed stop first lowers the player's volume over
+//! [`FADE_OUT`] (the volume rather than the stream, which is buffered
+//! seconds ahead of what is heard), then pauses and puts the volume back so
+//! the next play starts at the usual level. The end of a track needs no
+//! fade: playback pauses as soon as the next track starts.
+//!
+//! One timer runs at a time; starting another replaces it.
+
+use crate::now_playing::{self, NowPlaying};
+use crate::sendspin::{PlaybackCommand, SendspinManager};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the volume takes to go down before a timed stop
+const FADE_OUT: Duration = Duration::from_secs(20);
+/// Volume steps of the fade-out
+const FADE_STEPS: u32 = 40;
+/// How often a running timer checks whether it is due or cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Wait between pausing and restoring the volume, so the last of the audio
+/// isn't heard at full volume
+const RESTORE_DELAY: Duration = Duration::from_secs(1);
+/// Longest timer, 12 hours
+const MAX_MINUTES: u32 = 720;
+
+/// When to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepTimerMode {
+    /// After this many minutes
+    Minutes(u32),
+    /// When the current track ends
+    EndOfTrack,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SleepTimerStatus {
+    pub active: bool,
+    pub end_of_track: bool,
+    /// Seconds until the fade-out starts, for a timed stop
+    pub remaining_secs: Option<u64>,
+}
+
+/// Callback type for sleep timer changes
+pub type SleepTimerCallback = Arc<dyn Fn(&SleepTimerStatus) + Send + Sync>;
+
+struct Timer {
+    mode: SleepTimerMode,
+    /// When the fade-out starts, for a timed stop
+    deadline: Option<Instant>,
+}
+
+/// The running timer
+static TIMER: Mutex<Option<Timer>> = Mutex::new(None);
+/// Bumped by every start and cancel; a timer thread whose generation is no
+/// longer current gives up
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static CALLBACKS: Mutex<Vec<SleepTimerCallback>> = Mutex::new(Vec::new());
+
+/// Register a callback to be notified when a timer starts or ends
+pub fn on_change(callback: SleepTimerCallback) {
+    if let Ok(mut callbacks) = CALLBACKS.lock() {
+        callbacks.push(callback);
+    }
+}
+
+pub fn status() -> SleepTimerStatus {
+    let Ok(timer) = TIMER.lock() else {
+        return SleepTimerStatus::default();
+    };
+    timer
+        .as_ref()
+        .map_or_else(SleepTimerStatus::default, |timer| SleepTimerStatus {
+            active: true,
+            end_of_track: timer.mode == SleepTimerMode::EndOfTrack,
+            remaining_secs: timer
+                .deadline
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs()),
+        })
+}
+
+/// Start a timer, replacing any running one
+pub fn start(sendspin: &SendspinManager, mode: SleepTimerMode) -> Result<(), String> {
+    let deadline = match mode {
+        SleepTimerMode::Minutes(minutes) if (1..=MAX_MINUTES).contains(&minutes) => {
+            Some(Instant::now() + Duration::from_secs(u64::from(minutes) * 60))
+        }
+        SleepTimerMode::Minutes(_) => {
+            return Err(format!(
+                "Sleep timer must be between 1 and {} minutes",
+                MAX_MINUTES
+            ))
+        }
+        SleepTimerMode::EndOfTrack => {
+            if !now_playing::get_now_playing().is_playing {
+                return Err("Nothing is playing".to_string());
+            }
+            None
+        }
+    };
+
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    if let Ok(mut timer) = TIMER.lock() {
+        *timer = Some(Timer { mode, deadline });
+    }
+    let sendspin = sendspin.clone();
+    std::thread::spawn(move || run(&sendspin, generation, mode, deadline));
+    log::info!("[SleepTimer] Started: {:?}", mode);
+    notify();
+    Ok(())
+}
+
+/// Stop the running timer, if any. A fade-out in progress is undone.
+pub fn cancel() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    let cancelled = TIMER.lock().ok().and_then(|mut timer| timer.take());
+    if cancelled.is_some() {
+        log::info!("[SleepTimer] Cancelled");
+        notify();
+    }
+}
+
+fn notify() {
+    let status = status();
+    let callbacks = CALLBACKS
+        .lock()
+        .map(|callbacks| callbacks.clone())
+        .unwrap_or_default();
+    for callback in callbacks {
+        callback(&status);
+    }
+}
+
+fn is_current(generation: u64) -> bool {
+    GENERATION.load(Ordering::SeqCst) == generation
+}
+
+fn run(
+    sendspin: &SendspinManager,
+    generation: u64,
+    mode: SleepTimerMode,
+    deadline: Option<Instant>,
+) {
+    let due = match deadline {
+        Some(deadline) => wait_until(generation, deadline),
+        None => wait_for_track_end(generation),
+    };
+    if !due {
+        return;
+    }
+    match mode {
+        SleepTimerMode::Minutes(_) => fade_out_and_pause(sendspin, generation),
+        SleepTimerMode::EndOfTrack => pause(sendspin),
+    }
+    // Done, unless it was replaced meanwhile
+    if is_current(generation) {
+        if let Ok(mut timer) = TIMER.lock() {
+            *timer = None;
+        }
+        notify();
+    }
+}
+
+/// Wait for `deadline`; false if the timer was cancelled first
+fn wait_until(generation: u64, deadline: Instant) -> bool {
+    loop {
+        if !is_current(generation) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(POLL_INTERVAL));
+    }
+}
+
+/// The track playing, to tell when the next one starts
+fn track_of(now_playing: &NowPlaying) -> (Option<String>, Option<String>, Option<String>) {
+    (
+        now_playing.track.clone(),
+        now_playing.artist.clone(),
+        now_playing.album.clone(),
+    )
+}
+
+/// Wait for another track to start; false if the timer was cancelled first
+fn wait_for_track_end(generation: u64) -> bool {
+    let track = track_of(&now_playing::get_now_playing());
+    loop {
+        if !is_current(generation) {
+            return false;
+        }
+        if track_of(&now_playing::get_now_playing()) != track {
+            return true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// `volume` at `step` of the fade-out
+fn faded(volume: u8, step: u32) -> u8 {
+    (u32::from(volume) * FADE_STEPS.saturating_sub(step) / FADE_STEPS) as u8
+}
+
+fn fade_out_and_pause(sendspin: &SendspinManager, generation: u64) {
+    let volume = match sendspin.get_volume_percent() {
+        Ok(volume) => volume,
+        Err(e) => {
+            log::debug!("[SleepTimer] Pausing without a fade: {}", e);
+            pause(sendspin);
+            return;
+        }
+    };
+    log::info!("[SleepTimer] Fading out");
+    for step in 1..=FADE_STEPS {
+        if !is_current(generation) {
+            let _ = sendspin.set_volume_percent(volume);
+            return;
+        }
+        let _ = sendspin.set_volume_percent(faded(volume, step));
+        std::thread::sleep(FADE_OUT / FADE_STEPS);
+    }
+    pause(sendspin);
+    std::thread::sleep(RESTORE_DELAY);
+    if let Err(e) = sendspin.set_volume_percent(volume) {
+        log::warn!("[SleepTimer] Failed to restore the volume: {}", e);
+    }
+}
+
+fn pause(sendspin: &SendspinManager) {
+    match sendspin.send_command(PlaybackCommand::Pause) {
+        Ok(()) => log::info!("[SleepTimer] Paused playback"),
+        Err(e) => log::warn!("[SleepTimer] Failed to pause: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_out_ends_silent() {
+        assert_eq!(faded(80, 0), 80);
+        assert_eq!(faded(80, FADE_STEPS / 2), 40);
+        assert_eq!(faded(80, FADE_STEPS), 0);
+    }
+
+    #[test]
+    fn modes_deserialize_from_the_frontend() {
+        let minutes: SleepTimerMode = serde_json::from_str(r#"{"minutes":30}"#).unwrap();
+        assert_eq!(minutes, SleepTimerMode::Minutes(30));
+        let end: SleepTimerMode = serde_json::from_str(r#""end_of_track""#).unwrap();
+        assert_eq!(end, SleepTimerMode::EndOfTrack);
+    }
+}
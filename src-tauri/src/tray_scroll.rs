@@ -0,0 +1,146 @@
+//! Scrolling over the tray icon
+//!
+//! Tauri's tray reports clicks but not the scroll wheel, so it is watched
+//! directly where the platform allows: on macOS a local event monitor sees
+//! scrolls over the status item's window, and on Windows a low-level mouse
+//! hook sees wheel turns while the pointer is over the icon, which the tray's
+//! enter and leave events tell [`set_pointer_over`]. Linux trays keep the
+//! wheel to themselves, so there scrolling does nothing.
+#![allow(unsafe_code)] // The platform event hooks are all `unsafe`; lift the workspace deny.
+
+/// Called with the steps scrolled, up if positive
+pub type ScrollCallback = Box<dyn Fn(i32) + Send + Sync>;
+
+/// Start watching for scrolls over the tray icon. Call on the main thread.
+pub fn init(on_scroll: ScrollCallback) {
+    platform::init(on_scroll);
+}
+
+/// Record whether the pointer is over the tray icon
+pub fn set_pointer_over(over: bool) {
+    platform::set_pointer_over(over);
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::ScrollCallback;
+    use block2::RcBlock;
+    use objc2::MainThreadMarker;
+    use objc2_app_kit::{NSEvent, NSEventMask};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    /// Trackpad scrolling, in points, that makes one step
+    const POINTS_PER_STEP: f64 = 12.0;
+
+    pub(super) fn init(on_scroll: ScrollCallback) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            log::warn!("[Tray] Scrolling over the tray icon needs setting up on the main thread");
+            return;
+        };
+        // Trackpads scroll in small precise deltas, wheels in whole lines
+        let pending = Cell::new(0.0);
+        let handler = RcBlock::new(move |event: NonNull<NSEvent>| -> *mut NSEvent {
+            let ns_event = unsafe { event.as_ref() };
+            let over_tray = unsafe { ns_event.window(mtm) }
+                .is_some_and(|window| window.class().name() == c"NSStatusBarWindow");
+            if over_tray {
+                let delta = unsafe { ns_event.scrollingDeltaY() };
+                let steps = if unsafe { ns_event.hasPreciseScrollingDeltas() } {
+                    pending.set(pending.get() + delta);
+                    let steps = (pending.get() / POINTS_PER_STEP).trunc();
+                    pending.set(pending.get() - steps * POINTS_PER_STEP);
+                    steps as i32
+                } else if delta == 0.0 {
+                    // Scrolled sideways
+                    0
+                } else {
+                    delta.signum() as i32
+                };
+                if steps != 0 {
+                    on_scroll(steps);
+                }
+            }
+            event.as_ptr()
+        });
+        let monitor = unsafe {
+            NSEvent::addLocalMonitorForEventsMatchingMask_handler(
+                NSEventMask::ScrollWheel,
+                &handler,
+            )
+        };
+        // Watched for as long as the app runs
+        std::mem::forget(monitor);
+    }
+
+    pub(super) fn set_pointer_over(_over: bool) {}
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::ScrollCallback;
+    use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+    use std::sync::OnceLock;
+    use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CallNextHookEx, GetMessageW, SetWindowsHookExW, UnhookWindowsHookEx, MSG, MSLLHOOKSTRUCT,
+        WHEEL_DELTA, WH_MOUSE_LL, WM_MOUSEWHEEL,
+    };
+
+    static ON_SCROLL: OnceLock<ScrollCallback> = OnceLock::new();
+    static POINTER_OVER: AtomicBool = AtomicBool::new(false);
+    /// Wheel movement short of a whole notch, from high-resolution wheels
+    static PENDING: AtomicI32 = AtomicI32::new(0);
+
+    pub(super) fn init(on_scroll: ScrollCallback) {
+        if ON_SCROLL.set(on_scroll).is_err() {
+            return;
+        }
+        // A low-level hook runs on the thread that set it, which has to
+        // keep pumping messages for it
+        std::thread::spawn(|| unsafe {
+            let hook = match SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_hook), None, 0) {
+                Ok(hook) => hook,
+                Err(e) => {
+                    log::warn!("[Tray] Failed to watch the scroll wheel: {e}");
+                    return;
+                }
+            };
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, None, 0, 0).as_bool() {}
+            let _ = UnhookWindowsHookEx(hook);
+        });
+    }
+
+    pub(super) fn set_pointer_over(over: bool) {
+        if !POINTER_OVER.swap(over, Ordering::Relaxed) {
+            PENDING.store(0, Ordering::Relaxed);
+        }
+    }
+
+    unsafe extern "system" fn mouse_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code >= 0 && wparam.0 as u32 == WM_MOUSEWHEEL && POINTER_OVER.load(Ordering::Relaxed) {
+            let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+            // The high word of mouseData is the signed wheel delta, up if positive
+            let delta = i32::from((info.mouseData >> 16) as u16 as i16);
+            let pending = PENDING.fetch_add(delta, Ordering::Relaxed) + delta;
+            let steps = pending / WHEEL_DELTA as i32;
+            if steps != 0 {
+                PENDING.fetch_sub(steps * WHEEL_DELTA as i32, Ordering::Relaxed);
+                if let Some(on_scroll) = ON_SCROLL.get() {
+                    on_scroll(steps);
+                }
+            }
+        }
+        CallNextHookEx(None, code, wparam, lparam)
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use super::ScrollCallback;
+
+    pub(super) fn init(_on_scroll: ScrollCallback) {}
+
+    pub(super) fn set_pointer_over(_over: bool) {}
+}
@@ -0,0 +1,321 @@
+//! Webhooks
+//!
+//! POSTs JSON to user-configured URLs when the track changes, playback
+//! starts or pauses, or the built-in player loses its server connection, so
+//! Home Assistant automations and logging services can follow along without
+//! polling.
+//!
+//! A webhook's body is an object of all the [`VARIABLES`], or its own JSON
+//! template with `{{variable}}` placeholders. A placeholder is replaced by
+//! the variable's JSON value (a quoted string, a number, or `null` when it
+//! isn't known), so it goes in the template without quotes:
+//! `{"message": {{track}}, "by": {{artist}}}`.
+//!
+//! Deliveries are sent one at a time on a background thread; failures are
+//! logged and not retried.
+
+use crate::now_playing::{self, NowPlaying};
+use crate::sendspin::events::StatusEvent;
+use crate::settings::{self, Webhook, WebhookEvent};
+use serde_json::{json, Value};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Endpoints that don't answer by then get dropped, so one slow webhook
+/// can't hold up the rest for long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Names usable as `{{placeholders}}` in templates
+pub const VARIABLES: &[&str] = &[
+    "event",
+    "timestamp",
+    "player_id",
+    "player_name",
+    "track",
+    "artist",
+    "album",
+    "image_url",
+    "duration",
+    "elapsed",
+    "is_playing",
+    "error",
+];
+
+/// Now-playing state of the last change, to tell what changed next
+static LAST_NOW_PLAYING: Mutex<Option<NowPlaying>> = Mutex::new(None);
+
+/// Queue of the delivery thread as (URL, body), started on first use
+static QUEUE: Mutex<Option<Sender<(String, String)>>> = Mutex::new(None);
+
+/// Fire webhooks on now-playing changes from here on. Connection loss is
+/// reported by the player through [`connection_lost`].
+pub fn init() {
+    now_playing::on_now_playing_change(Arc::new(|now_playing| {
+        let previous = LAST_NOW_PLAYING
+            .lock()
+            .ok()
+            .and_then(|mut last| last.replace(now_playing.clone()));
+        for event in changes(previous.as_ref(), now_playing) {
+            fire(event, &variables(event, now_playing, None));
+        }
+    }));
+}
+
+/// Fire `connection_lost` webhooks for the player in `status`, which was
+/// connected until now
+pub(crate) fn connection_lost(status: &StatusEvent) {
+    let mut now_playing = now_playing::get_now_playing();
+    // Only describe what was playing if it was playing on this player
+    if now_playing.player_id.as_deref() != Some(&status.player_id) {
+        now_playing = NowPlaying {
+            player_id: Some(status.player_id.clone()),
+            ..NowPlaying::default()
+        };
+    }
+    let event = WebhookEvent::ConnectionLost;
+    let error = status.error.as_ref().map(ToString::to_string);
+    fire(event, &variables(event, &now_playing, error.as_deref()));
+}
+
+/// Send one request to `webhook` right away, as if `event` had happened
+/// with what is playing now, and report how the endpoint answered.
+///
+/// Blocking; call from a worker thread.
+pub fn test(webhook: &Webhook, event: WebhookEvent) -> Result<(), String> {
+    let variables = variables(event, &now_playing::get_now_playing(), None);
+    post(&webhook.url, &body(webhook, &variables))
+}
+
+/// Check a webhook before it is saved: an http(s) URL, and a template (if
+/// any) that renders to valid JSON
+pub fn validate(webhook: &Webhook) -> Result<(), String> {
+    if !(webhook.url.starts_with("http://") || webhook.url.starts_with("https://")) {
+        return Err("Webhook URL must start with http:// or https://".to_string());
+    }
+    if let Some(template) = &webhook.template {
+        // Text in every field, so a quoted placeholder breaks the JSON
+        let sample = variables(
+            WebhookEvent::TrackChanged,
+            &NowPlaying {
+                track: Some("Track".to_string()),
+                artist: Some("Artist".to_string()),
+                album: Some("Album".to_string()),
+                image_url: Some("https://example.com/image.jpg".to_string()),
+                player_name: Some("Player".to_string()),
+                player_id: Some("player".to_string()),
+                ..NowPlaying::default()
+            },
+            Some("Error"),
+        );
+        serde_json::from_str::<Value>(&render(template, &sample))
+            .map_err(|e| format!("Webhook template is not valid JSON: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Events between two now-playing states. Any track counts as a change
+/// when there is no previous state.
+fn changes(previous: Option<&NowPlaying>, current: &NowPlaying) -> Vec<WebhookEvent> {
+    let mut events = Vec::new();
+    let same_track = previous.is_some_and(|previous| {
+        previous.player_id == current.player_id
+            && previous.track == current.track
+            && previous.artist == current.artist
+            && previous.album == current.album
+    });
+    if current.track.is_some() && !same_track {
+        events.push(WebhookEvent::TrackChanged);
+    }
+    match (previous.is_some_and(|p| p.is_playing), current.is_playing) {
+        (false, true) => events.push(WebhookEvent::Playing),
+        (true, false) => events.push(WebhookEvent::Paused),
+        _ => {}
+    }
+    events
+}
+
+/// Template variables for `event` (see [`VARIABLES`])
+fn variables(event: WebhookEvent, now_playing: &NowPlaying, error: Option<&str>) -> Value {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    json!({
+        "event": event,
+        "timestamp": timestamp,
+        "player_id": now_playing.player_id,
+        "player_name": now_playing.player_name,
+        "track": now_playing.track,
+        "artist": now_playing.artist,
+        "album": now_playing.album,
+        "image_url": now_playing.image_url,
+        "duration": now_playing.duration,
+        "elapsed": now_playing.elapsed,
+        "is_playing": now_playing.is_playing,
+        "error": error,
+    })
+}
+
+/// Replace each `{{name}}` in `template` by the JSON value of the variable
+/// `name`. Unknown names are left as they are.
+fn render(template: &str, variables: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after
+            .find("}}")
+            .and_then(|end| Some((variables.get(after[..end].trim())?, end)));
+        if let Some((value, end)) = value {
+            rendered.push_str(&value.to_string());
+            rest = &after[end + 2..];
+        } else {
+            rendered.push_str("{{");
+            rest = after;
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn body(webhook: &Webhook, variables: &Value) -> String {
+    match &webhook.template {
+        Some(template) => render(template, variables),
+        None => variables.to_string(),
+    }
+}
+
+/// Queue a request to every webhook that fires on `event`
+fn fire(event: WebhookEvent, variables: &Value) {
+    let webhooks: Vec<Webhook> = settings::get_settings()
+        .webhooks
+        .into_iter()
+        .filter(|webhook| webhook.events.contains(&event))
+        .collect();
+    if webhooks.is_empty() {
+        return;
+    }
+    let Ok(mut queue) = QUEUE.lock() else {
+        return;
+    };
+    let queue = queue.get_or_insert_with(|| {
+        let (tx, rx) = mpsc::channel::<(String, String)>();
+        std::thread::spawn(move || {
+            for (url, body) in rx {
+                // The URL often holds a secret, so it stays out of the log
+                if let Err(e) = post(&url, &body) {
+                    log::warn!("[Webhooks] Delivery failed: {}", e);
+                }
+            }
+        });
+        tx
+    });
+    log::debug!(
+        "[Webhooks] Firing {:?} to {} webhook(s)",
+        event,
+        webhooks.len()
+    );
+    for webhook in &webhooks {
+        let _ = queue.send((webhook.url.clone(), body(webhook, variables)));
+    }
+}
+
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::Agent::config_builder()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .build()
+            .into()
+    })
+}
+
+fn post(url: &str, body: &str) -> Result<(), String> {
+    agent()
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(track: &str, is_playing: bool) -> NowPlaying {
+        NowPlaying {
+            is_playing,
+            track: Some(track.to_string()),
+            artist: Some("Artist".to_string()),
+            player_id: Some("player".to_string()),
+            ..NowPlaying::default()
+        }
+    }
+
+    #[test]
+    fn detects_track_changes_and_play_pause() {
+        let a = playing("A", true);
+        assert_eq!(
+            changes(None, &a),
+            vec![WebhookEvent::TrackChanged, WebhookEvent::Playing]
+        );
+        assert!(changes(Some(&a), &a).is_empty());
+        assert_eq!(
+            changes(Some(&a), &playing("A", false)),
+            vec![WebhookEvent::Paused]
+        );
+        assert_eq!(
+            changes(Some(&a), &playing("B", true)),
+            vec![WebhookEvent::TrackChanged]
+        );
+        assert_eq!(
+            changes(Some(&a), &NowPlaying::default()),
+            vec![WebhookEvent::Paused]
+        );
+    }
+
+    #[test]
+    fn placeholders_become_json_values() {
+        let mut now_playing = playing("Say \"hi\"", true);
+        now_playing.duration = Some(200.0);
+        let variables = variables(WebhookEvent::TrackChanged, &now_playing, None);
+        let rendered = render(
+            r#"{"title": {{ track }}, "length": {{duration}}, "album": {{album}}, "keep": "{{nope}}"}"#,
+            &variables,
+        );
+        let value: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(value["title"], "Say \"hi\"");
+        assert_eq!(value["length"], 200.0);
+        assert!(value["album"].is_null());
+        assert_eq!(value["keep"], "{{nope}}");
+    }
+
+    #[test]
+    fn every_variable_is_provided() {
+        let variables = variables(WebhookEvent::Paused, &NowPlaying::default(), None);
+        let variables = variables.as_object().unwrap();
+        assert_eq!(variables.len(), VARIABLES.len());
+        for name in VARIABLES {
+            assert!(variables.contains_key(*name), "{}", name);
+        }
+        assert_eq!(variables["event"], "paused");
+    }
+
+    #[test]
+    fn validation_rejects_bad_urls_and_templates() {
+        let mut webhook = Webhook {
+            id: "id".to_string(),
+            url: "https://example.com/hook".to_string(),
+            events: vec![WebhookEvent::TrackChanged],
+            template: Some(r#"{"text": {{track}}}"#.to_string()),
+        };
+        assert!(validate(&webhook).is_ok());
+        webhook.template = Some(r#"{"text": "{{track}}"}"#.to_string());
+        assert!(validate(&webhook).is_err());
+        webhook.template = None;
+        webhook.url = "ftp://example.com".to_string();
+        assert!(validate(&webhook).is_err());
+    }
+}